@@ -0,0 +1,211 @@
+// Shared by the benchmarks: reproducible synthetic records and stores filled with
+// them. A test can take it too, with
+//
+//   #[path = "../benches/common/mod.rs"]
+//   mod common;
+//
+// Each bench binary uses only part of it.
+#![allow(dead_code)]
+
+use json_store::{
+    error::JsonStoreError,
+    raw::ValueMode,
+    store::{Info, JsonStore, LoadOptions},
+};
+use serde_json::{json, Value};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+use tokio::runtime::Runtime;
+
+pub const TREE: &str = "docs";
+
+const NAMES: [&str; 8] = [
+    "ada", "brian", "carl", "dennis", "edsger", "frances", "grace", "hedy",
+];
+const TAGS: [&str; 6] = ["red", "green", "blue", "cyan", "magenta", "yellow"];
+const WORDS: [&str; 8] = [
+    "lorem",
+    "ipsum",
+    "dolor",
+    "sit",
+    "amet",
+    "consectetur",
+    "adipiscing",
+    "elit",
+];
+
+// Documents built from a seed and their number alone, so any one of them can be made
+// again without the others: the same seed and number always give the same record.
+// email and handle differ between any two numbers, for unique constraints.
+#[derive(Debug, Clone, Copy)]
+pub struct Documents {
+    seed: u64,
+    body: usize,
+}
+
+impl Documents {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, body: 64 }
+    }
+
+    // documents with a body of about bytes of text
+    pub fn body(mut self, bytes: usize) -> Self {
+        self.body = bytes;
+        self
+    }
+
+    pub fn get(&self, n: u64) -> Value {
+        let mut state = self.seed ^ n.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        let mut next = || splitmix(&mut state);
+
+        let mut body = String::with_capacity(self.body + 12);
+        while body.len() < self.body {
+            if !body.is_empty() {
+                body.push(' ');
+            }
+            body.push_str(WORDS[next() as usize % WORDS.len()]);
+        }
+
+        json!({
+            "name": format!("{} {}", NAMES[next() as usize % NAMES.len()], n),
+            "email": format!("user{}@example.com", n),
+            // an odd multiplier maps numbers one to one
+            "handle": format!("{:016x}", n.wrapping_mul(0xd6e8_feb8_6659_fd93)),
+            "score": next() % 1_000,
+            "active": next() % 2 == 0,
+            "tags": [TAGS[next() as usize % TAGS.len()], TAGS[next() as usize % TAGS.len()]],
+            "body": body,
+        })
+    }
+
+    // count documents numbered from first on
+    pub fn take(&self, first: u64, count: usize) -> Vec<Value> {
+        (first..first + count as u64).map(|n| self.get(n)).collect()
+    }
+
+    // how many documents serialize to about bytes
+    pub fn count_for(&self, bytes: usize) -> usize {
+        let each = serde_json::to_vec(&self.get(0)).map_or(1, |doc| doc.len());
+        (bytes / each).max(1)
+    }
+}
+
+fn splitmix(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// The ways a tree can hold its records that a benchmark is run over, so a change
+// that helps one and hurts another shows up in both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    // parsed records, shared as Arc<Value>, with no field index
+    Parsed,
+    // parsed records with an equality index on score
+    Indexed,
+    // records kept as JSON text, see raw.rs
+    Raw,
+}
+
+impl Variant {
+    pub const ALL: [Variant; 3] = [Variant::Parsed, Variant::Indexed, Variant::Raw];
+
+    // the tree's Info, with unique constraints on the first unique of email and handle
+    pub fn info(&self, unique: usize) -> Info {
+        let mut builder = Info::builder().sequence_field("id");
+        for field in ["email", "handle"].into_iter().take(unique) {
+            builder = builder.unique(field, [field]);
+        }
+        builder = match self {
+            Variant::Parsed => builder,
+            Variant::Indexed => builder.index("score"),
+            Variant::Raw => builder.value_mode(ValueMode::Raw),
+        };
+        builder.build().expect("a valid info")
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Variant::Parsed => "parsed",
+            Variant::Indexed => "indexed",
+            Variant::Raw => "raw",
+        };
+        f.write_str(name)
+    }
+}
+
+pub fn runtime() -> Runtime {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("a tokio runtime")
+}
+
+// An in-memory store with tree TREE holding the first count documents of docs.
+pub async fn filled(
+    docs: &Documents,
+    count: usize,
+    variant: Variant,
+    unique: usize,
+) -> Result<JsonStore, JsonStoreError> {
+    let store = JsonStore::in_memory();
+    fill(&store, TREE, docs, count, variant, unique).await?;
+    Ok(store)
+}
+
+// create tname in store and insert the first count documents of docs
+pub async fn fill(
+    store: &JsonStore,
+    tname: &str,
+    docs: &Documents,
+    count: usize,
+    variant: Variant,
+    unique: usize,
+) -> Result<(), JsonStoreError> {
+    store.create_tree(tname, variant.info(unique)).await?;
+    for n in 0..count as u64 {
+        store.insert(tname, &docs.get(n)).await?;
+    }
+    Ok(())
+}
+
+// a store in a directory of its own, loaded with options
+pub async fn on_disk(dir: &ScratchDir, options: LoadOptions) -> Result<JsonStore, JsonStoreError> {
+    JsonStore::load_with_options(dir.path(), options).await
+}
+
+// A directory under the system's temporary one, removed when dropped.
+pub struct ScratchDir(PathBuf);
+
+impl ScratchDir {
+    pub fn new(name: &str) -> Self {
+        static DIRS: AtomicU64 = AtomicU64::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "json-store-bench-{}-{}-{}",
+            name,
+            std::process::id(),
+            DIRS.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).expect("a scratch directory");
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
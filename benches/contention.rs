@@ -0,0 +1,74 @@
+mod common;
+
+use common::{Documents, Variant, TREE};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use json_store::{
+    backend::InMemoryBackend,
+    batch::WriteBatching,
+    store::{JsonStore, LoadOptions},
+};
+use std::time::{Duration, Instant};
+
+const WRITERS: u64 = 16;
+const EACH: u64 = 256;
+
+// WRITERS tasks each inserting EACH records into one tree at once, with every write
+// batching mode; timed is the lot, throughput counting every insert. The tree is
+// dropped and made again between iterations, outside the timing.
+fn contention(c: &mut Criterion) {
+    let runtime = common::runtime();
+    let docs = Documents::new(1);
+
+    let mut group = c.benchmark_group("contention");
+    group.throughput(Throughput::Elements(WRITERS * EACH));
+    for batching in [
+        WriteBatching::Off,
+        WriteBatching::Contended,
+        WriteBatching::Always,
+    ] {
+        let options = LoadOptions {
+            write_batching: batching,
+            ..LoadOptions::default()
+        };
+        let store = runtime
+            .block_on(JsonStore::load_with_backend(
+                InMemoryBackend::new(),
+                options,
+            ))
+            .expect("an in-memory store");
+        let id = BenchmarkId::new(format!("{:?}", batching).to_lowercase(), WRITERS);
+        group.bench_function(id, |b| {
+            b.iter_custom(|iters| {
+                runtime.block_on(async {
+                    let mut took = Duration::ZERO;
+                    for _ in 0..iters {
+                        store
+                            .create_tree(TREE, Variant::Parsed.info(1))
+                            .await
+                            .expect("a tree");
+                        let started = Instant::now();
+                        let writers = (0..WRITERS).map(|writer| {
+                            let store = store.clone();
+                            tokio::spawn(async move {
+                                for n in 0..EACH {
+                                    let doc = docs.get(writer * EACH + n);
+                                    store.insert(TREE, &doc).await.expect("an insert");
+                                }
+                            })
+                        });
+                        for writer in writers.collect::<Vec<_>>() {
+                            writer.await.expect("a writer");
+                        }
+                        took += started.elapsed();
+                        store.drop_tree(TREE).await.expect("a drop");
+                    }
+                    took
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, contention);
+criterion_main!(benches);
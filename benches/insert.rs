@@ -0,0 +1,48 @@
+mod common;
+
+use common::{Documents, Variant, TREE};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::time::{Duration, Instant};
+
+const SIZES: [usize; 3] = [1_000, 10_000, 100_000];
+
+// One insert into a tree already holding each number of records, with none, one and
+// two unique constraints. The record is deleted again outside the timing, so the tree
+// stays the size it was filled to however many iterations run.
+fn insert(c: &mut Criterion) {
+    let runtime = common::runtime();
+    let docs = Documents::new(1);
+
+    let mut group = c.benchmark_group("insert");
+    for variant in Variant::ALL {
+        for unique in 0..=2 {
+            for size in SIZES {
+                let store = runtime
+                    .block_on(common::filled(&docs, size, variant, unique))
+                    .expect("a filled store");
+                let mut next = size as u64;
+                let id = BenchmarkId::new(format!("{}/unique-{}", variant, unique), size);
+                group.bench_function(id, |b| {
+                    b.iter_custom(|iters| {
+                        runtime.block_on(async {
+                            let mut took = Duration::ZERO;
+                            for _ in 0..iters {
+                                let doc = docs.get(next);
+                                next += 1;
+                                let started = Instant::now();
+                                let seq = store.insert(TREE, &doc).await.expect("an insert");
+                                took += started.elapsed();
+                                store.delete(TREE, seq).await.expect("a delete");
+                            }
+                            took
+                        })
+                    })
+                });
+            }
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, insert);
+criterion_main!(benches);
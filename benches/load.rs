@@ -0,0 +1,48 @@
+mod common;
+
+use common::{Documents, ScratchDir, Variant};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use json_store::store::{JsonStore, LoadOptions};
+
+const TREES: usize = 8;
+const RECORDS: usize = 10_000;
+
+// Loading a store of TREES trees of RECORDS records each, every tree read up front
+// (LoadOptions::eager) rather than on first use.
+fn load(c: &mut Criterion) {
+    let runtime = common::runtime();
+    let docs = Documents::new(4);
+
+    let mut group = c.benchmark_group("load");
+    group.sample_size(10);
+    for variant in Variant::ALL {
+        let dir = ScratchDir::new("load");
+        runtime
+            .block_on(async {
+                let store = common::on_disk(&dir, LoadOptions::default()).await?;
+                for i in 0..TREES {
+                    let tname = format!("tree{}", i);
+                    common::fill(&store, &tname, &docs, RECORDS, variant, 1).await?;
+                }
+                store.close().await
+            })
+            .expect("a saved store");
+
+        let id = BenchmarkId::new(variant.to_string(), format!("{}x{}", TREES, RECORDS));
+        group.bench_function(id, |b| {
+            b.iter(|| {
+                let options = LoadOptions {
+                    eager: true,
+                    ..LoadOptions::default()
+                };
+                runtime
+                    .block_on(JsonStore::load_with_options(dir.path(), options))
+                    .expect("a loaded store")
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, load);
+criterion_main!(benches);
@@ -0,0 +1,47 @@
+mod common;
+
+use common::{Documents, ScratchDir, Variant, TREE};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use json_store::{merge::MergePolicy, store::LoadOptions};
+
+const SIZES: [usize; 2] = [1_000, 10_000];
+
+// merge_from of a store on disk holding the same records as the one merged into,
+// both with two unique constraints: every record is matched by its keys and skipped,
+// so nothing changes between runs. The time takes in reading the other store, as
+// merge_from does each time.
+fn merge(c: &mut Criterion) {
+    let runtime = common::runtime();
+    let docs = Documents::new(5);
+
+    for variant in Variant::ALL {
+        let mut group = c.benchmark_group(format!("merge/{}", variant));
+        group.sample_size(10);
+        for size in SIZES {
+            let store = runtime
+                .block_on(common::filled(&docs, size, variant, 2))
+                .expect("a filled store");
+            let dir = ScratchDir::new("merge");
+            runtime
+                .block_on(async {
+                    let other = common::on_disk(&dir, LoadOptions::default()).await?;
+                    common::fill(&other, TREE, &docs, size, variant, 2).await?;
+                    other.close().await
+                })
+                .expect("a saved store");
+
+            group.throughput(Throughput::Elements(size as u64));
+            group.bench_function(BenchmarkId::new("matched", size), |b| {
+                b.iter(|| {
+                    runtime
+                        .block_on(store.merge_from(dir.path(), MergePolicy::Error))
+                        .expect("a merge")
+                })
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, merge);
+criterion_main!(benches);
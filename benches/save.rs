@@ -0,0 +1,60 @@
+mod common;
+
+use common::{Documents, ScratchDir, Variant, TREE};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use json_store::store::LoadOptions;
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+const MB: usize = 1 << 20;
+
+// save_tree of trees that serialize to about 1, 10 and 100 MB. Before each save one
+// record is updated, untimed, so there is something to write; the whole snapshot is
+// written every time.
+fn save(c: &mut Criterion) {
+    let runtime = common::runtime();
+    let docs = Documents::new(3).body(512);
+
+    let mut group = c.benchmark_group("save_tree");
+    group.sample_size(10);
+    for variant in Variant::ALL {
+        for megabytes in [1, 10, 100] {
+            let dir = ScratchDir::new("save");
+            let count = docs.count_for(megabytes * MB);
+            let store = runtime.block_on(async {
+                let store = common::on_disk(&dir, LoadOptions::default()).await?;
+                common::fill(&store, TREE, &docs, count, variant, 0).await?;
+                store.save_tree(TREE).await?;
+                Ok::<_, json_store::error::JsonStoreError>(store)
+            });
+            let store = store.expect("a filled store");
+
+            group.throughput(Throughput::Bytes((megabytes * MB) as u64));
+            let id = BenchmarkId::new(variant.to_string(), format!("{}MB", megabytes));
+            let mut n = 0u64;
+            group.bench_function(id, |b| {
+                b.iter_custom(|iters| {
+                    runtime.block_on(async {
+                        let mut took = Duration::ZERO;
+                        for _ in 0..iters {
+                            let seq = n % count as u64 + 1;
+                            let mut doc: Value = docs.get(n + count as u64);
+                            doc["id"] = seq.into();
+                            n += 1;
+                            store.update(TREE, &doc).await.expect("an update");
+
+                            let started = Instant::now();
+                            store.save_tree(TREE).await.expect("a save");
+                            took += started.elapsed();
+                        }
+                        took
+                    })
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, save);
+criterion_main!(benches);
@@ -0,0 +1,61 @@
+mod common;
+
+use common::{Documents, Variant, TREE};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use serde_json::Value;
+
+const SIZES: [usize; 3] = [1_000, 10_000, 100_000];
+
+// Reads from trees of each size: one record deserialized (select) or shared with the
+// tree (select_shared), chosen by stepping through the sequences out of order, and
+// every record at once (select_where taking all of them).
+fn select(c: &mut Criterion) {
+    let runtime = common::runtime();
+    let docs = Documents::new(2);
+
+    for variant in Variant::ALL {
+        for size in SIZES {
+            let store = runtime
+                .block_on(common::filled(&docs, size, variant, 0))
+                .expect("a filled store");
+            // sequences run from 1; a step prime to size visits them all
+            let mut n = 0u64;
+            let mut next_seq = move || {
+                n = (n + 7_919) % size as u64;
+                n + 1
+            };
+
+            let mut group = c.benchmark_group(format!("select/{}", variant));
+            group.bench_function(BenchmarkId::new("one", size), |b| {
+                b.iter(|| {
+                    runtime
+                        .block_on(store.select::<Value>(TREE, next_seq()))
+                        .expect("a record")
+                })
+            });
+            group.bench_function(BenchmarkId::new("shared", size), |b| {
+                b.iter(|| {
+                    runtime
+                        .block_on(store.select_shared(TREE, next_seq()))
+                        .expect("a record")
+                })
+            });
+
+            group.throughput(Throughput::Elements(size as u64));
+            if size >= 100_000 {
+                group.sample_size(10);
+            }
+            group.bench_function(BenchmarkId::new("all", size), |b| {
+                b.iter(|| {
+                    runtime
+                        .block_on(store.select_where::<Value, _>(TREE, |_| true))
+                        .expect("every record")
+                })
+            });
+            group.finish();
+        }
+    }
+}
+
+criterion_group!(benches, select);
+criterion_main!(benches);
@@ -0,0 +1,336 @@
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{
+    meta::ParseNestedMeta, parse_macro_input, spanned::Spanned, Data, DeriveInput, Error, Fields,
+    LitInt, LitStr, Result,
+};
+
+// #[derive(StoreEntity)] for json_store::entity::StoreEntity:
+//
+//   #[derive(Serialize, Deserialize, StoreEntity)]
+//   #[store(tree = "users", capacity = 1000)]
+//   struct User {
+//       #[store(sequence)]
+//       id: Option<u64>,
+//       #[store(unique)]
+//       email: String,
+//       #[store(unique(group = "name_org"))]
+//       name: String,
+//       #[store(unique(group = "name_org"))]
+//       org_id: u64,
+//   }
+//
+// A plain unique field is a constraint named after the field; fields sharing a group
+// form one constraint over all of them, in declaration order. Field names follow
+// serde's rename and rename_all, as that is what ends up in the records. Whatever
+// Info::validate would refuse is a compile error here, so info() never fails.
+#[proc_macro_derive(StoreEntity, attributes(store))]
+pub fn derive_store_entity(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> Result<proc_macro2::TokenStream> {
+    let ident = &input.ident;
+
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new_spanned(
+            ident,
+            "StoreEntity can only be derived for structs",
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(Error::new_spanned(
+            ident,
+            "StoreEntity needs a struct with named fields",
+        ));
+    };
+
+    let mut tree = None;
+    let mut capacity = None;
+    let mut rename_all = None;
+    for attr in input.attrs.iter() {
+        if attr.path().is_ident("store") {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("tree") {
+                    set(&meta, &mut tree, meta.value()?.parse::<LitStr>()?)
+                } else if meta.path.is_ident("capacity") {
+                    let literal = meta.value()?.parse::<LitInt>()?;
+                    let value = literal.base10_parse::<u32>()?;
+                    if value == 0 {
+                        return Err(Error::new_spanned(literal, "capacity must be at least 1"));
+                    }
+                    set(&meta, &mut capacity, value)
+                } else {
+                    Err(meta.error("expected `tree` or `capacity`"))
+                }
+            })?;
+        } else if attr.path().is_ident("serde") {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename_all") {
+                    rename_all = Some(serde_rename(&meta)?);
+                    Ok(())
+                } else {
+                    skip(&meta)
+                }
+            })?;
+        }
+    }
+    let Some(tree) = tree else {
+        return Err(Error::new(
+            Span::call_site(),
+            "missing #[store(tree = \"...\")] on the struct",
+        ));
+    };
+
+    let mut sequence = None;
+    // constraint name, its fields and whether it is a group, in order of appearance
+    let mut uniques: Vec<(String, Vec<Field>, bool)> = Vec::new();
+    for field in fields.named.iter() {
+        let field_ident = field.ident.as_ref().unwrap();
+
+        let mut name = None;
+        let mut is_sequence = false;
+        let mut unique = None;
+        let mut skipped = None;
+        for attr in field.attrs.iter() {
+            if attr.path().is_ident("store") {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("sequence") {
+                        if is_sequence {
+                            return Err(meta.error("duplicate `sequence`"));
+                        }
+                        is_sequence = true;
+                        Ok(())
+                    } else if meta.path.is_ident("unique") {
+                        if unique.is_some() {
+                            return Err(meta.error("duplicate `unique`"));
+                        }
+                        let mut group = None;
+                        if meta.input.peek(syn::token::Paren) {
+                            meta.parse_nested_meta(|inner| {
+                                if inner.path.is_ident("group") {
+                                    set(&inner, &mut group, inner.value()?.parse::<LitStr>()?)
+                                } else {
+                                    Err(inner.error("expected `group`"))
+                                }
+                            })?;
+                        }
+                        unique = Some((group, meta.path.span()));
+                        Ok(())
+                    } else {
+                        Err(meta.error("expected `sequence` or `unique`"))
+                    }
+                })?;
+            } else if attr.path().is_ident("serde") {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("rename") {
+                        name = Some(serde_rename(&meta)?);
+                        Ok(())
+                    } else if ["skip", "skip_serializing", "flatten"]
+                        .iter()
+                        .any(|option| meta.path.is_ident(option))
+                    {
+                        skipped = Some(meta.path.span());
+                        skip(&meta)
+                    } else {
+                        skip(&meta)
+                    }
+                })?;
+            }
+        }
+
+        let name = name.unwrap_or_else(|| {
+            let name = field_ident.to_string();
+            let name = name.strip_prefix("r#").unwrap_or(&name);
+            match &rename_all {
+                Some(rule) => apply_rename_all(rule, name),
+                None => name.to_string(),
+            }
+        });
+
+        if is_sequence || unique.is_some() {
+            // Info::validate would only find these when info() is called
+            if name.is_empty() {
+                return Err(Error::new_spanned(
+                    field_ident,
+                    "the field is serialized under an empty name",
+                ));
+            }
+            if let Some(span) = skipped {
+                return Err(Error::new(
+                    span,
+                    "a skipped or flattened field is not in the record, so it can't be the sequence or unique",
+                ));
+            }
+        }
+
+        if is_sequence {
+            if unique.is_some() {
+                return Err(Error::new_spanned(
+                    field_ident,
+                    "the sequence field is unique already; drop `unique`",
+                ));
+            }
+            if sequence.is_some() {
+                return Err(Error::new_spanned(
+                    field_ident,
+                    "only one field can be the sequence",
+                ));
+            }
+            sequence = Some((field_ident.clone(), name.clone()));
+        }
+
+        if let Some((group, span)) = unique {
+            let (constraint, grouped) = match group {
+                Some(group) => (group.value(), true),
+                None => (name.clone(), false),
+            };
+            match uniques.iter_mut().find(|(c, _, _)| *c == constraint) {
+                Some((_, fields, true)) if grouped => {
+                    if fields.iter().any(|(field, _)| *field == name) {
+                        return Err(Error::new_spanned(
+                            field_ident,
+                            format!(
+                                "'{}' is in unique constraint '{}' already",
+                                name, constraint
+                            ),
+                        ));
+                    }
+                    fields.push((name, field_ident.span()))
+                }
+                Some(_) => {
+                    return Err(Error::new(
+                        span,
+                        format!("unique constraint '{}' is defined twice", constraint),
+                    ))
+                }
+                None => uniques.push((constraint, vec![(name, field_ident.span())], grouped)),
+            }
+        }
+    }
+
+    let Some((sequence_ident, sequence_name)) = sequence else {
+        return Err(Error::new(
+            Span::call_site(),
+            "missing #[store(sequence)] on the field holding the record's sequence",
+        ));
+    };
+
+    // a unique field renamed to what the sequence field is serialized as
+    for (constraint, fields, _) in uniques.iter() {
+        if let Some((_, span)) = fields.iter().find(|(field, _)| *field == sequence_name) {
+            return Err(Error::new(
+                *span,
+                format!(
+                    "unique constraint '{}' has '{}', the sequence field",
+                    constraint, sequence_name
+                ),
+            ));
+        }
+    }
+
+    let uniques = uniques.iter().map(|(constraint, fields, _)| {
+        let fields = fields.iter().map(|(field, _)| field);
+        quote! { .unique(#constraint, [#(#fields),*]) }
+    });
+    let capacity = capacity.map(|capacity| quote! { .capacity(#capacity) });
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::json_store::entity::StoreEntity for #ident #ty_generics #where_clause {
+            fn tree_name() -> &'static str {
+                #tree
+            }
+
+            fn info() -> ::json_store::store::Info {
+                ::json_store::store::Info::builder()
+                    .sequence_field(#sequence_name)
+                    #(#uniques)*
+                    #capacity
+                    .build()
+                    .expect("the derive refuses each Info that Info::validate would")
+            }
+
+            fn sequence(&self) -> ::core::option::Option<u64> {
+                ::core::convert::Into::<::core::option::Option<u64>>::into(self.#sequence_ident)
+            }
+        }
+    })
+}
+
+// a field of a unique constraint, by its serialized name and where it was declared
+type Field = (String, Span);
+
+fn set<T>(meta: &ParseNestedMeta, slot: &mut Option<T>, value: T) -> Result<()> {
+    if slot.is_some() {
+        return Err(meta.error("duplicate option"));
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+// the serialized name from `rename = "x"` or `rename(serialize = "x", ...)`
+fn serde_rename(meta: &ParseNestedMeta) -> Result<String> {
+    if meta.input.peek(syn::Token![=]) {
+        return Ok(meta.value()?.parse::<LitStr>()?.value());
+    }
+
+    let mut name = None;
+    meta.parse_nested_meta(|inner| {
+        if inner.path.is_ident("serialize") {
+            name = Some(inner.value()?.parse::<LitStr>()?.value());
+            Ok(())
+        } else {
+            skip(&inner)
+        }
+    })?;
+    name.ok_or_else(|| meta.error("expected `serialize = \"...\"`"))
+}
+
+// step over a serde option this derive doesn't care about
+fn skip(meta: &ParseNestedMeta) -> Result<()> {
+    if meta.input.peek(syn::Token![=]) {
+        meta.value()?.parse::<syn::Expr>()?;
+    } else if meta.input.peek(syn::token::Paren) {
+        meta.parse_nested_meta(|inner| skip(&inner))?;
+    }
+    Ok(())
+}
+
+// serde's rename_all rules, applied to a snake_case field name
+fn apply_rename_all(rule: &str, field: &str) -> String {
+    let pascal = || {
+        field
+            .split('_')
+            .map(|word| {
+                let mut chars = word.chars();
+                match chars.next() {
+                    Some(first) => first.to_uppercase().chain(chars).collect(),
+                    None => String::new(),
+                }
+            })
+            .collect::<String>()
+    };
+
+    match rule {
+        "lowercase" => field.to_lowercase(),
+        "UPPERCASE" => field.to_uppercase(),
+        "PascalCase" => pascal(),
+        "camelCase" => {
+            let pascal = pascal();
+            let mut chars = pascal.chars();
+            match chars.next() {
+                Some(first) => first.to_lowercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        }
+        "SCREAMING_SNAKE_CASE" => field.to_uppercase(),
+        "kebab-case" => field.replace('_', "-"),
+        "SCREAMING-KEBAB-CASE" => field.to_uppercase().replace('_', "-"),
+        _ => field.to_string(),
+    }
+}
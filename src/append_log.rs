@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+
+use crate::{backend::StorageBackend, error::JsonStoreError, key::Records, wal};
+
+// One line of an append-log tree file. Updates append the full new value and
+// deletes append a tombstone; loading keeps the last line for each sequence.
+#[derive(Serialize, Deserialize, Debug)]
+struct LogRecord<V> {
+    seq: u64,
+    deleted: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    value: Option<V>,
+}
+
+pub(crate) async fn append(
+    backend: &dyn StorageBackend,
+    key: &str,
+    seq: u64,
+    value: Option<&Value>,
+    fsync: bool,
+) -> Result<(), JsonStoreError> {
+    let record = LogRecord {
+        seq,
+        deleted: value.is_none(),
+        value,
+    };
+    wal::append_line(backend, key, serde_json::to_vec(&record)?, fsync).await
+}
+
+// Fold the log at key into data, returning the number of lines read.
+pub(crate) async fn fold(
+    backend: &dyn StorageBackend,
+    key: &str,
+    data: &mut Records,
+    sequence: &mut u64,
+    repair: bool,
+) -> Result<usize, JsonStoreError> {
+    wal::read_lines(backend, key, repair, |record: LogRecord<Value>| {
+        *sequence = (*sequence).max(record.seq);
+        match record.value {
+            Some(value) if !record.deleted => {
+                data.insert(record.seq, Arc::new(value));
+            }
+            _ => {
+                data.remove(&record.seq);
+            }
+        }
+    })
+    .await
+}
+
+// the log as it would be written fresh: one line per live record, in sequence order
+pub(crate) fn encode(data: &Records) -> Result<Vec<u8>, JsonStoreError> {
+    let mut seqs = data.keys().collect::<Vec<_>>();
+    seqs.sort();
+
+    let mut context = Vec::new();
+    for seq in seqs {
+        let record = LogRecord {
+            seq: *seq,
+            deleted: false,
+            value: data.get(seq).map(|value| &**value),
+        };
+        serde_json::to_writer(&mut context, &record)?;
+        context.push(b'\n');
+    }
+
+    Ok(context)
+}
@@ -0,0 +1,168 @@
+use flate2::{read::GzDecoder, write::GzEncoder};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use crate::{checksum, error::JsonStoreError, io::tmp_path, meta::FORMAT_VERSION, rt};
+
+// A store archive is a gzip'd tar of the store's files, led by `manifest.json` listing
+// the format version and the sha256 of every other file in it. Files sit at the top
+// level, apart from those in the layout's tree directory and the shards of sharded
+// trees, at most two directories down.
+
+const MANIFEST_FILE: &str = "manifest.json";
+// directories a file may sit under
+const MAX_DEPTH: usize = 2;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Manifest {
+    format_version: u32,
+    files: BTreeMap<String, String>,
+}
+
+// pack the regular files of dir and its subdirectories into a new archive at file
+pub(crate) async fn pack(dir: &Path, file: &Path) -> Result<(), JsonStoreError> {
+    let dir: PathBuf = dir.into();
+    let file: PathBuf = file.into();
+
+    rt::unblock(move || {
+        let tmp = tmp_path(&file);
+        match pack_blocking(&dir, &tmp) {
+            Ok(()) => Ok(std::fs::rename(&tmp, &file)?),
+            Err(e) => {
+                let _ = std::fs::remove_file(&tmp);
+                Err(e)
+            }
+        }
+    })
+    .await?
+}
+
+fn pack_blocking(dir: &Path, file: &Path) -> Result<(), JsonStoreError> {
+    let mut files = BTreeMap::new();
+    collect(dir, "", &mut files)?;
+
+    let manifest = Manifest {
+        format_version: FORMAT_VERSION,
+        files: files
+            .iter()
+            .map(|(name, bytes)| (name.clone(), checksum::digest(bytes)))
+            .collect(),
+    };
+
+    let gz = GzEncoder::new(std::fs::File::create(file)?, flate2::Compression::default());
+    let mut tar = tar::Builder::new(gz);
+
+    append(
+        &mut tar,
+        MANIFEST_FILE,
+        &serde_json::to_vec_pretty(&manifest)?,
+    )?;
+    for (name, bytes) in files.iter() {
+        append(&mut tar, name, bytes)?;
+    }
+
+    tar.into_inner()?.finish()?.sync_all()?;
+
+    Ok(())
+}
+
+// read the files under dir into files, named by their path below the store
+fn collect(
+    dir: &Path,
+    prefix: &str,
+    files: &mut BTreeMap<String, Vec<u8>>,
+) -> Result<(), JsonStoreError> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_file() {
+            files.insert(name, std::fs::read(entry.path())?);
+        } else if entry.file_type()?.is_dir() && name.matches('/').count() < MAX_DEPTH {
+            collect(&entry.path(), &format!("{}/", name), files)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn append<W: std::io::Write>(
+    tar: &mut tar::Builder<W>,
+    name: &str,
+    bytes: &[u8],
+) -> Result<(), JsonStoreError> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(bytes.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    tar.append_data(&mut header, name, bytes)?;
+    Ok(())
+}
+
+// Read the archive at file and check it against its manifest, returning its files.
+// Nothing is written.
+pub(crate) async fn unpack(file: &Path) -> Result<BTreeMap<String, Vec<u8>>, JsonStoreError> {
+    let file: PathBuf = file.into();
+
+    rt::unblock(move || unpack_blocking(&file)).await?
+}
+
+fn unpack_blocking(file: &Path) -> Result<BTreeMap<String, Vec<u8>>, JsonStoreError> {
+    let invalid = |reason: String| JsonStoreError::InvalidArchive {
+        path: file.into(),
+        reason,
+    };
+
+    let mut tar = tar::Archive::new(GzDecoder::new(std::fs::File::open(file)?));
+
+    let mut files = BTreeMap::new();
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+
+        // at most MAX_DEPTH directories deep and nothing hidden; anything else could
+        // write outside the destination
+        let parts = name.split('/').collect::<Vec<_>>();
+        if parts.len() > MAX_DEPTH + 1
+            || name.contains('\\')
+            || parts.iter().any(|p| p.is_empty() || p.starts_with('.'))
+        {
+            return Err(invalid(format!("unexpected entry '{}'", name)));
+        }
+
+        let mut bytes = Vec::new();
+        entry.read_to_end(&mut bytes)?;
+        files.insert(name, bytes);
+    }
+
+    let manifest = files
+        .remove(MANIFEST_FILE)
+        .ok_or_else(|| invalid(format!("{} not found", MANIFEST_FILE)))?;
+    let manifest: Manifest = serde_json::from_slice(&manifest)?;
+
+    if manifest.format_version > FORMAT_VERSION {
+        return Err(JsonStoreError::UnsupportedFormatVersion {
+            found: manifest.format_version,
+            supported: FORMAT_VERSION,
+        });
+    }
+
+    for (name, expected) in manifest.files.iter() {
+        match files.get(name) {
+            Some(bytes) if checksum::digest(bytes) == *expected => {}
+            Some(_) => return Err(invalid(format!("'{}' does not match its checksum", name))),
+            None => return Err(invalid(format!("'{}' is missing", name))),
+        }
+    }
+    if let Some(name) = files
+        .keys()
+        .find(|name| !manifest.files.contains_key(*name))
+    {
+        return Err(invalid(format!("'{}' is not in the manifest", name)));
+    }
+
+    Ok(files)
+}
@@ -0,0 +1,74 @@
+use futures::{
+    channel::oneshot,
+    future::{self, Either},
+};
+use std::{pin::pin, time::Duration};
+
+use crate::{
+    error::JsonStoreError,
+    rt::{self, Task},
+    store::JsonStore,
+    trace,
+};
+
+// Background task saving changed trees every interval. Dropping the handle stops
+// the task without a final save; `stop` stops it and flushes once more.
+#[derive(Debug)]
+pub struct AutosaveHandle {
+    store: JsonStore,
+    stop: Option<oneshot::Sender<()>>,
+    task: Option<Task>,
+}
+
+impl AutosaveHandle {
+    pub(crate) fn start(store: JsonStore, interval: Duration) -> Self {
+        let (stop, mut stopped) = oneshot::channel();
+        let task_store = store.clone();
+
+        let task = rt::spawn(async move {
+            // a save that overruns delays the next one rather than bunching them up
+            loop {
+                let tick = pin!(rt::sleep(interval));
+                if let Either::Left(_) = future::select(&mut stopped, tick).await {
+                    break;
+                }
+                match task_store.save().await {
+                    Ok(saved) if saved.is_empty() => {}
+                    Ok(saved) => trace::debug!(trees = ?saved, "autosaved"),
+                    Err(JsonStoreError::StoreClosed | JsonStoreError::ReadOnlyStore) => break,
+                    Err(e) => trace::error!(error = %e, "autosave failed"),
+                }
+            }
+        });
+
+        Self {
+            store,
+            stop: Some(stop),
+            task: Some(task),
+        }
+    }
+
+    // stop the task and perform one final save
+    pub async fn stop(mut self) -> Result<(), JsonStoreError> {
+        self.halt().await;
+        self.store.save().await?;
+        Ok(())
+    }
+
+    async fn halt(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        if let Some(task) = self.task.take() {
+            task.join().await;
+        }
+    }
+}
+
+impl Drop for AutosaveHandle {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+    }
+}
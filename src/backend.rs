@@ -0,0 +1,297 @@
+use futures::future::BoxFuture;
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    error::JsonStoreError,
+    io::{read_bytes, remove_file_if_exists, write_text},
+    rt::{fs, Instant},
+    store::Durability,
+    trace, wal,
+};
+
+// Where a store keeps its files. Keys are the logical file names of the store
+// (`infos.json`, `users.seq`, `users.json`, ...); a key with a `/` in it belongs to
+// a nested group such as a migration backup. Backends report their own failures
+// as JsonStoreError::Backend.
+pub trait StorageBackend: Debug + Send + Sync {
+    // contents of key, or None if it does not exist
+    fn read<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>, JsonStoreError>>;
+
+    // Replace key with bytes. With any durability but None a failed write must leave
+    // the previous contents intact.
+    fn write<'a>(
+        &'a self,
+        key: &'a str,
+        bytes: Vec<u8>,
+        durability: Durability,
+    ) -> BoxFuture<'a, Result<(), JsonStoreError>>;
+
+    // add bytes to the end of key, creating it if missing
+    fn append<'a>(
+        &'a self,
+        key: &'a str,
+        bytes: Vec<u8>,
+        fsync: bool,
+    ) -> BoxFuture<'a, Result<(), JsonStoreError>>;
+
+    // remove key; a missing key is not an error
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), JsonStoreError>>;
+
+    // every top-level key, sorted
+    fn list(&self) -> BoxFuture<'_, Result<Vec<String>, JsonStoreError>>;
+
+    // identity of key's current contents, to notice changes made by someone else
+    fn stamp<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Stamp>, JsonStoreError>>;
+
+    // where key lives, for errors and logs
+    fn location(&self, key: &str) -> PathBuf;
+
+    // Called once a save or catalog change has written all its files; backends that
+    // keep an index of their keys bring it up to date here.
+    fn sync(&self) -> BoxFuture<'_, Result<(), JsonStoreError>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stamp {
+    modified: SystemTime,
+    len: u64,
+}
+
+impl Stamp {
+    pub fn new(modified: SystemTime, len: u64) -> Self {
+        Self { modified, len }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn modified(&self) -> SystemTime {
+        self.modified
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+// The store's files in a directory, written as JsonStore always has: through a temp
+// file and rename, fsync'd as durability asks.
+#[derive(Debug, Clone)]
+pub struct FsBackend {
+    root: PathBuf,
+}
+
+impl FsBackend {
+    pub fn new(root: &Path) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+}
+
+impl StorageBackend for FsBackend {
+    fn read<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>, JsonStoreError>> {
+        Box::pin(async move {
+            let file = self.root.join(key);
+            read_bytes(&file)
+                .await
+                .map_err(JsonStoreError::reading(&file))
+        })
+    }
+
+    fn write<'a>(
+        &'a self,
+        key: &'a str,
+        bytes: Vec<u8>,
+        durability: Durability,
+    ) -> BoxFuture<'a, Result<(), JsonStoreError>> {
+        Box::pin(async move {
+            let file = self.root.join(key);
+            if key.contains('/') {
+                if let Some(dir) = file.parent() {
+                    fs::create_dir_all(dir)
+                        .await
+                        .map_err(|e| JsonStoreError::writing(dir)(e.into()))?;
+                }
+            }
+            let started = Instant::now();
+            let len = bytes.len();
+            write_text(file.clone(), bytes, durability)
+                .await
+                .map_err(JsonStoreError::writing(&file))?;
+            trace::info!(file = ?file, bytes = len, elapsed_ms = started.elapsed().as_millis() as u64, "wrote file");
+            Ok(())
+        })
+    }
+
+    fn append<'a>(
+        &'a self,
+        key: &'a str,
+        bytes: Vec<u8>,
+        fsync: bool,
+    ) -> BoxFuture<'a, Result<(), JsonStoreError>> {
+        Box::pin(async move {
+            let file = self.root.join(key);
+            wal::append_file(&file, bytes, fsync)
+                .await
+                .map_err(JsonStoreError::writing(&file))
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), JsonStoreError>> {
+        Box::pin(async move {
+            let file = self.root.join(key);
+            remove_file_if_exists(&file)
+                .await
+                .map_err(JsonStoreError::writing(&file))?;
+            // a nested group's directory goes with its last file
+            if key.contains('/') {
+                if let Some(dir) = file.parent() {
+                    let _ = fs::remove_dir(dir).await;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<String>, JsonStoreError>> {
+        Box::pin(async move {
+            let entries = match fs::read_dir(&self.root).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+                Err(e) => return Err(e.into()),
+            };
+
+            let mut keys = Vec::new();
+            for entry in entries {
+                if entry.file_type()?.is_file() {
+                    keys.push(entry.file_name().to_string_lossy().into_owned());
+                }
+            }
+            keys.sort();
+
+            Ok(keys)
+        })
+    }
+
+    fn stamp<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Stamp>, JsonStoreError>> {
+        Box::pin(async move {
+            let file = self.root.join(key);
+            match fs::metadata(&file).await {
+                Ok(m) => Ok(Some(Stamp::new(m.modified()?, m.len()))),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(JsonStoreError::reading(&file)(e.into())),
+            }
+        })
+    }
+
+    fn location(&self, key: &str) -> PathBuf {
+        self.root.join(key)
+    }
+}
+
+// Keeps everything in memory and loses it on drop; meant for tests. Every write
+// bumps a generation counter that stands in for the modification time in stamps.
+#[derive(Debug, Default)]
+pub struct InMemoryBackend {
+    files: Mutex<Files>,
+}
+
+#[derive(Debug, Default)]
+struct Files {
+    generation: u64,
+    entries: BTreeMap<String, (Vec<u8>, Stamp)>,
+}
+
+impl Files {
+    fn put(&mut self, key: &str, bytes: Vec<u8>) {
+        self.generation += 1;
+        let modified = UNIX_EPOCH + Duration::from_nanos(self.generation);
+        let stamp = Stamp::new(modified, bytes.len() as u64);
+        self.entries.insert(key.to_string(), (bytes, stamp));
+    }
+}
+
+impl InMemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn files(&self) -> std::sync::MutexGuard<'_, Files> {
+        self.files.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl StorageBackend for InMemoryBackend {
+    fn read<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>, JsonStoreError>> {
+        let bytes = self
+            .files()
+            .entries
+            .get(key)
+            .map(|(bytes, _)| bytes.clone());
+        Box::pin(async move { Ok(bytes) })
+    }
+
+    fn write<'a>(
+        &'a self,
+        key: &'a str,
+        bytes: Vec<u8>,
+        _durability: Durability,
+    ) -> BoxFuture<'a, Result<(), JsonStoreError>> {
+        self.files().put(key, bytes);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn append<'a>(
+        &'a self,
+        key: &'a str,
+        bytes: Vec<u8>,
+        _fsync: bool,
+    ) -> BoxFuture<'a, Result<(), JsonStoreError>> {
+        let mut files = self.files();
+        let mut context = files
+            .entries
+            .remove(key)
+            .map(|(context, _)| context)
+            .unwrap_or_default();
+        context.extend_from_slice(&bytes);
+        files.put(key, context);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), JsonStoreError>> {
+        self.files().entries.remove(key);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<String>, JsonStoreError>> {
+        let keys = self
+            .files()
+            .entries
+            .keys()
+            .filter(|key| !key.contains('/'))
+            .cloned()
+            .collect();
+        Box::pin(async move { Ok(keys) })
+    }
+
+    fn stamp<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Stamp>, JsonStoreError>> {
+        let stamp = self.files().entries.get(key).map(|(_, stamp)| *stamp);
+        Box::pin(async move { Ok(stamp) })
+    }
+
+    fn location(&self, key: &str) -> PathBuf {
+        PathBuf::from(key)
+    }
+}
@@ -0,0 +1,337 @@
+use futures::{
+    channel::oneshot,
+    future::{self, Either},
+};
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fs::DirEntry,
+    path::{Path, PathBuf},
+    pin::pin,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    backend::StorageBackend,
+    error::JsonStoreError,
+    io::write_text,
+    rt::{self, fs, Task},
+    store::{Durability, JsonStore},
+    trace,
+};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BackupOptions {
+    // allow writing into a destination that already has files in it
+    pub overwrite: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct BackupReport {
+    pub path: PathBuf,
+    // file name -> bytes copied
+    pub files: BTreeMap<String, u64>,
+    pub created_at: SystemTime,
+}
+
+impl BackupReport {
+    pub fn total_bytes(&self) -> u64 {
+        self.files.values().sum()
+    }
+}
+
+// create dest if missing and refuse a non-empty one unless overwriting
+pub(crate) async fn prepare(dest: &Path, overwrite: bool) -> Result<(), JsonStoreError> {
+    fs::create_dir_all(dest).await?;
+
+    if !overwrite && !fs::read_dir(dest).await?.is_empty() {
+        return Err(JsonStoreError::BackupDestinationNotEmpty(dest.into()));
+    }
+
+    Ok(())
+}
+
+// copy key from backend to dest/key; returns the size copied, or None if there was no such key
+pub(crate) async fn copy(
+    backend: &dyn StorageBackend,
+    dest: &Path,
+    key: &str,
+) -> Result<Option<u64>, JsonStoreError> {
+    let Some(context) = backend.read(key).await? else {
+        return Ok(None);
+    };
+    let len = context.len() as u64;
+    let file = dest.join(key);
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir).await?;
+    }
+    write_text(file, context, Durability::None).await?;
+
+    Ok(Some(len))
+}
+
+// Replace the files in path with those in backup. The backup is copied into a staging
+// directory first, so a failed copy leaves path as it was; then the current files
+// move aside into `.pre-restore-{millis}/` and the staged ones are renamed in. Renames
+// that fail part way are rolled back. tree_dirs names the directories holding tree
+// files under either store's layout.
+pub(crate) async fn restore(
+    path: &Path,
+    backup: &Path,
+    tree_dirs: &[String],
+) -> Result<(), JsonStoreError> {
+    fs::create_dir_all(path).await?;
+
+    let millis = rt::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+
+    let staging = path.join(format!(".restore-{}.tmp", millis));
+    if let Err(e) = copy_files(backup, &staging, tree_dirs).await {
+        let _ = fs::remove_dir_all(&staging).await;
+        return Err(e);
+    }
+
+    let aside = path.join(format!(".pre-restore-{}", millis));
+    fs::create_dir(&aside).await?;
+
+    let mut moved_aside = Vec::new();
+    if let Err(e) = move_files(path, &aside, &mut moved_aside, tree_dirs).await {
+        move_back(&aside, path, &moved_aside).await;
+        let _ = fs::remove_dir_all(&staging).await;
+        return Err(e);
+    }
+
+    let mut moved_in = Vec::new();
+    if let Err(e) = move_files(&staging, path, &mut moved_in, tree_dirs).await {
+        for name in moved_in.iter() {
+            let file = path.join(name);
+            if fs::remove_file(&file).await.is_err() {
+                let _ = fs::remove_dir_all(&file).await;
+            }
+        }
+        move_back(&aside, path, &moved_aside).await;
+        let _ = fs::remove_dir_all(&staging).await;
+        return Err(e);
+    }
+
+    fs::remove_dir(&staging).await?;
+
+    Ok(())
+}
+
+// copy the store files of src into dest, creating it
+async fn copy_files(src: &Path, dest: &Path, tree_dirs: &[String]) -> Result<(), JsonStoreError> {
+    fs::create_dir_all(dest).await?;
+
+    for entry in fs::read_dir(src).await? {
+        if entry.file_type()?.is_file() {
+            fs::copy(entry.path(), dest.join(entry.file_name())).await?;
+        } else if is_store_dir(&entry, tree_dirs).await? {
+            Box::pin(copy_files(
+                &entry.path(),
+                &dest.join(entry.file_name()),
+                &[],
+            ))
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+// rename the store files of src into dest, recording each one moved
+async fn move_files(
+    src: &Path,
+    dest: &Path,
+    moved: &mut Vec<OsString>,
+    tree_dirs: &[String],
+) -> Result<(), JsonStoreError> {
+    for entry in fs::read_dir(src).await? {
+        if entry.file_type()?.is_file() || is_store_dir(&entry, tree_dirs).await? {
+            fs::rename(entry.path(), dest.join(entry.file_name())).await?;
+            moved.push(entry.file_name());
+        }
+    }
+
+    Ok(())
+}
+
+// the layout's tree directory, or the `{tree}/` directory of a sharded tree, told
+// apart from other directories a store path may hold (such as backups) by its
+// `part-` files
+async fn is_store_dir(entry: &DirEntry, tree_dirs: &[String]) -> Result<bool, JsonStoreError> {
+    let name = entry.file_name().to_string_lossy().into_owned();
+    if !entry.file_type()?.is_dir() || name.starts_with('.') {
+        return Ok(false);
+    }
+    if tree_dirs.contains(&name) {
+        return Ok(true);
+    }
+
+    for entry in fs::read_dir(entry.path()).await? {
+        if entry.file_name().to_string_lossy().starts_with("part-") {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+async fn move_back(aside: &Path, path: &Path, moved: &[OsString]) {
+    for name in moved {
+        if let Err(e) = fs::rename(aside.join(name), path.join(name)).await {
+            trace::error!(file = ?name, error = %e, "failed to move file back after aborted restore");
+        }
+    }
+}
+
+// Rotated backups live in `backup-YYYY-MM-DDTHH-MM-SS-mmm/` (UTC) subdirectories, so
+// sorting by name sorts by age whatever the file mtimes say.
+const ROTATED_PREFIX: &str = "backup-";
+
+// names taken by backups in the same millisecond get a counter up to this after them
+const ROTATED_COUNTER_MAX: u32 = 999;
+
+pub(crate) fn rotated_name(time: SystemTime) -> String {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since.as_secs();
+    let (days, rem) = (secs / 86400, secs % 86400);
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{}{:04}-{:02}-{:02}T{:02}-{:02}-{:02}-{:03}",
+        ROTATED_PREFIX,
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60,
+        since.subsec_millis()
+    )
+}
+
+// Create the directory in dir for a rotated backup at time and return it. Should its
+// name be taken, by a backup in the same millisecond or from before the clock went
+// back, a counter goes after it; creating the directory claims the name, so two
+// backups at once never share one.
+pub(crate) async fn claim_rotated(dir: &Path, time: SystemTime) -> Result<PathBuf, JsonStoreError> {
+    fs::create_dir_all(dir).await?;
+    let name = rotated_name(time);
+    for n in 0..=ROTATED_COUNTER_MAX {
+        let path = match n {
+            0 => dir.join(&name),
+            n => dir.join(format!("{}-{:03}", name, n)),
+        };
+        match fs::create_dir(&path).await {
+            Ok(()) => return Ok(path),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Err(JsonStoreError::BackupDestinationNotEmpty(dir.join(name)))
+}
+
+fn is_rotated_name(name: &str) -> bool {
+    let stamp = match name.strip_prefix(ROTATED_PREFIX) {
+        Some(stamp) => stamp.as_bytes(),
+        None => return false,
+    };
+    // YYYY-MM-DDTHH-MM-SS, then -mmm and a -nnn counter; backups from before
+    // milliseconds were named have neither
+    matches!(stamp.len(), 19 | 23 | 27)
+        && stamp.iter().enumerate().all(|(i, b)| match i {
+            4 | 7 | 13 | 16 | 19 | 23 => *b == b'-',
+            10 => *b == b'T',
+            _ => b.is_ascii_digit(),
+        })
+}
+
+// days since 1970-01-01 to (year, month, day), after Howard Hinnant's algorithm
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+// Remove all but the newest keep rotated backups in dir, returning the removed paths.
+// Only directories named like a rotated backup are considered.
+pub(crate) async fn prune(dir: &Path, keep: usize) -> Result<Vec<PathBuf>, JsonStoreError> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir).await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_dir() && is_rotated_name(&name) {
+            names.push(name);
+        }
+    }
+
+    names.sort();
+
+    let excess = names.len().saturating_sub(keep);
+    let mut removed = Vec::with_capacity(excess);
+    for name in names.into_iter().take(excess) {
+        let path = dir.join(name);
+        fs::remove_dir_all(&path).await?;
+        removed.push(path);
+    }
+
+    Ok(removed)
+}
+
+// Background task taking a rotated backup every interval, starting right away.
+// Dropping the handle stops the task; `stop` also waits for a backup in progress.
+#[derive(Debug)]
+pub struct BackupScheduleHandle {
+    stop: Option<oneshot::Sender<()>>,
+    task: Option<Task>,
+}
+
+impl BackupScheduleHandle {
+    pub(crate) fn start(store: JsonStore, dir: PathBuf, interval: Duration, keep: usize) -> Self {
+        let (stop, mut stopped) = oneshot::channel();
+
+        let task = rt::spawn(async move {
+            loop {
+                match store.backup_rotated(&dir, keep).await {
+                    Ok(report) => trace::debug!(path = ?report.path, "backed up"),
+                    Err(JsonStoreError::StoreClosed) => break,
+                    Err(e) => trace::error!(error = %e, "scheduled backup failed"),
+                }
+                let tick = pin!(rt::sleep(interval));
+                if let Either::Left(_) = future::select(&mut stopped, tick).await {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            stop: Some(stop),
+            task: Some(task),
+        }
+    }
+
+    pub async fn stop(mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        if let Some(task) = self.task.take() {
+            task.join().await;
+        }
+    }
+}
+
+impl Drop for BackupScheduleHandle {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+    }
+}
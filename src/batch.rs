@@ -0,0 +1,88 @@
+use futures::channel::oneshot;
+use serde_json::Value;
+use std::{mem, sync::Mutex};
+
+use crate::{error::JsonStoreError, store::Info};
+
+// Micro-batching of a tree's plain inserts (at reserved sequences too), updates and
+// deletes, set by LoadOptions::write_batching. A batched write is queued on its tree
+// rather than taking the tree's write lock itself; the first one into an idle queue
+// starts a task that takes the lock once and applies whatever is queued, in the order
+// it was queued, until the queue is empty, handing each write its own result. Under
+// many writers to one tree that saves a lock handoff, and a wakeup, per write.
+//
+// A write goes through exactly the checks and steps it would on its own, so its result
+// is the same and another's failure doesn't touch it; the writes of one caller, each
+// awaited before the next, keep their order. Only the order of writes racing each
+// other may differ, as it may anyway. A queued write is applied even if its caller
+// stops waiting for it.
+//
+// Writes on behalf of a record lock owner, and every write to a partitioned tree or
+// one with a resident limit or Raw records, take the lock as ever.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WriteBatching {
+    // every write takes the lock itself
+    #[default]
+    Off,
+    // queue a write when its tree's lock is held or others are queued already
+    Contended,
+    // queue every write that can be
+    Always,
+}
+
+// a write waiting in a queue, checked as far as it can be without the lock
+#[derive(Debug)]
+pub(crate) enum BatchOp {
+    // at the next sequence, or the reserved one given
+    Insert(Value, Option<u64>),
+    Update(u64, Value),
+    Delete(u64),
+}
+
+// Inserts give their new sequence, the others the one they wrote.
+pub(crate) type BatchResult = Result<u64, JsonStoreError>;
+
+#[derive(Debug)]
+pub(crate) struct Queued {
+    pub(crate) info: Info,
+    pub(crate) op: BatchOp,
+    pub(crate) done: oneshot::Sender<BatchResult>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct WriteQueue {
+    state: Mutex<QueueState>,
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    queued: Vec<Queued>,
+    // a task is applying the queue
+    draining: bool,
+}
+
+impl WriteQueue {
+    // queue a write; true if no task is draining the queue, so the caller must start one
+    pub(crate) fn push(&self, queued: Queued) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.queued.push(queued);
+        !mem::replace(&mut state.draining, true)
+    }
+
+    // everything queued, oldest first; None once there is nothing, which ends the drain
+    pub(crate) fn take(&self) -> Option<Vec<Queued>> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.queued.is_empty() {
+            state.draining = false;
+            return None;
+        }
+        Some(mem::take(&mut state.queued))
+    }
+
+    pub(crate) fn is_draining(&self) -> bool {
+        self.state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .draining
+    }
+}
@@ -0,0 +1,7 @@
+use std::process::ExitCode;
+
+// see json_store::cli for the commands
+#[tokio::main]
+async fn main() -> ExitCode {
+    json_store::cli::main(std::env::args_os()).await
+}
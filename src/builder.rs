@@ -0,0 +1,206 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    batch::WriteBatching,
+    clock::Clock,
+    codec::Codec,
+    error::JsonStoreError,
+    layout::Layout,
+    repair::CorruptionPolicy,
+    store::{Durability, FlushPolicy, JsonStore, LoadOptions, OutputFormat},
+    wal::WalOptions,
+};
+
+// Every open-time option of a store directory in one chain, checked together by
+// open(). JsonStore::load(path) is this with nothing set. The defaults:
+//
+//   durability          Flush: temp file and rename, no fsync
+//   format              Compact JSON
+//   wal                 off
+//   read_only           false
+//   replica             false
+//   clock               the system clock
+//   codec, layout       whatever the store already uses; JSON and the flat layout
+//                       for a new store
+//   eager               false: trees are read on first use
+//   create_if_missing   true
+//   corruption_policy   Fail
+//   metrics             off
+//   undo_depth          16 mutations per tree
+//   load_concurrency    trees read at once by an eager load or preload, 8
+//   incremental_save    off: saves write whole snapshots
+//   shrink_below        off
+//   spill_dir           the system's temporary directory
+//   write_batching      Off
+//   flush_policy        Manual: only save calls write
+#[derive(Debug, Clone)]
+pub struct JsonStoreBuilder {
+    path: PathBuf,
+    options: LoadOptions,
+    flush_policy: FlushPolicy,
+}
+
+impl JsonStoreBuilder {
+    pub(crate) fn new(path: &Path) -> Self {
+        Self {
+            path: path.into(),
+            options: LoadOptions::default(),
+            flush_policy: FlushPolicy::default(),
+        }
+    }
+
+    pub fn durability(mut self, durability: Durability) -> Self {
+        self.options.durability = durability;
+        self
+    }
+
+    pub fn format(mut self, format: OutputFormat) -> Self {
+        self.options.format = format;
+        self
+    }
+
+    pub fn wal(mut self, wal: WalOptions) -> Self {
+        self.options.wal = Some(wal);
+        self
+    }
+
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.options.read_only = read_only;
+        self
+    }
+
+    pub fn replica(mut self, replica: bool) -> Self {
+        self.options.replica = replica;
+        self
+    }
+
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.options.clock = Some(clock);
+        self
+    }
+
+    pub fn codec(mut self, codec: Codec) -> Self {
+        self.options.codec = Some(codec);
+        self
+    }
+
+    pub fn eager(mut self, eager: bool) -> Self {
+        self.options.eager = eager;
+        self
+    }
+
+    pub fn create_if_missing(mut self, create: bool) -> Self {
+        self.options.create_if_missing = create;
+        self
+    }
+
+    pub fn layout(mut self, layout: Layout) -> Self {
+        self.options.layout = Some(layout);
+        self
+    }
+
+    pub fn corruption_policy(mut self, policy: CorruptionPolicy) -> Self {
+        self.options.corruption_policy = policy;
+        self
+    }
+
+    pub fn metrics(mut self, metrics: bool) -> Self {
+        self.options.metrics = metrics;
+        self
+    }
+
+    pub fn undo_depth(mut self, depth: usize) -> Self {
+        self.options.undo_depth = depth;
+        self
+    }
+
+    pub fn load_concurrency(mut self, trees: usize) -> Self {
+        self.options.load_concurrency = trees;
+        self
+    }
+
+    // save only the changed records while they are at most this fraction of a tree
+    pub fn incremental_save(mut self, fraction: f64) -> Self {
+        self.options.incremental_save = Some(fraction);
+        self
+    }
+
+    pub fn shrink_below(mut self, fraction: f64) -> Self {
+        self.options.shrink_below = Some(fraction);
+        self
+    }
+
+    // where trees with a resident limit spill records
+    pub fn spill_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.spill_dir = Some(dir.into());
+        self
+    }
+
+    pub fn write_batching(mut self, batching: WriteBatching) -> Self {
+        self.options.write_batching = batching;
+        self
+    }
+
+    // store-wide flush policy, as set_flush_policy
+    pub fn flush_policy(mut self, policy: FlushPolicy) -> Self {
+        self.flush_policy = policy;
+        self
+    }
+
+    pub async fn open(self) -> Result<JsonStore, JsonStoreError> {
+        self.validate()?;
+
+        let store = JsonStore::load_with_options(&self.path, self.options).await?;
+        store.set_flush_policy(self.flush_policy);
+
+        Ok(store)
+    }
+
+    fn validate(&self) -> Result<(), JsonStoreError> {
+        let invalid = |reason: &str| Err(JsonStoreError::InvalidOptions(reason.to_string()));
+        let options = &self.options;
+
+        if options
+            .incremental_save
+            .is_some_and(|f| !(f > 0.0 && f <= 1.0))
+        {
+            return invalid(
+                "incremental_save is a fraction of a tree's records, above 0 and at most 1",
+            );
+        }
+        if options.shrink_below.is_some_and(|f| !(f > 0.0 && f < 1.0)) {
+            return invalid("shrink_below is a fraction of the room kept, above 0 and below 1");
+        }
+
+        if options.read_only {
+            if self.options.wal.is_some() {
+                return invalid("a read-only store can't keep a write-ahead log");
+            }
+            if self.flush_policy != FlushPolicy::Manual {
+                return invalid("a read-only store never saves, so it takes no flush policy");
+            }
+            if self.options.replica {
+                return invalid("a read-only store can't sync, so it can't be a replica");
+            }
+            if options.incremental_save.is_some() {
+                return invalid("a read-only store never saves, incrementally or not");
+            }
+            if options.write_batching != WriteBatching::Off || options.shrink_below.is_some() {
+                return invalid("a read-only store takes no writes to batch or shrink after");
+            }
+            // elsewhere spilled records are out of the store's way
+            if options
+                .spill_dir
+                .as_ref()
+                .is_some_and(|dir| dir.starts_with(&self.path))
+            {
+                return invalid("a read-only store can't spill records into its own directory");
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,150 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    sync::{Arc, Mutex, Weak},
+};
+
+// The typed read cache behind TreeHandle::select_cached: records already deserialized,
+// by sequence, the least recently read going first once it is full. A tree keeps a
+// weak reference to each cache filled from it and drops a record from them when it
+// writes the record, under its write lock, and every record when its contents are
+// replaced wholesale. A cache is filled under the tree's read lock, so no write can
+// land between reading a record and caching it.
+
+// what a tree needs of the caches over it, whatever their type
+pub(crate) trait Invalidate: Send + Sync {
+    fn invalidate(&self, seq: u64);
+    fn clear(&self);
+}
+
+#[derive(Debug)]
+pub(crate) struct ReadCache<T> {
+    capacity: usize,
+    lru: Mutex<Lru<T>>,
+}
+
+#[derive(Debug)]
+struct Lru<T> {
+    // each record with the tick it was last read at
+    entries: HashMap<u64, (Arc<T>, u64)>,
+    // sequences by that tick, oldest first
+    order: BTreeMap<u64, u64>,
+    tick: u64,
+}
+
+impl<T> ReadCache<T> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            lru: Mutex::new(Lru {
+                entries: HashMap::new(),
+                order: BTreeMap::new(),
+                tick: 0,
+            }),
+        }
+    }
+
+    pub(crate) fn get(&self, seq: u64) -> Option<Arc<T>> {
+        let mut lru = self.lru.lock().unwrap_or_else(|e| e.into_inner());
+        lru.tick += 1;
+        let Lru {
+            entries,
+            order,
+            tick,
+        } = &mut *lru;
+        let (value, used) = entries.get_mut(&seq)?;
+        order.remove(used);
+        order.insert(*tick, seq);
+        *used = *tick;
+        Some(value.clone())
+    }
+
+    pub(crate) fn insert(&self, seq: u64, value: Arc<T>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut lru = self.lru.lock().unwrap_or_else(|e| e.into_inner());
+        lru.remove(seq);
+        if lru.entries.len() >= self.capacity {
+            if let Some((_, oldest)) = lru.order.pop_first() {
+                lru.entries.remove(&oldest);
+            }
+        }
+        lru.tick += 1;
+        let tick = lru.tick;
+        lru.order.insert(tick, seq);
+        lru.entries.insert(seq, (value, tick));
+    }
+}
+
+impl<T> Lru<T> {
+    fn remove(&mut self, seq: u64) {
+        if let Some((_, used)) = self.entries.remove(&seq) {
+            self.order.remove(&used);
+        }
+    }
+}
+
+impl<T: Send + Sync> Invalidate for ReadCache<T> {
+    fn invalidate(&self, seq: u64) {
+        self.lru
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(seq);
+    }
+
+    fn clear(&self) {
+        let mut lru = self.lru.lock().unwrap_or_else(|e| e.into_inner());
+        lru.entries.clear();
+        lru.order.clear();
+    }
+}
+
+// The caches filled from one tree. Held in the tree, but registered to under its read
+// lock, hence the mutex.
+#[derive(Default)]
+pub(crate) struct Caches(Mutex<Vec<Weak<dyn Invalidate>>>);
+
+impl Caches {
+    pub(crate) fn register(&self, cache: &Arc<dyn Invalidate>) {
+        let mut caches = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let known = caches
+            .iter()
+            .any(|known| std::ptr::addr_eq(known.as_ptr(), Arc::as_ptr(cache)));
+        if !known {
+            caches.retain(|known| known.strong_count() > 0);
+            caches.push(Arc::downgrade(cache));
+        }
+    }
+
+    pub(crate) fn invalidate(&self, seq: u64) {
+        self.each(|cache| cache.invalidate(seq));
+    }
+
+    pub(crate) fn clear(&self) {
+        self.each(|cache| cache.clear());
+    }
+
+    fn each(&self, f: impl Fn(&dyn Invalidate)) {
+        let caches = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        for cache in caches.iter().filter_map(Weak::upgrade) {
+            f(&*cache);
+        }
+    }
+}
+
+// a copy of a tree invalidates the same caches
+impl Clone for Caches {
+    fn clone(&self) -> Self {
+        Self(Mutex::new(
+            self.0.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+        ))
+    }
+}
+
+impl fmt::Debug for Caches {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let caches = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        write!(f, "Caches({})", caches.len())
+    }
+}
@@ -0,0 +1,292 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use serde_json::{json, Value};
+use std::{
+    ffi::OsString,
+    io::Write,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use crate::{
+    checksum::ChecksumStatus,
+    error::JsonStoreError,
+    export::ExportFormat,
+    import::{ImportMode, ImportReport, OnConflict},
+    store::{JsonStore, LoadOptions},
+};
+
+// The json-store binary of the cli feature: one command against the store in --path,
+// its result printed to stdout as JSON. A failure prints {"error", "kind"} to stderr
+// and exits with 1, as does verify finding a damaged tree.
+//
+// Read commands load the store read-only and never write to it. Commands that change
+// it (insert, delete, import) save before exiting. Nothing locks a store against
+// other processes, so don't run those against a store a running program has open:
+// whichever saves last wins, and the other's changes are lost.
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "json-store",
+    version,
+    about = "Inspect and change a json-store directory"
+)]
+pub struct Cli {
+    // the store's directory
+    #[arg(long, global = true, default_value = ".")]
+    pub path: PathBuf,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    #[command(about = "List the trees with their record counts")]
+    Trees,
+    #[command(about = "Show a tree's Info")]
+    Info { tree: String },
+    #[command(about = "Print the record at a sequence")]
+    Get { tree: String, sequence: u64 },
+    #[command(
+        about = "Print records whose fields equal those of a JSON object, in sequence order"
+    )]
+    Query {
+        tree: String,
+        #[arg(long, default_value = "{}")]
+        filter: String,
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    #[command(about = "Insert a record and print it with its sequence")]
+    Insert {
+        tree: String,
+        #[arg(long)]
+        json: String,
+    },
+    #[command(about = "Delete the record at a sequence")]
+    Delete { tree: String, sequence: u64 },
+    #[command(about = "Write a tree's records to a file")]
+    Export {
+        tree: String,
+        #[arg(long, value_enum, default_value_t = FileFormat::Json)]
+        format: FileFormat,
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    #[command(about = "Insert the records of a JSON array or JSON-lines file")]
+    Import {
+        tree: String,
+        file: PathBuf,
+        #[arg(long, value_enum, default_value_t = FileFormat::Json)]
+        format: FileFormat,
+        #[arg(
+            long,
+            help = "Keep the records' own sequences instead of handing out new ones"
+        )]
+        preserve: bool,
+        #[arg(
+            long,
+            help = "Leave out records that can't be imported instead of importing nothing"
+        )]
+        skip_conflicts: bool,
+    },
+    #[command(about = "Check every snapshot against its checksum")]
+    Verify,
+    #[command(about = "Record counts and sizes, per tree and in total")]
+    Stats,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    // a JSON array
+    Json,
+    // one record per line
+    Ndjson,
+}
+
+// what a command printed, and whether what it found was in order
+#[derive(Debug, Clone, PartialEq)]
+pub struct Output {
+    pub json: Value,
+    pub ok: bool,
+}
+
+impl From<Value> for Output {
+    fn from(json: Value) -> Self {
+        Self { json, ok: true }
+    }
+}
+
+// Parse args, the program name first, run the command and print its output.
+pub async fn main(args: impl IntoIterator<Item = impl Into<OsString> + Clone>) -> ExitCode {
+    let cli = match Cli::try_parse_from(args) {
+        Ok(cli) => cli,
+        Err(e) => {
+            // help and version come this way too
+            let _ = e.print();
+            return match e.use_stderr() {
+                true => ExitCode::from(2),
+                false => ExitCode::SUCCESS,
+            };
+        }
+    };
+
+    match run(cli).await {
+        Ok(output) => {
+            // a closed pipe, as with | head, isn't worth a panic
+            let _ = writeln!(std::io::stdout(), "{}", pretty(&output.json));
+            match output.ok {
+                true => ExitCode::SUCCESS,
+                false => ExitCode::FAILURE,
+            }
+        }
+        Err(e) => {
+            let error = json!({ "error": e.to_string(), "kind": e.kind() });
+            let _ = writeln!(std::io::stderr(), "{}", pretty(&error));
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// The command's output, without printing it.
+pub async fn run(cli: Cli) -> Result<Output, JsonStoreError> {
+    let writes = matches!(
+        cli.command,
+        Command::Insert { .. } | Command::Delete { .. } | Command::Import { .. }
+    );
+    // counts are only known for trees that are read, and these count every tree
+    let eager = matches!(cli.command, Command::Trees | Command::Stats);
+    let options = LoadOptions {
+        read_only: !writes,
+        create_if_missing: false,
+        eager,
+        ..LoadOptions::default()
+    };
+    let store = JsonStore::load_with_options(&cli.path, options).await?;
+
+    let output = command(&store, cli.command).await?;
+    if writes {
+        store.close().await?;
+    }
+    Ok(output)
+}
+
+async fn command(store: &JsonStore, command: Command) -> Result<Output, JsonStoreError> {
+    let output = match command {
+        Command::Trees => {
+            let mut trees = Vec::new();
+            for name in store.list_trees() {
+                let records = store.tree_stats(&name).await?.records;
+                trees.push(json!({ "name": name, "records": records }));
+            }
+            Value::Array(trees)
+        }
+        Command::Info { tree } => serde_json::to_value(store.get_info(&tree)?)?,
+        Command::Get { tree, sequence } => store.select(&tree, sequence).await?,
+        Command::Query {
+            tree,
+            filter,
+            limit,
+        } => {
+            let filter = match serde_json::from_str(&filter)? {
+                Value::Object(fields) => fields,
+                _ => return Err(JsonStoreError::UnObjectValue),
+            };
+            let records: Vec<Value> = store
+                .select_where(&tree, |record| {
+                    filter
+                        .iter()
+                        .all(|(field, value)| record.get(field).unwrap_or(&Value::Null) == value)
+                })
+                .await?;
+            let limit = limit.unwrap_or(records.len());
+            Value::Array(records.into_iter().take(limit).collect())
+        }
+        Command::Insert { tree, json } => {
+            let record: Value = serde_json::from_str(&json)?;
+            let sequence = store.insert(&tree, &record).await?;
+            let record: Value = store.select(&tree, sequence).await?;
+            json!({ "sequence": sequence, "record": record })
+        }
+        Command::Delete { tree, sequence } => {
+            store.delete(&tree, sequence).await?;
+            json!({ "deleted": sequence })
+        }
+        Command::Export {
+            tree,
+            format,
+            output,
+        } => {
+            let format = match format {
+                FileFormat::Json => ExportFormat::JsonArray,
+                FileFormat::Ndjson => ExportFormat::NdJson,
+            };
+            let exported = store.export_tree(&tree, &output, format).await?;
+            json!({ "exported": exported, "file": output })
+        }
+        Command::Import {
+            tree,
+            file,
+            format,
+            preserve,
+            skip_conflicts,
+        } => {
+            let report = import(store, &tree, &file, format, preserve, skip_conflicts).await?;
+            json!({
+                "imported": report.imported.len(),
+                "skipped": report
+                    .skipped
+                    .iter()
+                    .map(|issue| json!({ "index": issue.index, "reason": issue.reason }))
+                    .collect::<Vec<_>>(),
+            })
+        }
+        Command::Verify => {
+            let report = store.verify().await?;
+            let trees = report
+                .trees
+                .iter()
+                .map(|(name, status)| {
+                    let status = match status {
+                        ChecksumStatus::Ok => "ok",
+                        ChecksumStatus::Mismatch => "mismatch",
+                        ChecksumStatus::NoChecksum => "no_checksum",
+                        ChecksumStatus::Missing => "missing",
+                    };
+                    (name.clone(), Value::from(status))
+                })
+                .collect::<serde_json::Map<_, _>>();
+            return Ok(Output {
+                json: json!({ "ok": report.is_ok(), "trees": trees }),
+                ok: report.is_ok(),
+            });
+        }
+        Command::Stats => serde_json::to_value(store.stats().await?)?,
+    };
+    Ok(output.into())
+}
+
+async fn import(
+    store: &JsonStore,
+    tree: &str,
+    file: &Path,
+    format: FileFormat,
+    preserve: bool,
+    skip_conflicts: bool,
+) -> Result<ImportReport, JsonStoreError> {
+    let mode = match preserve {
+        true => ImportMode::Preserve,
+        false => ImportMode::Append,
+    };
+    let on_conflict = match skip_conflicts {
+        true => OnConflict::Skip,
+        false => OnConflict::Fail,
+    };
+    match format {
+        FileFormat::Json => store.import_tree_with(tree, file, mode, on_conflict).await,
+        FileFormat::Ndjson => store.import_ndjson(tree, file, mode, on_conflict).await,
+    }
+}
+
+fn pretty(value: &Value) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string())
+}
@@ -0,0 +1,18 @@
+use std::{fmt::Debug, time::SystemTime};
+
+use crate::rt;
+
+// Source of wall-clock time for anything the store timestamps, such as backups.
+// Swap in a fixed or stepping clock through LoadOptions to make those predictable.
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        rt::now()
+    }
+}
@@ -0,0 +1,104 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::path::Path;
+
+use crate::{
+    error::JsonStoreError,
+    io::{to_bytes, utf8},
+    store::OutputFormat,
+};
+
+// Encoding of snapshot files, chosen per store and recorded in `meta.json`. The file
+// extension follows the codec (`{tree}.json`, `{tree}.mp`, `{tree}.cbor`). Catalog
+// and metadata files, logs and exports stay JSON whatever the codec, so a store can
+// always be identified and inspected. MessagePack and CBOR need the `msgpack` and
+// `cbor` features; a store using one that isn't compiled in fails to load.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Codec {
+    #[default]
+    Json,
+    MessagePack,
+    Cbor,
+}
+
+impl Codec {
+    pub const ALL: [Codec; 3] = [Codec::Json, Codec::MessagePack, Codec::Cbor];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Codec::Json => "json",
+            Codec::MessagePack => "mp",
+            Codec::Cbor => "cbor",
+        }
+    }
+
+    pub fn is_available(&self) -> bool {
+        match self {
+            Codec::Json => true,
+            Codec::MessagePack => cfg!(feature = "msgpack"),
+            Codec::Cbor => cfg!(feature = "cbor"),
+        }
+    }
+
+    pub(crate) fn check_available(&self) -> Result<(), JsonStoreError> {
+        match self.is_available() {
+            true => Ok(()),
+            false => Err(JsonStoreError::CodecUnavailable(*self)),
+        }
+    }
+
+    // format only applies to JSON
+    pub(crate) fn encode<T: Serialize>(
+        &self,
+        value: &T,
+        format: OutputFormat,
+    ) -> Result<Vec<u8>, JsonStoreError> {
+        match self {
+            Codec::Json => to_bytes(value, format),
+            #[cfg(all(feature = "msgpack", not(feature = "arbitrary-precision")))]
+            Codec::MessagePack => rmp_serde::to_vec(value).map_err(|e| self.error(e)),
+            // a number is a struct holding its text then, which only comes back as one
+            // when written as a map
+            #[cfg(all(feature = "msgpack", feature = "arbitrary-precision"))]
+            Codec::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| self.error(e)),
+            #[cfg(feature = "cbor")]
+            Codec::Cbor => {
+                let mut context = Vec::new();
+                ciborium::into_writer(value, &mut context).map_err(|e| self.error(e))?;
+                Ok(context)
+            }
+            #[allow(unreachable_patterns)]
+            _ => Err(JsonStoreError::CodecUnavailable(*self)),
+        }
+    }
+
+    // file is only used in errors about JSON
+    pub(crate) fn decode<T: DeserializeOwned>(
+        &self,
+        file: &Path,
+        context: Vec<u8>,
+    ) -> Result<T, JsonStoreError> {
+        match self {
+            Codec::Json => serde_json::from_str(&utf8(file, context)?).map_err(|source| {
+                JsonStoreError::ParseFile {
+                    path: file.into(),
+                    source,
+                }
+            }),
+            #[cfg(feature = "msgpack")]
+            Codec::MessagePack => rmp_serde::from_slice(&context).map_err(|e| self.error(e)),
+            #[cfg(feature = "cbor")]
+            Codec::Cbor => ciborium::from_reader(context.as_slice()).map_err(|e| self.error(e)),
+            #[allow(unreachable_patterns)]
+            _ => Err(JsonStoreError::CodecUnavailable(*self)),
+        }
+    }
+
+    #[cfg(any(feature = "msgpack", feature = "cbor"))]
+    fn error(&self, e: impl std::fmt::Display) -> JsonStoreError {
+        JsonStoreError::Codec {
+            codec: *self,
+            message: e.to_string(),
+        }
+    }
+}
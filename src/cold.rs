@@ -0,0 +1,83 @@
+use serde_json::Value;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{error::JsonStoreError, rt::fs};
+
+// Where archive_where moves records. Either way they keep their sequences.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArchiveDest {
+    // another tree of the same store; its counter moves past the records' sequences
+    Tree(String),
+    // an NDJSON file, created or appended to; import_ndjson with ImportMode::Preserve
+    // puts the records back
+    File(PathBuf),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ArchiveOptions {
+    // find and check the records, returning their count, but move nothing
+    pub dry_run: bool,
+}
+
+// append records to the NDJSON file at path, one line each, in a single write
+pub(crate) async fn append_ndjson<'a>(
+    path: &Path,
+    records: impl Iterator<Item = &'a Value>,
+    fsync: bool,
+) -> Result<(), JsonStoreError> {
+    let mut context = Vec::new();
+    for record in records {
+        serde_json::to_writer(&mut context, record)?;
+        context.push(b'\n');
+    }
+
+    let file = fs::File::append(path).await?;
+    file.write_all(context).await?;
+    if fsync {
+        file.sync_all().await?;
+    }
+
+    Ok(())
+}
+
+// Which records prune_to_capacity sheds to bring a tree back within its capacity.
+// Either way the lowest sequences go first.
+pub enum PruneStrategy {
+    Oldest,
+    // only records the filter accepts; if too few do, all of them go and the tree
+    // stays over capacity
+    Matching(Box<dyn Fn(&Value) -> bool + Send + Sync>),
+}
+
+impl std::fmt::Debug for PruneStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Oldest => f.write_str("Oldest"),
+            Self::Matching(_) => f.write_str("Matching(..)"),
+        }
+    }
+}
+
+impl PruneStrategy {
+    // the sequences to shed from records, sorted by sequence, so that at most
+    // capacity are left
+    pub(crate) fn choose<'a>(
+        &self,
+        records: impl Iterator<Item = (&'a u64, &'a Arc<Value>)>,
+        len: usize,
+        capacity: usize,
+    ) -> Vec<u64> {
+        let excess = len.saturating_sub(capacity);
+        records
+            .filter(|(_, value)| match self {
+                Self::Oldest => true,
+                Self::Matching(filter) => filter(value),
+            })
+            .map(|(seq, _)| *seq)
+            .take(excess)
+            .collect()
+    }
+}
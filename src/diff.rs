@@ -0,0 +1,120 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+use crate::{key::Records, store::Info};
+
+// What changed between two stores or trees, from this store (old) to the other
+// (new). Records are matched by sequence and compared field by field at the top
+// level; key order within objects never counts as a change.
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiffOptions {
+    // top-level fields left out of the comparison, such as timestamps
+    pub ignore_fields: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    // None where the record lacks the field, told apart from a null by its absence
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new: Option<Value>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RecordDiff {
+    pub sequence: u64,
+    pub fields: BTreeMap<String, FieldChange>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct InfoDiff {
+    pub old: Info,
+    pub new: Info,
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct TreeDiff {
+    // sequences only the other tree has
+    pub added: Vec<u64>,
+    // sequences only this tree has
+    pub removed: Vec<u64>,
+    pub modified: Vec<RecordDiff>,
+    // set when the trees' Infos differ
+    pub info: Option<InfoDiff>,
+}
+
+impl TreeDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.modified.is_empty()
+            && self.info.is_none()
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct StoreDiff {
+    // trees only the other store has
+    pub added_trees: Vec<String>,
+    // trees only this store has
+    pub removed_trees: Vec<String>,
+    // trees both have that differ
+    pub trees: BTreeMap<String, TreeDiff>,
+}
+
+impl StoreDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_trees.is_empty() && self.removed_trees.is_empty() && self.trees.is_empty()
+    }
+}
+
+pub(crate) fn diff_records(old: &Records, new: &Records, options: &DiffOptions) -> TreeDiff {
+    let mut diff = TreeDiff::default();
+
+    for (seq, old_value) in old.iter() {
+        match new.get(seq) {
+            None => diff.removed.push(*seq),
+            Some(new_value) => {
+                let fields = diff_fields(old_value, new_value, options);
+                if !fields.is_empty() {
+                    diff.modified.push(RecordDiff {
+                        sequence: *seq,
+                        fields,
+                    });
+                }
+            }
+        }
+    }
+    diff.added = new
+        .keys()
+        .filter(|seq| !old.contains_key(seq))
+        .copied()
+        .collect();
+
+    diff.added.sort_unstable();
+    diff.removed.sort_unstable();
+    diff.modified.sort_unstable_by_key(|record| record.sequence);
+    diff
+}
+
+fn diff_fields(old: &Value, new: &Value, options: &DiffOptions) -> BTreeMap<String, FieldChange> {
+    let empty = Map::new();
+    let old = old.as_object().unwrap_or(&empty);
+    let new = new.as_object().unwrap_or(&empty);
+
+    old.keys()
+        .chain(new.keys().filter(|key| !old.contains_key(*key)))
+        .filter(|key| !options.ignore_fields.contains(key))
+        .filter(|key| old.get(*key) != new.get(*key))
+        .map(|key| {
+            let change = FieldChange {
+                old: old.get(key).cloned(),
+                new: new.get(key).cloned(),
+            };
+            (key.clone(), change)
+        })
+        .collect()
+}
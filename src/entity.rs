@@ -0,0 +1,22 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::store::Info;
+
+// #[derive(StoreEntity)], see json-store-derive for its attributes
+#[cfg(feature = "derive")]
+pub use json_store_derive::StoreEntity;
+
+// A record type that knows its tree, so call sites name the type instead of
+// repeating the tree's name and Info:
+//
+//   store.create_tree_for::<User>().await?;
+//   let seq = store.insert_entity(&user).await?;
+//   let user: User = store.select_entity(seq).await?;
+pub trait StoreEntity: Serialize + DeserializeOwned {
+    fn tree_name() -> &'static str;
+
+    fn info() -> Info;
+
+    // the record's sequence field, None until it has been inserted
+    fn sequence(&self) -> Option<u64>;
+}
@@ -1,5 +1,9 @@
+use serde::Serialize;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
+use crate::{codec::Codec, integrity::TreeIntegrityReport, layout::Layout, store::Info};
+
 #[derive(Error, Debug)]
 pub enum JsonStoreError {
     // Serde deserialize/serialize error
@@ -8,6 +12,45 @@ pub enum JsonStoreError {
     // IO error
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
+    // CSV read/write error
+    #[cfg(feature = "csv")]
+    #[error("CSV error: {0}")]
+    Csv(#[from] csv::Error),
+    // SQLite error, from export_sqlite and import_sqlite
+    #[cfg(feature = "sqlite")]
+    #[error("SQLite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+
+    #[error("Failed to read {path:?}: {source}")]
+    ReadFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Failed to write {path:?}: {source}")]
+    WriteFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Failed to parse {path:?}: {source}")]
+    ParseFile {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+
+    #[error("Tree at '{tree}' sequence {sequence} does not deserialize: {source}")]
+    DeserializeRecord {
+        tree: String,
+        sequence: u64,
+        source: serde_json::Error,
+    },
+
+    #[error("File {path:?} is not valid UTF-8: {source}")]
+    InvalidUtf8 {
+        path: PathBuf,
+        source: std::str::Utf8Error,
+    },
 
     #[error("Tree at '{0}' in Use")]
     InUseTree(String),
@@ -30,6 +73,213 @@ pub enum JsonStoreError {
     #[error("Tree at '{0}' sequence does not exist")]
     SequenceNotExist(String),
 
+    #[error("Tree at '{tree}' sequence file {path:?} is corrupt: {content:?}")]
+    CorruptSequenceFile {
+        tree: String,
+        path: PathBuf,
+        content: String,
+    },
+
+    #[error("Tree at '{tree}' is corrupt and unavailable until repaired: {reason}")]
+    TreeCorrupt { tree: String, reason: String },
+
+    // records that break the tree's own constraints, found by the check load runs
+    // under CorruptionPolicy::Fail
+    #[error(
+        "Tree at '{tree}' failed its integrity check with {} violation(s), the first: {}",
+        .report.violations.len(),
+        .report.violations.first().map(ToString::to_string).unwrap_or_default()
+    )]
+    IntegrityViolation {
+        tree: String,
+        report: Box<TreeIntegrityReport>,
+    },
+
+    #[error("Tree at '{tree}' sequence {sequence} has no history entry {index}")]
+    HistoryNotFound {
+        tree: String,
+        sequence: u64,
+        index: usize,
+    },
+
+    #[error("Tree at '{0}' has nothing to undo")]
+    NothingToUndo(String),
+
+    #[error("Tree at '{tree}' can't undo sequence {sequence}: {reason}")]
+    UndoConflict {
+        tree: String,
+        sequence: u64,
+        reason: String,
+    },
+
+    #[error("Tree at '{tree}' can't be restored to that point: {reason}")]
+    RestorePointUnavailable { tree: String, reason: String },
+
+    #[error("Tree at '{tree}' can't merge sequence {sequence}: {reason}")]
+    MergeConflict {
+        tree: String,
+        sequence: u64,
+        reason: String,
+    },
+
+    #[error("Tree at '{tree}' can't take archived sequence {sequence}: {reason}")]
+    ArchiveConflict {
+        tree: String,
+        sequence: u64,
+        reason: String,
+    },
+
+    #[error("Tree at '{tree}' sequence {sequence} refers by '{field}' to {target} not in '{referenced}'")]
+    DanglingReference {
+        tree: String,
+        sequence: u64,
+        field: String,
+        target: u64,
+        referenced: String,
+    },
+
+    #[error("Tree at '{tree}' file {path:?} modified externally")]
+    ExternallyModified { tree: String, path: PathBuf },
+
+    #[error("Tree at '{tree}' sequence {sequence} locked by '{owner}'")]
+    RecordLocked {
+        tree: String,
+        sequence: u64,
+        owner: String,
+    },
+
+    #[error(
+        "Failed to save trees [{}] (saved [{}])",
+        .failed.iter().map(|(t, e)| format!("'{t}': {e}")).collect::<Vec<_>>().join(", "),
+        .saved.join(", ")
+    )]
+    SaveFailed {
+        failed: Vec<(String, JsonStoreError)>,
+        saved: Vec<String>,
+    },
+
+    #[error("Tree at '{tree}' has no record with key '{key}'")]
+    KeyNotExist { tree: String, key: String },
+
+    #[error("Tree at '{tree}' sequence {sequence} is not reserved")]
+    SequenceNotReserved { tree: String, sequence: u64 },
+
+    #[error("Tree at '{tree}' has no unique constraint '{constraint}'")]
+    UniqueConstraintNotFound { tree: String, constraint: String },
+
+    #[error("Fixture {path:?} is for tree '{tree}', which does not exist")]
+    FixtureTreeNotFound { tree: String, path: PathBuf },
+
+    #[error("Tree at '{tree}' record key is invalid: {reason}")]
+    InvalidKey { tree: String, reason: String },
+
+    #[error("Tree at '{tree}' file {path:?} does not match its checksum")]
+    ChecksumMismatch { tree: String, path: PathBuf },
+
+    #[error("Store format version {found} is newer than supported version {supported}")]
+    UnsupportedFormatVersion { found: u32, supported: u32 },
+
+    #[error("Destination '{0}' is not empty")]
+    BackupDestinationNotEmpty(PathBuf),
+
+    #[error("Tree at '{tree}' rejected imported record {index}: {reason}")]
+    ImportRejected {
+        tree: String,
+        index: usize,
+        reason: String,
+    },
+
+    #[error("Backup at '{path}' is invalid: {reason}")]
+    InvalidBackup { path: PathBuf, reason: String },
+
+    #[error("Store path '{path}' is invalid: {reason}")]
+    InvalidStorePath { path: PathBuf, reason: String },
+
+    #[error("Archive at '{path}' is invalid: {reason}")]
+    InvalidArchive { path: PathBuf, reason: String },
+
+    #[error("{codec:?} encoding error: {message}")]
+    Codec { codec: Codec, message: String },
+
+    #[error("Codec {0:?} is not compiled in")]
+    CodecUnavailable(Codec),
+
+    #[error("Store uses codec {store:?}, not {requested:?}")]
+    CodecMismatch { store: Codec, requested: Codec },
+
+    // names that would share files on a case- or normalization-insensitive file system
+    #[error("Tree at '{tree}' collides with tree '{existing}', its name differing only in case or Unicode normalization")]
+    TreeNameCollision { tree: String, existing: String },
+
+    #[error("Tree at '{tree}' has info {stored:?}, not {requested:?}")]
+    InfoMismatch {
+        tree: String,
+        stored: Box<Info>,
+        requested: Box<Info>,
+    },
+
+    #[error(
+        "Tree at '{tree}' migration to version {version} failed{}: {reason}",
+        .sequence.map(|seq| format!(" on sequence {}", seq)).unwrap_or_default()
+    )]
+    MigrationFailed {
+        tree: String,
+        version: u32,
+        sequence: Option<u64>,
+        reason: String,
+    },
+
+    #[error("Store uses layout {store:?}, not {requested:?}")]
+    LayoutMismatch {
+        store: Box<Layout>,
+        requested: Box<Layout>,
+    },
+
+    #[error("Invalid layout: {0}")]
+    InvalidLayout(String),
+
+    #[error("Invalid tree info: {0}")]
+    InvalidInfo(String),
+
+    // an Info that breaks a rule of Info::validate, given to create_tree or found in
+    // the catalog at load
+    #[error("Tree at '{tree}' has invalid info: {source}")]
+    InvalidTreeInfo {
+        tree: String,
+        source: InfoValidationError,
+    },
+
+    #[error("Invalid store options: {0}")]
+    InvalidOptions(String),
+
+    // request to an object store that failed, after retries if it was transient
+    #[cfg(feature = "object_store")]
+    #[error("Object store request for '{key}' failed after {attempts} attempt(s): {source}")]
+    ObjectStore {
+        key: String,
+        attempts: u32,
+        source: object_store::Error,
+    },
+
+    // failure reported by a storage backend other than the filesystem
+    #[error("Storage backend error on '{key}': {message}")]
+    Backend { key: String, message: String },
+
+    #[error("Store is read-only")]
+    ReadOnlyStore,
+
+    #[error("Tree at '{0}' is frozen")]
+    TreeFrozen(String),
+
+    #[error("No store attached as '{0}'")]
+    UnknownStore(String),
+
+    #[error("Store is a replica; only sync_from changes it")]
+    ReplicaStore,
+
+    #[error("Store is closed")]
+    StoreClosed,
+
     #[error("Un Object Value")]
     UnObjectValue,
 
@@ -37,3 +287,257 @@ pub enum JsonStoreError {
     #[error("An error occurred")]
     DefaultError,
 }
+
+// Coarse classes of JsonStoreError for callers that map errors onto something
+// else, such as HTTP status codes. The set is meant to stay small and stable while
+// variants come and go.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ErrorKind {
+    // no such tree or record
+    NotFound,
+    // clashes with what is already there: an existing tree, a unique field, a
+    // change made by someone else
+    Conflict,
+    // the request or its arguments can't be honoured as given
+    InvalidInput,
+    CapacityExceeded,
+    // the storage underneath failed
+    Io,
+    // stored files don't read back as written
+    Corruption,
+    // held by someone else for now; trying again later may work
+    Locked,
+    Internal,
+}
+
+impl JsonStoreError {
+    // No wildcard arm: a new variant has to be classified here to compile.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::NotFoundTree(_)
+            | Self::SequenceNotExist(_)
+            | Self::KeyNotExist { .. }
+            | Self::UniqueConstraintNotFound { .. }
+            | Self::FixtureTreeNotFound { .. }
+            | Self::HistoryNotFound { .. }
+            | Self::NothingToUndo(_)
+            | Self::UnknownStore(_) => ErrorKind::NotFound,
+            Self::FoundTree(_)
+            | Self::DuplicateUniqueFields(_)
+            | Self::UndoConflict { .. }
+            | Self::MergeConflict { .. }
+            | Self::ArchiveConflict { .. }
+            | Self::DanglingReference { .. }
+            | Self::ExternallyModified { .. }
+            | Self::InfoMismatch { .. }
+            | Self::TreeNameCollision { .. }
+            | Self::TreeFrozen(_)
+            | Self::BackupDestinationNotEmpty(_) => ErrorKind::Conflict,
+            Self::DeserializeFromStr(_)
+            | Self::DeserializeRecord { .. }
+            | Self::ImportRejected { .. }
+            | Self::InvalidKey { .. }
+            | Self::SequenceNotReserved { .. }
+            | Self::MigrationFailed { .. }
+            | Self::RestorePointUnavailable { .. }
+            | Self::UnsupportedFormatVersion { .. }
+            | Self::InvalidBackup { .. }
+            | Self::InvalidStorePath { .. }
+            | Self::InvalidArchive { .. }
+            | Self::CodecUnavailable(_)
+            | Self::CodecMismatch { .. }
+            | Self::LayoutMismatch { .. }
+            | Self::InvalidLayout(_)
+            | Self::InvalidInfo(_)
+            | Self::InvalidTreeInfo { .. }
+            | Self::InvalidOptions(_)
+            | Self::ReadOnlyStore
+            | Self::ReplicaStore
+            | Self::StoreClosed
+            | Self::UnObjectValue => ErrorKind::InvalidInput,
+            #[cfg(feature = "csv")]
+            Self::Csv(_) => ErrorKind::InvalidInput,
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(_) => ErrorKind::InvalidInput,
+            Self::CapacityExceeded(_) => ErrorKind::CapacityExceeded,
+            Self::Io(_)
+            | Self::ReadFile { .. }
+            | Self::WriteFile { .. }
+            | Self::SaveFailed { .. }
+            | Self::Backend { .. } => ErrorKind::Io,
+            #[cfg(feature = "object_store")]
+            Self::ObjectStore { .. } => ErrorKind::Io,
+            Self::ParseFile { .. }
+            | Self::InvalidUtf8 { .. }
+            | Self::CorruptSequenceFile { .. }
+            | Self::TreeCorrupt { .. }
+            | Self::IntegrityViolation { .. }
+            | Self::ChecksumMismatch { .. }
+            | Self::Codec { .. } => ErrorKind::Corruption,
+            Self::InUseTree(_) | Self::RecordLocked { .. } => ErrorKind::Locked,
+            Self::UnableToMutValue(_) | Self::DefaultError => ErrorKind::Internal,
+        }
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        self.kind() == ErrorKind::NotFound
+    }
+
+    pub fn is_conflict(&self) -> bool {
+        self.kind() == ErrorKind::Conflict
+    }
+
+    // Whether the same call may succeed if made again unchanged: the tree or record
+    // was busy, or the I/O failure was of a transient sort.
+    pub fn is_retryable(&self) -> bool {
+        let transient = |e: &std::io::Error| {
+            matches!(
+                e.kind(),
+                std::io::ErrorKind::Interrupted
+                    | std::io::ErrorKind::TimedOut
+                    | std::io::ErrorKind::WouldBlock
+            )
+        };
+
+        match self {
+            Self::Io(e) | Self::ReadFile { source: e, .. } | Self::WriteFile { source: e, .. } => {
+                transient(e)
+            }
+            Self::SaveFailed { failed, .. } => failed.iter().all(|(_, e)| e.is_retryable()),
+            e => e.kind() == ErrorKind::Locked,
+        }
+    }
+}
+
+// Accessors for the context an error carries, so callers needn't match on variants
+// that may gain fields or change.
+impl JsonStoreError {
+    // the file involved, if the error is about one
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::ReadFile { path, .. }
+            | Self::WriteFile { path, .. }
+            | Self::ParseFile { path, .. }
+            | Self::InvalidUtf8 { path, .. }
+            | Self::CorruptSequenceFile { path, .. }
+            | Self::ExternallyModified { path, .. }
+            | Self::FixtureTreeNotFound { path, .. }
+            | Self::ChecksumMismatch { path, .. }
+            | Self::InvalidBackup { path, .. }
+            | Self::InvalidStorePath { path, .. }
+            | Self::InvalidArchive { path, .. }
+            | Self::BackupDestinationNotEmpty(path) => Some(path),
+            _ => None,
+        }
+    }
+
+    // the tree involved, if the error is about one
+    pub fn tree(&self) -> Option<&str> {
+        match self {
+            Self::InUseTree(tree)
+            | Self::NotFoundTree(tree)
+            | Self::FoundTree(tree)
+            | Self::DuplicateUniqueFields(tree)
+            | Self::CapacityExceeded(tree)
+            | Self::UnableToMutValue(tree)
+            | Self::SequenceNotExist(tree)
+            | Self::NothingToUndo(tree)
+            | Self::DeserializeRecord { tree, .. }
+            | Self::CorruptSequenceFile { tree, .. }
+            | Self::TreeCorrupt { tree, .. }
+            | Self::IntegrityViolation { tree, .. }
+            | Self::ExternallyModified { tree, .. }
+            | Self::RecordLocked { tree, .. }
+            | Self::ChecksumMismatch { tree, .. }
+            | Self::ImportRejected { tree, .. }
+            | Self::InfoMismatch { tree, .. }
+            | Self::TreeNameCollision { tree, .. }
+            | Self::InvalidTreeInfo { tree, .. }
+            | Self::UniqueConstraintNotFound { tree, .. }
+            | Self::FixtureTreeNotFound { tree, .. }
+            | Self::SequenceNotReserved { tree, .. }
+            | Self::MigrationFailed { tree, .. }
+            | Self::HistoryNotFound { tree, .. }
+            | Self::UndoConflict { tree, .. }
+            | Self::RestorePointUnavailable { tree, .. }
+            | Self::MergeConflict { tree, .. }
+            | Self::ArchiveConflict { tree, .. }
+            | Self::DanglingReference { tree, .. } => Some(tree),
+            _ => None,
+        }
+    }
+
+    // the record involved, if the error is about one
+    pub fn sequence(&self) -> Option<u64> {
+        match self {
+            Self::DeserializeRecord { sequence, .. }
+            | Self::RecordLocked { sequence, .. }
+            | Self::UndoConflict { sequence, .. }
+            | Self::MergeConflict { sequence, .. }
+            | Self::ArchiveConflict { sequence, .. }
+            | Self::SequenceNotReserved { sequence, .. }
+            | Self::DanglingReference { sequence, .. } => Some(*sequence),
+            Self::MigrationFailed { sequence, .. } => *sequence,
+            Self::HistoryNotFound { sequence, .. } => Some(*sequence),
+            _ => None,
+        }
+    }
+
+    // give an I/O failure on file the path it happened on
+    pub(crate) fn reading(file: &Path) -> impl FnOnce(Self) -> Self + '_ {
+        move |e| match e {
+            Self::Io(source) => Self::ReadFile {
+                path: file.into(),
+                source,
+            },
+            e => e,
+        }
+    }
+
+    pub(crate) fn writing(file: &Path) -> impl FnOnce(Self) -> Self + '_ {
+        move |e| match e {
+            Self::Io(source) => Self::WriteFile {
+                path: file.into(),
+                source,
+            },
+            e => e,
+        }
+    }
+}
+
+// The rule of Info::validate an Info breaks, naming the unique constraint at fault.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum InfoValidationError {
+    #[error("sequence field is empty")]
+    EmptySequenceField,
+    // a tree no record fits in
+    #[error("capacity is 0")]
+    ZeroCapacity,
+    #[error("unique constraint '{constraint}' has no fields")]
+    EmptyConstraint { constraint: String },
+    #[error("unique constraint '{constraint}' has an empty field")]
+    EmptyConstraintField { constraint: String },
+    // sequences are unique anyway, so the constraint could never be broken
+    #[error("unique constraint '{constraint}' includes the sequence field '{field}'")]
+    ConstraintHasSequenceField { constraint: String, field: String },
+    #[error("unique constraint '{constraint}' names field '{field}' twice")]
+    DuplicateConstraintField { constraint: String, field: String },
+    // a metadata key older catalogs kept the store's own state under
+    #[error("metadata key '{key}' is reserved")]
+    ReservedMetadataKey { key: String },
+}
+
+impl InfoValidationError {
+    // the unique constraint at fault, if the rule is about one
+    pub fn constraint(&self) -> Option<&str> {
+        match self {
+            Self::EmptyConstraint { constraint }
+            | Self::EmptyConstraintField { constraint }
+            | Self::ConstraintHasSequenceField { constraint, .. }
+            | Self::DuplicateConstraintField { constraint, .. } => Some(constraint),
+            Self::EmptySequenceField | Self::ZeroCapacity | Self::ReservedMetadataKey { .. } => {
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use serde_json::Value;
+use std::time::SystemTime;
+
+use crate::{partition, store::Info};
+
+// Records that expire on their own, by the time in the field Info::expiry_field names:
+// a number of milliseconds since the epoch or an RFC 3339 string, read as partition.rs
+// reads times. A record whose time has come is expired; one without the field, or
+// with something there that isn't a time, never expires. Time is the store's Clock,
+// so a test can move it along through LoadOptions.
+//
+// Reads treat an expired record as gone: select and the *_by_key selects fail with
+// SequenceNotExist or KeyNotExist, and select_where, the find_* queries, select_range
+// and exports leave it out. It stays in the tree until purge_expired removes it, and
+// counts in stats and against capacity until then. Writes still see it, so an update
+// can give it a new time and bring it back, and delete removes it.
+//
+// Nor does an expired record hold on to its unique values: an insert or update that
+// clashes only with expired records removes them first, as purge_expired would, in
+// the same write (and undo_last puts them back with it). A clash with a record that
+// hasn't expired fails as ever.
+#[derive(Debug, Clone)]
+pub(crate) struct Expiry {
+    field: String,
+    now: i64,
+}
+
+impl Expiry {
+    // for a tree with an expiry field, as of now
+    pub(crate) fn new(info: &Info, now: SystemTime) -> Option<Self> {
+        let field = info.expiry_field.clone()?;
+        Some(Self {
+            field,
+            now: partition::millis(now),
+        })
+    }
+
+    pub(crate) fn expired(&self, value: &Value) -> bool {
+        partition::time_of(&value[self.field.as_str()]).is_some_and(|at| at <= self.now)
+    }
+}
+
+// whether value is live as of expiry, which is always so without one
+pub(crate) fn live(expiry: Option<&Expiry>, value: &Value) -> bool {
+    expiry.is_none_or(|expiry| !expiry.expired(value))
+}
@@ -0,0 +1,290 @@
+use serde_json::Value;
+use std::{borrow::Cow, path::Path};
+
+use crate::{checksum, error::JsonStoreError, io::tmp_path, rt::fs};
+
+// Shapes a tree can be exported in for other tools. Records carry their sequence
+// field, so every format holds everything needed to import them again.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    // `[{...},{...}]`, one record per line
+    #[default]
+    JsonArray,
+    // one record per line, for `jq` and log pipelines
+    NdJson,
+    // One row per record under a header row. The columns are columns if given, else
+    // every field name in order of first appearance. With flatten nested objects
+    // become dotted columns (`address.city`); other arrays and objects are written
+    // as JSON text. Nulls and missing fields are empty cells.
+    #[cfg(feature = "csv")]
+    Csv {
+        delimiter: u8,
+        flatten: bool,
+        columns: Option<Vec<String>>,
+    },
+}
+
+// Fields to strip or mask in exported records; the records in the store are left as
+// they are. A path names a field with dots (`address.street`) and `*` stands for
+// every element of an array or every field of an object (`members.*.email`).
+#[derive(Debug, Clone, Default)]
+pub struct Redaction {
+    rules: Vec<(Vec<String>, RedactionPolicy)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RedactionPolicy {
+    Drop,
+    // replace the value, e.g. with "***"
+    Replace(Value),
+    // replace the value with the hex SHA-256 of it (of the text itself for strings),
+    // so redacted records can still be joined on it
+    Hash,
+}
+
+impl Redaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn field(mut self, path: &str, policy: RedactionPolicy) -> Self {
+        self.rules
+            .push((path.split('.').map(str::to_string).collect(), policy));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub(crate) fn apply<'a>(&self, record: &'a Value) -> Cow<'a, Value> {
+        if self.rules.is_empty() {
+            return Cow::Borrowed(record);
+        }
+
+        let mut record = record.clone();
+        for (path, policy) in self.rules.iter() {
+            redact(&mut record, path, policy);
+        }
+        Cow::Owned(record)
+    }
+}
+
+fn redact(value: &mut Value, path: &[String], policy: &RedactionPolicy) {
+    let Some((key, rest)) = path.split_first() else {
+        return;
+    };
+
+    if key == "*" {
+        match value {
+            Value::Array(items) if rest.is_empty() => {
+                if *policy == RedactionPolicy::Drop {
+                    items.clear();
+                } else {
+                    items.iter_mut().for_each(|item| mask(item, policy));
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| redact(item, rest, policy)),
+            Value::Object(fields) if rest.is_empty() => {
+                if *policy == RedactionPolicy::Drop {
+                    fields.clear();
+                } else {
+                    fields.values_mut().for_each(|field| mask(field, policy));
+                }
+            }
+            Value::Object(fields) => fields
+                .values_mut()
+                .for_each(|field| redact(field, rest, policy)),
+            _ => {}
+        }
+        return;
+    }
+
+    let Value::Object(fields) = value else {
+        return;
+    };
+
+    if rest.is_empty() {
+        if *policy == RedactionPolicy::Drop {
+            fields.remove(key);
+        } else if let Some(field) = fields.get_mut(key) {
+            mask(field, policy);
+        }
+    } else if let Some(field) = fields.get_mut(key) {
+        redact(field, rest, policy);
+    }
+}
+
+fn mask(value: &mut Value, policy: &RedactionPolicy) {
+    match policy {
+        RedactionPolicy::Drop => {}
+        RedactionPolicy::Replace(with) => *value = with.clone(),
+        RedactionPolicy::Hash => {
+            let digest = match &*value {
+                Value::String(s) => checksum::digest(s.as_bytes()),
+                other => checksum::digest(other.to_string().as_bytes()),
+            };
+            *value = Value::String(digest);
+        }
+    }
+}
+
+// Stream records to file in the given format, returning how many were written. The
+// output goes to a temp file renamed into place, so a failed export leaves no stub.
+pub(crate) async fn write<'a>(
+    file: &Path,
+    format: &ExportFormat,
+    redaction: &Redaction,
+    records: impl Iterator<Item = &'a Value> + Clone,
+) -> Result<u64, JsonStoreError> {
+    let tmp = tmp_path(file);
+    let records = records.map(|record| redaction.apply(record));
+
+    match write_records(&tmp, format, records).await {
+        Ok(count) => {
+            fs::rename(&tmp, file).await?;
+            Ok(count)
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&tmp).await;
+            Err(e)
+        }
+    }
+}
+
+async fn write_records<'a>(
+    file: &Path,
+    format: &ExportFormat,
+    records: impl Iterator<Item = Cow<'a, Value>> + Clone,
+) -> Result<u64, JsonStoreError> {
+    let mut writer = fs::BufWriter::new(fs::File::create(file).await?);
+    let mut count = 0;
+    let mut line = Vec::new();
+
+    match format {
+        ExportFormat::JsonArray => {
+            writer.write_all(b"[").await?;
+            for record in records {
+                line.clear();
+                line.extend_from_slice(if count == 0 { b"\n" } else { b",\n" });
+                serde_json::to_writer(&mut line, &record)?;
+                writer.write_all(&line).await?;
+                count += 1;
+            }
+            writer.write_all(b"\n]\n").await?;
+        }
+        ExportFormat::NdJson => {
+            for record in records {
+                line.clear();
+                serde_json::to_writer(&mut line, &record)?;
+                line.push(b'\n');
+                writer.write_all(&line).await?;
+                count += 1;
+            }
+        }
+        #[cfg(feature = "csv")]
+        ExportFormat::Csv {
+            delimiter,
+            flatten,
+            columns,
+        } => {
+            let columns = match columns {
+                Some(columns) => columns.clone(),
+                None => csv_columns(records.clone(), *flatten),
+            };
+
+            write_csv_row(&mut line, *delimiter, columns.iter().map(String::as_str))?;
+            writer.write_all(&line).await?;
+
+            let mut cells = serde_json::Map::new();
+            for record in records {
+                cells.clear();
+                csv_cells(&record, *flatten, "", &mut cells);
+                let row = columns
+                    .iter()
+                    .map(|c| csv_cell(cells.get(c)))
+                    .collect::<Vec<_>>();
+                write_csv_row(&mut line, *delimiter, row.iter().map(String::as_str))?;
+                writer.write_all(&line).await?;
+                count += 1;
+            }
+        }
+    }
+
+    writer.flush().await?;
+
+    Ok(count)
+}
+
+// every column name across records, in order of first appearance
+#[cfg(feature = "csv")]
+fn csv_columns<'a>(records: impl Iterator<Item = Cow<'a, Value>>, flatten: bool) -> Vec<String> {
+    let mut seen = std::collections::HashSet::new();
+    let mut columns = Vec::new();
+    let mut cells = serde_json::Map::new();
+
+    for record in records {
+        cells.clear();
+        csv_cells(&record, flatten, "", &mut cells);
+        for name in cells.keys() {
+            if seen.insert(name.clone()) {
+                columns.push(name.clone());
+            }
+        }
+    }
+
+    columns
+}
+
+// the record's fields keyed by column name, nested objects flattened if asked
+#[cfg(feature = "csv")]
+fn csv_cells(
+    value: &Value,
+    flatten: bool,
+    prefix: &str,
+    cells: &mut serde_json::Map<String, Value>,
+) {
+    let Value::Object(fields) = value else {
+        cells.insert(prefix.to_string(), value.clone());
+        return;
+    };
+
+    for (name, field) in fields {
+        let column = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{}.{}", prefix, name)
+        };
+        match field {
+            Value::Object(_) if flatten => csv_cells(field, flatten, &column, cells),
+            _ => {
+                cells.insert(column, field.clone());
+            }
+        }
+    }
+}
+
+// encode one row into line, replacing its contents
+#[cfg(feature = "csv")]
+fn write_csv_row<'a>(
+    line: &mut Vec<u8>,
+    delimiter: u8,
+    row: impl Iterator<Item = &'a str>,
+) -> Result<(), JsonStoreError> {
+    line.clear();
+    let mut csv = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(std::mem::take(line));
+    csv.write_record(row)?;
+    *line = csv.into_inner().map_err(|e| e.into_error())?;
+    Ok(())
+}
+
+#[cfg(feature = "csv")]
+fn csv_cell(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(v) => v.to_string(),
+    }
+}
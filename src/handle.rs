@@ -0,0 +1,154 @@
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::{marker::PhantomData, ops::RangeBounds, sync::Arc};
+
+use crate::{
+    cache::ReadCache,
+    error::JsonStoreError,
+    index::SortOrder,
+    store::{Info, JsonStore},
+};
+
+// One tree seen as records of type T, from JsonStore::tree. The handle owns a clone of
+// the store rather than borrowing it, so it can be cloned into tasks freely. It keeps
+// only the tree's name, every call going to whatever tree has it then: if the tree is
+// dropped meanwhile, every call fails with NotFoundTree, and once one is created again
+// under the name, calls go to that one, under its Info.
+#[derive(Debug)]
+pub struct TreeHandle<T> {
+    store: JsonStore,
+    tname: String,
+    // from with_cache, shared by the handle's clones
+    cache: Option<Arc<ReadCache<T>>>,
+    record: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for TreeHandle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            store: self.store.clone(),
+            tname: self.tname.clone(),
+            cache: self.cache.clone(),
+            record: PhantomData,
+        }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> TreeHandle<T> {
+    pub(crate) fn new(store: JsonStore, tname: &str) -> Self {
+        Self {
+            store,
+            tname: tname.to_string(),
+            cache: None,
+            record: PhantomData,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.tname
+    }
+
+    // the tree's Info as it is now
+    pub fn info(&self) -> Result<Info, JsonStoreError> {
+        self.store.get_info(&self.tname)
+    }
+
+    // The handle with a cache of up to capacity deserialized records for
+    // select_cached, in place of any it had. Its hits and misses count in the store's
+    // metrics.
+    pub fn with_cache(mut self, capacity: usize) -> Self {
+        self.cache = Some(Arc::new(ReadCache::new(capacity)));
+        self
+    }
+
+    pub async fn insert(&self, record: &T) -> Result<u64, JsonStoreError> {
+        self.store.insert(&self.tname, record).await
+    }
+
+    pub async fn select(&self, sequence: u64) -> Result<T, JsonStoreError> {
+        self.store.select(&self.tname, sequence).await
+    }
+
+    // select through the handle's cache, if with_cache gave it one, which keeps the
+    // records read last and drops each one as soon as it is written to, by any handle
+    // or by the store. A hit takes no lock on the tree and copies nothing.
+    pub async fn select_cached(&self, sequence: u64) -> Result<Arc<T>, JsonStoreError>
+    where
+        T: Send + Sync + 'static,
+    {
+        match &self.cache {
+            Some(cache) => self.store.select_cached(&self.tname, sequence, cache).await,
+            None => Ok(Arc::new(self.select(sequence).await?)),
+        }
+    }
+
+    pub async fn select_all(&self) -> Result<Vec<T>, JsonStoreError> {
+        self.store.select_where(&self.tname, |_| true).await
+    }
+
+    pub async fn update(&self, record: &T) -> Result<(), JsonStoreError> {
+        self.store.update(&self.tname, record).await
+    }
+
+    pub async fn delete(&self, sequence: u64) -> Result<(), JsonStoreError> {
+        self.store.delete(&self.tname, sequence).await
+    }
+
+    // by the string key of a string-keyed tree, see key.rs
+    pub async fn select_by_key(&self, key: &str) -> Result<T, JsonStoreError> {
+        self.store.select_by_key(&self.tname, key).await
+    }
+
+    pub async fn update_by_key(&self, key: &str, record: &T) -> Result<(), JsonStoreError> {
+        self.store.update_by_key(&self.tname, key, record).await
+    }
+
+    pub async fn delete_by_key(&self, key: &str) -> Result<(), JsonStoreError> {
+        self.store.delete_by_key(&self.tname, key).await
+    }
+
+    // the record holding candidate's values under the unique constraint, see
+    // JsonStore::check_unique
+    pub async fn check_unique(
+        &self,
+        constraint: &str,
+        candidate: &Value,
+    ) -> Result<Option<u64>, JsonStoreError> {
+        self.store
+            .check_unique(&self.tname, constraint, candidate)
+            .await
+    }
+
+    // records whose stored JSON filter accepts
+    pub async fn query<F: Fn(&Value) -> bool>(&self, filter: F) -> Result<Vec<T>, JsonStoreError> {
+        self.store.select_where(&self.tname, filter).await
+    }
+
+    // records whose field equals value, see JsonStore::find_by_field
+    pub async fn find_by_field(
+        &self,
+        field: &str,
+        value: &Value,
+    ) -> Result<Vec<T>, JsonStoreError> {
+        self.store.find_by_field(&self.tname, field, value).await
+    }
+
+    pub async fn find_range<R: RangeBounds<Value>>(
+        &self,
+        field: &str,
+        range: R,
+    ) -> Result<Vec<T>, JsonStoreError> {
+        self.store.find_range(&self.tname, field, range).await
+    }
+
+    pub async fn find_sorted(
+        &self,
+        field: &str,
+        order: SortOrder,
+        limit: usize,
+    ) -> Result<Vec<T>, JsonStoreError> {
+        self.store
+            .find_sorted(&self.tname, field, order, limit)
+            .await
+    }
+}
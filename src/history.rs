@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::BTreeMap, time::SystemTime};
+
+// Prior versions of a tree's records, kept when its Info::history is set. They live
+// in memory with the tree and are written to `{tree}.history.json` when it is saved.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryConfig {
+    // versions kept per record; older ones are dropped
+    pub max_versions: u32,
+}
+
+// What replaced a prior version.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryOp {
+    Update,
+    Delete,
+    Revert,
+    Undo,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    // when the record stopped looking like value
+    pub timestamp: SystemTime,
+    pub op: HistoryOp,
+    pub value: Value,
+}
+
+// per sequence, oldest first
+pub(crate) type History = BTreeMap<u64, Vec<HistoryEntry>>;
+
+pub(crate) fn record(history: &mut History, seq: u64, entry: HistoryEntry, config: HistoryConfig) {
+    let versions = history.entry(seq).or_default();
+    versions.push(entry);
+    let excess = versions.len().saturating_sub(config.max_versions as usize);
+    versions.drain(..excess);
+    if versions.is_empty() {
+        history.remove(&seq);
+    }
+}
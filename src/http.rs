@@ -0,0 +1,213 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use tokio::net::{TcpListener, ToSocketAddrs};
+
+use crate::{
+    error::{ErrorKind, JsonStoreError},
+    store::{Info, JsonStore},
+    trace,
+};
+
+// A REST interface to a store, for the http feature. Mount router() in an axum app of
+// your own, or have serve() listen on an address:
+//
+//   GET    /trees                       every tree, with its Info
+//   POST   /trees                       create one from {"name": ..., "info": Info}
+//   GET    /trees/{t}/records           records in sequence order, see list_records
+//   POST   /trees/{t}/records           insert; 201 with the sequence and record
+//   GET    /trees/{t}/records/{seq}     select
+//   PUT    /trees/{t}/records/{seq}     update, the body replacing the record
+//   PATCH  /trees/{t}/records/{seq}     merge_patch
+//   DELETE /trees/{t}/records/{seq}     delete; 204
+//   POST   /trees/{t}/save              save_tree; {"saved": whether it wrote}
+//
+// Bodies are JSON both ways. A failed call answers with the status of its ErrorKind
+// and {"error": message, "kind": kind}. Nothing here authenticates anyone: put it
+// behind whatever guards the rest of your service.
+
+// page size of list_records when none is asked for, and the most it hands out
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1_000;
+
+pub fn router(store: JsonStore) -> Router {
+    Router::new()
+        .route("/trees", get(list_trees).post(create_tree))
+        .route(
+            "/trees/{tree}/records",
+            get(list_records).post(insert_record),
+        )
+        .route(
+            "/trees/{tree}/records/{seq}",
+            get(select_record)
+                .put(update_record)
+                .patch(patch_record)
+                .delete(delete_record),
+        )
+        .route("/trees/{tree}/save", post(save_tree))
+        .with_state(store)
+}
+
+// Serve router(store) on addr until the listener fails.
+pub async fn serve(store: JsonStore, addr: impl ToSocketAddrs) -> Result<(), JsonStoreError> {
+    let listener = TcpListener::bind(addr).await?;
+    trace::info!(addr = ?listener.local_addr().ok(), "serving json store over http");
+    axum::serve(listener, router(store)).await?;
+    Ok(())
+}
+
+impl IntoResponse for JsonStoreError {
+    fn into_response(self) -> Response {
+        let kind = self.kind();
+        let status = match kind {
+            ErrorKind::NotFound => StatusCode::NOT_FOUND,
+            ErrorKind::Conflict => StatusCode::CONFLICT,
+            ErrorKind::InvalidInput => StatusCode::BAD_REQUEST,
+            ErrorKind::CapacityExceeded => StatusCode::INSUFFICIENT_STORAGE,
+            ErrorKind::Locked => StatusCode::LOCKED,
+            ErrorKind::Io | ErrorKind::Corruption | ErrorKind::Internal => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        let body = json!({ "error": self.to_string(), "kind": kind });
+        (status, Json(body)).into_response()
+    }
+}
+
+type Reply<T> = Result<T, JsonStoreError>;
+
+async fn list_trees(State(store): State<JsonStore>) -> Reply<Json<Value>> {
+    let trees = store
+        .list_trees()
+        .into_iter()
+        .map(|name| {
+            let info = store.get_info(&name)?;
+            Ok(json!({ "name": name, "info": info }))
+        })
+        .collect::<Reply<Vec<_>>>()?;
+    Ok(Json(Value::Array(trees)))
+}
+
+#[derive(Deserialize)]
+struct NewTree {
+    name: String,
+    info: Info,
+}
+
+async fn create_tree(
+    State(store): State<JsonStore>,
+    Json(tree): Json<NewTree>,
+) -> Reply<(StatusCode, Json<Value>)> {
+    store.create_tree(&tree.name, tree.info).await?;
+    let info = store.get_info(&tree.name)?;
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({ "name": tree.name, "info": info })),
+    ))
+}
+
+// A page of a tree's records in sequence order. offset and limit pick the page
+// (limit at most MAX_LIMIT); every other parameter is a filter, field=value keeping
+// the records whose top-level field equals value, read as JSON if it parses and as a
+// string otherwise, so ?done=true&owner=ann. total counts every record matched.
+async fn list_records(
+    State(store): State<JsonStore>,
+    Path(tree): Path<String>,
+    Query(mut params): Query<BTreeMap<String, String>>,
+) -> Reply<Json<Value>> {
+    let number = |value: Option<String>, name: &str| {
+        value
+            .map(|value| value.parse::<usize>())
+            .transpose()
+            .map_err(|_| JsonStoreError::InvalidOptions(format!("{} must be a number", name)))
+    };
+    let offset = number(params.remove("offset"), "offset")?.unwrap_or(0);
+    let limit = number(params.remove("limit"), "limit")?
+        .unwrap_or(DEFAULT_LIMIT)
+        .min(MAX_LIMIT);
+    let filters = params
+        .into_iter()
+        .map(|(field, value)| {
+            let value = serde_json::from_str(&value).unwrap_or(Value::String(value));
+            (field, value)
+        })
+        .collect::<Vec<_>>();
+
+    let matches = |record: &Value| {
+        filters
+            .iter()
+            .all(|(field, value)| record.get(field).unwrap_or(&Value::Null) == value)
+    };
+    let (records, total): (Vec<Value>, _) =
+        store.select_page(&tree, matches, offset, limit).await?;
+
+    Ok(Json(json!({
+        "records": records,
+        "offset": offset,
+        "limit": limit,
+        "total": total,
+    })))
+}
+
+async fn insert_record(
+    State(store): State<JsonStore>,
+    Path(tree): Path<String>,
+    Json(record): Json<Value>,
+) -> Reply<(StatusCode, Json<Value>)> {
+    let sequence = store.insert(&tree, &record).await?;
+    let record: Value = store.select(&tree, sequence).await?;
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({ "sequence": sequence, "record": record })),
+    ))
+}
+
+async fn select_record(
+    State(store): State<JsonStore>,
+    Path((tree, sequence)): Path<(String, u64)>,
+) -> Reply<Json<Value>> {
+    Ok(Json(store.select(&tree, sequence).await?))
+}
+
+// the record in the body, under the sequence in the path whatever the body says
+async fn update_record(
+    State(store): State<JsonStore>,
+    Path((tree, sequence)): Path<(String, u64)>,
+    Json(mut record): Json<Value>,
+) -> Reply<Json<Value>> {
+    let info = store.get_info(&tree)?;
+    record
+        .as_object_mut()
+        .ok_or(JsonStoreError::UnObjectValue)?
+        .insert(info.sequence_field, sequence.into());
+    store.update(&tree, &record).await?;
+    Ok(Json(record))
+}
+
+async fn patch_record(
+    State(store): State<JsonStore>,
+    Path((tree, sequence)): Path<(String, u64)>,
+    Json(patch): Json<Value>,
+) -> Reply<Json<Value>> {
+    Ok(Json(store.merge_patch(&tree, sequence, &patch).await?))
+}
+
+async fn delete_record(
+    State(store): State<JsonStore>,
+    Path((tree, sequence)): Path<(String, u64)>,
+) -> Reply<StatusCode> {
+    store.delete(&tree, sequence).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn save_tree(State(store): State<JsonStore>, Path(tree): Path<String>) -> Reply<Json<Value>> {
+    let saved = store.save_tree(&tree).await?;
+    Ok(Json(json!({ "saved": saved })))
+}
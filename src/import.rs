@@ -0,0 +1,233 @@
+use serde_json::Value;
+use std::{
+    io::BufRead,
+    path::{Path, PathBuf},
+};
+
+#[cfg(feature = "csv")]
+use std::collections::HashMap;
+
+use crate::{error::JsonStoreError, rt};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImportMode {
+    // give every record a fresh sequence, ignoring its sequence field
+    #[default]
+    Append,
+    // keep each record's sequence field; the tree's counter moves past the highest
+    Preserve,
+}
+
+// What to do with a record that can't be imported: a sequence already taken, a
+// unique field clash, a full tree or a record that isn't an object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnConflict {
+    // import nothing and return the first problem
+    #[default]
+    Fail,
+    // leave the record out and note it in the report
+    Skip,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ImportReport {
+    // sequences of the imported records, in file order
+    pub imported: Vec<u64>,
+    pub skipped: Vec<ImportIssue>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportIssue {
+    // position of the record in the file: its index in a JSON array, or the line it
+    // starts on for line-based formats
+    pub index: usize,
+    pub reason: String,
+}
+
+// Parse a JSON array file into its elements. The file is read through a buffered
+// reader on the blocking pool, element by element, never as a single Value.
+pub(crate) async fn read_array(file: &Path) -> Result<Vec<Value>, JsonStoreError> {
+    let file: PathBuf = file.into();
+
+    rt::unblock(move || {
+        let reader = std::io::BufReader::new(std::fs::File::open(file)?);
+        Ok(serde_json::from_reader::<_, Vec<Value>>(reader)?)
+    })
+    .await?
+}
+
+// Read a JSON-lines file into records keyed by line number, one line in memory at a
+// time. Blank lines are skipped; a line that doesn't parse carries the reason.
+pub(crate) async fn read_ndjson(
+    file: &Path,
+) -> Result<Vec<(usize, Result<Value, String>)>, JsonStoreError> {
+    let file: PathBuf = file.into();
+
+    rt::unblock(move || {
+        let reader = std::io::BufReader::new(std::fs::File::open(file)?);
+        let mut records = Vec::new();
+        for (lineno, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record = serde_json::from_str(&line).map_err(|e| format!("malformed line: {}", e));
+            records.push((lineno + 1, record));
+        }
+        Ok(records)
+    })
+    .await?
+}
+
+#[cfg(feature = "csv")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsvType {
+    Integer,
+    Float,
+    Bool,
+    String,
+    // the cell holds JSON text, such as an array written by a CSV export
+    Json,
+}
+
+#[cfg(feature = "csv")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EmptyCells {
+    #[default]
+    Null,
+    // leave the field out of the record
+    Omit,
+}
+
+#[cfg(feature = "csv")]
+#[derive(Debug, Clone)]
+pub struct CsvImportOptions {
+    pub delimiter: u8,
+    // without a header row columns are named by position: "0", "1", ...
+    pub has_headers: bool,
+    // cells of columns not listed here are inferred: integer, float, bool, else string
+    pub types: HashMap<String, CsvType>,
+    pub empty_cells: EmptyCells,
+    // fields every row must have a non-empty value for
+    pub required: Vec<String>,
+    pub mode: ImportMode,
+    pub on_conflict: OnConflict,
+}
+
+#[cfg(feature = "csv")]
+impl Default for CsvImportOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            has_headers: true,
+            types: HashMap::new(),
+            empty_cells: EmptyCells::default(),
+            required: Vec::new(),
+            mode: ImportMode::default(),
+            // partner files tend to have a few bad rows; report them, keep the rest
+            on_conflict: OnConflict::Skip,
+        }
+    }
+}
+
+// Read a CSV file into records keyed by the line each row starts on. Rows that can't
+// be turned into a record carry the reason instead.
+#[cfg(feature = "csv")]
+pub(crate) async fn read_csv(
+    file: &Path,
+    options: &CsvImportOptions,
+) -> Result<Vec<(usize, Result<Value, String>)>, JsonStoreError> {
+    let file: PathBuf = file.into();
+    let options = options.clone();
+
+    rt::unblock(move || {
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(options.delimiter)
+            .has_headers(options.has_headers)
+            .flexible(true)
+            .from_path(file)?;
+
+        let headers = match options.has_headers {
+            true => reader.headers()?.iter().map(str::to_string).collect(),
+            false => Vec::new(),
+        };
+
+        let mut records = Vec::new();
+        for row in reader.records() {
+            let row = match row {
+                Ok(row) => row,
+                Err(e) => match e.kind() {
+                    csv::ErrorKind::Io(_) => return Err(e.into()),
+                    _ => {
+                        let line = e.position().map_or(0, |p| p.line() as usize);
+                        records.push((line, Err(e.to_string())));
+                        continue;
+                    }
+                },
+            };
+            let line = row.position().map_or(0, |p| p.line() as usize);
+            records.push((line, csv_record(&headers, &row, &options)));
+        }
+
+        Ok(records)
+    })
+    .await?
+}
+
+#[cfg(feature = "csv")]
+fn csv_record(
+    headers: &[String],
+    row: &csv::StringRecord,
+    options: &CsvImportOptions,
+) -> Result<Value, String> {
+    let mut record = serde_json::Map::new();
+
+    for (i, cell) in row.iter().enumerate() {
+        let name = headers.get(i).cloned().unwrap_or_else(|| i.to_string());
+
+        if cell.is_empty() {
+            if options.empty_cells == EmptyCells::Null {
+                record.insert(name, Value::Null);
+            }
+            continue;
+        }
+
+        let value = match options.types.get(&name) {
+            Some(ty) => csv_typed(cell, *ty)
+                .ok_or_else(|| format!("column '{}' is not a valid {:?}", name, ty))?,
+            None => csv_inferred(cell),
+        };
+        record.insert(name, value);
+    }
+
+    for field in options.required.iter() {
+        if record.get(field).is_none_or(Value::is_null) {
+            return Err(format!("missing required field '{}'", field));
+        }
+    }
+
+    Ok(Value::Object(record))
+}
+
+#[cfg(feature = "csv")]
+fn csv_typed(cell: &str, ty: CsvType) -> Option<Value> {
+    match ty {
+        CsvType::Integer => cell
+            .parse::<i64>()
+            .map(Value::from)
+            .or_else(|_| cell.parse::<u64>().map(Value::from))
+            .ok(),
+        CsvType::Float => cell.parse::<f64>().ok().map(Value::from),
+        CsvType::Bool => cell.parse::<bool>().ok().map(Value::from),
+        CsvType::String => Some(Value::from(cell)),
+        CsvType::Json => serde_json::from_str(cell).ok(),
+    }
+}
+
+#[cfg(feature = "csv")]
+fn csv_inferred(cell: &str) -> Value {
+    [CsvType::Integer, CsvType::Float, CsvType::Bool]
+        .into_iter()
+        .find_map(|ty| csv_typed(cell, ty))
+        .unwrap_or_else(|| Value::from(cell))
+}
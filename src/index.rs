@@ -0,0 +1,589 @@
+use serde_json::{Number, Value};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    ops::{Bound, RangeBounds},
+    sync::Arc,
+};
+
+use crate::{key::Records, stats::value_size, store::Info};
+
+// A tree's unique constraints as hash maps from each record's key to its sequence,
+// so a write checks them with one lookup each rather than comparing every record.
+// The key is the compact JSON of each of the constraint's field values in turn, a
+// missing field being null; two records both without a field therefore clash, as
+// they always have. Kept in memory only: a tree builds it on its first checked
+// write after a load, and again after its records are replaced wholesale. The key
+// fields of a keyed tree (see key.rs) are one more constraint, checked first, so a
+// write meets a clash of keys before it gets to the records, which are kept by key.
+#[derive(Debug, Clone)]
+pub(crate) struct UniqueIndex {
+    constraints: Vec<Constraint>,
+}
+
+#[derive(Debug, Clone)]
+struct Constraint {
+    // as in Info::unique_fields; None for the key
+    name: Option<String>,
+    fields: Vec<String>,
+    keys: HashMap<Vec<u8>, u64>,
+}
+
+impl UniqueIndex {
+    // an index of info's constraints with no records yet
+    pub(crate) fn new(info: &Info) -> Self {
+        let mut constraints = info.unique_fields.iter().collect::<Vec<_>>();
+        // checked in a fixed order, so the same write always meets the same clash
+        constraints.sort();
+        let key = info.key.fields();
+        let key = (!key.is_empty()).then(|| key.into_iter().map(str::to_string).collect());
+        Self {
+            constraints: key
+                .map(|fields| (None, fields))
+                .into_iter()
+                .chain(
+                    constraints
+                        .into_iter()
+                        .map(|(name, fields)| (Some(name.clone()), fields.clone())),
+                )
+                .map(|(name, fields)| Constraint {
+                    name,
+                    fields,
+                    keys: HashMap::new(),
+                })
+                .collect(),
+        }
+    }
+
+    pub(crate) fn build<'a>(
+        info: &Info,
+        records: impl Iterator<Item = (&'a u64, &'a Arc<Value>)>,
+    ) -> Self {
+        let mut index = Self::new(info);
+        for (seq, value) in records {
+            index.insert(*seq, value);
+        }
+        index
+    }
+
+    // the record, other than skip, sharing a constraint's key with value
+    pub(crate) fn conflict(&self, value: &Value, skip: Option<u64>) -> Option<u64> {
+        self.owners(value).find(|seq| Some(*seq) != skip)
+    }
+
+    // for each constraint, the record holding value's key, if any
+    pub(crate) fn owners<'a>(&'a self, value: &'a Value) -> impl Iterator<Item = u64> + 'a {
+        self.constraints.iter().filter_map(|constraint| {
+            constraint
+                .keys
+                .get(&key(&constraint.fields, value))
+                .copied()
+        })
+    }
+
+    // the record holding value's key under the constraint called name
+    pub(crate) fn constraint_owner(&self, name: &str, value: &Value) -> Option<u64> {
+        let constraint = self
+            .constraints
+            .iter()
+            .find(|constraint| constraint.name.as_deref() == Some(name))?;
+        constraint
+            .keys
+            .get(&key(&constraint.fields, value))
+            .copied()
+    }
+
+    pub(crate) fn insert(&mut self, seq: u64, value: &Value) {
+        for constraint in self.constraints.iter_mut() {
+            constraint.keys.insert(key(&constraint.fields, value), seq);
+        }
+    }
+
+    pub(crate) fn remove(&mut self, seq: u64, value: &Value) {
+        for constraint in self.constraints.iter_mut() {
+            let key = key(&constraint.fields, value);
+            // leave a key that has since gone to another record
+            if constraint.keys.get(&key) == Some(&seq) {
+                constraint.keys.remove(&key);
+            }
+        }
+    }
+
+    // rough bytes held, counting the maps' spare room
+    pub(crate) fn memory_size(&self) -> usize {
+        self.constraints
+            .iter()
+            .map(|constraint| {
+                let keys = constraint.keys.keys().map(Vec::len).sum::<usize>();
+                constraint.keys.capacity() * size_of::<(Vec<u8>, u64)>() + keys
+            })
+            .sum()
+    }
+
+    pub(crate) fn shrink(&mut self) {
+        for constraint in self.constraints.iter_mut() {
+            constraint.keys.shrink_to_fit();
+        }
+    }
+}
+
+pub(crate) fn key(fields: &[String], value: &Value) -> Vec<u8> {
+    let mut key = Vec::new();
+    for field in fields {
+        push_value(&mut key, &value[field.as_str()]);
+    }
+    key
+}
+
+fn value_key(value: &Value) -> Vec<u8> {
+    let mut key = Vec::new();
+    push_value(&mut key, value);
+    key
+}
+
+fn push_value(key: &mut Vec<u8>, value: &Value) {
+    write_json(key, value);
+    key.push(b'\n');
+}
+
+// the compact JSON of value; serializing a Value to memory can't fail
+#[cfg(not(feature = "arbitrary-precision"))]
+fn write_json(out: &mut Vec<u8>, value: &Value) {
+    let _ = serde_json::to_writer(out, value);
+}
+
+// With preserve_order an object keeps its fields in the order they were written, so
+// here they're put in key order, as a Map holds them otherwise: equal objects must
+// make equal keys.
+#[cfg(feature = "arbitrary-precision")]
+fn write_json(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Array(items) => {
+            out.push(b'[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+                write_json(out, item);
+            }
+            out.push(b']');
+        }
+        Value::Object(map) => {
+            let mut fields = map.iter().collect::<Vec<_>>();
+            fields.sort_unstable_by_key(|(name, _)| *name);
+            out.push(b'{');
+            for (i, (name, field)) in fields.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+                let _ = serde_json::to_writer(&mut *out, name);
+                out.push(b':');
+                write_json(out, field);
+            }
+            out.push(b'}');
+        }
+        _ => {
+            let _ = serde_json::to_writer(out, value);
+        }
+    }
+}
+
+// The secondary indexes of a tree. An equality index, for each field named in
+// Info::indexes, holds the sequences of the records with each value of the field by
+// the value's key as above; an ordered one, for Info::ordered_indexes, holds them in
+// the order of compare, for ranges and sorting. Unlike the unique index these are
+// kept up to date from the tree's load on, so reads can use them as they are.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FieldIndexes {
+    equal: HashMap<String, HashMap<Vec<u8>, BTreeSet<u64>>>,
+    ordered: HashMap<String, BTreeMap<OrderedKey, BTreeSet<u64>>>,
+}
+
+impl FieldIndexes {
+    pub(crate) fn build(info: &Info, data: &Records) -> Self {
+        let mut indexes = Self::default();
+        for field in info.indexes.iter() {
+            indexes.add(field, data);
+        }
+        for field in info.ordered_indexes.iter() {
+            indexes.add_ordered(field, data);
+        }
+        indexes
+    }
+
+    pub(crate) fn add(&mut self, field: &str, data: &Records) {
+        let mut postings = HashMap::<_, BTreeSet<u64>>::new();
+        for (seq, value) in data.iter() {
+            postings
+                .entry(value_key(&value[field]))
+                .or_default()
+                .insert(*seq);
+        }
+        self.equal.insert(field.to_string(), postings);
+    }
+
+    pub(crate) fn add_ordered(&mut self, field: &str, data: &Records) {
+        let mut postings = BTreeMap::<_, BTreeSet<u64>>::new();
+        for (seq, value) in data.iter() {
+            postings
+                .entry(OrderedKey(value[field].clone()))
+                .or_default()
+                .insert(*seq);
+        }
+        self.ordered.insert(field.to_string(), postings);
+    }
+
+    // drop both kinds of index on field
+    pub(crate) fn remove_field(&mut self, field: &str) {
+        self.equal.remove(field);
+        self.ordered.remove(field);
+    }
+
+    // index every field again from data
+    pub(crate) fn rebuild(&mut self, data: &Records) {
+        for field in self.equal.keys().cloned().collect::<Vec<_>>() {
+            self.add(&field, data);
+        }
+        for field in self.ordered.keys().cloned().collect::<Vec<_>>() {
+            self.add_ordered(&field, data);
+        }
+    }
+
+    // the records whose field holds value, in sequence order; None if field has no
+    // equality index
+    pub(crate) fn lookup(&self, field: &str, value: &Value) -> Option<Vec<u64>> {
+        let postings = self.equal.get(field)?;
+        Some(
+            postings
+                .get(&value_key(value))
+                .map(|seqs| seqs.iter().copied().collect())
+                .unwrap_or_default(),
+        )
+    }
+
+    // the records whose field lies in range, by value and then sequence; None if
+    // field has no ordered index
+    pub(crate) fn range(&self, field: &str, range: &impl RangeBounds<Value>) -> Option<Vec<u64>> {
+        let postings = self.ordered.get(field)?;
+        if is_empty(range) {
+            return Some(Vec::new());
+        }
+
+        let key = |bound: Bound<&Value>| bound.map(|value| OrderedKey(value.clone()));
+        let within = postings.range((key(range.start_bound()), key(range.end_bound())));
+        // an open end stops at the other's type
+        let found: Vec<&BTreeSet<u64>> = match (range.start_bound(), range.end_bound()) {
+            (Bound::Unbounded, Bound::Unbounded) => within.map(|(_, seqs)| seqs).collect(),
+            (Bound::Unbounded, Bound::Included(end) | Bound::Excluded(end)) => {
+                let mut found = within
+                    .rev()
+                    .take_while(|(key, _)| class(&key.0) == class(end))
+                    .map(|(_, seqs)| seqs)
+                    .collect::<Vec<_>>();
+                found.reverse();
+                found
+            }
+            (Bound::Included(start) | Bound::Excluded(start), Bound::Unbounded) => within
+                .take_while(|(key, _)| class(&key.0) == class(start))
+                .map(|(_, seqs)| seqs)
+                .collect(),
+            _ => within.map(|(_, seqs)| seqs).collect(),
+        };
+        Some(found.into_iter().flatten().copied().collect())
+    }
+
+    // the first limit records by field in order, those with equal values by
+    // sequence; None if field has no ordered index
+    pub(crate) fn sorted(&self, field: &str, order: SortOrder, limit: usize) -> Option<Vec<u64>> {
+        let postings = self.ordered.get(field)?;
+        let seqs = postings.values().flatten().copied();
+        Some(match order {
+            SortOrder::Ascending => seqs.take(limit).collect(),
+            SortOrder::Descending => postings
+                .values()
+                .rev()
+                .flatten()
+                .copied()
+                .take(limit)
+                .collect(),
+        })
+    }
+
+    pub(crate) fn insert(&mut self, seq: u64, value: &Value) {
+        for (field, postings) in self.equal.iter_mut() {
+            postings
+                .entry(value_key(&value[field.as_str()]))
+                .or_default()
+                .insert(seq);
+        }
+        for (field, postings) in self.ordered.iter_mut() {
+            postings
+                .entry(OrderedKey(value[field.as_str()].clone()))
+                .or_default()
+                .insert(seq);
+        }
+    }
+
+    // Rough bytes held: the hash maps by their capacity, the ordered ones by entry,
+    // and a sequence per posting.
+    pub(crate) fn memory_size(&self) -> usize {
+        let posting =
+            |seqs: &BTreeSet<u64>| size_of::<BTreeSet<u64>>() + seqs.len() * size_of::<u64>();
+        let equal = self.equal.values().map(|postings| {
+            postings.capacity() * size_of::<(Vec<u8>, BTreeSet<u64>)>()
+                + postings
+                    .iter()
+                    .map(|(key, seqs)| key.len() + posting(seqs))
+                    .sum::<usize>()
+        });
+        let ordered = self
+            .ordered
+            .values()
+            .flatten()
+            .map(|(key, seqs)| value_size(&key.0) + posting(seqs));
+        equal.sum::<usize>() + ordered.sum::<usize>()
+    }
+
+    // give the equality indexes' spare room back; B-trees keep none
+    pub(crate) fn shrink(&mut self) {
+        for postings in self.equal.values_mut() {
+            postings.shrink_to_fit();
+        }
+    }
+
+    pub(crate) fn remove(&mut self, seq: u64, value: &Value) {
+        for (field, postings) in self.equal.iter_mut() {
+            let key = value_key(&value[field.as_str()]);
+            if let Some(seqs) = postings.get_mut(&key) {
+                seqs.remove(&seq);
+                if seqs.is_empty() {
+                    postings.remove(&key);
+                }
+            }
+        }
+        for (field, postings) in self.ordered.iter_mut() {
+            let key = OrderedKey(value[field.as_str()].clone());
+            if let Some(seqs) = postings.get_mut(&key) {
+                seqs.remove(&seq);
+                if seqs.is_empty() {
+                    postings.remove(&key);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortOrder {
+    #[default]
+    Ascending,
+    Descending,
+}
+
+// The order ordered indexes, find_range and find_sorted put JSON values in: null,
+// false, true, numbers, strings, arrays, objects. Numbers compare by value, an
+// integer and a float exactly (so 1 and 1.0 are equal here, though not to
+// find_by_field), and under the arbitrary-precision feature by every digit written;
+// strings by their bytes, and arrays and objects by their compact JSON with fields in
+// key order.
+pub fn compare(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        (Value::Number(a), Value::Number(b)) => compare_numbers(a, b),
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Array(_), Value::Array(_)) | (Value::Object(_), Value::Object(_)) => {
+            let json = |value| {
+                let mut out = Vec::new();
+                write_json(&mut out, value);
+                out
+            };
+            json(a).cmp(&json(b))
+        }
+        _ => class(a).cmp(&class(b)),
+    }
+}
+
+// the kinds of value in the order compare puts them
+fn class(value: &Value) -> u8 {
+    match value {
+        Value::Null => 0,
+        Value::Bool(_) => 1,
+        Value::Number(_) => 2,
+        Value::String(_) => 3,
+        Value::Array(_) => 4,
+        Value::Object(_) => 5,
+    }
+}
+
+fn integer(n: &Number) -> Option<i128> {
+    n.as_i64().map(i128::from).or(n.as_u64().map(i128::from))
+}
+
+#[cfg(not(feature = "arbitrary-precision"))]
+fn compare_numbers(a: &Number, b: &Number) -> Ordering {
+    let float = |n: &Number| n.as_f64().unwrap_or_default();
+    match (integer(a), integer(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(a), None) => compare_mixed(a, float(b)),
+        (None, Some(b)) => compare_mixed(b, float(a)).reverse(),
+        (None, None) => float(a).partial_cmp(&float(b)).unwrap_or(Ordering::Equal),
+    }
+}
+
+// A number here is the text it was written as, which may hold more digits than an
+// f64 or u64 does, so all but two small integers compare by their decimal digits.
+#[cfg(feature = "arbitrary-precision")]
+fn compare_numbers(a: &Number, b: &Number) -> Ordering {
+    match (integer(a), integer(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        _ => Decimal::parse(&a.to_string()).cmp(&Decimal::parse(&b.to_string())),
+    }
+}
+
+// A JSON number as sign, significant digits and exponent: 0.d1d2... times 10 to the
+// exponent, with no leading or trailing zero digits, so zero has none.
+#[cfg(feature = "arbitrary-precision")]
+#[derive(Debug, PartialEq, Eq)]
+struct Decimal {
+    negative: bool,
+    digits: Vec<u8>,
+    exponent: i64,
+}
+
+#[cfg(feature = "arbitrary-precision")]
+impl Decimal {
+    fn parse(text: &str) -> Self {
+        let (negative, text) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+        let (mantissa, exponent) = match text.find(['e', 'E']) {
+            Some(at) => (&text[..at], text[at + 1..].parse::<i64>().unwrap_or(0)),
+            None => (text, 0),
+        };
+        let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+
+        let mut digits = whole.bytes().chain(fraction.bytes()).collect::<Vec<_>>();
+        let mut exponent = exponent.saturating_add(whole.len() as i64);
+        let leading = digits.iter().take_while(|d| **d == b'0').count();
+        digits.drain(..leading);
+        exponent = exponent.saturating_sub(leading as i64);
+        while digits.last() == Some(&b'0') {
+            digits.pop();
+        }
+        if digits.is_empty() {
+            exponent = 0;
+        }
+        Self {
+            negative: negative && !digits.is_empty(),
+            digits,
+            exponent,
+        }
+    }
+
+    fn sign(&self) -> i8 {
+        match (self.negative, self.digits.is_empty()) {
+            (_, true) => 0,
+            (true, false) => -1,
+            (false, false) => 1,
+        }
+    }
+}
+
+#[cfg(feature = "arbitrary-precision")]
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let magnitude = self
+            .exponent
+            .cmp(&other.exponent)
+            .then_with(|| self.digits.cmp(&other.digits));
+        self.sign().cmp(&other.sign()).then(match self.sign() {
+            -1 => magnitude.reverse(),
+            0 => Ordering::Equal,
+            _ => magnitude,
+        })
+    }
+}
+
+#[cfg(feature = "arbitrary-precision")]
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// an integer against a float, without rounding either
+#[cfg(not(feature = "arbitrary-precision"))]
+fn compare_mixed(integer: i128, float: f64) -> Ordering {
+    // past every i64 and u64
+    if float < -1e19 {
+        return Ordering::Greater;
+    }
+    if float > 1e20 {
+        return Ordering::Less;
+    }
+    let whole = float.trunc();
+    integer
+        .cmp(&(whole as i128))
+        .then(0.0.partial_cmp(&(float - whole)).unwrap_or(Ordering::Equal))
+}
+
+// whether range holds nothing, which BTreeMap::range would panic on
+fn is_empty(range: &impl RangeBounds<Value>) -> bool {
+    match (range.start_bound(), range.end_bound()) {
+        (Bound::Included(start), Bound::Included(end)) => compare(start, end).is_gt(),
+        (
+            Bound::Included(start) | Bound::Excluded(start),
+            Bound::Included(end) | Bound::Excluded(end),
+        ) => compare(start, end).is_ge(),
+        _ => false,
+    }
+}
+
+// whether value lies in range, the way FieldIndexes::range sees it
+pub(crate) fn in_range(value: &Value, range: &impl RangeBounds<Value>) -> bool {
+    let after_start = match range.start_bound() {
+        Bound::Included(start) => compare(value, start).is_ge(),
+        Bound::Excluded(start) => compare(value, start).is_gt(),
+        Bound::Unbounded => true,
+    };
+    let before_end = match range.end_bound() {
+        Bound::Included(end) => compare(value, end).is_le(),
+        Bound::Excluded(end) => compare(value, end).is_lt(),
+        Bound::Unbounded => true,
+    };
+    let same_class = match (range.start_bound(), range.end_bound()) {
+        (Bound::Unbounded, Bound::Included(end) | Bound::Excluded(end)) => {
+            class(value) == class(end)
+        }
+        (Bound::Included(start) | Bound::Excluded(start), Bound::Unbounded) => {
+            class(value) == class(start)
+        }
+        _ => true,
+    };
+    after_start && before_end && same_class
+}
+
+// a value under compare's order
+#[derive(Debug, Clone)]
+struct OrderedKey(Value);
+
+impl PartialEq for OrderedKey {
+    fn eq(&self, other: &Self) -> bool {
+        compare(&self.0, &other.0).is_eq()
+    }
+}
+
+impl Eq for OrderedKey {}
+
+impl PartialOrd for OrderedKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OrderedKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare(&self.0, &other.0)
+    }
+}
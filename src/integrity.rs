@@ -0,0 +1,146 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::{collections::HashMap, fmt};
+
+use crate::{index, key::Records, store::Info};
+
+// Whether a tree's records keep the rules every write keeps, for files edited by hand
+// or written by something other than the store. Checked when load reads a tree, with
+// CorruptionPolicy deciding what a violation does, and by validate_tree at any time.
+//
+// Load raises a counter behind the records by itself (see reconcile_sequence), so
+// CounterBehind only turns up from validate_tree. Nor does load parse records it
+// reads as text, spills or leaves in partitions not read yet; validate_tree reads
+// them all.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct TreeIntegrityReport {
+    // records checked
+    pub records: usize,
+    pub violations: Vec<Violation>,
+}
+
+impl TreeIntegrityReport {
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Violation {
+    // Records sharing a key under a unique constraint, None being the key of a keyed
+    // tree (see key.rs). values are the constraint's fields in order, a missing one
+    // null. The first write to meet the key fails on the others.
+    DuplicateUnique {
+        constraint: Option<String>,
+        values: Vec<Value>,
+        sequences: Vec<u64>,
+    },
+    // a record whose sequence field doesn't hold the sequence it's stored under
+    SequenceMismatch {
+        sequence: u64,
+        found: Value,
+    },
+    // the next insert would take the sequence of a record there already
+    CounterBehind {
+        counter: u64,
+        max: u64,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DuplicateUnique {
+                constraint: Some(name),
+                sequences,
+                ..
+            } => write!(
+                f,
+                "sequences {:?} share a key under unique constraint '{}'",
+                sequences, name
+            ),
+            Self::DuplicateUnique {
+                constraint: None,
+                sequences,
+                ..
+            } => write!(f, "sequences {:?} share a key", sequences),
+            Self::SequenceMismatch { sequence, found } => write!(
+                f,
+                "record at sequence {} has {} in its sequence field",
+                sequence, found
+            ),
+            Self::CounterBehind { counter, max } => write!(
+                f,
+                "sequence counter {} is behind the highest sequence {}",
+                counter, max
+            ),
+        }
+    }
+}
+
+// Check records against info's constraints and counter. Violations come in a fixed
+// order: the counter, then records by sequence, then duplicates by constraint as
+// UniqueIndex checks them and by their first sequence.
+pub(crate) fn check(info: &Info, counter: u64, records: &Records) -> TreeIntegrityReport {
+    let mut sequences = records.keys().copied().collect::<Vec<_>>();
+    sequences.sort_unstable();
+
+    let mut violations = Vec::new();
+    if let Some(&max) = sequences.last().filter(|max| **max > counter) {
+        violations.push(Violation::CounterBehind { counter, max });
+    }
+
+    for seq in sequences.iter() {
+        let found = &records[seq][info.sequence_field.as_str()];
+        if found.as_u64() != Some(*seq) {
+            violations.push(Violation::SequenceMismatch {
+                sequence: *seq,
+                found: found.clone(),
+            });
+        }
+    }
+
+    let mut constraints = info.unique_fields.iter().collect::<Vec<_>>();
+    constraints.sort();
+    let key = info.key.fields();
+    let key = (!key.is_empty()).then(|| key.into_iter().map(str::to_string).collect::<Vec<_>>());
+    let constraints = key.iter().map(|fields| (None, fields)).chain(
+        constraints
+            .into_iter()
+            .map(|(name, fields)| (Some(name), fields)),
+    );
+    for (name, fields) in constraints {
+        // each key's holders, and keys in the order they were first met
+        let mut holders = HashMap::<_, Vec<u64>>::new();
+        let mut keys = Vec::new();
+        for seq in sequences.iter() {
+            let key = index::key(fields, &records[seq]);
+            let held = holders.entry(key.clone()).or_default();
+            if held.is_empty() {
+                keys.push(key);
+            }
+            held.push(*seq);
+        }
+        for key in keys {
+            let sequences = holders.remove(&key).unwrap_or_default();
+            if sequences.len() < 2 {
+                continue;
+            }
+            let record = &records[&sequences[0]];
+            violations.push(Violation::DuplicateUnique {
+                constraint: name.cloned(),
+                values: fields
+                    .iter()
+                    .map(|field| record[field.as_str()].clone())
+                    .collect(),
+                sequences,
+            });
+        }
+    }
+
+    TreeIntegrityReport {
+        records: records.len(),
+        violations,
+    }
+}
@@ -0,0 +1,322 @@
+use flate2::{read::GzDecoder, write::GzEncoder};
+use serde::{de::DeserializeOwned, Serialize};
+use std::io::{Read, Write};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    backend::StorageBackend,
+    error::JsonStoreError,
+    layout::Layout,
+    rt::fs,
+    store::{Durability, OutputFormat},
+    trace,
+};
+
+// suffix of the scratch file a save writes before renaming it over the target
+const TMP_SUFFIX: &str = ".tmp";
+
+pub(crate) async fn exists(
+    backend: &dyn StorageBackend,
+    key: &str,
+) -> Result<bool, JsonStoreError> {
+    Ok(backend.stamp(key).await?.is_some())
+}
+
+pub(crate) async fn get_json<T: DeserializeOwned>(
+    backend: &dyn StorageBackend,
+    key: &str,
+) -> Result<Option<T>, JsonStoreError> {
+    let context = match read_text(backend, key).await? {
+        Some(s) => s,
+        None => return Ok(None),
+    };
+    serde_json::from_str(&context)
+        .map(Some)
+        .map_err(|source| JsonStoreError::ParseFile {
+            path: backend.location(key),
+            source,
+        })
+}
+
+pub(crate) async fn put_json<T: Serialize + Debug>(
+    backend: &dyn StorageBackend,
+    key: &str,
+    value: &T,
+    format: OutputFormat,
+    durability: Durability,
+) -> Result<(), JsonStoreError> {
+    backend
+        .write(key, to_bytes(value, format)?, durability)
+        .await
+}
+
+pub(crate) fn to_bytes<T: Serialize>(
+    value: &T,
+    format: OutputFormat,
+) -> Result<Vec<u8>, JsonStoreError> {
+    // serialize straight to bytes; large trees don't need a String copy on top
+    let mut context = Vec::new();
+    match format {
+        OutputFormat::Compact => serde_json::to_writer(&mut context, value)?,
+        OutputFormat::Pretty => serde_json::to_writer_pretty(&mut context, value)?,
+    }
+    Ok(context)
+}
+
+pub(crate) fn sorted<K: Ord, V>(map: &HashMap<K, V>) -> BTreeMap<&K, &V> {
+    map.iter().collect()
+}
+
+// the counter in tname's sequence file key, or None if there is no such file
+pub(crate) async fn get_sequence(
+    backend: &dyn StorageBackend,
+    tname: &str,
+    key: &str,
+) -> Result<Option<u64>, JsonStoreError> {
+    let Some(line) = read_text(backend, key).await? else {
+        return Ok(None);
+    };
+
+    match line.trim().parse() {
+        Ok(seq) => Ok(Some(seq)),
+        Err(_) => Err(JsonStoreError::CorruptSequenceFile {
+            tree: tname.to_string(),
+            path: backend.location(key),
+            content: line,
+        }),
+    }
+}
+
+pub(crate) async fn put_sequence(
+    backend: &dyn StorageBackend,
+    key: &str,
+    sequence: u64,
+    durability: Durability,
+) -> Result<(), JsonStoreError> {
+    backend
+        .write(key, sequence.to_string().into_bytes(), durability)
+        .await
+}
+
+pub(crate) async fn read_text(
+    backend: &dyn StorageBackend,
+    key: &str,
+) -> Result<Option<String>, JsonStoreError> {
+    // one read of the whole file; validating in place avoids a second copy
+    match backend.read(key).await? {
+        Some(context) => Ok(Some(utf8(&backend.location(key), context)?)),
+        None => Ok(None),
+    }
+}
+
+pub(crate) async fn read_bytes(file: &Path) -> Result<Option<Vec<u8>>, JsonStoreError> {
+    match fs::read(file).await {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub(crate) fn utf8(file: &Path, context: Vec<u8>) -> Result<String, JsonStoreError> {
+    String::from_utf8(context).map_err(|e| JsonStoreError::InvalidUtf8 {
+        path: file.into(),
+        source: e.utf8_error(),
+    })
+}
+
+pub(crate) fn gzip(context: &[u8]) -> Result<Vec<u8>, JsonStoreError> {
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(context)?;
+    Ok(encoder.finish()?)
+}
+
+pub(crate) fn gunzip(context: &[u8]) -> Result<Vec<u8>, JsonStoreError> {
+    let mut plain = Vec::new();
+    GzDecoder::new(context).read_to_end(&mut plain)?;
+    Ok(plain)
+}
+
+pub(crate) async fn remove_file_if_exists(file: &Path) -> Result<(), JsonStoreError> {
+    match fs::remove_file(file).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+// Write to a temp file next to the target and rename it into place, so a crash
+// mid-write leaves the previous version intact rather than a truncated file.
+pub(crate) async fn write_text(
+    file: PathBuf,
+    context: Vec<u8>,
+    durability: Durability,
+) -> Result<(), JsonStoreError> {
+    if durability == Durability::None {
+        return write_file(&file, context, false).await;
+    }
+
+    let tmp = tmp_path(&file);
+
+    if let Err(e) = write_file(&tmp, context, durability == Durability::Fsync).await {
+        let _ = fs::remove_file(&tmp).await;
+        return Err(e);
+    }
+
+    fs::rename(&tmp, &file).await?;
+
+    if durability == Durability::Fsync {
+        if let Some(dir) = file.parent() {
+            sync_dir(dir).await?;
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn write_file(
+    file: &Path,
+    context: Vec<u8>,
+    sync: bool,
+) -> Result<(), JsonStoreError> {
+    let file = fs::File::create(file).await?;
+    file.write_all(context).await?;
+
+    if sync {
+        file.sync_all().await?;
+    }
+
+    Ok(())
+}
+
+// make a rename in dir durable; directories can only be synced this way on unix
+#[cfg(unix)]
+pub(crate) async fn sync_dir(dir: &Path) -> Result<(), JsonStoreError> {
+    fs::File::open(dir).await?.sync_all().await?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub(crate) async fn sync_dir(_dir: &Path) -> Result<(), JsonStoreError> {
+    Ok(())
+}
+
+pub(crate) fn tmp_path(file: &Path) -> PathBuf {
+    let mut name = file.file_name().unwrap_or_default().to_os_string();
+    name.push(TMP_SUFFIX);
+    file.with_file_name(name)
+}
+
+// Make sure path is a directory, creating it (and its parents) if missing and create
+// is set. With writable it must also take writes, tested with a probe file as
+// permission bits don't tell the whole story.
+pub(crate) async fn prepare_store_dir(
+    path: &Path,
+    create: bool,
+    writable: bool,
+) -> Result<(), JsonStoreError> {
+    let invalid = |reason: String| JsonStoreError::InvalidStorePath {
+        path: path.into(),
+        reason,
+    };
+
+    match fs::metadata(path).await {
+        Ok(m) if m.is_dir() => {}
+        Ok(_) => return Err(invalid("not a directory".to_string())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && create => {
+            fs::create_dir_all(path)
+                .await
+                .map_err(|e| invalid(format!("cannot create directory: {}", e)))?;
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(invalid("does not exist".to_string()))
+        }
+        Err(e) => return Err(invalid(e.to_string())),
+    }
+
+    if !writable {
+        return Ok(());
+    }
+
+    let probe = tmp_path(&path.join(".write-probe.json"));
+    fs::write(&probe, Vec::new())
+        .await
+        .map_err(|e| invalid(format!("not writable: {}", e)))?;
+    fs::remove_file(&probe).await?;
+
+    Ok(())
+}
+
+// Leftovers of saves interrupted by a crash, at the top of path and in the layout's
+// tree directory, and one directory down from either, where a sharded tree keeps its
+// `part-NNN` files and a partitioned tree its periods; the targets they were meant to
+// replace are intact.
+pub(crate) async fn remove_stale_tmp_files(
+    path: &Path,
+    layout: &Layout,
+) -> Result<(), JsonStoreError> {
+    let mut extensions = vec!["json", "jsonl", "sha256", "gz", "mp", "cbor"];
+    extensions.extend([
+        layout.data_extension.as_str(),
+        layout.sequence_extension.as_str(),
+    ]);
+
+    let tree_dir = layout.tree_dir.as_ref().map(|dir| path.join(dir));
+    let mut dirs = vec![path.to_path_buf()];
+    dirs.extend(tree_dir.clone());
+
+    let mut subdirs = Vec::new();
+    for dir in dirs {
+        for subdir in remove_tmp_files_in(&dir, &extensions).await? {
+            if Some(&subdir) != tree_dir.as_ref() {
+                subdirs.push(subdir);
+            }
+        }
+    }
+    for dir in subdirs {
+        remove_tmp_files_in(&dir, &extensions).await?;
+    }
+
+    Ok(())
+}
+
+// remove the temp files of dir's targets with one of extensions, giving back the
+// directories found beside them
+async fn remove_tmp_files_in(
+    dir: &Path,
+    extensions: &[&str],
+) -> Result<Vec<PathBuf>, JsonStoreError> {
+    let entries = match fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut subdirs = Vec::new();
+    for entry in entries {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if entry.file_type()?.is_dir() {
+            if !name.starts_with('.') {
+                subdirs.push(entry.path());
+            }
+            continue;
+        }
+        let Some(target) = name.strip_suffix(TMP_SUFFIX) else {
+            continue;
+        };
+        if !extensions
+            .iter()
+            .any(|ext| target.ends_with(&format!(".{}", ext)))
+        {
+            continue;
+        }
+
+        trace::warn!(file = ?entry.path(), "removing stale temp file from an interrupted save");
+        fs::remove_file(entry.path()).await?;
+    }
+
+    Ok(subdirs)
+}
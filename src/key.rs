@@ -0,0 +1,572 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value};
+use std::{
+    collections::{hash_map, BTreeMap, HashMap},
+    ops::Index,
+    sync::Arc,
+};
+
+use crate::{error::JsonStoreError, store::Info};
+
+// How a tree's records are named, set by Info::key. A Sequence tree (the default) names
+// them by the sequence insert hands out, as it always has. A String tree names them
+// also by a string in a field of their own, a UUID or a natural key such as a country
+// code, which select_by_key, update_by_key, delete_by_key and sequence_of_key take in
+// place of a sequence. A Composite tree names them by the values of several fields
+// together, a price by its product and region: the *_by_key_fields methods take an
+// object holding those fields, and the *_by_key ones the canonical key text, the JSON
+// array of the values in the order the fields are listed (`[17,"eu"]`, see key_of).
+//
+// A keyed tree keeps its records by key, in memory (see Records) and in its snapshot
+// and delta files, which are objects from each key to its record where a Sequence
+// tree's go from each sequence:
+//
+//   {"FR": {"code": "FR", "id": 1, "name": "France"}, "DE": {...}}
+//
+// The loader reads a tree's files as its Info says. A record there may leave out its
+// key fields, which are filled in from the key it is filed under, and its sequence
+// field, in which case it is handed the next sequence when the tree loads, so
+// fixtures can be written by hand; a record whose key fields disagree with its key
+// makes the file corrupt.
+//
+// The sequence stays as well, in the record's sequence field, so every sequence
+// method works on a keyed tree too, and the WAL, the append log, history and undo go
+// on naming records by it. Insert fills a String key in or refuses a record without
+// its key, as import does record by record, and update refuses to change it. The key
+// is also the first of the tree's unique constraints, so every path that checks those
+// before writing checks it the same way.
+//
+// Migrating a tree to keys, or between kinds of key, rewrites its files: give every
+// record its key (unique, and for a String tree non-empty strings), export the tree,
+// create the new one with Info::builder().string_key(field, generator) or
+// composite_key(fields) and import the export with ImportMode::Preserve, which keeps
+// the sequences references and history point at. Going back is the same, the key
+// staying in the records as plain fields. Files of one kind aren't read as another:
+// those of a Sequence tree loaded as a keyed one are corrupt, their numbers not being
+// their records' keys.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum KeyKind {
+    #[default]
+    Sequence,
+    String {
+        // the record field holding the key
+        field: String,
+        #[serde(default)]
+        generator: KeyGenerator,
+    },
+    // the fields whose values together, none of them null, make up the key
+    Composite {
+        fields: Vec<String>,
+    },
+}
+
+// What insert does with a record of a String tree that has no key.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyGenerator {
+    // refuse it; the caller names every record
+    #[default]
+    None,
+    // give it a random (v4) UUID, in hyphenated lowercase; needs the uuid feature
+    Uuid,
+}
+
+impl KeyKind {
+    pub fn is_sequence(&self) -> bool {
+        matches!(self, Self::Sequence)
+    }
+
+    // the key field of a String tree
+    pub fn field(&self) -> Option<&str> {
+        match self {
+            Self::String { field, .. } => Some(field),
+            _ => None,
+        }
+    }
+
+    // the fields making up the key, none for a Sequence tree
+    pub fn fields(&self) -> Vec<&str> {
+        match self {
+            Self::Sequence => Vec::new(),
+            Self::String { field, .. } => vec![field.as_str()],
+            Self::Composite { fields } => fields.iter().map(String::as_str).collect(),
+        }
+    }
+
+    // The key of record as the *_by_key methods take it: a String tree's key, or the
+    // compact JSON array of a Composite tree's key values. None for a Sequence tree
+    // and for a record without a whole key.
+    pub fn key_of(&self, record: &Value) -> Option<String> {
+        match self {
+            Self::Sequence => None,
+            Self::String { field, .. } => record[field.as_str()].as_str().map(str::to_string),
+            Self::Composite { fields } => {
+                let values = fields
+                    .iter()
+                    .map(|field| match &record[field.as_str()] {
+                        Value::Null => None,
+                        value => Some(value.clone()),
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                Some(Value::Array(values).to_string())
+            }
+        }
+    }
+}
+
+// Give value, a record about to be inserted into a String tree, its key if it lacks
+// one, and check the key it has, or check that a Composite tree's record has all its
+// key fields. Sequence trees' records are left alone.
+pub(crate) fn assign(tname: &str, info: &Info, value: &mut Value) -> Result<(), JsonStoreError> {
+    if info.key.is_sequence() {
+        return Ok(());
+    }
+    let record = value.as_object_mut().ok_or(JsonStoreError::UnObjectValue)?;
+
+    match &info.key {
+        KeyKind::Sequence => Ok(()),
+        KeyKind::String { field, generator } => match record.get(field.as_str()) {
+            None | Some(Value::Null) => {
+                let key = generate(*generator)
+                    .ok_or_else(|| invalid(tname, format!("'{}' is missing", field)))?;
+                record.insert(field.clone(), Value::String(key?));
+                Ok(())
+            }
+            Some(Value::String(key)) if !key.is_empty() => Ok(()),
+            Some(_) => Err(invalid(
+                tname,
+                format!("'{}' is not a non-empty string", field),
+            )),
+        },
+        KeyKind::Composite { fields } => {
+            match fields
+                .iter()
+                .find(|field| record.get(field.as_str()).is_none_or(Value::is_null))
+            {
+                Some(field) => Err(invalid(tname, format!("'{}' is missing", field))),
+                None => Ok(()),
+            }
+        }
+    }
+}
+
+// Check that value, replacing prior in a keyed tree, keeps its key.
+pub(crate) fn check_unchanged(
+    tname: &str,
+    info: &Info,
+    prior: &Value,
+    value: &Value,
+) -> Result<(), JsonStoreError> {
+    match info
+        .key
+        .fields()
+        .into_iter()
+        .find(|field| prior[*field] != value[*field])
+    {
+        Some(field) => Err(invalid(tname, format!("'{}' can't be changed", field))),
+        None => Ok(()),
+    }
+}
+
+// The key fields as an object, from a key as the *_by_key methods take it.
+pub(crate) fn parse(tname: &str, info: &Info, key: &str) -> Result<Value, JsonStoreError> {
+    match &info.key {
+        KeyKind::Sequence => Err(sequence_keyed(tname)),
+        KeyKind::String { field, .. } => {
+            let mut record = Map::new();
+            record.insert(field.clone(), Value::String(key.to_string()));
+            Ok(Value::Object(record))
+        }
+        KeyKind::Composite { fields } => {
+            let values = match serde_json::from_str(key) {
+                Ok(Value::Array(values)) if values.len() == fields.len() => values,
+                _ => {
+                    return Err(invalid(
+                        tname,
+                        format!("'{}' is not a JSON array of {} values", key, fields.len()),
+                    ))
+                }
+            };
+            Ok(Value::Object(fields.iter().cloned().zip(values).collect()))
+        }
+    }
+}
+
+// The same from an object holding at least the key fields, as the *_by_key_fields
+// methods take it.
+pub(crate) fn project(tname: &str, info: &Info, fields: &Value) -> Result<Value, JsonStoreError> {
+    if info.key.is_sequence() {
+        return Err(sequence_keyed(tname));
+    }
+    let fields = fields.as_object().ok_or(JsonStoreError::UnObjectValue)?;
+    let mut record = Map::new();
+    for field in info.key.fields() {
+        match fields.get(field) {
+            Some(value) if !value.is_null() => record.insert(field.to_string(), value.clone()),
+            _ => return Err(invalid(tname, format!("'{}' is missing", field))),
+        };
+    }
+    Ok(Value::Object(record))
+}
+
+fn invalid(tname: &str, reason: String) -> JsonStoreError {
+    JsonStoreError::InvalidKey {
+        tree: tname.to_string(),
+        reason,
+    }
+}
+
+fn sequence_keyed(tname: &str) -> JsonStoreError {
+    invalid(tname, "the tree is keyed by sequence".to_string())
+}
+
+// a new key from generator, None if it makes none
+fn generate(generator: KeyGenerator) -> Option<Result<String, JsonStoreError>> {
+    match generator {
+        KeyGenerator::None => None,
+        #[cfg(feature = "uuid")]
+        KeyGenerator::Uuid => Some(Ok(uuid::Uuid::new_v4().to_string())),
+        #[cfg(not(feature = "uuid"))]
+        KeyGenerator::Uuid => Some(Err(JsonStoreError::InvalidInfo(
+            "uuid keys need the uuid feature".to_string(),
+        ))),
+    }
+}
+
+// A tree's records. A Sequence tree's are kept by sequence; a keyed tree's by key, the
+// text key_of makes of the record, each with its sequence, which names maps back to
+// its key. Either is read and written by sequence like a map, as the store mostly
+// goes, and a keyed tree's by key through sequence_of. Each record is shared, so
+// reads and snapshot_tree hand out the Arc rather than a copy; a write puts a new one
+// in its place, never changing a record others may still hold.
+#[derive(Debug, Clone)]
+pub(crate) enum Records {
+    Sequence(HashMap<u64, Arc<Value>>),
+    Keyed(KeyedRecords),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct KeyedRecords {
+    kind: KeyKind,
+    records: HashMap<String, (u64, Arc<Value>)>,
+    names: HashMap<u64, String>,
+}
+
+impl Default for Records {
+    fn default() -> Self {
+        Self::Sequence(HashMap::new())
+    }
+}
+
+impl Records {
+    // no records, kept as info's tree keeps them
+    pub(crate) fn new(info: &Info) -> Self {
+        Self::of_kind(&info.key)
+    }
+
+    fn of_kind(kind: &KeyKind) -> Self {
+        match kind {
+            KeyKind::Sequence => Self::default(),
+            kind => Self::Keyed(KeyedRecords {
+                kind: kind.clone(),
+                records: HashMap::new(),
+                names: HashMap::new(),
+            }),
+        }
+    }
+
+    // no records, kept as these are
+    pub(crate) fn emptied(&self) -> Self {
+        match self {
+            Self::Sequence(_) => Self::default(),
+            Self::Keyed(keyed) => Self::of_kind(&keyed.kind),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Self::Sequence(records) => records.len(),
+            Self::Keyed(keyed) => keyed.records.len(),
+        }
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        match self {
+            Self::Sequence(records) => records.capacity(),
+            Self::Keyed(keyed) => keyed.records.capacity(),
+        }
+    }
+
+    // room for each record, a keyed tree's counting its key twice
+    pub(crate) fn entry_size(&self) -> usize {
+        match self {
+            Self::Sequence(_) => size_of::<(u64, Arc<Value>)>(),
+            Self::Keyed(_) => size_of::<(String, (u64, Arc<Value>))>() + size_of::<(u64, String)>(),
+        }
+    }
+
+    pub(crate) fn shrink_to_fit(&mut self) {
+        match self {
+            Self::Sequence(records) => records.shrink_to_fit(),
+            Self::Keyed(keyed) => {
+                keyed.records.shrink_to_fit();
+                keyed.names.shrink_to_fit();
+            }
+        }
+    }
+
+    pub(crate) fn get(&self, seq: &u64) -> Option<&Arc<Value>> {
+        self.get_key_value(seq).map(|(_, value)| value)
+    }
+
+    pub(crate) fn get_key_value(&self, seq: &u64) -> Option<(&u64, &Arc<Value>)> {
+        match self {
+            Self::Sequence(records) => records.get_key_value(seq),
+            Self::Keyed(keyed) => {
+                let (seq, value) = keyed.records.get(keyed.names.get(seq)?)?;
+                Some((seq, value))
+            }
+        }
+    }
+
+    pub(crate) fn contains_key(&self, seq: &u64) -> bool {
+        match self {
+            Self::Sequence(records) => records.contains_key(seq),
+            Self::Keyed(keyed) => keyed.names.contains_key(seq),
+        }
+    }
+
+    // The record at seq set to value, returning the one it replaces. A keyed tree
+    // files it under its key; one without a whole key, which the write paths don't
+    // let in, stays under the key the sequence had, if any.
+    pub(crate) fn insert(&mut self, seq: u64, value: Arc<Value>) -> Option<Arc<Value>> {
+        let keyed = match self {
+            Self::Sequence(records) => return records.insert(seq, value),
+            Self::Keyed(keyed) => keyed,
+        };
+        let key = keyed
+            .kind
+            .key_of(&value)
+            .or_else(|| keyed.names.get(&seq).cloned())
+            .unwrap_or_else(|| seq.to_string());
+        let prior = keyed
+            .names
+            .remove(&seq)
+            .and_then(|old| keyed.records.remove(&old))
+            .map(|(_, prior)| prior);
+        // a record the key was filed under before goes, as a map's would
+        if let Some((other, _)) = keyed.records.insert(key.clone(), (seq, value)) {
+            keyed.names.remove(&other);
+        }
+        keyed.names.insert(seq, key);
+        prior
+    }
+
+    pub(crate) fn remove(&mut self, seq: &u64) -> Option<Arc<Value>> {
+        match self {
+            Self::Sequence(records) => records.remove(seq),
+            Self::Keyed(keyed) => {
+                let key = keyed.names.remove(seq)?;
+                keyed.records.remove(&key).map(|(_, prior)| prior)
+            }
+        }
+    }
+
+    pub(crate) fn iter(&self) -> Iter<'_> {
+        match self {
+            Self::Sequence(records) => Iter::Sequence(records.iter()),
+            Self::Keyed(keyed) => Iter::Keyed(keyed.records.values()),
+        }
+    }
+
+    // every record in sequence order
+    pub(crate) fn sorted(&self) -> BTreeMap<&u64, &Arc<Value>> {
+        self.iter().collect()
+    }
+
+    pub(crate) fn keys(&self) -> impl Iterator<Item = &u64> + '_ {
+        self.iter().map(|(seq, _)| seq)
+    }
+
+    pub(crate) fn values(&self) -> impl Iterator<Item = &Arc<Value>> + '_ {
+        self.iter().map(|(_, value)| value)
+    }
+
+    // Each record to change in place. A keyed tree's mustn't change its key, which it
+    // stays filed under; records whose key may change are put back with insert.
+    pub(crate) fn values_mut(&mut self) -> Box<dyn Iterator<Item = &mut Arc<Value>> + '_> {
+        match self {
+            Self::Sequence(records) => Box::new(records.values_mut()),
+            Self::Keyed(keyed) => Box::new(keyed.records.values_mut().map(|(_, value)| value)),
+        }
+    }
+
+    // the sequence of the record with key, in a keyed tree
+    pub(crate) fn sequence_of(&self, key: &str) -> Option<u64> {
+        match self {
+            Self::Sequence(_) => None,
+            Self::Keyed(keyed) => keyed.records.get(key).map(|(seq, _)| *seq),
+        }
+    }
+
+    // the key of the record at seq in a keyed tree
+    pub(crate) fn key_at(&self, seq: u64) -> Option<&str> {
+        match self {
+            Self::Sequence(_) => None,
+            Self::Keyed(keyed) => keyed.names.get(&seq).map(String::as_str),
+        }
+    }
+
+    pub(crate) fn is_keyed(&self) -> bool {
+        matches!(self, Self::Keyed(_))
+    }
+
+    // A keyed tree's records with a sequence that include takes, by key, as its
+    // snapshot files hold them; None for a Sequence tree.
+    pub(crate) fn by_key(
+        &self,
+        include: impl Fn(u64) -> bool,
+    ) -> Option<BTreeMap<String, Arc<Value>>> {
+        let Self::Keyed(keyed) = self else {
+            return None;
+        };
+        let records = keyed.records.iter().filter(|(_, (seq, _))| include(*seq));
+        Some(
+            records
+                .map(|(key, (_, value))| (key.clone(), value.clone()))
+                .collect(),
+        )
+    }
+}
+
+pub(crate) enum Iter<'a> {
+    Sequence(hash_map::Iter<'a, u64, Arc<Value>>),
+    Keyed(hash_map::Values<'a, String, (u64, Arc<Value>)>),
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a u64, &'a Arc<Value>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Sequence(records) => records.next(),
+            Self::Keyed(records) => records.next().map(|(seq, value)| (seq, value)),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            Self::Sequence(records) => records.size_hint(),
+            Self::Keyed(records) => records.size_hint(),
+        }
+    }
+}
+
+impl Index<&u64> for Records {
+    type Output = Arc<Value>;
+
+    fn index(&self, seq: &u64) -> &Arc<Value> {
+        self.get(seq).expect("no record at the sequence")
+    }
+}
+
+impl Extend<(u64, Arc<Value>)> for Records {
+    fn extend<I: IntoIterator<Item = (u64, Arc<Value>)>>(&mut self, records: I) {
+        for (seq, value) in records {
+            self.insert(seq, value);
+        }
+    }
+}
+
+// a Sequence tree's records
+impl FromIterator<(u64, Arc<Value>)> for Records {
+    fn from_iter<I: IntoIterator<Item = (u64, Arc<Value>)>>(records: I) -> Self {
+        Self::Sequence(records.into_iter().collect())
+    }
+}
+
+impl IntoIterator for Records {
+    type Item = (u64, Arc<Value>);
+    type IntoIter = Box<dyn Iterator<Item = (u64, Arc<Value>)> + Send>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        match self {
+            Self::Sequence(records) => Box::new(records.into_iter()),
+            Self::Keyed(keyed) => Box::new(keyed.records.into_values()),
+        }
+    }
+}
+
+// by sequence, whichever way they are kept
+impl Serialize for Records {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter().collect::<BTreeMap<_, _>>())
+    }
+}
+
+impl<'de> Deserialize<'de> for Records {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Self::Sequence(HashMap::deserialize(deserializer)?))
+    }
+}
+
+// The records of a keyed tree as its files hold them, by key, with the key fields a
+// record leaves out filled in from its key and a sequence handed to each that has
+// none, or has one another record has too, after the highest of sequence and those
+// the records hold. Returns them and whether any record was changed.
+pub(crate) fn from_keyed(
+    tname: &str,
+    info: &Info,
+    keyed: impl IntoIterator<Item = (String, Arc<Value>)>,
+    sequence: u64,
+) -> Result<(Records, bool), JsonStoreError> {
+    let corrupt = |reason: String| JsonStoreError::TreeCorrupt {
+        tree: tname.to_string(),
+        reason,
+    };
+    let mut keyed = keyed.into_iter().collect::<Vec<_>>();
+    keyed.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut records = Records::new(info);
+    let mut unnumbered = Vec::new();
+    let mut changed = false;
+    let mut last = sequence;
+    for (key, mut value) in keyed {
+        let fields = parse(tname, info, &key).map_err(|e| corrupt(e.to_string()))?;
+        let record = Arc::make_mut(&mut value)
+            .as_object_mut()
+            .ok_or_else(|| corrupt(format!("record '{}' is not an object", key)))?;
+        for (field, part) in fields.as_object().into_iter().flatten() {
+            match record.get(field) {
+                None | Some(Value::Null) => {
+                    record.insert(field.clone(), part.clone());
+                    changed = true;
+                }
+                Some(held) if held == part => {}
+                Some(held) => {
+                    return Err(corrupt(format!(
+                        "record '{}' has '{}' {} in its key field",
+                        key, field, held
+                    )))
+                }
+            }
+        }
+        match record.get(&info.sequence_field).and_then(Value::as_u64) {
+            Some(seq) if !records.contains_key(&seq) => {
+                last = last.max(seq);
+                records.insert(seq, value);
+            }
+            _ => unnumbered.push(value),
+        }
+    }
+    for mut value in unnumbered {
+        last += 1;
+        if let Value::Object(record) = Arc::make_mut(&mut value) {
+            record.insert(info.sequence_field.clone(), last.into());
+        }
+        records.insert(last, value);
+        changed = true;
+    }
+    Ok((records, changed))
+}
@@ -0,0 +1,142 @@
+use futures::future::{BoxFuture, LocalBoxFuture};
+use send_wrapper::SendWrapper;
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt::Debug,
+    hash::{Hash, Hasher},
+    path::PathBuf,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use crate::{
+    backend::{Stamp, StorageBackend},
+    error::JsonStoreError,
+    store::{Durability, JsonStore, LoadOptions},
+};
+
+// Persistence a wasm32 host provides: localStorage, IndexedDB, a Tauri command, or
+// whatever else the app bridges to from JavaScript. It only has to get, set and remove
+// byte values by key and list its keys; KeyValueBackend makes a StorageBackend of it.
+// The futures can be JsFutures, as nothing leaves the one thread a wasm32 build runs
+// on. Report the host's failures as JsonStoreError::Backend.
+//
+//   #[derive(Debug)]
+//   struct Local(web_sys::Storage);
+//
+//   impl KeyValue for Local {
+//       fn get<'a>(&'a self, key: &'a str) -> LocalBoxFuture<'a, Result<Option<Vec<u8>>, JsonStoreError>> {
+//           let value = self.0.get_item(key).map_err(|e| backend_error(key, e));
+//           Box::pin(async move { Ok(value?.map(String::into_bytes)) })
+//       }
+//       ...
+//   }
+//
+//   let store = JsonStore::load_key_value(Local(storage), options).await?;
+//
+// Every write replaces a value whole and appends read the value back first, so WAL and
+// append-log trees cost a round trip per write here. A key's stamp is taken from a
+// hash of its value, the host keeping no modification times.
+pub trait KeyValue: Debug + 'static {
+    fn get<'a>(
+        &'a self,
+        key: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Option<Vec<u8>>, JsonStoreError>>;
+
+    fn set<'a>(
+        &'a self,
+        key: &'a str,
+        value: Vec<u8>,
+    ) -> LocalBoxFuture<'a, Result<(), JsonStoreError>>;
+
+    // a missing key is not an error
+    fn remove<'a>(&'a self, key: &'a str) -> LocalBoxFuture<'a, Result<(), JsonStoreError>>;
+
+    // every key, in any order
+    fn keys(&self) -> LocalBoxFuture<'_, Result<Vec<String>, JsonStoreError>>;
+}
+
+#[derive(Debug)]
+pub struct KeyValueBackend<K: KeyValue> {
+    host: SendWrapper<K>,
+}
+
+impl<K: KeyValue> KeyValueBackend<K> {
+    pub fn new(host: K) -> Self {
+        Self {
+            host: SendWrapper::new(host),
+        }
+    }
+}
+
+impl JsonStore {
+    // load the store kept in host's key-value storage
+    pub async fn load_key_value(
+        host: impl KeyValue,
+        options: LoadOptions,
+    ) -> Result<Self, JsonStoreError> {
+        Self::load_with_backend(KeyValueBackend::new(host), options).await
+    }
+}
+
+// the host's futures, which are only ever polled on the thread they came from
+fn send<'a, T: 'a>(future: LocalBoxFuture<'a, T>) -> BoxFuture<'a, T> {
+    Box::pin(SendWrapper::new(future))
+}
+
+impl<K: KeyValue> StorageBackend for KeyValueBackend<K> {
+    fn read<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>, JsonStoreError>> {
+        send(self.host.get(key))
+    }
+
+    fn write<'a>(
+        &'a self,
+        key: &'a str,
+        bytes: Vec<u8>,
+        _durability: Durability,
+    ) -> BoxFuture<'a, Result<(), JsonStoreError>> {
+        send(self.host.set(key, bytes))
+    }
+
+    fn append<'a>(
+        &'a self,
+        key: &'a str,
+        bytes: Vec<u8>,
+        _fsync: bool,
+    ) -> BoxFuture<'a, Result<(), JsonStoreError>> {
+        send(Box::pin(async move {
+            let mut value = self.host.get(key).await?.unwrap_or_default();
+            value.extend_from_slice(&bytes);
+            self.host.set(key, value).await
+        }))
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), JsonStoreError>> {
+        send(self.host.remove(key))
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<String>, JsonStoreError>> {
+        send(Box::pin(async move {
+            let mut keys = self.host.keys().await?;
+            keys.retain(|key| !key.contains('/'));
+            keys.sort();
+            Ok(keys)
+        }))
+    }
+
+    fn stamp<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Stamp>, JsonStoreError>> {
+        send(Box::pin(async move {
+            let Some(value) = self.host.get(key).await? else {
+                return Ok(None);
+            };
+            let mut hasher = DefaultHasher::new();
+            value.hash(&mut hasher);
+            // kept within what a SystemTime holds everywhere
+            let modified = UNIX_EPOCH + Duration::from_nanos(hasher.finish() >> 1);
+            Ok(Some(Stamp::new(modified, value.len() as u64)))
+        }))
+    }
+
+    fn location(&self, key: &str) -> PathBuf {
+        PathBuf::from(key)
+    }
+}
@@ -0,0 +1,184 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{
+    codec::Codec,
+    error::JsonStoreError,
+    meta::{INFOS_FILE, META_FILE},
+    store::{tree_files, Compression, Info},
+};
+
+// Where a store's files go and what they are called. It is chosen when the store is
+// created and recorded in `meta.json`, which always sits at the top of the store, so
+// later loads pick it up by themselves; relayout moves an existing store to another.
+// The default is the historical flat layout:
+//
+//   infos.json  users.seq  users.json  users.wal  ...
+//
+// With `tree_dir: Some("trees")` the tree files move to `trees/users.seq` and so on,
+// which also keeps a tree called `infos` clear of the catalog.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct Layout {
+    // catalog of the store's trees
+    pub infos_file: String,
+    // directory holding the tree files, relative to the store; top level if None
+    pub tree_dir: Option<String>,
+    // extension of JSON snapshots; MessagePack and CBOR ones keep their codec's
+    pub data_extension: String,
+    pub sequence_extension: String,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            infos_file: INFOS_FILE.to_string(),
+            tree_dir: None,
+            data_extension: "json".to_string(),
+            sequence_extension: "seq".to_string(),
+        }
+    }
+}
+
+impl Layout {
+    pub(crate) fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), JsonStoreError> {
+        let invalid = |reason: String| Err(JsonStoreError::InvalidLayout(reason));
+
+        let names = [
+            ("infos_file", Some(&self.infos_file)),
+            ("tree_dir", self.tree_dir.as_ref()),
+            ("data_extension", Some(&self.data_extension)),
+            ("sequence_extension", Some(&self.sequence_extension)),
+        ];
+        for (field, name) in names {
+            let Some(name) = name else { continue };
+            if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+                return invalid(format!("{} '{}' is not a plain name", field, name));
+            }
+        }
+
+        if self.infos_file == META_FILE {
+            return invalid(format!("infos_file can't be {}", META_FILE));
+        }
+
+        // every extension the tree files use must stay distinct
+        let mut extensions = vec!["wal", "jsonl", "sha256", "gz"];
+        extensions.extend(
+            Codec::ALL
+                .iter()
+                .filter(|codec| **codec != Codec::Json)
+                .map(|codec| codec.extension()),
+        );
+        for ext in [&self.data_extension, &self.sequence_extension] {
+            if extensions.contains(&ext.as_str()) {
+                return invalid(format!("extension '{}' is already in use", ext));
+            }
+            extensions.push(ext);
+        }
+
+        Ok(())
+    }
+
+    // refuse a tree whose files would overwrite the store's own, as a tree called
+    // `infos` (or `Infos`, see fold) would in the default layout
+    pub(crate) fn check_tree(&self, tname: &str, info: &Info) -> Result<(), JsonStoreError> {
+        let own = [self.infos_file.as_str(), META_FILE];
+        match tree_files(self, tname, info)
+            .into_iter()
+            .find_map(|key| own.into_iter().find(|own| fold(own) == fold(&key)))
+        {
+            Some(key) => Err(JsonStoreError::InvalidLayout(format!(
+                "files of tree '{}' would overwrite {}",
+                tname, key
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    // key of a tree file named name
+    fn tree_key(&self, name: &str) -> String {
+        match &self.tree_dir {
+            Some(dir) => format!("{}/{}", dir, name),
+            None => name.to_string(),
+        }
+    }
+
+    pub(crate) fn seq_key(&self, tname: &str) -> String {
+        self.tree_key(&format!("{}.{}", tname, self.sequence_extension))
+    }
+
+    pub(crate) fn wal_key(&self, tname: &str) -> String {
+        self.tree_key(&format!("{}.wal", tname))
+    }
+
+    pub(crate) fn history_key(&self, tname: &str) -> String {
+        self.tree_key(&format!("{}.history.json", tname))
+    }
+
+    // changes saved since the snapshot of a tree kept in one file
+    pub(crate) fn delta_key(&self, tname: &str) -> String {
+        self.tree_key(&format!("{}.delta.json", tname))
+    }
+
+    // the index of a partitioned tree, see partition.rs
+    pub(crate) fn partitions_key(&self, tname: &str) -> String {
+        self.tree_key(&format!("{}.partitions.json", tname))
+    }
+
+    pub(crate) fn log_key(&self, tname: &str) -> String {
+        self.tree_key(&format!("{}.jsonl", tname))
+    }
+
+    // a tree's snapshot files without extension; sharded trees add `/part-NNN` and
+    // partitioned ones `/{period}`
+    pub(crate) fn base(&self, tname: &str) -> String {
+        self.tree_key(tname)
+    }
+
+    pub(crate) fn extension(&self, codec: Codec) -> &str {
+        match codec {
+            Codec::Json => &self.data_extension,
+            _ => codec.extension(),
+        }
+    }
+
+    pub(crate) fn snapshot_key(
+        &self,
+        base: &str,
+        codec: Codec,
+        compression: Option<Compression>,
+    ) -> String {
+        match compression {
+            Some(Compression::Gzip) => format!("{}.{}.gz", base, self.extension(codec)),
+            None => format!("{}.{}", base, self.extension(codec)),
+        }
+    }
+}
+
+// A name as a file system that ignores case, Unicode normalization or both sees it:
+// `Users` and `users`, or `café` composed (NFC) and decomposed (NFD), fold the same.
+// macOS and Windows would give such trees one set of files, so no two trees of a
+// store may fold alike, on any file system.
+pub(crate) fn fold(name: &str) -> String {
+    name.nfd().flat_map(char::to_lowercase).nfc().collect()
+}
+
+// the first pair of names that fold alike, later one first
+pub(crate) fn collision<'a>(
+    names: impl IntoIterator<Item = &'a String>,
+) -> Option<(&'a str, &'a str)> {
+    let mut names = names.into_iter().collect::<Vec<_>>();
+    names.sort();
+    let mut seen = HashMap::new();
+    for name in names {
+        if let Some(existing) = seen.insert(fold(name), name) {
+            return Some((name.as_str(), existing.as_str()));
+        }
+    }
+    None
+}
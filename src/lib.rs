@@ -1,2 +1,59 @@
+mod append_log;
+#[cfg(feature = "archive")]
+mod archive;
+pub mod autosave;
+pub mod backend;
+pub mod backup;
+pub mod batch;
+pub mod builder;
+mod cache;
+pub mod checksum;
+#[cfg(feature = "cli")]
+pub mod cli;
+pub mod clock;
+pub mod codec;
+pub mod cold;
+pub mod diff;
+pub mod entity;
 pub mod error;
+mod expiry;
+pub mod export;
+pub mod handle;
+pub mod history;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod import;
+pub mod index;
+pub mod integrity;
+mod io;
+pub mod key;
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub mod kv;
+pub mod layout;
+pub mod lock;
+pub mod merge;
+pub mod meta;
+pub mod metrics;
+pub mod migrations;
+pub mod multi;
+#[cfg(feature = "object_store")]
+pub mod object_backend;
+pub mod partition;
+pub mod profile;
+pub mod raw;
+pub mod repair;
+pub mod replica;
+mod rt;
+pub mod schema;
+pub mod seed;
+pub mod session;
+mod spill;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod stats;
 pub mod store;
+mod trace;
+pub mod undo;
+pub mod wal;
+#[cfg(feature = "watch")]
+pub mod watch;
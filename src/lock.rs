@@ -0,0 +1,159 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use crate::{error::JsonStoreError, rt::Instant};
+
+// Record locks are advisory and live only in memory: they are not persisted, are
+// forgotten when the store is reloaded, and only guard writes made through this
+// store instance. They reserve a record for one owner while it is being edited.
+
+static NEXT_TOKEN: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug)]
+struct LockEntry {
+    owner: String,
+    expires: Instant,
+    token: u64,
+}
+
+impl LockEntry {
+    fn is_live(&self) -> bool {
+        self.expires > Instant::now()
+    }
+}
+
+// tree name -> sequence -> lock
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LockTable(Arc<Mutex<HashMap<String, HashMap<u64, LockEntry>>>>);
+
+impl LockTable {
+    pub(crate) fn acquire(
+        &self,
+        tname: &str,
+        sequence: u64,
+        owner: &str,
+        ttl: Duration,
+    ) -> Result<RecordLock, JsonStoreError> {
+        let mut table = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let locks = table.entry(tname.to_string()).or_default();
+
+        if let Some(entry) = locks.get(&sequence) {
+            if entry.is_live() && entry.owner != owner {
+                return Err(JsonStoreError::RecordLocked {
+                    tree: tname.to_string(),
+                    sequence,
+                    owner: entry.owner.clone(),
+                });
+            }
+        }
+
+        let token = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed);
+        locks.insert(
+            sequence,
+            LockEntry {
+                owner: owner.to_string(),
+                expires: Instant::now() + ttl,
+                token,
+            },
+        );
+
+        Ok(RecordLock {
+            table: self.clone(),
+            tree: tname.to_string(),
+            sequence,
+            owner: owner.to_string(),
+            token,
+        })
+    }
+
+    // fails if the record is held by someone other than owner (None = anonymous writer)
+    pub(crate) fn check(
+        &self,
+        tname: &str,
+        sequence: u64,
+        owner: Option<&str>,
+    ) -> Result<(), JsonStoreError> {
+        let table = self.0.lock().unwrap_or_else(|e| e.into_inner());
+
+        match table.get(tname).and_then(|locks| locks.get(&sequence)) {
+            Some(entry) if entry.is_live() && Some(entry.owner.as_str()) != owner => {
+                Err(JsonStoreError::RecordLocked {
+                    tree: tname.to_string(),
+                    sequence,
+                    owner: entry.owner.clone(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn release(
+        &self,
+        tname: &str,
+        sequence: u64,
+        owner: &str,
+    ) -> Result<(), JsonStoreError> {
+        self.check(tname, sequence, Some(owner))?;
+        self.remove(tname, sequence);
+        Ok(())
+    }
+
+    pub(crate) fn remove(&self, tname: &str, sequence: u64) {
+        let mut table = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(locks) = table.get_mut(tname) {
+            locks.remove(&sequence);
+        }
+    }
+
+    pub(crate) fn remove_tree(&self, tname: &str) {
+        let mut table = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        table.remove(tname);
+    }
+
+    fn release_token(&self, tname: &str, sequence: u64, token: u64) {
+        let mut table = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(locks) = table.get_mut(tname) {
+            if locks.get(&sequence).is_some_and(|e| e.token == token) {
+                locks.remove(&sequence);
+            }
+        }
+    }
+}
+
+// Reservation of one record, released on drop. Renewing the lock with
+// `lock_record` hands out a new guard and makes the previous one inert.
+#[derive(Debug)]
+pub struct RecordLock {
+    table: LockTable,
+    tree: String,
+    sequence: u64,
+    owner: String,
+    token: u64,
+}
+
+impl RecordLock {
+    pub fn tree(&self) -> &str {
+        &self.tree
+    }
+
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    pub fn owner(&self) -> &str {
+        &self.owner
+    }
+}
+
+impl Drop for RecordLock {
+    fn drop(&mut self) {
+        self.table
+            .release_token(&self.tree, self.sequence, self.token);
+    }
+}
@@ -0,0 +1,79 @@
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::error::JsonStoreError;
+
+// How merge_from settles a record of the other store that collides with one here:
+// they share unique fields (or, in a tree without unique constraints, a sequence)
+// but differ otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MergePolicy {
+    // keep the record here and leave the other out
+    #[default]
+    PreferSelf,
+    // replace the record here with the other, under the sequence it has here
+    PreferOther,
+    // leave the whole tree unmerged
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MergeOptions {
+    pub policy: MergePolicy,
+    // Keep the other store's sequences for the records added, rather than handing out
+    // new ones. A sequence already taken here is then a collision as well.
+    pub preserve_sequences: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TreeMerge {
+    // records of the other store added or replacing one here
+    pub merged: usize,
+    // records of the other store left out: identical to one here, or losing a collision
+    pub skipped: usize,
+    // records of the other store that collided with one here, however settled
+    pub conflicting: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct MergeReport {
+    // trees only the other store had, copied whole
+    pub created: Vec<String>,
+    pub trees: BTreeMap<String, TreeMerge>,
+    // trees left untouched, and why
+    pub failed: Vec<(String, JsonStoreError)>,
+}
+
+// whether two records hold the same data, whatever their sequences
+pub(crate) fn same_record(a: &Value, b: &Value, sequence_field: &str) -> bool {
+    match (a.as_object(), b.as_object()) {
+        (Some(a), Some(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, value)| key == sequence_field || b.get(key) == Some(value))
+        }
+        _ => a == b,
+    }
+}
+
+// patch merged into target as RFC 7396 has it, for JsonStore::merge_patch
+pub(crate) fn apply_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(fields) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+    let Value::Object(target) = target else {
+        return;
+    };
+    for (key, value) in fields {
+        match value {
+            Value::Null => {
+                target.remove(key);
+            }
+            value => apply_patch(target.entry(key.clone()).or_insert(Value::Null), value),
+        }
+    }
+}
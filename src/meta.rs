@@ -0,0 +1,165 @@
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    backend::StorageBackend,
+    checksum,
+    codec::Codec,
+    error::JsonStoreError,
+    io::{exists, get_json, put_json, to_bytes},
+    layout::Layout,
+    store::{Durability, Info, OutputFormat},
+    trace,
+};
+
+// `meta.json` records the on-disk format version of a store directory. Directories
+// written before it existed are version 1. Opening an older directory upgrades it in
+// place through MIGRATIONS, after copying its files into a backup directory; opening
+// a newer one fails rather than risk misreading it.
+
+pub(crate) const META_FILE: &str = "meta.json";
+pub(crate) const INFOS_FILE: &str = "infos.json";
+pub const FORMAT_VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct Meta {
+    pub(crate) format_version: u32,
+    #[serde(default)]
+    pub(crate) codec: Codec,
+    // left out while it is the default, so older builds can still read the file
+    #[serde(default, skip_serializing_if = "Layout::is_default")]
+    pub(crate) layout: Layout,
+    // the store's user metadata, see set_store_metadata
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) metadata: HashMap<String, Value>,
+}
+
+impl Default for Meta {
+    fn default() -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            codec: Codec::default(),
+            layout: Layout::default(),
+            metadata: HashMap::new(),
+        }
+    }
+}
+
+type Step = for<'a> fn(&'a dyn StorageBackend) -> BoxFuture<'a, Result<(), JsonStoreError>>;
+
+// MIGRATIONS[i] upgrades a directory from version i + 1 to i + 2
+const MIGRATIONS: [Step; FORMAT_VERSION as usize - 1] = [|backend| Box::pin(v1_to_v2(backend))];
+
+// Read the format version of the store in backend, failing if it is newer than this
+// build understands. Nothing is written.
+pub(crate) async fn check(backend: &dyn StorageBackend) -> Result<Meta, JsonStoreError> {
+    let meta = match get_json::<Meta>(backend, META_FILE).await? {
+        Some(meta) => meta,
+        None if exists(backend, INFOS_FILE).await? => Meta {
+            format_version: 1,
+            ..Meta::default()
+        },
+        None => Meta::default(),
+    };
+
+    if meta.format_version > FORMAT_VERSION {
+        return Err(JsonStoreError::UnsupportedFormatVersion {
+            found: meta.format_version,
+            supported: FORMAT_VERSION,
+        });
+    }
+
+    Ok(meta)
+}
+
+// Bring the store in backend up to FORMAT_VERSION. A store without infos.json holds
+// no trees yet and is left alone; meta.json is written with the first tree.
+pub(crate) async fn upgrade(backend: &dyn StorageBackend) -> Result<Meta, JsonStoreError> {
+    let meta = check(backend).await?;
+
+    if meta.format_version == FORMAT_VERSION {
+        return Ok(meta);
+    }
+
+    backup(backend, meta.format_version).await?;
+
+    for version in meta.format_version..FORMAT_VERSION {
+        trace::info!(
+            path = ?backend.location(""),
+            from = version,
+            to = version + 1,
+            "migrating store format"
+        );
+        MIGRATIONS[version as usize - 1](backend).await?;
+    }
+
+    let meta = Meta {
+        format_version: FORMAT_VERSION,
+        ..meta
+    };
+    put_meta(
+        backend,
+        &meta,
+        OutputFormat::default(),
+        Durability::default(),
+    )
+    .await?;
+
+    Ok(meta)
+}
+
+pub(crate) async fn put_meta(
+    backend: &dyn StorageBackend,
+    meta: &Meta,
+    format: OutputFormat,
+    durability: Durability,
+) -> Result<(), JsonStoreError> {
+    put_json(backend, META_FILE, meta, format, durability).await
+}
+
+// copy the store's files into `.backup-format-v{version}/` before migrating them
+async fn backup(backend: &dyn StorageBackend, version: u32) -> Result<(), JsonStoreError> {
+    let dest = format!(".backup-format-v{}", version);
+
+    for key in backend.list().await? {
+        if let Some(context) = backend.read(&key).await? {
+            let key = format!("{}/{}", dest, key);
+            backend.write(&key, context, Durability::default()).await?;
+        }
+    }
+
+    Ok(())
+}
+
+// Version 2 introduces meta.json and checksum sidecars. Each Info is written in full,
+// so fields added with serde defaults (such as storage) are explicit in infos.json
+// from then on, and each tree's snapshot is rewritten in sequence order, the order
+// saves keep since, with a sidecar beside it. Version 1 knew nothing but snapshot
+// trees at the top of the directory, so that is all there is to rewrite.
+async fn v1_to_v2(backend: &dyn StorageBackend) -> Result<(), JsonStoreError> {
+    let Some(infos) = get_json::<BTreeMap<String, Info>>(backend, INFOS_FILE).await? else {
+        return Ok(());
+    };
+
+    for tname in infos.keys() {
+        let key = format!("{}.json", tname);
+        let Some(records) = get_json::<BTreeMap<u64, Value>>(backend, &key).await? else {
+            continue;
+        };
+        let context = to_bytes(&records, OutputFormat::default())?;
+        let digest = checksum::digest(&context);
+        backend.write(&key, context, Durability::default()).await?;
+        checksum::write_sidecar(backend, &key, &digest, Durability::default()).await?;
+    }
+
+    put_json(
+        backend,
+        INFOS_FILE,
+        &serde_json::to_value(infos)?,
+        OutputFormat::default(),
+        Durability::default(),
+    )
+    .await
+}
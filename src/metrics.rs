@@ -0,0 +1,157 @@
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use crate::error::{ErrorKind, JsonStoreError};
+
+// Counts and latencies of the store's operations, kept when LoadOptions::metrics is
+// set. Ops on a tree are counted under it; the rest (save, verify, backup...) under
+// the store.
+
+// upper bounds of the latency buckets in microseconds; slower calls land in one more,
+// open-ended bucket
+pub const LATENCY_BUCKETS_MICROS: [u64; 5] = [100, 1_000, 10_000, 100_000, 1_000_000];
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct OpStats {
+    pub count: u64,
+    // failed calls, by kind of error
+    pub errors: BTreeMap<ErrorKind, u64>,
+    pub total_micros: u64,
+    pub max_micros: u64,
+    // calls per LATENCY_BUCKETS_MICROS bucket
+    pub latency_buckets: Vec<u64>,
+}
+
+impl Default for OpStats {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            errors: BTreeMap::new(),
+            total_micros: 0,
+            max_micros: 0,
+            latency_buckets: vec![0; LATENCY_BUCKETS_MICROS.len() + 1],
+        }
+    }
+}
+
+impl OpStats {
+    pub fn error_count(&self) -> u64 {
+        self.errors.values().sum()
+    }
+
+    fn record(&mut self, elapsed: Duration, error: Option<ErrorKind>) {
+        let micros = elapsed.as_micros() as u64;
+        self.count += 1;
+        self.total_micros += micros;
+        self.max_micros = self.max_micros.max(micros);
+        let bucket = LATENCY_BUCKETS_MICROS
+            .iter()
+            .position(|bound| micros <= *bound)
+            .unwrap_or(LATENCY_BUCKETS_MICROS.len());
+        self.latency_buckets[bucket] += 1;
+        if let Some(kind) = error {
+            *self.errors.entry(kind).or_default() += 1;
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct TreeMetrics {
+    pub ops: BTreeMap<String, OpStats>,
+    pub lock_acquisitions: u64,
+    // acquisitions that waited at least LOCK_WAIT_THRESHOLD
+    pub lock_contended: u64,
+    pub lock_wait_micros: u64,
+    // reads by TreeHandle::select_cached, answered from the cache or not
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    // records find_by_field, find_range and find_sorted looked at one by one, having
+    // no index on the field to go by
+    pub records_scanned: u64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    // when counting started, or was last reset; rates are counts over the time since
+    pub since: SystemTime,
+    pub store: BTreeMap<String, OpStats>,
+    pub trees: BTreeMap<String, TreeMetrics>,
+}
+
+impl MetricsSnapshot {
+    fn new(since: SystemTime) -> Self {
+        Self {
+            since,
+            store: BTreeMap::new(),
+            trees: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Metrics {
+    state: Mutex<MetricsSnapshot>,
+}
+
+impl Metrics {
+    pub(crate) fn new(since: SystemTime) -> Self {
+        Self {
+            state: Mutex::new(MetricsSnapshot::new(since)),
+        }
+    }
+
+    pub(crate) fn record(
+        &self,
+        op: &str,
+        tname: Option<&str>,
+        elapsed: Duration,
+        error: Option<&JsonStoreError>,
+    ) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let ops = match tname {
+            Some(tname) => &mut state.trees.entry(tname.to_string()).or_default().ops,
+            None => &mut state.store,
+        };
+        ops.entry(op.to_string())
+            .or_default()
+            .record(elapsed, error.map(JsonStoreError::kind));
+    }
+
+    pub(crate) fn record_lock(&self, tname: &str, waited: Duration, contended: bool) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let tree = state.trees.entry(tname.to_string()).or_default();
+        tree.lock_acquisitions += 1;
+        tree.lock_contended += contended as u64;
+        tree.lock_wait_micros += waited.as_micros() as u64;
+    }
+
+    pub(crate) fn record_cache(&self, tname: &str, hit: bool) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let tree = state.trees.entry(tname.to_string()).or_default();
+        match hit {
+            true => tree.cache_hits += 1,
+            false => tree.cache_misses += 1,
+        }
+    }
+
+    pub(crate) fn record_scan(&self, tname: &str, records: u64) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state
+            .trees
+            .entry(tname.to_string())
+            .or_default()
+            .records_scanned += records;
+    }
+
+    pub(crate) fn snapshot(&self) -> MetricsSnapshot {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub(crate) fn reset(&self, since: SystemTime) {
+        *self.state.lock().unwrap_or_else(|e| e.into_inner()) = MetricsSnapshot::new(since);
+    }
+}
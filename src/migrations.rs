@@ -0,0 +1,40 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+// An upgrade of one tree's records to version, run by JsonStore::migrate on each
+// record in turn. Versions start at 1; a tree's applied version is kept in its
+// Info::migration_version and is 0 before any migration ran.
+#[derive(Debug, Clone)]
+pub struct Migration {
+    pub version: u32,
+    pub tree: String,
+    pub up: fn(&mut Value) -> Result<(), String>,
+}
+
+// where the version was kept, in Info::metadata, before it had a field of its own
+pub(crate) const LEGACY_VERSION_KEY: &str = "migration_version";
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+    // steps run by this call, in the order they ran
+    pub applied: Vec<AppliedMigration>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct AppliedMigration {
+    pub tree: String,
+    pub version: u32,
+    pub records: usize,
+}
+
+pub(crate) fn is_unset(version: &u32) -> bool {
+    *version == 0
+}
+
+// the version a catalog written before Info::migration_version keeps in metadata, or
+// the number found there if it is too big to be one
+pub(crate) fn legacy_version(metadata: &HashMap<String, Value>) -> Option<Result<u32, u64>> {
+    let version = metadata.get(LEGACY_VERSION_KEY)?.as_u64()?;
+    Some(u32::try_from(version).map_err(|_| version))
+}
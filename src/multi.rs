@@ -0,0 +1,158 @@
+use futures::future;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+};
+
+use crate::{error::JsonStoreError, store::JsonStore};
+
+// Several stores under labels, e.g. one directory per tenant, read as one. Calls
+// naming a label go to that store unchanged; query_all asks every store with the
+// tree at once. Clones share the same set of stores.
+#[derive(Debug, Clone, Default)]
+pub struct MultiStore {
+    stores: Arc<RwLock<BTreeMap<String, JsonStore>>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tagged<T> {
+    // label of the store the record came from
+    pub label: String,
+    pub record: T,
+}
+
+#[derive(Debug)]
+pub struct FanOut<T> {
+    // by label, then in each store's sequence order
+    pub records: Vec<Tagged<T>>,
+    // stores whose tree couldn't be read; the others are still in records
+    pub errors: BTreeMap<String, JsonStoreError>,
+}
+
+impl MultiStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // add store under label, which must not be taken yet
+    pub fn attach(&self, label: &str, store: JsonStore) -> Result<(), JsonStoreError> {
+        let mut stores = self.stores.write().unwrap_or_else(|e| e.into_inner());
+        if stores.contains_key(label) {
+            return Err(JsonStoreError::InvalidOptions(format!(
+                "a store is attached as '{}' already",
+                label
+            )));
+        }
+        stores.insert(label.to_string(), store);
+        Ok(())
+    }
+
+    pub fn detach(&self, label: &str) -> Option<JsonStore> {
+        let mut stores = self.stores.write().unwrap_or_else(|e| e.into_inner());
+        stores.remove(label)
+    }
+
+    // labels of the attached stores, sorted
+    pub fn labels(&self) -> Vec<String> {
+        let stores = self.stores.read().unwrap_or_else(|e| e.into_inner());
+        stores.keys().cloned().collect()
+    }
+
+    pub fn store(&self, label: &str) -> Result<JsonStore, JsonStoreError> {
+        let stores = self.stores.read().unwrap_or_else(|e| e.into_inner());
+        stores
+            .get(label)
+            .cloned()
+            .ok_or(JsonStoreError::UnknownStore(label.to_string()))
+    }
+
+    pub async fn select<T: DeserializeOwned>(
+        &self,
+        label: &str,
+        tname: &str,
+        sequence: u64,
+    ) -> Result<T, JsonStoreError> {
+        self.store(label)?.select(tname, sequence).await
+    }
+
+    pub async fn select_where<T: DeserializeOwned, F: Fn(&Value) -> bool>(
+        &self,
+        label: &str,
+        tname: &str,
+        filter: F,
+    ) -> Result<Vec<T>, JsonStoreError> {
+        self.store(label)?.select_where(tname, filter).await
+    }
+
+    pub async fn insert<T: Serialize>(
+        &self,
+        label: &str,
+        tname: &str,
+        value: &T,
+    ) -> Result<u64, JsonStoreError> {
+        self.store(label)?.insert(tname, value).await
+    }
+
+    pub async fn update<T: Serialize>(
+        &self,
+        label: &str,
+        tname: &str,
+        value: &T,
+    ) -> Result<(), JsonStoreError> {
+        self.store(label)?.update(tname, value).await
+    }
+
+    pub async fn delete(
+        &self,
+        label: &str,
+        tname: &str,
+        sequence: u64,
+    ) -> Result<(), JsonStoreError> {
+        self.store(label)?.delete(tname, sequence).await
+    }
+
+    // The records matching filter in tname of every attached store that has it, read
+    // from all of them concurrently. A store failing doesn't stop the rest.
+    pub async fn query_all<T: DeserializeOwned, F: Fn(&Value) -> bool>(
+        &self,
+        tname: &str,
+        filter: F,
+    ) -> FanOut<T> {
+        let stores = {
+            let stores = self.stores.read().unwrap_or_else(|e| e.into_inner());
+            stores
+                .iter()
+                .filter(|(_, store)| store.has_tree(tname))
+                .map(|(label, store)| (label.clone(), store.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        let results = future::join_all(
+            stores
+                .iter()
+                .map(|(_, store)| store.select_where::<T, _>(tname, &filter)),
+        )
+        .await;
+
+        let mut fan_out = FanOut {
+            records: Vec::new(),
+            errors: BTreeMap::new(),
+        };
+        for ((label, _), result) in stores.into_iter().zip(results) {
+            match result {
+                Ok(records) => fan_out
+                    .records
+                    .extend(records.into_iter().map(|record| Tagged {
+                        label: label.clone(),
+                        record,
+                    })),
+                Err(e) => {
+                    fan_out.errors.insert(label, e);
+                }
+            }
+        }
+        fan_out
+    }
+}
@@ -0,0 +1,260 @@
+use futures::{future::BoxFuture, TryStreamExt};
+use object_store::{path::Path as ObjectPath, ObjectStore, ObjectStoreExt, PutPayload};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    backend::{Stamp, StorageBackend},
+    error::JsonStoreError,
+    rt::{self, Instant},
+    store::Durability,
+    trace,
+};
+
+// Store files as objects in any `object_store` implementation (S3, MinIO, GCS,
+// Azure, or its in-memory store for tests), under a key prefix. Enable the cloud
+// you need on your own `object_store` dependency and pass the built store in.
+//
+// There is no rename, so the model is last-writer-wins per object: each write is a
+// single PUT that replaces the object whole, and a crash mid-save can leave some of
+// a tree's objects from the new save and some from the old. Appends (WAL and
+// append-log trees) read the object and PUT it back, so they cost a round trip
+// each and must not race another writer. Stamps come from the object's last
+// modified time and size, which some services only keep to the second.
+//
+// With a manifest, every completed save ends by writing `{prefix}/manifest.json`
+// listing the store's objects; since trees are written first, the manifest only
+// ever names objects of finished saves, and list() answers from it.
+
+const MANIFEST: &str = "manifest.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectStoreOptions {
+    // extra attempts for requests that fail in transit; other failures are not retried
+    pub retries: u32,
+    // wait before the first retry, doubled for each one after
+    pub retry_delay: Duration,
+    pub manifest: bool,
+}
+
+impl Default for ObjectStoreOptions {
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            retry_delay: Duration::from_millis(100),
+            manifest: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Manifest {
+    // key -> size in bytes
+    objects: BTreeMap<String, u64>,
+}
+
+#[derive(Debug)]
+pub struct ObjectStoreBackend {
+    store: Arc<dyn ObjectStore>,
+    prefix: ObjectPath,
+    options: ObjectStoreOptions,
+}
+
+impl ObjectStoreBackend {
+    pub fn new(store: Arc<dyn ObjectStore>, prefix: &str, options: ObjectStoreOptions) -> Self {
+        Self {
+            store,
+            prefix: ObjectPath::from(prefix),
+            options,
+        }
+    }
+
+    fn path(&self, key: &str) -> ObjectPath {
+        match self.prefix.is_root() {
+            true => ObjectPath::from(key),
+            false => ObjectPath::from(format!("{}/{}", self.prefix, key)),
+        }
+    }
+
+    // run op, retrying while it fails with a transient error
+    async fn retry<T, F, Fut>(&self, key: &str, op: F) -> Result<T, JsonStoreError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = object_store::Result<T>>,
+    {
+        let mut delay = self.options.retry_delay;
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            match op().await {
+                Ok(value) => return Ok(value),
+                Err(e @ object_store::Error::Generic { .. })
+                    if attempts <= self.options.retries =>
+                {
+                    trace::warn!(key, attempts, error = %e, "retrying object store request");
+                    rt::sleep(delay).await;
+                    delay *= 2;
+                }
+                Err(source) => {
+                    return Err(JsonStoreError::ObjectStore {
+                        key: key.to_string(),
+                        attempts,
+                        source,
+                    })
+                }
+            }
+        }
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, JsonStoreError> {
+        let path = self.path(key);
+        self.retry(key, || async {
+            match self.store.get(&path).await {
+                Ok(result) => Ok(Some(result.bytes().await?.to_vec())),
+                Err(object_store::Error::NotFound { .. }) => Ok(None),
+                Err(e) => Err(e),
+            }
+        })
+        .await
+    }
+
+    async fn put(&self, key: &str, bytes: Vec<u8>) -> Result<(), JsonStoreError> {
+        let path = self.path(key);
+        let payload = PutPayload::from(bytes);
+        self.retry(key, || async {
+            self.store.put(&path, payload.clone()).await.map(|_| ())
+        })
+        .await
+    }
+
+    async fn list_objects(&self) -> Result<BTreeMap<String, u64>, JsonStoreError> {
+        let objects = self
+            .retry("", || async {
+                self.store
+                    .list(Some(&self.prefix))
+                    .try_collect::<Vec<_>>()
+                    .await
+            })
+            .await?;
+
+        Ok(objects
+            .into_iter()
+            .filter_map(|meta| {
+                let parts = meta
+                    .location
+                    .prefix_match(&self.prefix)?
+                    .collect::<Vec<_>>();
+                match parts.as_slice() {
+                    [name] => Some((name.as_ref().to_string(), meta.size)),
+                    _ => None,
+                }
+            })
+            .filter(|(key, _)| key != MANIFEST)
+            .collect())
+    }
+}
+
+impl StorageBackend for ObjectStoreBackend {
+    fn read<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>, JsonStoreError>> {
+        Box::pin(self.get(key))
+    }
+
+    fn write<'a>(
+        &'a self,
+        key: &'a str,
+        bytes: Vec<u8>,
+        _durability: Durability,
+    ) -> BoxFuture<'a, Result<(), JsonStoreError>> {
+        Box::pin(async move {
+            let started = Instant::now();
+            let len = bytes.len();
+            self.put(key, bytes).await?;
+            trace::info!(
+                key,
+                bytes = len,
+                elapsed_ms = started.elapsed().as_millis() as u64,
+                "wrote object"
+            );
+            Ok(())
+        })
+    }
+
+    fn append<'a>(
+        &'a self,
+        key: &'a str,
+        bytes: Vec<u8>,
+        _fsync: bool,
+    ) -> BoxFuture<'a, Result<(), JsonStoreError>> {
+        Box::pin(async move {
+            let mut context = self.get(key).await?.unwrap_or_default();
+            context.extend_from_slice(&bytes);
+            self.put(key, context).await
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), JsonStoreError>> {
+        let path = self.path(key);
+        Box::pin(async move {
+            self.retry(key, || async {
+                match self.store.delete(&path).await {
+                    Err(object_store::Error::NotFound { .. }) => Ok(()),
+                    result => result,
+                }
+            })
+            .await
+        })
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<String>, JsonStoreError>> {
+        Box::pin(async move {
+            if self.options.manifest {
+                if let Some(context) = self.get(MANIFEST).await? {
+                    let manifest = serde_json::from_slice::<Manifest>(&context)?;
+                    return Ok(manifest.objects.into_keys().collect());
+                }
+            }
+
+            Ok(self.list_objects().await?.into_keys().collect())
+        })
+    }
+
+    fn stamp<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Stamp>, JsonStoreError>> {
+        let path = self.path(key);
+        Box::pin(async move {
+            self.retry(key, || async {
+                match self.store.head(&path).await {
+                    Ok(meta) => Ok(Some(Stamp::new(
+                        SystemTime::from(meta.last_modified),
+                        meta.size,
+                    ))),
+                    Err(object_store::Error::NotFound { .. }) => Ok(None),
+                    Err(e) => Err(e),
+                }
+            })
+            .await
+        })
+    }
+
+    fn location(&self, key: &str) -> PathBuf {
+        PathBuf::from(self.path(key).to_string())
+    }
+
+    fn sync(&self) -> BoxFuture<'_, Result<(), JsonStoreError>> {
+        Box::pin(async move {
+            if !self.options.manifest {
+                return Ok(());
+            }
+
+            let manifest = Manifest {
+                objects: self.list_objects().await?,
+            };
+            self.put(MANIFEST, serde_json::to_vec(&manifest)?).await
+        })
+    }
+}
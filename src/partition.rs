@@ -0,0 +1,228 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{backend::Stamp, backup::civil_from_days};
+
+// Time-based partitioning of a tree, set by Info::partition_by. Each record goes by
+// the time in its field into one snapshot file per period, `{tree}/2024-06.json` by
+// month or `{tree}/2024-06-01.json` by day; records whose field is missing or not a
+// time go to `{tree}/undated.json`. `{tree}.partitions.json` lists the sequences in
+// each period, so a tree is read with none of its partitions and pulls each in on
+// first use. Sequences stay global to the tree, in its one `.seq` file.
+//
+// A time is a number of milliseconds since the epoch or an RFC 3339 string, a bare
+// date ("2024-06-01") or date and time ("2024-06-01T12:00:00.250+02:00"); one
+// without an offset is taken as UTC, as are the periods.
+//
+// What this version supports: insert, update, delete, select and select_range load
+// only the partitions they touch, and unload_partitions_before and
+// archive_partitions_before let old ones go. Everything else that works on a whole
+// tree (select_where, export, import, merge, diff, undo, migrations...) loads every
+// partition first. Unique constraints, secondary indexes, shards and append-log
+// storage can't be combined with partitioning, as a write would have to see every
+// partition; Info::build and create_index refuse them, and restore_tree_to and
+// reshard_tree refuse partitioned trees.
+
+// where records without a usable time go
+pub const UNDATED: &str = "undated";
+
+const DAY_MILLIS: i64 = 86_400_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Granularity {
+    Day,
+    Month,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PartitionSpec {
+    // the record field holding its time
+    pub field: String,
+    pub granularity: Granularity,
+}
+
+impl PartitionSpec {
+    // the period the record value falls in
+    pub fn period(&self, value: &Value) -> String {
+        match self.time(value) {
+            Some(millis) => self.period_at(millis),
+            None => UNDATED.to_string(),
+        }
+    }
+
+    // the record's time in milliseconds since the epoch, if its field holds one
+    pub(crate) fn time(&self, value: &Value) -> Option<i64> {
+        time_of(&value[self.field.as_str()])
+    }
+
+    fn period_at(&self, millis: i64) -> String {
+        let (year, month, day) = civil_from_days(millis.div_euclid(DAY_MILLIS));
+        match self.granularity {
+            Granularity::Day => format!("{:04}-{:02}-{:02}", year, month, day),
+            Granularity::Month => format!("{:04}-{:02}", year, month),
+        }
+    }
+
+    // the first and one past the last millisecond of period; None for UNDATED
+    fn bounds(&self, period: &str) -> Option<(i64, i64)> {
+        let (year, month, day) = match self.granularity {
+            Granularity::Day if period.len() == 10 => (
+                digits(period, 0..4)?,
+                digits(period, 5..7)?,
+                digits(period, 8..10)?,
+            ),
+            Granularity::Month if period.len() == 7 => {
+                (digits(period, 0..4)?, digits(period, 5..7)?, 1)
+            }
+            _ => return None,
+        };
+        let start = days_from_civil(year, month as u32, day as u32);
+        let end = match self.granularity {
+            Granularity::Day => start + 1,
+            Granularity::Month if month == 12 => days_from_civil(year + 1, 1, 1),
+            Granularity::Month => days_from_civil(year, month as u32 + 1, 1),
+        };
+        Some((start * DAY_MILLIS, end * DAY_MILLIS))
+    }
+
+    // whether any of period lies in [from, to)
+    pub(crate) fn overlaps(&self, period: &str, from: i64, to: i64) -> bool {
+        self.bounds(period)
+            .is_some_and(|(start, end)| start < to && end > from)
+    }
+
+    // whether all of period lies before cutoff
+    pub(crate) fn ends_by(&self, period: &str, cutoff: i64) -> bool {
+        self.bounds(period).is_some_and(|(_, end)| end <= cutoff)
+    }
+}
+
+// A partition of a tree as kept in memory. Its sequences are known whether or not
+// its records are loaded.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Partition {
+    pub(crate) sequences: BTreeSet<u64>,
+    pub(crate) loaded: bool,
+    // holds writes since the last save
+    pub(crate) dirty: bool,
+    // of its file as of our last read or write
+    pub(crate) stamp: Option<Stamp>,
+}
+
+impl Partition {
+    // a partition new to the tree, with no file yet
+    pub(crate) fn empty() -> Self {
+        Self {
+            loaded: true,
+            ..Self::default()
+        }
+    }
+}
+
+// contents of `{tree}.partitions.json`: the sequences in each period
+pub(crate) type PartitionIndex = BTreeMap<String, BTreeSet<u64>>;
+
+pub(crate) fn index(partitions: &BTreeMap<String, Partition>) -> PartitionIndex {
+    partitions
+        .iter()
+        .map(|(period, partition)| (period.clone(), partition.sequences.clone()))
+        .collect()
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PartitionStats {
+    pub period: String,
+    pub records: usize,
+    pub loaded: bool,
+}
+
+// a time as a record holds it, in milliseconds since the epoch
+pub(crate) fn time_of(value: &Value) -> Option<i64> {
+    match value {
+        Value::Number(n) => n.as_i64().or_else(|| n.as_f64().map(|f| f.floor() as i64)),
+        Value::String(s) => parse_time(s),
+        _ => None,
+    }
+}
+
+pub(crate) fn millis(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_millis() as i64,
+        Err(e) => -(e.duration().as_millis() as i64),
+    }
+}
+
+// an RFC 3339 date or date-time in milliseconds since the epoch
+pub(crate) fn parse_time(s: &str) -> Option<i64> {
+    let b = s.as_bytes();
+    if b.len() < 10 || b[4] != b'-' || b[7] != b'-' {
+        return None;
+    }
+    let (year, month, day) = (digits(s, 0..4)?, digits(s, 5..7)?, digits(s, 8..10)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let mut millis = days_from_civil(year, month as u32, day as u32) * DAY_MILLIS;
+    if b.len() == 10 {
+        return Some(millis);
+    }
+
+    if b.len() < 19 || !matches!(b[10], b'T' | b't' | b' ') || b[13] != b':' || b[16] != b':' {
+        return None;
+    }
+    let (hour, minute, second) = (digits(s, 11..13)?, digits(s, 14..16)?, digits(s, 17..19)?);
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    millis += ((hour * 60 + minute) * 60 + second) * 1000;
+
+    let mut rest = &s[19..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let n = fraction.bytes().take_while(u8::is_ascii_digit).count();
+        if n == 0 {
+            return None;
+        }
+        millis += format!("{:0<3}", &fraction[..n.min(3)])
+            .parse::<i64>()
+            .ok()?;
+        rest = &fraction[n..];
+    }
+
+    match rest {
+        "" | "Z" | "z" => Some(millis),
+        offset if offset.len() == 6 && offset.as_bytes()[3] == b':' => {
+            let sign = match offset.as_bytes()[0] {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            let minutes = digits(offset, 1..3)? * 60 + digits(offset, 4..6)?;
+            Some(millis - sign * minutes * 60_000)
+        }
+        _ => None,
+    }
+}
+
+fn digits(s: &str, range: std::ops::Range<usize>) -> Option<i64> {
+    let part = s.get(range)?;
+    if !part.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    part.parse().ok()
+}
+
+// (year, month, day) to days since 1970-01-01, the inverse of civil_from_days
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
@@ -0,0 +1,158 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+// What the values of one top-level field of a tree look like, from profile_field and
+// profile_tree. Each record is looked at once; the distinct values are counted in a
+// map, so a field holding large objects costs memory in proportion.
+
+// how many of the most frequent values a profile lists
+pub const TOP_VALUES: usize = 10;
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct FieldProfile {
+    pub field: String,
+    // records holding a value other than null
+    pub present: usize,
+    pub null: usize,
+    pub missing: usize,
+    pub types: TypeCounts,
+    // over the numbers only, None if there are none; likewise strings
+    pub numbers: Option<NumberStats>,
+    pub strings: Option<StringStats>,
+    // most frequent first, ties in the order of their JSON text
+    pub top_values: Vec<ValueCount>,
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct TypeCounts {
+    pub strings: usize,
+    pub numbers: usize,
+    pub bools: usize,
+    pub objects: usize,
+    pub arrays: usize,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct NumberStats {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct StringStats {
+    // by byte order
+    pub min: String,
+    pub max: String,
+    // in chars
+    pub min_length: usize,
+    pub max_length: usize,
+    pub mean_length: f64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ValueCount {
+    pub value: Value,
+    pub count: usize,
+}
+
+// a profile being gathered, fed one record's value at a time
+#[derive(Debug, Default)]
+pub(crate) struct Profiler {
+    present: usize,
+    null: usize,
+    types: TypeCounts,
+    numbers: Option<(f64, f64, f64)>,
+    strings: Option<(String, String, usize, usize, usize)>,
+    // by JSON text
+    values: HashMap<String, ValueCount>,
+}
+
+impl Profiler {
+    pub(crate) fn add(&mut self, value: &Value) {
+        match value {
+            Value::Null => {
+                self.null += 1;
+                return;
+            }
+            Value::Bool(_) => self.types.bools += 1,
+            Value::Number(n) => {
+                self.types.numbers += 1;
+                let n = n.as_f64().unwrap_or_default();
+                self.numbers = Some(match self.numbers {
+                    Some((min, max, sum)) => (min.min(n), max.max(n), sum + n),
+                    None => (n, n, n),
+                });
+            }
+            Value::String(s) => {
+                self.types.strings += 1;
+                let len = s.chars().count();
+                match &mut self.strings {
+                    Some((min, max, min_len, max_len, total)) => {
+                        if s < min {
+                            *min = s.clone();
+                        }
+                        if s > max {
+                            *max = s.clone();
+                        }
+                        *min_len = (*min_len).min(len);
+                        *max_len = (*max_len).max(len);
+                        *total += len;
+                    }
+                    None => self.strings = Some((s.clone(), s.clone(), len, len, len)),
+                }
+            }
+            Value::Array(_) => self.types.arrays += 1,
+            Value::Object(_) => self.types.objects += 1,
+        }
+        self.present += 1;
+
+        self.values
+            .entry(value.to_string())
+            .or_insert_with(|| ValueCount {
+                value: value.clone(),
+                count: 0,
+            })
+            .count += 1;
+    }
+
+    // the profile of field over records records, those not fed being missing
+    pub(crate) fn finish(self, field: &str, records: usize) -> FieldProfile {
+        let numbers = self.numbers.map(|(min, max, sum)| NumberStats {
+            min,
+            max,
+            mean: sum / self.types.numbers as f64,
+        });
+        let strings = self
+            .strings
+            .map(|(min, max, min_length, max_length, total)| StringStats {
+                min,
+                max,
+                min_length,
+                max_length,
+                mean_length: total as f64 / self.types.strings as f64,
+            });
+
+        let mut values = self.values.into_iter().collect::<Vec<_>>();
+        values.sort_by(|(a, a_count), (b, b_count)| {
+            b_count.count.cmp(&a_count.count).then_with(|| a.cmp(b))
+        });
+        let top_values = values
+            .into_iter()
+            .take(TOP_VALUES)
+            .map(|(_, count)| count)
+            .collect();
+
+        FieldProfile {
+            field: field.to_string(),
+            present: self.present,
+            null: self.null,
+            missing: records - self.present - self.null,
+            types: self.types,
+            numbers,
+            strings,
+            top_values,
+        }
+    }
+}
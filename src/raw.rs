@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{value::RawValue, Value};
+use std::{borrow::Cow, collections::HashMap, sync::Arc};
+
+use crate::{error::JsonStoreError, key::Records};
+
+// How a tree holds its records in memory, set by Info::value_mode. A Raw tree keeps
+// each one as the JSON text it was written with and builds no Value for it, for
+// trees used as document stores: records written and read back whole, never looked
+// into. insert_raw, update_raw and select_raw pass the text through byte for byte.
+//
+// What parses what: insert_raw and update_raw read only the top level of the
+// document, for its sequence field, and select, select_shared and delete parse at
+// most the record they are given (delete only to keep it for undo_last and
+// history). Saving, loading, lock_record, stats and memory_estimate leave the text
+// alone. Every other operation that looks at records (insert and update of a value,
+// select_where, the find_* and export functions, imports, merges, diffs, undo_last,
+// migrations, profiles, vacuum_tree...) parses the whole tree first, as do raw
+// writes to a tree with unique constraints or indexes, which check them against
+// every record. A tree once parsed keeps both forms until it is unloaded. Records
+// written as a Value, by update, undo_last or a migration say, are kept in
+// serde_json's rendering of it.
+//
+// A Raw tree needs snapshot storage and can't be partitioned. Its files look like
+// any other tree's; under a codec other than JSON the records go through Value on
+// their way to and from disk, so only JSON keeps their text as it was.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueMode {
+    #[default]
+    Parsed,
+    Raw,
+}
+
+// a Raw tree's records by sequence, shared like Records
+pub(crate) type RawRecords = HashMap<u64, Arc<RawValue>>;
+
+pub(crate) fn parse(record: &RawValue) -> Result<Value, JsonStoreError> {
+    Ok(serde_json::from_str(record.get())?)
+}
+
+pub(crate) fn parse_all(
+    records: impl IntoIterator<Item = (u64, Arc<RawValue>)>,
+) -> Result<Records, JsonStoreError> {
+    records
+        .into_iter()
+        .map(|(seq, record)| Ok((seq, Arc::new(parse(&record)?))))
+        .collect()
+}
+
+pub(crate) fn to_raw(value: &Value) -> Arc<RawValue> {
+    let record = serde_json::value::to_raw_value(value).expect("a Value always serializes");
+    Arc::from(record)
+}
+
+// the sequence in field of document, an object, if it holds one
+pub(crate) fn sequence(document: &str, field: &str) -> Result<Option<u64>, JsonStoreError> {
+    let fields = top_level(document)?;
+    Ok(fields
+        .get(field)
+        .and_then(|value| serde_json::from_str(value.get()).ok()))
+}
+
+// Document, an object, with field set to seq. The number is written into the text,
+// over the field's value if it has one and as the first field otherwise, so the rest
+// of the document stays as it was.
+pub(crate) fn with_sequence(
+    document: &str,
+    field: &str,
+    seq: u64,
+) -> Result<Box<RawValue>, JsonStoreError> {
+    let fields = top_level(document)?;
+    let text = match fields.get(field) {
+        Some(value) => {
+            // the value is a slice of document
+            let start = value.get().as_ptr() as usize - document.as_ptr() as usize;
+            let end = start + value.get().len();
+            format!("{}{}{}", &document[..start], seq, &document[end..])
+        }
+        None => {
+            let open = document.find('{').unwrap_or_default() + 1;
+            let separator = if fields.is_empty() { "" } else { "," };
+            let key = serde_json::to_string(field)?;
+            let (head, tail) = document.split_at(open);
+            format!("{}{}:{}{}{}", head, key, seq, separator, tail)
+        }
+    };
+    Ok(RawValue::from_string(text)?)
+}
+
+// the fields of document, each value left as text
+fn top_level(document: &str) -> Result<HashMap<Cow<'_, str>, &RawValue>, JsonStoreError> {
+    if !document.trim_start().starts_with('{') {
+        return Err(JsonStoreError::UnObjectValue);
+    }
+    Ok(serde_json::from_str(document)?)
+}
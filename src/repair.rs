@@ -0,0 +1,76 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use crate::{error::JsonStoreError, integrity::TreeIntegrityReport};
+
+// What loading does with a tree whose files don't parse (truncated or garbled
+// snapshots, checksum mismatches, corrupt sequence files). I/O failures such as
+// missing permissions are never treated as corruption.
+//
+// Records that parse but fail the integrity check (see integrity.rs), such as two
+// sharing a unique key, fail the read under Fail. The other policies load the tree
+// as it is and only log them, so the duplicates can be fixed through the store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CorruptionPolicy {
+    // fail the operation that read the tree; the next use reads it again
+    #[default]
+    Fail,
+    // keep the other trees usable and fail every use of the bad one with
+    // TreeCorrupt until repair_tree fixes it
+    Skip,
+    // Skip, and read every tree while loading so load_report() can list them all,
+    // and the violations of each in integrity
+    Report,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeOutcome {
+    Loaded,
+    // the reason the tree's files could not be read
+    Corrupt(String),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LoadReport {
+    pub trees: BTreeMap<String, TreeOutcome>,
+    // trees that loaded with integrity violations
+    pub integrity: BTreeMap<String, TreeIntegrityReport>,
+}
+
+impl LoadReport {
+    // names of the trees that failed to load
+    pub fn corrupt(&self) -> Vec<&str> {
+        self.trees
+            .iter()
+            .filter(|(_, outcome)| matches!(outcome, TreeOutcome::Corrupt(_)))
+            .map(|(tname, _)| tname.as_str())
+            .collect()
+    }
+}
+
+// How repair_tree replaces a tree's files. Either way the current ones are moved
+// into `.corrupt-{millis}/` first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RepairStrategy {
+    // copy the tree's files from the backup directory at this path
+    RestoreFromBackup(PathBuf),
+    // an empty tree; the sequence counter is kept if it can still be read, so
+    // sequences of lost records aren't handed out again
+    StartEmpty,
+}
+
+pub(crate) fn is_corruption(e: &JsonStoreError) -> bool {
+    match e {
+        JsonStoreError::DeserializeFromStr(_)
+        | JsonStoreError::ParseFile { .. }
+        | JsonStoreError::InvalidUtf8 { .. }
+        | JsonStoreError::ChecksumMismatch { .. }
+        | JsonStoreError::CorruptSequenceFile { .. }
+        | JsonStoreError::Codec { .. } => true,
+        // what a truncated gzip stream reads as
+        JsonStoreError::Io(e) => matches!(
+            e.kind(),
+            std::io::ErrorKind::InvalidData | std::io::ErrorKind::UnexpectedEof
+        ),
+        _ => false,
+    }
+}
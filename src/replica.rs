@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, time::UNIX_EPOCH};
+
+use crate::backend::Stamp;
+
+// One-way replication. A store opened with LoadOptions::replica refuses every change
+// except sync_from, which makes it match another store directory: the source always
+// wins. Each tree's Info::sync_cursor says how far the replica got; while the
+// source's snapshot files are unchanged only its new WAL entries are applied,
+// otherwise the snapshots are compared record by record.
+
+// where the cursor was kept, in Info::metadata, before it had a field of its own
+pub(crate) const LEGACY_CURSOR_KEY: &str = "sync_cursor";
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct TreeSync {
+    pub inserted: usize,
+    pub updated: usize,
+    pub deleted: usize,
+    // caught up from the source's WAL rather than by comparing snapshots
+    pub from_log: bool,
+}
+
+impl TreeSync {
+    pub fn applied(&self) -> usize {
+        self.inserted + self.updated + self.deleted
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    // every tree of the source
+    pub trees: BTreeMap<String, TreeSync>,
+    // trees the replica had and the source no longer has
+    pub dropped: Vec<String>,
+}
+
+impl SyncReport {
+    pub fn applied(&self) -> usize {
+        self.trees.values().map(TreeSync::applied).sum()
+    }
+}
+
+// Kept by sync_from alone; what a caller puts in Info::sync_cursor is ignored.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncCursor {
+    // the source's snapshot files as of the last sync
+    pub(crate) snapshot: String,
+    // WAL entries on top of that snapshot already applied
+    pub(crate) changes: u64,
+}
+
+// what the stamps of a tree's files say about them, for spotting a new snapshot
+pub(crate) fn fingerprint(stamps: &[Option<Stamp>]) -> String {
+    stamps
+        .iter()
+        .map(|stamp| match stamp {
+            Some(stamp) => {
+                let modified = stamp.modified().duration_since(UNIX_EPOCH);
+                format!(
+                    "{}@{}",
+                    stamp.len(),
+                    modified.unwrap_or_default().as_nanos()
+                )
+            }
+            None => "-".to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
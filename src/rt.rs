@@ -0,0 +1,387 @@
+use futures::channel::oneshot;
+use std::{
+    future::Future,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use crate::error::JsonStoreError;
+
+// Everything the store asks of an async runtime: a pool for blocking work, which all
+// file access goes through, a timer, a way to run a background task and the time. The
+// tokio feature (the default) takes them from tokio and the smol feature from smol;
+// with both, tokio's are used. smol's pool, timer and executor run on threads of their
+// own, so the smol feature also serves async-std or any other executor:
+//
+//   json-store = { version = "0.1", default-features = false, features = ["smol"] }
+//
+//   smol::block_on(async {
+//       let store = JsonStore::load(Path::new("data")).await?;
+//       store.insert("users", &json!({ "name": "ada" })).await?;
+//       store.close().await
+//   })
+//
+// The http and cli features and the object_store backend are built on tokio and
+// turn the tokio feature on.
+//
+// On wasm32 the wasm feature stands in for both, on the browser's event loop: blocking
+// work runs in place, as there are no threads to hand it to, and there's no
+// filesystem, so a store there lives in JsonStore::in_memory() or in a
+// KeyValueBackend over storage the host provides (see kv.rs).
+
+#[cfg(not(any(
+    all(feature = "wasm", target_arch = "wasm32"),
+    feature = "tokio",
+    feature = "smol"
+)))]
+compile_error!(
+    "json-store needs a runtime: enable its tokio (the default) or smol feature, or wasm on wasm32"
+);
+
+// stands in for the missing runtime so that the error above is the only one
+#[cfg(not(any(
+    all(feature = "wasm", target_arch = "wasm32"),
+    feature = "tokio",
+    feature = "smol"
+)))]
+mod runtime {
+    use std::{future::Future, io, time::Duration};
+
+    pub(super) async fn spawn_blocking<T, F>(_: F) -> io::Result<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        unreachable!()
+    }
+
+    pub(super) async fn sleep(_: Duration) {}
+
+    pub(super) fn spawn(_: impl Future<Output = ()> + Send + 'static) {}
+}
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+use wasm as runtime;
+
+#[cfg(all(feature = "tokio", not(all(feature = "wasm", target_arch = "wasm32"))))]
+use self::tokio as runtime;
+
+#[cfg(all(
+    feature = "smol",
+    not(feature = "tokio"),
+    not(all(feature = "wasm", target_arch = "wasm32"))
+))]
+use self::smol as runtime;
+
+#[cfg(all(feature = "tokio", not(all(feature = "wasm", target_arch = "wasm32"))))]
+mod tokio {
+    use std::{future::Future, io, time::Duration};
+
+    pub(super) async fn spawn_blocking<T, F>(f: F) -> io::Result<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        ::tokio::task::spawn_blocking(f)
+            .await
+            .map_err(io::Error::from)
+    }
+
+    pub(super) async fn sleep(duration: Duration) {
+        ::tokio::time::sleep(duration).await
+    }
+
+    pub(super) fn spawn(future: impl Future<Output = ()> + Send + 'static) {
+        ::tokio::spawn(future);
+    }
+}
+
+#[cfg(all(
+    feature = "smol",
+    not(feature = "tokio"),
+    not(all(feature = "wasm", target_arch = "wasm32"))
+))]
+mod smol {
+    use std::{future::Future, io, time::Duration};
+
+    pub(super) async fn spawn_blocking<T, F>(f: F) -> io::Result<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        Ok(::smol::unblock(f).await)
+    }
+
+    pub(super) async fn sleep(duration: Duration) {
+        ::smol::Timer::after(duration).await;
+    }
+
+    pub(super) fn spawn(future: impl Future<Output = ()> + Send + 'static) {
+        ::smol::spawn(future).detach();
+    }
+}
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+mod wasm {
+    use send_wrapper::SendWrapper;
+    use std::{future::Future, io, time::Duration};
+
+    pub(super) async fn spawn_blocking<T, F>(f: F) -> io::Result<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        Ok(f())
+    }
+
+    // the browser's timer future isn't Send, and on one thread needn't be
+    pub(super) async fn sleep(duration: Duration) {
+        SendWrapper::new(gloo_timers::future::sleep(duration)).await
+    }
+
+    pub(super) fn spawn(future: impl Future<Output = ()> + Send + 'static) {
+        wasm_bindgen_futures::spawn_local(future);
+    }
+}
+
+// std's clocks panic on wasm32, where the browser's stand in
+#[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+pub(crate) use std::time::Instant;
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub(crate) use web_time::Instant;
+
+pub(crate) fn now() -> SystemTime {
+    #[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+    return SystemTime::now();
+
+    #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+    {
+        let since = web_time::SystemTime::now()
+            .duration_since(web_time::UNIX_EPOCH)
+            .unwrap_or_default();
+        std::time::UNIX_EPOCH + since
+    }
+}
+
+// where spilled records go: the directory LoadOptions names, or else the system's
+// temporary one; nowhere on wasm32, whatever it names
+pub(crate) fn spill_dir(configured: Option<PathBuf>) -> Option<PathBuf> {
+    #[cfg(not(target_arch = "wasm32"))]
+    return Some(configured.unwrap_or_else(std::env::temp_dir));
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = configured;
+        None
+    }
+}
+
+// run f on the blocking pool
+pub(crate) async fn unblock<T, F>(f: F) -> Result<T, JsonStoreError>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    Ok(runtime::spawn_blocking(f).await?)
+}
+
+pub(crate) async fn sleep(duration: Duration) {
+    runtime::sleep(duration).await
+}
+
+// let the runtime poll other tasks before going on
+pub(crate) async fn yield_now() {
+    let mut yielded = false;
+    futures::future::poll_fn(|cx| match std::mem::replace(&mut yielded, true) {
+        true => std::task::Poll::Ready(()),
+        false => {
+            cx.waker().wake_by_ref();
+            std::task::Poll::Pending
+        }
+    })
+    .await
+}
+
+// A future spawned onto the runtime. It runs to its end whether or not the Task is
+// kept; join waits for that end.
+#[derive(Debug)]
+pub(crate) struct Task(oneshot::Receiver<()>);
+
+impl Task {
+    // also returns if the future panicked
+    pub(crate) async fn join(self) {
+        let _ = self.0.await;
+    }
+}
+
+pub(crate) fn spawn(future: impl Future<Output = ()> + Send + 'static) -> Task {
+    let (done, finished) = oneshot::channel();
+    runtime::spawn(async move {
+        future.await;
+        let _ = done.send(());
+    });
+    Task(finished)
+}
+
+// The file operations the store uses, as std::fs ones run on the blocking pool.
+pub(crate) mod fs {
+    use std::{
+        fs::{DirEntry, Metadata},
+        io::{self, Write},
+        path::{Path, PathBuf},
+        sync::Arc,
+    };
+
+    async fn run<T, F>(f: F) -> io::Result<T>
+    where
+        F: FnOnce() -> io::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        super::runtime::spawn_blocking(f).await?
+    }
+
+    pub(crate) async fn read(path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+        let path = path.as_ref().to_owned();
+        run(move || std::fs::read(path)).await
+    }
+
+    pub(crate) async fn write(path: impl AsRef<Path>, bytes: Vec<u8>) -> io::Result<()> {
+        let path = path.as_ref().to_owned();
+        run(move || std::fs::write(path, bytes)).await
+    }
+
+    pub(crate) async fn rename(from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<()> {
+        let (from, to) = (from.as_ref().to_owned(), to.as_ref().to_owned());
+        run(move || std::fs::rename(from, to)).await
+    }
+
+    pub(crate) async fn copy(from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<u64> {
+        let (from, to) = (from.as_ref().to_owned(), to.as_ref().to_owned());
+        run(move || std::fs::copy(from, to)).await
+    }
+
+    pub(crate) async fn remove_file(path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref().to_owned();
+        run(move || std::fs::remove_file(path)).await
+    }
+
+    pub(crate) async fn remove_dir(path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref().to_owned();
+        run(move || std::fs::remove_dir(path)).await
+    }
+
+    pub(crate) async fn remove_dir_all(path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref().to_owned();
+        run(move || std::fs::remove_dir_all(path)).await
+    }
+
+    pub(crate) async fn create_dir(path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref().to_owned();
+        run(move || std::fs::create_dir(path)).await
+    }
+
+    pub(crate) async fn create_dir_all(path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref().to_owned();
+        run(move || std::fs::create_dir_all(path)).await
+    }
+
+    pub(crate) async fn metadata(path: impl AsRef<Path>) -> io::Result<Metadata> {
+        let path = path.as_ref().to_owned();
+        run(move || std::fs::metadata(path)).await
+    }
+
+    pub(crate) async fn try_exists(path: impl AsRef<Path>) -> io::Result<bool> {
+        let path = path.as_ref().to_owned();
+        run(move || path.try_exists()).await
+    }
+
+    // the entries of a directory, read in one go
+    pub(crate) async fn read_dir(path: impl AsRef<Path>) -> io::Result<Vec<DirEntry>> {
+        let path = path.as_ref().to_owned();
+        run(move || std::fs::read_dir(path)?.collect()).await
+    }
+
+    // an open file, each call one trip to the blocking pool
+    #[derive(Debug, Clone)]
+    pub(crate) struct File(Arc<std::fs::File>);
+
+    impl File {
+        pub(crate) async fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+            let path = path.as_ref().to_owned();
+            Ok(Self(Arc::new(
+                run(move || std::fs::File::create(path)).await?,
+            )))
+        }
+
+        // only sync_dir opens a file to read, and only there
+        #[cfg(unix)]
+        pub(crate) async fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+            let path = path.as_ref().to_owned();
+            Ok(Self(Arc::new(
+                run(move || std::fs::File::open(path)).await?,
+            )))
+        }
+
+        // for appending, created if missing
+        pub(crate) async fn append(path: impl AsRef<Path>) -> io::Result<Self> {
+            let path: PathBuf = path.as_ref().to_owned();
+            let file = run(move || {
+                std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+            })
+            .await?;
+            Ok(Self(Arc::new(file)))
+        }
+
+        pub(crate) async fn write_all(&self, bytes: Vec<u8>) -> io::Result<()> {
+            let file = self.0.clone();
+            run(move || (&*file).write_all(&bytes)).await
+        }
+
+        pub(crate) async fn sync_all(&self) -> io::Result<()> {
+            let file = self.0.clone();
+            run(move || file.sync_all()).await
+        }
+
+        pub(crate) async fn sync_data(&self) -> io::Result<()> {
+            let file = self.0.clone();
+            run(move || file.sync_data()).await
+        }
+    }
+
+    // writes gathered into chunks of CHUNK bytes before they go to the file
+    const CHUNK: usize = 64 * 1024;
+
+    #[derive(Debug)]
+    pub(crate) struct BufWriter {
+        file: File,
+        buf: Vec<u8>,
+    }
+
+    impl BufWriter {
+        pub(crate) fn new(file: File) -> Self {
+            Self {
+                file,
+                buf: Vec::with_capacity(CHUNK),
+            }
+        }
+
+        pub(crate) async fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+            self.buf.extend_from_slice(bytes);
+            if self.buf.len() >= CHUNK {
+                self.flush().await?;
+            }
+            Ok(())
+        }
+
+        pub(crate) async fn flush(&mut self) -> io::Result<()> {
+            if self.buf.is_empty() {
+                return Ok(());
+            }
+            let chunk = std::mem::replace(&mut self.buf, Vec::with_capacity(CHUNK));
+            self.file.write_all(chunk).await
+        }
+    }
+}
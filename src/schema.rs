@@ -0,0 +1,192 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{cmp::Ordering, collections::BTreeMap};
+
+use crate::{index, partition};
+
+// A draft JSON Schema (2020-12) for the records of a tree, from infer_schema. Every
+// record is fed in once and the schema describes all of them, so each validates
+// against it; it says no more than the data did, and is meant to be read and
+// tightened by hand before it's put to use:
+//
+// - type is the union of the types seen at a place, integer only if every number
+//   there was one
+// - required lists the fields present, null or not, in every object seen there
+// - format is date-time or uuid when every string there is one
+// - the smallest and largest number seen go in x-minimum and x-maximum, which
+//   validators take as annotations, so newer records may fall outside them
+//
+// Objects, and arrays of anything, are described down to depth levels below the
+// record; deeper ones only by their type.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct SchemaOptions {
+    pub depth: usize,
+}
+
+impl Default for SchemaOptions {
+    fn default() -> Self {
+        Self { depth: 8 }
+    }
+}
+
+pub(crate) const DRAFT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+// what the values at one place in the records look like so far
+#[derive(Debug, Default)]
+pub(crate) struct Shape {
+    // values added, null ones too
+    count: usize,
+    null: bool,
+    boolean: bool,
+    // smallest and largest, and whether all were integers
+    numbers: Option<(Value, Value, bool)>,
+    // whether all were date-times, and uuids
+    strings: Option<(bool, bool)>,
+    // how many, and the values of their fields
+    objects: Option<(usize, BTreeMap<String, Shape>)>,
+    // the shape of the items of all of them, None past the depth
+    arrays: Option<Option<Box<Shape>>>,
+}
+
+impl Shape {
+    pub(crate) fn add(&mut self, value: &Value, depth: usize) {
+        self.count += 1;
+        match value {
+            Value::Null => self.null = true,
+            Value::Bool(_) => self.boolean = true,
+            Value::Number(n) => {
+                let integer = n.is_i64() || n.is_u64();
+                match &mut self.numbers {
+                    Some((min, max, all)) => {
+                        if index::compare(value, min) == Ordering::Less {
+                            *min = value.clone();
+                        }
+                        if index::compare(value, max) == Ordering::Greater {
+                            *max = value.clone();
+                        }
+                        *all &= integer;
+                    }
+                    None => self.numbers = Some((value.clone(), value.clone(), integer)),
+                }
+            }
+            Value::String(s) => {
+                let (date_time, uuid) = self.strings.get_or_insert((true, true));
+                *date_time &= is_date_time(s);
+                *uuid &= is_uuid(s);
+            }
+            Value::Object(map) => {
+                let (count, fields) = self.objects.get_or_insert_with(Default::default);
+                *count += 1;
+                if depth > 0 {
+                    for (field, value) in map {
+                        fields
+                            .entry(field.clone())
+                            .or_default()
+                            .add(value, depth - 1);
+                    }
+                }
+            }
+            Value::Array(items) => {
+                let shape = self
+                    .arrays
+                    .get_or_insert_with(|| (depth > 0).then(Default::default));
+                if let Some(shape) = shape {
+                    for item in items {
+                        shape.add(item, depth - 1);
+                    }
+                }
+            }
+        }
+    }
+
+    // the schema of everything added, true if nothing was
+    pub(crate) fn schema(&self) -> Value {
+        let mut schema = Map::new();
+        let mut types = Vec::new();
+
+        if let Some((count, fields)) = &self.objects {
+            types.push("object");
+            if !fields.is_empty() {
+                let properties = fields
+                    .iter()
+                    .map(|(field, shape)| (field.clone(), shape.schema()))
+                    .collect::<Map<_, _>>();
+                let required = fields
+                    .iter()
+                    .filter(|(_, shape)| shape.count == *count)
+                    .map(|(field, _)| Value::from(field.as_str()))
+                    .collect::<Vec<_>>();
+                schema.insert("properties".into(), properties.into());
+                if !required.is_empty() {
+                    schema.insert("required".into(), required.into());
+                }
+            }
+        }
+        if let Some(items) = &self.arrays {
+            types.push("array");
+            if let Some(items) = items.as_deref().filter(|items| items.count > 0) {
+                schema.insert("items".into(), items.schema());
+            }
+        }
+        if let Some((date_time, uuid)) = self.strings {
+            types.push("string");
+            if date_time {
+                schema.insert("format".into(), "date-time".into());
+            } else if uuid {
+                schema.insert("format".into(), "uuid".into());
+            }
+        }
+        if let Some((min, max, integer)) = &self.numbers {
+            types.push(if *integer { "integer" } else { "number" });
+            schema.insert("x-minimum".into(), min.clone());
+            schema.insert("x-maximum".into(), max.clone());
+        }
+        if self.boolean {
+            types.push("boolean");
+        }
+        if self.null {
+            types.push("null");
+        }
+
+        let mut typed = Map::new();
+        match types.as_slice() {
+            [] => return Value::Bool(true),
+            [one] => typed.insert("type".into(), (*one).into()),
+            _ => typed.insert("type".into(), types.into()),
+        };
+        typed.extend(schema);
+        Value::Object(typed)
+    }
+}
+
+// The root of the schema of a tree, as Shape::schema with the draft and a title.
+pub(crate) fn root(tname: &str, shape: &Shape) -> Value {
+    let mut schema = Map::new();
+    schema.insert("$schema".into(), DRAFT.into());
+    schema.insert("title".into(), tname.into());
+    if let Value::Object(described) = shape.schema() {
+        schema.extend(described);
+    }
+    Value::Object(schema)
+}
+
+// RFC 3339 as time fields of partitioned trees take it, less what the format
+// date-time doesn't: dates alone, a space for the T, a missing offset
+fn is_date_time(s: &str) -> bool {
+    let b = s.as_bytes();
+    let offset = b.ends_with(b"Z")
+        || b.ends_with(b"z")
+        || (b.len() > 6 && matches!(b[b.len() - 6], b'+' | b'-'));
+    b.len() > 10 && matches!(b[10], b'T' | b't') && offset && partition::parse_time(s).is_some()
+}
+
+// hex in groups of 8-4-4-4-12, either case
+fn is_uuid(s: &str) -> bool {
+    let b = s.as_bytes();
+    b.len() == 36
+        && b.iter().enumerate().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => *c == b'-',
+            _ => c.is_ascii_hexdigit(),
+        })
+}
@@ -0,0 +1,47 @@
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::{error::JsonStoreError, rt::fs};
+
+// How JsonStore::seed puts fixture records into a tree. Every mode checks all the
+// records before it writes any, under the tree's write lock, so a seed that fails
+// leaves the tree as it was, and one that succeeds is a single undo_last away. The
+// sequence fields of the fixtures are ignored: inserted records take the tree's
+// next sequences, updated ones keep theirs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SeedMode {
+    // insert the records into a tree with no live records; otherwise skip them all
+    IfEmpty,
+    // Update the record holding each one's key under the unique constraint of this
+    // name, replacing it whole, and insert the rest. Two records with the same key
+    // are refused.
+    Merge(String),
+    // delete every record, then insert
+    Replace,
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SeedReport {
+    pub created: usize,
+    pub updated: usize,
+    pub skipped: usize,
+    // by Replace, and expired records in the way of a unique key
+    pub deleted: usize,
+}
+
+// The fixture files in dir, `{tree}.json` each holding a JSON array of the tree's
+// records, as (tree, file) sorted by tree. Other files are left alone.
+pub(crate) async fn fixtures(dir: &Path) -> Result<Vec<(String, PathBuf)>, JsonStoreError> {
+    let mut fixtures = Vec::new();
+    for entry in fs::read_dir(dir).await? {
+        let path = entry.path();
+        if path.extension().is_none_or(|ext| ext != "json") || !entry.file_type()?.is_file() {
+            continue;
+        }
+        if let Some(tname) = path.file_stem().and_then(|stem| stem.to_str()) {
+            fixtures.push((tname.to_string(), path.clone()));
+        }
+    }
+    fixtures.sort();
+    Ok(fixtures)
+}
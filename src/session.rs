@@ -0,0 +1,90 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::BTreeSet;
+
+use crate::{error::JsonStoreError, store::JsonStore, trace};
+
+// Groups related operations on a store and flushes exactly the trees they touched.
+// Writes are applied to the store immediately, so reads through the session (or the
+// store) always see them; only the save is deferred until `commit`.
+#[derive(Debug)]
+pub struct Session<'a> {
+    store: &'a JsonStore,
+    dirty: BTreeSet<String>,
+    finished: bool,
+}
+
+impl<'a> Session<'a> {
+    pub(crate) fn new(store: &'a JsonStore) -> Self {
+        Self {
+            store,
+            dirty: BTreeSet::new(),
+            finished: false,
+        }
+    }
+
+    pub async fn insert<T: Serialize>(
+        &mut self,
+        tname: &str,
+        value: &T,
+    ) -> Result<u64, JsonStoreError> {
+        let seq = self.store.insert(tname, value).await?;
+        self.dirty.insert(tname.to_string());
+        Ok(seq)
+    }
+
+    pub async fn update<T: Serialize>(
+        &mut self,
+        tname: &str,
+        value: &T,
+    ) -> Result<(), JsonStoreError> {
+        self.store.update(tname, value).await?;
+        self.dirty.insert(tname.to_string());
+        Ok(())
+    }
+
+    pub async fn delete(&mut self, tname: &str, sequence: u64) -> Result<(), JsonStoreError> {
+        self.store.delete(tname, sequence).await?;
+        self.dirty.insert(tname.to_string());
+        Ok(())
+    }
+
+    pub async fn select<T: DeserializeOwned>(
+        &self,
+        tname: &str,
+        sequence: u64,
+    ) -> Result<T, JsonStoreError> {
+        self.store.select(tname, sequence).await
+    }
+
+    // trees written through this session and not yet committed
+    pub fn dirty_trees(&self) -> Vec<String> {
+        self.dirty.iter().cloned().collect()
+    }
+
+    // save the trees dirtied by this session, and only those
+    pub async fn commit(mut self) -> Result<(), JsonStoreError> {
+        while let Some(tname) = self.dirty.first().cloned() {
+            self.store.save_tree(&tname).await?;
+            self.dirty.remove(&tname);
+        }
+        self.finished = true;
+
+        Ok(())
+    }
+
+    // leave the changes unsaved in the store without complaint
+    pub fn abandon(mut self) {
+        self.finished = true;
+    }
+}
+
+impl Drop for Session<'_> {
+    fn drop(&mut self) {
+        if !self.finished && !self.dirty.is_empty() {
+            trace::warn!(
+                trees = ?self.dirty,
+                "session dropped without commit; changes to these trees are unsaved"
+            );
+        }
+    }
+}
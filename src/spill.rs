@@ -0,0 +1,435 @@
+use serde_json::{value::RawValue, Value};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crate::{error::JsonStoreError, key::Records, rt};
+
+// Records of a tree with Info::resident_limit kept out of memory. At most that many
+// records stay in the tree's map, the ones read or written longest ago going first;
+// the rest wait in an overflow file, appended as compact JSON and found again through
+// an offset per sequence, and are read back when asked for.
+//
+// What reads what: select and the other single-record reads, update and delete bring
+// just their record back in, and select_where, find_by_field, find_range, find_sorted
+// and describe_with_samples read spilled records from the file one at a time without
+// keeping them. Field indexes and unique constraints cover every record, spilled or
+// not, so inserts and indexed finds never need the file. Saves write spilled records
+// from their text. Every other operation over the records (exports, diffs, merges,
+// imports, undo_last, migrations, profiles...) reads all of them back first, and the
+// tree shrinks to its limit again with its next write or save, or compact_memory.
+//
+// The file is scratch space, made afresh on every load and removed with the tree;
+// the tree's own files stay the record of it. It is read and written on the blocking
+// pool, a batch of records at a time. wasm32 has no filesystem to put it in, so there
+// a tree with a resident limit is refused. With LoadOptions::incremental_save a
+// record written since the last save isn't spilled until that save, so a save that
+// writes only the delta file finds every record it needs in memory; the tree can then
+// hold more than its limit until it saves.
+//
+// Resident limits need a tree kept in a single snapshot file, of parsed records.
+
+// the file is rewritten once this many bytes of it are dead and they outweigh the live
+const COMPACT_AFTER_BYTES: u64 = 1 << 20;
+
+// what a tree with a resident limit gets where there is nowhere to spill to
+pub(crate) fn unavailable() -> JsonStoreError {
+    JsonStoreError::InvalidOptions(
+        "a resident limit needs a directory to spill to, and there is no filesystem here"
+            .to_string(),
+    )
+}
+
+pub(crate) struct Spill {
+    limit: usize,
+    dir: PathBuf,
+    file: SpillFile,
+    // where each record copied out lies in the file, resident or not; a write to a
+    // record drops its copy
+    slots: HashMap<u64, Slot>,
+    live_bytes: u64,
+    // the records in the file alone
+    out: HashSet<u64>,
+    // resident records by when they were last read or written, under a mutex as reads
+    // hold the tree's lock shared
+    recency: Mutex<Recency>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    offset: u64,
+    len: u32,
+}
+
+struct SpillFile {
+    path: PathBuf,
+    // shared with the blocking pool, where every read and write of it runs
+    file: Arc<Mutex<File>>,
+    end: u64,
+}
+
+#[derive(Debug, Default)]
+struct Recency {
+    tick: u64,
+    ticks: HashMap<u64, u64>,
+    order: BTreeMap<u64, u64>,
+}
+
+impl Recency {
+    fn touch(&mut self, seq: u64) {
+        self.tick += 1;
+        if let Some(old) = self.ticks.insert(seq, self.tick) {
+            self.order.remove(&old);
+        }
+        self.order.insert(self.tick, seq);
+    }
+
+    fn forget(&mut self, seq: u64) {
+        if let Some(old) = self.ticks.remove(&seq) {
+            self.order.remove(&old);
+        }
+    }
+}
+
+impl Spill {
+    // an empty overflow file in dir for a tree keeping limit records in memory; None
+    // is no directory to spill to, as on wasm32
+    pub(crate) async fn create(dir: Option<&Path>, limit: usize) -> Result<Self, JsonStoreError> {
+        let dir = dir.ok_or_else(unavailable)?;
+        Ok(Self {
+            limit,
+            dir: dir.to_path_buf(),
+            file: SpillFile::create(dir.to_path_buf()).await?,
+            slots: HashMap::new(),
+            live_bytes: 0,
+            out: HashSet::new(),
+            recency: Mutex::new(Recency::default()),
+        })
+    }
+
+    pub(crate) fn limit(&self) -> usize {
+        self.limit
+    }
+
+    // whether the record at seq is in the file and not in memory
+    pub(crate) fn holds(&self, seq: u64) -> bool {
+        self.out.contains(&seq)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.out.len()
+    }
+
+    pub(crate) fn sequences(&self) -> impl Iterator<Item = u64> + '_ {
+        self.out.iter().copied()
+    }
+
+    pub(crate) async fn read(&self, seq: u64) -> Result<Option<Arc<Value>>, JsonStoreError> {
+        Ok(self.read_many(&[seq]).await?.remove(&seq))
+    }
+
+    // those of the records at seqs with a copy in the file, read in one go
+    pub(crate) async fn read_many(
+        &self,
+        seqs: &[u64],
+    ) -> Result<HashMap<u64, Arc<Value>>, JsonStoreError> {
+        self.file
+            .read(self.slots_of(seqs), |bytes| {
+                Ok(Arc::new(serde_json::from_slice(&bytes)?))
+            })
+            .await
+    }
+
+    // the records at seqs as the text they were written out as
+    pub(crate) async fn read_raw_many(
+        &self,
+        seqs: &[u64],
+    ) -> Result<HashMap<u64, Arc<RawValue>>, JsonStoreError> {
+        self.file
+            .read(self.slots_of(seqs), |bytes| {
+                let text = String::from_utf8(bytes)
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+                Ok(Arc::from(RawValue::from_string(text)?))
+            })
+            .await
+    }
+
+    fn slots_of(&self, seqs: &[u64]) -> Vec<(u64, Slot)> {
+        seqs.iter()
+            .filter_map(|seq| Some((*seq, *self.slots.get(seq)?)))
+            .collect()
+    }
+
+    // put records, by sequence and as text, in the file and out of memory
+    pub(crate) async fn spill(
+        &mut self,
+        records: Vec<(u64, Arc<RawValue>)>,
+    ) -> Result<(), JsonStoreError> {
+        let seqs = records.iter().map(|(seq, _)| *seq).collect::<Vec<_>>();
+        let records = records
+            .into_iter()
+            .map(|(seq, record)| (seq, record.get().as_bytes().to_vec()))
+            .collect();
+        self.append(records).await?;
+        self.out.extend(seqs);
+        Ok(())
+    }
+
+    // the record at seq is back in memory; its copy stays for the next eviction
+    pub(crate) fn faulted_in(&mut self, seq: u64) {
+        self.out.remove(&seq);
+        self.touch(seq);
+    }
+
+    // every spilled record is back in memory
+    pub(crate) fn all_in(&mut self) {
+        self.out.clear();
+    }
+
+    // the record at seq was written or removed: its copy in the file is stale
+    pub(crate) fn changed(&mut self, seq: u64) {
+        if let Some(slot) = self.slots.remove(&seq) {
+            self.live_bytes -= slot.len as u64;
+        }
+        self.out.remove(&seq);
+        self.touch(seq);
+    }
+
+    pub(crate) fn removed(&mut self, seq: u64) {
+        self.recency().forget(seq);
+    }
+
+    pub(crate) fn touch(&self, seq: u64) {
+        self.recency().touch(seq);
+    }
+
+    // write out those of records without a copy in the file yet
+    async fn append(&mut self, records: Vec<(u64, Vec<u8>)>) -> Result<(), JsonStoreError> {
+        let records = records
+            .into_iter()
+            .filter(|(seq, _)| !self.slots.contains_key(seq))
+            .collect();
+        for (seq, slot) in self.file.append(records).await? {
+            self.live_bytes += slot.len as u64;
+            self.slots.insert(seq, slot);
+        }
+        Ok(())
+    }
+
+    // Write out the resident records to let go of to get down to the limit, least
+    // recent first and none of keep, and return them for the caller to drop.
+    pub(crate) async fn evict(
+        &mut self,
+        data: &Records,
+        keep: &HashSet<u64>,
+    ) -> Result<Vec<u64>, JsonStoreError> {
+        let excess = data.len().saturating_sub(self.limit);
+        if excess == 0 {
+            return Ok(Vec::new());
+        }
+
+        let victims = {
+            let recency = self.recency();
+            // records never read or written since they were read back go first, by
+            // sequence
+            let untouched = || {
+                let mut untouched = data
+                    .keys()
+                    .filter(|seq| !recency.ticks.contains_key(seq))
+                    .copied()
+                    .collect::<Vec<_>>();
+                untouched.sort_unstable();
+                untouched
+            };
+            let pick = |first: Vec<u64>| {
+                first
+                    .into_iter()
+                    .chain(recency.order.values().copied())
+                    .filter(|seq| data.contains_key(seq) && !keep.contains(seq))
+                    .take(excess)
+                    .collect::<Vec<_>>()
+            };
+            // they are only looked for when there can be some, or when sequences no
+            // longer in data take up the order
+            match recency.ticks.len() < data.len() {
+                true => pick(untouched()),
+                false => match pick(Vec::new()) {
+                    victims if victims.len() < excess => pick(untouched()),
+                    victims => victims,
+                },
+            }
+        };
+        // records with a copy from before aren't written again
+        let mut records = Vec::new();
+        for seq in victims.iter().filter(|seq| !self.slots.contains_key(seq)) {
+            records.push((*seq, serde_json::to_vec(&*data[seq])?));
+        }
+        self.append(records).await?;
+        for seq in victims.iter() {
+            self.out.insert(*seq);
+            self.recency().forget(*seq);
+        }
+        self.compact().await?;
+        Ok(victims)
+    }
+
+    // rewrite the file without the copies of records since written or removed
+    async fn compact(&mut self) -> Result<(), JsonStoreError> {
+        let dead = self.file.end - self.live_bytes;
+        if dead < COMPACT_AFTER_BYTES || dead < self.live_bytes {
+            return Ok(());
+        }
+        let mut slots = self
+            .slots
+            .iter()
+            .map(|(seq, slot)| (*seq, *slot))
+            .collect::<Vec<_>>();
+        slots.sort_unstable_by_key(|(_, slot)| slot.offset);
+        let (dir, old) = (self.dir.clone(), self.file.file.clone());
+        let (file, moved) = rt::unblock(move || {
+            let mut file = SpillFile::create_blocking(&dir)?;
+            let old = &mut *old.lock().unwrap_or_else(|e| e.into_inner());
+            let moved = slots
+                .into_iter()
+                .map(|(seq, slot)| {
+                    let bytes = read_slot(old, slot)?;
+                    Ok((seq, file.append_blocking(&bytes)?))
+                })
+                .collect::<Result<HashMap<_, _>, JsonStoreError>>()?;
+            Ok::<_, JsonStoreError>((file, moved))
+        })
+        .await??;
+        self.file = file;
+        self.slots = moved;
+        self.live_bytes = self.file.end;
+        Ok(())
+    }
+
+    fn recency(&self) -> std::sync::MutexGuard<'_, Recency> {
+        self.recency.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl fmt::Debug for Spill {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Spill")
+            .field("limit", &self.limit)
+            .field("path", &self.file.path)
+            .field("records", &self.slots.len())
+            .finish()
+    }
+}
+
+impl SpillFile {
+    async fn create(dir: PathBuf) -> Result<Self, JsonStoreError> {
+        rt::unblock(move || Self::create_blocking(&dir)).await?
+    }
+
+    fn create_blocking(dir: &Path) -> Result<Self, JsonStoreError> {
+        static FILES: AtomicU64 = AtomicU64::new(0);
+        let name = format!(
+            "json-store-{}-{}.spill",
+            std::process::id(),
+            FILES.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = dir.join(name);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(Self {
+            path,
+            file: Arc::new(Mutex::new(file)),
+            end: 0,
+        })
+    }
+
+    // records appended at the end, on the blocking pool, with where each one went
+    async fn append(
+        &mut self,
+        records: Vec<(u64, Vec<u8>)>,
+    ) -> Result<Vec<(u64, Slot)>, JsonStoreError> {
+        if records.is_empty() {
+            return Ok(Vec::new());
+        }
+        let (file, mut end) = (self.file.clone(), self.end);
+        let (slots, end) = rt::unblock(move || {
+            let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+            file.seek(SeekFrom::Start(end))?;
+            let mut writer = BufWriter::new(&mut *file);
+            let mut slots = Vec::with_capacity(records.len());
+            for (seq, bytes) in records {
+                writer.write_all(&bytes)?;
+                slots.push((
+                    seq,
+                    Slot {
+                        offset: end,
+                        len: bytes.len() as u32,
+                    },
+                ));
+                end += bytes.len() as u64;
+            }
+            writer.flush()?;
+            Ok::<_, JsonStoreError>((slots, end))
+        })
+        .await??;
+        self.end = end;
+        Ok(slots)
+    }
+
+    fn append_blocking(&mut self, bytes: &[u8]) -> Result<Slot, JsonStoreError> {
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        file.seek(SeekFrom::Start(self.end))?;
+        file.write_all(bytes)?;
+        let slot = Slot {
+            offset: self.end,
+            len: bytes.len() as u32,
+        };
+        self.end += bytes.len() as u64;
+        Ok(slot)
+    }
+
+    // the records in slots, each decoded, read on the blocking pool
+    async fn read<T: Send + 'static>(
+        &self,
+        mut slots: Vec<(u64, Slot)>,
+        decode: fn(Vec<u8>) -> Result<T, JsonStoreError>,
+    ) -> Result<HashMap<u64, T>, JsonStoreError> {
+        if slots.is_empty() {
+            return Ok(HashMap::new());
+        }
+        // in file order, for fewer seeks
+        slots.sort_unstable_by_key(|(_, slot)| slot.offset);
+        let file = self.file.clone();
+        rt::unblock(move || {
+            let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+            slots
+                .into_iter()
+                .map(|(seq, slot)| Ok((seq, decode(read_slot(&mut file, slot)?)?)))
+                .collect()
+        })
+        .await?
+    }
+}
+
+fn read_slot(file: &mut File, slot: Slot) -> Result<Vec<u8>, JsonStoreError> {
+    file.seek(SeekFrom::Start(slot.offset))?;
+    let mut bytes = vec![0; slot.len as usize];
+    file.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,426 @@
+use rusqlite::{
+    params_from_iter,
+    types::{Value as SqlValue, ValueRef},
+    Connection, OpenFlags, Transaction,
+};
+use serde_json::{Map, Number, Value};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    error::JsonStoreError,
+    import::{ImportMode, OnConflict},
+    rt,
+};
+
+// A bridge to SQLite databases, for reading a store with SQL tools and for pulling
+// tables into trees (the sqlite feature).
+//
+// export_sqlite writes one table per tree, named as the tree, whose first column is
+// the sequence field as INTEGER PRIMARY KEY. The rest is either
+//
+// - SqliteColumns::Json: one TEXT column holding each record whole as JSON text,
+//   sequence field included, or
+// - SqliteColumns::Flat: a column per top-level field that holds a scalar or null in
+//   every record, plus one TEXT column holding the record's other fields as a JSON
+//   object (NULL if it has none); the latter is left out when no record has any.
+//
+// JSON values go to SQLite as:
+//
+//   null                              NULL
+//   true, false                       INTEGER 1, 0
+//   a number fitting an i64           INTEGER
+//   any other number                  REAL, rounded to the nearest f64
+//   a string                          TEXT
+//
+// A flat column is declared BOOLEAN if its field only held booleans, INTEGER if only
+// integers, REAL if only other numbers, TEXT if only strings, and with no type if it
+// held a mix, numbers of both sorts included, so SQLite keeps each value as given (a
+// NUMERIC column would turn a REAL of 2.0 into the INTEGER 2).
+//
+// Exported tables are dropped and made again, all in one transaction; other tables
+// in the database are left alone.
+//
+// import_sqlite reads the rows of a table into records, through the same checks as
+// the other imports. SQLite values come back as:
+//
+//   NULL                              null
+//   INTEGER                           a number, or a boolean (zero or not) in a column
+//                                     declared BOOLEAN or BOOL
+//   REAL                              a number; infinities reject the row
+//   TEXT                              a string, or fields in the json column
+//   BLOB                              a string of lowercase hex
+//
+// so importing an export gives back the records it was made from, but for numbers
+// that were rounded to REAL, booleans in flat columns of mixed type, which come back
+// as 1 and 0, and fields a record lacked that have a flat column, which come back as
+// null.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SqliteColumns {
+    #[default]
+    Json,
+    Flat,
+}
+
+#[derive(Debug, Clone)]
+pub struct SqliteExportOptions {
+    pub columns: SqliteColumns,
+    // the column of JSON text, the whole record or the fields that weren't flattened
+    pub json_column: String,
+    // trees to export, every one if None
+    pub trees: Option<Vec<String>>,
+}
+
+impl Default for SqliteExportOptions {
+    fn default() -> Self {
+        Self {
+            columns: SqliteColumns::default(),
+            json_column: "json".to_string(),
+            trees: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SqliteImport {
+    pub table: String,
+    // (column, field) pairs; if empty, every column but json_column goes to the field
+    // of its name. Map a column to the tree's sequence field to keep its values with
+    // ImportMode::Preserve.
+    pub columns: Vec<(String, String)>,
+    // a column of JSON objects whose fields are added to the record, as exports
+    // write; fields of mapped columns win over them
+    pub json_column: Option<String>,
+    pub mode: ImportMode,
+    pub on_conflict: OnConflict,
+}
+
+impl SqliteImport {
+    pub fn new(table: &str) -> Self {
+        Self {
+            table: table.to_string(),
+            columns: Vec::new(),
+            json_column: None,
+            mode: ImportMode::default(),
+            on_conflict: OnConflict::default(),
+        }
+    }
+}
+
+// the live records of a tree, in sequence order, as export_sqlite found them
+#[derive(Debug)]
+pub(crate) struct Table {
+    pub(crate) name: String,
+    pub(crate) sequence_field: String,
+    pub(crate) records: Vec<(u64, Arc<Value>)>,
+}
+
+// Write tables into the database at file, made if missing, returning the rows
+// written to each. Rows go in one at a time from the records shared with the store.
+pub(crate) async fn write(
+    file: &Path,
+    tables: Vec<Table>,
+    options: &SqliteExportOptions,
+) -> Result<BTreeMap<String, u64>, JsonStoreError> {
+    let file: PathBuf = file.into();
+    let options = options.clone();
+
+    rt::unblock(move || {
+        let mut db = Connection::open(file)?;
+        let tx = db.transaction()?;
+        let mut written = BTreeMap::new();
+        for table in tables {
+            let rows = write_table(&tx, &table, &options)?;
+            written.insert(table.name, rows);
+        }
+        tx.commit()?;
+        Ok(written)
+    })
+    .await?
+}
+
+fn write_table(
+    tx: &Transaction,
+    table: &Table,
+    options: &SqliteExportOptions,
+) -> Result<u64, JsonStoreError> {
+    let flat = match options.columns {
+        SqliteColumns::Json => Vec::new(),
+        SqliteColumns::Flat => flat_columns(table),
+    };
+    // with Flat, only needed if some field wasn't flattened
+    let json = match options.columns {
+        SqliteColumns::Json => true,
+        SqliteColumns::Flat => table.records.iter().any(|(_, record)| {
+            record
+                .as_object()
+                .is_some_and(|fields| fields.keys().any(|f| !is_flat(&flat, f, table)))
+        }),
+    };
+    if json && flat.iter().any(|(field, _)| *field == options.json_column) {
+        return Err(JsonStoreError::InvalidOptions(format!(
+            "tree '{}' has a field called '{}', the json_column",
+            table.name, options.json_column
+        )));
+    }
+
+    let mut columns = vec![(table.sequence_field.as_str(), "INTEGER PRIMARY KEY")];
+    columns.extend(flat.iter().map(|(field, ty)| (field.as_str(), *ty)));
+    if json {
+        columns.push((&options.json_column, "TEXT"));
+    }
+
+    let name = quote(&table.name);
+    let mut create = format!("CREATE TABLE {} (", name);
+    for (i, (column, ty)) in columns.iter().enumerate() {
+        let sep = if i == 0 { "" } else { ", " };
+        let _ = write!(create, "{}{} {}", sep, quote(column), ty);
+    }
+    create.push(')');
+    tx.execute(&format!("DROP TABLE IF EXISTS {}", name), [])?;
+    tx.execute(&create, [])?;
+
+    let holes = vec!["?"; columns.len()].join(", ");
+    let mut insert = tx.prepare(&format!("INSERT INTO {} VALUES ({})", name, holes))?;
+    let mut row = Vec::with_capacity(columns.len());
+    for (sequence, record) in table.records.iter() {
+        row.clear();
+        row.push(SqlValue::Integer(*sequence as i64));
+        match options.columns {
+            SqliteColumns::Json => row.push(SqlValue::Text(record.to_string())),
+            SqliteColumns::Flat => {
+                row.extend(
+                    flat.iter()
+                        .map(|(field, _)| scalar(&record[field.as_str()])),
+                );
+                if json {
+                    let rest = record
+                        .as_object()
+                        .into_iter()
+                        .flatten()
+                        .filter(|(field, _)| !is_flat(&flat, field, table))
+                        .map(|(field, value)| (field.clone(), value.clone()))
+                        .collect::<Map<_, _>>();
+                    row.push(match rest.is_empty() {
+                        true => SqlValue::Null,
+                        false => SqlValue::Text(Value::Object(rest).to_string()),
+                    });
+                }
+            }
+        }
+        insert.execute(params_from_iter(row.iter()))?;
+    }
+
+    Ok(table.records.len() as u64)
+}
+
+// The fields of table's records that are a scalar or null in every one, in order of
+// first appearance, with the type to declare their column with.
+fn flat_columns(table: &Table) -> Vec<(String, &'static str)> {
+    // None once a field holds an array or object
+    let mut fields: Vec<(String, Option<Declared>)> = Vec::new();
+    let mut at = HashMap::new();
+
+    for (_, record) in table.records.iter() {
+        let Some(record) = record.as_object() else {
+            continue;
+        };
+        for (field, value) in record {
+            if *field == table.sequence_field {
+                continue;
+            }
+            let i = *at.entry(field.clone()).or_insert_with(|| {
+                fields.push((field.clone(), Some(Declared::Unseen)));
+                fields.len() - 1
+            });
+            let declared = &mut fields[i].1;
+            *declared = declared.and_then(|declared| declared.with(value));
+        }
+    }
+
+    fields
+        .into_iter()
+        .filter_map(|(field, declared)| Some((field, declared?.name())))
+        .collect()
+}
+
+fn is_flat(flat: &[(String, &str)], field: &str, table: &Table) -> bool {
+    field == table.sequence_field || flat.iter().any(|(f, _)| f == field)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Declared {
+    // nothing but nulls so far
+    Unseen,
+    Boolean,
+    Integer,
+    Real,
+    Text,
+    Mixed,
+}
+
+impl Declared {
+    // what a column declared self becomes with value in it too; None if value
+    // can't go in a column
+    fn with(self, value: &Value) -> Option<Self> {
+        let of = match value {
+            Value::Null => return Some(self),
+            Value::Bool(_) => Self::Boolean,
+            Value::Number(n) if n.as_i64().is_some() => Self::Integer,
+            Value::Number(_) => Self::Real,
+            Value::String(_) => Self::Text,
+            Value::Array(_) | Value::Object(_) => return None,
+        };
+        Some(match (self, of) {
+            (Self::Unseen, of) => of,
+            (a, b) if a == b => a,
+            _ => Self::Mixed,
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Boolean => "BOOLEAN",
+            Self::Integer => "INTEGER",
+            Self::Real => "REAL",
+            Self::Text => "TEXT",
+            Self::Unseen | Self::Mixed => "",
+        }
+    }
+}
+
+fn scalar(value: &Value) -> SqlValue {
+    match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(*b as i64),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => SqlValue::Text(s.clone()),
+        other => SqlValue::Text(other.to_string()),
+    }
+}
+
+// an identifier, quoted whatever it holds
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+// Read the rows of a table into records keyed by their position in it, from 0. Rows
+// that can't be turned into a record carry the reason instead.
+pub(crate) async fn read(
+    file: &Path,
+    mapping: &SqliteImport,
+) -> Result<Vec<(usize, Result<Value, String>)>, JsonStoreError> {
+    let file: PathBuf = file.into();
+    let mapping = mapping.clone();
+
+    rt::unblock(move || {
+        let db = Connection::open_with_flags(file, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let mut select = db.prepare(&format!("SELECT * FROM {}", quote(&mapping.table)))?;
+        let names = select
+            .column_names()
+            .into_iter()
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+
+        let mut booleans = Vec::new();
+        let mut info = db.prepare(&format!("PRAGMA table_info({})", quote(&mapping.table)))?;
+        let mut declared = info.query([])?;
+        while let Some(column) = declared.next()? {
+            let ty = column.get::<_, String>(2)?.trim().to_uppercase();
+            if ty == "BOOLEAN" || ty == "BOOL" {
+                booleans.push(column.get::<_, String>(1)?);
+            }
+        }
+
+        let missing = |column: &str| {
+            JsonStoreError::InvalidOptions(format!(
+                "table '{}' has no column '{}'",
+                mapping.table, column
+            ))
+        };
+        let json = match &mapping.json_column {
+            Some(column) => Some(
+                names
+                    .iter()
+                    .position(|n| n == column)
+                    .ok_or_else(|| missing(column))?,
+            ),
+            None => None,
+        };
+        // (column index, field)
+        let mapped = match mapping.columns.is_empty() {
+            true => names
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| Some(*i) != json)
+                .map(|(i, name)| (i, name.clone()))
+                .collect::<Vec<_>>(),
+            false => mapping
+                .columns
+                .iter()
+                .map(|(column, field)| {
+                    let i = names
+                        .iter()
+                        .position(|n| n == column)
+                        .ok_or_else(|| missing(column))?;
+                    Ok((i, field.clone()))
+                })
+                .collect::<Result<_, JsonStoreError>>()?,
+        };
+
+        let mut records = Vec::new();
+        let mut rows = select.query([])?;
+        while let Some(row) = rows.next()? {
+            let record = (|| {
+                let mut record = Map::new();
+                if let Some(i) = json {
+                    match row.get_ref(i).map_err(|e| e.to_string())? {
+                        ValueRef::Null => {}
+                        ValueRef::Text(text) => match serde_json::from_slice(text) {
+                            Ok(Value::Object(fields)) => record = fields,
+                            _ => return Err(format!("column '{}' is not a JSON object", names[i])),
+                        },
+                        _ => return Err(format!("column '{}' is not a JSON object", names[i])),
+                    }
+                }
+                for (i, field) in mapped.iter() {
+                    let value = row.get_ref(*i).map_err(|e| e.to_string())?;
+                    let value = json_value(value, booleans.contains(&names[*i]))
+                        .map_err(|reason| format!("column '{}' {}", names[*i], reason))?;
+                    record.insert(field.clone(), value);
+                }
+                Ok(Value::Object(record))
+            })();
+            records.push((records.len(), record));
+        }
+
+        Ok(records)
+    })
+    .await?
+}
+
+fn json_value(value: ValueRef, boolean: bool) -> Result<Value, &'static str> {
+    Ok(match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(i) if boolean => Value::Bool(i != 0),
+        ValueRef::Integer(i) => Value::from(i),
+        ValueRef::Real(f) => Value::Number(Number::from_f64(f).ok_or("holds an infinity")?),
+        ValueRef::Text(text) => Value::String(
+            std::str::from_utf8(text)
+                .map_err(|_| "holds text that isn't UTF-8")?
+                .to_string(),
+        ),
+        ValueRef::Blob(bytes) => Value::String(bytes.iter().fold(String::new(), |mut hex, b| {
+            let _ = write!(hex, "{:02x}", b);
+            hex
+        })),
+    })
+}
@@ -0,0 +1,149 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::{collections::BTreeMap, fmt, path::PathBuf};
+
+use crate::store::Info;
+
+// Sizes and counts for health checks and dashboards. Records, sequence and memory
+// are 0 for trees that aren't loaded; their files still count on disk.
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct TreeStats {
+    pub records: usize,
+    pub sequence: u64,
+    pub dirty: bool,
+    pub loaded: bool,
+    // rough bytes held by the records, see memory_estimate
+    pub memory_bytes: usize,
+    // every file of the tree: sequence, snapshots, sidecars, WAL
+    pub disk_bytes: u64,
+}
+
+// memory_estimate of a tree before and after compact_memory
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryCompaction {
+    pub before_bytes: usize,
+    pub after_bytes: usize,
+}
+
+impl MemoryCompaction {
+    pub fn reclaimed_bytes(&self) -> usize {
+        self.before_bytes.saturating_sub(self.after_bytes)
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct StoreStats {
+    pub path: PathBuf,
+    pub tree_count: usize,
+    pub records: usize,
+    pub memory_bytes: usize,
+    pub disk_bytes: u64,
+    pub trees: BTreeMap<String, TreeStats>,
+}
+
+impl StoreStats {
+    pub(crate) fn new(path: PathBuf, trees: BTreeMap<String, TreeStats>) -> Self {
+        Self {
+            path,
+            tree_count: trees.len(),
+            records: trees.values().map(|t| t.records).sum(),
+            memory_bytes: trees.values().map(|t| t.memory_bytes).sum(),
+            disk_bytes: trees.values().map(|t| t.disk_bytes).sum(),
+            trees,
+        }
+    }
+}
+
+impl fmt::Display for StoreStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:?}: {} trees, {} records, {} bytes in memory, {} bytes on disk",
+            self.path, self.tree_count, self.records, self.memory_bytes, self.disk_bytes
+        )?;
+        for (tname, tree) in self.trees.iter() {
+            write!(
+                f,
+                "  {}: {} records, sequence {}, {} bytes in memory, {} bytes on disk",
+                tname, tree.records, tree.sequence, tree.memory_bytes, tree.disk_bytes
+            )?;
+            match (tree.loaded, tree.dirty) {
+                (false, _) => writeln!(f, " (not loaded)")?,
+                (true, true) => writeln!(f, " (dirty)")?,
+                (true, false) => writeln!(f)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// bytes of a record in memory: its values and their strings, not allocator overhead
+// or spare capacity
+pub(crate) fn value_size(value: &Value) -> usize {
+    size_of::<Value>()
+        + match value {
+            Value::String(s) => s.len(),
+            Value::Array(values) => values.iter().map(value_size).sum(),
+            Value::Object(map) => map
+                .iter()
+                .map(|(key, value)| size_of::<String>() + key.len() + value_size(value))
+                .sum(),
+            _ => 0,
+        }
+}
+
+// What a store holds: each tree's settings and counts, but none of its records unless
+// samples were asked for. Like stats, unloaded trees show 0 records.
+#[derive(Serialize, Debug, Clone)]
+pub struct StoreDescription {
+    pub path: PathBuf,
+    pub trees: BTreeMap<String, TreeDescription>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TreeDescription {
+    pub info: Info,
+    pub records: usize,
+    pub sequence: u64,
+    pub dirty: bool,
+    pub loaded: bool,
+    // the first records by sequence, from describe_with_samples
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub samples: Vec<Value>,
+}
+
+impl fmt::Display for StoreDescription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:?}: {} trees", self.path, self.trees.len())?;
+        for (tname, tree) in self.trees.iter() {
+            let info = &tree.info;
+            let mut unique = info
+                .unique_fields
+                .keys()
+                .map(String::as_str)
+                .collect::<Vec<_>>();
+            unique.sort();
+            write!(
+                f,
+                "  {}: {} records, sequence {}, sequence field '{}', unique [{}], capacity {}, {:?}",
+                tname,
+                tree.records,
+                tree.sequence,
+                info.sequence_field,
+                unique.join(", "),
+                info.capacity,
+                info.storage
+            )?;
+            match (tree.loaded, tree.dirty) {
+                (false, _) => writeln!(f, " (not loaded)")?,
+                (true, true) => writeln!(f, " (dirty)")?,
+                (true, false) => writeln!(f)?,
+            }
+            for sample in tree.samples.iter() {
+                writeln!(f, "    {}", sample)?;
+            }
+        }
+        Ok(())
+    }
+}
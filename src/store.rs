@@ -1,25 +1,183 @@
+use async_lock::{Mutex, RwLock, RwLockReadGuardArc, RwLockWriteGuardArc};
+use futures::{channel::oneshot, stream, Future, Stream, StreamExt, TryStreamExt};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use serde_json::{json, Value};
+use serde_json::{json, value::RawValue, Value};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
     fmt::Debug,
+    hash::Hash,
+    ops::{RangeBounds, RangeInclusive},
     path::{Path, PathBuf},
-    sync::Arc,
+    pin::pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex as StdMutex, RwLock as StdRwLock, RwLockReadGuard as StdReadGuard,
+        RwLockWriteGuard as StdWriteGuard,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
-use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt},
-    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+
+#[cfg(feature = "archive")]
+use crate::archive;
+#[cfg(feature = "csv")]
+use crate::import::CsvImportOptions;
+#[cfg(feature = "sqlite")]
+use crate::sqlite::{self, SqliteExportOptions, SqliteImport};
+#[cfg(feature = "watch")]
+use crate::watch::{self, WatchEvent, WatchHandle};
+
+use crate::{
+    append_log,
+    autosave::AutosaveHandle,
+    backend::{FsBackend, InMemoryBackend, Stamp, StorageBackend},
+    backup::{self, BackupOptions, BackupReport, BackupScheduleHandle},
+    batch::{BatchOp, BatchResult, Queued, WriteBatching, WriteQueue},
+    builder::JsonStoreBuilder,
+    cache::{Caches, Invalidate, ReadCache},
+    checksum::{self, ChecksumStatus, VerifyReport},
+    clock::{Clock, SystemClock},
+    codec::Codec,
+    cold::{self, ArchiveDest, ArchiveOptions, PruneStrategy},
+    diff::{self, DiffOptions, InfoDiff, StoreDiff, TreeDiff},
+    entity::StoreEntity,
+    error::{InfoValidationError, JsonStoreError},
+    expiry::{self, Expiry},
+    export::{self, ExportFormat, Redaction},
+    handle::TreeHandle,
+    history::{self, History, HistoryConfig, HistoryEntry, HistoryOp},
+    import::{self, ImportIssue, ImportMode, ImportReport, OnConflict},
+    index::{self, FieldIndexes, SortOrder, UniqueIndex},
+    integrity::{self, TreeIntegrityReport},
+    io::{
+        exists, get_json, get_sequence, gunzip, gzip, prepare_store_dir, put_json, put_sequence,
+        remove_stale_tmp_files, sorted,
+    },
+    key::{self, KeyGenerator, KeyKind, Records},
+    layout::{self, Layout},
+    lock::{LockTable, RecordLock},
+    merge::{self, MergeOptions, MergePolicy, MergeReport, TreeMerge},
+    meta::{self, Meta, META_FILE},
+    metrics::{Metrics, MetricsSnapshot},
+    migrations::{self, AppliedMigration, Migration, MigrationReport},
+    partition::{self, Granularity, Partition, PartitionIndex, PartitionSpec, PartitionStats},
+    profile::{FieldProfile, Profiler},
+    raw::{self, RawRecords, ValueMode},
+    repair::{is_corruption, CorruptionPolicy, LoadReport, RepairStrategy, TreeOutcome},
+    replica::{self, SyncCursor, SyncReport, TreeSync},
+    rt::{self, fs, Instant},
+    schema::{self, SchemaOptions, Shape},
+    seed::{self, SeedMode, SeedReport},
+    session::Session,
+    spill::{self, Spill},
+    stats::{
+        value_size, MemoryCompaction, StoreDescription, StoreStats, TreeDescription, TreeStats,
+    },
+    trace,
+    undo::{self, UndoEntry, UndoInfo, UndoOp, DEFAULT_UNDO_DEPTH},
+    wal::{self, CheckpointStats, RestorePoint, Stamped, WalEntry, WalOptions},
 };
 
-use crate::error::JsonStoreError;
+// trees written at once by save(), to stay well clear of file handle limits
+const SAVE_CONCURRENCY: usize = 8;
+
+// trees read at once by an eager load or preload, unless LoadOptions says otherwise
+pub const DEFAULT_LOAD_CONCURRENCY: usize = 8;
+
+// snapshot files at least this large are decoded off the async workers, and
+// snapshots of at least this many records encoded off them
+const BLOCKING_DECODE_SIZE: usize = 1 << 20;
+const BLOCKING_ENCODE_RECORDS: usize = 5_000;
 
-const INFOS_FILE: &str = "infos.json";
+// LoadOptions::shrink_below leaves maps smaller than this alone
+const SHRINK_MIN_CAPACITY: usize = 1_024;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+// times save_tree_with encodes a tree with it unlocked before giving up on writes
+// to it letting up
+const ENCODE_ATTEMPTS: usize = 3;
+
+// spilled records Tree::scan reads from the file at a time
+const SCAN_BATCH: usize = 256;
+
+// waits for a tree lock at least this long are logged
+const LOCK_WAIT_THRESHOLD: Duration = Duration::from_millis(10);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct Info {
     pub sequence_field: String,
     pub unique_fields: HashMap<String, Vec<String>>,
     pub capacity: u32,
+    #[serde(default)]
+    pub storage: StorageFormat,
+    // compress the snapshot file; ignored for append-log trees
+    #[serde(default)]
+    pub compression: Option<Compression>,
+    // records kept parsed or as JSON text, see raw.rs
+    #[serde(default)]
+    pub value_mode: ValueMode,
+    // keep at most this many records in memory and the rest in an overflow file,
+    // see spill.rs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resident_limit: Option<u32>,
+    // split the snapshot into this many `{tree}/part-NNN` files by sequence % n, so
+    // a save rewrites only the parts that changed; set at creation, then reshard_tree
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shards: Option<u32>,
+    // keep prior versions of records on update and delete, see history()
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history: Option<HistoryConfig>,
+    // split the records into one file per period of a time field, see partition.rs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partition_by: Option<PartitionSpec>,
+    // the record field holding the time it expires at, see expiry.rs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry_field: Option<String>,
+    // fields holding the sequence of a record in another tree (or this one), by the
+    // tree they refer to; nothing checks them on write, vacuum_tree rewrites them
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub references: HashMap<String, String>,
+    // fields with a secondary index, for find_by_field; see create_index
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub indexes: BTreeSet<String>,
+    // fields with an ordered index, for find_range and find_sorted
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub ordered_indexes: BTreeSet<String>,
+    // a string key the records are named by besides their sequence, see key.rs
+    #[serde(default, skip_serializing_if = "KeyKind::is_sequence")]
+    pub key: KeyKind,
+    // sealed by freeze_tree: its records and Info can be read but not changed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub frozen: bool,
+    // the last version migrate brought the records to, see migrations.rs
+    #[serde(default, skip_serializing_if = "migrations::is_unset")]
+    pub migration_version: u32,
+    // the caller's own notes on the tree (owner, description...); never read by the store,
+    // save for state older catalogs kept here, which load moves to its own field
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, Value>,
+    // how far sync_from has brought a replica's copy of the tree, see replica.rs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sync_cursor: Option<SyncCursor>,
+}
+
+// How a tree's records are kept on disk.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageFormat {
+    // `{tree}.json` holding every record, rewritten on each save
+    #[default]
+    Snapshot,
+    // `{tree}.jsonl` with one line appended per write (tombstones for deletes),
+    // folded last-write-wins at load; compact_tree drops superseded lines
+    AppendLog,
+}
+
+// Compression of a snapshot file on disk. A gzip'd snapshot is `{tree}.json.gz`.
+// Changing the setting takes effect at the tree's next save, which also removes
+// the file in the old form; until then the old file is read.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    Gzip,
 }
 
 impl Info {
@@ -32,351 +190,8450 @@ impl Info {
             sequence_field,
             unique_fields,
             capacity,
+            storage: StorageFormat::default(),
+            compression: None,
+            value_mode: ValueMode::default(),
+            resident_limit: None,
+            shards: None,
+            history: None,
+            partition_by: None,
+            expiry_field: None,
+            references: HashMap::new(),
+            indexes: BTreeSet::new(),
+            ordered_indexes: BTreeSet::new(),
+            key: KeyKind::Sequence,
+            frozen: false,
+            migration_version: 0,
+            metadata: HashMap::new(),
+            sync_cursor: None,
         }
     }
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct Tree {
-    sequence: u64,
-    data: HashMap<u64, Value>,
-    changed: bool,
-}
 
-impl Tree {
-    pub fn new(sequence: u64, data: HashMap<u64, Value>, changed: bool) -> Self {
-        Self {
-            sequence,
-            data,
-            changed,
-        }
+    pub fn builder() -> InfoBuilder {
+        InfoBuilder::default()
     }
-}
 
-type Trees = HashMap<String, Arc<RwLock<Tree>>>;
-
-#[derive(Debug)]
-pub struct JsonStore {
-    path: Box<Path>,
-    infos: HashMap<String, Info>,
-    trees: Trees,
-}
+    // Check the rules an Info has to keep whichever way it was made: build() applies
+    // them, and create_tree and load for Infos put together by hand. Constraints are
+    // gone through by name, so the same one is reported every time.
+    pub fn validate(&self) -> Result<(), InfoValidationError> {
+        if self.sequence_field.is_empty() {
+            return Err(InfoValidationError::EmptySequenceField);
+        }
+        if self.capacity == 0 {
+            return Err(InfoValidationError::ZeroCapacity);
+        }
+        if self.metadata.contains_key(migrations::LEGACY_VERSION_KEY) {
+            return Err(InfoValidationError::ReservedMetadataKey {
+                key: migrations::LEGACY_VERSION_KEY.to_string(),
+            });
+        }
 
-impl JsonStore {
-    pub async fn create_tree(&mut self, tname: &str, info: Info) -> Result<(), JsonStoreError> {
-        if self.infos.contains_key(tname) {
-            return Err(JsonStoreError::FoundTree(tname.to_string()));
+        let mut constraints = self.unique_fields.iter().collect::<Vec<_>>();
+        constraints.sort();
+        for (name, fields) in constraints {
+            let constraint = name.clone();
+            if fields.is_empty() {
+                return Err(InfoValidationError::EmptyConstraint { constraint });
+            }
+            let mut seen = HashSet::new();
+            for field in fields {
+                if field.is_empty() {
+                    return Err(InfoValidationError::EmptyConstraintField { constraint });
+                }
+                if *field == self.sequence_field {
+                    return Err(InfoValidationError::ConstraintHasSequenceField {
+                        constraint,
+                        field: field.clone(),
+                    });
+                }
+                if !seen.insert(field) {
+                    return Err(InfoValidationError::DuplicateConstraintField {
+                        constraint,
+                        field: field.clone(),
+                    });
+                }
+            }
         }
 
-        self.infos.insert(tname.to_string(), info);
+        Ok(())
+    }
 
-        self.trees.insert(
-            tname.to_string(),
-            Arc::new(RwLock::new(Tree::new(0, HashMap::default(), true))),
-        );
+    // whether a write must see the fields of every record, for unique constraints,
+    // string keys or indexes
+    pub(crate) fn indexed(&self) -> bool {
+        self.unique() || !self.indexes.is_empty() || !self.ordered_indexes.is_empty()
+    }
 
-        put_json::<HashMap<String, Info>>(self.path.join(INFOS_FILE), &self.infos).await?;
+    // whether UniqueIndex holds anything for the tree: its constraints or its keys
+    pub(crate) fn unique(&self) -> bool {
+        !self.unique_fields.is_empty() || !self.key.is_sequence()
+    }
 
-        self.save_tree(tname).await?;
+    // the Info less what the store keeps in it at runtime, for telling whether two
+    // describe the same tree
+    fn shape(&self) -> Info {
+        Info {
+            metadata: HashMap::new(),
+            frozen: false,
+            migration_version: 0,
+            sync_cursor: None,
+            ..self.clone()
+        }
+    }
 
+    // move the version migrate once kept in metadata into its own field
+    fn take_legacy_version(&mut self, tname: &str) -> Result<(), JsonStoreError> {
+        match migrations::legacy_version(&self.metadata) {
+            Some(Ok(version)) => self.migration_version = version,
+            Some(Err(version)) => {
+                return Err(JsonStoreError::InvalidInfo(format!(
+                    "tree '{}' has migration version {}, more than a u32 holds",
+                    tname, version
+                )))
+            }
+            None => return Ok(()),
+        }
+        self.metadata.remove(migrations::LEGACY_VERSION_KEY);
         Ok(())
     }
 
-    pub async fn drop_tree(&mut self, tname: &str) -> Result<(), JsonStoreError> {
-        if !self.infos.contains_key(tname) {
-            return Err(JsonStoreError::NotFoundTree(tname.to_string()));
+    // move the cursor a replica once kept in metadata into its own field; a value that
+    // isn't one is the caller's and stays
+    fn take_legacy_cursor(&mut self) {
+        let cursor = self.metadata.get(replica::LEGACY_CURSOR_KEY).cloned();
+        if let Some(Ok(cursor)) = cursor.map(serde_json::from_value::<SyncCursor>) {
+            self.metadata.remove(replica::LEGACY_CURSOR_KEY);
+            self.sync_cursor = Some(cursor);
         }
-        self.infos.remove(tname);
-        self.trees.remove(tname);
-
-        put_json::<HashMap<String, Info>>(self.path.join(INFOS_FILE), &self.infos).await?;
-
-        let path = self.path.join(format!("{}.seq", tname));
-        let _ = tokio::fs::remove_file(path).await;
+    }
+}
 
-        let path = self.path.join(format!("{}.json", tname));
-        let _ = tokio::fs::remove_file(path).await;
+// Info assembled field by field and checked by build():
+//
+//   Info::builder().sequence_field("id").unique("email", ["email"]).capacity(1000).build()?
+//
+// capacity is unlimited unless set.
+#[derive(Debug, Clone)]
+pub struct InfoBuilder {
+    sequence_field: String,
+    unique_fields: Vec<(String, Vec<String>)>,
+    capacity: u32,
+    storage: StorageFormat,
+    compression: Option<Compression>,
+    value_mode: ValueMode,
+    resident_limit: Option<u32>,
+    shards: Option<u32>,
+    history: Option<HistoryConfig>,
+    partition_by: Option<PartitionSpec>,
+    expiry_field: Option<String>,
+    references: HashMap<String, String>,
+    indexes: BTreeSet<String>,
+    ordered_indexes: BTreeSet<String>,
+    key: KeyKind,
+    metadata: HashMap<String, Value>,
+}
 
-        Ok(())
+impl Default for InfoBuilder {
+    fn default() -> Self {
+        Self {
+            sequence_field: String::new(),
+            unique_fields: Vec::new(),
+            capacity: u32::MAX,
+            storage: StorageFormat::default(),
+            compression: None,
+            value_mode: ValueMode::default(),
+            resident_limit: None,
+            shards: None,
+            history: None,
+            partition_by: None,
+            expiry_field: None,
+            references: HashMap::new(),
+            indexes: BTreeSet::new(),
+            ordered_indexes: BTreeSet::new(),
+            key: KeyKind::Sequence,
+            metadata: HashMap::new(),
+        }
     }
+}
 
-    pub async fn load(path: &Path) -> Result<Self, JsonStoreError> {
-        let infos = get_json::<HashMap<String, Info>>(path.join(INFOS_FILE))
-            .await?
-            .unwrap_or(HashMap::new());
+impl InfoBuilder {
+    pub fn sequence_field(mut self, field: impl Into<String>) -> Self {
+        self.sequence_field = field.into();
+        self
+    }
 
-        let mut trees: Trees = HashMap::new();
+    // a unique constraint called name over the combination of fields
+    pub fn unique<S: Into<String>>(
+        mut self,
+        name: impl Into<String>,
+        fields: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.unique_fields
+            .push((name.into(), fields.into_iter().map(Into::into).collect()));
+        self
+    }
 
-        for (key, _value) in infos.iter() {
-            let file = path.join(format!("{}.seq", key));
-            let sequence = get_sequence(file).await?;
+    pub fn capacity(mut self, capacity: u32) -> Self {
+        self.capacity = capacity;
+        self
+    }
 
-            let path = path.join(format!("{}.json", key));
-            let data = get_json::<HashMap<u64, Value>>(path)
-                .await?
-                .unwrap_or(HashMap::new());
+    pub fn storage(mut self, storage: StorageFormat) -> Self {
+        self.storage = storage;
+        self
+    }
 
-            trees.insert(
-                key.clone(),
-                Arc::new(RwLock::new(Tree::new(sequence, data, false))),
-            );
-        }
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
 
-        Ok(Self {
-            path: path.into(),
-            infos,
-            trees,
-        })
+    pub fn value_mode(mut self, mode: ValueMode) -> Self {
+        self.value_mode = mode;
+        self
     }
 
-    // insert tree
-    pub async fn insert<T: Serialize>(
-        &mut self,
-        tname: &str,
-        value: &T,
-    ) -> Result<u64, JsonStoreError> {
-        let info = self
-            .infos
-            .get(tname)
-            .ok_or(JsonStoreError::NotFoundTree(tname.to_string()))?;
+    pub fn resident_limit(mut self, records: u32) -> Self {
+        self.resident_limit = Some(records);
+        self
+    }
 
-        let mut tree = self._write_lock(tname).await?;
+    pub fn shards(mut self, shards: u32) -> Self {
+        self.shards = Some(shards);
+        self
+    }
 
-        if tree.data.len() >= info.capacity as usize {
-            return Err(JsonStoreError::CapacityExceeded(tname.to_string()));
-        }
+    pub fn history(mut self, max_versions: u32) -> Self {
+        self.history = Some(HistoryConfig { max_versions });
+        self
+    }
 
-        let mut json_value = serde_json::to_value(value)?;
+    pub fn partition_by(mut self, field: impl Into<String>, granularity: Granularity) -> Self {
+        self.partition_by = Some(PartitionSpec {
+            field: field.into(),
+            granularity,
+        });
+        self
+    }
 
-        for (_, fields) in &info.unique_fields {
-            let mut n = json!({});
-            for field in fields {
-                n.as_object_mut()
-                    .ok_or(JsonStoreError::UnObjectValue)?
-                    .insert(field.clone(), json_value[field].clone());
-            }
+    // records expire at the time in field
+    pub fn expiry_field(mut self, field: impl Into<String>) -> Self {
+        self.expiry_field = Some(field.into());
+        self
+    }
 
-            for (_, row) in &tree.data {
-                let mut o = json!({});
-                for field in fields {
-                    o.as_object_mut()
-                        .ok_or(JsonStoreError::UnObjectValue)?
-                        .insert(field.clone(), row[field].clone());
-                }
-                if n == o {
-                    return Err(JsonStoreError::DuplicateUniqueFields(tname.to_string()));
-                }
-            }
-        }
+    // field holds the sequence of a record of tree
+    pub fn reference(mut self, field: impl Into<String>, tree: impl Into<String>) -> Self {
+        self.references.insert(field.into(), tree.into());
+        self
+    }
 
-        let seq = tree.sequence + 1;
-        tree.sequence = seq;
+    pub fn index(mut self, field: impl Into<String>) -> Self {
+        self.indexes.insert(field.into());
+        self
+    }
 
-        if json_value[info.sequence_field.clone()].is_null() {
-            json_value
-                .as_object_mut()
-                .ok_or(JsonStoreError::UnObjectValue)?
-                .insert(info.sequence_field.clone(), serde_json::to_value(seq)?);
-        } else {
-            *json_value
-                .get_mut(info.sequence_field.clone())
-                .ok_or(JsonStoreError::UnableToMutValue(tname.to_string()))? =
-                serde_json::to_value(seq)?;
-        }
+    pub fn index_ordered(mut self, field: impl Into<String>) -> Self {
+        self.ordered_indexes.insert(field.into());
+        self
+    }
 
-        tree.data.insert(seq, json_value);
+    // name records by the string in field as well as their sequence, see key.rs
+    pub fn string_key(mut self, field: impl Into<String>, generator: KeyGenerator) -> Self {
+        self.key = KeyKind::String {
+            field: field.into(),
+            generator,
+        };
+        self
+    }
 
-        tree.changed = true;
+    // name records by the values of fields together as well as their sequence
+    pub fn composite_key<S: Into<String>>(mut self, fields: impl IntoIterator<Item = S>) -> Self {
+        self.key = KeyKind::Composite {
+            fields: fields.into_iter().map(Into::into).collect(),
+        };
+        self
+    }
 
-        Ok(seq)
+    pub fn metadata(mut self, key: impl Into<String>, value: Value) -> Self {
+        self.metadata.insert(key.into(), value);
+        self
     }
 
-    // update tree
-    pub async fn update<T: Serialize>(
-        &mut self,
-        tname: &str,
-        value: &T,
-    ) -> Result<(), JsonStoreError> {
-        let info = self
-            .infos
-            .get(tname)
-            .ok_or(JsonStoreError::NotFoundTree(tname.to_string()))?;
+    pub fn build(self) -> Result<Info, JsonStoreError> {
+        let invalid = |reason: String| Err(JsonStoreError::InvalidInfo(reason));
 
-        let mut tree = self._write_lock(tname).await?;
+        if self.shards == Some(0) {
+            return invalid("shards must be at least 1".to_string());
+        }
 
-        let json_value = serde_json::to_value(value)?;
+        let mut unique_fields = HashMap::new();
+        for (name, fields) in self.unique_fields {
+            if unique_fields.contains_key(&name) {
+                return invalid(format!("unique constraint '{}' is defined twice", name));
+            }
+            unique_fields.insert(name, fields);
+        }
 
-        let seq = match json_value[info.sequence_field.clone()].as_u64() {
-            Some(n) => n,
-            None => return Err(JsonStoreError::SequenceNotExist(tname.to_string())),
-        };
+        for (field, tree) in self.references.iter() {
+            if field.is_empty() || tree.is_empty() {
+                return invalid("reference with an empty field or tree".to_string());
+            }
+            if *field == self.sequence_field {
+                return invalid(format!("sequence field '{}' can't be a reference", field));
+            }
+        }
 
-        if !tree.data.contains_key(&seq) {
-            return Err(JsonStoreError::SequenceNotExist(tname.to_string()));
+        if self.indexes.contains("") || self.ordered_indexes.contains("") {
+            return invalid("index on an empty field".to_string());
         }
 
-        for (_, fields) in &info.unique_fields {
-            let mut n = json!({});
-            for field in fields {
-                n.as_object_mut()
-                    .ok_or(JsonStoreError::UnObjectValue)?
-                    .insert(field.clone(), json_value[field].clone());
+        // what partition.rs leaves out for now
+        if let Some(spec) = &self.partition_by {
+            if spec.field.is_empty() {
+                return invalid("partition field is empty".to_string());
+            }
+            if spec.field == self.sequence_field {
+                return invalid("a tree can't be partitioned by its sequence field".to_string());
+            }
+            if self.shards.is_some() {
+                return invalid("a partitioned tree can't also be sharded".to_string());
+            }
+            if self.storage != StorageFormat::Snapshot {
+                return invalid("a partitioned tree needs snapshot storage".to_string());
+            }
+            if !unique_fields.is_empty() {
+                return invalid(
+                    "unique constraints aren't supported on partitioned trees".to_string(),
+                );
+            }
+            if !self.indexes.is_empty() || !self.ordered_indexes.is_empty() {
+                return invalid("indexes aren't supported on partitioned trees".to_string());
             }
+        }
 
-            for (key, row) in &tree.data {
-                if *key == seq {
-                    continue;
-                }
-                let mut o = json!({});
-                for field in fields {
-                    o.as_object_mut()
-                        .ok_or(JsonStoreError::UnObjectValue)?
-                        .insert(field.clone(), row[field].clone());
-                }
-                if n == o {
-                    return Err(JsonStoreError::DuplicateUniqueFields(tname.to_string()));
-                }
+        if self.value_mode == ValueMode::Raw {
+            if self.storage != StorageFormat::Snapshot {
+                return invalid("raw records need snapshot storage".to_string());
+            }
+            if self.partition_by.is_some() {
+                return invalid("a tree with raw records can't be partitioned".to_string());
             }
         }
 
-        tree.data.entry(seq).and_modify(|v| *v = json_value);
+        if let Some(field) = &self.expiry_field {
+            if field.is_empty() {
+                return invalid("expiry field is empty".to_string());
+            }
+            if *field == self.sequence_field {
+                return invalid(format!(
+                    "sequence field '{}' can't be the expiry field",
+                    field
+                ));
+            }
+            if self.value_mode == ValueMode::Raw {
+                return invalid("a tree with raw records can't have an expiry field".to_string());
+            }
+        }
 
-        tree.changed = true;
+        let key = self.key.fields();
+        if let KeyKind::Composite { fields } = &self.key {
+            if fields.is_empty() {
+                return invalid("composite key has no fields".to_string());
+            }
+            if fields.iter().collect::<HashSet<_>>().len() < fields.len() {
+                return invalid("composite key names a field twice".to_string());
+            }
+        }
+        if let KeyKind::String {
+            generator: KeyGenerator::Uuid,
+            ..
+        } = &self.key
+        {
+            if cfg!(not(feature = "uuid")) {
+                return invalid("uuid keys need the uuid feature".to_string());
+            }
+        }
+        for field in key.iter() {
+            if field.is_empty() {
+                return invalid("key field is empty".to_string());
+            }
+            if *field == self.sequence_field {
+                return invalid(format!("sequence field '{}' can't be in the key", field));
+            }
+            if self.references.contains_key(*field) {
+                return invalid(format!("key field '{}' can't be a reference", field));
+            }
+        }
+        if !key.is_empty() {
+            // the key index has to see every record, parsed and in memory
+            if self.partition_by.is_some() {
+                return invalid("a keyed tree can't be partitioned".to_string());
+            }
+            if self.value_mode == ValueMode::Raw {
+                return invalid("a keyed tree needs parsed records".to_string());
+            }
+            if self.resident_limit.is_some() {
+                return invalid("a keyed tree can't have a resident limit".to_string());
+            }
+        }
 
-        Ok(())
-    }
+        if let Some(limit) = self.resident_limit {
+            if limit == 0 {
+                return invalid("resident limit must be at least 1".to_string());
+            }
+            if self.storage != StorageFormat::Snapshot
+                || self.shards.is_some()
+                || self.partition_by.is_some()
+            {
+                return invalid("a resident limit needs a single snapshot file".to_string());
+            }
+            if self.value_mode == ValueMode::Raw {
+                return invalid("a tree with raw records can't have a resident limit".to_string());
+            }
+        }
 
-    pub async fn delete(&mut self, tname: &str, sequence: u64) -> Result<(), JsonStoreError> {
-        let mut tree = self._write_lock(tname).await?;
+        let info = Info {
+            sequence_field: self.sequence_field,
+            unique_fields,
+            capacity: self.capacity,
+            storage: self.storage,
+            compression: self.compression,
+            value_mode: self.value_mode,
+            resident_limit: self.resident_limit,
+            shards: self.shards,
+            history: self.history,
+            partition_by: self.partition_by,
+            expiry_field: self.expiry_field,
+            references: self.references,
+            indexes: self.indexes,
+            ordered_indexes: self.ordered_indexes,
+            key: self.key,
+            frozen: false,
+            migration_version: 0,
+            metadata: self.metadata,
+            sync_cursor: None,
+        };
+        info.validate()
+            .map_err(|e| JsonStoreError::InvalidInfo(e.to_string()))?;
+        Ok(info)
+    }
+}
 
-        tree.data
-            .remove(&sequence)
-            .ok_or(JsonStoreError::SequenceNotExist(tname.to_string()))?;
+// When the write paths save a tree on their own. Whatever the policy, any save
+// (manual or automatic) resets the tree's pending write count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FlushPolicy {
+    // only explicit save/save_tree calls write files
+    #[default]
+    Manual,
+    // save after every insert/update/delete
+    EveryWrite,
+    // save once this many writes are pending
+    AfterWrites(u32),
+    // save on a write when the last save is at least this old; idle trees are
+    // not touched, pair with start_autosave for that
+    Interval(Duration),
+}
 
-        tree.changed = true;
+// How hard a save works to get data onto disk before returning.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Durability {
+    // overwrite files in place; fastest, but a crash mid-write can truncate a file
+    None,
+    // write a temp file, flush it and rename it over the target, so a crash leaves
+    // either the old or the new file
+    #[default]
+    Flush,
+    // like Flush, plus fsync of the temp file before and of the directory after the
+    // rename, so a completed save also survives power loss
+    Fsync,
+}
 
-        Ok(())
-    }
+// Layout of the JSON written to disk. Either way records are written in ascending
+// sequence order and object keys sorted (kept in the order they were written under
+// arbitrary-precision), so saving the same data gives the same bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Compact,
+    // indented, one field per line; meant for data directories kept in git
+    Pretty,
+}
 
-    pub async fn select<T: DeserializeOwned>(
-        &self,
-        tname: &str,
-        sequence: u64,
-    ) -> Result<T, JsonStoreError> {
-        let tree = self._read_lock(tname).await?;
+#[derive(Debug, Clone)]
+pub struct LoadOptions {
+    // default durability of saves; save_with/save_tree_with override it per call
+    pub durability: Durability,
+    pub format: OutputFormat,
+    // log every mutation to `{tree}.wal` so unsaved writes survive a crash
+    pub wal: Option<WalOptions>,
+    // refuse every change and leave the directory untouched, even torn logs
+    pub read_only: bool,
+    // refuse every change but sync_from, see replica.rs
+    pub replica: bool,
+    // time source for timestamps; the system clock if None
+    pub clock: Option<Arc<dyn Clock>>,
+    // codec of a new store's snapshot files; an existing store must already use it
+    pub codec: Option<Codec>,
+    // read every tree while loading instead of on its first use
+    pub eager: bool,
+    // create a missing store directory; otherwise loading it fails with InvalidStorePath
+    pub create_if_missing: bool,
+    // file layout of a new store; an existing store must already use it
+    pub layout: Option<Layout>,
+    // what to do with trees whose files are corrupt
+    pub corruption_policy: CorruptionPolicy,
+    // count operations and their latencies for metrics()
+    pub metrics: bool,
+    // mutations per tree that undo_last can take back; 0 keeps none
+    pub undo_depth: usize,
+    // trees read at once by an eager load or preload; 0 counts as 1
+    pub load_concurrency: usize,
+    // Save a tree kept in a single snapshot file by writing only the records changed
+    // since its snapshot to `{tree}.delta.json`, for as long as there are at most this
+    // fraction of its records; past that the snapshot is written whole and the delta
+    // goes. None always writes the whole snapshot. Deltas are read either way.
+    pub incremental_save: Option<f64>,
+    // shrink a tree's maps, as compact_memory does, once a write leaves its records
+    // filling less than this fraction of the room kept for them; None never does
+    pub shrink_below: Option<f64>,
+    // where trees with Info::resident_limit keep the records they spill; None is the
+    // system's temporary directory. There is none on wasm32, where such trees are
+    // refused.
+    pub spill_dir: Option<PathBuf>,
+    // queue writes to a tree to apply them under one lock, see batch.rs
+    pub write_batching: WriteBatching,
+}
 
-        Ok(serde_json::from_value::<T>(
-            tree.data
-                .get(&sequence)
-                .ok_or(JsonStoreError::SequenceNotExist(tname.to_string()))?
-                .clone(),
-        )?)
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            durability: Durability::default(),
+            format: OutputFormat::default(),
+            wal: None,
+            read_only: false,
+            replica: false,
+            clock: None,
+            codec: None,
+            eager: false,
+            create_if_missing: true,
+            layout: None,
+            corruption_policy: CorruptionPolicy::default(),
+            metrics: false,
+            undo_depth: DEFAULT_UNDO_DEPTH,
+            load_concurrency: DEFAULT_LOAD_CONCURRENCY,
+            incremental_save: None,
+            shrink_below: None,
+            spill_dir: None,
+            write_batching: WriteBatching::default(),
+        }
     }
+}
 
-    pub async fn save(&self) -> Result<(), JsonStoreError> {
-        for (key, _value) in self.infos.iter() {
-            self.save_tree(key).await?
-        }
+#[derive(Serialize, Deserialize, Debug)]
+struct Tree {
+    sequence: u64,
+    data: Records,
+    changed: bool,
+    // Of a Raw tree: its records as text, which is what gets saved, and whether data
+    // and the indexes hold them parsed as well. Until then both are empty.
+    #[serde(skip)]
+    raw: Option<RawRecords>,
+    #[serde(skip)]
+    parsed: bool,
+    // records out of memory, for trees with Info::resident_limit
+    #[serde(skip)]
+    spill: Option<Spill>,
+    // on-disk state of the .seq/.json files as of our last read or write
+    #[serde(skip)]
+    seq_stamp: Option<Stamp>,
+    #[serde(skip)]
+    data_stamp: Option<Stamp>,
+    // writes since the last save, and when that was
+    #[serde(skip)]
+    pending_writes: u32,
+    #[serde(skip, default = "Instant::now")]
+    last_saved: Instant,
+    // overrides the store-wide policy
+    #[serde(skip)]
+    flush_policy: Option<FlushPolicy>,
+    // entries in the tree's WAL since the last checkpoint
+    #[serde(skip)]
+    wal_entries: u64,
+    #[serde(skip)]
+    storage: StorageFormat,
+    #[serde(skip)]
+    compression: Option<Compression>,
+    // 0 for a single snapshot file
+    #[serde(skip)]
+    shards: u32,
+    // shards holding writes since the last save, and their files' stamps
+    #[serde(skip)]
+    dirty_shards: BTreeSet<u32>,
+    #[serde(skip)]
+    shard_stamps: Vec<Option<Stamp>>,
+    // Of a tree kept in a single snapshot file: the records written or removed since
+    // that was last written whole, which the delta file holds once saved, and whether
+    // the next save has to write it whole anyway, as after wholesale changes.
+    #[serde(skip)]
+    unsnapshotted: HashSet<u64>,
+    // the keys those of a keyed tree removed were under, for the delta file
+    #[serde(skip)]
+    removed_keys: HashMap<u64, String>,
+    #[serde(skip)]
+    whole: bool,
+    // changes whenever the records or the way they are split into files do, from a
+    // counter shared by all trees, so a save can tell if its encoding still holds
+    #[serde(skip)]
+    mark: u64,
+    // snapshot files save_tree_with encoded with the tree unlocked, by key
+    #[serde(skip)]
+    encoded: HashMap<String, Encoded>,
+    // false until the tree's files are first read
+    #[serde(skip)]
+    loaded: bool,
+    // why the files failed to read, if the corruption policy let the store go on
+    #[serde(skip)]
+    corrupt: Option<String>,
+    // for trees with Info::partition_by: every period's sequences, loaded or not, and
+    // the period each sequence is in
+    #[serde(skip)]
+    partition_by: Option<PartitionSpec>,
+    #[serde(skip)]
+    partitions: BTreeMap<String, Partition>,
+    #[serde(skip)]
+    partition_of: HashMap<u64, String>,
+    // sequences moved between periods since the index was last written
+    #[serde(skip)]
+    partitions_changed: bool,
+    // prior versions of records, for trees with Info::history
+    #[serde(skip)]
+    history: History,
+    #[serde(skip)]
+    history_changed: bool,
+    // recent mutations, newest last, for undo_last
+    #[serde(skip)]
+    undo: VecDeque<UndoEntry>,
+    // built by unique_index; None until then and after data is replaced wholesale
+    #[serde(skip)]
+    unique: Option<UniqueIndex>,
+    // typed caches of TreeHandles reading this tree, told of every write
+    #[serde(skip)]
+    caches: Caches,
+    #[serde(skip)]
+    indexes: FieldIndexes,
+    // sequences allocate_sequences handed out that insert_reserved hasn't filled yet,
+    // as ranges by first and last; in memory only
+    #[serde(skip)]
+    reserved: BTreeMap<u64, u64>,
+}
 
-        Ok(())
+impl Tree {
+    pub fn new(sequence: u64, data: Records, changed: bool) -> Self {
+        Self {
+            sequence,
+            data,
+            changed,
+            raw: None,
+            parsed: true,
+            spill: None,
+            seq_stamp: None,
+            data_stamp: None,
+            pending_writes: 0,
+            last_saved: Instant::now(),
+            flush_policy: None,
+            wal_entries: 0,
+            storage: StorageFormat::default(),
+            compression: None,
+            shards: 0,
+            dirty_shards: BTreeSet::new(),
+            shard_stamps: Vec::new(),
+            unsnapshotted: HashSet::new(),
+            removed_keys: HashMap::new(),
+            whole: true,
+            mark: next_mark(),
+            encoded: HashMap::new(),
+            loaded: true,
+            corrupt: None,
+            partition_by: None,
+            partitions: BTreeMap::new(),
+            partition_of: HashMap::new(),
+            partitions_changed: false,
+            history: History::new(),
+            history_changed: false,
+            undo: VecDeque::new(),
+            unique: None,
+            indexes: FieldIndexes::default(),
+            caches: Caches::default(),
+            reserved: BTreeMap::new(),
+        }
     }
 
-    pub async fn save_tree(&self, tname: &str) -> Result<(), JsonStoreError> {
-        let mut tree = self._write_lock(tname).await?;
+    // a tree with no records yet, set up as info says
+    fn empty(sequence: u64, info: &Info) -> Self {
+        let mut tree = Self::new(sequence, Records::new(info), true);
+        tree.storage = info.storage;
+        tree.compression = info.compression;
+        tree.set_value_mode(info.value_mode);
+        tree.set_shards(info.shards.unwrap_or(0));
+        tree.partition_by = info.partition_by.clone();
+        tree.indexes = FieldIndexes::build(info, &tree.data);
+        tree
+    }
 
-        if !tree.changed {
-            return Ok(());
+    // stands in for a tree until its first access reads it
+    fn unloaded() -> Self {
+        Self {
+            loaded: false,
+            ..Self::new(0, Records::default(), false)
         }
+    }
 
-        let file = self.path.join(format!("{}.seq", tname));
-        put_sequence(file, tree.sequence).await?;
+    fn set_value_mode(&mut self, mode: ValueMode) {
+        self.raw = (mode == ValueMode::Raw).then(HashMap::new);
+        self.parsed = mode == ValueMode::Parsed;
+    }
 
-        let file = self.path.join(format!("{}.json", tname));
-        put_json(file, &tree.data).await?;
+    fn set_shards(&mut self, shards: u32) {
+        self.shards = shards;
+        self.shard_stamps = vec![None; shards as usize];
+        self.dirty_shards = (0..shards).collect();
+        self.whole = true;
+        self.mark = next_mark();
+    }
 
-        tree.changed = false;
+    // whether the records are kept in one snapshot file, the only form saved in part
+    fn single_file(&self) -> bool {
+        self.storage == StorageFormat::Snapshot && self.shards == 0 && self.partition_by.is_none()
+    }
 
-        Ok(())
+    // take on a partitioned tree's index, with none of its partitions loaded
+    fn set_partitions(&mut self, spec: PartitionSpec, index: PartitionIndex) {
+        self.partition_of = index
+            .iter()
+            .flat_map(|(period, seqs)| seqs.iter().map(|seq| (*seq, period.clone())))
+            .collect();
+        self.partitions = index
+            .into_iter()
+            .map(|(period, sequences)| {
+                let partition = Partition {
+                    sequences,
+                    ..Partition::default()
+                };
+                (period, partition)
+            })
+            .collect();
+        self.partition_by = Some(spec);
     }
 
-    async fn _write_lock(&self, tname: &str) -> Result<RwLockWriteGuard<'_, Tree>, JsonStoreError> {
-        Ok(self
-            .trees
-            .get(tname)
-            .ok_or(JsonStoreError::NotFoundTree(tname.to_string()))?
-            .write()
-            .await)
+    // note a write to the record at seq, moving it to the period it now falls in
+    fn touch(&mut self, seq: u64) {
+        if self.shards > 0 {
+            self.dirty_shards.insert((seq % self.shards as u64) as u32);
+        }
+
+        let Some(spec) = &self.partition_by else {
+            return;
+        };
+        let period = self.data.get(&seq).map(|value| spec.period(value));
+        let old = self.partition_of.remove(&seq);
+        if let Some(partition) = old.as_ref().and_then(|old| self.partitions.get_mut(old)) {
+            partition.sequences.remove(&seq);
+            partition.dirty = true;
+        }
+        if let Some(period) = period.clone() {
+            let partition = self
+                .partitions
+                .entry(period.clone())
+                .or_insert_with(Partition::empty);
+            partition.sequences.insert(seq);
+            partition.dirty = true;
+            self.partition_of.insert(seq, period);
+        }
+        self.partitions_changed |= old != period;
     }
 
-    async fn _read_lock(&self, tname: &str) -> Result<RwLockReadGuard<'_, Tree>, JsonStoreError> {
-        Ok(self
-            .trees
-            .get(tname)
-            .ok_or(JsonStoreError::NotFoundTree(tname.to_string()))?
-            .read()
-            .await)
+    // route every record afresh after data was replaced wholesale; every partition
+    // must be loaded
+    fn repartition(&mut self) {
+        let Some(spec) = &self.partition_by else {
+            return;
+        };
+        // periods left empty stay, so their files go at the next save
+        for partition in self.partitions.values_mut() {
+            partition.sequences.clear();
+            partition.dirty = true;
+        }
+        self.partition_of.clear();
+        for (seq, value) in self.data.iter() {
+            let period = spec.period(value);
+            let partition = self
+                .partitions
+                .entry(period.clone())
+                .or_insert_with(Partition::empty);
+            partition.sequences.insert(*seq);
+            partition.dirty = true;
+            self.partition_of.insert(*seq, period);
+        }
+        self.partitions_changed = true;
     }
 
-    pub fn show(&self) {
-        println!("{:?}", self.infos);
-        println!("{:?}", self.trees);
+    // whether the records of every one of periods are in memory
+    fn has_loaded(&self, periods: &[String]) -> bool {
+        self.loaded
+            && periods
+                .iter()
+                .all(|period| self.partitions.get(period).is_none_or(|p| p.loaded))
     }
-}
 
-async fn get_json<T: DeserializeOwned>(file: PathBuf) -> Result<Option<T>, JsonStoreError> {
-    let context = match read_text(file).await? {
-        Some(s) => s,
-        None => return Ok(None),
-    };
-    Ok(Some(serde_json::from_str(&context)?))
-}
+    // records in the tree, counting those of partitions not in memory
+    fn len(&self) -> usize {
+        match (&self.partition_by, &self.raw) {
+            (Some(_), _) => self.partition_of.len(),
+            (None, Some(raw)) => raw.len(),
+            (None, None) => self.data.len() + self.spill.as_ref().map_or(0, Spill::len),
+        }
+    }
 
-async fn put_json<T: Serialize + Debug>(file: PathBuf, value: &T) -> Result<(), JsonStoreError> {
-    write_text(file, serde_json::to_string(value)?).await
-}
+    fn contains(&self, seq: u64) -> bool {
+        match &self.raw {
+            Some(raw) => raw.contains_key(&seq),
+            None => self.data.contains_key(&seq) || self.spilled(seq),
+        }
+    }
 
-async fn get_sequence(file: PathBuf) -> Result<u64, JsonStoreError> {
-    let line = match read_text(file).await? {
-        Some(s) => s,
-        None => return Ok(0),
-    };
+    fn spilled(&self, seq: u64) -> bool {
+        self.spill.as_ref().is_some_and(|spill| spill.holds(seq))
+    }
 
-    let seq: u64 = line.parse().unwrap_or_default();
+    fn any_spilled(&self) -> bool {
+        self.spill.as_ref().is_some_and(|spill| spill.len() > 0)
+    }
 
-    Ok(seq)
-}
+    // the record at seq, read from the overflow file if it is there
+    async fn record(&self, seq: u64) -> Result<Option<Arc<Value>>, JsonStoreError> {
+        match (self.data.get(&seq), &self.spill) {
+            (Some(value), _) => Ok(Some(value.clone())),
+            (None, Some(spill)) => spill.read(seq).await,
+            (None, None) => Ok(None),
+        }
+    }
 
-async fn put_sequence(file: PathBuf, sequence: u64) -> Result<(), JsonStoreError> {
-    write_text(file, sequence.to_string()).await
-}
+    // Every record in sequence order, those spilled read from the file as they come, a
+    // batch at a time.
+    fn scan(&self) -> impl Stream<Item = Result<(u64, Arc<Value>), JsonStoreError>> + '_ {
+        let spilled = self.spill.iter().flat_map(Spill::sequences);
+        let mut seqs = self.data.keys().copied().chain(spilled).collect::<Vec<_>>();
+        seqs.sort_unstable();
+        let batches = seqs
+            .chunks(SCAN_BATCH)
+            .map(<[u64]>::to_vec)
+            .collect::<Vec<_>>();
+        stream::iter(batches)
+            .then(move |seqs| self.records(seqs))
+            .map_ok(|records| stream::iter(records.into_iter().map(Ok)))
+            .try_flatten()
+    }
 
-async fn read_text(file: PathBuf) -> Result<Option<String>, JsonStoreError> {
-    let file = match tokio::fs::File::open(file).await {
-        Ok(f) => f,
-        Err(e) if e.kind() == tokio::io::ErrorKind::NotFound => return Ok(None),
-        Err(e) => return Err(e.into()),
-    };
+    // the records at seqs there are, in that order
+    async fn records(&self, seqs: Vec<u64>) -> Result<Vec<(u64, Arc<Value>)>, JsonStoreError> {
+        let mut spilled = HashMap::new();
+        if let Some(spill) = &self.spill {
+            let out = seqs
+                .iter()
+                .filter(|seq| !self.data.contains_key(seq))
+                .copied()
+                .collect::<Vec<_>>();
+            spilled = spill.read_many(&out).await?;
+        }
+        Ok(seqs
+            .into_iter()
+            .filter_map(|seq| {
+                let value = self
+                    .data
+                    .get(&seq)
+                    .cloned()
+                    .or_else(|| spilled.remove(&seq));
+                Some((seq, value?))
+            })
+            .collect())
+    }
 
-    let mut reader = tokio::io::BufReader::new(file);
-    let mut context = String::new();
-    let mut buf = String::new();
-    while let Ok(size) = reader.read_line(&mut buf).await {
-        if size == 0 {
-            break;
+    // a read of the record at seq, for the order records are spilled in
+    fn touch_read(&self, seq: u64) {
+        if let Some(spill) = self.spill.as_ref().filter(|_| self.data.contains_key(&seq)) {
+            spill.touch(seq);
         }
-        context.push_str(&buf);
-        buf = String::new();
     }
 
-    Ok(Some(context))
-}
+    // bring the record at seq back into memory if it was spilled
+    async fn fault_in(&mut self, seq: u64) -> Result<(), JsonStoreError> {
+        let Some(spill) = &mut self.spill else {
+            return Ok(());
+        };
+        if spill.holds(seq) {
+            if let Some(value) = spill.read(seq).await? {
+                self.data.insert(seq, value);
+            }
+            spill.faulted_in(seq);
+        }
+        Ok(())
+    }
+
+    // bring every spilled record back into memory
+    async fn unspill(&mut self) -> Result<(), JsonStoreError> {
+        let Some(spill) = &mut self.spill else {
+            return Ok(());
+        };
+        let seqs = spill.sequences().collect::<Vec<_>>();
+        self.data.extend(spill.read_many(&seqs).await?);
+        spill.all_in();
+        Ok(())
+    }
+
+    // Spill records past the tree's resident limit, but those written since the last
+    // save if keep_unsaved, for a save to the delta file. The unique index is built
+    // first, if it has to be, so that it covers them.
+    async fn trim(&mut self, info: &Info, keep_unsaved: bool) -> Result<(), JsonStoreError> {
+        let Some(spill) = &self.spill else {
+            return Ok(());
+        };
+        if self.data.len() <= spill.limit() {
+            return Ok(());
+        }
+        if self.unique.is_none() && info.unique() {
+            let mut index = UniqueIndex::new(info);
+            {
+                let mut scan = pin!(self.scan());
+                while let Some(record) = scan.next().await {
+                    let (seq, value) = record?;
+                    index.insert(seq, &value);
+                }
+            }
+            self.unique = Some(index);
+        }
+
+        let Some(spill) = &mut self.spill else {
+            return Ok(());
+        };
+        let none = HashSet::new();
+        let keep = match keep_unsaved {
+            true => &self.unsnapshotted,
+            false => &none,
+        };
+        for seq in spill.evict(&self.data, keep).await? {
+            self.data.remove(&seq);
+        }
+        Ok(())
+    }
+
+    // Set up a tree with a resident limit from its records as read, raw, and spill:
+    // parse each one to index it, keep the last ones by sequence in memory and return
+    // the rest for Spill::spill to write out.
+    fn spill_raw(
+        &mut self,
+        info: &Info,
+        spill: Spill,
+    ) -> Result<Vec<(u64, Arc<RawValue>)>, JsonStoreError> {
+        let limit = spill.limit();
+        let mut raw = self.raw.take().unwrap_or_default();
+        self.parsed = true;
+
+        let mut seqs = raw.keys().copied().collect::<Vec<_>>();
+        seqs.sort_unstable();
+        let resident_from = seqs.len().saturating_sub(limit);
+        let mut indexes = FieldIndexes::build(info, &Records::default());
+        let mut unique = UniqueIndex::new(info);
+        let mut out = Vec::with_capacity(resident_from);
+        for (i, seq) in seqs.into_iter().enumerate() {
+            let Some(record) = raw.remove(&seq) else {
+                continue;
+            };
+            let value = raw::parse(&record)?;
+            indexes.insert(seq, &value);
+            unique.insert(seq, &value);
+            match i >= resident_from || self.unsnapshotted.contains(&seq) {
+                true => {
+                    self.data.insert(seq, Arc::new(value));
+                    spill.touch(seq);
+                }
+                false => out.push((seq, record)),
+            }
+        }
+
+        self.indexes = indexes;
+        self.unique = Some(unique);
+        self.spill = Some(spill);
+        Ok(out)
+    }
+
+    // records, their maps' spare room and the indexes over them
+    fn memory_size(&self) -> usize {
+        let parsed = self.data.capacity() * self.data.entry_size()
+            + self
+                .data
+                .values()
+                .map(|value| value_size(value))
+                .sum::<usize>();
+        let raw = self.raw.as_ref().map_or(0, |raw| {
+            raw.capacity() * size_of::<(u64, Arc<RawValue>)>()
+                + raw.values().map(|record| record.get().len()).sum::<usize>()
+        });
+        let unique = self.unique.as_ref().map_or(0, UniqueIndex::memory_size);
+        parsed + raw + self.indexes.memory_size() + unique
+    }
+
+    // whether the records fill less than fraction of the room their map has kept
+    fn sparse(&self, fraction: f64) -> bool {
+        let (len, capacity) = match &self.raw {
+            Some(raw) => (raw.len(), raw.capacity()),
+            None => (self.data.len(), self.data.capacity()),
+        };
+        capacity >= SHRINK_MIN_CAPACITY && (len as f64) < capacity as f64 * fraction
+    }
+
+    // hand back the room maps kept from records since removed
+    fn shrink(&mut self) {
+        self.data.shrink_to_fit();
+        if let Some(raw) = &mut self.raw {
+            raw.shrink_to_fit();
+        }
+        self.indexes.shrink();
+        if let Some(index) = &mut self.unique {
+            index.shrink();
+        }
+        self.unsnapshotted.shrink_to_fit();
+        self.removed_keys.shrink_to_fit();
+        self.partition_of.shrink_to_fit();
+        self.encoded.shrink_to_fit();
+    }
+
+    // a write to the record at seq, for the next save
+    fn record_changed(&mut self, seq: u64) {
+        if self.single_file() {
+            self.unsnapshotted.insert(seq);
+        }
+        self.mark = next_mark();
+        self.caches.invalidate(seq);
+        if let Some(spill) = &mut self.spill {
+            spill.changed(seq);
+        }
+    }
+
+    // The record at seq set to value, keeping the unique index in step. A Raw tree
+    // must be parsed; it takes serde_json's text of value.
+    fn put(&mut self, seq: u64, value: impl Into<Arc<Value>>) -> Option<Arc<Value>> {
+        let value = value.into();
+        if let Some(raw) = &mut self.raw {
+            raw.insert(seq, raw::to_raw(&value));
+        }
+        self.put_parsed(seq, value)
+    }
+
+    fn put_parsed(&mut self, seq: u64, value: Arc<Value>) -> Option<Arc<Value>> {
+        self.record_changed(seq);
+        let prior = self.data.insert(seq, value);
+        let value = &self.data[&seq];
+        if let Some(prior) = &prior {
+            self.indexes.remove(seq, prior);
+        }
+        self.indexes.insert(seq, value);
+        if let Some(index) = &mut self.unique {
+            if let Some(prior) = &prior {
+                index.remove(seq, prior);
+            }
+            index.insert(seq, value);
+        }
+        prior
+    }
+
+    // the record at seq removed; None for one a Raw tree holds only as text
+    fn take(&mut self, seq: u64) -> Option<Arc<Value>> {
+        if let Some(raw) = &mut self.raw {
+            raw.remove(&seq);
+        }
+        self.record_changed(seq);
+        if self.single_file() {
+            if let Some(key) = self.data.key_at(seq) {
+                self.removed_keys.insert(seq, key.to_string());
+            }
+        }
+        let prior = self.data.remove(&seq);
+        if let Some(spill) = &mut self.spill {
+            spill.removed(seq);
+        }
+        if let Some(prior) = &prior {
+            self.indexes.remove(seq, prior);
+            if let Some(index) = &mut self.unique {
+                index.remove(seq, prior);
+            }
+        }
+        prior
+    }
+
+    // the record at seq of a Raw tree set to the text record; value is the record
+    // parsed, needed if the tree is
+    fn put_raw(
+        &mut self,
+        seq: u64,
+        record: Arc<RawValue>,
+        value: Option<Value>,
+    ) -> Option<Arc<RawValue>> {
+        match value {
+            Some(value) if self.parsed => {
+                self.put_parsed(seq, Arc::new(value));
+            }
+            _ => self.record_changed(seq),
+        }
+        self.raw
+            .get_or_insert_with(HashMap::new)
+            .insert(seq, record)
+    }
+
+    // the records of the snapshot file of shard, or of the only one
+    async fn file_records(&self, shard: Option<u32>) -> Result<FileRecords, JsonStoreError> {
+        Ok(match (&self.raw, shard) {
+            (Some(raw), None) => FileRecords::Raw(shared_records(raw.iter())),
+            (Some(raw), Some(i)) => FileRecords::Raw(shard_records(raw, self.shards, i)),
+            (None, None) if self.any_spilled() => FileRecords::Raw(self.spilled_records().await?),
+            (None, shard) => {
+                let shards = self.shards as u64;
+                let include = |seq: u64| shard.is_none_or(|i| seq % shards == i as u64);
+                match self.data.by_key(include) {
+                    Some(records) => FileRecords::Keyed(records),
+                    None => FileRecords::Parsed(shared_records(
+                        self.data.iter().filter(|(seq, _)| include(**seq)),
+                    )),
+                }
+            }
+        })
+    }
+
+    // every record of a tree with some spilled as text, theirs as in the file
+    async fn spilled_records(&self) -> Result<BTreeMap<u64, Arc<RawValue>>, JsonStoreError> {
+        let mut records = BTreeMap::new();
+        for (seq, value) in self.data.iter() {
+            records.insert(*seq, raw::to_raw(value));
+        }
+        if let Some(spill) = &self.spill {
+            let seqs = spill.sequences().collect::<Vec<_>>();
+            records.extend(spill.read_raw_many(&seqs).await?);
+        }
+        Ok(records)
+    }
+
+    // Bring a Raw tree's text in line with data after that was replaced wholesale.
+    // Records whose value didn't change keep their text.
+    fn sync_raw(&mut self) {
+        let Tree { data, raw, .. } = self;
+        let Some(raw) = raw else {
+            return;
+        };
+        let mut old = std::mem::take(raw);
+        for (seq, value) in data.iter() {
+            let record = match old.remove(seq) {
+                Some(record) if raw::parse(&record).is_ok_and(|old| old == **value) => record,
+                _ => raw::to_raw(value),
+            };
+            raw.insert(*seq, record);
+        }
+    }
+
+    // the reserved range holding seq
+    fn reserved_range(&self, seq: u64) -> Option<(u64, u64)> {
+        let (first, last) = self.reserved.range(..=seq).next_back()?;
+        (seq <= *last).then_some((*first, *last))
+    }
+
+    // take seq out of the reserved ranges, once a record has it
+    fn unreserve(&mut self, seq: u64) {
+        let Some((first, last)) = self.reserved_range(seq) else {
+            return;
+        };
+        self.reserved.remove(&first);
+        if first < seq {
+            self.reserved.insert(first, seq - 1);
+        }
+        if seq < last {
+            self.reserved.insert(seq + 1, last);
+        }
+    }
+
+    fn unique_index(&mut self, info: &Info) -> &UniqueIndex {
+        self.unique
+            .get_or_insert_with(|| UniqueIndex::build(info, self.data.iter()))
+    }
+
+    // take over persisted state from a freshly read tree, keeping runtime settings
+    fn replace_contents(&mut self, other: Tree) {
+        let flush_policy = self.flush_policy;
+        let caches = std::mem::take(&mut self.caches);
+        let reserved = std::mem::take(&mut self.reserved);
+        *self = other;
+        self.flush_policy = flush_policy;
+        self.reserved = reserved;
+        caches.clear();
+        self.caches = caches;
+    }
+}
+
+type Trees = HashMap<String, Arc<RwLock<Tree>>>;
+
+// the record at sequence as a T; a Raw tree's is read from its text
+fn deserialize_record<T: DeserializeOwned>(
+    tname: &str,
+    tree: &Tree,
+    sequence: u64,
+) -> Result<T, JsonStoreError> {
+    let not_found = || JsonStoreError::SequenceNotExist(tname.to_string());
+    let result = match &tree.raw {
+        Some(raw) => serde_json::from_str(raw.get(&sequence).ok_or_else(not_found)?.get()),
+        None => T::deserialize(&**tree.data.get(&sequence).ok_or_else(not_found)?),
+    };
+    result.map_err(|source| JsonStoreError::DeserializeRecord {
+        tree: tname.to_string(),
+        sequence,
+        source,
+    })
+}
+
+// SequenceNotExist for a record that has expired, as a read sees it
+fn check_live(
+    tname: &str,
+    tree: &Tree,
+    sequence: u64,
+    expiry: Option<&Expiry>,
+) -> Result<(), JsonStoreError> {
+    match tree.data.get(&sequence) {
+        Some(value) if !expiry::live(expiry, value) => {
+            Err(JsonStoreError::SequenceNotExist(tname.to_string()))
+        }
+        _ => Ok(()),
+    }
+}
+
+// drop from sequences those of records that have expired
+async fn retain_live(
+    tree: &Tree,
+    sequences: &mut Vec<u64>,
+    expiry: Option<&Expiry>,
+) -> Result<(), JsonStoreError> {
+    if expiry.is_none() {
+        return Ok(());
+    }
+    let mut live = Vec::with_capacity(sequences.len());
+    for batch in sequences.chunks(SCAN_BATCH) {
+        let records = tree.records(batch.to_vec()).await?;
+        let records = records.into_iter().collect::<HashMap<_, _>>();
+        live.extend(batch.iter().copied().filter(|seq| {
+            records
+                .get(seq)
+                .is_none_or(|value| expiry::live(expiry, value))
+        }));
+    }
+    *sequences = live;
+    Ok(())
+}
+
+fn next_mark() -> u64 {
+    static MARKS: AtomicU64 = AtomicU64::new(0);
+    MARKS.fetch_add(1, Ordering::Relaxed)
+}
+
+#[derive(Debug, Default)]
+struct Catalog {
+    infos: HashMap<String, Info>,
+    trees: Trees,
+}
+
+#[derive(Debug)]
+struct Shared {
+    backend: Arc<dyn StorageBackend>,
+    // only held for map lookups/updates, never across an await
+    catalog: StdRwLock<Catalog>,
+    // serializes create_tree/drop_tree so infos.json is written in order
+    catalog_write: Mutex<()>,
+    record_locks: LockTable,
+    flush_policy: StdRwLock<FlushPolicy>,
+    closed: AtomicBool,
+    // set while close saves: writes are refused from then on, so none lands after it
+    closing: AtomicBool,
+    durability: Durability,
+    format: OutputFormat,
+    wal: Option<WalOptions>,
+    read_only: bool,
+    replica: bool,
+    clock: Arc<dyn Clock>,
+    codec: Codec,
+    layout: Layout,
+    // store-level user metadata, kept in meta.json
+    metadata: StdRwLock<HashMap<String, Value>>,
+    migrations: StdMutex<Vec<Migration>>,
+    corruption_policy: CorruptionPolicy,
+    load_report: Option<LoadReport>,
+    metrics: Option<Metrics>,
+    undo_depth: usize,
+    load_concurrency: usize,
+    incremental_save: Option<f64>,
+    shrink_below: Option<f64>,
+    // None where there is nowhere to spill to, see spill.rs
+    spill_dir: Option<PathBuf>,
+    write_batching: WriteBatching,
+    // by tree, see batch.rs
+    write_queues: StdMutex<HashMap<String, Arc<WriteQueue>>>,
+}
+
+// Handle to a store. Clones are cheap and share the same trees, so a store can be
+// used from several tasks at once.
+#[derive(Debug, Clone)]
+pub struct JsonStore {
+    shared: Arc<Shared>,
+}
+
+impl JsonStore {
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn create_tree(&self, tname: &str, info: Info) -> Result<(), JsonStoreError> {
+        self._metered("create_tree", Some(tname), async {
+            self._check_writable()?;
+            self._create_tree(tname, info).await
+        })
+        .await
+    }
+
+    async fn _create_tree(&self, tname: &str, mut info: Info) -> Result<(), JsonStoreError> {
+        let _guard = self.shared.catalog_write.lock().await;
+        // a new tree hasn't been synced, whatever the Info given says
+        info.sync_cursor = None;
+
+        let infos = {
+            let mut catalog = self._catalog_mut();
+
+            if catalog.infos.contains_key(tname) {
+                return Err(JsonStoreError::FoundTree(tname.to_string()));
+            }
+            let folded = layout::fold(tname);
+            if let Some(existing) = catalog
+                .infos
+                .keys()
+                .find(|name| layout::fold(name) == folded)
+            {
+                return Err(JsonStoreError::TreeNameCollision {
+                    tree: tname.to_string(),
+                    existing: existing.clone(),
+                });
+            }
+            info.validate()
+                .map_err(|source| JsonStoreError::InvalidTreeInfo {
+                    tree: tname.to_string(),
+                    source,
+                })?;
+            self.shared.layout.check_tree(tname, &info)?;
+            if info.resident_limit.is_some() && self.shared.spill_dir.is_none() {
+                return Err(spill::unavailable());
+            }
+
+            let tree = Tree::empty(0, &info);
+
+            catalog.infos.insert(tname.to_string(), info);
+            catalog
+                .trees
+                .insert(tname.to_string(), Arc::new(RwLock::new(tree)));
+
+            catalog.infos.clone()
+        };
+
+        self._put_infos(&infos).await?;
+
+        // a new tree owns its files, so overwrite whatever may be lying around
+        self.save_tree_force(tname).await?;
+
+        self.shared.backend.sync().await
+    }
+
+    // Create tname unless it exists already, in which case its Info must equal info
+    // or this fails with InfoMismatch. Metadata, the frozen flag and the sync cursor are
+    // left out of the comparison, as they change at runtime.
+    pub async fn ensure_tree(&self, tname: &str, info: Info) -> Result<(), JsonStoreError> {
+        // an invalid Info is refused even if the stored one is the same
+        info.validate()
+            .map_err(|source| JsonStoreError::InvalidTreeInfo {
+                tree: tname.to_string(),
+                source,
+            })?;
+        loop {
+            if let Ok(stored) = self._info(tname) {
+                let same = info.shape() == stored.shape();
+                return match same {
+                    true => Ok(()),
+                    false => Err(JsonStoreError::InfoMismatch {
+                        tree: tname.to_string(),
+                        stored: Box::new(stored),
+                        requested: Box::new(info),
+                    }),
+                };
+            }
+
+            match self.create_tree(tname, info.clone()).await {
+                // created concurrently; compare against that one
+                Err(JsonStoreError::FoundTree(_)) => continue,
+                result => return result,
+            }
+        }
+    }
+
+    pub async fn create_tree_for<T: StoreEntity>(&self) -> Result<(), JsonStoreError> {
+        self.ensure_tree(T::tree_name(), T::info()).await
+    }
+
+    pub async fn insert_entity<T: StoreEntity>(&self, entity: &T) -> Result<u64, JsonStoreError> {
+        self.insert(T::tree_name(), entity).await
+    }
+
+    pub async fn update_entity<T: StoreEntity>(&self, entity: &T) -> Result<(), JsonStoreError> {
+        self.update(T::tree_name(), entity).await
+    }
+
+    pub async fn delete_entity<T: StoreEntity>(&self, entity: &T) -> Result<(), JsonStoreError> {
+        match entity.sequence() {
+            Some(sequence) => self.delete(T::tree_name(), sequence).await,
+            None => Err(JsonStoreError::SequenceNotExist(T::tree_name().to_string())),
+        }
+    }
+
+    pub async fn select_entity<T: StoreEntity>(&self, sequence: u64) -> Result<T, JsonStoreError> {
+        self.select(T::tree_name(), sequence).await
+    }
+
+    // Remove tname and its files. A frozen tree is refused; drop_tree_force drops it
+    // all the same.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn drop_tree(&self, tname: &str) -> Result<(), JsonStoreError> {
+        self._metered("drop_tree", Some(tname), async {
+            self._writable_info(tname)?;
+            self._drop_tree(tname).await
+        })
+        .await
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn drop_tree_force(&self, tname: &str) -> Result<(), JsonStoreError> {
+        self._metered("drop_tree", Some(tname), async {
+            self._check_writable()?;
+            self._drop_tree(tname).await
+        })
+        .await
+    }
+
+    async fn _drop_tree(&self, tname: &str) -> Result<(), JsonStoreError> {
+        let _guard = self.shared.catalog_write.lock().await;
+
+        let (info, infos, tree) = {
+            let mut catalog = self._catalog_mut();
+
+            let Some(info) = catalog.infos.remove(tname) else {
+                return Err(JsonStoreError::NotFoundTree(tname.to_string()));
+            };
+            let tree = catalog.trees.remove(tname);
+
+            (info, catalog.infos.clone(), tree)
+        };
+        self.shared.record_locks.remove_tree(tname);
+        // cached records of the tree go with it, so handles to it find it gone
+        if let Some(tree) = tree {
+            tree.read().await.caches.clear();
+        }
+
+        let backend = &*self.shared.backend;
+        let keys = stored_tree_files(backend, &self.shared.layout, tname, &info)
+            .await
+            .unwrap_or_else(|_| tree_files(&self.shared.layout, tname, &info));
+
+        self._put_infos(&infos).await?;
+
+        for key in keys {
+            let _ = backend.delete(&key).await;
+        }
+
+        self.shared.backend.sync().await
+    }
+
+    pub async fn load(path: &Path) -> Result<Self, JsonStoreError> {
+        Self::load_with_options(path, LoadOptions::default()).await
+    }
+
+    // all open-time options of the store at path, see JsonStoreBuilder
+    pub fn builder(path: &Path) -> JsonStoreBuilder {
+        JsonStoreBuilder::new(path)
+    }
+
+    pub async fn load_with_options(
+        path: &Path,
+        options: LoadOptions,
+    ) -> Result<Self, JsonStoreError> {
+        // a read-only store creates nothing, not even its directory
+        let writable = !options.read_only;
+        prepare_store_dir(path, options.create_if_missing && writable, writable).await?;
+        if writable {
+            let layout = meta::check(&FsBackend::new(path)).await?.layout;
+            remove_stale_tmp_files(path, &layout).await?;
+        }
+
+        Self::load_with_backend(FsBackend::new(path), options).await
+    }
+
+    // Open the store at path for reading only: every change fails with ReadOnlyStore
+    // and nothing in the directory is created, repaired or rewritten.
+    pub async fn load_read_only(path: &Path) -> Result<Self, JsonStoreError> {
+        Self::load_with_options(
+            path,
+            LoadOptions {
+                read_only: true,
+                ..LoadOptions::default()
+            },
+        )
+        .await
+    }
+
+    // load the store kept in backend; load_with_options is this with an FsBackend
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(backend = ?backend)))]
+    pub async fn load_with_backend(
+        backend: impl StorageBackend + 'static,
+        options: LoadOptions,
+    ) -> Result<Self, JsonStoreError> {
+        let backend: Arc<dyn StorageBackend> = Arc::new(backend);
+
+        let meta = if options.read_only {
+            meta::check(&*backend).await?
+        } else {
+            meta::upgrade(&*backend).await?
+        };
+
+        // the codec is fixed when the store is created; convert_codec changes it
+        let stored =
+            exists(&*backend, META_FILE).await? || exists(&*backend, meta::INFOS_FILE).await?;
+        let codec = match options.codec {
+            Some(requested) if stored && requested != meta.codec => {
+                return Err(JsonStoreError::CodecMismatch {
+                    store: meta.codec,
+                    requested,
+                });
+            }
+            Some(requested) => requested,
+            None => meta.codec,
+        };
+        codec.check_available()?;
+
+        // so is the layout; relayout changes it
+        let layout = match options.layout.clone() {
+            Some(requested) if stored && requested != meta.layout => {
+                return Err(JsonStoreError::LayoutMismatch {
+                    store: Box::new(meta.layout),
+                    requested: Box::new(requested),
+                });
+            }
+            Some(requested) => requested,
+            None => meta.layout,
+        };
+        layout.validate()?;
+        let meta = Meta {
+            format_version: meta.format_version,
+            codec,
+            layout,
+            metadata: meta.metadata,
+        };
+        let layout = &meta.layout;
+
+        let mut infos = get_json::<HashMap<String, Info>>(&*backend, &layout.infos_file)
+            .await?
+            .unwrap_or(HashMap::new());
+        for (tname, info) in infos.iter_mut() {
+            info.take_legacy_version(tname)?;
+            if options.replica {
+                info.take_legacy_cursor();
+            }
+        }
+        // written before names were folded, or by hand; either tree could be the one
+        // whose files these are, so neither is loaded
+        if let Some((tree, existing)) = layout::collision(infos.keys()) {
+            return Err(JsonStoreError::TreeNameCollision {
+                tree: tree.to_string(),
+                existing: existing.to_string(),
+            });
+        }
+
+        let mut trees: Trees = HashMap::new();
+
+        let policy = options.corruption_policy;
+        let spill_dir = rt::spill_dir(options.spill_dir.clone());
+        let mut report = LoadReport::default();
+
+        // Infos edited by hand, or written before validate() existed, can break its
+        // rules. Under a lenient policy such a tree is left unread, like one whose
+        // files are corrupt, and drop_tree gets rid of it.
+        let mut invalid = infos
+            .iter()
+            .filter_map(|(key, info)| {
+                let source = info.validate().err()?;
+                Some(JsonStoreError::InvalidTreeInfo {
+                    tree: key.clone(),
+                    source,
+                })
+            })
+            .collect::<Vec<_>>();
+        invalid.sort_by(|a, b| a.tree().cmp(&b.tree()));
+        if policy == CorruptionPolicy::Fail && !invalid.is_empty() {
+            return Err(invalid.swap_remove(0));
+        }
+        for e in &invalid {
+            let key = e.tree().unwrap_or_default().to_string();
+            trace::error!(tree = key, error = %e, "skipping tree with invalid info");
+            report
+                .trees
+                .insert(key.clone(), TreeOutcome::Corrupt(e.to_string()));
+            let tree = Tree {
+                corrupt: Some(e.to_string()),
+                ..Tree::unloaded()
+            };
+            trees.insert(key, Arc::new(RwLock::new(tree)));
+        }
+
+        // a report has to say how every tree read
+        let eager = options.eager || policy == CorruptionPolicy::Report;
+        let valid = |key: &String| !invalid.iter().any(|e| e.tree() == Some(key.as_str()));
+        if !eager {
+            for key in infos.keys().filter(|key| valid(key)) {
+                trees.insert(key.clone(), Arc::new(RwLock::new(Tree::unloaded())));
+            }
+        }
+
+        // Trees are read side by side, so the load takes about as long as the largest
+        // rather than all of them. The results are gone through by name, so with
+        // several trees failing it is always the same one that fails the load.
+        let mut results = stream::iter(infos.iter().filter(|(key, _)| eager && valid(key)))
+            .map(|(key, info)| {
+                let backend = &*backend;
+                let spill_dir = spill_dir.as_deref();
+                async move {
+                    let result = read_tree(
+                        backend,
+                        layout,
+                        key,
+                        info,
+                        codec,
+                        options.read_only,
+                        spill_dir,
+                    )
+                    .await;
+                    (key, result)
+                }
+            })
+            .buffer_unordered(options.load_concurrency.max(1))
+            .collect::<Vec<_>>()
+            .await;
+        results.sort_by_key(|(key, _)| *key);
+
+        for (key, result) in results {
+            let tree = match result {
+                Ok(tree) => {
+                    if let Some(integrity) = check_loaded(key, &infos[key], &tree, policy)? {
+                        report.integrity.insert(key.clone(), integrity);
+                    }
+                    report.trees.insert(key.clone(), TreeOutcome::Loaded);
+                    tree
+                }
+                Err(e) if policy != CorruptionPolicy::Fail && is_corruption(&e) => {
+                    trace::error!(tree = key, error = %e, "skipping corrupt tree");
+                    report
+                        .trees
+                        .insert(key.clone(), TreeOutcome::Corrupt(e.to_string()));
+                    Tree {
+                        corrupt: Some(e.to_string()),
+                        ..Tree::unloaded()
+                    }
+                }
+                Err(e) => return Err(e),
+            };
+            trees.insert(key.clone(), Arc::new(RwLock::new(tree)));
+        }
+        let report = (policy == CorruptionPolicy::Report).then_some(report);
+
+        Ok(Self::new(
+            backend,
+            options,
+            meta,
+            Catalog { infos, trees },
+            report,
+        ))
+    }
+
+    // A store that never touches the filesystem: its files are kept in an
+    // InMemoryBackend and are gone with the last handle. persist_to writes them out.
+    pub fn in_memory() -> Self {
+        Self::new(
+            Arc::new(InMemoryBackend::new()),
+            LoadOptions::default(),
+            Meta::default(),
+            Catalog::default(),
+            None,
+        )
+    }
+
+    fn new(
+        backend: Arc<dyn StorageBackend>,
+        options: LoadOptions,
+        meta: Meta,
+        catalog: Catalog,
+        load_report: Option<LoadReport>,
+    ) -> Self {
+        let clock = options.clock.unwrap_or_else(|| Arc::new(SystemClock));
+        let spill_dir = rt::spill_dir(options.spill_dir);
+        let metrics = options.metrics.then(|| Metrics::new(clock.now()));
+        Self {
+            shared: Arc::new(Shared {
+                backend,
+                catalog: StdRwLock::new(catalog),
+                catalog_write: Mutex::new(()),
+                record_locks: LockTable::default(),
+                flush_policy: StdRwLock::new(FlushPolicy::default()),
+                closed: AtomicBool::new(false),
+                closing: AtomicBool::new(false),
+                durability: options.durability,
+                format: options.format,
+                wal: options.wal,
+                read_only: options.read_only,
+                replica: options.replica,
+                clock,
+                codec: meta.codec,
+                layout: meta.layout,
+                metadata: StdRwLock::new(meta.metadata),
+                migrations: StdMutex::new(Vec::new()),
+                corruption_policy: options.corruption_policy,
+                load_report,
+                metrics,
+                undo_depth: options.undo_depth,
+                load_concurrency: options.load_concurrency.max(1),
+                incremental_save: options.incremental_save,
+                shrink_below: options.shrink_below,
+                spill_dir,
+                write_batching: options.write_batching,
+                write_queues: StdMutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    // insert tree
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn insert<T: Serialize>(
+        &self,
+        tname: &str,
+        value: &T,
+    ) -> Result<u64, JsonStoreError> {
+        self._metered("insert", Some(tname), async {
+            let info = self._writable_info(tname)?;
+
+            let mut json_value = serde_json::to_value(value)?;
+            key::assign(tname, &info, &mut json_value)?;
+
+            if self._batches(tname, &info, None) {
+                return self
+                    ._batched(tname, info, BatchOp::Insert(json_value, None))
+                    .await;
+            }
+            self._insert(tname, &info, json_value, None).await
+        })
+        .await
+    }
+
+    // Insert value at sequence, which allocate_sequences must have reserved and no
+    // record taken yet; the tree's counter stays where the reservation left it.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, sequence = sequence)))]
+    pub async fn insert_reserved<T: Serialize>(
+        &self,
+        tname: &str,
+        sequence: u64,
+        value: &T,
+    ) -> Result<(), JsonStoreError> {
+        self._metered("insert", Some(tname), async {
+            let info = self._writable_info(tname)?;
+
+            let mut json_value = serde_json::to_value(value)?;
+            key::assign(tname, &info, &mut json_value)?;
+
+            if self._batches(tname, &info, None) {
+                self._batched(tname, info, BatchOp::Insert(json_value, Some(sequence)))
+                    .await?;
+                return Ok(());
+            }
+            self._insert(tname, &info, json_value, Some(sequence))
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    // Reserve the next n sequences of tname, for records to be inserted later with
+    // insert_reserved, so they can be referred to beforehand. The counter is written
+    // before this returns, as durably as a save, so no later insert or allocation
+    // gets them, not even after a crash. Reservations are kept in memory, so those
+    // not used by a reload are left as gaps.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, n = n)))]
+    pub async fn allocate_sequences(
+        &self,
+        tname: &str,
+        n: u64,
+    ) -> Result<RangeInclusive<u64>, JsonStoreError> {
+        self._metered("allocate_sequences", Some(tname), async {
+            self._writable_info(tname)?;
+
+            let mut tree = self._write_lock(tname).await?;
+
+            let first = tree.sequence + 1;
+            if n == 0 {
+                return Ok(first..=tree.sequence);
+            }
+            let last = tree
+                .sequence
+                .checked_add(n)
+                .ok_or_else(|| JsonStoreError::CapacityExceeded(tname.to_string()))?;
+
+            let backend = &*self.shared.backend;
+            let key = self.shared.layout.seq_key(tname);
+            put_sequence(backend, &key, last, self.shared.durability).await?;
+            tree.seq_stamp = backend.stamp(&key).await?;
+            tree.sequence = last;
+            tree.reserved.insert(first, last);
+
+            trace::debug!(tree = tname, first, last, "allocated sequences");
+
+            Ok(first..=last)
+        })
+        .await
+    }
+
+    // insert, at the next sequence or a reserved one
+    async fn _insert(
+        &self,
+        tname: &str,
+        info: &Info,
+        json_value: Value,
+        reserved: Option<u64>,
+    ) -> Result<u64, JsonStoreError> {
+        // of a partitioned tree, only the partition the record goes to is needed
+        let period = info
+            .partition_by
+            .as_ref()
+            .map(|spec| spec.period(&json_value));
+        let mut tree = match info.resident_limit {
+            Some(_) => self._write_lock_unparsed(tname).await?,
+            None => {
+                self._write_lock_periods(tname, |_| period.iter().cloned().collect())
+                    .await?
+            }
+        };
+
+        self._insert_locked(tname, info, &mut tree, json_value, reserved)
+            .await
+    }
+
+    // insert's checks and write, with tree locked and the record's partition loaded
+    async fn _insert_locked(
+        &self,
+        tname: &str,
+        info: &Info,
+        tree: &mut Tree,
+        mut json_value: Value,
+        reserved: Option<u64>,
+    ) -> Result<u64, JsonStoreError> {
+        if tree.len() >= info.capacity as usize {
+            return Err(JsonStoreError::CapacityExceeded(tname.to_string()));
+        }
+
+        let seq = match reserved {
+            Some(seq) if tree.reserved_range(seq).is_none() => {
+                return Err(JsonStoreError::SequenceNotReserved {
+                    tree: tname.to_string(),
+                    sequence: seq,
+                });
+            }
+            Some(seq) => seq,
+            None => tree.sequence + 1,
+        };
+
+        let mut prior = self
+            ._clear_unique(tname, info, tree, &json_value, None)
+            .await?;
+
+        if json_value[info.sequence_field.clone()].is_null() {
+            json_value
+                .as_object_mut()
+                .ok_or(JsonStoreError::UnObjectValue)?
+                .insert(info.sequence_field.clone(), serde_json::to_value(seq)?);
+        } else {
+            *json_value
+                .get_mut(info.sequence_field.clone())
+                .ok_or(JsonStoreError::UnableToMutValue(tname.to_string()))? =
+                serde_json::to_value(seq)?;
+        }
+
+        self._log(
+            tname,
+            tree,
+            &WalEntry::Insert {
+                tree: tname.to_string(),
+                seq,
+                value: &json_value,
+            },
+        )
+        .await?;
+
+        match reserved {
+            Some(_) => tree.unreserve(seq),
+            None => tree.sequence = seq,
+        }
+        tree.put(seq, json_value);
+        tree.touch(seq);
+        prior.push((seq, None));
+        self._push_undo(tree, UndoOp::Insert, prior);
+
+        self._written(tname, tree).await?;
+
+        Ok(seq)
+    }
+
+    // update tree
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn update<T: Serialize>(&self, tname: &str, value: &T) -> Result<(), JsonStoreError> {
+        self._metered("update", Some(tname), async {
+            self._update(tname, value, None).await
+        })
+        .await
+    }
+
+    // update tree on behalf of owner, who may hold the record lock
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, owner = owner)))]
+    pub async fn update_as<T: Serialize>(
+        &self,
+        tname: &str,
+        value: &T,
+        owner: &str,
+    ) -> Result<(), JsonStoreError> {
+        self._metered("update", Some(tname), async {
+            self._update(tname, value, Some(owner)).await
+        })
+        .await
+    }
+
+    async fn _update<T: Serialize>(
+        &self,
+        tname: &str,
+        value: &T,
+        owner: Option<&str>,
+    ) -> Result<(), JsonStoreError> {
+        let info = self._writable_info(tname)?;
+
+        let json_value = serde_json::to_value(value)?;
+
+        let seq = match json_value[info.sequence_field.clone()].as_u64() {
+            Some(n) => n,
+            None => return Err(JsonStoreError::SequenceNotExist(tname.to_string())),
+        };
+
+        if self._batches(tname, &info, owner) {
+            self._batched(tname, info, BatchOp::Update(seq, json_value))
+                .await?;
+            return Ok(());
+        }
+
+        // the record's partition, and the one it moves to if its time changed
+        let period = info
+            .partition_by
+            .as_ref()
+            .map(|spec| spec.period(&json_value));
+        let mut tree = match info.resident_limit {
+            Some(_) => self._write_lock_record(tname, seq).await?,
+            None => {
+                self._write_lock_periods(tname, |tree| {
+                    let current = tree.partition_of.get(&seq).cloned();
+                    current.into_iter().chain(period.clone()).collect()
+                })
+                .await?
+            }
+        };
+
+        self._update_locked(tname, &info, &mut tree, seq, json_value, owner)
+            .await
+    }
+
+    // update's checks and write, with tree locked and the record at seq in memory
+    async fn _update_locked(
+        &self,
+        tname: &str,
+        info: &Info,
+        tree: &mut Tree,
+        seq: u64,
+        json_value: Value,
+        owner: Option<&str>,
+    ) -> Result<(), JsonStoreError> {
+        let Some(prior) = tree.data.get(&seq) else {
+            return Err(JsonStoreError::SequenceNotExist(tname.to_string()));
+        };
+        key::check_unchanged(tname, info, prior, &json_value)?;
+
+        self.shared.record_locks.check(tname, seq, owner)?;
+
+        let mut purged = self
+            ._clear_unique(tname, info, tree, &json_value, Some(seq))
+            .await?;
+
+        self._log(
+            tname,
+            tree,
+            &WalEntry::Update {
+                tree: tname.to_string(),
+                seq,
+                value: &json_value,
+            },
+        )
+        .await?;
+
+        if let Some(prior) = tree.put(seq, json_value) {
+            purged.push((seq, Some(prior.clone())));
+            self._push_undo(tree, UndoOp::Update, purged);
+            self._record_history(info, tree, seq, prior, HistoryOp::Update);
+        }
+        tree.touch(seq);
+
+        self._written(tname, tree).await?;
+
+        Ok(())
+    }
+
+    // Change the record at sequence by a JSON merge patch (RFC 7396): each field of
+    // patch replaces the record's, null removes it, and an object is merged into the
+    // one it replaces field by field. The sequence field is left as it is. Read and
+    // written under one lock, so no other write lands in between. Returns the record
+    // as written.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, sequence = sequence)))]
+    pub async fn merge_patch(
+        &self,
+        tname: &str,
+        sequence: u64,
+        patch: &Value,
+    ) -> Result<Value, JsonStoreError> {
+        self._metered("merge_patch", Some(tname), async {
+            let info = self._writable_info(tname)?;
+            if !patch.is_object() {
+                return Err(JsonStoreError::UnObjectValue);
+            }
+
+            let mut tree = match info.resident_limit {
+                Some(_) => self._write_lock_record(tname, sequence).await?,
+                None => self._write_lock(tname).await?,
+            };
+            let mut value = match tree.data.get(&sequence) {
+                Some(value) => Value::clone(value),
+                None => return Err(JsonStoreError::SequenceNotExist(tname.to_string())),
+            };
+            merge::apply_patch(&mut value, patch);
+            value[info.sequence_field.as_str()] = sequence.into();
+
+            self._update_locked(tname, &info, &mut tree, sequence, value.clone(), None)
+                .await?;
+            Ok(value)
+        })
+        .await
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, sequence = sequence)))]
+    pub async fn delete(&self, tname: &str, sequence: u64) -> Result<(), JsonStoreError> {
+        self._metered("delete", Some(tname), async {
+            self._delete(tname, sequence, None).await
+        })
+        .await
+    }
+
+    // delete on behalf of owner, who may hold the record lock
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, sequence = sequence, owner = owner)))]
+    pub async fn delete_as(
+        &self,
+        tname: &str,
+        sequence: u64,
+        owner: &str,
+    ) -> Result<(), JsonStoreError> {
+        self._metered("delete", Some(tname), async {
+            self._delete(tname, sequence, Some(owner)).await
+        })
+        .await
+    }
+
+    async fn _delete(
+        &self,
+        tname: &str,
+        sequence: u64,
+        owner: Option<&str>,
+    ) -> Result<(), JsonStoreError> {
+        let info = self._writable_info(tname)?;
+
+        if self._batches(tname, &info, owner) {
+            self._batched(tname, info, BatchOp::Delete(sequence))
+                .await?;
+            return Ok(());
+        }
+
+        let mut tree = match info.value_mode {
+            ValueMode::Raw => self._write_lock_unparsed(tname).await?,
+            ValueMode::Parsed if info.resident_limit.is_some() => {
+                self._write_lock_record(tname, sequence).await?
+            }
+            ValueMode::Parsed => {
+                self._write_lock_periods(tname, |tree| {
+                    tree.partition_of
+                        .get(&sequence)
+                        .cloned()
+                        .into_iter()
+                        .collect()
+                })
+                .await?
+            }
+        };
+
+        self._delete_locked(tname, &info, &mut tree, sequence, owner)
+            .await
+    }
+
+    // delete's checks and write, with tree locked and the record at sequence in memory
+    async fn _delete_locked(
+        &self,
+        tname: &str,
+        info: &Info,
+        tree: &mut Tree,
+        sequence: u64,
+        owner: Option<&str>,
+    ) -> Result<(), JsonStoreError> {
+        if !tree.contains(sequence) {
+            return Err(JsonStoreError::SequenceNotExist(tname.to_string()));
+        }
+
+        self.shared.record_locks.check(tname, sequence, owner)?;
+        let raw_prior = match tree.parsed {
+            true => None,
+            false => self._raw_prior(info, tree, sequence)?,
+        };
+
+        self._log(
+            tname,
+            tree,
+            &WalEntry::Delete {
+                tree: tname.to_string(),
+                seq: sequence,
+            },
+        )
+        .await?;
+
+        if let Some(prior) = tree.take(sequence).or(raw_prior) {
+            self._push_undo(tree, UndoOp::Delete, vec![(sequence, Some(prior.clone()))]);
+            self._record_history(info, tree, sequence, prior, HistoryOp::Delete);
+        }
+        tree.touch(sequence);
+        self.shared.record_locks.remove(tname, sequence);
+
+        self._written(tname, tree).await?;
+
+        Ok(())
+    }
+
+    // The sequence of tname's record with key, in a keyed tree (see key.rs). A
+    // record's key never changes and sequences aren't handed out twice, so it names
+    // the same record for as long as that is there.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, key = key)))]
+    pub async fn sequence_of_key(&self, tname: &str, key: &str) -> Result<u64, JsonStoreError> {
+        self._metered("sequence_of_key", Some(tname), async {
+            let info = self._info(tname)?;
+            let fields = key::parse(tname, &info, key)?;
+            self._sequence_of_key(tname, &info, &fields).await
+        })
+        .await
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, key = key)))]
+    pub async fn select_by_key<T: DeserializeOwned>(
+        &self,
+        tname: &str,
+        key: &str,
+    ) -> Result<T, JsonStoreError> {
+        self._metered("select_by_key", Some(tname), async {
+            let info = self._info(tname)?;
+            let fields = key::parse(tname, &info, key)?;
+            self._select_by_key(tname, &info, &fields).await
+        })
+        .await
+    }
+
+    // select_by_key with the key given as an object holding the key fields, such as
+    // {"product": 17, "region": "eu"}; other fields are ignored
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn select_by_key_fields<T: DeserializeOwned>(
+        &self,
+        tname: &str,
+        fields: &Value,
+    ) -> Result<T, JsonStoreError> {
+        self._metered("select_by_key", Some(tname), async {
+            let info = self._info(tname)?;
+            let fields = key::project(tname, &info, fields)?;
+            self._select_by_key(tname, &info, &fields).await
+        })
+        .await
+    }
+
+    // Replace the record with key by value, which may leave the key fields out but
+    // can't change them. Its sequence field is filled in.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, key = key)))]
+    pub async fn update_by_key<T: Serialize>(
+        &self,
+        tname: &str,
+        key: &str,
+        value: &T,
+    ) -> Result<(), JsonStoreError> {
+        self._metered("update_by_key", Some(tname), async {
+            let info = self._info(tname)?;
+            let fields = key::parse(tname, &info, key)?;
+            self._update_by_key(tname, &info, fields, value).await
+        })
+        .await
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn update_by_key_fields<T: Serialize>(
+        &self,
+        tname: &str,
+        fields: &Value,
+        value: &T,
+    ) -> Result<(), JsonStoreError> {
+        self._metered("update_by_key", Some(tname), async {
+            let info = self._info(tname)?;
+            let fields = key::project(tname, &info, fields)?;
+            self._update_by_key(tname, &info, fields, value).await
+        })
+        .await
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, key = key)))]
+    pub async fn delete_by_key(&self, tname: &str, key: &str) -> Result<(), JsonStoreError> {
+        self._metered("delete_by_key", Some(tname), async {
+            let info = self._info(tname)?;
+            let fields = key::parse(tname, &info, key)?;
+            let sequence = self._sequence_of_key(tname, &info, &fields).await?;
+            self._delete(tname, sequence, None).await
+        })
+        .await
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn delete_by_key_fields(
+        &self,
+        tname: &str,
+        fields: &Value,
+    ) -> Result<(), JsonStoreError> {
+        self._metered("delete_by_key", Some(tname), async {
+            let info = self._info(tname)?;
+            let fields = key::project(tname, &info, fields)?;
+            let sequence = self._sequence_of_key(tname, &info, &fields).await?;
+            self._delete(tname, sequence, None).await
+        })
+        .await
+    }
+
+    async fn _select_by_key<T: DeserializeOwned>(
+        &self,
+        tname: &str,
+        info: &Info,
+        fields: &Value,
+    ) -> Result<T, JsonStoreError> {
+        let sequence = self._sequence_of_key(tname, info, fields).await?;
+        let expiry = self._expiry(info);
+        let tree = self._read_lock_record(tname, sequence).await?;
+        check_live(tname, &tree, sequence, expiry.as_ref()).map_err(|_| {
+            JsonStoreError::KeyNotExist {
+                tree: tname.to_string(),
+                key: info.key.key_of(fields).unwrap_or_default(),
+            }
+        })?;
+        deserialize_record(tname, &tree, sequence)
+    }
+
+    async fn _update_by_key<T: Serialize>(
+        &self,
+        tname: &str,
+        info: &Info,
+        fields: Value,
+        value: &T,
+    ) -> Result<(), JsonStoreError> {
+        let mut json_value = serde_json::to_value(value)?;
+        let sequence = self._sequence_of_key(tname, info, &fields).await?;
+
+        let record = json_value
+            .as_object_mut()
+            .ok_or(JsonStoreError::UnObjectValue)?;
+        record.insert(info.sequence_field.clone(), sequence.into());
+        if let Value::Object(fields) = fields {
+            for (field, value) in fields {
+                record.entry(field).or_insert(value);
+            }
+        }
+
+        self._update(tname, &json_value, None).await
+    }
+
+    // the sequence of the record with the key in fields, the key fields of a keyed tree
+    async fn _sequence_of_key(
+        &self,
+        tname: &str,
+        info: &Info,
+        fields: &Value,
+    ) -> Result<u64, JsonStoreError> {
+        let key = info.key.key_of(fields).unwrap_or_default();
+        let tree = self._read_lock(tname).await?;
+        tree.data
+            .sequence_of(&key)
+            .ok_or_else(|| JsonStoreError::KeyNotExist {
+                tree: tname.to_string(),
+                key,
+            })
+    }
+
+    // The sequence of the record candidate would clash with under tname's unique
+    // constraint called constraint, or None if its values are free. candidate is the
+    // record about to be written or just the constraint's fields, read as insert and
+    // update read them: a missing field counts as null, and an expired record (see
+    // expiry.rs) holds no values. Nothing is locked past the call, so a write in
+    // between can still take them.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, constraint = constraint)))]
+    pub async fn check_unique(
+        &self,
+        tname: &str,
+        constraint: &str,
+        candidate: &Value,
+    ) -> Result<Option<u64>, JsonStoreError> {
+        self._metered("check_unique", Some(tname), async {
+            let info = self._info(tname)?;
+            if !info.unique_fields.contains_key(constraint) {
+                return Err(JsonStoreError::UniqueConstraintNotFound {
+                    tree: tname.to_string(),
+                    constraint: constraint.to_string(),
+                });
+            }
+            if !candidate.is_object() {
+                return Err(JsonStoreError::UnObjectValue);
+            }
+            let expiry = self._expiry(&info);
+
+            loop {
+                let tree = self._read_lock(tname).await?;
+                if let Some(index) = &tree.unique {
+                    let Some(owner) = index.constraint_owner(constraint, candidate) else {
+                        return Ok(None);
+                    };
+                    return match tree.record(owner).await? {
+                        Some(record) if !expiry::live(expiry.as_ref(), &record) => Ok(None),
+                        _ => Ok(Some(owner)),
+                    };
+                }
+                drop(tree);
+                // building the index takes the write lock, once per load
+                self._write_lock(tname).await?.unique_index(&info);
+            }
+        })
+        .await
+    }
+
+    // Insert a record given as JSON text, an object. The new sequence is written into
+    // the text, which a Raw tree then keeps as it is, without parsing the rest of it
+    // (see raw.rs); any other tree takes the record as insert would.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn insert_raw(&self, tname: &str, document: &str) -> Result<u64, JsonStoreError> {
+        if self._info(tname)?.value_mode == ValueMode::Parsed {
+            let value = serde_json::from_str::<Value>(document)?;
+            return self.insert(tname, &value).await;
+        }
+
+        self._metered("insert_raw", Some(tname), async {
+            let info = self._writable_info(tname)?;
+
+            let mut tree = self._write_lock_unparsed(tname).await?;
+            if info.indexed() {
+                self._parse(tname, &mut tree).await?;
+            }
+
+            if tree.len() >= info.capacity as usize {
+                return Err(JsonStoreError::CapacityExceeded(tname.to_string()));
+            }
+
+            let seq = tree.sequence + 1;
+            let record = Arc::from(raw::with_sequence(document, &info.sequence_field, seq)?);
+            let value = match tree.parsed {
+                true => Some(raw::parse(&record)?),
+                false => None,
+            };
+
+            if let Some(value) = &value {
+                if tree.unique_index(&info).conflict(value, None).is_some() {
+                    return Err(JsonStoreError::DuplicateUniqueFields(tname.to_string()));
+                }
+            }
+
+            self._log_raw(
+                tname,
+                &mut tree,
+                &WalEntry::Insert {
+                    tree: tname.to_string(),
+                    seq,
+                    value: &*record,
+                },
+            )
+            .await?;
+
+            tree.sequence = seq;
+            tree.put_raw(seq, record, value);
+            tree.touch(seq);
+            self._push_undo(&mut tree, UndoOp::Insert, vec![(seq, None)]);
+
+            self._written(tname, &mut tree).await?;
+
+            Ok(seq)
+        })
+        .await
+    }
+
+    // Replace the record named by the sequence field of document, JSON text, with it.
+    // A Raw tree keeps the text as it is; any other tree takes it as update would.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn update_raw(&self, tname: &str, document: &str) -> Result<(), JsonStoreError> {
+        if self._info(tname)?.value_mode == ValueMode::Parsed {
+            let value = serde_json::from_str::<Value>(document)?;
+            return self.update(tname, &value).await;
+        }
+
+        self._metered("update_raw", Some(tname), async {
+            let info = self._writable_info(tname)?;
+
+            let seq = raw::sequence(document, &info.sequence_field)?
+                .ok_or(JsonStoreError::SequenceNotExist(tname.to_string()))?;
+            let record = Arc::from(RawValue::from_string(document.to_string())?);
+
+            let mut tree = self._write_lock_unparsed(tname).await?;
+            if info.indexed() {
+                self._parse(tname, &mut tree).await?;
+            }
+
+            if !tree.contains(seq) {
+                return Err(JsonStoreError::SequenceNotExist(tname.to_string()));
+            }
+
+            self.shared.record_locks.check(tname, seq, None)?;
+
+            let value = match tree.parsed {
+                true => Some(raw::parse(&record)?),
+                false => None,
+            };
+            if let Some(value) = &value {
+                if tree
+                    .unique_index(&info)
+                    .conflict(value, Some(seq))
+                    .is_some()
+                {
+                    return Err(JsonStoreError::DuplicateUniqueFields(tname.to_string()));
+                }
+            }
+            let prior = match tree.data.get(&seq) {
+                Some(prior) => Some(prior.clone()),
+                None => self._raw_prior(&info, &tree, seq)?,
+            };
+
+            self._log_raw(
+                tname,
+                &mut tree,
+                &WalEntry::Update {
+                    tree: tname.to_string(),
+                    seq,
+                    value: &*record,
+                },
+            )
+            .await?;
+
+            tree.put_raw(seq, record, value);
+            if let Some(prior) = prior {
+                self._push_undo(&mut tree, UndoOp::Update, vec![(seq, Some(prior.clone()))]);
+                self._record_history(&info, &mut tree, seq, prior, HistoryOp::Update);
+            }
+            tree.touch(seq);
+
+            self._written(tname, &mut tree).await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    // a Raw tree's record at seq parsed for undo_last and history, if either keeps it
+    fn _raw_prior(
+        &self,
+        info: &Info,
+        tree: &Tree,
+        seq: u64,
+    ) -> Result<Option<Arc<Value>>, JsonStoreError> {
+        if self.shared.undo_depth == 0 && info.history.is_none() {
+            return Ok(None);
+        }
+        match tree.raw.as_ref().and_then(|raw| raw.get(&seq)) {
+            Some(record) => Ok(Some(Arc::new(raw::parse(record)?))),
+            None => Ok(None),
+        }
+    }
+
+    // prior versions of record sequence, newest first; empty for trees without history
+    pub async fn history(
+        &self,
+        tname: &str,
+        sequence: u64,
+    ) -> Result<Vec<HistoryEntry>, JsonStoreError> {
+        let tree = self._read_lock(tname).await?;
+
+        let mut versions = tree.history.get(&sequence).cloned().unwrap_or_default();
+        versions.reverse();
+        Ok(versions)
+    }
+
+    // Put back the version at index in history(tname, sequence), re-creating the
+    // record if it was deleted. Constraints are checked as for any write, and the
+    // version replaced goes into the history in turn.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, sequence = sequence)))]
+    pub async fn revert(
+        &self,
+        tname: &str,
+        sequence: u64,
+        index: usize,
+    ) -> Result<(), JsonStoreError> {
+        self._metered("revert", Some(tname), async {
+            let info = self._writable_info(tname)?;
+
+            let mut tree = self._write_lock(tname).await?;
+
+            let value = tree
+                .history
+                .get(&sequence)
+                .and_then(|versions| versions.iter().rev().nth(index))
+                .map(|entry| entry.value.clone())
+                .ok_or(JsonStoreError::HistoryNotFound {
+                    tree: tname.to_string(),
+                    sequence,
+                    index,
+                })?;
+
+            let exists = tree.data.contains_key(&sequence);
+            if exists {
+                self.shared.record_locks.check(tname, sequence, None)?;
+            } else if tree.data.len() >= info.capacity as usize {
+                return Err(JsonStoreError::CapacityExceeded(tname.to_string()));
+            }
+
+            if tree
+                .unique_index(&info)
+                .conflict(&value, Some(sequence))
+                .is_some()
+            {
+                return Err(JsonStoreError::DuplicateUniqueFields(tname.to_string()));
+            }
+
+            let entry = match exists {
+                true => WalEntry::Update {
+                    tree: tname.to_string(),
+                    seq: sequence,
+                    value: &value,
+                },
+                false => WalEntry::Insert {
+                    tree: tname.to_string(),
+                    seq: sequence,
+                    value: &value,
+                },
+            };
+            self._log(tname, &mut tree, &entry).await?;
+
+            let prior = tree.put(sequence, value);
+            self._push_undo(&mut tree, UndoOp::Revert, vec![(sequence, prior.clone())]);
+            if let Some(prior) = prior {
+                self._record_history(&info, &mut tree, sequence, prior, HistoryOp::Revert);
+            }
+            tree.touch(sequence);
+
+            self._written(tname, &mut tree).await
+        })
+        .await
+    }
+
+    fn _record_history(
+        &self,
+        info: &Info,
+        tree: &mut Tree,
+        seq: u64,
+        prior: Arc<Value>,
+        op: HistoryOp,
+    ) {
+        let Some(config) = info.history else {
+            return;
+        };
+
+        let entry = HistoryEntry {
+            timestamp: self.shared.clock.now(),
+            op,
+            value: Arc::unwrap_or_clone(prior),
+        };
+        history::record(&mut tree.history, seq, entry, config);
+        tree.history_changed = true;
+    }
+
+    // Take back the tree's most recent insert, update, delete, revert or import, all
+    // of it or nothing. Records put back are checked against the tree as it is now,
+    // so an undo that would break a constraint fails and stays on the stack.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn undo_last(&self, tname: &str) -> Result<UndoInfo, JsonStoreError> {
+        self._metered("undo", Some(tname), async {
+            let info = self._writable_info(tname)?;
+
+            let mut tree = self._write_lock(tname).await?;
+
+            let Some(entry) = tree.undo.back().cloned() else {
+                return Err(JsonStoreError::NothingToUndo(tname.to_string()));
+            };
+
+            let conflict = |sequence, reason: &str| JsonStoreError::UndoConflict {
+                tree: tname.to_string(),
+                sequence,
+                reason: reason.to_string(),
+            };
+            let touched: HashSet<u64> = entry.prior.iter().map(|(seq, _)| *seq).collect();
+            let untouched = tree
+                .data
+                .keys()
+                .filter(|seq| !touched.contains(seq))
+                .count();
+            let restored = entry
+                .prior
+                .iter()
+                .filter_map(|(seq, value)| value.as_ref().map(|value| (seq, value)));
+
+            for (seq, _) in entry.prior.iter() {
+                if tree.data.contains_key(seq) {
+                    self.shared.record_locks.check(tname, *seq, None)?;
+                }
+            }
+            if untouched + restored.clone().count() > info.capacity as usize {
+                return Err(JsonStoreError::CapacityExceeded(tname.to_string()));
+            }
+            // against the records staying as they are, then against each other
+            let index = tree.unique_index(&info);
+            let mut batch = UniqueIndex::new(&info);
+            for (seq, value) in restored {
+                if index.owners(value).any(|owner| !touched.contains(&owner))
+                    || batch.conflict(value, None).is_some()
+                {
+                    return Err(conflict(*seq, "unique fields already exist"));
+                }
+                batch.insert(*seq, value);
+            }
+
+            for (seq, value) in entry.prior.iter().rev() {
+                let wal_entry = match (value.as_deref(), tree.data.contains_key(seq)) {
+                    (Some(value), true) => WalEntry::Update {
+                        tree: tname.to_string(),
+                        seq: *seq,
+                        value,
+                    },
+                    (Some(value), false) => WalEntry::Insert {
+                        tree: tname.to_string(),
+                        seq: *seq,
+                        value,
+                    },
+                    (None, true) => WalEntry::Delete {
+                        tree: tname.to_string(),
+                        seq: *seq,
+                    },
+                    (None, false) => continue,
+                };
+                self._log(tname, &mut tree, &wal_entry).await?;
+
+                let current = match value {
+                    Some(value) => tree.put(*seq, value.clone()),
+                    None => {
+                        self.shared.record_locks.remove(tname, *seq);
+                        tree.take(*seq)
+                    }
+                };
+                if let Some(current) = current {
+                    self._record_history(&info, &mut tree, *seq, current, HistoryOp::Undo);
+                }
+                tree.touch(*seq);
+            }
+            tree.undo.pop_back();
+
+            self._written(tname, &mut tree).await?;
+
+            Ok(UndoInfo {
+                op: entry.op,
+                sequences: entry.prior.iter().map(|(seq, _)| *seq).collect(),
+            })
+        })
+        .await
+    }
+
+    fn _push_undo(&self, tree: &mut Tree, op: UndoOp, prior: Vec<(u64, Option<Arc<Value>>)>) {
+        undo::push(
+            &mut tree.undo,
+            UndoEntry { op, prior },
+            self.shared.undo_depth,
+        );
+    }
+
+    // Reserve a record for owner until ttl passes or the returned guard is dropped.
+    // While held, update/delete by anyone else fail with RecordLocked; use
+    // update_as/delete_as to write as the owner. Locks are advisory and in-memory
+    // only: they are not persisted and do not survive a reload.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, sequence = sequence, owner = owner)))]
+    pub async fn lock_record(
+        &self,
+        tname: &str,
+        sequence: u64,
+        owner: &str,
+        ttl: Duration,
+    ) -> Result<RecordLock, JsonStoreError> {
+        self._metered("lock_record", Some(tname), async {
+            let tree = self._read_lock_record(tname, sequence).await?;
+
+            if !tree.contains(sequence) {
+                return Err(JsonStoreError::SequenceNotExist(tname.to_string()));
+            }
+
+            self.shared
+                .record_locks
+                .acquire(tname, sequence, owner, ttl)
+        })
+        .await
+    }
+
+    // release owner's lock on a record; fails if someone else holds it
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, sequence = sequence, owner = owner)))]
+    pub async fn unlock_record(
+        &self,
+        tname: &str,
+        sequence: u64,
+        owner: &str,
+    ) -> Result<(), JsonStoreError> {
+        self._metered("unlock_record", Some(tname), async {
+            self._tree(tname)?;
+
+            self.shared.record_locks.release(tname, sequence, owner)
+        })
+        .await
+    }
+
+    // start a session that tracks which trees its operations dirty
+    pub fn session(&self) -> Session<'_> {
+        Session::new(self)
+    }
+
+    // Set key in tname's Info::metadata and write the catalog right away. A Null value
+    // removes the key. "migration_version" is refused: older catalogs kept the tree's
+    // migration version under it, and load still reads it as that.
+    pub async fn set_tree_metadata(
+        &self,
+        tname: &str,
+        key: &str,
+        value: Value,
+    ) -> Result<(), JsonStoreError> {
+        self._check_writable()?;
+        if key == migrations::LEGACY_VERSION_KEY {
+            return Err(JsonStoreError::InvalidTreeInfo {
+                tree: tname.to_string(),
+                source: InfoValidationError::ReservedMetadataKey {
+                    key: key.to_string(),
+                },
+            });
+        }
+
+        let _guard = self.shared.catalog_write.lock().await;
+
+        let infos = {
+            let mut catalog = self._catalog_mut();
+            let info = catalog
+                .infos
+                .get_mut(tname)
+                .ok_or(JsonStoreError::NotFoundTree(tname.to_string()))?;
+            match value {
+                Value::Null => info.metadata.remove(key),
+                value => info.metadata.insert(key.to_string(), value),
+            };
+            catalog.infos.clone()
+        };
+        self._put_infos(&infos).await?;
+
+        self.shared.backend.sync().await
+    }
+
+    // Change tname's capacity and write the catalog right away. Records over a lowered
+    // capacity stay, refusing inserts, until prune_to_capacity sheds them.
+    pub async fn set_tree_capacity(
+        &self,
+        tname: &str,
+        capacity: u32,
+    ) -> Result<(), JsonStoreError> {
+        self._writable_info(tname)?;
+        if capacity == 0 {
+            return Err(JsonStoreError::InvalidTreeInfo {
+                tree: tname.to_string(),
+                source: InfoValidationError::ZeroCapacity,
+            });
+        }
+
+        let _guard = self.shared.catalog_write.lock().await;
+
+        let infos = {
+            let mut catalog = self._catalog_mut();
+            catalog
+                .infos
+                .get_mut(tname)
+                .ok_or(JsonStoreError::NotFoundTree(tname.to_string()))?
+                .capacity = capacity;
+            catalog.infos.clone()
+        };
+        self._put_infos(&infos).await?;
+
+        self.shared.backend.sync().await
+    }
+
+    // Seal tname against change, after a migration say: every write to its records
+    // or Info fails with TreeFrozen, as do imports, merges into it, undo, vacuum and
+    // drop_tree, while reads, exports, indexes and metadata work as before. Changes
+    // not yet saved are saved first, and writes already holding the tree finish. The
+    // flag is kept in the catalog, so it holds across loads until unfreeze_tree.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn freeze_tree(&self, tname: &str) -> Result<(), JsonStoreError> {
+        self._metered("freeze_tree", Some(tname), async {
+            self._set_frozen(tname, true).await
+        })
+        .await
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn unfreeze_tree(&self, tname: &str) -> Result<(), JsonStoreError> {
+        self._metered("unfreeze_tree", Some(tname), async {
+            self._set_frozen(tname, false).await
+        })
+        .await
+    }
+
+    async fn _set_frozen(&self, tname: &str, frozen: bool) -> Result<(), JsonStoreError> {
+        self._check_writable()?;
+
+        // catalog before tree, the order reshard_tree takes them in
+        let _guard = self.shared.catalog_write.lock().await;
+        let mut tree = self._write_lock_raw(tname).await?;
+        if frozen && tree.changed {
+            self._save_locked(tname, &mut tree, self.shared.durability)
+                .await?;
+        }
+
+        let infos = {
+            let mut catalog = self._catalog_mut();
+            let info = catalog
+                .infos
+                .get_mut(tname)
+                .ok_or(JsonStoreError::NotFoundTree(tname.to_string()))?;
+            if info.frozen == frozen {
+                return Ok(());
+            }
+            info.frozen = frozen;
+            catalog.infos.clone()
+        };
+        self._put_infos(&infos).await?;
+
+        self.shared.backend.sync().await
+    }
+
+    // Index field of tname's records, so find_by_field on it looks up the matching
+    // records instead of reading them all, and record it in the catalog so the index
+    // is built again at each load. Indexing a field twice does nothing.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, field = field)))]
+    pub async fn create_index(&self, tname: &str, field: &str) -> Result<(), JsonStoreError> {
+        self._create_index(tname, field, false).await
+    }
+
+    // create_index for find_range and find_sorted: the index keeps the records in the
+    // order of index::compare
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, field = field)))]
+    pub async fn create_index_ordered(
+        &self,
+        tname: &str,
+        field: &str,
+    ) -> Result<(), JsonStoreError> {
+        self._create_index(tname, field, true).await
+    }
+
+    async fn _create_index(
+        &self,
+        tname: &str,
+        field: &str,
+        ordered: bool,
+    ) -> Result<(), JsonStoreError> {
+        self._check_writable()?;
+
+        if field.is_empty() {
+            return Err(JsonStoreError::InvalidOptions(
+                "index on an empty field".to_string(),
+            ));
+        }
+
+        // catalog before tree, as reshard_tree takes them
+        let _guard = self.shared.catalog_write.lock().await;
+        let info = self._info(tname)?;
+        let declared = match ordered {
+            true => &info.ordered_indexes,
+            false => &info.indexes,
+        };
+        if declared.contains(field) {
+            return Ok(());
+        }
+        if info.partition_by.is_some() {
+            return Err(JsonStoreError::InvalidOptions(
+                "indexes aren't supported on partitioned trees".to_string(),
+            ));
+        }
+
+        // a tree not loaded yet gets the index when it is
+        let mut tree = self._write_lock_raw(tname).await?;
+        let infos = {
+            let mut catalog = self._catalog_mut();
+            if let Some(info) = catalog.infos.get_mut(tname) {
+                match ordered {
+                    true => info.ordered_indexes.insert(field.to_string()),
+                    false => info.indexes.insert(field.to_string()),
+                };
+            }
+            catalog.infos.clone()
+        };
+        self._put_infos(&infos).await?;
+        // spilled records are read back to be indexed, and spilled again after
+        tree.unspill().await?;
+        let Tree { indexes, data, .. } = &mut *tree;
+        match ordered {
+            true => indexes.add_ordered(field, data),
+            false => indexes.add(field, data),
+        }
+        if tree.loaded {
+            self._trim(tname, &mut tree).await?;
+        }
+
+        self.shared.backend.sync().await
+    }
+
+    // drop the indexes on field, of either kind
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, field = field)))]
+    pub async fn drop_index(&self, tname: &str, field: &str) -> Result<(), JsonStoreError> {
+        self._check_writable()?;
+
+        let _guard = self.shared.catalog_write.lock().await;
+        let info = self._info(tname)?;
+        if !info.indexes.contains(field) && !info.ordered_indexes.contains(field) {
+            return Err(JsonStoreError::InvalidOptions(format!(
+                "tree '{}' has no index on '{}'",
+                tname, field
+            )));
+        }
+
+        let mut tree = self._write_lock_raw(tname).await?;
+        let infos = {
+            let mut catalog = self._catalog_mut();
+            if let Some(info) = catalog.infos.get_mut(tname) {
+                info.indexes.remove(field);
+                info.ordered_indexes.remove(field);
+            }
+            catalog.infos.clone()
+        };
+        self._put_infos(&infos).await?;
+        tree.indexes.remove_field(field);
+
+        self.shared.backend.sync().await
+    }
+
+    pub fn get_tree_metadata(
+        &self,
+        tname: &str,
+        key: &str,
+    ) -> Result<Option<Value>, JsonStoreError> {
+        Ok(self._info(tname)?.metadata.get(key).cloned())
+    }
+
+    // Set key in the store's own metadata and write meta.json right away. A Null
+    // value removes the key.
+    pub async fn set_store_metadata(&self, key: &str, value: Value) -> Result<(), JsonStoreError> {
+        self._check_writable()?;
+
+        let _guard = self.shared.catalog_write.lock().await;
+
+        {
+            let mut metadata = self
+                .shared
+                .metadata
+                .write()
+                .unwrap_or_else(|e| e.into_inner());
+            match value {
+                Value::Null => metadata.remove(key),
+                value => metadata.insert(key.to_string(), value),
+            };
+        }
+        meta::put_meta(
+            &*self.shared.backend,
+            &self._meta(),
+            self.shared.format,
+            self.shared.durability,
+        )
+        .await?;
+
+        self.shared.backend.sync().await
+    }
+
+    pub fn get_store_metadata(&self, key: &str) -> Option<Value> {
+        self.shared
+            .metadata
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(key)
+            .cloned()
+    }
+
+    // add a step for migrate() to run; each tree's versions must be distinct and above 0
+    pub fn register_migration(&self, migration: Migration) -> Result<(), JsonStoreError> {
+        let mut migrations = self
+            .shared
+            .migrations
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+
+        if migration.version == 0
+            || migrations
+                .iter()
+                .any(|m| m.tree == migration.tree && m.version == migration.version)
+        {
+            return Err(JsonStoreError::InvalidOptions(format!(
+                "migration {} of tree '{}' is 0 or registered twice",
+                migration.version, migration.tree
+            )));
+        }
+        migrations.push(migration);
+
+        Ok(())
+    }
+
+    // the last migration version applied to tname, 0 if none
+    pub fn migration_version(&self, tname: &str) -> Result<u32, JsonStoreError> {
+        Ok(self._info(tname)?.migration_version)
+    }
+
+    // Run every registered migration above its tree's version, in version order. A step
+    // changes all of a tree's records or, if up fails on any or leaves two alike under a
+    // unique constraint, none of them; its version is recorded once its records are
+    // saved. Steps before a failed one stay applied.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn migrate(&self) -> Result<MigrationReport, JsonStoreError> {
+        self._metered("migrate", None, async {
+            self._check_writable()?;
+
+            let mut steps = self
+                .shared
+                .migrations
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone();
+            steps.sort_by(|a, b| (&a.tree, a.version).cmp(&(&b.tree, b.version)));
+
+            let mut report = MigrationReport::default();
+            for step in steps {
+                if step.version <= self.migration_version(&step.tree)? {
+                    continue;
+                }
+
+                let records = self._migrate_step(&step).await?;
+                trace::info!(
+                    tree = step.tree,
+                    version = step.version,
+                    records,
+                    "migrated tree"
+                );
+                report.applied.push(AppliedMigration {
+                    tree: step.tree,
+                    version: step.version,
+                    records,
+                });
+            }
+
+            Ok(report)
+        })
+        .await
+    }
+
+    async fn _migrate_step(&self, step: &Migration) -> Result<usize, JsonStoreError> {
+        let tname = step.tree.as_str();
+        // catalog before tree, the order reshard_tree takes them in
+        let _guard = self.shared.catalog_write.lock().await;
+        let info = self._info(tname)?;
+        self._check_frozen(tname, &info)?;
+        let mut tree = self._write_lock(tname).await?;
+
+        let failed = |sequence, reason| JsonStoreError::MigrationFailed {
+            tree: tname.to_string(),
+            version: step.version,
+            sequence,
+            reason,
+        };
+
+        // work on a copy, so a failing record leaves the tree untouched; the records
+        // themselves are shared until a step writes one
+        let mut data = tree.data.emptied();
+        // the unique keys as they will be, checked as check_unique would: an expired
+        // record holds none. Records go in sequence order, so a clash names the later
+        // of the two
+        let expiry = self._expiry(&info);
+        let mut unique = UniqueIndex::new(&info);
+        for (seq, prior) in tree.data.sorted() {
+            let mut value = prior.clone();
+            let record = Arc::make_mut(&mut value);
+            (step.up)(record).map_err(|reason| failed(Some(*seq), reason))?;
+            if record.get(&info.sequence_field).and_then(Value::as_u64) != Some(*seq) {
+                return Err(failed(
+                    Some(*seq),
+                    format!("changed the sequence field '{}'", info.sequence_field),
+                ));
+            }
+            key::check_unchanged(tname, &info, prior, record)
+                .map_err(|_| failed(Some(*seq), "changed the record's key".to_string()))?;
+            if expiry::live(expiry.as_ref(), record) {
+                if let Some(owner) = unique.conflict(record, None) {
+                    let reason = format!("has the same unique fields as record {}", owner);
+                    return Err(failed(Some(*seq), reason));
+                }
+                unique.insert(*seq, record);
+            }
+            data.insert(*seq, value);
+        }
+
+        // the version goes in the catalog only once the records and it are written; a
+        // failed write puts the old records back, to be written again at the next save
+        let mut infos = self._catalog().infos.clone();
+        if let Some(info) = infos.get_mut(tname) {
+            info.migration_version = step.version;
+        }
+        let prior = std::mem::replace(&mut tree.data, data);
+        let written = async {
+            self._rewrite_tree(tname, &mut tree).await?;
+            self._put_infos(&infos).await?;
+            self.shared.backend.sync().await
+        }
+        .await;
+        if let Err(e) = written {
+            let tree = &mut *tree;
+            tree.data = prior;
+            tree.repartition();
+            tree.indexes.rebuild(&tree.data);
+            tree.sync_raw();
+            tree.caches.clear();
+            tree.unique = None;
+            return Err(e);
+        }
+        self._catalog_mut().infos = infos;
+
+        Ok(tree.data.len())
+    }
+
+    // Write a tree whose records were replaced wholesale straight to its files; no WAL
+    // entry describes such a change. Its undo stack goes, holding records in their
+    // old shape.
+    async fn _rewrite_tree(&self, tname: &str, tree: &mut Tree) -> Result<(), JsonStoreError> {
+        tree.changed = true;
+        tree.dirty_shards = (0..tree.shards).collect();
+        tree.whole = true;
+        tree.mark = next_mark();
+        tree.repartition();
+        tree.undo.clear();
+        tree.unique = None;
+        tree.indexes.rebuild(&tree.data);
+        tree.sync_raw();
+        tree.caches.clear();
+        if tree.storage == StorageFormat::AppendLog {
+            let key = self.shared.layout.log_key(tname);
+            let context = append_log::encode(&tree.data)?;
+            self.shared
+                .backend
+                .write(&key, context, self.shared.durability)
+                .await?;
+        }
+        self.write_tree(tname, tree, self.shared.durability).await
+    }
+
+    // Renumber the records of tname 1..=n in sequence order, rewriting their sequence
+    // field, and reset its counter to n. Returns the new sequence of each record by
+    // its old one. Fields declared with InfoBuilder::reference to tname, in any tree,
+    // are rewritten in the same go, every tree involved locked and written together;
+    // one naming a record that isn't there stops it with DanglingReference before
+    // anything changes. Without declared references fixing up is left to the caller.
+    //
+    // Sequences handed out before no longer hold and the trees' undo stacks go, so
+    // take a backup first. confirm must be true for it to run. A tree already
+    // numbered 1..=n is left as it is.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn vacuum_tree(
+        &self,
+        tname: &str,
+        confirm: bool,
+    ) -> Result<HashMap<u64, u64>, JsonStoreError> {
+        self._metered("vacuum_tree", Some(tname), async {
+            self._check_writable()?;
+
+            if !confirm {
+                return Err(JsonStoreError::InvalidOptions(format!(
+                    "vacuum_tree renumbers every record of '{}' and needs confirm",
+                    tname
+                )));
+            }
+
+            // catalog before trees, so no reference is declared meanwhile
+            let _guard = self.shared.catalog_write.lock().await;
+            let info = self._info(tname)?;
+
+            // the fields referring to tname, by tree
+            let referring = self
+                ._catalog()
+                .infos
+                .iter()
+                .map(|(name, info)| {
+                    let fields = info
+                        .references
+                        .iter()
+                        .filter(|(_, tree)| *tree == tname)
+                        .map(|(field, _)| field.clone())
+                        .collect::<Vec<_>>();
+                    (name.clone(), fields)
+                })
+                .filter(|(_, fields)| !fields.is_empty())
+                .collect::<BTreeMap<_, _>>();
+
+            let mut names = referring.keys().cloned().collect::<BTreeSet<_>>();
+            names.insert(tname.to_string());
+            for name in names.iter() {
+                self._check_frozen(name, &self._info(name)?)?;
+            }
+            let mut trees = BTreeMap::new();
+            for name in names {
+                let tree = self._write_lock(&name).await?;
+                trees.insert(name, tree);
+            }
+
+            let tree = &trees[tname];
+            let renumbered = tree
+                .data
+                .sorted()
+                .into_keys()
+                .zip(1..)
+                .map(|(old, new)| (*old, new))
+                .collect::<HashMap<_, _>>();
+            let len = renumbered.len() as u64;
+            if renumbered.iter().all(|(old, new)| old == new) && tree.sequence == len {
+                return Ok(renumbered);
+            }
+            for seq in renumbered.keys() {
+                self.shared.record_locks.check(tname, *seq, None)?;
+            }
+
+            for (rname, fields) in referring.iter() {
+                for (seq, value) in trees[rname].data.iter() {
+                    for field in fields {
+                        let Some(target) = value.get(field).and_then(Value::as_u64) else {
+                            continue;
+                        };
+                        if !renumbered.contains_key(&target) {
+                            return Err(JsonStoreError::DanglingReference {
+                                tree: rname.clone(),
+                                sequence: *seq,
+                                field: field.clone(),
+                                target,
+                                referenced: tname.to_string(),
+                            });
+                        }
+                    }
+                }
+            }
+
+            let tree = trees.get_mut(tname).unwrap();
+            tree.data = std::mem::take(&mut tree.data)
+                .into_iter()
+                .map(|(old, mut value)| {
+                    let new = renumbered[&old];
+                    Arc::make_mut(&mut value)[&info.sequence_field] = json!(new);
+                    (new, value)
+                })
+                .collect();
+            tree.sequence = len;
+            // versions of deleted records go with them, their sequences being reused
+            tree.history = std::mem::take(&mut tree.history)
+                .into_iter()
+                .filter_map(|(old, versions)| Some((*renumbered.get(&old)?, versions)))
+                .collect();
+            tree.history_changed = true;
+
+            for (rname, fields) in referring.iter() {
+                let tree = trees.get_mut(rname).unwrap();
+                for value in tree.data.values_mut() {
+                    for field in fields {
+                        if let Some(target) = value.get(field).and_then(Value::as_u64) {
+                            Arc::make_mut(value)[field] = json!(renumbered[&target]);
+                        }
+                    }
+                }
+            }
+
+            for (name, tree) in trees.iter_mut() {
+                self._rewrite_tree(name, tree).await?;
+            }
+            self.shared.backend.sync().await?;
+
+            trace::info!(
+                tree = tname,
+                records = len,
+                referring = referring.len(),
+                "vacuumed tree"
+            );
+
+            Ok(renumbered)
+        })
+        .await
+    }
+
+    // names of all trees, sorted
+    pub fn list_trees(&self) -> Vec<String> {
+        let mut tnames = self._catalog().infos.keys().cloned().collect::<Vec<_>>();
+        tnames.sort();
+        tnames
+    }
+
+    pub fn has_tree(&self, tname: &str) -> bool {
+        self._catalog().infos.contains_key(tname)
+    }
+
+    pub fn get_info(&self, tname: &str) -> Result<Info, JsonStoreError> {
+        self._info(tname)
+    }
+
+    // tname as records of type T, without naming either again
+    pub fn tree<T: Serialize + DeserializeOwned>(
+        &self,
+        tname: &str,
+    ) -> Result<TreeHandle<T>, JsonStoreError> {
+        self._info(tname)?;
+        Ok(TreeHandle::new(self.clone(), tname))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, sequence = sequence)))]
+    pub async fn select<T: DeserializeOwned>(
+        &self,
+        tname: &str,
+        sequence: u64,
+    ) -> Result<T, JsonStoreError> {
+        self._metered("select", Some(tname), async {
+            let expiry = self._expiry(&self._info(tname)?);
+            let tree = self._read_lock_record(tname, sequence).await?;
+            check_live(tname, &tree, sequence, expiry.as_ref())?;
+            deserialize_record(tname, &tree, sequence)
+        })
+        .await
+    }
+
+    // select for TreeHandle::select_cached: the record from cache if it holds it, and
+    // otherwise read and put there under the tree's read lock
+    pub(crate) async fn select_cached<T: DeserializeOwned + Send + Sync + 'static>(
+        &self,
+        tname: &str,
+        sequence: u64,
+        cache: &Arc<ReadCache<T>>,
+    ) -> Result<Arc<T>, JsonStoreError> {
+        // a cached record may have expired since, so records that can aren't cached
+        if self._info(tname)?.expiry_field.is_some() {
+            return Ok(Arc::new(self.select(tname, sequence).await?));
+        }
+        if let Some(value) = cache.get(sequence) {
+            if let Some(metrics) = &self.shared.metrics {
+                metrics.record_cache(tname, true);
+            }
+            return Ok(value);
+        }
+
+        self._metered("select_cached", Some(tname), async {
+            let tree = self._read_lock_record(tname, sequence).await?;
+            let value: Arc<T> = Arc::new(deserialize_record(tname, &tree, sequence)?);
+            let invalidate: Arc<dyn Invalidate> = cache.clone();
+            tree.caches.register(&invalidate);
+            cache.insert(sequence, value.clone());
+            if let Some(metrics) = &self.shared.metrics {
+                metrics.record_cache(tname, false);
+            }
+            Ok(value)
+        })
+        .await
+    }
+
+    // select without deserializing or copying: the record itself, shared with the tree.
+    // A later write to it puts a new record in the tree and leaves this one as it was.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, sequence = sequence)))]
+    pub async fn select_shared(
+        &self,
+        tname: &str,
+        sequence: u64,
+    ) -> Result<Arc<Value>, JsonStoreError> {
+        self._metered("select_shared", Some(tname), async {
+            let expiry = self._expiry(&self._info(tname)?);
+            let tree = self._read_lock_record(tname, sequence).await?;
+            check_live(tname, &tree, sequence, expiry.as_ref())?;
+
+            if let Some(value) = tree.data.get(&sequence) {
+                return Ok(value.clone());
+            }
+            // of a Raw tree not parsed, a copy
+            match tree.raw.as_ref().and_then(|raw| raw.get(&sequence)) {
+                Some(record) => Ok(Arc::new(raw::parse(record)?)),
+                None => Err(JsonStoreError::SequenceNotExist(tname.to_string())),
+            }
+        })
+        .await
+    }
+
+    // The record at sequence as JSON text: a Raw tree's own, shared with it, or any
+    // other tree's record written out.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, sequence = sequence)))]
+    pub async fn select_raw(
+        &self,
+        tname: &str,
+        sequence: u64,
+    ) -> Result<Arc<RawValue>, JsonStoreError> {
+        self._metered("select_raw", Some(tname), async {
+            let expiry = self._expiry(&self._info(tname)?);
+            let tree = self._read_lock_record(tname, sequence).await?;
+            check_live(tname, &tree, sequence, expiry.as_ref())?;
+
+            let record = match &tree.raw {
+                Some(raw) => raw.get(&sequence).cloned(),
+                None => tree.data.get(&sequence).map(|value| raw::to_raw(value)),
+            };
+            record.ok_or(JsonStoreError::SequenceNotExist(tname.to_string()))
+        })
+        .await
+    }
+
+    // a read lock for a read of the record at sequence alone: only its partition is
+    // loaded, a Raw tree isn't parsed and a spilled record is read back in
+    async fn _read_lock_record(
+        &self,
+        tname: &str,
+        sequence: u64,
+    ) -> Result<RwLockReadGuardArc<Tree>, JsonStoreError> {
+        let info = self._info(tname)?;
+        if info.value_mode == ValueMode::Raw {
+            return self._read_lock_unparsed(tname).await;
+        }
+        if info.resident_limit.is_some() {
+            let tree = self._read_lock_unparsed(tname).await?;
+            if !tree.spilled(sequence) {
+                tree.touch_read(sequence);
+                return Ok(tree);
+            }
+            drop(tree);
+            // room is made first, so the record read back stays
+            let mut tree = self._write_lock_unparsed(tname).await?;
+            self._trim(tname, &mut tree).await?;
+            tree.fault_in(sequence).await?;
+            return Ok(RwLockWriteGuardArc::downgrade(tree));
+        }
+        self._read_lock_periods(tname, |tree| {
+            tree.partition_of
+                .get(&sequence)
+                .cloned()
+                .into_iter()
+                .collect()
+        })
+        .await
+    }
+
+    // Every record of the tree as it is now, by sequence. Records are shared rather
+    // than copied, so this costs a map of pointers however large they are, and writes
+    // made afterwards don't show in it.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn snapshot_tree(
+        &self,
+        tname: &str,
+    ) -> Result<BTreeMap<u64, Arc<Value>>, JsonStoreError> {
+        self._metered("snapshot_tree", Some(tname), async {
+            let tree = self._read_lock(tname).await?;
+            Ok(shared_records(tree.data.iter()))
+        })
+        .await
+    }
+
+    // Save every changed tree concurrently; one failing tree does not stop the others.
+    // Returns the names of the trees that were written.
+    pub async fn save(&self) -> Result<Vec<String>, JsonStoreError> {
+        self.save_with(self.shared.durability).await
+    }
+
+    // save with a durability other than the store's default
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn save_with(&self, durability: Durability) -> Result<Vec<String>, JsonStoreError> {
+        self._metered("save", None, async {
+            self._check_savable()?;
+
+            let tnames = self._catalog().infos.keys().cloned().collect::<Vec<_>>();
+
+            let results = stream::iter(tnames)
+                .map(|key| async move {
+                    let result = self.save_tree_with(&key, durability).await;
+                    (key, result)
+                })
+                .buffer_unordered(SAVE_CONCURRENCY)
+                .collect::<Vec<_>>()
+                .await;
+
+            let mut saved = Vec::new();
+            let mut failed = Vec::new();
+            for (key, result) in results {
+                match result {
+                    Ok(true) => saved.push(key),
+                    Ok(false) => {}
+                    Err(e) => failed.push((key, e)),
+                }
+            }
+
+            saved.sort();
+
+            if failed.is_empty() {
+                if !saved.is_empty() {
+                    self.shared.backend.sync().await?;
+                }
+                return Ok(saved);
+            }
+
+            failed.sort_by(|a, b| a.0.cmp(&b.0));
+
+            Err(JsonStoreError::SaveFailed { failed, saved })
+        })
+        .await
+    }
+
+    // save tree if it has changed; returns whether anything was written
+    pub async fn save_tree(&self, tname: &str) -> Result<bool, JsonStoreError> {
+        self.save_tree_with(tname, self.shared.durability).await
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn save_tree_with(
+        &self,
+        tname: &str,
+        durability: Durability,
+    ) -> Result<bool, JsonStoreError> {
+        self._metered("save", Some(tname), async {
+            self._check_savable()?;
+
+            // clean (or unloaded) trees only need a read lock, so checking them never
+            // blocks readers
+            if !self._read_lock_raw(tname).await?.changed {
+                return Ok(false);
+            }
+
+            let tree = self._save_lock_raw(tname).await?;
+            let mut tree = self._encode_unlocked(tname, tree).await?;
+
+            // someone may have saved it while we waited for the write lock
+            if !tree.changed {
+                tree.encoded.clear();
+                return Ok(false);
+            }
+
+            let saved = self._save_locked(tname, &mut tree, durability).await;
+            tree.encoded.clear();
+            saved?;
+
+            Ok(true)
+        })
+        .await
+    }
+
+    pub async fn is_dirty(&self, tname: &str) -> Result<bool, JsonStoreError> {
+        Ok(self._read_lock_raw(tname).await?.changed)
+    }
+
+    // Save tname if it is dirty and drop its records from memory; its next use reads
+    // it again. Fails with InUseTree rather than wait while another task holds it.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn unload_tree(&self, tname: &str) -> Result<(), JsonStoreError> {
+        self._metered("unload", Some(tname), async {
+            let Some(mut tree) = self._tree(tname)?.try_write_arc() else {
+                return Err(JsonStoreError::InUseTree(tname.to_string()));
+            };
+            if !tree.loaded {
+                return Ok(());
+            }
+
+            // a read-only store can't save; its replayed writes are replayed again on reload
+            if tree.changed && !self.shared.read_only {
+                self._save_locked(tname, &mut tree, self.shared.durability)
+                    .await?;
+            }
+
+            tree.replace_contents(Tree::unloaded());
+
+            Ok(())
+        })
+        .await
+    }
+
+    // names of the trees currently in memory
+    pub async fn loaded_trees(&self) -> Vec<String> {
+        let trees = self
+            ._catalog()
+            .trees
+            .iter()
+            .map(|(tname, tree)| (tname.clone(), tree.clone()))
+            .collect::<Vec<_>>();
+
+        let mut loaded = Vec::new();
+        for (tname, tree) in trees {
+            if tree.read().await.loaded {
+                loaded.push(tname);
+            }
+        }
+        loaded.sort();
+
+        loaded
+    }
+
+    // the periods of a partitioned tree, oldest first, with whether each is in memory
+    pub async fn partitions(&self, tname: &str) -> Result<Vec<PartitionStats>, JsonStoreError> {
+        self._partition_spec(tname)?;
+        let tree = self._read_lock_periods(tname, |_| Vec::new()).await?;
+
+        Ok(tree
+            .partitions
+            .iter()
+            .map(|(period, partition)| PartitionStats {
+                period: period.clone(),
+                records: partition.sequences.len(),
+                loaded: partition.loaded,
+            })
+            .collect())
+    }
+
+    // Drop from memory the partitions of tname whose whole period lies before cutoff,
+    // saving the tree first if it is dirty; their next use reads them again. Returns
+    // the periods unloaded.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn unload_partitions_before(
+        &self,
+        tname: &str,
+        cutoff: SystemTime,
+    ) -> Result<Vec<String>, JsonStoreError> {
+        self._metered("unload", Some(tname), async {
+            let spec = self._partition_spec(tname)?;
+            let mut tree = self._write_lock_raw(tname).await?;
+            if !tree.loaded {
+                return Ok(Vec::new());
+            }
+
+            if tree.changed && !self.shared.read_only {
+                self._save_locked(tname, &mut tree, self.shared.durability)
+                    .await?;
+            }
+
+            // a read-only store keeps the partitions its replayed writes went to
+            let cutoff = partition::millis(cutoff);
+            let periods = tree
+                .partitions
+                .iter()
+                .filter(|(period, p)| p.loaded && !p.dirty && spec.ends_by(period, cutoff))
+                .map(|(period, _)| period.clone())
+                .collect::<Vec<_>>();
+            let Tree {
+                partitions, data, ..
+            } = &mut *tree;
+            for period in periods.iter() {
+                if let Some(partition) = partitions.get_mut(period) {
+                    partition.loaded = false;
+                    for seq in partition.sequences.iter() {
+                        data.remove(seq);
+                    }
+                }
+            }
+
+            Ok(periods)
+        })
+        .await
+    }
+
+    // Move the partitions of tname whose whole period lies before cutoff out of the
+    // tree and into `.archive-{millis}/`, under their usual names, saving the tree
+    // first. Their records are gone from the tree, though not their sequences, which
+    // are never handed out again. Returns the periods archived.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn archive_partitions_before(
+        &self,
+        tname: &str,
+        cutoff: SystemTime,
+    ) -> Result<Vec<String>, JsonStoreError> {
+        self._metered("archive_partitions", Some(tname), async {
+            self._writable_info(tname)?;
+
+            let spec = self._partition_spec(tname)?;
+            let mut tree = self._write_lock_periods(tname, |_| Vec::new()).await?;
+
+            if tree.changed {
+                self._save_locked(tname, &mut tree, self.shared.durability)
+                    .await?;
+            }
+
+            let cutoff = partition::millis(cutoff);
+            let periods = tree
+                .partitions
+                .keys()
+                .filter(|period| spec.ends_by(period, cutoff))
+                .cloned()
+                .collect::<Vec<_>>();
+            if periods.is_empty() {
+                return Ok(periods);
+            }
+
+            // copied before the index drops them and removed after, so a crash
+            // leaves the records in one place or both
+            let backend = &*self.shared.backend;
+            let keys = partition_files(&self.shared.layout, tname, &periods);
+            let millis = rt::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis();
+            let aside = format!(".archive-{}", millis);
+            set_aside(backend, &keys, &aside, false).await?;
+
+            let records = periods
+                .iter()
+                .filter_map(|period| tree.partitions.get(period))
+                .map(|partition| partition.sequences.len())
+                .sum::<usize>();
+            for period in periods.iter() {
+                let Some(partition) = tree.partitions.remove(period) else {
+                    continue;
+                };
+                for seq in partition.sequences {
+                    tree.take(seq);
+                    tree.partition_of.remove(&seq);
+                    self.shared.record_locks.remove(tname, seq);
+                }
+            }
+            // the stack may put back records that are now archived
+            tree.undo.clear();
+
+            let index = partition::index(&tree.partitions);
+            let key = self.shared.layout.partitions_key(tname);
+            put_json(
+                backend,
+                &key,
+                &index,
+                self.shared.format,
+                self.shared.durability,
+            )
+            .await?;
+            tree.partitions_changed = false;
+
+            for key in keys.iter() {
+                backend.delete(key).await?;
+            }
+            trace::info!(tree = tname, aside, periods = ?periods, records, "archived partitions");
+
+            backend.sync().await?;
+
+            Ok(periods)
+        })
+        .await
+    }
+
+    // Rough number of bytes tname's records take in memory, 0 if it isn't loaded. It
+    // counts the room their maps and indexes keep, which deleted records leave behind
+    // until compact_memory.
+    pub async fn memory_estimate(&self, tname: &str) -> Result<usize, JsonStoreError> {
+        Ok(self._read_lock_raw(tname).await?.memory_size())
+    }
+
+    // Shrink the maps holding tname's records and indexes to what they hold now, as
+    // after deleting most of them. A tree with a resident limit first spills records
+    // read back past it. A tree that isn't loaded is left alone.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn compact_memory(&self, tname: &str) -> Result<MemoryCompaction, JsonStoreError> {
+        self._metered("compact_memory", Some(tname), async {
+            let mut tree = self._write_lock_raw(tname).await?;
+
+            let before_bytes = tree.memory_size();
+            if tree.loaded {
+                self._trim(tname, &mut tree).await?;
+            }
+            tree.shrink();
+            let after_bytes = tree.memory_size();
+            trace::debug!(
+                tree = tname,
+                before_bytes,
+                after_bytes,
+                "compacted tree memory"
+            );
+
+            Ok(MemoryCompaction {
+                before_bytes,
+                after_bytes,
+            })
+        })
+        .await
+    }
+
+    // compact_memory for every loaded tree
+    pub async fn compact_all_memory(
+        &self,
+    ) -> Result<BTreeMap<String, MemoryCompaction>, JsonStoreError> {
+        let mut compacted = BTreeMap::new();
+        for tname in self.loaded_trees().await {
+            let compaction = self.compact_memory(&tname).await?;
+            compacted.insert(tname, compaction);
+        }
+        Ok(compacted)
+    }
+
+    // counts and sizes of tname, without loading it
+    pub async fn tree_stats(&self, tname: &str) -> Result<TreeStats, JsonStoreError> {
+        let info = self._info(tname)?;
+        let mut stats = {
+            let tree = self._read_lock_raw(tname).await?;
+            TreeStats {
+                records: tree.len(),
+                sequence: tree.sequence,
+                dirty: tree.changed,
+                loaded: tree.loaded,
+                memory_bytes: tree.memory_size(),
+                disk_bytes: 0,
+            }
+        };
+
+        let backend = &*self.shared.backend;
+        for key in stored_tree_files(backend, &self.shared.layout, tname, &info).await? {
+            if let Some(stamp) = backend.stamp(&key).await? {
+                stats.disk_bytes += stamp.len();
+            }
+        }
+
+        Ok(stats)
+    }
+
+    pub async fn stats(&self) -> Result<StoreStats, JsonStoreError> {
+        let mut trees = BTreeMap::new();
+        for tname in self.list_trees() {
+            let stats = self.tree_stats(&tname).await?;
+            trees.insert(tname, stats);
+        }
+
+        Ok(StoreStats::new(self._path(), trees))
+    }
+
+    // what the values of field look like across tname's records, see profile.rs
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, field = field)))]
+    pub async fn profile_field(
+        &self,
+        tname: &str,
+        field: &str,
+    ) -> Result<FieldProfile, JsonStoreError> {
+        self._metered("profile_field", Some(tname), async {
+            let tree = self._read_lock(tname).await?;
+
+            let mut profiler = Profiler::default();
+            for value in tree.data.values() {
+                if let Some(value) = value.get(field) {
+                    profiler.add(value);
+                }
+            }
+
+            Ok(profiler.finish(field, tree.data.len()))
+        })
+        .await
+    }
+
+    // profile_field for every top-level field any record of tname has, in one pass
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn profile_tree(
+        &self,
+        tname: &str,
+    ) -> Result<BTreeMap<String, FieldProfile>, JsonStoreError> {
+        self._metered("profile_tree", Some(tname), async {
+            let tree = self._read_lock(tname).await?;
+
+            let mut profilers = HashMap::<&str, Profiler>::new();
+            for map in tree.data.values().filter_map(|value| value.as_object()) {
+                for (field, value) in map {
+                    profilers.entry(field).or_default().add(value);
+                }
+            }
+
+            Ok(profilers
+                .into_iter()
+                .map(|(field, profiler)| {
+                    (field.to_string(), profiler.finish(field, tree.data.len()))
+                })
+                .collect())
+        })
+        .await
+    }
+
+    // a draft JSON Schema every live record of tname validates against, see schema.rs
+    pub async fn infer_schema(&self, tname: &str) -> Result<Value, JsonStoreError> {
+        self.infer_schema_with(tname, SchemaOptions::default())
+            .await
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn infer_schema_with(
+        &self,
+        tname: &str,
+        options: SchemaOptions,
+    ) -> Result<Value, JsonStoreError> {
+        self._metered("infer_schema", Some(tname), async {
+            let expiry = self._expiry(&self._info(tname)?);
+            let tree = self._read_lock_scan(tname).await?;
+
+            let mut shape = Shape::default();
+            let mut scan = pin!(tree.scan());
+            while let Some(record) = scan.next().await {
+                let (_, value) = record?;
+                if expiry::live(expiry.as_ref(), &value) {
+                    shape.add(&value, options.depth);
+                }
+            }
+
+            Ok(schema::root(tname, &shape))
+        })
+        .await
+    }
+
+    pub async fn describe(&self) -> Result<StoreDescription, JsonStoreError> {
+        self.describe_with_samples(0).await
+    }
+
+    // describe(), with up to samples records of each loaded tree; for debugging only,
+    // as the records end up wherever the description is printed
+    pub async fn describe_with_samples(
+        &self,
+        samples: usize,
+    ) -> Result<StoreDescription, JsonStoreError> {
+        let infos = self._catalog().infos.clone();
+
+        let mut trees = BTreeMap::new();
+        for (tname, info) in infos {
+            let tree = self._read_lock_raw(&tname).await?;
+            let samples = match (&tree.raw, tree.parsed) {
+                (Some(raw), false) => sorted(raw)
+                    .into_values()
+                    .take(samples)
+                    .filter_map(|record| raw::parse(record).ok())
+                    .collect(),
+                _ => {
+                    tree.scan()
+                        .take(samples)
+                        .filter_map(|record| async { record.ok() })
+                        .map(|(_, value)| Value::clone(&value))
+                        .collect()
+                        .await
+                }
+            };
+            let description = TreeDescription {
+                records: tree.len(),
+                sequence: tree.sequence,
+                dirty: tree.changed,
+                loaded: tree.loaded,
+                samples,
+                info,
+            };
+            trees.insert(tname, description);
+        }
+
+        Ok(StoreDescription {
+            path: self._path(),
+            trees,
+        })
+    }
+
+    pub async fn diff_tree(
+        &self,
+        tname: &str,
+        other: &JsonStore,
+    ) -> Result<TreeDiff, JsonStoreError> {
+        self.diff_tree_with(tname, other, &DiffOptions::default())
+            .await
+    }
+
+    // what changed in tname from this store to other, see diff.rs
+    pub async fn diff_tree_with(
+        &self,
+        tname: &str,
+        other: &JsonStore,
+        options: &DiffOptions,
+    ) -> Result<TreeDiff, JsonStoreError> {
+        self._metered("diff", Some(tname), async {
+            let (old_info, new_info) = (self._info(tname)?, other._info(tname)?);
+
+            let old = self._read_lock(tname).await?;
+            let new = other._read_lock(tname).await?;
+
+            let mut diff = diff::diff_records(&old.data, &new.data, options);
+            if old_info != new_info {
+                diff.info = Some(InfoDiff {
+                    old: old_info,
+                    new: new_info,
+                });
+            }
+
+            Ok(diff)
+        })
+        .await
+    }
+
+    pub async fn diff(&self, other: &JsonStore) -> Result<StoreDiff, JsonStoreError> {
+        self.diff_with(other, &DiffOptions::default()).await
+    }
+
+    // diff_tree_with over every tree, listing the trees only one side has
+    pub async fn diff_with(
+        &self,
+        other: &JsonStore,
+        options: &DiffOptions,
+    ) -> Result<StoreDiff, JsonStoreError> {
+        let mut diff = StoreDiff::default();
+
+        for tname in self.list_trees() {
+            if !other.has_tree(&tname) {
+                diff.removed_trees.push(tname);
+                continue;
+            }
+            let tree_diff = self.diff_tree_with(&tname, other, options).await?;
+            if !tree_diff.is_empty() {
+                diff.trees.insert(tname, tree_diff);
+            }
+        }
+        diff.added_trees = other
+            .list_trees()
+            .into_iter()
+            .filter(|tname| !self.has_tree(tname))
+            .collect();
+
+        Ok(diff)
+    }
+
+    // names of all trees with unsaved changes
+    pub async fn unsaved_changes(&self) -> Vec<String> {
+        let trees = self
+            ._catalog()
+            .trees
+            .iter()
+            .map(|(tname, tree)| (tname.clone(), tree.clone()))
+            .collect::<Vec<_>>();
+
+        let mut dirty = Vec::new();
+        for (tname, tree) in trees {
+            if tree.read().await.changed {
+                dirty.push(tname);
+            }
+        }
+        dirty.sort();
+
+        dirty
+    }
+
+    // Encode the snapshot files a save of the tree would write whole, letting go of
+    // the tree meanwhile so writes to it carry on, and hand it back locked again with
+    // them in tree.encoded. Should records change in between the files are encoded
+    // afresh, a few times at most before leaving it to the save to do under the lock.
+    async fn _encode_unlocked(
+        &self,
+        tname: &str,
+        mut tree: RwLockWriteGuardArc<Tree>,
+    ) -> Result<RwLockWriteGuardArc<Tree>, JsonStoreError> {
+        for _ in 0..ENCODE_ATTEMPTS {
+            if !tree.changed {
+                break;
+            }
+            let files = self._whole_files(tname, &tree).await?;
+            if files
+                .iter()
+                .map(|(_, records)| records.len())
+                .sum::<usize>()
+                < BLOCKING_ENCODE_RECORDS
+            {
+                break;
+            }
+
+            let mark = tree.mark;
+            drop(tree);
+            let mut encoded = HashMap::new();
+            for (key, records) in files {
+                let file =
+                    encode_snapshot(&key, records, self.shared.codec, self.shared.format).await?;
+                encoded.insert(key, file);
+            }
+
+            tree = self._save_lock_raw(tname).await?;
+            if tree.mark == mark {
+                tree.encoded = encoded;
+                break;
+            }
+        }
+
+        Ok(tree)
+    }
+
+    // whether saving the tree writes its delta file rather than its snapshot
+    fn _writes_delta(&self, tree: &Tree) -> bool {
+        match self.shared.incremental_save {
+            Some(ratio) => {
+                tree.single_file()
+                    && !tree.whole
+                    && tree.unsnapshotted.len() as f64 <= ratio * tree.len() as f64
+            }
+            None => false,
+        }
+    }
+
+    // the snapshot files the next save of a tree writes whole, with their records:
+    // its only one unless it writes the delta, or its dirty shards
+    async fn _whole_files(
+        &self,
+        tname: &str,
+        tree: &Tree,
+    ) -> Result<Vec<(String, FileRecords)>, JsonStoreError> {
+        if tree.storage != StorageFormat::Snapshot || tree.partition_by.is_some() {
+            return Ok(Vec::new());
+        }
+        let layout = &self.shared.layout;
+        let key = |base: &str| layout.snapshot_key(base, self.shared.codec, tree.compression);
+
+        match tree.shards {
+            0 if self._writes_delta(tree) => Ok(Vec::new()),
+            0 => Ok(vec![(
+                key(&layout.base(tname)),
+                tree.file_records(None).await?,
+            )]),
+            _ => {
+                let mut files = Vec::with_capacity(tree.dirty_shards.len());
+                for i in tree.dirty_shards.iter() {
+                    let records = tree.file_records(Some(*i)).await?;
+                    files.push((key(&shard_base(layout, tname, *i)), records));
+                }
+                Ok(files)
+            }
+        }
+    }
+
+    async fn _save_locked(
+        &self,
+        tname: &str,
+        tree: &mut Tree,
+        durability: Durability,
+    ) -> Result<(), JsonStoreError> {
+        // refuse to clobber files someone else rewrote since we last touched them
+        if let Some(key) = self._external_change(tname, tree, false).await? {
+            return Err(JsonStoreError::ExternallyModified {
+                tree: tname.to_string(),
+                path: self.shared.backend.location(&key),
+            });
+        }
+
+        self.write_tree(tname, tree, durability).await
+    }
+
+    // The first of tname's files not as tree last read or wrote it: the .seq file and
+    // the snapshot files a save of tree would write, or with every, all it has read.
+    async fn _external_change(
+        &self,
+        tname: &str,
+        tree: &Tree,
+        every: bool,
+    ) -> Result<Option<String>, JsonStoreError> {
+        let backend = &*self.shared.backend;
+        let layout = &self.shared.layout;
+
+        let key = layout.seq_key(tname);
+        if backend.stamp(&key).await? != tree.seq_stamp {
+            return Ok(Some(key));
+        }
+
+        let mut expected = vec![(layout.base(tname), tree.data_stamp)];
+        if tree.partition_by.is_some() {
+            expected = tree
+                .partitions
+                .iter()
+                .filter(|(_, partition)| match every {
+                    true => partition.loaded,
+                    false => partition.dirty,
+                })
+                .map(|(period, partition)| (partition_base(layout, tname, period), partition.stamp))
+                .collect();
+        } else if tree.shards > 0 {
+            let shards = match every {
+                true => (0..tree.shards).collect(),
+                false => tree.dirty_shards.clone(),
+            };
+            expected = shards
+                .into_iter()
+                .map(|i| (shard_base(layout, tname, i), tree.shard_stamps[i as usize]))
+                .collect();
+        }
+        for (base, stamp) in expected {
+            let key =
+                snapshot_file(backend, layout, &base, self.shared.codec, tree.compression).await?;
+            if backend.stamp(&key).await? != stamp {
+                return Ok(Some(key));
+            }
+        }
+
+        Ok(None)
+    }
+
+    // save tree even if its files were modified externally
+    pub async fn save_tree_force(&self, tname: &str) -> Result<(), JsonStoreError> {
+        self._check_savable()?;
+
+        let mut tree = self._write_lock_saving(tname).await?;
+
+        self.write_tree(tname, &mut tree, self.shared.durability)
+            .await
+    }
+
+    // Read trees now rather than on first use, e.g. to pay for large ones at startup
+    // or to find damaged files early. Trees already in memory are left as they are.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(trees = ?trees)))]
+    pub async fn preload(&self, trees: &[&str]) -> Result<(), JsonStoreError> {
+        self._metered("preload", None, async {
+            // several at once; should more than one fail, the first named is reported
+            let mut results = stream::iter(trees.iter().enumerate())
+                .map(|(i, tname)| async move { (i, self._read_lock(tname).await.map(drop)) })
+                .buffer_unordered(self.shared.load_concurrency)
+                .collect::<Vec<_>>()
+                .await;
+            results.sort_by_key(|(i, _)| *i);
+
+            results.into_iter().try_for_each(|(_, result)| result)
+        })
+        .await
+    }
+
+    // discard in-memory state of tree and read it again from disk
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn reload_tree(&self, tname: &str) -> Result<(), JsonStoreError> {
+        self._metered("reload", Some(tname), async {
+            let mut tree = self._write_lock_raw(tname).await?;
+            self._reload(tname, &mut tree).await
+        })
+        .await
+    }
+
+    async fn _reload(&self, tname: &str, tree: &mut Tree) -> Result<(), JsonStoreError> {
+        let info = self._info(tname)?;
+        tree.replace_contents(
+            read_tree(
+                &*self.shared.backend,
+                &self.shared.layout,
+                tname,
+                &info,
+                self.shared.codec,
+                self.shared.read_only,
+                self.shared.spill_dir.as_deref(),
+            )
+            .await?,
+        );
+        Ok(())
+    }
+
+    // watch_with the default debounce
+    #[cfg(feature = "watch")]
+    pub async fn watch(&self) -> Result<WatchHandle, JsonStoreError> {
+        self.watch_with(watch::DEFAULT_DEBOUNCE).await
+    }
+
+    // Reload trees as other processes change their files, until the handle is dropped;
+    // see watch.rs. Only a store in a directory can be watched.
+    #[cfg(feature = "watch")]
+    pub async fn watch_with(&self, debounce: Duration) -> Result<WatchHandle, JsonStoreError> {
+        self._check_open()?;
+
+        let root = self._path();
+        if !fs::metadata(&root).await.is_ok_and(|m| m.is_dir()) {
+            return Err(JsonStoreError::InvalidOptions(format!(
+                "only a store in a directory can be watched, not {:?}",
+                root
+            )));
+        }
+
+        WatchHandle::start(self.clone(), root, debounce)
+    }
+
+    // the trees some of whose files are at paths under root, sorted
+    #[cfg(feature = "watch")]
+    pub(crate) fn _trees_of(&self, root: &Path, paths: &BTreeSet<PathBuf>) -> Vec<String> {
+        let keys = paths
+            .iter()
+            .filter_map(|path| watch::key(root, path))
+            .collect::<Vec<_>>();
+        let layout = &self.shared.layout;
+
+        let mut tnames = sorted(&self._catalog().infos)
+            .into_iter()
+            .filter(|(tname, info)| {
+                let files = tree_files(layout, tname, info);
+                let dir = format!("{}/", layout.base(tname));
+                keys.iter()
+                    .any(|key| files.contains(key) || key.starts_with(&dir))
+            })
+            .map(|(tname, _)| tname.clone())
+            .collect::<Vec<_>>();
+        tnames.dedup();
+        tnames
+    }
+
+    // Read tname again if its files changed since it last read or wrote them and it
+    // holds no unsaved changes; None if they didn't or it isn't loaded yet.
+    #[cfg(feature = "watch")]
+    pub(crate) async fn _reload_changed(
+        &self,
+        tname: &str,
+    ) -> Result<Option<WatchEvent>, JsonStoreError> {
+        let info = self._info(tname)?;
+        let mut tree = self._write_lock_raw(tname).await?;
+        if !tree.loaded {
+            return Ok(None);
+        }
+        let Some(key) = self._external_change(tname, &tree, true).await? else {
+            return Ok(None);
+        };
+        if tree.changed {
+            return Ok(Some(WatchEvent::Conflict {
+                tree: tname.to_string(),
+                path: self.shared.backend.location(&key),
+            }));
+        }
+
+        tree.replace_contents(
+            read_tree(
+                &*self.shared.backend,
+                &self.shared.layout,
+                tname,
+                &info,
+                self.shared.codec,
+                self.shared.read_only,
+                self.shared.spill_dir.as_deref(),
+            )
+            .await?,
+        );
+
+        Ok(Some(WatchEvent::Reloaded {
+            tree: tname.to_string(),
+        }))
+    }
+
+    // Replace the files of tname, typically one a corrupt file left unavailable, and
+    // read it again. Its current files are moved into `.corrupt-{millis}/` first.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn repair_tree(
+        &self,
+        tname: &str,
+        strategy: RepairStrategy,
+    ) -> Result<(), JsonStoreError> {
+        self._metered("repair", Some(tname), async {
+            self._check_savable()?;
+
+            let info = self._info(tname)?;
+            self._check_frozen(tname, &info)?;
+            let mut tree = self._write_lock_raw(tname).await?;
+
+            let backend = &*self.shared.backend;
+            let layout = &self.shared.layout;
+            // a damaged index can't name the partitions; those files then stay put
+            let keys = stored_tree_files(backend, layout, tname, &info)
+                .await
+                .unwrap_or_else(|_| tree_files(layout, tname, &info));
+
+            // gather the replacement before anything is moved
+            let mut files = Vec::new();
+            if let RepairStrategy::RestoreFromBackup(path) = &strategy {
+                let source = FsBackend::new(path);
+                let source_layout = meta::check(&source).await?.layout;
+                let periods = read_periods(&source, &source_layout, tname, &info).await?;
+                let mut from = tree_files(&source_layout, tname, &info);
+                from.extend(partition_files(&source_layout, tname, &periods));
+                let mut to = tree_files(layout, tname, &info);
+                to.extend(partition_files(layout, tname, &periods));
+                for (from, to) in from.into_iter().zip(to) {
+                    if let Some(context) = source.read(&from).await? {
+                        let context = checksum::moved_contents(&to, context);
+                        files.push((to, context));
+                    }
+                }
+                if files.is_empty() {
+                    return Err(JsonStoreError::InvalidBackup {
+                        path: path.clone(),
+                        reason: format!("no files of tree '{}'", tname),
+                    });
+                }
+            }
+            let sequence = get_sequence(backend, tname, &self.shared.layout.seq_key(tname))
+                .await
+                .ok()
+                .flatten()
+                .unwrap_or(0);
+
+            let millis = rt::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis();
+            let aside = format!(".corrupt-{}", millis);
+            set_aside(backend, &keys, &aside, true).await?;
+            trace::warn!(tree = tname, aside, "moved tree files aside for repair");
+
+            match strategy {
+                RepairStrategy::RestoreFromBackup(_) => {
+                    for (key, context) in files {
+                        backend.write(&key, context, self.shared.durability).await?;
+                    }
+                    let fresh = read_tree(
+                        backend,
+                        &self.shared.layout,
+                        tname,
+                        &info,
+                        self.shared.codec,
+                        false,
+                        self.shared.spill_dir.as_deref(),
+                    )
+                    .await?;
+                    tree.replace_contents(fresh);
+                }
+                RepairStrategy::StartEmpty => {
+                    tree.replace_contents(Tree::empty(sequence, &info));
+                    self.write_tree(tname, &mut tree, self.shared.durability)
+                        .await?;
+                }
+            }
+
+            backend.sync().await
+        })
+        .await
+    }
+
+    // Take the snapshot files of tname as they are, after editing them by hand or with
+    // another program: rewrite each one's checksum sidecar to match its contents, so
+    // that it reads instead of failing with ChecksumMismatch. Load never does this on
+    // its own. A tree not in memory, or left unavailable by a mismatch, is read from
+    // the files on its next use; reload_tree takes them over one in memory. Returns
+    // the files whose sidecar changed.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn rehash_tree(&self, tname: &str) -> Result<Vec<PathBuf>, JsonStoreError> {
+        self._metered("rehash", Some(tname), async {
+            self._check_savable()?;
+
+            let info = self._info(tname)?;
+            let mut tree = self._write_lock_raw(tname).await?;
+            if info.storage == StorageFormat::AppendLog {
+                return Ok(Vec::new());
+            }
+
+            let backend = &*self.shared.backend;
+            let mut rehashed = Vec::new();
+            for key in self._snapshot_keys(tname, &info).await? {
+                let Some(context) = backend.read(&key).await? else {
+                    continue;
+                };
+                let digest = checksum::digest(&context);
+                if checksum::read_sidecar(backend, &key).await?.as_ref() == Some(&digest) {
+                    continue;
+                }
+                checksum::write_sidecar(backend, &key, &digest, self.shared.durability).await?;
+                trace::info!(
+                    tree = tname,
+                    key,
+                    "rewrote checksum of a snapshot edited outside the store"
+                );
+                rehashed.push(backend.location(&key));
+            }
+
+            if !tree.loaded {
+                tree.corrupt = None;
+            }
+            Ok(rehashed)
+        })
+        .await
+    }
+
+    // Throw away unsaved changes, reverting tree to its saved files. A tree that was
+    // never saved comes back empty with sequence 0.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn discard_changes(&self, tname: &str) -> Result<(), JsonStoreError> {
+        self._metered("discard_changes", Some(tname), async {
+            let mut tree = self._write_lock_raw(tname).await?;
+            // The log holds every write since the last save, which are the ones being
+            // discarded; left in place, the reload would replay them.
+            if !self.shared.read_only {
+                let key = self.shared.layout.wal_key(tname);
+                wal::truncate(&*self.shared.backend, &key).await?;
+            }
+            self._reload(tname, &mut tree).await
+        })
+        .await
+    }
+
+    // discard_changes for every dirty tree; returns the trees reverted
+    pub async fn discard_all_changes(&self) -> Result<Vec<String>, JsonStoreError> {
+        self._check_open()?;
+
+        let dirty = self.unsaved_changes().await;
+        for tname in &dirty {
+            self.discard_changes(tname).await?;
+        }
+
+        Ok(dirty)
+    }
+
+    async fn write_tree(
+        &self,
+        tname: &str,
+        tree: &mut Tree,
+        durability: Durability,
+    ) -> Result<(), JsonStoreError> {
+        let backend = &*self.shared.backend;
+        let key = self.shared.layout.seq_key(tname);
+        put_sequence(backend, &key, tree.sequence, durability).await?;
+        tree.seq_stamp = backend.stamp(&key).await?;
+
+        // an append log is written as it goes; only the counter needed saving
+        if tree.storage == StorageFormat::Snapshot {
+            self._write_snapshot(tname, tree, durability).await?;
+        }
+
+        if tree.history_changed {
+            let key = self.shared.layout.history_key(tname);
+            put_json(backend, &key, &tree.history, self.shared.format, durability).await?;
+            tree.history_changed = false;
+        }
+
+        tree.changed = false;
+        tree.pending_writes = 0;
+        tree.last_saved = Instant::now();
+
+        // records saved just now may go
+        self._trim(tname, tree).await
+    }
+
+    async fn _write_snapshot(
+        &self,
+        tname: &str,
+        tree: &mut Tree,
+        durability: Durability,
+    ) -> Result<(), JsonStoreError> {
+        self._write_data(tname, tree, durability).await?;
+
+        // the snapshot now holds everything logged so far
+        wal::truncate(&*self.shared.backend, &self.shared.layout.wal_key(tname)).await?;
+        tree.wal_entries = 0;
+
+        Ok(())
+    }
+
+    // write the snapshot file, or each dirty shard or partition
+    async fn _write_data(
+        &self,
+        tname: &str,
+        tree: &mut Tree,
+        durability: Durability,
+    ) -> Result<(), JsonStoreError> {
+        let backend = &*self.shared.backend;
+
+        if tree.partition_by.is_some() {
+            return self._write_partitions(tname, tree, durability).await;
+        }
+
+        if tree.shards == 0 {
+            let delta = self.shared.layout.delta_key(tname);
+            if self._writes_delta(tree) {
+                return write_delta(backend, &delta, tree, self.shared.format, durability).await;
+            }
+
+            let base = self.shared.layout.base(tname);
+            let key = self
+                .shared
+                .layout
+                .snapshot_key(&base, self.shared.codec, tree.compression);
+            let encoded = match tree.encoded.remove(&key) {
+                Some(encoded) => encoded,
+                None => {
+                    let records = tree.file_records(None).await?;
+                    encode_snapshot(&key, records, self.shared.codec, self.shared.format).await?
+                }
+            };
+            write_snapshot(backend, &key, encoded, durability).await?;
+            tree.data_stamp = backend.stamp(&key).await?;
+            backend.delete(&delta).await?;
+            tree.unsnapshotted.clear();
+            tree.removed_keys.clear();
+            tree.whole = false;
+
+            // the snapshot in another form, if the setting changed, is now stale
+            return remove_stale_snapshots(backend, &self.shared.layout, &base, &key).await;
+        }
+
+        while let Some(i) = tree.dirty_shards.first().copied() {
+            let base = shard_base(&self.shared.layout, tname, i);
+            let key = self
+                .shared
+                .layout
+                .snapshot_key(&base, self.shared.codec, tree.compression);
+            let encoded = match tree.encoded.remove(&key) {
+                Some(encoded) => encoded,
+                None => {
+                    let records = tree.file_records(Some(i)).await?;
+                    encode_snapshot(&key, records, self.shared.codec, self.shared.format).await?
+                }
+            };
+            write_snapshot(backend, &key, encoded, durability).await?;
+            tree.shard_stamps[i as usize] = backend.stamp(&key).await?;
+            remove_stale_snapshots(backend, &self.shared.layout, &base, &key).await?;
+
+            tree.dirty_shards.remove(&i);
+        }
+
+        Ok(())
+    }
+
+    // write each dirty partition, removing those left empty, then the index
+    async fn _write_partitions(
+        &self,
+        tname: &str,
+        tree: &mut Tree,
+        durability: Durability,
+    ) -> Result<(), JsonStoreError> {
+        let backend = &*self.shared.backend;
+        let layout = &self.shared.layout;
+
+        let dirty = tree
+            .partitions
+            .iter()
+            .filter(|(_, partition)| partition.dirty)
+            .map(|(period, _)| period.clone())
+            .collect::<Vec<_>>();
+        for period in dirty {
+            let base = partition_base(layout, tname, &period);
+            let sequences = &tree.partitions[&period].sequences;
+            if sequences.is_empty() {
+                remove_stale_snapshots(backend, layout, &base, "").await?;
+                tree.partitions.remove(&period);
+                tree.partitions_changed = true;
+                continue;
+            }
+
+            let records = FileRecords::Parsed(shared_records(
+                sequences
+                    .iter()
+                    .filter_map(|seq| tree.data.get_key_value(seq)),
+            ));
+            let key = layout.snapshot_key(&base, self.shared.codec, tree.compression);
+            let encoded =
+                encode_snapshot(&key, records, self.shared.codec, self.shared.format).await?;
+            write_snapshot(backend, &key, encoded, durability).await?;
+            remove_stale_snapshots(backend, layout, &base, &key).await?;
+
+            let stamp = backend.stamp(&key).await?;
+            if let Some(partition) = tree.partitions.get_mut(&period) {
+                partition.stamp = stamp;
+                partition.dirty = false;
+            }
+        }
+
+        if tree.partitions_changed {
+            let index = partition::index(&tree.partitions);
+            let key = layout.partitions_key(tname);
+            put_json(backend, &key, &index, self.shared.format, durability).await?;
+            tree.partitions_changed = false;
+        }
+
+        Ok(())
+    }
+
+    // Change the number of shards of a snapshot tree (0 for a single file) and
+    // rewrite its data in the new layout. The whole tree goes into its WAL first and
+    // the WAL is only emptied once infos.json names the new layout, so an
+    // interrupted reshard loses nothing: load replays the WAL over whichever files
+    // were written, and the next save or reshard finishes the job.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, shards = shards)))]
+    pub async fn reshard_tree(&self, tname: &str, shards: u32) -> Result<(), JsonStoreError> {
+        self._metered("reshard", Some(tname), async {
+            self._check_savable()?;
+
+            let info = self._info(tname)?;
+            self._check_frozen(tname, &info)?;
+            if info.partition_by.is_some() {
+                return Err(JsonStoreError::InvalidOptions(format!(
+                    "tree '{}' is partitioned and can't also be sharded",
+                    tname
+                )));
+            }
+            if info.resident_limit.is_some() && shards > 0 {
+                return Err(JsonStoreError::InvalidOptions(format!(
+                    "tree '{}' has a resident limit and can't be sharded",
+                    tname
+                )));
+            }
+
+            let _guard = self.shared.catalog_write.lock().await;
+
+            let mut tree = self._write_lock(tname).await?;
+
+            if tree.storage != StorageFormat::Snapshot || tree.shards == shards {
+                return Ok(());
+            }
+
+            if tree.changed {
+                self._save_locked(tname, &mut tree, self.shared.durability)
+                    .await?;
+            }
+
+            let backend = &*self.shared.backend;
+            let key = self.shared.layout.wal_key(tname);
+            let fsync = self.shared.durability == Durability::Fsync;
+            let entries = tree
+                .data
+                .sorted()
+                .into_iter()
+                .map(|(seq, value)| WalEntry::Insert {
+                    tree: tname.to_string(),
+                    seq: *seq,
+                    value,
+                })
+                .collect::<Vec<_>>();
+            wal::append_all(backend, &key, &entries, fsync).await?;
+            tree.wal_entries += entries.len() as u64;
+
+            let old = snapshot_bases(&self.shared.layout, tname, tree.shards);
+            tree.set_shards(shards);
+            self._write_data(tname, &mut tree, self.shared.durability)
+                .await?;
+
+            let infos = {
+                let mut catalog = self._catalog_mut();
+                if let Some(info) = catalog.infos.get_mut(tname) {
+                    info.shards = (shards > 0).then_some(shards);
+                }
+                catalog.infos.clone()
+            };
+            self._put_infos(&infos).await?;
+
+            let new = snapshot_bases(&self.shared.layout, tname, shards);
+            for base in old.iter().filter(|base| !new.contains(base)) {
+                remove_stale_snapshots(backend, &self.shared.layout, base, "").await?;
+            }
+
+            wal::truncate(backend, &key).await?;
+            tree.wal_entries = 0;
+
+            backend.sync().await
+        })
+        .await
+    }
+
+    // Persist a mutation before it is applied: append-log trees append it to their
+    // data file, other trees to their WAL if WAL mode is on.
+    async fn _log(
+        &self,
+        tname: &str,
+        tree: &mut Tree,
+        entry: &WalEntry<&Value>,
+    ) -> Result<(), JsonStoreError> {
+        if tree.storage == StorageFormat::AppendLog {
+            let (seq, value) = match entry {
+                WalEntry::Insert { seq, value, .. } | WalEntry::Update { seq, value, .. } => {
+                    (*seq, Some(*value))
+                }
+                WalEntry::Delete { seq, .. } => (*seq, None),
+            };
+            let key = self.shared.layout.log_key(tname);
+            let fsync = self.shared.durability == Durability::Fsync;
+            return append_log::append(&*self.shared.backend, &key, seq, value, fsync).await;
+        }
+
+        let Some(options) = self.shared.wal else {
+            return Ok(());
+        };
+
+        let key = self.shared.layout.wal_key(tname);
+        let entry = Stamped::new(entry, self.shared.clock.now());
+        wal::append(&*self.shared.backend, &key, &entry, options.fsync).await?;
+        tree.wal_entries += 1;
+
+        Ok(())
+    }
+
+    // _log for a write of a Raw tree's text, which only ever has snapshot storage
+    async fn _log_raw(
+        &self,
+        tname: &str,
+        tree: &mut Tree,
+        entry: &WalEntry<&RawValue>,
+    ) -> Result<(), JsonStoreError> {
+        let Some(options) = self.shared.wal else {
+            return Ok(());
+        };
+
+        let key = self.shared.layout.wal_key(tname);
+        let entry = Stamped::new(entry, self.shared.clock.now());
+        wal::append(&*self.shared.backend, &key, &entry, options.fsync).await?;
+        tree.wal_entries += 1;
+
+        Ok(())
+    }
+
+    // Rewind a tree kept in WAL mode to an earlier state: its snapshot plus the logged
+    // changes up to point. The tree's files are copied into `.pre-rewind-{millis}/`
+    // first, so repair_tree can restore them from there to undo the rewind. The log
+    // is cut back to point and the tree left dirty for the next save.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, point = ?point)))]
+    pub async fn restore_tree_to(
+        &self,
+        tname: &str,
+        point: RestorePoint,
+    ) -> Result<(), JsonStoreError> {
+        self._metered("restore_tree_to", Some(tname), async {
+            let info = self._writable_info(tname)?;
+            let Some(options) = self
+                .shared
+                .wal
+                .filter(|_| info.storage == StorageFormat::Snapshot)
+            else {
+                return Err(JsonStoreError::InvalidOptions(format!(
+                    "tree '{}' keeps no write-ahead log to restore from",
+                    tname
+                )));
+            };
+            if info.partition_by.is_some() {
+                return Err(JsonStoreError::InvalidOptions(format!(
+                    "tree '{}' is partitioned, which restore_tree_to doesn't support",
+                    tname
+                )));
+            }
+
+            let mut tree = self._write_lock(tname).await?;
+
+            let backend = &*self.shared.backend;
+            let key = self.shared.layout.wal_key(tname);
+            let entries = wal::read_entries(backend, &key).await?;
+
+            let unavailable = |reason: String| JsonStoreError::RestorePointUnavailable {
+                tree: tname.to_string(),
+                reason,
+            };
+            let keep = match point {
+                RestorePoint::Change(n) if n > entries.len() as u64 => {
+                    return Err(unavailable(format!(
+                        "the log holds {} changes since the last checkpoint",
+                        entries.len()
+                    )));
+                }
+                RestorePoint::Change(n) => n as usize,
+                RestorePoint::Time(time) => {
+                    let Some(first) = entries.first() else {
+                        return Err(unavailable(
+                            "nothing is logged since the last checkpoint, which is the \
+                             earliest restorable point"
+                                .to_string(),
+                        ));
+                    };
+                    match first.time() {
+                        None => {
+                            return Err(unavailable(
+                                "the log predates timestamps; restore to a change instead"
+                                    .to_string(),
+                            ));
+                        }
+                        Some(earliest) if time < earliest => {
+                            return Err(unavailable(format!(
+                                "the earliest restorable time is {} ms after the epoch, \
+                                 when the first change since the last checkpoint was made",
+                                first.at.unwrap_or_default()
+                            )));
+                        }
+                        Some(_) => entries
+                            .iter()
+                            .take_while(|entry| entry.time().is_some_and(|at| at <= time))
+                            .count(),
+                    }
+                }
+            };
+
+            let millis = rt::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis();
+            let aside = format!(".pre-rewind-{}", millis);
+            let keys = tree_files(&self.shared.layout, tname, &info);
+            set_aside(backend, &keys, &aside, false).await?;
+
+            wal::rewrite(backend, &key, &entries[..keep], options.fsync).await?;
+
+            let mut fresh = read_tree(
+                backend,
+                &self.shared.layout,
+                tname,
+                &info,
+                self.shared.codec,
+                false,
+                self.shared.spill_dir.as_deref(),
+            )
+            .await?;
+            // sequences handed out to records rewound away are not handed out again
+            fresh.sequence = fresh.sequence.max(tree.sequence);
+            fresh.changed = true;
+            fresh.dirty_shards = (0..fresh.shards).collect();
+            tree.replace_contents(fresh);
+            trace::warn!(
+                tree = tname,
+                aside,
+                kept = keep,
+                dropped = entries.len() - keep,
+                "rewound tree"
+            );
+
+            backend.sync().await
+        })
+        .await
+    }
+
+    // Make this replica match the store at source, which is opened read-only and never
+    // written; trees and records the source lacks are removed. Each synced tree is
+    // saved before its cursor moves on, so an interrupted sync just repeats work.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(source = ?source)))]
+    pub async fn sync_from(&self, source: &Path) -> Result<SyncReport, JsonStoreError> {
+        self._metered("sync_from", None, async {
+            self._check_savable()?;
+            if !self.shared.replica {
+                return Err(JsonStoreError::InvalidOptions(
+                    "sync_from needs a store opened as a replica".to_string(),
+                ));
+            }
+
+            let source = JsonStore::load_read_only(source).await?;
+            let mut report = SyncReport::default();
+
+            for tname in self.list_trees() {
+                if !source.has_tree(&tname) {
+                    self._drop_tree(&tname).await?;
+                    report.dropped.push(tname);
+                }
+            }
+
+            for tname in source.list_trees() {
+                let synced = self._sync_tree(&source, &tname).await?;
+                if synced.applied() > 0 {
+                    trace::info!(
+                        tree = tname,
+                        inserted = synced.inserted,
+                        updated = synced.updated,
+                        deleted = synced.deleted,
+                        from_log = synced.from_log,
+                        "synced tree"
+                    );
+                }
+                report.trees.insert(tname, synced);
+            }
+
+            Ok(report)
+        })
+        .await
+    }
+
+    async fn _sync_tree(
+        &self,
+        source: &JsonStore,
+        tname: &str,
+    ) -> Result<TreeSync, JsonStoreError> {
+        let info = source._info(tname)?;
+
+        // a tree set up differently is replaced; metadata and the frozen flag are
+        // copied over afterwards
+        let cursor = match self._info(tname) {
+            Ok(stored) if stored.shape() == info.shape() => stored.sync_cursor,
+            Ok(_) => {
+                self._drop_tree(tname).await?;
+                self._create_tree(tname, info.clone()).await?;
+                None
+            }
+            Err(_) => {
+                self._create_tree(tname, info.clone()).await?;
+                None
+            }
+        };
+
+        let backend = &*source.shared.backend;
+        let wal_key = source.shared.layout.wal_key(tname);
+        let mut stamps = Vec::new();
+        for key in stored_tree_files(backend, &source.shared.layout, tname, &info).await? {
+            if key != wal_key {
+                stamps.push(backend.stamp(&key).await?);
+            }
+        }
+        let snapshot = replica::fingerprint(&stamps);
+
+        // with the same snapshot underneath, the entries logged since are all that changed
+        let logged = match cursor {
+            Some(cursor)
+                if cursor.snapshot == snapshot && info.storage == StorageFormat::Snapshot =>
+            {
+                let entries = wal::read_entries(backend, &wal_key).await?;
+                (entries.len() as u64 >= cursor.changes).then_some((entries, cursor.changes))
+            }
+            _ => None,
+        };
+
+        let mut synced = TreeSync::default();
+        let mut tree = self._write_lock(tname).await?;
+
+        let (changes, sequence, seen) = match logged {
+            Some((entries, seen)) => {
+                synced.from_log = true;
+                let mut sequence = tree.sequence;
+                let changes = entries
+                    .iter()
+                    .skip(seen as usize)
+                    .map(|entry| match &entry.entry {
+                        WalEntry::Insert { seq, value, .. }
+                        | WalEntry::Update { seq, value, .. } => {
+                            sequence = sequence.max(*seq);
+                            (*seq, Some(Arc::new(value.clone())))
+                        }
+                        WalEntry::Delete { seq, .. } => (*seq, None),
+                    })
+                    .collect::<Vec<_>>();
+                (changes, sequence, entries.len() as u64)
+            }
+            None => {
+                let from = source._read_lock(tname).await?;
+                let mut changes = from
+                    .data
+                    .sorted()
+                    .into_iter()
+                    .filter(|(seq, value)| tree.data.get(seq) != Some(value))
+                    .map(|(seq, value)| (*seq, Some(value.clone())))
+                    .collect::<Vec<_>>();
+                changes.extend(
+                    tree.data
+                        .sorted()
+                        .into_keys()
+                        .filter(|seq| !from.data.contains_key(seq))
+                        .map(|seq| (*seq, None)),
+                );
+                (changes, from.sequence, from.wal_entries)
+            }
+        };
+
+        for (seq, value) in changes.iter() {
+            let entry = match (value.as_deref(), tree.data.contains_key(seq)) {
+                (Some(value), true) => {
+                    synced.updated += 1;
+                    WalEntry::Update {
+                        tree: tname.to_string(),
+                        seq: *seq,
+                        value,
+                    }
+                }
+                (Some(value), false) => {
+                    synced.inserted += 1;
+                    WalEntry::Insert {
+                        tree: tname.to_string(),
+                        seq: *seq,
+                        value,
+                    }
+                }
+                (None, true) => {
+                    synced.deleted += 1;
+                    WalEntry::Delete {
+                        tree: tname.to_string(),
+                        seq: *seq,
+                    }
+                }
+                (None, false) => continue,
+            };
+            self._log(tname, &mut tree, &entry).await?;
+
+            match value {
+                Some(value) => tree.put(*seq, value.clone()),
+                None => tree.take(*seq),
+            };
+            tree.touch(*seq);
+            tree.changed = true;
+        }
+        if tree.sequence < sequence {
+            tree.sequence = sequence;
+            tree.changed = true;
+        }
+        if tree.changed {
+            self._save_locked(tname, &mut tree, self.shared.durability)
+                .await?;
+        }
+        drop(tree);
+
+        let cursor = Some(SyncCursor {
+            snapshot,
+            changes: seen,
+        });
+
+        let _guard = self.shared.catalog_write.lock().await;
+        let infos = {
+            let mut catalog = self._catalog_mut();
+            match catalog.infos.get_mut(tname) {
+                Some(stored)
+                    if stored.metadata != info.metadata
+                        || stored.frozen != info.frozen
+                        || stored.migration_version != info.migration_version
+                        || stored.sync_cursor != cursor =>
+                {
+                    stored.metadata = info.metadata.clone();
+                    stored.frozen = info.frozen;
+                    stored.migration_version = info.migration_version;
+                    stored.sync_cursor = cursor;
+                    Some(catalog.infos.clone())
+                }
+                _ => None,
+            }
+        };
+        if let Some(infos) = infos {
+            self._put_infos(&infos).await?;
+            self.shared.backend.sync().await?;
+        }
+
+        Ok(synced)
+    }
+
+    pub async fn merge_from(
+        &self,
+        other: &Path,
+        policy: MergePolicy,
+    ) -> Result<MergeReport, JsonStoreError> {
+        let options = MergeOptions {
+            policy,
+            ..MergeOptions::default()
+        };
+        self.merge_from_with(other, options).await
+    }
+
+    // Merge in the trees of the store at other, which is opened read-only. Trees only
+    // there are copied whole, Info and sequences included. In trees both have, records
+    // are matched by unique constraints and string keys, or by sequence in trees
+    // without either, and options.policy settles matches that differ. A tree that
+    // can't be merged is left as it was and listed in the report's failed; the others
+    // are still merged.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(other = ?other)))]
+    pub async fn merge_from_with(
+        &self,
+        other: &Path,
+        options: MergeOptions,
+    ) -> Result<MergeReport, JsonStoreError> {
+        self._metered("merge_from", None, async {
+            self._check_writable()?;
+
+            let other = JsonStore::load_read_only(other).await?;
+            let mut report = MergeReport::default();
+
+            for tname in other.list_trees() {
+                let info = other._info(&tname)?;
+                let created = !self.has_tree(&tname);
+                if created {
+                    // a name folding like one here's, say
+                    if let Err(e) = self._create_tree(&tname, info.clone()).await {
+                        trace::warn!(tree = tname, error = %e, "tree not merged");
+                        report.failed.push((tname, e));
+                        continue;
+                    }
+                    report.created.push(tname.clone());
+                }
+
+                match self
+                    ._merge_tree(&other, &tname, &info, created, options)
+                    .await
+                {
+                    Ok(merged) => {
+                        report.trees.insert(tname, merged);
+                    }
+                    Err(e) => {
+                        trace::warn!(tree = tname, error = %e, "tree not merged");
+                        report.failed.push((tname, e));
+                    }
+                }
+            }
+
+            Ok(report)
+        })
+        .await
+    }
+
+    async fn _merge_tree(
+        &self,
+        other: &JsonStore,
+        tname: &str,
+        other_info: &Info,
+        created: bool,
+        options: MergeOptions,
+    ) -> Result<TreeMerge, JsonStoreError> {
+        let info = self._info(tname)?;
+        self._check_frozen(tname, &info)?;
+        if info.sequence_field != other_info.sequence_field {
+            return Err(JsonStoreError::InfoMismatch {
+                tree: tname.to_string(),
+                stored: Box::new(info),
+                requested: Box::new(other_info.clone()),
+            });
+        }
+        let field = info.sequence_field.as_str();
+
+        let from = other._read_lock(tname).await?;
+        let mut tree = self._write_lock(tname).await?;
+
+        let mut merged = TreeMerge::default();
+        // records to write, under the sequence each gets here
+        let mut plan: Vec<(u64, Arc<Value>)> = Vec::new();
+
+        if created {
+            plan.extend(
+                from.data
+                    .sorted()
+                    .into_iter()
+                    .map(|(seq, value)| (*seq, value.clone())),
+            );
+            merged.merged = plan.len();
+        } else {
+            // matched against the tree's unique index, the one writes check, so each
+            // record of the other store costs a lookup per constraint
+            tree.unique_index(&info);
+
+            let mut claimed = HashSet::new();
+            let mut next = tree.sequence;
+            for (seq, value) in from.data.sorted() {
+                let collision = |reason: String| JsonStoreError::MergeConflict {
+                    tree: tname.to_string(),
+                    sequence: *seq,
+                    reason,
+                };
+
+                let mut matches: BTreeSet<u64> = match info.unique() {
+                    true => tree
+                        .unique
+                        .iter()
+                        .flat_map(|index| index.owners(value))
+                        .collect(),
+                    false => tree
+                        .data
+                        .contains_key(seq)
+                        .then_some(*seq)
+                        .into_iter()
+                        .collect(),
+                };
+                if matches.is_empty() && options.preserve_sequences && tree.data.contains_key(seq) {
+                    matches.insert(*seq);
+                }
+
+                // the one record here it stands against, or why there isn't one
+                let target = match matches.iter().copied().collect::<Vec<_>>()[..] {
+                    [] => {
+                        let new = match options.preserve_sequences {
+                            true => *seq,
+                            false => {
+                                next += 1;
+                                next
+                            }
+                        };
+                        plan.push((new, value.clone()));
+                        merged.merged += 1;
+                        continue;
+                    }
+                    [target] if claimed.insert(target) => Ok(target),
+                    [target] => Err(format!(
+                        "sequence {} here matches another record too",
+                        target
+                    )),
+                    _ => Err(format!("matches sequences {:?} here", matches)),
+                };
+
+                if let Ok(target) = target {
+                    if merge::same_record(&tree.data[&target], value, field) {
+                        merged.skipped += 1;
+                        continue;
+                    }
+                }
+
+                merged.conflicting += 1;
+                match (options.policy, target) {
+                    (MergePolicy::PreferSelf, _) => merged.skipped += 1,
+                    (MergePolicy::PreferOther, Ok(target)) => {
+                        plan.push((target, value.clone()));
+                        merged.merged += 1;
+                    }
+                    (_, Ok(target)) => {
+                        return Err(collision(format!("differs from sequence {} here", target)));
+                    }
+                    (_, Err(reason)) => return Err(collision(reason)),
+                }
+            }
+        }
+
+        let added = plan
+            .iter()
+            .filter(|(seq, _)| !tree.data.contains_key(seq))
+            .count();
+        if tree.data.len() + added > info.capacity as usize {
+            return Err(JsonStoreError::CapacityExceeded(tname.to_string()));
+        }
+
+        let mut prior = Vec::new();
+        for (seq, mut value) in plan {
+            // shared with the other store until the sequence field is set here
+            if let Some(object) = Arc::make_mut(&mut value).as_object_mut() {
+                object.insert(field.to_string(), seq.into());
+            }
+            let entry = match tree.data.contains_key(&seq) {
+                true => WalEntry::Update {
+                    tree: tname.to_string(),
+                    seq,
+                    value: &*value,
+                },
+                false => WalEntry::Insert {
+                    tree: tname.to_string(),
+                    seq,
+                    value: &*value,
+                },
+            };
+            self._log(tname, &mut tree, &entry).await?;
+
+            tree.sequence = tree.sequence.max(seq);
+            let replaced = tree.put(seq, value);
+            prior.push((seq, replaced.clone()));
+            if let Some(replaced) = replaced {
+                self._record_history(&info, &mut tree, seq, replaced, HistoryOp::Update);
+            }
+            tree.touch(seq);
+        }
+        // a copied tree carries on counting where the other left off
+        if created && from.sequence > tree.sequence {
+            tree.sequence = from.sequence;
+            tree.changed = true;
+        }
+
+        if !prior.is_empty() {
+            self._push_undo(&mut tree, UndoOp::Merge, prior);
+            self._written(tname, &mut tree).await?;
+        }
+
+        Ok(merged)
+    }
+
+    // Fold the tree's WAL into a fresh snapshot and empty the log. Writers append to
+    // the log under the tree's write lock, which is held here from snapshot to
+    // truncate, so no entry can slip in between and be lost.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn checkpoint(&self, tname: &str) -> Result<CheckpointStats, JsonStoreError> {
+        self._metered("checkpoint", Some(tname), async {
+            self._check_savable()?;
+
+            let mut tree = self._write_lock_saving(tname).await?;
+
+            let stats = CheckpointStats {
+                bytes_reclaimed: wal::size(
+                    &*self.shared.backend,
+                    &self.shared.layout.wal_key(tname),
+                )
+                .await?,
+                entries_folded: tree.wal_entries,
+            };
+
+            if tree.changed {
+                self._save_locked(tname, &mut tree, self.shared.durability)
+                    .await?;
+            }
+
+            Ok(stats)
+        })
+        .await
+    }
+
+    // Rewrite an append-log tree's file without superseded lines and tombstones.
+    // Returns the bytes reclaimed; snapshot trees have nothing to compact.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn compact_tree(&self, tname: &str) -> Result<u64, JsonStoreError> {
+        self._metered("compact", Some(tname), async {
+            self._check_savable()?;
+
+            let mut tree = self._write_lock(tname).await?;
+
+            if tree.storage != StorageFormat::AppendLog {
+                return Ok(0);
+            }
+
+            // the counter must be on disk before tombstones that may carry the highest sequence go
+            let backend = &*self.shared.backend;
+            let key = self.shared.layout.seq_key(tname);
+            put_sequence(backend, &key, tree.sequence, self.shared.durability).await?;
+            tree.seq_stamp = backend.stamp(&key).await?;
+
+            let key = self.shared.layout.log_key(tname);
+            let before = wal::size(backend, &key).await?;
+            let context = append_log::encode(&tree.data)?;
+            let after = context.len() as u64;
+            backend.write(&key, context, self.shared.durability).await?;
+
+            Ok(before.saturating_sub(after))
+        })
+        .await
+    }
+
+    // Check tname's records against its unique constraints and sequence counter, and
+    // that each holds its own sequence; see integrity.rs. Every record is read,
+    // spilled and partitioned ones too. Meant for monitoring: nothing is changed.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn validate_tree(&self, tname: &str) -> Result<TreeIntegrityReport, JsonStoreError> {
+        self._metered("validate_tree", Some(tname), async {
+            let info = self._info(tname)?;
+            let tree = self._read_lock(tname).await?;
+            Ok(integrity::check(&info, tree.sequence, &tree.data))
+        })
+        .await
+    }
+
+    // Check every tree's data file against its checksum, reading the files from
+    // disk without loading them into the store.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn verify(&self) -> Result<VerifyReport, JsonStoreError> {
+        self._metered("verify", None, async {
+            self._check_open()?;
+
+            let trees = self
+                ._catalog()
+                .infos
+                .iter()
+                .map(|(tname, info)| (tname.clone(), info.clone()))
+                .collect::<Vec<_>>();
+
+            let mut report = VerifyReport::default();
+            for (tname, info) in trees {
+                let status = match info.storage {
+                    StorageFormat::Snapshot => {
+                        // a sharded or partitioned tree reports its first file that isn't Ok
+                        let mut status = ChecksumStatus::Ok;
+                        for key in self._snapshot_keys(&tname, &info).await? {
+                            status = checksum::status(&*self.shared.backend, &key).await?;
+                            if status != ChecksumStatus::Ok {
+                                break;
+                            }
+                        }
+                        status
+                    }
+                    StorageFormat::AppendLog => ChecksumStatus::NoChecksum,
+                };
+                report.trees.insert(tname, status);
+            }
+
+            Ok(report)
+        })
+        .await
+    }
+
+    // the key of each snapshot file of a snapshot tree, whichever of its shards or
+    // partitions it is
+    async fn _snapshot_keys(
+        &self,
+        tname: &str,
+        info: &Info,
+    ) -> Result<Vec<String>, JsonStoreError> {
+        let backend = &*self.shared.backend;
+        let layout = &self.shared.layout;
+        let bases = match info.partition_by {
+            Some(_) => read_periods(backend, layout, tname, info)
+                .await?
+                .iter()
+                .map(|period| partition_base(layout, tname, period))
+                .collect(),
+            None => snapshot_bases(layout, tname, info.shards.unwrap_or(0)),
+        };
+        let mut keys = Vec::with_capacity(bases.len());
+        for base in bases {
+            keys.push(
+                snapshot_file(backend, layout, &base, self.shared.codec, info.compression).await?,
+            );
+        }
+        Ok(keys)
+    }
+
+    pub async fn backup(&self, dest: &Path) -> Result<BackupReport, JsonStoreError> {
+        self.backup_with(dest, BackupOptions::default()).await
+    }
+
+    // Flush dirty trees, then copy the store's files into dest. The catalog and every
+    // tree are held for the copy (trees read-locked in name order), so the copied
+    // files agree with each other and the backup loads like any store directory.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(dest = ?dest)))]
+    pub async fn backup_with(
+        &self,
+        dest: &Path,
+        options: BackupOptions,
+    ) -> Result<BackupReport, JsonStoreError> {
+        self._metered("backup", None, async {
+            self._check_open()?;
+
+            backup::prepare(dest, options.overwrite).await?;
+
+            let _guard = self.shared.catalog_write.lock().await;
+
+            if !self.shared.read_only {
+                self.save().await?;
+            }
+
+            let infos = sorted(&self._catalog().infos)
+                .into_iter()
+                .map(|(tname, info)| (tname.clone(), info.clone()))
+                .collect::<Vec<_>>();
+
+            let mut _trees = Vec::with_capacity(infos.len());
+            for (tname, _) in infos.iter() {
+                _trees.push(self._read_lock_raw(tname).await?);
+            }
+
+            let mut names = vec![META_FILE.to_string(), self.shared.layout.infos_file.clone()];
+            let backend = &*self.shared.backend;
+            for (tname, info) in infos.iter() {
+                names.extend(stored_tree_files(backend, &self.shared.layout, tname, info).await?);
+            }
+
+            let mut files = BTreeMap::new();
+            for name in names {
+                if let Some(len) = backup::copy(&*self.shared.backend, dest, &name).await? {
+                    files.insert(name, len);
+                }
+            }
+
+            Ok(BackupReport {
+                path: dest.into(),
+                files,
+                created_at: self.shared.clock.now(),
+            })
+        })
+        .await
+    }
+
+    // Write the store out as a directory that JsonStore::load opens, typically to keep
+    // an in_memory store. Same as backup: dest must be empty or missing.
+    pub async fn persist_to(&self, dest: &Path) -> Result<(), JsonStoreError> {
+        self.backup(dest).await.map(|_| ())
+    }
+
+    // write every record of tree to path, in sequence order; returns the record count
+    pub async fn export_tree(
+        &self,
+        tname: &str,
+        path: &Path,
+        format: ExportFormat,
+    ) -> Result<u64, JsonStoreError> {
+        self.export_tree_where(tname, path, format, |_| true).await
+    }
+
+    // Write the live records of each tree into a table of the SQLite database at path,
+    // returning the rows each got; see sqlite.rs. The records are gathered a tree at a
+    // time, under its read lock, and written once all are.
+    #[cfg(feature = "sqlite")]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(path = ?path)))]
+    pub async fn export_sqlite(
+        &self,
+        path: &Path,
+        options: SqliteExportOptions,
+    ) -> Result<BTreeMap<String, u64>, JsonStoreError> {
+        self._metered("export_sqlite", None, async {
+            let tnames = match &options.trees {
+                Some(tnames) => tnames.clone(),
+                None => self.list_trees(),
+            };
+
+            let mut tables = Vec::with_capacity(tnames.len());
+            for tname in tnames {
+                let info = self._info(&tname)?;
+                let expiry = self._expiry(&info);
+                let tree = self._read_lock(&tname).await?;
+                let records = tree
+                    .data
+                    .sorted()
+                    .into_iter()
+                    .filter(|(_, value)| expiry::live(expiry.as_ref(), value))
+                    .map(|(sequence, value)| (*sequence, value.clone()))
+                    .collect();
+                tables.push(sqlite::Table {
+                    name: tname,
+                    sequence_field: info.sequence_field,
+                    records,
+                });
+            }
+
+            sqlite::write(path, tables, &options).await
+        })
+        .await
+    }
+
+    // every record of tname that filter accepts, in sequence order
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn select_where<T: DeserializeOwned, F: Fn(&Value) -> bool>(
+        &self,
+        tname: &str,
+        filter: F,
+    ) -> Result<Vec<T>, JsonStoreError> {
+        self._metered("select", Some(tname), async {
+            let expiry = self._expiry(&self._info(tname)?);
+            let tree = self._read_lock_scan(tname).await?;
+
+            let mut selected = Vec::new();
+            let mut scan = pin!(tree.scan());
+            while let Some(record) = scan.next().await {
+                let (sequence, value) = record?;
+                if !expiry::live(expiry.as_ref(), &value) || !filter(&value) {
+                    continue;
+                }
+                let value = T::deserialize(&*value).map_err(|source| {
+                    JsonStoreError::DeserializeRecord {
+                        tree: tname.to_string(),
+                        sequence,
+                        source,
+                    }
+                })?;
+                selected.push(value);
+            }
+            Ok(selected)
+        })
+        .await
+    }
+
+    // A page of select_where: the limit records filter accepts after the first offset,
+    // and how many it accepts in all. Only the page's records are deserialized.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, offset = offset, limit = limit)))]
+    pub async fn select_page<T: DeserializeOwned, F: Fn(&Value) -> bool>(
+        &self,
+        tname: &str,
+        filter: F,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<T>, usize), JsonStoreError> {
+        self._metered("select", Some(tname), async {
+            let expiry = self._expiry(&self._info(tname)?);
+            let tree = self._read_lock_scan(tname).await?;
+
+            let (mut page, mut total) = (Vec::new(), 0);
+            let mut scan = pin!(tree.scan());
+            while let Some(record) = scan.next().await {
+                let (sequence, value) = record?;
+                if !expiry::live(expiry.as_ref(), &value) || !filter(&value) {
+                    continue;
+                }
+                total += 1;
+                if total <= offset || page.len() >= limit {
+                    continue;
+                }
+                let value = T::deserialize(&*value).map_err(|source| {
+                    JsonStoreError::DeserializeRecord {
+                        tree: tname.to_string(),
+                        sequence,
+                        source,
+                    }
+                })?;
+                page.push(value);
+            }
+            Ok((page, total))
+        })
+        .await
+    }
+
+    // The records of tname whose field equals value, in sequence order, a missing
+    // field counting as null. Taken from the field's index if it has one (see
+    // create_index), otherwise by looking at every record.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, field = field)))]
+    pub async fn find_by_field<T: DeserializeOwned>(
+        &self,
+        tname: &str,
+        field: &str,
+        value: &Value,
+    ) -> Result<Vec<T>, JsonStoreError> {
+        self._metered("find_by_field", Some(tname), async {
+            let expiry = self._expiry(&self._info(tname)?);
+            let tree = self._read_lock_scan(tname).await?;
+
+            let mut sequences = match tree.indexes.lookup(field, value) {
+                Some(sequences) => sequences,
+                None => {
+                    let mut sequences = Vec::new();
+                    let mut scanned = 0;
+                    let mut scan = pin!(tree.scan());
+                    while let Some(record) = scan.next().await {
+                        let (seq, record) = record?;
+                        scanned += 1;
+                        if record[field] == *value {
+                            sequences.push(seq);
+                        }
+                    }
+                    self._record_scan(tname, scanned);
+                    sequences
+                }
+            };
+            retain_live(&tree, &mut sequences, expiry.as_ref()).await?;
+
+            deserialize_records(tname, &tree, sequences).await
+        })
+        .await
+    }
+
+    // The records of tname whose field lies in range under index::compare, by field
+    // and then sequence; an open end stops at the type of the other, so ..100 finds
+    // numbers below 100 and not nulls or booleans. Taken from the field's ordered
+    // index if it has one (see create_index_ordered), otherwise by a scan and sort.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, field = field)))]
+    pub async fn find_range<T: DeserializeOwned, R: RangeBounds<Value>>(
+        &self,
+        tname: &str,
+        field: &str,
+        range: R,
+    ) -> Result<Vec<T>, JsonStoreError> {
+        self._metered("find_range", Some(tname), async {
+            let expiry = self._expiry(&self._info(tname)?);
+            let tree = self._read_lock_scan(tname).await?;
+
+            let mut sequences = match tree.indexes.range(field, &range) {
+                Some(sequences) => sequences,
+                None => {
+                    let mut found = Vec::new();
+                    let mut scanned = 0;
+                    let mut scan = pin!(tree.scan());
+                    while let Some(record) = scan.next().await {
+                        let (seq, record) = record?;
+                        scanned += 1;
+                        if index::in_range(&record[field], &range) {
+                            found.push((seq, record));
+                        }
+                    }
+                    self._record_scan(tname, scanned);
+                    found.sort_by(|(a_seq, a), (b_seq, b)| {
+                        index::compare(&a[field], &b[field]).then(a_seq.cmp(b_seq))
+                    });
+                    found.into_iter().map(|(seq, _)| seq).collect()
+                }
+            };
+            retain_live(&tree, &mut sequences, expiry.as_ref()).await?;
+
+            deserialize_records(tname, &tree, sequences).await
+        })
+        .await
+    }
+
+    // The first limit records of tname by field in order, those with equal values by
+    // sequence; a record without the field counts as null. With an ordered index on
+    // field only those records are looked at, otherwise every one is.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, field = field, limit = limit)))]
+    pub async fn find_sorted<T: DeserializeOwned>(
+        &self,
+        tname: &str,
+        field: &str,
+        order: SortOrder,
+        limit: usize,
+    ) -> Result<Vec<T>, JsonStoreError> {
+        self._metered("find_sorted", Some(tname), async {
+            let expiry = self._expiry(&self._info(tname)?);
+            let tree = self._read_lock_scan(tname).await?;
+
+            let sequences = match tree.indexes.sorted(field, order, limit) {
+                Some(mut sequences) => {
+                    retain_live(&tree, &mut sequences, expiry.as_ref()).await?;
+                    // expired records were passed over, so ask the index for more
+                    // until limit are live or it has no more to give
+                    let mut want = limit;
+                    while expiry.is_some() && sequences.len() < limit && want < tree.len() {
+                        want = want.saturating_mul(2);
+                        sequences = tree.indexes.sorted(field, order, want).unwrap_or_default();
+                        retain_live(&tree, &mut sequences, expiry.as_ref()).await?;
+                    }
+                    sequences.truncate(limit);
+                    sequences
+                }
+                None => {
+                    let by = |(a_seq, a): &(u64, Arc<Value>), (b_seq, b): &(u64, Arc<Value>)| {
+                        let by_field = match order {
+                            SortOrder::Ascending => index::compare(&a[field], &b[field]),
+                            SortOrder::Descending => index::compare(&b[field], &a[field]),
+                        };
+                        by_field.then(a_seq.cmp(b_seq))
+                    };
+                    // only the first limit need sorting, and only those are kept past
+                    // every so many records
+                    let keep = limit.saturating_mul(2).max(1_024);
+                    let mut found = Vec::new();
+                    let mut scanned = 0;
+                    let mut scan = pin!(tree.scan());
+                    while let Some(record) = scan.next().await {
+                        let (seq, value) = record?;
+                        scanned += 1;
+                        if !expiry::live(expiry.as_ref(), &value) {
+                            continue;
+                        }
+                        found.push((seq, value));
+                        if found.len() >= keep {
+                            found.select_nth_unstable_by(limit, by);
+                            found.truncate(limit);
+                        }
+                    }
+                    self._record_scan(tname, scanned);
+                    if limit < found.len() {
+                        found.select_nth_unstable_by(limit, by);
+                        found.truncate(limit);
+                    }
+                    found.sort_by(by);
+                    found.into_iter().map(|(seq, _)| seq).collect()
+                }
+            };
+
+            deserialize_records(tname, &tree, sequences).await
+        })
+        .await
+    }
+
+    // the records of a partitioned tree whose time lies in [from, to), in sequence
+    // order; only the partitions of periods overlapping it are read
+    pub async fn select_range<T: DeserializeOwned>(
+        &self,
+        tname: &str,
+        from: SystemTime,
+        to: SystemTime,
+    ) -> Result<Vec<T>, JsonStoreError> {
+        self.select_range_where(tname, from, to, |_| true).await
+    }
+
+    // select_range limited to the records filter accepts
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn select_range_where<T: DeserializeOwned, F: Fn(&Value) -> bool>(
+        &self,
+        tname: &str,
+        from: SystemTime,
+        to: SystemTime,
+        filter: F,
+    ) -> Result<Vec<T>, JsonStoreError> {
+        self._metered("select", Some(tname), async {
+            let spec = self._partition_spec(tname)?;
+            let expiry = self._expiry(&self._info(tname)?);
+            let (from, to) = (partition::millis(from), partition::millis(to));
+            let periods = |tree: &Tree| {
+                tree.partitions
+                    .keys()
+                    .filter(|period| spec.overlaps(period, from, to))
+                    .cloned()
+                    .collect::<Vec<_>>()
+            };
+
+            let tree = self._read_lock_periods(tname, periods).await?;
+
+            let mut records = BTreeMap::new();
+            for period in periods(&tree) {
+                for seq in tree.partitions[&period].sequences.iter() {
+                    let Some(value) = tree.data.get(seq) else {
+                        continue;
+                    };
+                    let time = spec.time(value);
+                    if time.is_some_and(|time| from <= time && time < to)
+                        && expiry::live(expiry.as_ref(), value)
+                        && filter(value)
+                    {
+                        records.insert(*seq, value);
+                    }
+                }
+            }
+
+            records
+                .into_iter()
+                .map(|(sequence, value)| {
+                    T::deserialize(&**value).map_err(|source| JsonStoreError::DeserializeRecord {
+                        tree: tname.to_string(),
+                        sequence,
+                        source,
+                    })
+                })
+                .collect()
+        })
+        .await
+    }
+
+    // export_tree limited to the records filter accepts
+    pub async fn export_tree_where<F: Fn(&Value) -> bool>(
+        &self,
+        tname: &str,
+        path: &Path,
+        format: ExportFormat,
+        filter: F,
+    ) -> Result<u64, JsonStoreError> {
+        self.export_tree_with(tname, path, format, &Redaction::default(), filter)
+            .await
+    }
+
+    // export_tree_where with fields of the written records redacted
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, path = ?path)))]
+    pub async fn export_tree_with<F: Fn(&Value) -> bool>(
+        &self,
+        tname: &str,
+        path: &Path,
+        format: ExportFormat,
+        redaction: &Redaction,
+        filter: F,
+    ) -> Result<u64, JsonStoreError> {
+        self._metered("export", Some(tname), async {
+            let expiry = self._expiry(&self._info(tname)?);
+            let tree = self._read_lock(tname).await?;
+
+            let sorted = tree.data.sorted();
+            let records = sorted
+                .values()
+                .map(|v| &***v)
+                .filter(|v| expiry::live(expiry.as_ref(), v) && filter(v));
+            export::write(path, &format, redaction, records).await
+        })
+        .await
+    }
+
+    // Move the records of tname that filter accepts to dest, returning how many. Into
+    // a tree they all go or none do: both trees are locked and every record checked
+    // against dest before either changes. Into a file they are appended and flushed
+    // before any leaves tname. The move can't be undone with undo_last.
+    pub async fn archive_where<F: Fn(&Value) -> bool>(
+        &self,
+        tname: &str,
+        filter: F,
+        dest: ArchiveDest,
+    ) -> Result<u64, JsonStoreError> {
+        self.archive_where_with(tname, filter, dest, ArchiveOptions::default())
+            .await
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, dest = ?dest)))]
+    pub async fn archive_where_with<F: Fn(&Value) -> bool>(
+        &self,
+        tname: &str,
+        filter: F,
+        dest: ArchiveDest,
+        options: ArchiveOptions,
+    ) -> Result<u64, JsonStoreError> {
+        self._metered("archive_where", Some(tname), async {
+            self._writable_info(tname)?;
+
+            let select = |tree: &Tree| {
+                tree.data
+                    .sorted()
+                    .into_iter()
+                    .filter(|(_, value)| filter(value))
+                    .map(|(seq, _)| *seq)
+                    .collect()
+            };
+            let archived = self._archive(tname, select, &dest, options).await?.len() as u64;
+            if !options.dry_run && archived > 0 {
+                trace::info!(tree = tname, dest = ?dest, archived, "archived records");
+            }
+
+            Ok(archived)
+        })
+        .await
+    }
+
+    // move the records select picks out of tname, under one lock, returning their
+    // sequences
+    async fn _archive<S: FnOnce(&Tree) -> Vec<u64>>(
+        &self,
+        tname: &str,
+        select: S,
+        dest: &ArchiveDest,
+        options: ArchiveOptions,
+    ) -> Result<Vec<u64>, JsonStoreError> {
+        match dest {
+            ArchiveDest::Tree(dname) => self._archive_to_tree(tname, select, dname, options).await,
+            ArchiveDest::File(path) => self._archive_to_file(tname, select, path, options).await,
+        }
+    }
+
+    async fn _archive_to_tree<S: FnOnce(&Tree) -> Vec<u64>>(
+        &self,
+        tname: &str,
+        select: S,
+        dname: &str,
+        options: ArchiveOptions,
+    ) -> Result<Vec<u64>, JsonStoreError> {
+        if dname == tname {
+            return Err(JsonStoreError::InvalidOptions(format!(
+                "tree '{}' can't be archived into itself",
+                tname
+            )));
+        }
+        self._info(tname)?;
+        let dinfo = self._info(dname)?;
+        self._check_frozen(dname, &dinfo)?;
+
+        // trees locked together are taken in name order, so two archives between the
+        // same trees can't deadlock
+        let (mut tree, mut dest) = match tname < dname {
+            true => {
+                let tree = self._write_lock(tname).await?;
+                (tree, self._write_lock(dname).await?)
+            }
+            false => {
+                let dest = self._write_lock(dname).await?;
+                (self._write_lock(tname).await?, dest)
+            }
+        };
+
+        let mut records = select(&tree)
+            .into_iter()
+            .map(|seq| (seq, Value::clone(&tree.data[&seq])))
+            .collect::<Vec<_>>();
+        let sequences = records.iter().map(|(seq, _)| *seq).collect::<Vec<_>>();
+
+        let conflict = |sequence, reason: &str| JsonStoreError::ArchiveConflict {
+            tree: dname.to_string(),
+            sequence,
+            reason: reason.to_string(),
+        };
+        if dest.len() + records.len() > dinfo.capacity as usize {
+            return Err(JsonStoreError::CapacityExceeded(dname.to_string()));
+        }
+        let mut batch = UniqueIndex::new(&dinfo);
+        for (seq, value) in records.iter_mut() {
+            self.shared.record_locks.check(tname, *seq, None)?;
+            if dest.data.contains_key(seq) {
+                return Err(conflict(*seq, "sequence already exists"));
+            }
+            value[&dinfo.sequence_field] = json!(*seq);
+
+            if dest.unique_index(&dinfo).conflict(value, None).is_some()
+                || batch.conflict(value, None).is_some()
+            {
+                return Err(conflict(*seq, "unique fields already exist"));
+            }
+            batch.insert(*seq, value);
+        }
+        if options.dry_run || records.is_empty() {
+            return Ok(sequences);
+        }
+
+        for (seq, value) in records.iter() {
+            let entry = WalEntry::Insert {
+                tree: dname.to_string(),
+                seq: *seq,
+                value,
+            };
+            self._log(dname, &mut dest, &entry).await?;
+            dest.sequence = dest.sequence.max(*seq);
+            dest.put(*seq, value.clone());
+            dest.touch(*seq);
+
+            self._archive_delete(tname, &mut tree, *seq).await?;
+        }
+        tree.undo.clear();
+
+        self._written(dname, &mut dest).await?;
+        self._written(tname, &mut tree).await?;
+
+        Ok(sequences)
+    }
+
+    async fn _archive_to_file<S: FnOnce(&Tree) -> Vec<u64>>(
+        &self,
+        tname: &str,
+        select: S,
+        path: &Path,
+        options: ArchiveOptions,
+    ) -> Result<Vec<u64>, JsonStoreError> {
+        let mut tree = self._write_lock(tname).await?;
+
+        let sequences = select(&tree);
+        for seq in sequences.iter() {
+            self.shared.record_locks.check(tname, *seq, None)?;
+        }
+        if options.dry_run || sequences.is_empty() {
+            return Ok(sequences);
+        }
+
+        // records carry their sequence field, which Preserve imports go by
+        let fsync = self.shared.durability == Durability::Fsync;
+        let records = sequences.iter().map(|seq| &*tree.data[seq]);
+        cold::append_ndjson(path, records, fsync).await?;
+
+        for seq in sequences.iter() {
+            self._archive_delete(tname, &mut tree, *seq).await?;
+        }
+        tree.undo.clear();
+
+        self._written(tname, &mut tree).await?;
+
+        Ok(sequences)
+    }
+
+    // take an archived record out of its tree; the caller clears the undo stack, whose
+    // entries could otherwise bring the record back next to its archived copy
+    async fn _archive_delete(
+        &self,
+        tname: &str,
+        tree: &mut Tree,
+        seq: u64,
+    ) -> Result<(), JsonStoreError> {
+        let entry = WalEntry::Delete {
+            tree: tname.to_string(),
+            seq,
+        };
+        self._log(tname, tree, &entry).await?;
+        tree.take(seq);
+        tree.touch(seq);
+        self.shared.record_locks.remove(tname, seq);
+        Ok(())
+    }
+
+    // Delete the records strategy picks until tname holds no more than its capacity,
+    // returning their sequences. Each is recorded in history as a delete would be, and
+    // one undo_last brings them all back.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, strategy = ?strategy)))]
+    pub async fn prune_to_capacity(
+        &self,
+        tname: &str,
+        strategy: PruneStrategy,
+    ) -> Result<Vec<u64>, JsonStoreError> {
+        self._metered("prune_to_capacity", Some(tname), async {
+            let info = self._writable_info(tname)?;
+
+            let mut tree = self._write_lock(tname).await?;
+
+            let sequences = strategy.choose(
+                tree.data.sorted().into_iter(),
+                tree.len(),
+                info.capacity as usize,
+            );
+            for seq in sequences.iter() {
+                self.shared.record_locks.check(tname, *seq, None)?;
+            }
+            if sequences.is_empty() {
+                return Ok(sequences);
+            }
+
+            let prior = self
+                ._delete_records(tname, &info, &mut tree, &sequences)
+                .await?;
+            self._push_undo(&mut tree, UndoOp::Prune, prior);
+
+            self._written(tname, &mut tree).await?;
+
+            trace::info!(
+                tree = tname,
+                pruned = sequences.len(),
+                "pruned tree to capacity"
+            );
+
+            Ok(sequences)
+        })
+        .await
+    }
+
+    // Delete the records of tname that have expired (see expiry.rs), returning their
+    // sequences. Like prune_to_capacity, each goes into history as a delete and one
+    // undo_last brings them all back. A tree without an expiry field has none.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname)))]
+    pub async fn purge_expired(&self, tname: &str) -> Result<Vec<u64>, JsonStoreError> {
+        self._metered("purge_expired", Some(tname), async {
+            let info = self._writable_info(tname)?;
+            let Some(expiry) = self._expiry(&info) else {
+                return Ok(Vec::new());
+            };
+
+            let mut tree = self._write_lock(tname).await?;
+
+            let mut sequences = Vec::new();
+            {
+                let mut scan = pin!(tree.scan());
+                while let Some(record) = scan.next().await {
+                    let (seq, value) = record?;
+                    if expiry.expired(&value) {
+                        sequences.push(seq);
+                    }
+                }
+            }
+            sequences.sort_unstable();
+            for seq in sequences.iter() {
+                self.shared.record_locks.check(tname, *seq, None)?;
+            }
+            if sequences.is_empty() {
+                return Ok(sequences);
+            }
+
+            let prior = self
+                ._delete_records(tname, &info, &mut tree, &sequences)
+                .await?;
+            self._push_undo(&mut tree, UndoOp::Purge, prior);
+
+            self._written(tname, &mut tree).await?;
+
+            trace::info!(
+                tree = tname,
+                purged = sequences.len(),
+                "purged expired records"
+            );
+
+            Ok(sequences)
+        })
+        .await
+    }
+
+    // prune_to_capacity moving the records to dest, as archive_where does, rather than
+    // deleting them
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, strategy = ?strategy, dest = ?dest)))]
+    pub async fn prune_to_capacity_archiving(
+        &self,
+        tname: &str,
+        strategy: PruneStrategy,
+        dest: ArchiveDest,
+    ) -> Result<Vec<u64>, JsonStoreError> {
+        self._metered("prune_to_capacity", Some(tname), async {
+            let capacity = self._writable_info(tname)?.capacity as usize;
+            let select =
+                |tree: &Tree| strategy.choose(tree.data.sorted().into_iter(), tree.len(), capacity);
+            let pruned = self
+                ._archive(tname, select, &dest, ArchiveOptions::default())
+                .await?;
+            if !pruned.is_empty() {
+                trace::info!(tree = tname, dest = ?dest, pruned = pruned.len(), "pruned tree to capacity");
+            }
+
+            Ok(pruned)
+        })
+        .await
+    }
+
+    pub async fn import_tree(
+        &self,
+        tname: &str,
+        path: &Path,
+        mode: ImportMode,
+    ) -> Result<ImportReport, JsonStoreError> {
+        self.import_tree_with(tname, path, mode, OnConflict::default())
+            .await
+    }
+
+    // insert the records of a JSON array file into tree; see _import
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, path = ?path)))]
+    pub async fn import_tree_with(
+        &self,
+        tname: &str,
+        path: &Path,
+        mode: ImportMode,
+        on_conflict: OnConflict,
+    ) -> Result<ImportReport, JsonStoreError> {
+        self._metered("import", Some(tname), async {
+            self._writable_info(tname)?;
+
+            let records = import::read_array(path).await?;
+            let records = records.into_iter().map(Ok).enumerate().collect();
+
+            self._import(tname, records, mode, on_conflict).await
+        })
+        .await
+    }
+
+    // insert the records of a JSON-lines file into tree; see _import
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, path = ?path)))]
+    pub async fn import_ndjson(
+        &self,
+        tname: &str,
+        path: &Path,
+        mode: ImportMode,
+        on_conflict: OnConflict,
+    ) -> Result<ImportReport, JsonStoreError> {
+        self._metered("import", Some(tname), async {
+            self._writable_info(tname)?;
+
+            let records = import::read_ndjson(path).await?;
+
+            self._import(tname, records, mode, on_conflict).await
+        })
+        .await
+    }
+
+    // insert the rows of a CSV file into tree; see _import
+    #[cfg(feature = "csv")]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, path = ?path)))]
+    pub async fn import_csv(
+        &self,
+        tname: &str,
+        path: &Path,
+        options: CsvImportOptions,
+    ) -> Result<ImportReport, JsonStoreError> {
+        self._metered("import", Some(tname), async {
+            self._writable_info(tname)?;
+
+            let records = import::read_csv(path, &options).await?;
+
+            self._import(tname, records, options.mode, options.on_conflict)
+                .await
+        })
+        .await
+    }
+
+    // insert the rows of a SQLite table into tree; see sqlite.rs and _import
+    #[cfg(feature = "sqlite")]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, path = ?path, table = mapping.table)))]
+    pub async fn import_sqlite(
+        &self,
+        tname: &str,
+        path: &Path,
+        mapping: SqliteImport,
+    ) -> Result<ImportReport, JsonStoreError> {
+        self._metered("import", Some(tname), async {
+            self._writable_info(tname)?;
+
+            let records = sqlite::read(path, &mapping).await?;
+
+            self._import(tname, records, mapping.mode, mapping.on_conflict)
+                .await
+        })
+        .await
+    }
+
+    // Insert records into tree. Each comes with its position in the source file, or
+    // the reason it could not be read. Every record is checked against capacity and
+    // unique fields before any is applied, so with OnConflict::Fail a rejected file
+    // leaves the tree untouched.
+    async fn _import(
+        &self,
+        tname: &str,
+        records: Vec<(usize, Result<Value, String>)>,
+        mode: ImportMode,
+        on_conflict: OnConflict,
+    ) -> Result<ImportReport, JsonStoreError> {
+        let info = self._info(tname)?;
+
+        let mut tree = self._write_lock(tname).await?;
+
+        let mut report = ImportReport::default();
+        let mut accepted: Vec<(u64, Value)> = Vec::new();
+        let mut batch = UniqueIndex::new(&info);
+        let mut sequence = tree.sequence;
+
+        for (index, record) in records {
+            let (problem, mut record) = match record {
+                Ok(record) => (None, record),
+                Err(reason) => (Some(reason), Value::Null),
+            };
+
+            let problem = if problem.is_some() {
+                problem
+            } else if !record.is_object() {
+                Some("not an object".to_string())
+            } else if let Err(e) = key::assign(tname, &info, &mut record) {
+                Some(match e {
+                    JsonStoreError::InvalidKey { reason, .. } => reason,
+                    e => e.to_string(),
+                })
+            } else if tree.data.len() + accepted.len() >= info.capacity as usize {
+                Some("tree is at capacity".to_string())
+            } else {
+                None
+            };
+
+            let seq = match mode {
+                ImportMode::Append => sequence + 1,
+                ImportMode::Preserve => record[&info.sequence_field].as_u64().unwrap_or(0),
+            };
+
+            let problem = problem.or_else(|| match mode {
+                ImportMode::Preserve if seq == 0 => Some(format!(
+                    "'{}' is not a positive integer",
+                    info.sequence_field
+                )),
+                ImportMode::Preserve
+                    if tree.data.contains_key(&seq) || accepted.iter().any(|(s, _)| *s == seq) =>
+                {
+                    Some(format!("sequence {} already exists", seq))
+                }
+                _ => None,
+            });
+
+            let problem = match problem {
+                Some(problem) => Some(problem),
+                None => {
+                    record[&info.sequence_field] = serde_json::to_value(seq)?;
+                    if tree.unique_index(&info).conflict(&record, None).is_some()
+                        || batch.conflict(&record, None).is_some()
+                    {
+                        Some("unique fields already exist".to_string())
+                    } else {
+                        None
+                    }
+                }
+            };
+
+            match (problem, on_conflict) {
+                (None, _) => {
+                    sequence = sequence.max(seq);
+                    batch.insert(seq, &record);
+                    accepted.push((seq, record));
+                }
+                (Some(reason), OnConflict::Fail) => {
+                    return Err(JsonStoreError::ImportRejected {
+                        tree: tname.to_string(),
+                        index,
+                        reason,
+                    });
+                }
+                (Some(reason), OnConflict::Skip) => {
+                    report.skipped.push(ImportIssue { index, reason });
+                }
+            }
+        }
+
+        if accepted.is_empty() {
+            return Ok(report);
+        }
+
+        let prior = accepted.iter().map(|(seq, _)| (*seq, None)).collect();
+        for (seq, record) in accepted {
+            self._log(
+                tname,
+                &mut tree,
+                &WalEntry::Insert {
+                    tree: tname.to_string(),
+                    seq,
+                    value: &record,
+                },
+            )
+            .await?;
+
+            tree.sequence = tree.sequence.max(seq);
+            tree.put(seq, record);
+            tree.touch(seq);
+            report.imported.push(seq);
+        }
+        self._push_undo(&mut tree, UndoOp::Import, prior);
+
+        self._written(tname, &mut tree).await?;
+
+        Ok(report)
+    }
+
+    // Put fixture records into tname, all of them or none; see seed.rs. A record that
+    // can't be written fails the seed with ImportRejected, naming its index.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(tree = tname, mode = ?mode)))]
+    pub async fn seed(
+        &self,
+        tname: &str,
+        records: &[Value],
+        mode: SeedMode,
+    ) -> Result<SeedReport, JsonStoreError> {
+        self._metered("seed", Some(tname), async {
+            let info = self._writable_info(tname)?;
+            if let SeedMode::Merge(constraint) = &mode {
+                if !info.unique_fields.contains_key(constraint) {
+                    return Err(JsonStoreError::UniqueConstraintNotFound {
+                        tree: tname.to_string(),
+                        constraint: constraint.clone(),
+                    });
+                }
+            }
+            let expiry = self._expiry(&info);
+
+            let mut tree = self._write_lock(tname).await?;
+
+            let mut report = SeedReport::default();
+            let live = tree
+                .data
+                .iter()
+                .filter(|(_, value)| expiry::live(expiry.as_ref(), value))
+                .map(|(seq, _)| *seq)
+                .collect::<HashSet<_>>();
+            if mode == SeedMode::IfEmpty && !live.is_empty() {
+                report.skipped = records.len();
+                return Ok(report);
+            }
+
+            let rejected = |index, reason| JsonStoreError::ImportRejected {
+                tree: tname.to_string(),
+                index,
+                reason,
+            };
+
+            let mut deleted = match mode {
+                SeedMode::Replace => tree.data.sorted().into_keys().copied().collect(),
+                _ => Vec::new(),
+            };
+            // the tree's unique keys as they will be, the records about to be written in
+            let mut index = match mode {
+                SeedMode::Replace => UniqueIndex::new(&info),
+                _ => UniqueIndex::build(
+                    &info,
+                    tree.data.iter().filter(|(seq, _)| live.contains(seq)),
+                ),
+            };
+            // record index by sequence, and what goes there
+            let mut planned = HashMap::new();
+            let mut writes = Vec::with_capacity(records.len());
+            let mut sequence = tree.sequence;
+
+            for (i, record) in records.iter().enumerate() {
+                let mut record = record.clone();
+                if !record.is_object() {
+                    return Err(rejected(i, "not an object".to_string()));
+                }
+                key::assign(tname, &info, &mut record).map_err(|e| match e {
+                    JsonStoreError::InvalidKey { reason, .. } => rejected(i, reason),
+                    e => e,
+                })?;
+
+                let owner = match &mode {
+                    SeedMode::Merge(constraint) => index.constraint_owner(constraint, &record),
+                    _ => None,
+                };
+                let seq = match owner {
+                    Some(seq) if planned.contains_key(&seq) => {
+                        let reason = format!("has the same key as record {}", planned[&seq]);
+                        return Err(rejected(i, reason));
+                    }
+                    Some(seq) => {
+                        record[info.sequence_field.as_str()] = seq.into();
+                        let prior = tree.data[&seq].clone();
+                        key::check_unchanged(tname, &info, &prior, &record).map_err(
+                            |e| match e {
+                                JsonStoreError::InvalidKey { reason, .. } => rejected(i, reason),
+                                e => e,
+                            },
+                        )?;
+                        self.shared.record_locks.check(tname, seq, None)?;
+                        index.remove(seq, &prior);
+                        seq
+                    }
+                    None => {
+                        sequence += 1;
+                        record[info.sequence_field.as_str()] = sequence.into();
+                        sequence
+                    }
+                };
+
+                if let Some(other) = index.conflict(&record, Some(seq)) {
+                    let reason = match planned.get(&other) {
+                        Some(j) => format!("unique fields clash with record {}", j),
+                        None => format!("unique fields clash with sequence {}", other),
+                    };
+                    return Err(rejected(i, reason));
+                }
+                // expired records holding the key make way, as for insert
+                let expired = tree
+                    .unique_index(&info)
+                    .owners(&record)
+                    .filter(|other| *other != seq && !live.contains(other))
+                    .collect::<Vec<_>>();
+                for other in expired {
+                    if !deleted.contains(&other) {
+                        deleted.push(other);
+                    }
+                }
+
+                index.insert(seq, &record);
+                planned.insert(seq, i);
+                writes.push((seq, record));
+            }
+
+            let created = (sequence - tree.sequence) as usize;
+            if tree.data.len() - deleted.len() + created > info.capacity as usize {
+                return Err(JsonStoreError::CapacityExceeded(tname.to_string()));
+            }
+
+            let mut prior = self
+                ._delete_records(tname, &info, &mut tree, &deleted)
+                .await?;
+            report.deleted = prior.len();
+            for (seq, record) in writes {
+                let entry = match tree.data.contains_key(&seq) {
+                    true => WalEntry::Update {
+                        tree: tname.to_string(),
+                        seq,
+                        value: &record,
+                    },
+                    false => WalEntry::Insert {
+                        tree: tname.to_string(),
+                        seq,
+                        value: &record,
+                    },
+                };
+                self._log(tname, &mut tree, &entry).await?;
+
+                tree.sequence = tree.sequence.max(seq);
+                let replaced = tree.put(seq, record);
+                prior.push((seq, replaced.clone()));
+                match replaced {
+                    Some(replaced) => {
+                        self._record_history(&info, &mut tree, seq, replaced, HistoryOp::Update);
+                        report.updated += 1;
+                    }
+                    None => report.created += 1,
+                }
+                tree.touch(seq);
+            }
+
+            if !prior.is_empty() {
+                self._push_undo(&mut tree, UndoOp::Seed, prior);
+                self._written(tname, &mut tree).await?;
+            }
+
+            Ok(report)
+        })
+        .await
+    }
+
+    // Seed the tree of every fixture file in dir, `{tree}.json` holding an array of its
+    // records, with mode. Every file is read, and its tree found, before any tree is
+    // seeded; each tree is seeded on its own, so a failure leaves those before it
+    // seeded.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(dir = ?dir, mode = ?mode)))]
+    pub async fn seed_from_dir(
+        &self,
+        dir: &Path,
+        mode: SeedMode,
+    ) -> Result<BTreeMap<String, SeedReport>, JsonStoreError> {
+        self._metered("seed", None, async {
+            let fixtures = seed::fixtures(dir).await?;
+            if let Some((tname, path)) = fixtures.iter().find(|(tname, _)| !self.has_tree(tname)) {
+                return Err(JsonStoreError::FixtureTreeNotFound {
+                    tree: tname.clone(),
+                    path: path.clone(),
+                });
+            }
+
+            let mut seeds = Vec::with_capacity(fixtures.len());
+            for (tname, path) in fixtures {
+                let records = import::read_array(&path).await.map_err(|e| match e {
+                    JsonStoreError::DeserializeFromStr(source) => {
+                        JsonStoreError::ParseFile { path, source }
+                    }
+                    e => e,
+                })?;
+                seeds.push((tname, records));
+            }
+
+            let mut reports = BTreeMap::new();
+            for (tname, records) in seeds {
+                let report = self.seed(&tname, &records, mode.clone()).await?;
+                reports.insert(tname, report);
+            }
+            Ok(reports)
+        })
+        .await
+    }
+
+    // Back up into a new subdirectory of dir named for the time to the millisecond,
+    // then remove the oldest rotated backups there so at most keep (at least one)
+    // remain. Backups in the same millisecond each get their own.
+    pub async fn backup_rotated(
+        &self,
+        dir: &Path,
+        keep: usize,
+    ) -> Result<BackupReport, JsonStoreError> {
+        self._check_open()?;
+        let dest = backup::claim_rotated(dir, self.shared.clock.now()).await?;
+        let report = match self.backup(&dest).await {
+            Ok(report) => report,
+            Err(e) => {
+                // not left to be taken for a backup by prune
+                let _ = fs::remove_dir_all(&dest).await;
+                return Err(e);
+            }
+        };
+
+        for path in backup::prune(dir, keep.max(1)).await? {
+            trace::info!(path = ?path, "removed old backup");
+        }
+
+        Ok(report)
+    }
+
+    // take a rotated backup into dir every interval; see backup_rotated
+    pub fn start_scheduled_backups(
+        &self,
+        dir: &Path,
+        interval: Duration,
+        keep: usize,
+    ) -> BackupScheduleHandle {
+        BackupScheduleHandle::start(self.clone(), dir.into(), interval, keep)
+    }
+
+    // Write the whole store, flushed and consistent as for backup, to a gzip'd tar
+    // at path with a manifest of file checksums.
+    #[cfg(feature = "archive")]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(path = ?path)))]
+    pub async fn export_archive(&self, path: &Path) -> Result<(), JsonStoreError> {
+        self._metered("export_archive", None, async {
+            let mut staging = path.as_os_str().to_os_string();
+            staging.push(".staging");
+            let staging = PathBuf::from(staging);
+
+            let _ = fs::remove_dir_all(&staging).await;
+            let result = match self.backup(&staging).await {
+                Ok(_) => archive::pack(&staging, path).await,
+                Err(e) => Err(e),
+            };
+            let _ = fs::remove_dir_all(&staging).await;
+
+            result
+        })
+        .await
+    }
+
+    // Unpack an archive made by export_archive into dest, which must be empty or
+    // missing. The archive is checked against its manifest before anything is
+    // written, and the unpacked store is read back before returning.
+    #[cfg(feature = "archive")]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(path = ?path, dest = ?dest)))]
+    pub async fn import_archive(path: &Path, dest: &Path) -> Result<(), JsonStoreError> {
+        let files = archive::unpack(path).await?;
+
+        backup::prepare(dest, false).await?;
+
+        let backend = FsBackend::new(dest);
+        let mut result = Ok(());
+        for (name, bytes) in files.iter() {
+            result = backend.write(name, bytes.clone(), Durability::Flush).await;
+            if result.is_err() {
+                break;
+            }
+        }
+        if result.is_ok() {
+            result = Self::load_checked(dest).await.map(|_| ());
+        }
+
+        if result.is_err() {
+            for name in files.keys() {
+                let _ = backend.delete(name).await;
+            }
+        }
+
+        result
+    }
+
+    // Rewrite the snapshot files of the store at path with another codec. New files
+    // are written before meta.json switches over and old ones go only after, so an
+    // interrupted conversion leaves a loadable store and can simply be run again.
+    // Must not be called while path is loaded.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(path = ?path)))]
+    pub async fn convert_codec(path: &Path, codec: Codec) -> Result<(), JsonStoreError> {
+        codec.check_available()?;
+
+        if meta::check(&FsBackend::new(path)).await?.codec == codec {
+            return Ok(());
+        }
+
+        let store = Self::load_with_options(
+            path,
+            LoadOptions {
+                eager: true,
+                ..LoadOptions::default()
+            },
+        )
+        .await?;
+
+        // fold WALs and replayed writes into the snapshots being converted
+        store.save().await?;
+
+        let backend = &*store.shared.backend;
+        let mut written = Vec::new();
+        for tname in store.list_trees() {
+            // partitions included
+            let tree = store._read_lock(&tname).await?;
+            if tree.storage != StorageFormat::Snapshot {
+                continue;
+            }
+            for (base, records) in snapshot_parts(&store.shared.layout, &tname, &tree).await? {
+                let key = store
+                    .shared
+                    .layout
+                    .snapshot_key(&base, codec, tree.compression);
+                let encoded = encode_snapshot(&key, records, codec, store.shared.format).await?;
+                write_snapshot(backend, &key, encoded, store.shared.durability).await?;
+                written.push((base, key));
+            }
+        }
+
+        let meta = Meta {
+            codec,
+            ..store._meta()
+        };
+        meta::put_meta(backend, &meta, store.shared.format, store.shared.durability).await?;
+
+        for (base, key) in written {
+            remove_stale_snapshots(backend, &store.shared.layout, &base, &key).await?;
+        }
+
+        store.close().await
+    }
+
+    // Move the files of the store at path to layout. Every file is read first and
+    // the new ones written before meta.json switches over; the old ones go only
+    // after, so an interrupted move leaves a loadable store and can simply be run
+    // again. Must not be called while path is loaded.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(path = ?path)))]
+    pub async fn relayout(path: &Path, layout: Layout) -> Result<(), JsonStoreError> {
+        layout.validate()?;
+
+        let backend = FsBackend::new(path);
+        let meta = meta::upgrade(&backend).await?;
+        if meta.layout == layout {
+            return Ok(());
+        }
+        let old = &meta.layout;
+
+        let infos = get_json::<HashMap<String, Info>>(&backend, &old.infos_file)
+            .await?
+            .unwrap_or_default();
+
+        // tree_files lists the same files in the same order under any layout
+        let mut moves = vec![(old.infos_file.clone(), layout.infos_file.clone())];
+        for (tname, info) in sorted(&infos) {
+            layout.check_tree(tname, info)?;
+            let periods = read_periods(&backend, old, tname, info).await?;
+            let mut from = tree_files(old, tname, info);
+            from.extend(partition_files(old, tname, &periods));
+            let mut to = tree_files(&layout, tname, info);
+            to.extend(partition_files(&layout, tname, &periods));
+            moves.extend(from.into_iter().zip(to));
+        }
+        moves.retain(|(from, to)| from != to);
+
+        // read everything up front, as a new name may be an old one of another file
+        let mut files = Vec::new();
+        for (from, to) in moves {
+            if let Some(context) = backend.read(&from).await? {
+                let context = checksum::moved_contents(&to, context);
+                files.push((from, to, context));
+            }
+        }
+
+        for (_, to, context) in files.iter() {
+            backend
+                .write(to, context.clone(), Durability::default())
+                .await?;
+        }
+
+        let new_keys = files.iter().map(|(_, to, _)| to).collect::<BTreeSet<_>>();
+        let stale = files
+            .iter()
+            .map(|(from, _, _)| from)
+            .filter(|from| !new_keys.contains(from))
+            .collect::<Vec<_>>();
+
+        let meta = Meta {
+            layout: layout.clone(),
+            ..meta.clone()
+        };
+        meta::put_meta(
+            &backend,
+            &meta,
+            OutputFormat::default(),
+            Durability::default(),
+        )
+        .await?;
+
+        for key in stale {
+            backend.delete(key).await?;
+        }
+        if let Some(dir) = &old.tree_dir {
+            let _ = fs::remove_dir(path.join(dir)).await;
+        }
+
+        Ok(())
+    }
+
+    // Open a backup directory in place without changing anything in it.
+    pub async fn load_backup(backup: &Path) -> Result<Self, JsonStoreError> {
+        Self::load_read_only(backup).await
+    }
+
+    // load_backup, reading every tree so damaged files show up now
+    async fn load_checked(path: &Path) -> Result<Self, JsonStoreError> {
+        Self::load_with_options(
+            path,
+            LoadOptions {
+                read_only: true,
+                eager: true,
+                ..LoadOptions::default()
+            },
+        )
+        .await
+    }
+
+    // Replace the store directory at path with the backup, keeping the current files
+    // in `.pre-restore-*/`. The backup is fully read and checked first, so a damaged
+    // one is rejected before path is touched. Must not be called while path is loaded.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(path = ?path, backup = ?backup)))]
+    pub async fn restore(path: &Path, backup: &Path) -> Result<(), JsonStoreError> {
+        let layout = meta::check(&FsBackend::new(backup)).await?.layout;
+        if !fs::try_exists(backup.join(&layout.infos_file)).await? {
+            return Err(JsonStoreError::InvalidBackup {
+                path: backup.into(),
+                reason: format!("{} not found", layout.infos_file),
+            });
+        }
+
+        let store = Self::load_checked(backup).await?;
+        if let Some((tname, _)) =
+            store.verify().await?.trees.into_iter().find(|(_, status)| {
+                !matches!(status, ChecksumStatus::Ok | ChecksumStatus::NoChecksum)
+            })
+        {
+            return Err(JsonStoreError::InvalidBackup {
+                path: backup.into(),
+                reason: format!("data file of tree '{}' is missing or damaged", tname),
+            });
+        }
+        drop(store);
+
+        // tree directories of both layouts count as store files
+        let current = meta::check(&FsBackend::new(path)).await?.layout;
+        let tree_dirs = [layout.tree_dir, current.tree_dir]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+
+        backup::restore(path, backup, &tree_dirs).await
+    }
+
+    // current size in bytes of the tree's WAL
+    pub async fn wal_size(&self, tname: &str) -> Result<u64, JsonStoreError> {
+        let _tree = self._read_lock_raw(tname).await?;
+
+        wal::size(&*self.shared.backend, &self.shared.layout.wal_key(tname)).await
+    }
+
+    // Mark a tree changed after a write and save it if its flush policy says so. The
+    // write is done by then, so a failed save is not its error: it is logged, the tree
+    // stays dirty, and the next write or save tries again.
+    async fn _written(&self, tname: &str, tree: &mut Tree) -> Result<(), JsonStoreError> {
+        tree.changed = true;
+        tree.pending_writes = tree.pending_writes.saturating_add(1);
+        if self
+            .shared
+            .shrink_below
+            .is_some_and(|fraction| tree.sparse(fraction))
+        {
+            tree.shrink();
+        }
+
+        let due = match tree.flush_policy.unwrap_or(self.flush_policy()) {
+            FlushPolicy::Manual => false,
+            FlushPolicy::EveryWrite => true,
+            FlushPolicy::AfterWrites(n) => tree.pending_writes >= n,
+            FlushPolicy::Interval(d) => tree.last_saved.elapsed() >= d,
+        } || self.shared.wal.is_some_and(|w| {
+            w.checkpoint_after_entries > 0 && tree.wal_entries >= w.checkpoint_after_entries
+        });
+
+        if due {
+            if let Err(e) = self._save_locked(tname, tree, self.shared.durability).await {
+                trace::error!(tree = tname, error = %e, "flush after write failed");
+            }
+        }
+        self._trim(tname, tree).await
+    }
+
+    // spill what a tree with a resident limit holds past it, setting up its overflow
+    // file if the tree was made since the store was loaded
+    async fn _trim(&self, tname: &str, tree: &mut Tree) -> Result<(), JsonStoreError> {
+        let info = self._info(tname)?;
+        let Some(limit) = info.resident_limit else {
+            return Ok(());
+        };
+        if tree.spill.is_none() {
+            tree.spill =
+                Some(Spill::create(self.shared.spill_dir.as_deref(), limit as usize).await?);
+        }
+        tree.trim(&info, self.shared.incremental_save.is_some())
+            .await
+    }
+
+    fn _meta(&self) -> Meta {
+        Meta {
+            codec: self.shared.codec,
+            layout: self.shared.layout.clone(),
+            metadata: self
+                .shared
+                .metadata
+                .read()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone(),
+            ..Meta::default()
+        }
+    }
+
+    async fn _put_infos(&self, infos: &HashMap<String, Info>) -> Result<(), JsonStoreError> {
+        // a fresh directory gets its version marker along with its first infos.json
+        let backend = &*self.shared.backend;
+        if !exists(backend, META_FILE).await? {
+            meta::put_meta(
+                backend,
+                &self._meta(),
+                self.shared.format,
+                self.shared.durability,
+            )
+            .await?;
+        }
+
+        // through Value so nested maps come out sorted too
+        put_json(
+            backend,
+            &self.shared.layout.infos_file,
+            &serde_json::to_value(infos)?,
+            self.shared.format,
+            self.shared.durability,
+        )
+        .await
+    }
+
+    pub fn durability(&self) -> Durability {
+        self.shared.durability
+    }
+
+    pub fn flush_policy(&self) -> FlushPolicy {
+        *self
+            .shared
+            .flush_policy
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    // store-wide flush policy, used by trees without their own
+    pub fn set_flush_policy(&self, policy: FlushPolicy) {
+        *self
+            .shared
+            .flush_policy
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = policy;
+    }
+
+    // per-tree flush policy; None falls back to the store-wide one
+    pub async fn set_tree_flush_policy(
+        &self,
+        tname: &str,
+        policy: Option<FlushPolicy>,
+    ) -> Result<(), JsonStoreError> {
+        let mut tree = self._write_lock_raw(tname).await?;
+
+        tree.flush_policy = policy;
+
+        Ok(())
+    }
+
+    // periodically save changed trees in the background until the handle is stopped or dropped
+    pub fn start_autosave(&self, interval: Duration) -> AutosaveHandle {
+        AutosaveHandle::start(self.clone(), interval)
+    }
+
+    // Save all changed trees and close the store. Every other handle to it then gets
+    // StoreClosed. Writes from them are refused as soon as the close starts, so none is
+    // made after the save and lost; one holding a tree's lock by then is saved. If the
+    // save fails the store stays open for any remaining handles.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn close(self) -> Result<(), JsonStoreError> {
+        self._check_open()?;
+        if self.shared.closing.swap(true, Ordering::SeqCst) {
+            return Err(JsonStoreError::StoreClosed);
+        }
+
+        if !self.shared.read_only {
+            if let Err(e) = self.save().await {
+                self.shared.closing.store(false, Ordering::SeqCst);
+                return Err(e);
+            }
+        }
+
+        self.shared.closed.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.shared.read_only
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.shared.closed.load(Ordering::SeqCst)
+    }
+
+    fn _check_open(&self) -> Result<(), JsonStoreError> {
+        if self.is_closed() {
+            return Err(JsonStoreError::StoreClosed);
+        }
+        Ok(())
+    }
+
+    fn _check_not_closing(&self) -> Result<(), JsonStoreError> {
+        if self.shared.closing.load(Ordering::SeqCst) {
+            return Err(JsonStoreError::StoreClosed);
+        }
+        Ok(())
+    }
+
+    // may trees and records change
+    fn _check_writable(&self) -> Result<(), JsonStoreError> {
+        self._check_savable()?;
+        self._check_not_closing()?;
+        if self.shared.replica {
+            return Err(JsonStoreError::ReplicaStore);
+        }
+        Ok(())
+    }
+
+    // The Info of tname for an operation about to change its records or Info, which
+    // the store must allow and the tree not be frozen for. Every such operation
+    // starts here.
+    fn _writable_info(&self, tname: &str) -> Result<Info, JsonStoreError> {
+        self._check_writable()?;
+        let info = self._info(tname)?;
+        self._check_frozen(tname, &info)?;
+        Ok(info)
+    }
+
+    // whether a write to tname should be queued, see batch.rs
+    fn _batches(&self, tname: &str, info: &Info, owner: Option<&str>) -> bool {
+        if owner.is_some()
+            || info.partition_by.is_some()
+            || info.resident_limit.is_some()
+            || info.value_mode == ValueMode::Raw
+        {
+            return false;
+        }
+        match self.shared.write_batching {
+            WriteBatching::Off => false,
+            WriteBatching::Always => true,
+            WriteBatching::Contended => {
+                self._write_queue(tname).is_draining()
+                    || self
+                        ._tree(tname)
+                        .is_ok_and(|tree| tree.try_write_arc().is_none())
+            }
+        }
+    }
+
+    fn _write_queue(&self, tname: &str) -> Arc<WriteQueue> {
+        let mut queues = self
+            .shared
+            .write_queues
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        match queues.get(tname) {
+            Some(queue) => queue.clone(),
+            None => queues.entry(tname.to_string()).or_default().clone(),
+        }
+    }
+
+    // queue op on tname, starting a task to drain the queue if none is
+    async fn _batched(&self, tname: &str, info: Info, op: BatchOp) -> BatchResult {
+        let (done, result) = oneshot::channel();
+        let queue = self._write_queue(tname);
+        if queue.push(Queued { info, op, done }) {
+            let store = self.clone();
+            let tname = tname.to_string();
+            rt::spawn(async move { store._drain(&tname, &queue).await });
+        }
+        // the task only goes without answering if the runtime drops it
+        result.await.unwrap_or(Err(JsonStoreError::DefaultError))
+    }
+
+    // Apply everything queued on tname under one write lock, until nothing is. Should
+    // the lock fail, the writes take their own way, each failing as it would alone.
+    async fn _drain(&self, tname: &str, queue: &WriteQueue) {
+        let mut tree = self._write_lock(tname).await.ok();
+        while let Some(batch) = queue.take() {
+            trace::debug!(tree = tname, writes = batch.len(), "applying queued writes");
+            for Queued { info, op, done } in batch {
+                let result = match tree.as_mut() {
+                    Some(tree) => self._apply_locked(tname, &info, tree, op).await,
+                    None => self._apply(tname, &info, op).await,
+                };
+                let _ = done.send(result);
+            }
+            // writers just answered tend to queue their next write straight away
+            rt::yield_now().await;
+        }
+    }
+
+    async fn _apply_locked(
+        &self,
+        tname: &str,
+        info: &Info,
+        tree: &mut Tree,
+        op: BatchOp,
+    ) -> BatchResult {
+        match op {
+            BatchOp::Insert(value, reserved) => {
+                self._insert_locked(tname, info, tree, value, reserved)
+                    .await
+            }
+            BatchOp::Update(seq, value) => self
+                ._update_locked(tname, info, tree, seq, value, None)
+                .await
+                .map(|()| seq),
+            BatchOp::Delete(seq) => self
+                ._delete_locked(tname, info, tree, seq, None)
+                .await
+                .map(|()| seq),
+        }
+    }
+
+    async fn _apply(&self, tname: &str, info: &Info, op: BatchOp) -> BatchResult {
+        let mut tree = self._write_lock(tname).await?;
+        self._apply_locked(tname, info, &mut tree, op).await
+    }
+
+    // Delete the records at sequences, each into the WAL and history as delete would,
+    // returning them as they were for an undo entry. Their locks are the caller's to
+    // check.
+    async fn _delete_records(
+        &self,
+        tname: &str,
+        info: &Info,
+        tree: &mut Tree,
+        sequences: &[u64],
+    ) -> Result<Vec<(u64, Option<Arc<Value>>)>, JsonStoreError> {
+        let mut prior = Vec::with_capacity(sequences.len());
+        for seq in sequences.iter() {
+            let entry = WalEntry::Delete {
+                tree: tname.to_string(),
+                seq: *seq,
+            };
+            self._log(tname, tree, &entry).await?;
+            if let Some(value) = tree.take(*seq) {
+                self._record_history(info, tree, *seq, value.clone(), HistoryOp::Delete);
+                prior.push((*seq, Some(value)));
+            }
+            tree.touch(*seq);
+            self.shared.record_locks.remove(tname, *seq);
+        }
+        Ok(prior)
+    }
+
+    // Check value, about to be written at skip or a new sequence, against tname's
+    // unique constraints. Records it clashes with that have all expired are deleted to
+    // make way, and returned as they were for the write's undo entry; a clash with a
+    // live one is DuplicateUniqueFields.
+    async fn _clear_unique(
+        &self,
+        tname: &str,
+        info: &Info,
+        tree: &mut Tree,
+        value: &Value,
+        skip: Option<u64>,
+    ) -> Result<Vec<(u64, Option<Arc<Value>>)>, JsonStoreError> {
+        let duplicate = || JsonStoreError::DuplicateUniqueFields(tname.to_string());
+        if tree.unique_index(info).conflict(value, skip).is_none() {
+            return Ok(Vec::new());
+        }
+        let expiry = self._expiry(info).ok_or_else(duplicate)?;
+
+        let mut owners: Vec<u64> = tree
+            .unique_index(info)
+            .owners(value)
+            .filter(|owner| Some(*owner) != skip)
+            .collect();
+        owners.sort_unstable();
+        owners.dedup();
+        for owner in owners.iter() {
+            match tree.record(*owner).await? {
+                Some(record) if expiry.expired(&record) => {}
+                _ => return Err(duplicate()),
+            }
+            self.shared.record_locks.check(tname, *owner, None)?;
+        }
+        self._delete_records(tname, info, tree, &owners).await
+    }
+
+    // what has expired in a tree of info as of the store's clock
+    fn _expiry(&self, info: &Info) -> Option<Expiry> {
+        Expiry::new(info, self.shared.clock.now())
+    }
+
+    fn _check_frozen(&self, tname: &str, info: &Info) -> Result<(), JsonStoreError> {
+        match info.frozen {
+            true => Err(JsonStoreError::TreeFrozen(tname.to_string())),
+            false => Ok(()),
+        }
+    }
+
+    // may the store's files be written
+    fn _check_savable(&self) -> Result<(), JsonStoreError> {
+        self._check_open()?;
+        if self.shared.read_only {
+            return Err(JsonStoreError::ReadOnlyStore);
+        }
+        Ok(())
+    }
+
+    fn _catalog(&self) -> StdReadGuard<'_, Catalog> {
+        self.shared
+            .catalog
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn _catalog_mut(&self) -> StdWriteGuard<'_, Catalog> {
+        self.shared
+            .catalog
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn _info(&self, tname: &str) -> Result<Info, JsonStoreError> {
+        self._check_open()?;
+        self._catalog()
+            .infos
+            .get(tname)
+            .cloned()
+            .ok_or(JsonStoreError::NotFoundTree(tname.to_string()))
+    }
+
+    fn _partition_spec(&self, tname: &str) -> Result<PartitionSpec, JsonStoreError> {
+        self._info(tname)?.partition_by.ok_or_else(|| {
+            JsonStoreError::InvalidOptions(format!("tree '{}' isn't partitioned", tname))
+        })
+    }
+
+    fn _tree(&self, tname: &str) -> Result<Arc<RwLock<Tree>>, JsonStoreError> {
+        self._check_open()?;
+        self._catalog()
+            .trees
+            .get(tname)
+            .cloned()
+            .ok_or(JsonStoreError::NotFoundTree(tname.to_string()))
+    }
+
+    // Lock tname, reading its files first if this is its first use, and every
+    // partition of a partitioned tree. A failed read leaves the tree unloaded, so the
+    // next access tries again.
+    async fn _write_lock(&self, tname: &str) -> Result<RwLockWriteGuardArc<Tree>, JsonStoreError> {
+        self._write_lock_periods(tname, |tree| tree.partitions.keys().cloned().collect())
+            .await
+    }
+
+    async fn _read_lock(&self, tname: &str) -> Result<RwLockReadGuardArc<Tree>, JsonStoreError> {
+        self._read_lock_periods(tname, |tree| tree.partitions.keys().cloned().collect())
+            .await
+    }
+
+    // _write_lock with only the partitions periods picks loaded; other trees load whole
+    async fn _write_lock_periods(
+        &self,
+        tname: &str,
+        periods: impl Fn(&Tree) -> Vec<String>,
+    ) -> Result<RwLockWriteGuardArc<Tree>, JsonStoreError> {
+        let mut tree = self._write_lock_unparsed(tname).await?;
+        self._parse(tname, &mut tree).await?;
+        if tree.any_spilled() {
+            tree.unspill().await?;
+            trace::debug!(
+                tree = tname,
+                records = tree.len(),
+                "read back spilled records"
+            );
+        }
+        let periods = periods(&tree);
+        if !tree.has_loaded(&periods) {
+            read_partitions(
+                &*self.shared.backend,
+                &self.shared.layout,
+                tname,
+                self.shared.codec,
+                &mut tree,
+                &periods,
+            )
+            .await?;
+        }
+        Ok(tree)
+    }
+
+    async fn _read_lock_periods(
+        &self,
+        tname: &str,
+        periods: impl Fn(&Tree) -> Vec<String>,
+    ) -> Result<RwLockReadGuardArc<Tree>, JsonStoreError> {
+        let tree = self._read_lock_raw(tname).await?;
+        if tree.parsed && !tree.any_spilled() && tree.has_loaded(&periods(&tree)) {
+            return Ok(tree);
+        }
+        drop(tree);
+
+        // whoever gets the write lock first reads the files; the rest find them loaded
+        let tree = self._write_lock_periods(tname, periods).await?;
+        Ok(RwLockWriteGuardArc::downgrade(tree))
+    }
+
+    // _write_lock for operations that only save; a Raw tree, never partitioned, is
+    // left unparsed, and a tree with a resident limit spilled
+    async fn _write_lock_saving(
+        &self,
+        tname: &str,
+    ) -> Result<RwLockWriteGuardArc<Tree>, JsonStoreError> {
+        let info = self._info(tname)?;
+        match info.value_mode {
+            ValueMode::Raw => self._write_lock_unparsed(tname).await,
+            ValueMode::Parsed if info.resident_limit.is_some() => {
+                self._write_lock_unparsed(tname).await
+            }
+            ValueMode::Parsed => self._write_lock(tname).await,
+        }
+    }
+
+    // a write lock on a tree with a resident limit for a write to the record at seq,
+    // read back in if it was spilled
+    async fn _write_lock_record(
+        &self,
+        tname: &str,
+        seq: u64,
+    ) -> Result<RwLockWriteGuardArc<Tree>, JsonStoreError> {
+        let mut tree = self._write_lock_unparsed(tname).await?;
+        tree.fault_in(seq).await?;
+        Ok(tree)
+    }
+
+    // a read lock for going through every record with Tree::scan, which leaves spilled
+    // records where they are
+    async fn _read_lock_scan(
+        &self,
+        tname: &str,
+    ) -> Result<RwLockReadGuardArc<Tree>, JsonStoreError> {
+        match self._info(tname)?.resident_limit {
+            Some(_) => self._read_lock_unparsed(tname).await,
+            None => self._read_lock(tname).await,
+        }
+    }
+
+    // the tree loaded, but a Raw tree's records left as text
+    async fn _write_lock_unparsed(
+        &self,
+        tname: &str,
+    ) -> Result<RwLockWriteGuardArc<Tree>, JsonStoreError> {
+        let mut tree = self._write_lock_raw(tname).await?;
+        self._load(tname, &mut tree).await?;
+        Ok(tree)
+    }
+
+    async fn _read_lock_unparsed(
+        &self,
+        tname: &str,
+    ) -> Result<RwLockReadGuardArc<Tree>, JsonStoreError> {
+        let tree = self._read_lock_raw(tname).await?;
+        if tree.loaded {
+            return Ok(tree);
+        }
+        drop(tree);
+
+        let tree = self._write_lock_unparsed(tname).await?;
+        Ok(RwLockWriteGuardArc::downgrade(tree))
+    }
+
+    // Parse the records of a Raw tree, once, for an operation that looks at their
+    // fields, and build its indexes over them. It stays parsed until unloaded.
+    async fn _parse(&self, tname: &str, tree: &mut Tree) -> Result<(), JsonStoreError> {
+        if tree.parsed {
+            return Ok(());
+        }
+        let info = self._info(tname)?;
+
+        if let Some(raw) = &tree.raw {
+            let records = raw
+                .iter()
+                .map(|(seq, record)| (*seq, record.clone()))
+                .collect::<Vec<_>>();
+            tree.data = match records.len() < BLOCKING_ENCODE_RECORDS {
+                true => raw::parse_all(records)?,
+                false => rt::unblock(move || raw::parse_all(records)).await??,
+            };
+            trace::debug!(
+                tree = tname,
+                records = tree.data.len(),
+                "parsed raw records"
+            );
+        }
+        tree.indexes = FieldIndexes::build(&info, &tree.data);
+        tree.unique = None;
+        tree.parsed = true;
+
+        Ok(())
+    }
+
+    // lock tname as it is, loaded or not
+    async fn _write_lock_raw(
+        &self,
+        tname: &str,
+    ) -> Result<RwLockWriteGuardArc<Tree>, JsonStoreError> {
+        let guard = self._save_lock_raw(tname).await?;
+        // a write that got past its checks before a close started, and to the lock
+        // after, would change the tree once the close has saved it
+        self._check_not_closing()?;
+        Ok(guard)
+    }
+
+    // the write lock for saving tname, which a close still takes
+    async fn _save_lock_raw(
+        &self,
+        tname: &str,
+    ) -> Result<RwLockWriteGuardArc<Tree>, JsonStoreError> {
+        let tree = self._tree(tname)?;
+        let started = Instant::now();
+        let guard = tree.write_arc().await;
+        self._lock_waited(tname, "write", started);
+        Ok(guard)
+    }
+
+    async fn _read_lock_raw(
+        &self,
+        tname: &str,
+    ) -> Result<RwLockReadGuardArc<Tree>, JsonStoreError> {
+        let tree = self._tree(tname)?;
+        let started = Instant::now();
+        let guard = tree.read_arc().await;
+        self._lock_waited(tname, "read", started);
+        Ok(guard)
+    }
+
+    // the store's directory, or what stands for it in other backends
+    fn _path(&self) -> PathBuf {
+        let path = self.shared.backend.location(META_FILE);
+        path.parent().unwrap_or(&path).to_path_buf()
+    }
+
+    fn _lock_waited(&self, tname: &str, mode: &str, started: Instant) {
+        let waited = started.elapsed();
+        lock_waited(tname, mode, waited);
+        if let Some(metrics) = &self.shared.metrics {
+            metrics.record_lock(tname, waited, waited >= LOCK_WAIT_THRESHOLD);
+        }
+    }
+
+    fn _record_scan(&self, tname: &str, records: u64) {
+        if let Some(metrics) = &self.shared.metrics {
+            metrics.record_scan(tname, records);
+        }
+    }
+
+    // run op, counting it in the metrics if they are on
+    async fn _metered<T>(
+        &self,
+        op: &str,
+        tname: Option<&str>,
+        op_future: impl Future<Output = Result<T, JsonStoreError>>,
+    ) -> Result<T, JsonStoreError> {
+        let Some(metrics) = &self.shared.metrics else {
+            return op_future.await;
+        };
+
+        let started = Instant::now();
+        let result = op_future.await;
+        metrics.record(op, tname, started.elapsed(), result.as_ref().err());
+        result
+    }
+
+    async fn _load(&self, tname: &str, tree: &mut Tree) -> Result<(), JsonStoreError> {
+        if tree.loaded {
+            return Ok(());
+        }
+        if let Some(reason) = &tree.corrupt {
+            return Err(JsonStoreError::TreeCorrupt {
+                tree: tname.to_string(),
+                reason: reason.clone(),
+            });
+        }
+
+        let info = self._info(tname)?;
+        let result = read_tree(
+            &*self.shared.backend,
+            &self.shared.layout,
+            tname,
+            &info,
+            self.shared.codec,
+            self.shared.read_only,
+            self.shared.spill_dir.as_deref(),
+        )
+        .await;
+
+        match result {
+            Ok(fresh) => {
+                check_loaded(tname, &info, &fresh, self.shared.corruption_policy)?;
+                tree.replace_contents(fresh);
+            }
+            Err(e)
+                if self.shared.corruption_policy != CorruptionPolicy::Fail && is_corruption(&e) =>
+            {
+                trace::error!(tree = tname, error = %e, "tree is corrupt; it stays unavailable until repaired");
+                tree.corrupt = Some(e.to_string());
+                return Err(JsonStoreError::TreeCorrupt {
+                    tree: tname.to_string(),
+                    reason: e.to_string(),
+                });
+            }
+            Err(e) => return Err(e),
+        }
+
+        Ok(())
+    }
+
+    // counters since the store was loaded or metrics last reset; None unless
+    // LoadOptions::metrics is set
+    pub fn metrics(&self) -> Option<MetricsSnapshot> {
+        self.shared.metrics.as_ref().map(Metrics::snapshot)
+    }
+
+    pub fn reset_metrics(&self) {
+        if let Some(metrics) = &self.shared.metrics {
+            metrics.reset(self.shared.clock.now());
+        }
+    }
+
+    // per-tree outcome of loading under CorruptionPolicy::Report
+    pub fn load_report(&self) -> Option<&LoadReport> {
+        self.shared.load_report.as_ref()
+    }
+
+    #[deprecated(note = "use stats(), describe() or tracing instead of printing to stdout")]
+    pub fn show(&self) {
+        let catalog = self._catalog();
+        println!("{:?}", catalog.infos);
+        println!("{:?}", catalog.trees);
+    }
+}
+
+impl Drop for JsonStore {
+    fn drop(&mut self) {
+        // only the last handle speaks for the store
+        if Arc::strong_count(&self.shared) > 1 || self.is_closed() {
+            return;
+        }
+
+        let mut dirty = self
+            ._catalog()
+            .trees
+            .iter()
+            .filter(|(_, tree)| tree.try_read().is_none_or(|t| t.changed))
+            .map(|(tname, _)| tname.clone())
+            .collect::<Vec<_>>();
+
+        if !dirty.is_empty() {
+            dirty.sort();
+            trace::warn!(
+                trees = ?dirty,
+                backend = ?self.shared.backend,
+                "json store dropped with unsaved changes; call save() or close() first"
+            );
+        }
+    }
+}
+
+// the records at sequences of tree, in that order, as T
+async fn deserialize_records<T: DeserializeOwned>(
+    tname: &str,
+    tree: &Tree,
+    sequences: Vec<u64>,
+) -> Result<Vec<T>, JsonStoreError> {
+    let mut selected = Vec::with_capacity(sequences.len());
+    for batch in sequences.chunks(SCAN_BATCH) {
+        let records = tree.records(batch.to_vec()).await?;
+        if records.len() < batch.len() {
+            return Err(JsonStoreError::SequenceNotExist(tname.to_string()));
+        }
+        for (sequence, value) in records {
+            selected.push(T::deserialize(&*value).map_err(|source| {
+                JsonStoreError::DeserializeRecord {
+                    tree: tname.to_string(),
+                    sequence,
+                    source,
+                }
+            })?);
+        }
+    }
+    Ok(selected)
+}
+
+// read_only leaves torn log tails in place instead of truncating them; a tree with a
+// resident limit spills to spill_dir
+async fn read_tree(
+    backend: &dyn StorageBackend,
+    layout: &Layout,
+    tname: &str,
+    info: &Info,
+    codec: Codec,
+    read_only: bool,
+    spill_dir: Option<&Path>,
+) -> Result<Tree, JsonStoreError> {
+    let storage = info.storage;
+    // stamp before reading: a write in between then shows up as a conflict, not a lost edit
+    let key = layout.seq_key(tname);
+    let seq_stamp = backend.stamp(&key).await?;
+    let stored = get_sequence(backend, tname, &key).await;
+    let sequence = match stored {
+        Ok(stored) => stored.unwrap_or(0),
+        Err(JsonStoreError::CorruptSequenceFile { .. }) => 0,
+        Err(e) => return Err(e),
+    };
+
+    if storage == StorageFormat::AppendLog {
+        let mut data = Records::new(info);
+        let mut sequence = sequence;
+        let key = layout.log_key(tname);
+        append_log::fold(backend, &key, &mut data, &mut sequence, !read_only).await?;
+
+        let max = data.keys().max().copied();
+        let (sequence, fixed) = reconcile_sequence(tname, stored, sequence, max)?;
+        let mut tree = Tree::new(sequence, data, fixed && !read_only);
+        tree.storage = storage;
+        tree.seq_stamp = seq_stamp;
+        tree.history = read_history(backend, layout, tname, info).await?;
+        tree.indexes = FieldIndexes::build(info, &tree.data);
+        return Ok(tree);
+    }
+
+    let shards = info.shards.unwrap_or(0);
+    let mut tree = Tree::new(0, Records::new(info), false);
+    tree.compression = info.compression;
+    tree.set_value_mode(info.value_mode);
+    // records to be spilled are read as text, so they are written out without a Value
+    if info.resident_limit.is_some() {
+        tree.set_value_mode(ValueMode::Raw);
+    }
+    let mut stamps = Vec::new();
+    // whether a keyed tree's records were given fields they left out
+    let mut rekeyed = false;
+    let key = layout.wal_key(tname);
+    match &info.partition_by {
+        Some(spec) => {
+            let index = get_json::<PartitionIndex>(backend, &layout.partitions_key(tname))
+                .await?
+                .unwrap_or_default();
+            tree.set_partitions(spec.clone(), index);
+            // logged writes may touch any partition
+            if wal::size(backend, &key).await? > 0 {
+                let periods = tree.partitions.keys().cloned().collect::<Vec<_>>();
+                read_partitions(backend, layout, tname, codec, &mut tree, &periods).await?;
+            }
+        }
+        None => {
+            let compression = info.compression;
+            let mut keyed = Vec::new();
+            for base in snapshot_bases(layout, tname, shards) {
+                let stamp = match &mut tree.raw {
+                    None if !info.key.is_sequence() => {
+                        let (part, stamp) = read_snapshot::<String, Arc<Value>>(
+                            backend,
+                            layout,
+                            tname,
+                            &base,
+                            codec,
+                            compression,
+                        )
+                        .await?;
+                        keyed.extend(part);
+                        stamp
+                    }
+                    Some(raw) => {
+                        let (part, stamp) =
+                            read_raw_snapshot(backend, layout, tname, &base, codec, compression)
+                                .await?;
+                        raw.extend(part);
+                        stamp
+                    }
+                    None => {
+                        let (part, stamp) =
+                            read_snapshot(backend, layout, tname, &base, codec, compression)
+                                .await?;
+                        tree.data.extend(part);
+                        stamp
+                    }
+                };
+                stamps.push(stamp);
+            }
+            if !info.key.is_sequence() {
+                let (data, filled) = key::from_keyed(tname, info, keyed, sequence)?;
+                tree.data = data;
+                rekeyed = filled;
+            }
+            // writes saved since the snapshot, older than any in the log
+            if shards == 0 {
+                let key = layout.delta_key(tname);
+                let unsnapshotted = &mut tree.unsnapshotted;
+                match (&mut tree.raw, &mut tree.data) {
+                    (Some(raw), _) => read_delta(backend, &key, raw, unsnapshotted).await?,
+                    (None, Records::Sequence(records)) => {
+                        read_delta(backend, &key, records, unsnapshotted).await?
+                    }
+                    (None, Records::Keyed(_)) => {
+                        read_keyed_delta(backend, &key, tname, info, &mut tree).await?
+                    }
+                }
+            }
+        }
+    }
+
+    // a log left next to the snapshot holds writes made after it; replay them
+    // whether or not WAL mode is on now, and let the next save fold them in
+    let mut sequence = sequence;
+    let repair = !read_only;
+    let replayed = match &mut tree.raw {
+        Some(raw) => wal::replay_raw(backend, &key, raw, &mut sequence, repair).await?,
+        None => wal::replay(backend, &key, &mut tree.data, &mut sequence, repair).await?,
+    };
+    let max = tree
+        .data
+        .keys()
+        .chain(tree.partition_of.keys())
+        .chain(tree.raw.iter().flat_map(|raw| raw.keys()))
+        .max()
+        .copied();
+    let (sequence, fixed) = reconcile_sequence(tname, stored, sequence, max)?;
+
+    // a read-only store can never save, so it doesn't count replayed entries or a
+    // repaired counter as changes
+    tree.sequence = sequence;
+    tree.changed = (replayed > 0 || fixed || rekeyed) && !read_only;
+    tree.wal_entries = replayed as u64;
+    // replayed writes aren't among unsnapshotted, nor are fields filled in
+    tree.whole = replayed > 0 || rekeyed;
+    tree.seq_stamp = seq_stamp;
+    tree.history = read_history(backend, layout, tname, info).await?;
+    if replayed > 0 {
+        tree.repartition();
+    }
+    match info.resident_limit {
+        Some(limit) => {
+            let spill = Spill::create(spill_dir, limit as usize).await?;
+            let out = match tree.len() >= BLOCKING_ENCODE_RECORDS {
+                true => {
+                    let info = info.clone();
+                    let (back, out) = rt::unblock(move || {
+                        let out = tree.spill_raw(&info, spill)?;
+                        Ok::<_, JsonStoreError>((tree, out))
+                    })
+                    .await??;
+                    tree = back;
+                    out
+                }
+                false => tree.spill_raw(info, spill)?,
+            };
+            if let Some(spill) = &mut tree.spill {
+                spill.spill(out).await?;
+            }
+        }
+        None => tree.indexes = FieldIndexes::build(info, &tree.data),
+    }
+    if shards == 0 {
+        tree.data_stamp = stamps.first().copied().flatten();
+    } else {
+        tree.shards = shards;
+        tree.shard_stamps = stamps;
+        // replayed entries may touch any shard, and the sequence file is saved with them
+        if tree.changed {
+            tree.dirty_shards = (0..shards).collect();
+        }
+    }
+
+    Ok(tree)
+}
+
+// Run the integrity check on a tree just read for the first time. Under
+// CorruptionPolicy::Fail a violation is an error; otherwise the tree loads anyway and
+// the report is returned for the load report.
+fn check_loaded(
+    tname: &str,
+    info: &Info,
+    tree: &Tree,
+    policy: CorruptionPolicy,
+) -> Result<Option<TreeIntegrityReport>, JsonStoreError> {
+    let report = integrity::check(info, tree.sequence, &tree.data);
+    if report.is_ok() {
+        return Ok(None);
+    }
+    if policy == CorruptionPolicy::Fail {
+        return Err(JsonStoreError::IntegrityViolation {
+            tree: tname.to_string(),
+            report: Box::new(report),
+        });
+    }
+    trace::warn!(
+        tree = tname,
+        violations = report.violations.len(),
+        "tree loaded with integrity violations"
+    );
+    Ok(Some(report))
+}
+
+// Check the counter read from the sequence file against the records loaded. A
+// missing or corrupt file is recovered from the highest sequence present, and a
+// counter behind the records is raised to it, as the next insert would otherwise
+// overwrite one. Only a corrupt file with no records to go by is an error. Returns
+// the sequence to use and whether it was repaired.
+fn reconcile_sequence(
+    tname: &str,
+    stored: Result<Option<u64>, JsonStoreError>,
+    sequence: u64,
+    max: Option<u64>,
+) -> Result<(u64, bool), JsonStoreError> {
+    let empty = max.is_none();
+    let max = max.unwrap_or(0);
+
+    match stored {
+        Ok(Some(_)) if sequence >= max => return Ok((sequence, false)),
+        Ok(Some(stored)) => {
+            trace::warn!(
+                tree = tname,
+                stored,
+                max,
+                "sequence counter is behind the records; raising it"
+            );
+        }
+        Ok(None) if empty => return Ok((sequence, false)),
+        Ok(None) => {
+            trace::warn!(
+                tree = tname,
+                max,
+                "sequence file missing; recovering it from the records"
+            );
+        }
+        Err(e) if empty => return Err(e),
+        Err(e) => {
+            trace::warn!(tree = tname, max, error = %e, "recovering sequence from the records");
+        }
+    }
+
+    Ok((sequence.max(max), true))
+}
+
+// read the snapshot file named by base, checking it against its checksum
+async fn read_snapshot<K, V>(
+    backend: &dyn StorageBackend,
+    layout: &Layout,
+    tname: &str,
+    base: &str,
+    codec: Codec,
+    compression: Option<Compression>,
+) -> Result<(HashMap<K, V>, Option<Stamp>), JsonStoreError>
+where
+    K: DeserializeOwned + Eq + Hash + Send + 'static,
+    V: DeserializeOwned + Send + 'static,
+{
+    let key = snapshot_file(backend, layout, base, codec, compression).await?;
+    let stamp = backend.stamp(&key).await?;
+    let data = match backend.read(&key).await? {
+        Some(context) => {
+            let expected = checksum::read_sidecar(backend, &key).await?;
+            let tname = tname.to_string();
+            let path = backend.location(&key);
+            let gzipped = key.ends_with(".gz");
+            // hashing, unpacking and parsing a large file would hold up every other
+            // task on this worker, so that goes to the blocking pool
+            match context.len() < BLOCKING_DECODE_SIZE {
+                true => decode_snapshot(&tname, &path, context, expected, gzipped, codec)?,
+                false => {
+                    rt::unblock(move || {
+                        decode_snapshot(&tname, &path, context, expected, gzipped, codec)
+                    })
+                    .await??
+                }
+            }
+        }
+        None => HashMap::new(),
+    };
+
+    Ok((data, stamp))
+}
+
+fn decode_snapshot<K: DeserializeOwned + Eq + Hash, V: DeserializeOwned>(
+    tname: &str,
+    path: &Path,
+    mut context: Vec<u8>,
+    expected: Option<String>,
+    gzipped: bool,
+    codec: Codec,
+) -> Result<HashMap<K, V>, JsonStoreError> {
+    if let Some(expected) = expected {
+        if checksum::digest(&context) != expected {
+            return Err(JsonStoreError::ChecksumMismatch {
+                tree: tname.to_string(),
+                path: path.into(),
+            });
+        }
+    }
+    if gzipped {
+        context = gunzip(&context)?;
+    }
+    codec.decode(path, context)
+}
+
+// read_snapshot for a Raw tree; only a JSON file can be taken as text as it is
+async fn read_raw_snapshot(
+    backend: &dyn StorageBackend,
+    layout: &Layout,
+    tname: &str,
+    base: &str,
+    codec: Codec,
+    compression: Option<Compression>,
+) -> Result<(RawRecords, Option<Stamp>), JsonStoreError> {
+    if codec == Codec::Json {
+        return read_snapshot(backend, layout, tname, base, codec, compression).await;
+    }
+    let (records, stamp) =
+        read_snapshot::<u64, Arc<Value>>(backend, layout, tname, base, codec, compression).await?;
+    let records = records
+        .into_iter()
+        .map(|(seq, value)| (seq, raw::to_raw(&value)))
+        .collect();
+    Ok((records, stamp))
+}
+
+// read the records of each of periods not yet in memory into tree
+async fn read_partitions(
+    backend: &dyn StorageBackend,
+    layout: &Layout,
+    tname: &str,
+    codec: Codec,
+    tree: &mut Tree,
+    periods: &[String],
+) -> Result<(), JsonStoreError> {
+    for period in periods {
+        if tree.partitions.get(period).is_none_or(|p| p.loaded) {
+            continue;
+        }
+        let base = partition_base(layout, tname, period);
+        let (records, stamp) =
+            read_snapshot(backend, layout, tname, &base, codec, tree.compression).await?;
+        tree.data.extend(records);
+        if let Some(partition) = tree.partitions.get_mut(period) {
+            partition.loaded = true;
+            partition.stamp = stamp;
+        }
+    }
+    Ok(())
+}
+
+// Copy the files at keys that exist into dir, under the same keys; with remove the
+// originals go, making it a move.
+async fn set_aside(
+    backend: &dyn StorageBackend,
+    keys: &[String],
+    dir: &str,
+    remove: bool,
+) -> Result<(), JsonStoreError> {
+    for key in keys.iter() {
+        if let Some(context) = backend.read(key).await? {
+            let key_aside = format!("{}/{}", dir, key);
+            backend
+                .write(&key_aside, context, Durability::default())
+                .await?;
+            if remove {
+                backend.delete(key).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn read_history(
+    backend: &dyn StorageBackend,
+    layout: &Layout,
+    tname: &str,
+    info: &Info,
+) -> Result<History, JsonStoreError> {
+    if info.history.is_none() {
+        return Ok(History::new());
+    }
+    Ok(get_json(backend, &layout.history_key(tname))
+        .await?
+        .unwrap_or_default())
+}
+
+fn lock_waited(tname: &str, mode: &str, waited: Duration) {
+    if waited >= LOCK_WAIT_THRESHOLD {
+        trace::debug!(
+            tree = tname,
+            mode,
+            waited_ms = waited.as_millis() as u64,
+            "waited for tree lock"
+        );
+    }
+}
+
+fn shard_base(layout: &Layout, tname: &str, index: u32) -> String {
+    format!("{}/part-{:03}", layout.base(tname), index)
+}
+
+fn partition_base(layout: &Layout, tname: &str, period: &str) -> String {
+    format!("{}/{}", layout.base(tname), period)
+}
+
+// the periods a partitioned tree's index lists on disk; none for other trees
+async fn read_periods(
+    backend: &dyn StorageBackend,
+    layout: &Layout,
+    tname: &str,
+    info: &Info,
+) -> Result<Vec<String>, JsonStoreError> {
+    if info.partition_by.is_none() {
+        return Ok(Vec::new());
+    }
+    let index = get_json::<PartitionIndex>(backend, &layout.partitions_key(tname)).await?;
+    Ok(index.unwrap_or_default().into_keys().collect())
+}
+
+// the names, without extension, of a tree's snapshot files: itself, or one per shard
+fn snapshot_bases(layout: &Layout, tname: &str, shards: u32) -> Vec<String> {
+    match shards {
+        0 => vec![layout.base(tname)],
+        n => (0..n).map(|i| shard_base(layout, tname, i)).collect(),
+    }
+}
+
+// each snapshot file of a loaded tree with the records it holds
+async fn snapshot_parts(
+    layout: &Layout,
+    tname: &str,
+    tree: &Tree,
+) -> Result<Vec<(String, FileRecords)>, JsonStoreError> {
+    if tree.partition_by.is_some() {
+        return Ok(tree
+            .partitions
+            .iter()
+            .map(|(period, partition)| {
+                let records = shared_records(
+                    partition
+                        .sequences
+                        .iter()
+                        .filter_map(|seq| tree.data.get_key_value(seq)),
+                );
+                (
+                    partition_base(layout, tname, period),
+                    FileRecords::Parsed(records),
+                )
+            })
+            .collect());
+    }
+
+    let mut parts = Vec::new();
+    for (i, base) in snapshot_bases(layout, tname, tree.shards)
+        .into_iter()
+        .enumerate()
+    {
+        let records = match tree.shards {
+            0 => tree.file_records(None).await?,
+            _ => tree.file_records(Some(i as u32)).await?,
+        };
+        parts.push((base, records));
+    }
+    Ok(parts)
+}
+
+fn shard_records<V: Clone>(data: &HashMap<u64, V>, shards: u32, index: u32) -> BTreeMap<u64, V> {
+    shared_records(
+        data.iter()
+            .filter(|(seq, _)| **seq % shards as u64 == index as u64),
+    )
+}
 
-async fn write_text(file: PathBuf, context: String) -> Result<(), JsonStoreError> {
-    let file = tokio::fs::File::create(file).await?;
+// records by sequence, sharing rather than copying them
+fn shared_records<'a, V: Clone + 'a>(
+    records: impl Iterator<Item = (&'a u64, &'a V)>,
+) -> BTreeMap<u64, V> {
+    records.map(|(seq, value)| (*seq, value.clone())).collect()
+}
+
+// The records of one snapshot file, as the tree holds them. Those of a Raw tree
+// are written as they are under JSON, other codecs needing them parsed.
+enum FileRecords {
+    Parsed(BTreeMap<u64, Arc<Value>>),
+    Raw(BTreeMap<u64, Arc<RawValue>>),
+    // a keyed tree's, by key
+    Keyed(BTreeMap<String, Arc<Value>>),
+}
+
+impl FileRecords {
+    fn len(&self) -> usize {
+        match self {
+            FileRecords::Parsed(records) => records.len(),
+            FileRecords::Raw(records) => records.len(),
+            FileRecords::Keyed(records) => records.len(),
+        }
+    }
+
+    fn encode(&self, codec: Codec, format: OutputFormat) -> Result<Vec<u8>, JsonStoreError> {
+        match self {
+            FileRecords::Parsed(records) => codec.encode(records, format),
+            FileRecords::Keyed(records) => codec.encode(records, format),
+            FileRecords::Raw(records) if codec == Codec::Json => codec.encode(records, format),
+            FileRecords::Raw(records) => {
+                let records = raw::parse_all(shared_records(records.iter()))?;
+                codec.encode(&records.sorted(), format)
+            }
+        }
+    }
+}
+
+// the snapshot file to read: the one compression calls for, or if only the other
+// form exists (the setting changed since the last save), that one
+async fn snapshot_file(
+    backend: &dyn StorageBackend,
+    layout: &Layout,
+    base: &str,
+    codec: Codec,
+    compression: Option<Compression>,
+) -> Result<String, JsonStoreError> {
+    let key = layout.snapshot_key(base, codec, compression);
+    if exists(backend, &key).await? {
+        return Ok(key);
+    }
+
+    let other = match compression {
+        Some(Compression::Gzip) => layout.snapshot_key(base, codec, None),
+        None => layout.snapshot_key(base, codec, Some(Compression::Gzip)),
+    };
+    if exists(backend, &other).await? {
+        return Ok(other);
+    }
+
+    Ok(key)
+}
+
+// write records as the snapshot file key and its checksum, gzip'd if key says so
+// a snapshot file's contents, ready to write, and their checksum
+#[derive(Debug, Clone)]
+struct Encoded {
+    context: Vec<u8>,
+    digest: String,
+}
+
+// Encode records as the snapshot file key. Past a few thousand records this is
+// done on the blocking pool, taking long enough to hold up other tasks otherwise;
+// the records are shared, so handing them over copies none.
+async fn encode_snapshot(
+    key: &str,
+    records: FileRecords,
+    codec: Codec,
+    format: OutputFormat,
+) -> Result<Encoded, JsonStoreError> {
+    let gzipped = key.ends_with(".gz");
+    let small = records.len() < BLOCKING_ENCODE_RECORDS;
+    let encode = move || {
+        let mut context = records.encode(codec, format)?;
+        if gzipped {
+            context = gzip(&context)?;
+        }
+        let digest = checksum::digest(&context);
+        Ok(Encoded { context, digest })
+    };
+
+    match small {
+        true => encode(),
+        false => rt::unblock(encode).await?,
+    }
+}
+
+async fn write_snapshot(
+    backend: &dyn StorageBackend,
+    key: &str,
+    encoded: Encoded,
+    durability: Durability,
+) -> Result<(), JsonStoreError> {
+    let Encoded { context, digest } = encoded;
+    backend.write(key, context, durability).await?;
+    checksum::write_sidecar(backend, key, &digest, durability).await
+}
+
+// Write every record of tree changed since its snapshot to the delta file at key,
+// or null for one removed. Load applies it over the snapshot, so it is written whole
+// each time, holding the changes of every save since.
+async fn write_delta(
+    backend: &dyn StorageBackend,
+    key: &str,
+    tree: &Tree,
+    format: OutputFormat,
+    durability: Durability,
+) -> Result<(), JsonStoreError> {
+    let seqs = tree.unsnapshotted.iter();
+    match &tree.raw {
+        None if tree.data.is_keyed() => {
+            let mut delta = BTreeMap::new();
+            for seq in seqs {
+                match (tree.data.key_at(*seq), tree.removed_keys.get(seq)) {
+                    (Some(key), _) => {
+                        delta.insert(key, tree.data.get(seq));
+                    }
+                    // a key since taken by another record is that one's
+                    (None, Some(key)) => {
+                        delta.entry(key.as_str()).or_default();
+                    }
+                    (None, None) => {}
+                }
+            }
+            put_json(backend, key, &delta, format, durability).await
+        }
+        Some(raw) => {
+            let delta = seqs
+                .map(|seq| (*seq, raw.get(seq)))
+                .collect::<BTreeMap<_, _>>();
+            put_json(backend, key, &delta, format, durability).await
+        }
+        None => {
+            let delta = seqs
+                .map(|seq| (*seq, tree.data.get(seq)))
+                .collect::<BTreeMap<_, _>>();
+            put_json(backend, key, &delta, format, durability).await
+        }
+    }
+}
+
+// Apply the delta file at key over records, noting each record in it as not yet in
+// the snapshot.
+async fn read_delta<V: DeserializeOwned>(
+    backend: &dyn StorageBackend,
+    key: &str,
+    records: &mut HashMap<u64, V>,
+    unsnapshotted: &mut HashSet<u64>,
+) -> Result<(), JsonStoreError> {
+    let delta = get_json::<BTreeMap<u64, Option<V>>>(backend, key).await?;
+    for (seq, value) in delta.unwrap_or_default() {
+        match value {
+            Some(value) => records.insert(seq, value),
+            None => records.remove(&seq),
+        };
+        unsnapshotted.insert(seq);
+    }
+    Ok(())
+}
+
+// read_delta for a keyed tree, whose delta file is by key like its snapshot
+async fn read_keyed_delta(
+    backend: &dyn StorageBackend,
+    key: &str,
+    tname: &str,
+    info: &Info,
+    tree: &mut Tree,
+) -> Result<(), JsonStoreError> {
+    let delta = get_json::<BTreeMap<String, Option<Arc<Value>>>>(backend, key).await?;
+    for (name, value) in delta.unwrap_or_default() {
+        let seq = match value {
+            Some(value) => {
+                let seq = value[info.sequence_field.as_str()]
+                    .as_u64()
+                    .ok_or_else(|| JsonStoreError::TreeCorrupt {
+                        tree: tname.to_string(),
+                        reason: format!("record '{}' of the delta file has no sequence", name),
+                    })?;
+                tree.data.insert(seq, value);
+                seq
+            }
+            None => {
+                let Some(seq) = tree.data.sequence_of(&name) else {
+                    continue;
+                };
+                tree.data.remove(&seq);
+                tree.removed_keys.insert(seq, name);
+                seq
+            }
+        };
+        tree.unsnapshotted.insert(seq);
+    }
+    Ok(())
+}
 
-    let mut writer = tokio::io::BufWriter::new(file);
-    writer.write(context.as_bytes()).await?;
-    writer.flush().await?;
+// remove the snapshot files named by base in every form but current's
+async fn remove_stale_snapshots(
+    backend: &dyn StorageBackend,
+    layout: &Layout,
+    base: &str,
+    current: &str,
+) -> Result<(), JsonStoreError> {
+    for codec in Codec::ALL {
+        for compression in [None, Some(Compression::Gzip)] {
+            let key = layout.snapshot_key(base, codec, compression);
+            if key != current {
+                backend.delete(&checksum::sidecar_key(&key)).await?;
+                backend.delete(&key).await?;
+            }
+        }
+    }
 
     Ok(())
 }
+
+// Names of every file a tree may have in the store directory, but for the files of
+// a partitioned tree's partitions; see stored_tree_files.
+pub(crate) fn tree_files(layout: &Layout, tname: &str, info: &Info) -> Vec<String> {
+    let mut names = vec![
+        layout.seq_key(tname),
+        layout.wal_key(tname),
+        layout.log_key(tname),
+        layout.history_key(tname),
+        layout.partitions_key(tname),
+        layout.delta_key(tname),
+    ];
+    let mut bases = snapshot_bases(layout, tname, info.shards.unwrap_or(0));
+    if info.shards.is_some() {
+        bases.push(layout.base(tname));
+    }
+    for base in bases {
+        names.extend(snapshot_forms(layout, &base));
+    }
+    names
+}
+
+// the files of the partitions of a tree in periods, in the same order under any layout
+fn partition_files(layout: &Layout, tname: &str, periods: &[String]) -> Vec<String> {
+    periods
+        .iter()
+        .flat_map(|period| snapshot_forms(layout, &partition_base(layout, tname, period)))
+        .collect()
+}
+
+// tree_files with the partition files the tree's index lists on disk
+async fn stored_tree_files(
+    backend: &dyn StorageBackend,
+    layout: &Layout,
+    tname: &str,
+    info: &Info,
+) -> Result<Vec<String>, JsonStoreError> {
+    let periods = read_periods(backend, layout, tname, info).await?;
+    let mut names = tree_files(layout, tname, info);
+    names.extend(partition_files(layout, tname, &periods));
+    Ok(names)
+}
+
+// every file a snapshot named base may be, with its checksum
+fn snapshot_forms(layout: &Layout, base: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    for codec in Codec::ALL {
+        for compression in [None, Some(Compression::Gzip)] {
+            let name = layout.snapshot_key(base, codec, compression);
+            names.push(checksum::sidecar_key(&name));
+            names.push(name);
+        }
+    }
+    names
+}
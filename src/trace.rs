@@ -0,0 +1,50 @@
+// The `tracing` macros when the feature is on. Without it they are stand-ins that
+// log nothing, so logging call sites needn't be cfg'd one by one. The stand-ins
+// still take a reference to each field and message argument, so a value computed
+// only to be logged isn't left unused either way.
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::{debug, error, info, warn};
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! discard {
+    (@fields) => {
+        ()
+    };
+    // name = ?value, name = %value, name = value
+    (@fields $($name:ident).+ = ? $value:expr $(, $($rest:tt)*)?) => {{
+        let _ = &$value;
+        $crate::trace::discard!(@fields $($($rest)*)?)
+    }};
+    (@fields $($name:ident).+ = % $value:expr $(, $($rest:tt)*)?) => {{
+        let _ = &$value;
+        $crate::trace::discard!(@fields $($($rest)*)?)
+    }};
+    (@fields $($name:ident).+ = $value:expr $(, $($rest:tt)*)?) => {{
+        let _ = &$value;
+        $crate::trace::discard!(@fields $($($rest)*)?)
+    }};
+    // ?value, %value and value, named after themselves
+    (@fields ? $value:expr $(, $($rest:tt)*)?) => {{
+        let _ = &$value;
+        $crate::trace::discard!(@fields $($($rest)*)?)
+    }};
+    (@fields % $value:expr $(, $($rest:tt)*)?) => {{
+        let _ = &$value;
+        $crate::trace::discard!(@fields $($($rest)*)?)
+    }};
+    (@fields $($name:ident).+ $(, $($rest:tt)*)?) => {{
+        let _ = &$($name).+;
+        $crate::trace::discard!(@fields $($($rest)*)?)
+    }};
+    // the message, last
+    (@fields $format:literal $(, $arg:expr)* $(,)?) => {{
+        let _ = format_args!($format $(, $arg)*);
+    }};
+    ($($tokens:tt)*) => {
+        $crate::trace::discard!(@fields $($tokens)*)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) use {discard, discard as debug, discard as error, discard as info, discard as warn};
@@ -0,0 +1,48 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::{collections::VecDeque, sync::Arc};
+
+// Each tree keeps its last few mutations in memory so undo_last can take them back.
+// The stack goes when the tree is unloaded, reloaded or the store is dropped.
+
+pub const DEFAULT_UNDO_DEPTH: usize = 16;
+
+// the operation an undo took back
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UndoOp {
+    Insert,
+    Update,
+    Delete,
+    Revert,
+    Import,
+    Merge,
+    Prune,
+    Purge,
+    Seed,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct UndoInfo {
+    pub op: UndoOp,
+    // records restored or removed, in the order the operation touched them
+    pub sequences: Vec<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct UndoEntry {
+    pub(crate) op: UndoOp,
+    // every touched record as it was before the operation, shared with whatever else
+    // still holds it; None if it didn't exist
+    pub(crate) prior: Vec<(u64, Option<Arc<Value>>)>,
+}
+
+pub(crate) fn push(stack: &mut VecDeque<UndoEntry>, entry: UndoEntry, depth: usize) {
+    if depth == 0 {
+        return;
+    }
+    if stack.len() >= depth {
+        stack.pop_front();
+    }
+    stack.push_back(entry);
+}
@@ -0,0 +1,265 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{value::RawValue, Value};
+use std::{
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    backend::StorageBackend, error::JsonStoreError, key::Records, raw::RawRecords, rt::fs,
+    store::Durability, trace,
+};
+
+// Write-ahead log mode. Every mutation is appended to `{tree}.wal` as one JSON line
+// before it is applied, and load replays the log over the last snapshot. Saving a
+// tree is then a checkpoint: the snapshot is rewritten and the log emptied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WalOptions {
+    // fsync the log after every append; without it a power loss can drop the newest entries
+    pub fsync: bool,
+    // checkpoint a tree once its log holds this many entries; 0 leaves it to saves
+    pub checkpoint_after_entries: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CheckpointStats {
+    // size of the log that the checkpoint emptied
+    pub bytes_reclaimed: u64,
+    // log entries folded into the snapshot
+    pub entries_folded: u64,
+}
+
+// Where restore_tree_to rewinds a tree to. Only the log since the tree's last
+// checkpoint is kept, so that is as far back as either can reach.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestorePoint {
+    // the state as of this time: every logged change made at or before it
+    Time(SystemTime),
+    // the state after this many changes since the checkpoint; 0 is the checkpoint
+    Change(u64),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub(crate) enum WalEntry<V> {
+    Insert { tree: String, seq: u64, value: V },
+    Update { tree: String, seq: u64, value: V },
+    Delete { tree: String, seq: u64 },
+}
+
+// A log line: the entry and when it was made, in milliseconds since the epoch. Lines
+// written before entries were timestamped have no `at`.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct Stamped<E> {
+    #[serde(flatten)]
+    pub(crate) entry: E,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) at: Option<u64>,
+}
+
+impl<E> Stamped<E> {
+    pub(crate) fn new(entry: E, at: SystemTime) -> Self {
+        let at = at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        Self {
+            entry,
+            at: Some(at),
+        }
+    }
+
+    pub(crate) fn time(&self) -> Option<SystemTime> {
+        self.at.map(|at| UNIX_EPOCH + Duration::from_millis(at))
+    }
+}
+
+pub(crate) async fn append<V: Serialize>(
+    backend: &dyn StorageBackend,
+    key: &str,
+    entry: &Stamped<&WalEntry<V>>,
+    fsync: bool,
+) -> Result<(), JsonStoreError> {
+    append_line(backend, key, serde_json::to_vec(entry)?, fsync).await
+}
+
+// append entries in a single write, so a backend without real appends pays once
+pub(crate) async fn append_all<E: Serialize>(
+    backend: &dyn StorageBackend,
+    key: &str,
+    entries: &[E],
+    fsync: bool,
+) -> Result<(), JsonStoreError> {
+    let mut context = Vec::new();
+    for entry in entries {
+        serde_json::to_writer(&mut context, entry)?;
+        context.push(b'\n');
+    }
+    backend.append(key, context, fsync).await
+}
+
+// replace the log at key with entries in one write, through a temp file and rename,
+// so a crash leaves either the old log or the new one
+pub(crate) async fn rewrite<E: Serialize>(
+    backend: &dyn StorageBackend,
+    key: &str,
+    entries: &[E],
+    fsync: bool,
+) -> Result<(), JsonStoreError> {
+    let mut context = Vec::new();
+    for entry in entries {
+        serde_json::to_writer(&mut context, entry)?;
+        context.push(b'\n');
+    }
+    let durability = match fsync {
+        true => Durability::Fsync,
+        false => Durability::Flush,
+    };
+    backend.write(key, context, durability).await
+}
+
+// Apply the log at key to data, returning the number of entries applied.
+pub(crate) async fn replay(
+    backend: &dyn StorageBackend,
+    key: &str,
+    data: &mut Records,
+    sequence: &mut u64,
+    repair: bool,
+) -> Result<usize, JsonStoreError> {
+    read_lines(backend, key, repair, |entry: WalEntry<Value>| match entry {
+        WalEntry::Insert { seq, value, .. } | WalEntry::Update { seq, value, .. } => {
+            data.insert(seq, Arc::new(value));
+            *sequence = (*sequence).max(seq);
+        }
+        WalEntry::Delete { seq, .. } => {
+            data.remove(&seq);
+        }
+    })
+    .await
+}
+
+// A log line as replay_raw reads it. A RawValue can't be read through the flattened,
+// tagged Stamped<WalEntry>, so this names the fields it needs instead.
+#[derive(Deserialize)]
+struct RawLine {
+    op: String,
+    seq: u64,
+    #[serde(default)]
+    value: Option<Arc<RawValue>>,
+}
+
+// replay for a Raw tree, keeping each value's text as it was logged
+pub(crate) async fn replay_raw(
+    backend: &dyn StorageBackend,
+    key: &str,
+    records: &mut RawRecords,
+    sequence: &mut u64,
+    repair: bool,
+) -> Result<usize, JsonStoreError> {
+    read_lines(backend, key, repair, |line: RawLine| match line.value {
+        Some(value) if line.op != "delete" => {
+            records.insert(line.seq, value);
+            *sequence = (*sequence).max(line.seq);
+        }
+        _ => {
+            records.remove(&line.seq);
+        }
+    })
+    .await
+}
+
+// the entries of the log at key, oldest first; a torn tail is left out but kept
+pub(crate) async fn read_entries(
+    backend: &dyn StorageBackend,
+    key: &str,
+) -> Result<Vec<Stamped<WalEntry<Value>>>, JsonStoreError> {
+    let mut entries = Vec::new();
+    read_lines(backend, key, false, |entry| entries.push(entry)).await?;
+    Ok(entries)
+}
+
+// append one JSON line to a log
+pub(crate) async fn append_line(
+    backend: &dyn StorageBackend,
+    key: &str,
+    mut line: Vec<u8>,
+    fsync: bool,
+) -> Result<(), JsonStoreError> {
+    line.push(b'\n');
+    backend.append(key, line, fsync).await
+}
+
+// FsBackend's append
+pub(crate) async fn append_file(
+    file: &Path,
+    context: Vec<u8>,
+    fsync: bool,
+) -> Result<(), JsonStoreError> {
+    let file = fs::File::append(file).await?;
+    file.write_all(context).await?;
+
+    if fsync {
+        file.sync_data().await?;
+    }
+
+    Ok(())
+}
+
+// Feed each line of a JSON-lines log to apply. Reading stops at the first line that
+// does not parse (a write torn by a crash); with repair the file is cut back to the
+// good prefix so later appends are not stranded behind it. Returns the number of
+// lines applied.
+pub(crate) async fn read_lines<E: DeserializeOwned>(
+    backend: &dyn StorageBackend,
+    key: &str,
+    repair: bool,
+    mut apply: impl FnMut(E),
+) -> Result<usize, JsonStoreError> {
+    let Some(context) = backend.read(key).await? else {
+        return Ok(0);
+    };
+
+    let mut applied = 0;
+    let mut good_len = 0;
+
+    for (lineno, line) in context.split_inclusive(|b| *b == b'\n').enumerate() {
+        match serde_json::from_slice::<E>(line) {
+            Ok(entry) => apply(entry),
+            Err(e) => {
+                trace::warn!(
+                    file = ?backend.location(key),
+                    line = lineno + 1,
+                    error = %e,
+                    "stopping log replay at unreadable entry; later entries are discarded"
+                );
+                break;
+            }
+        }
+
+        applied += 1;
+        good_len += line.len();
+    }
+
+    if repair && (good_len < context.len() || (good_len > 0 && !context.ends_with(b"\n"))) {
+        let mut good = context[..good_len].to_vec();
+        if good_len > 0 && !good.ends_with(b"\n") {
+            // the last good entry lost its newline; restore it so appends start on a fresh line
+            good.push(b'\n');
+        }
+        backend.write(key, good, Durability::Flush).await?;
+    }
+
+    Ok(applied)
+}
+
+pub(crate) async fn size(backend: &dyn StorageBackend, key: &str) -> Result<u64, JsonStoreError> {
+    Ok(backend.stamp(key).await?.map_or(0, |stamp| stamp.len()))
+}
+
+pub(crate) async fn truncate(
+    backend: &dyn StorageBackend,
+    key: &str,
+) -> Result<(), JsonStoreError> {
+    backend.delete(key).await
+}
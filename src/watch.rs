@@ -0,0 +1,173 @@
+use futures::{
+    channel::{mpsc, oneshot},
+    future::{self, Either},
+    StreamExt,
+};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    pin::pin,
+    time::Duration,
+};
+
+use crate::{
+    error::JsonStoreError,
+    rt::{self, Task},
+    store::JsonStore,
+    trace,
+};
+
+// how long the files of a store must be left alone before a watch acts on changes
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(250);
+
+// What a watch did about a tree whose files another process changed.
+#[derive(Debug)]
+pub enum WatchEvent {
+    // the tree was read again from its files
+    Reloaded { tree: String },
+    // The tree had unsaved changes, which were kept. reload_tree takes the files'
+    // version and save_tree_force ours; until one is called every change to the
+    // files brings another Conflict.
+    Conflict { tree: String, path: PathBuf },
+    // the changed files didn't read, as when caught halfway through an edit; the tree
+    // is left as it was until they change again
+    Failed { tree: String, error: JsonStoreError },
+}
+
+// Reloads trees whose files change on disk behind the store's back, from
+// JsonStore::watch. Changes are gathered until the files are left alone for the
+// debounce interval, so an editor's burst of writes makes one reload. A tree whose
+// files still carry the stamps of the store's own last read or write, the same ones
+// saves check for ExternallyModified, is left alone, as are trees not read yet; they
+// pick the files up on first use anyway.
+//
+// A snapshot edited by hand fails its checksum (see checksum.rs) and comes up as
+// Failed; remove its `.sha256` sidecar, or rewrite it with `sha256sum`, as part of
+// the edit. rehash_tree does the same after the fact.
+//
+// Dropping the handle stops the watch; events not taken by then are lost.
+#[derive(Debug)]
+pub struct WatchHandle {
+    events: mpsc::UnboundedReceiver<WatchEvent>,
+    stop: Option<oneshot::Sender<()>>,
+    task: Option<Task>,
+    // watching lasts as long as this does
+    _watcher: RecommendedWatcher,
+}
+
+impl WatchHandle {
+    pub(crate) fn start(
+        store: JsonStore,
+        root: PathBuf,
+        debounce: Duration,
+    ) -> Result<Self, JsonStoreError> {
+        let (changed, mut changes) = mpsc::unbounded();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+                Ok(event) if matches!(event.kind, EventKind::Access(_)) => {}
+                Ok(event) => event.paths.into_iter().for_each(|path| {
+                    let _ = changed.unbounded_send(path);
+                }),
+                Err(e) => trace::warn!(error = %e, "watching the store failed"),
+            })
+            .map_err(watch_error)?;
+        watcher
+            .watch(&root, RecursiveMode::Recursive)
+            .map_err(watch_error)?;
+
+        let (stop, mut stopped) = oneshot::channel();
+        let (events, receiver) = mpsc::unbounded();
+        let task = rt::spawn(async move {
+            while let Some(paths) = gather(&mut changes, &mut stopped, debounce).await {
+                for tname in store._trees_of(&root, &paths) {
+                    let event = match store._reload_changed(&tname).await {
+                        Ok(None) => continue,
+                        Ok(Some(event)) => event,
+                        Err(JsonStoreError::StoreClosed) => return,
+                        Err(error) => WatchEvent::Failed { tree: tname, error },
+                    };
+                    trace::info!(event = ?event, "store files changed");
+                    let _ = events.unbounded_send(event);
+                }
+            }
+        });
+
+        Ok(Self {
+            events: receiver,
+            stop: Some(stop),
+            task: Some(task),
+            _watcher: watcher,
+        })
+    }
+
+    // the next event, waiting for one; None once the watch has stopped
+    pub async fn next(&mut self) -> Option<WatchEvent> {
+        self.events.next().await
+    }
+
+    // an event if there is one waiting
+    pub fn try_next(&mut self) -> Option<WatchEvent> {
+        self.events.try_recv().ok()
+    }
+
+    // stop watching, waiting for a reload under way to finish
+    pub async fn stop(mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        if let Some(task) = self.task.take() {
+            task.join().await;
+        }
+    }
+}
+
+impl Drop for WatchHandle {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+    }
+}
+
+// The paths changed from the next change on until debounce passes without one; None
+// once stopped.
+async fn gather(
+    changes: &mut mpsc::UnboundedReceiver<PathBuf>,
+    stopped: &mut oneshot::Receiver<()>,
+    debounce: Duration,
+) -> Option<BTreeSet<PathBuf>> {
+    let mut paths = BTreeSet::new();
+    loop {
+        let waiting = !paths.is_empty();
+        let quiet = pin!(async move {
+            match waiting {
+                true => rt::sleep(debounce).await,
+                false => future::pending().await,
+            }
+        });
+        let next = future::select(changes.next(), quiet);
+        match future::select(&mut *stopped, next).await {
+            Either::Left(_) => return None,
+            Either::Right((Either::Left((Some(path), _)), _)) => {
+                paths.insert(path);
+            }
+            Either::Right((Either::Left((None, _)), _)) => return None,
+            Either::Right((Either::Right(_), _)) => return Some(paths),
+        }
+    }
+}
+
+fn watch_error(e: notify::Error) -> JsonStoreError {
+    JsonStoreError::Io(std::io::Error::other(e))
+}
+
+// key of the store file at path under root, if it is one
+pub(crate) fn key(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    let parts = relative
+        .components()
+        .map(|part| part.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()?;
+    Some(parts.join("/"))
+}
@@ -0,0 +1,189 @@
+mod common;
+
+use common::{all, store_with_users, ScratchDir};
+use json_store::{error::JsonStoreError, store::JsonStore};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+
+fn counter(dir: &ScratchDir) -> String {
+    std::fs::read_to_string(dir.path().join("users.seq")).unwrap()
+}
+
+#[track_caller]
+fn assert_not_reserved(result: Result<(), JsonStoreError>, sequence: u64) {
+    match result {
+        Err(JsonStoreError::SequenceNotReserved { sequence: s, .. }) => assert_eq!(s, sequence),
+        other => panic!("{}: {:?}", sequence, other),
+    }
+}
+
+#[tokio::test]
+async fn reserved_sequences_are_filled_in_any_order() {
+    let dir = ScratchDir::new("allocate-fill");
+    let store = store_with_users(&dir).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+
+    let range = store.allocate_sequences("users", 3).await.unwrap();
+    assert_eq!(range, 2..=4);
+    // the counter is on disk before a record is
+    assert_eq!(counter(&dir).trim(), "4");
+
+    // records can point at each other before either is written
+    store
+        .insert_reserved("users", 4, &json!({"email": "d@x", "manager": 2}))
+        .await
+        .unwrap();
+    store
+        .insert_reserved("users", 2, &json!({"email": "b@x", "reports": [4]}))
+        .await
+        .unwrap();
+    // the counter stayed; a plain insert goes after the reservation
+    assert_eq!(
+        store
+            .insert("users", &json!({"email": "e@x"}))
+            .await
+            .unwrap(),
+        5
+    );
+    store
+        .insert_reserved("users", 3, &json!({"email": "c@x"}))
+        .await
+        .unwrap();
+
+    let ids = all(&store, "users")
+        .await
+        .iter()
+        .map(|record| record["id"].as_u64().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(ids, [1, 2, 3, 4, 5]);
+    let d: Value = store.select("users", 4).await.unwrap();
+    assert_eq!(d["manager"], 2);
+}
+
+#[tokio::test]
+async fn only_reserved_and_unfilled_sequences_are_taken() {
+    let dir = ScratchDir::new("allocate-refuse");
+    let store = store_with_users(&dir).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store.allocate_sequences("users", 2).await.unwrap();
+    store
+        .insert_reserved("users", 2, &json!({"email": "b@x"}))
+        .await
+        .unwrap();
+
+    let record = json!({"email": "z@x"});
+    // filled already, handed out by insert, or not handed out at all
+    for sequence in [2, 1, 4, 100, 0] {
+        assert_not_reserved(
+            store.insert_reserved("users", sequence, &record).await,
+            sequence,
+        );
+    }
+
+    // a refused record leaves its sequence reserved
+    assert!(matches!(
+        store
+            .insert_reserved("users", 3, &json!({"email": "a@x"}))
+            .await,
+        Err(JsonStoreError::DuplicateUniqueFields(_))
+    ));
+    store.insert_reserved("users", 3, &record).await.unwrap();
+
+    // nothing to allocate is an empty range at the counter
+    let none = store.allocate_sequences("users", 0).await.unwrap();
+    assert!(none.is_empty());
+    assert_eq!(*none.start(), 4);
+    assert_eq!(
+        store
+            .insert("users", &json!({"email": "c@x"}))
+            .await
+            .unwrap(),
+        4
+    );
+}
+
+// The store goes away without saving or closing, as in a crash, right after each
+// allocation. The next one, from the files alone, must not hand anything out again.
+#[tokio::test]
+async fn a_reservation_outlives_a_crash() {
+    let dir = ScratchDir::new("allocate-crash");
+    let store = store_with_users(&dir).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store.close().await.unwrap();
+
+    let mut handed_out = BTreeSet::from([1]);
+    for n in [3, 1, 5] {
+        let store = JsonStore::load(dir.path()).await.unwrap();
+        let range = store.allocate_sequences("users", n).await.unwrap();
+        assert_eq!(range.clone().count() as u64, n);
+        for seq in range {
+            assert!(handed_out.insert(seq), "{} handed out twice", seq);
+        }
+        drop(store);
+    }
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    let seq = store
+        .insert("users", &json!({"email": "b@x"}))
+        .await
+        .unwrap();
+    assert!(!handed_out.contains(&seq), "{} handed out twice", seq);
+    assert_eq!(seq, handed_out.last().unwrap() + 1);
+
+    // reservations don't outlive the store that made them: those left are gaps
+    assert_not_reserved(
+        store
+            .insert_reserved("users", 2, &json!({"email": "c@x"}))
+            .await,
+        2,
+    );
+}
+
+#[tokio::test]
+async fn concurrent_allocations_do_not_overlap() {
+    let dir = ScratchDir::new("allocate-concurrent");
+    let store = store_with_users(&dir).await;
+
+    let tasks = (1..=16).map(|n| {
+        let store = store.clone();
+        tokio::spawn(async move {
+            let inserted = store
+                .insert("users", &json!({ "email": format!("{}@x", n) }))
+                .await
+                .unwrap();
+            let range = store.allocate_sequences("users", n).await.unwrap();
+            (inserted, range)
+        })
+    });
+    let mut taken = BTreeSet::new();
+    for task in tasks.collect::<Vec<_>>() {
+        let (inserted, range) = task.await.unwrap();
+        assert!(taken.insert(inserted));
+        for seq in range {
+            assert!(taken.insert(seq), "{} handed out twice", seq);
+        }
+    }
+    // every sequence up to the counter went to exactly one caller
+    let last = *taken.last().unwrap();
+    assert_eq!(taken, (1..=last).collect());
+    assert_eq!(counter(&dir).trim(), last.to_string());
+}
+
+#[tokio::test]
+async fn an_unknown_tree_has_nothing_to_allocate() {
+    let dir = ScratchDir::new("allocate-unknown");
+    let store = store_with_users(&dir).await;
+    assert!(matches!(
+        store.allocate_sequences("nothing", 1).await,
+        Err(JsonStoreError::NotFoundTree(_))
+    ));
+}
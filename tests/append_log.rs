@@ -0,0 +1,163 @@
+mod common;
+
+use common::{all, ScratchDir};
+use json_store::store::{Info, JsonStore, StorageFormat};
+use serde_json::{json, Value};
+
+fn log_info() -> Info {
+    Info::builder()
+        .sequence_field("id")
+        .unique("email", ["email"])
+        .storage(StorageFormat::AppendLog)
+        .build()
+        .unwrap()
+}
+
+async fn store_with_log(dir: &ScratchDir) -> JsonStore {
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    store.create_tree("events", log_info()).await.unwrap();
+    store
+}
+
+fn lines(dir: &ScratchDir) -> Vec<Value> {
+    std::fs::read_to_string(dir.path().join("events.jsonl"))
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn each_write_appends_one_line() {
+    let dir = ScratchDir::new("log-lines");
+    let store = store_with_log(&dir).await;
+
+    store
+        .insert("events", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store
+        .insert("events", &json!({"email": "b@x"}))
+        .await
+        .unwrap();
+    store
+        .update("events", &json!({"id": 1, "email": "c@x"}))
+        .await
+        .unwrap();
+    store.delete("events", 2).await.unwrap();
+
+    assert_eq!(
+        lines(&dir),
+        [
+            json!({"seq": 1, "deleted": false, "value": {"id": 1, "email": "a@x"}}),
+            json!({"seq": 2, "deleted": false, "value": {"id": 2, "email": "b@x"}}),
+            json!({"seq": 1, "deleted": false, "value": {"id": 1, "email": "c@x"}}),
+            json!({"seq": 2, "deleted": true}),
+        ]
+    );
+    assert!(!dir.path().join("events.json").exists());
+}
+
+#[tokio::test]
+async fn load_keeps_the_last_line_of_each_record() {
+    let dir = ScratchDir::new("log-fold");
+    let store = store_with_log(&dir).await;
+    store
+        .insert("events", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store
+        .insert("events", &json!({"email": "b@x"}))
+        .await
+        .unwrap();
+    store
+        .update("events", &json!({"id": 1, "email": "c@x"}))
+        .await
+        .unwrap();
+    store.delete("events", 2).await.unwrap();
+    store.save().await.unwrap();
+    drop(store);
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(
+        all(&store, "events").await,
+        [json!({"id": 1, "email": "c@x"})]
+    );
+    // a deleted sequence is not handed out again
+    assert_eq!(
+        store
+            .insert("events", &json!({"email": "d@x"}))
+            .await
+            .unwrap(),
+        3
+    );
+}
+
+#[tokio::test]
+async fn compact_drops_superseded_lines_and_tombstones() {
+    let dir = ScratchDir::new("log-compact");
+    let store = store_with_log(&dir).await;
+    for i in 0..10 {
+        store
+            .insert("events", &json!({ "email": format!("{}@x", i) }))
+            .await
+            .unwrap();
+    }
+    for seq in 1..=5 {
+        store.delete("events", seq).await.unwrap();
+    }
+    store
+        .update("events", &json!({"id": 6, "email": "six@x"}))
+        .await
+        .unwrap();
+    let before = all(&store, "events").await;
+
+    assert!(store.compact_tree("events").await.unwrap() > 0);
+    assert_eq!(lines(&dir).len(), 5);
+    assert_eq!(store.compact_tree("events").await.unwrap(), 0);
+
+    let reloaded = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(all(&reloaded, "events").await, before);
+    assert_eq!(
+        reloaded
+            .insert("events", &json!({"email": "n@x"}))
+            .await
+            .unwrap(),
+        11
+    );
+}
+
+#[tokio::test]
+async fn a_torn_last_line_is_dropped() {
+    let dir = ScratchDir::new("log-torn");
+    let store = store_with_log(&dir).await;
+    store
+        .insert("events", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    drop(store);
+
+    let path = dir.path().join("events.jsonl");
+    let mut text = std::fs::read_to_string(&path).unwrap();
+    text.push_str(r#"{"seq":2,"deleted":fa"#);
+    std::fs::write(&path, text).unwrap();
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(all(&store, "events").await.len(), 1);
+    store
+        .insert("events", &json!({"email": "b@x"}))
+        .await
+        .unwrap();
+    assert_eq!(lines(&dir).len(), 2);
+}
+
+#[tokio::test]
+async fn snapshot_trees_have_nothing_to_compact() {
+    let dir = ScratchDir::new("log-snapshot");
+    let store = common::store_with_users(&dir).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    assert_eq!(store.compact_tree("users").await.unwrap(), 0);
+}
@@ -0,0 +1,154 @@
+#![cfg(feature = "arbitrary-precision")]
+
+mod common;
+
+use common::{all, ScratchDir};
+use json_store::{
+    error::JsonStoreError,
+    index::SortOrder,
+    store::{Info, JsonStore},
+};
+use serde_json::Value;
+
+// ledger entries by sequence "id", unique by amount, ordered by amount
+fn ledger() -> Info {
+    Info::builder()
+        .sequence_field("id")
+        .unique("amount", ["amount"])
+        .index("account")
+        .index_ordered("amount")
+        .build()
+        .unwrap()
+}
+
+// Amounts no f64 holds: 19-digit integers at and past i64's edges, and 30-digit
+// decimals, two of which differ only in their last digit.
+const AMOUNTS: [&str; 6] = [
+    "9223372036854775807",
+    "-9223372036854775808",
+    "9999999999999999999",
+    "123456789012345678901234.567890",
+    "0.100000000000000000000000000001",
+    "0.100000000000000000000000000002",
+];
+
+// a record with the amount as written, its fields in an order a Map wouldn't keep
+fn entry(amount: &str) -> Value {
+    let text = format!(
+        r#"{{"memo": "x", "amount": {}, "account": 1234567890123456789}}"#,
+        amount
+    );
+    serde_json::from_str(&text).unwrap()
+}
+
+async fn filled(dir: &ScratchDir) -> JsonStore {
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    store.create_tree("ledger", ledger()).await.unwrap();
+    for amount in AMOUNTS {
+        store.insert("ledger", &entry(amount)).await.unwrap();
+    }
+    store
+}
+
+fn amounts(records: &[Value]) -> Vec<String> {
+    records
+        .iter()
+        .map(|record| record["amount"].to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn numbers_round_trip_digit_for_digit() {
+    let dir = ScratchDir::new("precision-round-trip");
+    let store = filled(&dir).await;
+    let check = |records: Vec<Value>| {
+        assert_eq!(amounts(&records), AMOUNTS);
+        for (seq, record) in (1..).zip(&records) {
+            assert_eq!(record["id"].as_u64(), Some(seq));
+            assert_eq!(record["account"].to_string(), "1234567890123456789");
+            // fields as they were written, the sequence after them
+            let fields = record.as_object().unwrap().keys().collect::<Vec<_>>();
+            assert_eq!(fields, ["memo", "amount", "account", "id"]);
+        }
+    };
+    check(all(&store, "ledger").await);
+    store.close().await.unwrap();
+
+    // the file holds the digits as written
+    let text = std::fs::read_to_string(dir.path().join("ledger.json")).unwrap();
+    for amount in AMOUNTS {
+        assert!(text.contains(amount), "{} not in {}", amount, text);
+    }
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    check(all(&store, "ledger").await);
+    let one: Value = store.select("ledger", 4).await.unwrap();
+    assert_eq!(one["amount"].to_string(), AMOUNTS[3]);
+
+    // sequences go on from where they were
+    assert_eq!(store.insert("ledger", &entry("1")).await.unwrap(), 7);
+}
+
+#[tokio::test]
+async fn lookups_and_constraints_see_every_digit() {
+    let dir = ScratchDir::new("precision-lookups");
+    let store = filled(&dir).await;
+
+    // the two decimals an f64 can't tell apart are two values
+    let found = store
+        .find_by_field::<Value>("ledger", "amount", &entry(AMOUNTS[5])["amount"])
+        .await
+        .unwrap();
+    assert_eq!(amounts(&found), [AMOUNTS[5]]);
+    assert!(matches!(
+        store.insert("ledger", &entry(AMOUNTS[4])).await,
+        Err(JsonStoreError::DuplicateUniqueFields(_))
+    ));
+    store
+        .insert("ledger", &entry("0.100000000000000000000000000003"))
+        .await
+        .unwrap();
+
+    let found = store
+        .find_by_field::<Value>("ledger", "account", &entry("0")["account"])
+        .await
+        .unwrap();
+    assert_eq!(found.len(), AMOUNTS.len() + 1);
+}
+
+#[tokio::test]
+async fn ordered_indexes_compare_every_digit() {
+    let dir = ScratchDir::new("precision-order");
+    let store = filled(&dir).await;
+
+    let sorted = store
+        .find_sorted::<Value>("ledger", "amount", SortOrder::Ascending, 10)
+        .await
+        .unwrap();
+    assert_eq!(
+        amounts(&sorted),
+        [AMOUNTS[1], AMOUNTS[4], AMOUNTS[5], AMOUNTS[0], AMOUNTS[2], AMOUNTS[3]]
+    );
+
+    // a range whose ends differ from its records only in the 30th digit
+    let low = entry("0.100000000000000000000000000001")["amount"].clone();
+    let high = entry("0.100000000000000000000000000002")["amount"].clone();
+    let between = store
+        .find_range::<Value, _>("ledger", "amount", low.clone()..high.clone())
+        .await
+        .unwrap();
+    assert_eq!(amounts(&between), [AMOUNTS[4]]);
+    let between = store
+        .find_range::<Value, _>("ledger", "amount", low..=high)
+        .await
+        .unwrap();
+    assert_eq!(amounts(&between), [AMOUNTS[4], AMOUNTS[5]]);
+
+    // and integers past u64 against decimals
+    let past = entry("18446744073709551616")["amount"].clone();
+    let above = store
+        .find_range::<Value, _>("ledger", "amount", past..)
+        .await
+        .unwrap();
+    assert_eq!(amounts(&above), [AMOUNTS[3]]);
+}
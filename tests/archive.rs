@@ -0,0 +1,197 @@
+#![cfg(feature = "archive")]
+
+mod common;
+
+use common::{all, store_with_users, ScratchDir};
+use flate2::{read::GzDecoder, write::GzEncoder};
+use json_store::{error::JsonStoreError, meta::FORMAT_VERSION, store::JsonStore};
+use serde_json::{json, Value};
+use std::{io::Read, path::Path};
+
+fn entries(path: &Path) -> Vec<(String, Vec<u8>)> {
+    let mut archive = tar::Archive::new(GzDecoder::new(std::fs::File::open(path).unwrap()));
+    archive
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            let mut entry = entry.unwrap();
+            let name = entry.path().unwrap().to_string_lossy().into_owned();
+            let mut bytes = Vec::new();
+            entry.read_to_end(&mut bytes).unwrap();
+            (name, bytes)
+        })
+        .collect()
+}
+
+fn repack(path: &Path, entries: &[(String, Vec<u8>)]) {
+    let gz = GzEncoder::new(std::fs::File::create(path).unwrap(), Default::default());
+    let mut tar = tar::Builder::new(gz);
+    for (name, bytes) in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(bytes.len() as u64);
+        header.set_mode(0o644);
+        // set_path refuses `..`, so write the name in by hand
+        header.as_gnu_mut().unwrap().name[..name.len()].copy_from_slice(name.as_bytes());
+        header.set_cksum();
+        tar.append(&header, bytes.as_slice()).unwrap();
+    }
+    tar.into_inner().unwrap().finish().unwrap();
+}
+
+// an archive of a store whose users tree has had its highest record deleted
+async fn archived(dir: &ScratchDir) -> std::path::PathBuf {
+    let store = store_with_users(dir).await;
+    for email in ["a@x", "b@x", "c@x"] {
+        store
+            .insert("users", &json!({ "email": email }))
+            .await
+            .unwrap();
+    }
+    store.delete("users", 3).await.unwrap();
+    let path = dir.path().join("store.tar.gz");
+    store.export_archive(&path).await.unwrap();
+    path
+}
+
+async fn refused(path: &Path) -> JsonStoreError {
+    let dest = ScratchDir::new("archive-refused-dest");
+    let err = JsonStore::import_archive(path, dest.path())
+        .await
+        .unwrap_err();
+    assert!(!dest.path().join("users.json").exists());
+    err
+}
+
+#[tokio::test]
+async fn an_archive_round_trips_with_its_counters() {
+    let dir = ScratchDir::new("archive-round-trip");
+    let dest = ScratchDir::new("archive-round-trip-dest");
+    let path = archived(&dir).await;
+
+    let names = entries(&path)
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    assert_eq!(names[0], "manifest.json");
+    for name in ["infos.json", "meta.json", "users.json", "users.seq"] {
+        assert!(names.iter().any(|n| n == name), "{:?}", names);
+    }
+
+    JsonStore::import_archive(&path, dest.path()).await.unwrap();
+    let store = JsonStore::load(dest.path()).await.unwrap();
+    assert_eq!(
+        all(&store, "users").await,
+        [
+            json!({"id": 1, "email": "a@x"}),
+            json!({"id": 2, "email": "b@x"})
+        ]
+    );
+    assert_eq!(
+        store
+            .insert("users", &json!({"email": "d@x"}))
+            .await
+            .unwrap(),
+        4
+    );
+}
+
+#[tokio::test]
+async fn a_non_empty_destination_is_refused() {
+    let dir = ScratchDir::new("archive-dest");
+    let dest = ScratchDir::new("archive-dest-full");
+    let path = archived(&dir).await;
+    std::fs::write(dest.path().join("notes.txt"), "mine").unwrap();
+
+    assert!(matches!(
+        JsonStore::import_archive(&path, dest.path()).await,
+        Err(JsonStoreError::BackupDestinationNotEmpty(_))
+    ));
+}
+
+#[tokio::test]
+async fn a_file_changed_after_packing_is_refused() {
+    let dir = ScratchDir::new("archive-tampered");
+    let path = archived(&dir).await;
+    let mut files = entries(&path);
+    let users = files
+        .iter_mut()
+        .find(|(name, _)| name == "users.json")
+        .unwrap();
+    users.1 = br#"{"1":{"id":1,"email":"z@x"}}"#.to_vec();
+    repack(&path, &files);
+
+    match refused(&path).await {
+        JsonStoreError::InvalidArchive { reason, .. } => {
+            assert!(reason.contains("users.json"), "{}", reason);
+            assert!(reason.contains("checksum"), "{}", reason);
+        }
+        other => panic!("expected InvalidArchive, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn files_missing_from_or_added_to_the_manifest_are_refused() {
+    let dir = ScratchDir::new("archive-manifest");
+    let path = archived(&dir).await;
+    let files = entries(&path);
+
+    let mut dropped = files.clone();
+    dropped.retain(|(name, _)| name != "users.seq");
+    repack(&path, &dropped);
+    assert!(
+        matches!(refused(&path).await, JsonStoreError::InvalidArchive { reason, .. } if reason.contains("missing"))
+    );
+
+    let mut added = files.clone();
+    added.push(("extra.json".to_string(), b"{}".to_vec()));
+    repack(&path, &added);
+    assert!(
+        matches!(refused(&path).await, JsonStoreError::InvalidArchive { reason, .. } if reason.contains("extra.json"))
+    );
+
+    let mut unlisted = files.clone();
+    unlisted.retain(|(name, _)| name != "manifest.json");
+    repack(&path, &unlisted);
+    assert!(
+        matches!(refused(&path).await, JsonStoreError::InvalidArchive { reason, .. } if reason.contains("manifest.json"))
+    );
+}
+
+#[tokio::test]
+async fn entries_outside_the_store_are_refused() {
+    let dir = ScratchDir::new("archive-escape");
+    let path = archived(&dir).await;
+
+    for name in ["../escape.json", ".hidden", "a/b/c/deep.json"] {
+        let mut files = entries(&path);
+        files.push((name.to_string(), b"{}".to_vec()));
+        let bad = dir.path().join("bad.tar.gz");
+        repack(&bad, &files);
+        match refused(&bad).await {
+            JsonStoreError::InvalidArchive { reason, .. } => {
+                assert!(reason.contains(name), "{}", reason)
+            }
+            other => panic!("expected InvalidArchive for {}, got {:?}", name, other),
+        }
+    }
+}
+
+#[tokio::test]
+async fn a_newer_format_is_refused() {
+    let dir = ScratchDir::new("archive-newer");
+    let path = archived(&dir).await;
+    let mut files = entries(&path);
+    let manifest = files
+        .iter_mut()
+        .find(|(name, _)| name == "manifest.json")
+        .unwrap();
+    let mut value: Value = serde_json::from_slice(&manifest.1).unwrap();
+    value["format_version"] = json!(FORMAT_VERSION + 1);
+    manifest.1 = serde_json::to_vec(&value).unwrap();
+    repack(&path, &files);
+
+    assert!(matches!(
+        refused(&path).await,
+        JsonStoreError::UnsupportedFormatVersion { found, .. } if found == FORMAT_VERSION + 1
+    ));
+}
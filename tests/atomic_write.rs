@@ -0,0 +1,125 @@
+mod common;
+
+use common::{all, read_json, store_with_users, ScratchDir};
+use json_store::store::{Durability, Info, JsonStore};
+use serde_json::json;
+
+// A save whose temp file fails part way must leave the file it was replacing whole.
+// The temp file is pointed at /dev/full, a writer that errors on every write.
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn a_failed_write_leaves_the_original_file() {
+    for durability in [Durability::Flush, Durability::Fsync] {
+        let dir = ScratchDir::new("atomic-failed");
+        let store = store_with_users(&dir).await;
+        store
+            .insert("users", &json!({"email": "a@x"}))
+            .await
+            .unwrap();
+        store.save().await.unwrap();
+        let saved = read_json(&dir.path().join("users.json"));
+
+        store
+            .insert("users", &json!({"email": "b@x"}))
+            .await
+            .unwrap();
+        let tmp = dir.path().join("users.json.tmp");
+        std::os::unix::fs::symlink("/dev/full", &tmp).unwrap();
+
+        assert!(store.save_with(durability).await.is_err());
+        assert!(store.is_dirty("users").await.unwrap());
+        assert_eq!(read_json(&dir.path().join("users.json")), saved);
+        assert!(!tmp.exists());
+
+        let reloaded = JsonStore::load(dir.path()).await.unwrap();
+        assert_eq!(all(&reloaded, "users").await.len(), 1);
+
+        // with the writer back, the next save goes through
+        store.save_with(durability).await.unwrap();
+        assert_eq!(
+            all(&JsonStore::load(dir.path()).await.unwrap(), "users")
+                .await
+                .len(),
+            2
+        );
+    }
+}
+
+// a crash after writing the temp file but before the rename leaves both behind
+#[tokio::test]
+async fn load_ignores_and_removes_a_stale_temp_file() {
+    let dir = ScratchDir::new("atomic-stale");
+    let store = store_with_users(&dir).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+    drop(store);
+
+    let tmp = dir.path().join("users.json.tmp");
+    let seq_tmp = dir.path().join("users.seq.tmp");
+    std::fs::write(&tmp, r#"{"1":{"id":1,"email":"#).unwrap();
+    std::fs::write(&seq_tmp, "9").unwrap();
+    let unrelated = dir.path().join("notes.txt.tmp");
+    std::fs::write(&unrelated, "mine").unwrap();
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(
+        all(&store, "users").await,
+        [json!({"id": 1, "email": "a@x"})]
+    );
+    assert!(!tmp.exists());
+    assert!(!seq_tmp.exists());
+    assert!(unrelated.exists());
+}
+
+// and the same for a sharded tree, whose parts are in a directory of their own
+#[tokio::test]
+async fn load_removes_stale_temp_files_of_parts() {
+    let dir = ScratchDir::new("atomic-stale-parts");
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    let info = Info::builder()
+        .sequence_field("id")
+        .shards(2)
+        .build()
+        .unwrap();
+    store.create_tree("users", info).await.unwrap();
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store.close().await.unwrap();
+
+    let parts = dir.path().join("users");
+    let tmp = parts.join("part-000.json.tmp");
+    std::fs::write(&tmp, r#"{"2":{"id":2,"email":"#).unwrap();
+    let unrelated = parts.join("notes.txt.tmp");
+    std::fs::write(&unrelated, "mine").unwrap();
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(
+        all(&store, "users").await,
+        [json!({"id": 1, "email": "a@x"})]
+    );
+    assert!(!tmp.exists());
+    assert!(unrelated.exists());
+}
+
+#[tokio::test]
+async fn a_save_leaves_no_temp_files() {
+    let dir = ScratchDir::new("atomic-clean");
+    let store = store_with_users(&dir).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store.save_with(Durability::Fsync).await.unwrap();
+
+    let leftovers = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.ends_with(".tmp"))
+        .collect::<Vec<_>>();
+    assert!(leftovers.is_empty(), "{:?}", leftovers);
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use common::{all, store_with_users, ScratchDir};
+use json_store::store::JsonStore;
+use serde_json::json;
+use std::time::Duration;
+
+const TICK: Duration = Duration::from_millis(20);
+
+// how many users a fresh load of dir finds
+async fn saved_users(dir: &ScratchDir) -> usize {
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    all(&store, "users").await.len()
+}
+
+async fn wait_until_clean(store: &JsonStore) {
+    for _ in 0..250 {
+        if !store.is_dirty("users").await.unwrap() {
+            return;
+        }
+        tokio::time::sleep(TICK).await;
+    }
+    panic!("autosave never ran");
+}
+
+#[tokio::test]
+async fn autosave_saves_changed_trees_on_its_own() {
+    let dir = ScratchDir::new("autosave-runs");
+    let store = store_with_users(&dir).await;
+    let autosave = store.start_autosave(TICK);
+
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    wait_until_clean(&store).await;
+    assert_eq!(saved_users(&dir).await, 1);
+
+    // and keeps going
+    store
+        .insert("users", &json!({"email": "b@x"}))
+        .await
+        .unwrap();
+    wait_until_clean(&store).await;
+    assert_eq!(saved_users(&dir).await, 2);
+
+    autosave.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn stop_saves_once_more() {
+    let dir = ScratchDir::new("autosave-stop");
+    let store = store_with_users(&dir).await;
+    let autosave = store.start_autosave(Duration::from_secs(3600));
+
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    autosave.stop().await.unwrap();
+
+    assert!(!store.is_dirty("users").await.unwrap());
+    assert_eq!(saved_users(&dir).await, 1);
+}
+
+#[tokio::test]
+async fn dropping_the_handle_stops_without_saving() {
+    let dir = ScratchDir::new("autosave-drop");
+    let store = store_with_users(&dir).await;
+    drop(store.start_autosave(TICK));
+
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    tokio::time::sleep(TICK * 10).await;
+
+    assert!(store.is_dirty("users").await.unwrap());
+    assert_eq!(saved_users(&dir).await, 0);
+}
+
+#[tokio::test]
+async fn autosave_ends_when_the_store_closes() {
+    let dir = ScratchDir::new("autosave-close");
+    let store = store_with_users(&dir).await;
+    let autosave = store.start_autosave(TICK);
+
+    store.clone().close().await.unwrap();
+    tokio::time::sleep(TICK * 5).await;
+
+    // the task has gone, so stopping only has the final save to report
+    assert!(autosave.stop().await.is_err());
+}
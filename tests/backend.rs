@@ -0,0 +1,255 @@
+mod common;
+
+use common::{all, users, ScratchDir};
+use futures::future::BoxFuture;
+use json_store::{
+    backend::{FsBackend, InMemoryBackend, Stamp, StorageBackend},
+    error::JsonStoreError,
+    store::{Durability, JsonStore, LoadOptions},
+};
+use serde_json::json;
+use std::{path::PathBuf, sync::Arc};
+
+// hands the same InMemoryBackend to more than one store, so a reload can see what
+// an earlier store saved
+#[derive(Debug, Clone, Default)]
+struct Shared(Arc<InMemoryBackend>);
+
+// an InMemoryBackend that refuses every write
+#[derive(Debug, Default)]
+struct ReadOnlyDisk(InMemoryBackend);
+
+fn refused(key: &str) -> JsonStoreError {
+    JsonStoreError::Backend {
+        key: key.to_string(),
+        message: "disk is read-only".to_string(),
+    }
+}
+
+impl StorageBackend for Shared {
+    fn read<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>, JsonStoreError>> {
+        self.0.read(key)
+    }
+
+    fn write<'a>(
+        &'a self,
+        key: &'a str,
+        bytes: Vec<u8>,
+        durability: Durability,
+    ) -> BoxFuture<'a, Result<(), JsonStoreError>> {
+        self.0.write(key, bytes, durability)
+    }
+
+    fn append<'a>(
+        &'a self,
+        key: &'a str,
+        bytes: Vec<u8>,
+        fsync: bool,
+    ) -> BoxFuture<'a, Result<(), JsonStoreError>> {
+        self.0.append(key, bytes, fsync)
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), JsonStoreError>> {
+        self.0.delete(key)
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<String>, JsonStoreError>> {
+        self.0.list()
+    }
+
+    fn stamp<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Stamp>, JsonStoreError>> {
+        self.0.stamp(key)
+    }
+
+    fn location(&self, key: &str) -> PathBuf {
+        self.0.location(key)
+    }
+}
+
+impl StorageBackend for ReadOnlyDisk {
+    fn read<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>, JsonStoreError>> {
+        self.0.read(key)
+    }
+
+    fn write<'a>(
+        &'a self,
+        key: &'a str,
+        _bytes: Vec<u8>,
+        _durability: Durability,
+    ) -> BoxFuture<'a, Result<(), JsonStoreError>> {
+        Box::pin(async move { Err(refused(key)) })
+    }
+
+    fn append<'a>(
+        &'a self,
+        key: &'a str,
+        _bytes: Vec<u8>,
+        _fsync: bool,
+    ) -> BoxFuture<'a, Result<(), JsonStoreError>> {
+        Box::pin(async move { Err(refused(key)) })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), JsonStoreError>> {
+        Box::pin(async move { Err(refused(key)) })
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<String>, JsonStoreError>> {
+        self.0.list()
+    }
+
+    fn stamp<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Stamp>, JsonStoreError>> {
+        self.0.stamp(key)
+    }
+
+    fn location(&self, key: &str) -> PathBuf {
+        self.0.location(key)
+    }
+}
+
+// what the store relies on from any backend
+async fn check_contract(backend: &dyn StorageBackend) {
+    assert_eq!(backend.read("a.json").await.unwrap(), None);
+    assert_eq!(backend.stamp("a.json").await.unwrap(), None);
+    assert!(backend.list().await.unwrap().is_empty());
+
+    backend
+        .write("b.json", b"[2]".to_vec(), Durability::default())
+        .await
+        .unwrap();
+    backend
+        .write("a.json", b"[1]".to_vec(), Durability::Fsync)
+        .await
+        .unwrap();
+    assert_eq!(backend.read("a.json").await.unwrap().unwrap(), b"[1]");
+    assert_eq!(backend.stamp("a.json").await.unwrap().unwrap().len(), 3);
+
+    // a rewrite replaces the contents and the stamp changes with them
+    let before = backend.stamp("a.json").await.unwrap();
+    backend
+        .write("a.json", b"[1, 11]".to_vec(), Durability::None)
+        .await
+        .unwrap();
+    assert_eq!(backend.read("a.json").await.unwrap().unwrap(), b"[1, 11]");
+    assert_ne!(backend.stamp("a.json").await.unwrap(), before);
+
+    backend
+        .append("a.wal", b"one\n".to_vec(), false)
+        .await
+        .unwrap();
+    backend
+        .append("a.wal", b"two\n".to_vec(), true)
+        .await
+        .unwrap();
+    assert_eq!(backend.read("a.wal").await.unwrap().unwrap(), b"one\ntwo\n");
+
+    // nested keys can be read back but stay out of the listing
+    backend
+        .write("aside/a.json", b"[0]".to_vec(), Durability::default())
+        .await
+        .unwrap();
+    assert_eq!(backend.read("aside/a.json").await.unwrap().unwrap(), b"[0]");
+    assert_eq!(backend.list().await.unwrap(), ["a.json", "a.wal", "b.json"]);
+
+    backend.delete("b.json").await.unwrap();
+    backend.delete("b.json").await.unwrap();
+    backend.delete("aside/a.json").await.unwrap();
+    assert_eq!(backend.read("b.json").await.unwrap(), None);
+    assert_eq!(backend.read("aside/a.json").await.unwrap(), None);
+    assert_eq!(backend.list().await.unwrap(), ["a.json", "a.wal"]);
+}
+
+#[tokio::test]
+async fn the_filesystem_backend_keeps_the_contract() {
+    let dir = ScratchDir::new("backend-fs");
+    let backend = FsBackend::new(dir.path());
+    check_contract(&backend).await;
+
+    assert_eq!(backend.location("a.json"), dir.path().join("a.json"));
+    assert!(dir.path().join("a.json").is_file());
+    assert!(!dir.path().join("aside").exists());
+}
+
+#[tokio::test]
+async fn the_in_memory_backend_keeps_the_contract() {
+    check_contract(&InMemoryBackend::new()).await;
+}
+
+#[tokio::test]
+async fn a_store_reloads_from_the_backend_it_saved_to() {
+    let backend = Shared::default();
+    let store = JsonStore::load_with_backend(backend.clone(), LoadOptions::default())
+        .await
+        .unwrap();
+    store.create_tree("users", users()).await.unwrap();
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store
+        .insert("users", &json!({"email": "b@x"}))
+        .await
+        .unwrap();
+    store.delete("users", 1).await.unwrap();
+    store.save().await.unwrap();
+
+    let keys = backend.list().await.unwrap();
+    assert!(keys.contains(&"users.json".to_string()), "{:?}", keys);
+
+    let reloaded = JsonStore::load_with_backend(backend, LoadOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(reloaded.list_trees(), ["users"]);
+    assert_eq!(
+        all(&reloaded, "users").await,
+        [json!({"id": 2, "email": "b@x"})]
+    );
+    assert_eq!(
+        reloaded
+            .insert("users", &json!({"email": "c@x"}))
+            .await
+            .unwrap(),
+        3
+    );
+}
+
+#[tokio::test]
+async fn the_same_store_on_disk_and_in_memory_writes_the_same_files() {
+    let dir = ScratchDir::new("backend-same-files");
+    let memory = Shared::default();
+    let on_disk = JsonStore::load(dir.path()).await.unwrap();
+    let in_memory = JsonStore::load_with_backend(memory.clone(), LoadOptions::default())
+        .await
+        .unwrap();
+    for store in [&on_disk, &in_memory] {
+        store.create_tree("users", users()).await.unwrap();
+        store
+            .insert("users", &json!({"email": "a@x"}))
+            .await
+            .unwrap();
+        store.save().await.unwrap();
+    }
+
+    let disk = FsBackend::new(dir.path());
+    assert_eq!(disk.list().await.unwrap(), memory.list().await.unwrap());
+    for key in ["users.json", "users.seq"] {
+        assert_eq!(
+            disk.read(key).await.unwrap(),
+            memory.read(key).await.unwrap(),
+            "{}",
+            key
+        );
+    }
+}
+
+#[tokio::test]
+async fn backend_failures_reach_the_caller() {
+    let store = JsonStore::load_with_backend(ReadOnlyDisk::default(), LoadOptions::default())
+        .await
+        .unwrap();
+    match store.create_tree("users", users()).await {
+        Err(JsonStoreError::Backend { message, .. }) => {
+            assert_eq!(message, "disk is read-only");
+        }
+        other => panic!("expected a backend error, got {:?}", other),
+    }
+}
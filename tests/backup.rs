@@ -0,0 +1,341 @@
+mod common;
+
+use common::{all, store_with_users, ScratchDir};
+use json_store::{
+    backup::BackupOptions,
+    clock::Clock,
+    error::JsonStoreError,
+    store::{JsonStore, LoadOptions},
+    wal::WalOptions,
+};
+use serde_json::json;
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[tokio::test]
+async fn a_backup_loads_as_the_store_was() {
+    let dir = ScratchDir::new("backup-round-trip");
+    let dest = ScratchDir::new("backup-round-trip-dest");
+    let store = store_with_users(&dir).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store
+        .insert("users", &json!({"email": "b@x"}))
+        .await
+        .unwrap();
+
+    // unsaved changes are flushed first
+    let report = store.backup(dest.path()).await.unwrap();
+    assert!(!store.is_dirty("users").await.unwrap());
+    assert_eq!(report.path, dest.path());
+
+    for (name, len) in &report.files {
+        let file = dest.path().join(name);
+        assert_eq!(std::fs::metadata(&file).unwrap().len(), *len, "{}", name);
+    }
+    for name in ["meta.json", "infos.json", "users.json", "users.seq"] {
+        assert!(report.files.contains_key(name), "{:?}", report.files);
+    }
+    assert_eq!(report.total_bytes(), report.files.values().sum::<u64>());
+
+    store.delete("users", 1).await.unwrap();
+    store
+        .insert("users", &json!({"email": "c@x"}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+
+    let backup = JsonStore::load(dest.path()).await.unwrap();
+    assert_eq!(
+        all(&backup, "users").await,
+        [
+            json!({"id": 1, "email": "a@x"}),
+            json!({"id": 2, "email": "b@x"})
+        ]
+    );
+    assert_eq!(
+        backup
+            .insert("users", &json!({"email": "d@x"}))
+            .await
+            .unwrap(),
+        3
+    );
+}
+
+#[tokio::test]
+async fn a_non_empty_destination_needs_overwrite() {
+    let dir = ScratchDir::new("backup-overwrite");
+    let dest = ScratchDir::new("backup-overwrite-dest");
+    let store = store_with_users(&dir).await;
+    std::fs::write(dest.path().join("notes.txt"), "mine").unwrap();
+
+    match store.backup(dest.path()).await {
+        Err(JsonStoreError::BackupDestinationNotEmpty(path)) => assert_eq!(path, dest.path()),
+        other => panic!("expected BackupDestinationNotEmpty, got {:?}", other),
+    }
+    assert!(!dest.path().join("users.json").exists());
+
+    store
+        .backup_with(dest.path(), BackupOptions { overwrite: true })
+        .await
+        .unwrap();
+    assert!(dest.path().join("users.json").exists());
+    assert!(dest.path().join("notes.txt").exists());
+}
+
+#[tokio::test]
+async fn a_missing_destination_is_created() {
+    let dir = ScratchDir::new("backup-create");
+    let store = store_with_users(&dir).await;
+    let dest = dir.path().join("nested/backup");
+
+    store.backup(&dest).await.unwrap();
+    assert_eq!(
+        all(&JsonStore::load(&dest).await.unwrap(), "users")
+            .await
+            .len(),
+        0
+    );
+}
+
+#[tokio::test]
+async fn a_read_only_store_backs_up_what_is_on_disk() {
+    let dir = ScratchDir::new("backup-read-only");
+    let dest = ScratchDir::new("backup-read-only-dest");
+    let store = store_with_users(&dir).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+
+    let options = LoadOptions {
+        read_only: true,
+        ..Default::default()
+    };
+    let read_only = JsonStore::load_with_options(dir.path(), options)
+        .await
+        .unwrap();
+    read_only.backup(dest.path()).await.unwrap();
+    assert_eq!(
+        all(&JsonStore::load(dest.path()).await.unwrap(), "users")
+            .await
+            .len(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn a_store_with_a_log_backs_up_whole() {
+    let dir = ScratchDir::new("backup-wal");
+    let dest = ScratchDir::new("backup-wal-dest");
+    let options = LoadOptions {
+        wal: Some(WalOptions::default()),
+        ..Default::default()
+    };
+    let store = JsonStore::load_with_options(dir.path(), options.clone())
+        .await
+        .unwrap();
+    store.create_tree("users", common::users()).await.unwrap();
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+
+    store.backup(dest.path()).await.unwrap();
+    let backup = JsonStore::load_with_options(dest.path(), options)
+        .await
+        .unwrap();
+    assert_eq!(all(&backup, "users").await.len(), 1);
+}
+
+// A clock the test moves by hand, stepping on by step each time it is read.
+#[derive(Debug)]
+struct TestClock {
+    now: Mutex<SystemTime>,
+    step: Duration,
+}
+
+impl TestClock {
+    fn at(secs: u64, step: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            now: Mutex::new(UNIX_EPOCH + Duration::from_secs(secs)),
+            step,
+        })
+    }
+
+    fn set(&self, secs: u64) {
+        *self.now.lock().unwrap() = UNIX_EPOCH + Duration::from_secs(secs);
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> SystemTime {
+        let mut now = self.now.lock().unwrap();
+        let then = *now;
+        *now += self.step;
+        then
+    }
+}
+
+async fn store_with_clock(dir: &ScratchDir, clock: Arc<TestClock>) -> JsonStore {
+    let options = LoadOptions {
+        clock: Some(clock),
+        ..Default::default()
+    };
+    let store = JsonStore::load_with_options(dir.path(), options)
+        .await
+        .unwrap();
+    store.create_tree("users", common::users()).await.unwrap();
+    store
+}
+
+fn backups(dir: &Path) -> Vec<String> {
+    let mut names = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+// 2024-06-01T03:00:00Z
+const JUNE_FIRST: u64 = 1_717_210_800;
+
+#[tokio::test]
+async fn rotated_backups_are_named_by_time_and_pruned_by_name() {
+    let dir = ScratchDir::new("backup-rotated");
+    let backups_dir = ScratchDir::new("backup-rotated-dest");
+    let clock = TestClock::at(JUNE_FIRST, Duration::ZERO);
+    let store = store_with_clock(&dir, clock.clone()).await;
+
+    // never pruned: not named like a rotated backup, or not a directory
+    for name in ["backup-notes", "other", "backup-2024-06-01T03-00-0x"] {
+        std::fs::create_dir(backups_dir.path().join(name)).unwrap();
+    }
+    std::fs::write(
+        backups_dir.path().join("backup-1999-01-01T00-00-00"),
+        "a file",
+    )
+    .unwrap();
+
+    let report = store.backup_rotated(backups_dir.path(), 2).await.unwrap();
+    assert_eq!(
+        report.path,
+        backups_dir.path().join("backup-2024-06-01T03-00-00-000")
+    );
+
+    // the clock going backwards doesn't make a new backup look like the oldest
+    for secs in [JUNE_FIRST + 86_400, JUNE_FIRST - 3_600, JUNE_FIRST + 60] {
+        clock.set(secs);
+        store.backup_rotated(backups_dir.path(), 2).await.unwrap();
+    }
+
+    assert_eq!(
+        backups(backups_dir.path()),
+        [
+            "backup-1999-01-01T00-00-00",
+            "backup-2024-06-01T03-00-0x",
+            "backup-2024-06-01T03-01-00-000",
+            "backup-2024-06-02T03-00-00-000",
+            "backup-notes",
+            "other",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn backups_in_the_same_millisecond_each_get_their_own() {
+    let dir = ScratchDir::new("backup-same-time");
+    let backups_dir = ScratchDir::new("backup-same-time-dest");
+    // one named before milliseconds were, the oldest of them all
+    std::fs::create_dir(backups_dir.path().join("backup-2024-06-01T03-00-00")).unwrap();
+    let clock = TestClock::at(JUNE_FIRST, Duration::ZERO);
+    let store = store_with_clock(&dir, clock).await;
+
+    let mut paths = Vec::new();
+    for n in 0..3 {
+        store
+            .insert("users", &json!({ "email": format!("{}@x", n) }))
+            .await
+            .unwrap();
+        paths.push(
+            store
+                .backup_rotated(backups_dir.path(), 3)
+                .await
+                .unwrap()
+                .path,
+        );
+    }
+    assert_eq!(
+        backups(backups_dir.path()),
+        [
+            "backup-2024-06-01T03-00-00-000",
+            "backup-2024-06-01T03-00-00-000-001",
+            "backup-2024-06-01T03-00-00-000-002",
+        ]
+    );
+    // each backup as the store was when it was taken
+    for (n, path) in paths.iter().enumerate() {
+        let backup = JsonStore::load(path).await.unwrap();
+        assert_eq!(all(&backup, "users").await.len(), n + 1);
+    }
+
+    // and pruned oldest first
+    store.backup_rotated(backups_dir.path(), 2).await.unwrap();
+    assert_eq!(
+        backups(backups_dir.path()),
+        [
+            "backup-2024-06-01T03-00-00-000-002",
+            "backup-2024-06-01T03-00-00-000-003",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn keep_zero_still_keeps_the_newest() {
+    let dir = ScratchDir::new("backup-keep-zero");
+    let backups_dir = ScratchDir::new("backup-keep-zero-dest");
+    let clock = TestClock::at(JUNE_FIRST, Duration::from_secs(1));
+    let store = store_with_clock(&dir, clock).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+
+    store.backup_rotated(backups_dir.path(), 0).await.unwrap();
+    let report = store.backup_rotated(backups_dir.path(), 0).await.unwrap();
+
+    assert_eq!(backups(backups_dir.path()).len(), 1);
+    let backup = JsonStore::load(&report.path).await.unwrap();
+    assert_eq!(all(&backup, "users").await.len(), 1);
+}
+
+#[tokio::test]
+async fn scheduled_backups_run_until_stopped() {
+    let dir = ScratchDir::new("backup-scheduled");
+    let backups_dir = ScratchDir::new("backup-scheduled-dest");
+    let clock = TestClock::at(JUNE_FIRST, Duration::from_secs(3_600));
+    let store = store_with_clock(&dir, clock).await;
+
+    let handle = store.start_scheduled_backups(backups_dir.path(), Duration::from_millis(10), 3);
+    for _ in 0..500 {
+        if backups(backups_dir.path()).len() == 3 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    // let a few more run, so pruning has something to do
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    handle.stop().await;
+
+    let kept = backups(backups_dir.path());
+    assert_eq!(kept.len(), 3, "{:?}", kept);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(backups(backups_dir.path()), kept);
+}
@@ -0,0 +1,127 @@
+mod common;
+
+use common::{all, users, ScratchDir};
+use json_store::{
+    batch::WriteBatching,
+    builder::JsonStoreBuilder,
+    error::JsonStoreError,
+    repair::CorruptionPolicy,
+    store::{FlushPolicy, Info, JsonStore},
+    wal::WalOptions,
+};
+use serde_json::json;
+
+#[tokio::test]
+async fn the_options_set_reach_the_store() {
+    let dir = ScratchDir::new("builder-options");
+    let spill = ScratchDir::new("builder-spill");
+    let store = JsonStore::builder(dir.path())
+        .wal(WalOptions::default())
+        .eager(true)
+        .corruption_policy(CorruptionPolicy::Report)
+        .metrics(true)
+        .undo_depth(1)
+        .load_concurrency(2)
+        .incremental_save(0.5)
+        .shrink_below(0.25)
+        .spill_dir(spill.path())
+        .write_batching(WriteBatching::Contended)
+        .flush_policy(FlushPolicy::AfterWrites(100))
+        .open()
+        .await
+        .unwrap();
+
+    assert_eq!(store.flush_policy(), FlushPolicy::AfterWrites(100));
+    assert!(store.metrics().is_some());
+    assert!(store.load_report().is_some());
+
+    let info = Info {
+        resident_limit: Some(1),
+        ..users()
+    };
+    store.create_tree("users", info).await.unwrap();
+    for n in 0..4 {
+        store
+            .insert("users", &json!({ "email": format!("{}@x", n) }))
+            .await
+            .unwrap();
+    }
+    store.save().await.unwrap();
+    assert_eq!(all(&store, "users").await.len(), 4);
+
+    // a small change is saved as a delta
+    store
+        .update("users", &json!({"id": 1, "email": "changed@x"}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+    assert!(dir.path().join("users.delta.json").exists());
+    // and only the last write can be undone
+    store.undo_last("users").await.unwrap();
+    assert!(store.undo_last("users").await.is_err());
+}
+
+#[tokio::test]
+async fn a_read_only_store_opens_from_the_builder() {
+    let dir = ScratchDir::new("builder-read-only");
+    let store = JsonStore::builder(dir.path()).open().await.unwrap();
+    store.create_tree("users", users()).await.unwrap();
+    store.close().await.unwrap();
+
+    let elsewhere = ScratchDir::new("builder-read-only-spill");
+    let store = JsonStore::builder(dir.path())
+        .read_only(true)
+        .spill_dir(elsewhere.path())
+        .open()
+        .await
+        .unwrap();
+    assert!(store.is_read_only());
+    assert!(store.has_tree("users"));
+}
+
+fn builder(dir: &ScratchDir) -> JsonStoreBuilder {
+    JsonStore::builder(&dir.path().join("store"))
+}
+
+#[tokio::test]
+async fn conflicting_options_are_refused_before_anything_is_opened() {
+    let dir = ScratchDir::new("builder-invalid");
+    let read_only = || builder(&dir).read_only(true);
+    let cases = [
+        ("wal", read_only().wal(WalOptions::default())),
+        ("flush", read_only().flush_policy(FlushPolicy::EveryWrite)),
+        ("replica", read_only().replica(true)),
+        ("incremental", read_only().incremental_save(0.5)),
+        (
+            "batching",
+            read_only().write_batching(WriteBatching::Always),
+        ),
+        ("shrink", read_only().shrink_below(0.5)),
+        (
+            "spill",
+            read_only().spill_dir(dir.path().join("store").join("spill")),
+        ),
+        ("incremental 0", builder(&dir).incremental_save(0.0)),
+        ("incremental 2", builder(&dir).incremental_save(2.0)),
+        ("incremental NaN", builder(&dir).incremental_save(f64::NAN)),
+        ("shrink 1", builder(&dir).shrink_below(1.0)),
+        ("shrink -1", builder(&dir).shrink_below(-1.0)),
+    ];
+    for (name, builder) in cases {
+        match builder.open().await {
+            Err(JsonStoreError::InvalidOptions(_)) => {}
+            other => panic!("{}: {:?}", name, other.map(|_| ())),
+        }
+    }
+    // the checks come first, so no directory was made
+    assert!(!dir.path().join("store").exists());
+
+    // each on its own is fine
+    builder(&dir)
+        .incremental_save(1.0)
+        .shrink_below(0.5)
+        .write_batching(WriteBatching::Always)
+        .open()
+        .await
+        .unwrap();
+}
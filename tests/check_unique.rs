@@ -0,0 +1,204 @@
+mod common;
+
+use common::{all, ScratchDir};
+use json_store::{
+    error::JsonStoreError,
+    store::{Info, JsonStore},
+};
+use serde_json::{json, Value};
+
+// accounts unique by email, and by handle within a realm
+fn accounts() -> Info {
+    Info::builder()
+        .sequence_field("id")
+        .unique("email", ["email"])
+        .unique("handle", ["realm", "handle"])
+        .build()
+        .unwrap()
+}
+
+// the records each test starts from, 1 to 4
+fn records() -> Vec<Value> {
+    vec![
+        json!({"email": "a@x", "realm": "eu", "handle": "ann"}),
+        json!({"email": "b@x", "realm": "us", "handle": "ann"}),
+        json!({"email": 7, "realm": "eu", "handle": 17}),
+        // no email at all, so null, and no handle
+        json!({"realm": "eu"}),
+    ]
+}
+
+async fn filled(dir: &ScratchDir) -> JsonStore {
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    store.create_tree("accounts", accounts()).await.unwrap();
+    for record in records() {
+        store.insert("accounts", &record).await.unwrap();
+    }
+    store
+}
+
+// Candidates whose answer follows from the rules insert and update go by: values are
+// compared as JSON, so case, type and 7 against 7.0 all matter, a missing field is
+// null, and a multi-field constraint clashes only on all its fields. Each comes with
+// the constraint to ask about and the record it clashes with there.
+fn candidates() -> Vec<(&'static str, Value, Option<u64>)> {
+    vec![
+        ("email", json!({"email": "a@x"}), Some(1)),
+        ("email", json!({"email": "A@x"}), None),
+        ("email", json!({"email": "c@x"}), None),
+        ("email", json!({"email": 7}), Some(3)),
+        ("email", json!({"email": "7"}), None),
+        ("email", json!({"email": 7.0}), None),
+        ("email", json!({"email": null}), Some(4)),
+        ("email", json!({"realm": "eu"}), Some(4)),
+        ("handle", json!({"realm": "eu", "handle": "ann"}), Some(1)),
+        ("handle", json!({"realm": "us", "handle": "ann"}), Some(2)),
+        ("handle", json!({"realm": "ap", "handle": "ann"}), None),
+        ("handle", json!({"realm": "eu", "handle": 17}), Some(3)),
+        ("handle", json!({"realm": "eu"}), Some(4)),
+        ("handle", json!({"realm": "us"}), None),
+        ("handle", json!({"handle": "ann"}), None),
+    ]
+}
+
+// a full record with candidate's fields and values free under every other constraint
+fn full(constraint: &str, candidate: &Value) -> Value {
+    let mut record = match constraint {
+        "email" => json!({"realm": "free", "handle": "free"}),
+        _ => json!({"email": "free@x"}),
+    };
+    for (field, value) in candidate.as_object().unwrap() {
+        record[field] = value.clone();
+    }
+    if constraint == "email" && candidate.get("email").is_none() {
+        record.as_object_mut().unwrap().remove("email");
+    }
+    record
+}
+
+#[tokio::test]
+async fn check_unique_answers_as_a_write_would() {
+    let dir = ScratchDir::new("check-unique-same");
+    let store = filled(&dir).await;
+    let before = all(&store, "accounts").await;
+
+    for (constraint, candidate, expected) in candidates() {
+        let answer = store
+            .check_unique("accounts", constraint, &candidate)
+            .await
+            .unwrap();
+        assert_eq!(answer, expected, "{} {}", constraint, candidate);
+
+        // a whole record is answered the same as its constraint's fields alone
+        let record = full(constraint, &candidate);
+        let whole = store
+            .check_unique("accounts", constraint, &record)
+            .await
+            .unwrap();
+        assert_eq!(whole, expected, "{} {}", constraint, record);
+
+        // and the write agrees
+        match (expected, store.insert("accounts", &record).await) {
+            (Some(_), Err(JsonStoreError::DuplicateUniqueFields(_))) => {}
+            (None, Ok(seq)) => store.delete("accounts", seq).await.unwrap(),
+            (expected, result) => {
+                panic!("{} {}: {:?} but {:?}", constraint, record, expected, result)
+            }
+        }
+    }
+    assert_eq!(all(&store, "accounts").await, before);
+}
+
+// What an update of record 1 meets: a clash with itself isn't one to it, so the
+// answer to compare with is the owner other than the record written.
+#[tokio::test]
+async fn an_update_agrees_with_check_unique() {
+    let dir = ScratchDir::new("check-unique-update");
+    let store = filled(&dir).await;
+
+    for (constraint, candidate, expected) in candidates() {
+        let mut record = full(constraint, &candidate);
+        record["id"] = json!(1);
+        let answer = store
+            .check_unique("accounts", constraint, &record)
+            .await
+            .unwrap();
+        assert_eq!(answer, expected, "{} {}", constraint, record);
+
+        let prior: Value = store.select("accounts", 1).await.unwrap();
+        match (expected, store.update("accounts", &record).await) {
+            (Some(owner), Err(JsonStoreError::DuplicateUniqueFields(_))) if owner != 1 => {}
+            (Some(1) | None, Ok(())) => store.update("accounts", &prior).await.unwrap(),
+            (expected, result) => {
+                panic!("{} {}: {:?} but {:?}", constraint, record, expected, result)
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn answers_follow_the_tree() {
+    let dir = ScratchDir::new("check-unique-follow");
+    let store = filled(&dir).await;
+    let candidate = json!({"email": "a@x"});
+    assert_eq!(
+        store
+            .check_unique("accounts", "email", &candidate)
+            .await
+            .unwrap(),
+        Some(1)
+    );
+
+    store.delete("accounts", 1).await.unwrap();
+    assert_eq!(
+        store
+            .check_unique("accounts", "email", &candidate)
+            .await
+            .unwrap(),
+        None
+    );
+
+    let seq = store.insert("accounts", &candidate).await.unwrap();
+    assert_eq!(
+        store
+            .check_unique("accounts", "email", &candidate)
+            .await
+            .unwrap(),
+        Some(seq)
+    );
+
+    // and across a reload, when the index is built again
+    store.close().await.unwrap();
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(
+        store
+            .check_unique("accounts", "email", &candidate)
+            .await
+            .unwrap(),
+        Some(seq)
+    );
+    // nothing was written by asking
+    assert!(!store.is_dirty("accounts").await.unwrap());
+}
+
+#[tokio::test]
+async fn bad_questions_are_errors() {
+    let dir = ScratchDir::new("check-unique-errors");
+    let store = filled(&dir).await;
+    assert!(matches!(
+        store
+            .check_unique("accounts", "phone", &json!({"phone": 1}))
+            .await,
+        Err(JsonStoreError::UniqueConstraintNotFound { .. })
+    ));
+    assert!(matches!(
+        store.check_unique("accounts", "email", &json!("a@x")).await,
+        Err(JsonStoreError::UnObjectValue)
+    ));
+    assert!(matches!(
+        store
+            .check_unique("nothing", "email", &json!({"email": "a@x"}))
+            .await,
+        Err(JsonStoreError::NotFoundTree(_))
+    ));
+}
@@ -0,0 +1,187 @@
+mod common;
+
+use common::{all, damage, edit, store_with_users, touch, ScratchDir};
+use json_store::{
+    checksum::ChecksumStatus,
+    error::JsonStoreError,
+    repair::CorruptionPolicy,
+    store::{JsonStore, LoadOptions},
+};
+use serde_json::{json, Value};
+use std::time::{Duration, SystemTime};
+
+async fn saved_users(dir: &ScratchDir) -> JsonStore {
+    let store = store_with_users(dir).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store
+        .insert("users", &json!({"email": "b@x"}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+    store
+}
+
+#[tokio::test]
+async fn save_writes_a_sidecar_in_sha256sum_format() {
+    let dir = ScratchDir::new("checksum-sidecar");
+    let store = saved_users(&dir).await;
+
+    let sidecar = std::fs::read_to_string(dir.path().join("users.json.sha256")).unwrap();
+    let (digest, name) = sidecar.trim_end().split_once("  ").unwrap();
+    assert_eq!(digest.len(), 64);
+    assert_eq!(name, "users.json");
+
+    let report = store.verify().await.unwrap();
+    assert_eq!(report.trees["users"], ChecksumStatus::Ok);
+    assert!(report.is_ok());
+}
+
+#[tokio::test]
+async fn damaged_file_fails_to_load_with_checksum_mismatch() {
+    let dir = ScratchDir::new("checksum-damaged");
+    drop(saved_users(&dir).await);
+    let file = dir.path().join("users.json");
+    let text = std::fs::read_to_string(&file).unwrap();
+    damage(&file, &text.replace("a@x", "z@x"));
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(
+        store.verify().await.unwrap().trees["users"],
+        ChecksumStatus::Mismatch
+    );
+    match store
+        .select_where::<serde_json::Value, _>("users", |_| true)
+        .await
+    {
+        Err(JsonStoreError::ChecksumMismatch { tree, path }) => {
+            assert_eq!(tree, "users");
+            assert_eq!(path, file);
+        }
+        other => panic!("expected ChecksumMismatch, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn file_without_sidecar_loads() {
+    let dir = ScratchDir::new("checksum-none");
+    drop(saved_users(&dir).await);
+    std::fs::remove_file(dir.path().join("users.json.sha256")).unwrap();
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(
+        store.verify().await.unwrap().trees["users"],
+        ChecksumStatus::NoChecksum
+    );
+    assert_eq!(all(&store, "users").await.len(), 2);
+}
+
+// Nothing tells an edit made behind the store's back from damage, however much newer
+// the file is, so it fails the same way until rehash_tree takes it.
+#[tokio::test]
+async fn an_edit_fails_until_it_is_rehashed() {
+    let dir = ScratchDir::new("checksum-edit");
+    let store = saved_users(&dir).await;
+    let file = dir.path().join("users.json");
+    let sidecar = dir.path().join("users.json.sha256");
+    let before = std::fs::read(&sidecar).unwrap();
+
+    edit(&file, r#"{"7": {"id": 7, "email": "c@x"}}"#);
+    touch(&file, SystemTime::now() + Duration::from_secs(60));
+    assert_eq!(
+        store.verify().await.unwrap().trees["users"],
+        ChecksumStatus::Mismatch
+    );
+    assert!(matches!(
+        store.reload_tree("users").await,
+        Err(JsonStoreError::ChecksumMismatch { .. })
+    ));
+    // the store's records stand, and reading left the sidecar alone
+    assert_eq!(all(&store, "users").await.len(), 2);
+    assert_eq!(std::fs::read(&sidecar).unwrap(), before);
+
+    assert_eq!(
+        store.rehash_tree("users").await.unwrap(),
+        std::slice::from_ref(&file)
+    );
+    assert_eq!(
+        store.verify().await.unwrap().trees["users"],
+        ChecksumStatus::Ok
+    );
+    // a tree in memory keeps its records until reloaded
+    assert_eq!(all(&store, "users").await.len(), 2);
+    store.reload_tree("users").await.unwrap();
+    assert_eq!(
+        all(&store, "users").await,
+        vec![json!({"id": 7, "email": "c@x"})]
+    );
+    assert!(store.rehash_tree("users").await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn an_edited_file_fails_to_load_under_each_policy() {
+    let dir = ScratchDir::new("checksum-load-edited");
+    drop(saved_users(&dir).await);
+    let file = dir.path().join("users.json");
+    edit(&file, r#"{"1": {"id": 1, "email": "c@x"}}"#);
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    for _ in 0..2 {
+        assert!(matches!(
+            store.select::<Value>("users", 1).await,
+            Err(JsonStoreError::ChecksumMismatch { .. })
+        ));
+    }
+    store.rehash_tree("users").await.unwrap();
+    assert_eq!(
+        all(&store, "users").await,
+        vec![json!({"id": 1, "email": "c@x"})]
+    );
+
+    // a tree set aside as unavailable comes back once rehashed
+    edit(&file, r#"{"1": {"id": 1, "email": "d@x"}}"#);
+    let options = LoadOptions {
+        corruption_policy: CorruptionPolicy::Skip,
+        ..LoadOptions::default()
+    };
+    let store = JsonStore::load_with_options(dir.path(), options)
+        .await
+        .unwrap();
+    assert!(matches!(
+        store.select::<Value>("users", 1).await,
+        Err(JsonStoreError::TreeCorrupt { .. })
+    ));
+    assert_eq!(store.rehash_tree("users").await.unwrap(), [file]);
+    assert_eq!(
+        all(&store, "users").await,
+        vec![json!({"id": 1, "email": "d@x"})]
+    );
+}
+
+#[tokio::test]
+async fn a_read_only_store_neither_takes_nor_rehashes_an_edit() {
+    let dir = ScratchDir::new("checksum-read-only");
+    drop(saved_users(&dir).await);
+    let sidecar = dir.path().join("users.json.sha256");
+    let before = std::fs::read(&sidecar).unwrap();
+    edit(&dir.path().join("users.json"), "{}");
+
+    let options = LoadOptions {
+        read_only: true,
+        ..LoadOptions::default()
+    };
+    let store = JsonStore::load_with_options(dir.path(), options)
+        .await
+        .unwrap();
+    assert!(matches!(
+        store.select::<Value>("users", 1).await,
+        Err(JsonStoreError::ChecksumMismatch { .. })
+    ));
+    assert!(matches!(
+        store.rehash_tree("users").await,
+        Err(JsonStoreError::ReadOnlyStore)
+    ));
+    assert_eq!(std::fs::read(&sidecar).unwrap(), before);
+}
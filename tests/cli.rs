@@ -0,0 +1,215 @@
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::ScratchDir;
+use json_store::store::{Info, JsonStore};
+use serde_json::{json, Value};
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+// Snapshot tests of the json-store binary: each command's exit code, stdout and stderr
+// against tests/snapshots/cli/{name}.txt. Set UPDATE_SNAPSHOTS=1 to write them afresh
+// after a change to the output, and look over the diff.
+//
+// JSON output is compared with its keys sorted, as the preserve_order feature keeps
+// them in the order written, and with what changes from run to run masked: the store's
+// directory and byte counts of memory, which depend on the features.
+
+fn people() -> Info {
+    Info::builder()
+        .sequence_field("id")
+        .unique("email", ["email"])
+        .build()
+        .unwrap()
+}
+
+// a saved store of three people and an empty tree of notes
+async fn store(dir: &ScratchDir) {
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    store.create_tree("people", people()).await.unwrap();
+    store.create_tree("notes", people()).await.unwrap();
+    for (email, team) in [("ada@x", 1), ("bob@x", 2), ("cy@x", 1)] {
+        let person = json!({"email": email, "team": team});
+        store.insert("people", &person).await.unwrap();
+    }
+    store.close().await.unwrap();
+}
+
+// json-store --path dir args, as a snapshot
+fn run(dir: &ScratchDir, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_json-store"))
+        .arg("--path")
+        .arg(dir.path())
+        .args(args)
+        .output()
+        .expect("the binary to run");
+    let mask = |text: &[u8]| stable(&String::from_utf8_lossy(text), dir.path());
+    format!(
+        "$ json-store {}\nexit: {}\n--- stdout\n{}--- stderr\n{}",
+        args.join(" ")
+            .replace(&*dir.path().to_string_lossy(), "[store]"),
+        output.status.code().unwrap_or(-1),
+        mask(&output.stdout),
+        mask(&output.stderr),
+    )
+}
+
+fn stable(text: &str, dir: &Path) -> String {
+    let text = text.replace(&*dir.to_string_lossy(), "[store]");
+    match serde_json::from_str::<Value>(&text) {
+        Ok(value) => format!(
+            "{}\n",
+            serde_json::to_string_pretty(&sorted(value)).unwrap()
+        ),
+        Err(_) => text,
+    }
+}
+
+fn sorted(value: Value) -> Value {
+    match value {
+        Value::Object(fields) => {
+            let mut fields = fields.into_iter().collect::<Vec<_>>();
+            fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+            let fields = fields.into_iter().map(|(key, value)| match key.as_str() {
+                "memory_bytes" => (key, json!("[bytes]")),
+                _ => (key, sorted(value)),
+            });
+            Value::Object(fields.collect())
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(sorted).collect()),
+        value => value,
+    }
+}
+
+fn assert_snapshot(name: &str, actual: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/snapshots/cli")
+        .join(format!("{}.txt", name));
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path).unwrap_or_default();
+    assert!(
+        expected == actual,
+        "{} doesn't match; rerun with UPDATE_SNAPSHOTS=1 if this is meant\n--- expected\n{}--- actual\n{}",
+        path.display(),
+        expected,
+        actual
+    );
+}
+
+#[tokio::test]
+async fn reads() {
+    let dir = ScratchDir::new("cli_reads");
+    store(&dir).await;
+
+    assert_snapshot("trees", &run(&dir, &["trees"]));
+    assert_snapshot("info", &run(&dir, &["info", "people"]));
+    assert_snapshot("get", &run(&dir, &["get", "people", "2"]));
+    assert_snapshot(
+        "query",
+        &run(&dir, &["query", "people", "--filter", r#"{"team": 1}"#]),
+    );
+    assert_snapshot(
+        "query_limit",
+        &run(&dir, &["query", "people", "--limit", "1"]),
+    );
+    assert_snapshot("stats", &run(&dir, &["stats"]));
+    assert_snapshot("verify", &run(&dir, &["verify"]));
+}
+
+#[tokio::test]
+async fn writes() {
+    let dir = ScratchDir::new("cli_writes");
+    store(&dir).await;
+
+    assert_snapshot(
+        "insert",
+        &run(
+            &dir,
+            &[
+                "insert",
+                "notes",
+                "--json",
+                r#"{"email": "dee@x", "team": 3}"#,
+            ],
+        ),
+    );
+    assert_snapshot("delete", &run(&dir, &["delete", "people", "1"]));
+    // both saved, so the next process sees them
+    assert_snapshot("after_writes", &run(&dir, &["trees"]));
+}
+
+#[tokio::test]
+async fn export_and_import() {
+    let dir = ScratchDir::new("cli_files");
+    store(&dir).await;
+    let file = dir.path().join("people.ndjson");
+    let file = file.to_str().unwrap();
+
+    let exported = run(
+        &dir,
+        &["export", "people", "--format", "ndjson", "-o", file],
+    );
+    assert_snapshot("export", &exported);
+    let lines = std::fs::read_to_string(file).unwrap();
+    let lines = lines.lines().map(|line| {
+        let record = sorted(serde_json::from_str(line).unwrap());
+        format!("{}\n", record)
+    });
+    assert_snapshot("export_file", &lines.collect::<String>());
+
+    assert_snapshot(
+        "import",
+        &run(&dir, &["import", "notes", file, "--format", "ndjson"]),
+    );
+    assert_snapshot(
+        "import_conflicts",
+        &run(
+            &dir,
+            &[
+                "import",
+                "notes",
+                file,
+                "--format",
+                "ndjson",
+                "--preserve",
+                "--skip-conflicts",
+            ],
+        ),
+    );
+}
+
+#[tokio::test]
+async fn failures() {
+    let dir = ScratchDir::new("cli_failures");
+    store(&dir).await;
+
+    assert_snapshot("missing_record", &run(&dir, &["get", "people", "9"]));
+    assert_snapshot("missing_tree", &run(&dir, &["info", "teams"]));
+    assert_snapshot(
+        "bad_filter",
+        &run(&dir, &["query", "people", "--filter", "[1]"]),
+    );
+    assert_snapshot("bad_usage", &run(&dir, &["get", "people"]));
+
+    // a snapshot edited behind the store's back
+    let path = dir.path().join("people.json");
+    let edited = std::fs::read_to_string(&path)
+        .unwrap()
+        .replace("bob", "rob");
+    common::damage(&path, &edited);
+    assert_snapshot("verify_damaged", &run(&dir, &["verify"]));
+}
+
+#[tokio::test]
+async fn a_missing_store_is_not_made() {
+    let dir = ScratchDir::new("cli_missing_store");
+    assert_snapshot("missing_store", &run(&dir, &["trees"]));
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use common::{all, edit, store_with_users, ScratchDir};
+use json_store::{
+    error::JsonStoreError,
+    store::{JsonStore, LoadOptions},
+};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn close_saves_and_shuts_every_handle() {
+    let dir = ScratchDir::new("close-saves");
+    let store = store_with_users(&dir).await;
+    let other = store.clone();
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+
+    store.close().await.unwrap();
+    assert!(other.is_closed());
+
+    let reloaded = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(all(&reloaded, "users").await.len(), 1);
+
+    assert!(matches!(
+        other.insert("users", &json!({"email": "b@x"})).await,
+        Err(JsonStoreError::StoreClosed)
+    ));
+    assert!(matches!(
+        other.select::<Value>("users", 1).await,
+        Err(JsonStoreError::StoreClosed)
+    ));
+    assert!(matches!(
+        other.save().await,
+        Err(JsonStoreError::StoreClosed)
+    ));
+    assert!(matches!(
+        other.close().await,
+        Err(JsonStoreError::StoreClosed)
+    ));
+}
+
+#[tokio::test]
+async fn a_failed_save_keeps_the_store_open() {
+    let dir = ScratchDir::new("close-failed");
+    let store = store_with_users(&dir).await;
+    let other = store.clone();
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    edit(&dir.path().join("users.json"), "{}");
+
+    assert!(store.close().await.is_err());
+    assert!(!other.is_closed());
+    assert_eq!(all(&other, "users").await.len(), 1);
+}
+
+#[tokio::test]
+async fn a_read_only_store_closes_without_saving() {
+    let dir = ScratchDir::new("close-read-only");
+    store_with_users(&dir).await;
+
+    let options = LoadOptions {
+        read_only: true,
+        ..Default::default()
+    };
+    let store = JsonStore::load_with_options(dir.path(), options)
+        .await
+        .unwrap();
+    let other = store.clone();
+    store.close().await.unwrap();
+    assert!(other.is_closed());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn no_write_from_another_handle_is_lost_to_a_close() {
+    let dir = ScratchDir::new("close-racing");
+    let store = store_with_users(&dir).await;
+
+    // other handles inserting until the store refuses them
+    let writers = (0..4)
+        .map(|writer| {
+            let other = store.clone();
+            tokio::spawn(async move {
+                let mut inserted = Vec::new();
+                for n in 0.. {
+                    let email = format!("{}@{}", n, writer);
+                    match other.insert("users", &json!({ "email": email })).await {
+                        Ok(_) => inserted.push(email),
+                        Err(JsonStoreError::StoreClosed) => return inserted,
+                        Err(e) => panic!("{:?}", e),
+                    }
+                    tokio::task::yield_now().await;
+                }
+                inserted
+            })
+        })
+        .collect::<Vec<_>>();
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    store.close().await.unwrap();
+
+    let mut inserted = Vec::new();
+    for writer in writers {
+        inserted.extend(writer.await.unwrap());
+    }
+    assert!(!inserted.is_empty());
+
+    // every insert that succeeded was saved
+    let reloaded = JsonStore::load(dir.path()).await.unwrap();
+    let mut saved = all(&reloaded, "users")
+        .await
+        .into_iter()
+        .map(|record| record["email"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    saved.sort();
+    inserted.sort();
+    assert_eq!(saved, inserted);
+}
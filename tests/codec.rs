@@ -0,0 +1,116 @@
+mod common;
+
+use common::{all, read_json, users, ScratchDir};
+use json_store::{
+    codec::Codec,
+    error::JsonStoreError,
+    store::{JsonStore, LoadOptions},
+};
+use serde_json::{json, Value};
+
+fn available() -> impl Iterator<Item = Codec> {
+    Codec::ALL.into_iter().filter(Codec::is_available)
+}
+
+async fn store_with(dir: &ScratchDir, codec: Codec) -> Result<JsonStore, JsonStoreError> {
+    let options = LoadOptions {
+        codec: Some(codec),
+        ..Default::default()
+    };
+    JsonStore::load_with_options(dir.path(), options).await
+}
+
+fn records() -> Vec<Value> {
+    vec![
+        json!({"id": 1, "email": "a@x", "n": -7, "f": 1.25, "ok": true, "nil": null}),
+        json!({"id": 2, "email": "b@x", "nested": {"list": [1, "two", {"three": 3}]}, "text": "ünï 😀"}),
+    ]
+}
+
+async fn fill(store: &JsonStore) {
+    store.create_tree("users", users()).await.unwrap();
+    for mut record in records() {
+        record.as_object_mut().unwrap().remove("id");
+        store.insert("users", &record).await.unwrap();
+    }
+    store.save().await.unwrap();
+}
+
+#[tokio::test]
+async fn every_codec_round_trips_and_is_remembered() {
+    for codec in available() {
+        let dir = ScratchDir::new("codec-round-trip");
+        fill(&store_with(&dir, codec).await.unwrap()).await;
+
+        let file = dir.path().join(format!("users.{}", codec.extension()));
+        assert!(file.exists(), "{:?}", codec);
+        assert_eq!(
+            read_json(&dir.path().join("meta.json"))["codec"],
+            json!(codec)
+        );
+        // the catalog stays JSON
+        assert!(read_json(&dir.path().join("infos.json"))["users"].is_object());
+
+        // no need to name the codec again
+        let reloaded = JsonStore::load(dir.path()).await.unwrap();
+        assert_eq!(all(&reloaded, "users").await, records(), "{:?}", codec);
+    }
+}
+
+#[tokio::test]
+async fn a_different_codec_is_refused_for_an_existing_store() {
+    let dir = ScratchDir::new("codec-mismatch");
+    fill(&JsonStore::load(dir.path()).await.unwrap()).await;
+
+    for codec in Codec::ALL.into_iter().filter(|c| *c != Codec::Json) {
+        match store_with(&dir, codec).await {
+            Err(JsonStoreError::CodecMismatch { store, requested }) => {
+                assert_eq!((store, requested), (Codec::Json, codec));
+            }
+            other => panic!("expected CodecMismatch, got {:?}", other.err()),
+        }
+    }
+}
+
+#[cfg(not(all(feature = "msgpack", feature = "cbor")))]
+#[tokio::test]
+async fn a_codec_not_compiled_in_is_refused() {
+    let codec = Codec::ALL.into_iter().find(|c| !c.is_available()).unwrap();
+    let dir = ScratchDir::new("codec-unavailable");
+    assert!(matches!(
+        store_with(&dir, codec).await,
+        Err(JsonStoreError::CodecUnavailable(c)) if c == codec
+    ));
+}
+
+#[tokio::test]
+async fn convert_codec_rewrites_a_store_between_codecs() {
+    let dir = ScratchDir::new("codec-convert");
+    fill(&JsonStore::load(dir.path()).await.unwrap()).await;
+
+    let mut from = Codec::Json;
+    for codec in available()
+        .filter(|c| *c != Codec::Json)
+        .chain([Codec::Json])
+    {
+        JsonStore::convert_codec(dir.path(), codec).await.unwrap();
+        if from != codec {
+            assert!(!dir
+                .path()
+                .join(format!("users.{}", from.extension()))
+                .exists());
+        }
+        assert!(dir
+            .path()
+            .join(format!("users.{}", codec.extension()))
+            .exists());
+        assert_eq!(
+            read_json(&dir.path().join("meta.json"))["codec"],
+            json!(codec)
+        );
+
+        let store = JsonStore::load(dir.path()).await.unwrap();
+        assert_eq!(all(&store, "users").await, records(), "{:?}", codec);
+        from = codec;
+    }
+}
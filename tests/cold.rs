@@ -0,0 +1,250 @@
+mod common;
+
+use common::{all, store_with_users, users, ScratchDir};
+use json_store::{
+    cold::{ArchiveDest, ArchiveOptions, PruneStrategy},
+    error::JsonStoreError,
+    import::{ImportMode, OnConflict},
+    store::JsonStore,
+};
+use serde_json::{json, Value};
+
+// users 1 to 5, of 2018 to 2022 in turn
+async fn store_with_years(dir: &ScratchDir) -> JsonStore {
+    let store = store_with_users(dir).await;
+    for year in 2018..=2022 {
+        store
+            .insert(
+                "users",
+                &json!({"email": format!("{}@x", year), "year": year}),
+            )
+            .await
+            .unwrap();
+    }
+    store
+}
+
+fn before_2020(record: &Value) -> bool {
+    record["year"].as_u64().is_some_and(|year| year < 2020)
+}
+
+fn even_year(record: &Value) -> bool {
+    record["year"].as_u64().is_some_and(|year| year % 2 == 0)
+}
+
+fn user(seq: u64) -> Value {
+    let year = 2017 + seq;
+    json!({"id": seq, "email": format!("{}@x", year), "year": year})
+}
+
+#[tokio::test]
+async fn archived_records_move_to_another_tree() {
+    let dir = ScratchDir::new("cold-tree");
+    let store = store_with_years(&dir).await;
+    store.create_tree("archive", users()).await.unwrap();
+
+    let archived = store
+        .archive_where(
+            "users",
+            before_2020,
+            ArchiveDest::Tree("archive".to_string()),
+        )
+        .await
+        .unwrap();
+    assert_eq!(archived, 2);
+    assert_eq!(all(&store, "users").await, [user(3), user(4), user(5)]);
+    // under the sequences they had, which the archive's counter moves past
+    assert_eq!(all(&store, "archive").await, [user(1), user(2)]);
+    let seq = store
+        .insert("archive", &json!({"email": "new@x"}))
+        .await
+        .unwrap();
+    assert_eq!(seq, 3);
+
+    // and kept that way on disk
+    store.close().await.unwrap();
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(all(&store, "users").await.len(), 3);
+    assert_eq!(all(&store, "archive").await.len(), 3);
+}
+
+#[tokio::test]
+async fn a_clash_in_the_destination_moves_nothing() {
+    let dir = ScratchDir::new("cold-tree-clash");
+    let store = store_with_years(&dir).await;
+    store.create_tree("archive", users()).await.unwrap();
+    // 2022@x already there, under a sequence of its own
+    for email in ["a@x", "b@x", "2022@x"] {
+        store
+            .insert("archive", &json!({ "email": email }))
+            .await
+            .unwrap();
+    }
+    let archive = all(&store, "archive").await;
+
+    // 2021 would fit, but goes only with 2022
+    match store
+        .archive_where(
+            "users",
+            |record| record["year"].as_u64() > Some(2020),
+            ArchiveDest::Tree("archive".to_string()),
+        )
+        .await
+    {
+        Err(JsonStoreError::ArchiveConflict { tree, sequence, .. }) => {
+            assert_eq!((tree.as_str(), sequence), ("archive", 5));
+        }
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(all(&store, "users").await.len(), 5);
+    assert_eq!(all(&store, "archive").await, archive);
+}
+
+#[tokio::test]
+async fn records_archived_to_a_file_import_back_as_they_were() {
+    let dir = ScratchDir::new("cold-file");
+    let store = store_with_years(&dir).await;
+    let file = dir.path().join("cold.ndjson");
+
+    let archived = store
+        .archive_where("users", before_2020, ArchiveDest::File(file.clone()))
+        .await
+        .unwrap();
+    assert_eq!(archived, 2);
+    assert_eq!(all(&store, "users").await, [user(3), user(4), user(5)]);
+    let lines = std::fs::read_to_string(&file).unwrap();
+    let lines = lines
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(lines, [user(1), user(2)]);
+
+    // a second archive appends
+    store
+        .archive_where(
+            "users",
+            |record| record["year"] == 2022,
+            ArchiveDest::File(file.clone()),
+        )
+        .await
+        .unwrap();
+    assert_eq!(std::fs::read_to_string(&file).unwrap().lines().count(), 3);
+
+    let report = store
+        .import_ndjson("users", &file, ImportMode::Preserve, OnConflict::Fail)
+        .await
+        .unwrap();
+    assert_eq!(report.imported, [1, 2, 5]);
+    assert_eq!(
+        all(&store, "users").await,
+        (1..=5).map(user).collect::<Vec<_>>()
+    );
+}
+
+#[tokio::test]
+async fn a_dry_run_counts_but_moves_nothing() {
+    let dir = ScratchDir::new("cold-dry-run");
+    let store = store_with_years(&dir).await;
+    store.create_tree("archive", users()).await.unwrap();
+    let file = dir.path().join("cold.ndjson");
+    let dry_run = ArchiveOptions { dry_run: true };
+
+    for dest in [
+        ArchiveDest::Tree("archive".to_string()),
+        ArchiveDest::File(file.clone()),
+    ] {
+        let archived = store
+            .archive_where_with("users", before_2020, dest, dry_run)
+            .await
+            .unwrap();
+        assert_eq!(archived, 2);
+    }
+    assert_eq!(all(&store, "users").await.len(), 5);
+    assert!(all(&store, "archive").await.is_empty());
+    assert!(!file.exists());
+    assert!(!store.is_dirty("archive").await.unwrap());
+}
+
+#[tokio::test]
+async fn pruning_sheds_the_oldest_down_to_capacity() {
+    let dir = ScratchDir::new("cold-prune-oldest");
+    let store = store_with_years(&dir).await;
+
+    // nothing over capacity, nothing to do
+    let pruned = store
+        .prune_to_capacity("users", PruneStrategy::Oldest)
+        .await
+        .unwrap();
+    assert!(pruned.is_empty());
+
+    store.set_tree_capacity("users", 3).await.unwrap();
+    let pruned = store
+        .prune_to_capacity("users", PruneStrategy::Oldest)
+        .await
+        .unwrap();
+    assert_eq!(pruned, [1, 2]);
+    assert_eq!(all(&store, "users").await, [user(3), user(4), user(5)]);
+    // at capacity now, so it takes a new record only once another goes
+    assert!(matches!(
+        store.insert("users", &json!({"email": "new@x"})).await,
+        Err(JsonStoreError::CapacityExceeded(_))
+    ));
+}
+
+#[tokio::test]
+async fn pruning_sheds_only_matching_records() {
+    let dir = ScratchDir::new("cold-prune-matching");
+    let store = store_with_years(&dir).await;
+
+    // the first two even years of the three
+    store.set_tree_capacity("users", 3).await.unwrap();
+    let pruned = store
+        .prune_to_capacity("users", PruneStrategy::Matching(Box::new(even_year)))
+        .await
+        .unwrap();
+    assert_eq!(pruned, [1, 3]);
+    assert_eq!(all(&store, "users").await, [user(2), user(4), user(5)]);
+
+    // too few match to get down to 1: those that do go, the rest stay
+    store.set_tree_capacity("users", 1).await.unwrap();
+    let pruned = store
+        .prune_to_capacity("users", PruneStrategy::Matching(Box::new(even_year)))
+        .await
+        .unwrap();
+    assert_eq!(pruned, [5]);
+    assert_eq!(all(&store, "users").await, [user(2), user(4)]);
+}
+
+#[tokio::test]
+async fn pruned_records_can_be_archived_instead() {
+    let dir = ScratchDir::new("cold-prune-archiving");
+    let store = store_with_years(&dir).await;
+    store.create_tree("archive", users()).await.unwrap();
+    let file = dir.path().join("cold.ndjson");
+    store.set_tree_capacity("users", 3).await.unwrap();
+
+    let pruned = store
+        .prune_to_capacity_archiving(
+            "users",
+            PruneStrategy::Oldest,
+            ArchiveDest::Tree("archive".to_string()),
+        )
+        .await
+        .unwrap();
+    assert_eq!(pruned, [1, 2]);
+    assert_eq!(all(&store, "archive").await, [user(1), user(2)]);
+
+    store.set_tree_capacity("users", 2).await.unwrap();
+    let pruned = store
+        .prune_to_capacity_archiving(
+            "users",
+            PruneStrategy::Matching(Box::new(even_year)),
+            ArchiveDest::File(file.clone()),
+        )
+        .await
+        .unwrap();
+    assert_eq!(pruned, [3]);
+    assert_eq!(all(&store, "users").await, [user(4), user(5)]);
+    let line = std::fs::read_to_string(&file).unwrap();
+    assert_eq!(serde_json::from_str::<Value>(&line).unwrap(), user(3));
+}
@@ -0,0 +1,127 @@
+// Shared by the integration tests: scratch directories from the benchmarks' helpers,
+// a tree definition most tests can use, and small shortcuts. Each test binary uses
+// only part of it.
+#![allow(dead_code)]
+
+#[path = "../../benches/common/mod.rs"]
+mod bench;
+
+pub use bench::ScratchDir;
+
+use json_store::{
+    batch::WriteBatching,
+    store::{Info, JsonStore, LoadOptions},
+};
+use serde_json::Value;
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+// users by sequence "id", with email unique. With JSON_STORE_TEST_RESIDENT_LIMIT set
+// it keeps only that many records in memory, so that the suite run again with it goes
+// through the spill file (see spill.rs).
+pub fn users() -> Info {
+    let builder = Info::builder()
+        .sequence_field("id")
+        .unique("email", ["email"]);
+    match resident_limit() {
+        Some(limit) => builder.resident_limit(limit),
+        None => builder,
+    }
+    .build()
+    .expect("a valid info")
+}
+
+pub fn resident_limit() -> Option<u32> {
+    let limit = std::env::var("JSON_STORE_TEST_RESIDENT_LIMIT").ok()?;
+    Some(limit.parse().expect("a number of records"))
+}
+
+// With JSON_STORE_TEST_WRITE_BATCHING set to contended or always, stores made here
+// queue their writes (see batch.rs), so that the suite run again with it checks the
+// batched path does what the direct one does.
+pub fn load_options() -> LoadOptions {
+    LoadOptions {
+        write_batching: write_batching(),
+        ..Default::default()
+    }
+}
+
+pub fn write_batching() -> WriteBatching {
+    match std::env::var("JSON_STORE_TEST_WRITE_BATCHING").as_deref() {
+        Err(_) | Ok("off") => WriteBatching::Off,
+        Ok("contended") => WriteBatching::Contended,
+        Ok("always") => WriteBatching::Always,
+        Ok(other) => panic!("no write batching mode '{}'", other),
+    }
+}
+
+// a store loaded from a fresh directory, with users created
+pub async fn store_with_users(dir: &ScratchDir) -> JsonStore {
+    let store = JsonStore::load_with_options(dir.path(), load_options())
+        .await
+        .expect("a store");
+    store.create_tree("users", users()).await.expect("users");
+    store
+}
+
+// every record of tname, in sequence order
+pub async fn all(store: &JsonStore, tname: &str) -> Vec<Value> {
+    store
+        .select_where(tname, |_| true)
+        .await
+        .expect("the records")
+}
+
+pub fn read_json(path: &Path) -> Value {
+    let text = std::fs::read_to_string(path).expect("a readable file");
+    serde_json::from_str(&text).expect("JSON")
+}
+
+// write contents to path behind the store's back, as an editor or another program would
+pub fn edit(path: &Path, contents: &str) {
+    std::fs::write(path, contents).expect("a writable file");
+}
+
+// edit, dropping path's checksum sidecar as well, as a hand edit meant to be read has
+// to: an edited file with the old sidecar fails to load (see checksum.rs)
+pub fn hand_edit(path: &Path, contents: &str) {
+    edit(path, contents);
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".sha256");
+    match std::fs::remove_file(sidecar) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => panic!("a sidecar: {}", e),
+        _ => {}
+    }
+}
+
+// Change path's contents without changing its modification time, as a failing disk
+// would. The time is set back a minute, before the store wrote anything next to it.
+pub fn damage(path: &Path, contents: &str) {
+    std::fs::write(path, contents).expect("a writable file");
+    touch(path, SystemTime::now() - Duration::from_secs(60));
+}
+
+pub fn touch(path: &Path, modified: SystemTime) {
+    File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(modified))
+        .expect("a file to touch");
+}
+
+pub fn fixture_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+// copy the files of tests/fixtures/name into dir
+pub fn fixture(name: &str, dir: &ScratchDir) {
+    for entry in std::fs::read_dir(fixture_path(name)).expect("a fixture") {
+        let entry = entry.expect("a fixture file");
+        std::fs::copy(entry.path(), dir.path().join(entry.file_name())).expect("a copy");
+    }
+}
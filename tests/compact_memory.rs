@@ -0,0 +1,121 @@
+mod common;
+
+use common::{all, ScratchDir};
+use json_store::store::{Info, JsonStore, LoadOptions};
+use serde_json::json;
+
+fn events() -> Info {
+    Info::builder()
+        .sequence_field("id")
+        .unique("key", ["key"])
+        .index("kind")
+        .build()
+        .unwrap()
+}
+
+// 5000 records of which all but every tenth (keys 9, 19, ...) are deleted again
+async fn bloat(store: &JsonStore, tname: &str) {
+    store.create_tree(tname, events()).await.unwrap();
+    for n in 0..5000 {
+        store
+            .insert(
+                tname,
+                &json!({"key": n, "kind": n % 7, "body": "x".repeat(50)}),
+            )
+            .await
+            .unwrap();
+    }
+    for seq in 1..=5000 {
+        if seq % 10 != 0 {
+            store.delete(tname, seq).await.unwrap();
+        }
+    }
+}
+
+#[tokio::test]
+async fn compaction_gives_back_the_room_of_deleted_records() {
+    let dir = ScratchDir::new("compact-memory");
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    bloat(&store, "events").await;
+    let records = all(&store, "events").await;
+
+    let before = store.memory_estimate("events").await.unwrap();
+    let compaction = store.compact_memory("events").await.unwrap();
+    let after = store.memory_estimate("events").await.unwrap();
+
+    assert_eq!(compaction.before_bytes, before);
+    assert_eq!(compaction.after_bytes, after);
+    assert!(after * 4 < before * 3, "{} before, {} after", before, after);
+    assert_eq!(compaction.reclaimed_bytes(), before - after);
+
+    // nothing else changed: records, constraints and indexes all still work
+    assert_eq!(all(&store, "events").await, records);
+    assert!(store
+        .insert("events", &json!({"key": 9, "kind": 0}))
+        .await
+        .is_err());
+    assert_eq!(
+        store
+            .find_by_field::<serde_json::Value>("events", "kind", &json!(3))
+            .await
+            .unwrap()
+            .len(),
+        records.iter().filter(|r| r["kind"] == 3).count()
+    );
+
+    // a second pass has nothing left to give
+    let again = store.compact_memory("events").await.unwrap();
+    assert_eq!(again.reclaimed_bytes(), 0);
+}
+
+#[tokio::test]
+async fn compact_all_memory_covers_the_loaded_trees() {
+    let dir = ScratchDir::new("compact-memory-all");
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    bloat(&store, "a").await;
+    bloat(&store, "b").await;
+    store.save().await.unwrap();
+    store.unload_tree("b").await.unwrap();
+
+    let compacted = store.compact_all_memory().await.unwrap();
+    assert_eq!(compacted.keys().collect::<Vec<_>>(), ["a"]);
+    assert!(compacted["a"].reclaimed_bytes() > 0);
+    assert_eq!(store.loaded_trees().await, ["a"]);
+
+    // a tree not in memory is left alone
+    let unloaded = store.compact_memory("b").await.unwrap();
+    assert_eq!((unloaded.before_bytes, unloaded.after_bytes), (0, 0));
+    assert_eq!(store.loaded_trees().await, ["a"]);
+}
+
+#[tokio::test]
+async fn shrink_below_compacts_by_itself() {
+    let dir = ScratchDir::new("compact-memory-auto");
+    let plain = JsonStore::load(dir.path().join("plain").as_path())
+        .await
+        .unwrap();
+    let shrinking = JsonStore::load_with_options(
+        dir.path().join("shrinking").as_path(),
+        LoadOptions {
+            shrink_below: Some(0.25),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    bloat(&plain, "events").await;
+    bloat(&shrinking, "events").await;
+
+    let kept = plain.memory_estimate("events").await.unwrap();
+    let shrunk = shrinking.memory_estimate("events").await.unwrap();
+    assert!(shrunk * 4 < kept * 3, "{} shrunk, {} kept", shrunk, kept);
+    // which is about what compacting by hand gets
+    let compacted = plain.compact_memory("events").await.unwrap().after_bytes;
+    assert!(
+        shrunk <= compacted * 2,
+        "{} shrunk, {} compacted",
+        shrunk,
+        compacted
+    );
+    assert_eq!(all(&plain, "events").await, all(&shrinking, "events").await);
+}
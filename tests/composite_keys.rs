@@ -0,0 +1,321 @@
+mod common;
+
+use common::{all, read_json, ScratchDir};
+use json_store::{
+    error::JsonStoreError,
+    export::ExportFormat,
+    import::{ImportMode, ImportReport, OnConflict},
+    store::{Info, JsonStore},
+};
+use serde_json::{json, Value};
+use std::path::Path;
+
+// prices by product and region, with a unique sku and an index over currency
+fn prices() -> Info {
+    Info::builder()
+        .sequence_field("id")
+        .composite_key(["product", "region"])
+        .unique("sku", ["sku"])
+        .index("currency")
+        .build()
+        .unwrap()
+}
+
+async fn store_with_prices(dir: &ScratchDir) -> JsonStore {
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    store.create_tree("prices", prices()).await.unwrap();
+    store
+}
+
+// Pairs of keys that read the same once their values are joined with a comma, a
+// colon or nothing at all, or once a number is written as text. Each pair is two
+// keys all the same.
+fn lookalikes() -> Vec<(Value, Value)> {
+    vec![
+        (
+            json!({"product": "a,b", "region": "c"}),
+            json!({"product": "a", "region": "b,c"}),
+        ),
+        (
+            json!({"product": "a:b", "region": "c"}),
+            json!({"product": "a", "region": "b:c"}),
+        ),
+        (
+            json!({"product": "ab", "region": "c"}),
+            json!({"product": "a", "region": "bc"}),
+        ),
+        (
+            json!({"product": "a\",\"b", "region": "c"}),
+            json!({"product": "a", "region": "b\",\"c"}),
+        ),
+        (
+            json!({"product": 17, "region": "eu"}),
+            json!({"product": "17", "region": "eu"}),
+        ),
+        (
+            json!({"product": "[1", "region": "2]"}),
+            json!({"product": [1, 2], "region": ""}),
+        ),
+    ]
+}
+
+fn priced(key: &Value, sku: usize) -> Value {
+    let mut record = key.clone();
+    record["sku"] = json!(sku);
+    record["currency"] = json!("EUR");
+    record
+}
+
+#[tokio::test]
+async fn keys_that_join_alike_are_distinct() {
+    let dir = ScratchDir::new("composite-lookalikes");
+    let store = store_with_prices(&dir).await;
+    let lookalikes = lookalikes();
+    for (n, (a, b)) in lookalikes.iter().enumerate() {
+        store.insert("prices", &priced(a, 2 * n)).await.unwrap();
+        store.insert("prices", &priced(b, 2 * n + 1)).await.unwrap();
+    }
+
+    for (n, (a, b)) in lookalikes.iter().enumerate() {
+        let found: Value = store.select_by_key_fields("prices", a).await.unwrap();
+        assert_eq!(found["sku"], 2 * n, "{}", a);
+        let found: Value = store.select_by_key_fields("prices", b).await.unwrap();
+        assert_eq!(found["sku"], 2 * n + 1, "{}", b);
+    }
+
+    // and stay so in the file, each under its own key
+    store.save().await.unwrap();
+    let file = read_json(&dir.path().join("prices.json"));
+    assert_eq!(
+        file.as_object().unwrap().len(),
+        2 * lookalikes.len(),
+        "{}",
+        file
+    );
+    assert_eq!(file[r#"["a,b","c"]"#]["sku"], 0);
+    assert_eq!(file[r#"["a","b,c"]"#]["sku"], 1);
+    store.close().await.unwrap();
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    for (a, b) in &lookalikes {
+        let a = store
+            .select_by_key_fields::<Value>("prices", a)
+            .await
+            .unwrap();
+        let b = store
+            .select_by_key_fields::<Value>("prices", b)
+            .await
+            .unwrap();
+        assert_ne!(a["id"], b["id"]);
+    }
+}
+
+#[tokio::test]
+async fn the_key_text_is_the_json_array_of_the_values() {
+    let dir = ScratchDir::new("composite-key-text");
+    let store = store_with_prices(&dir).await;
+    let (a, b) = lookalikes().swap_remove(0);
+    store.insert("prices", &priced(&a, 1)).await.unwrap();
+    store.insert("prices", &priced(&b, 2)).await.unwrap();
+
+    let found: Value = store
+        .select_by_key("prices", r#"["a,b","c"]"#)
+        .await
+        .unwrap();
+    assert_eq!(found["sku"], 1);
+    // spacing doesn't matter, the values do
+    let found: Value = store
+        .select_by_key("prices", r#"[ "a" , "b,c" ]"#)
+        .await
+        .unwrap();
+    assert_eq!(found["sku"], 2);
+    assert!(matches!(
+        store.select_by_key::<Value>("prices", "a,b,c").await,
+        Err(JsonStoreError::InvalidKey { .. })
+    ));
+    assert!(matches!(
+        store.select_by_key::<Value>("prices", r#"["a,b"]"#).await,
+        Err(JsonStoreError::InvalidKey { .. })
+    ));
+    assert!(matches!(
+        store.select_by_key::<Value>("prices", r#"["a","c"]"#).await,
+        Err(JsonStoreError::KeyNotExist { .. })
+    ));
+}
+
+#[tokio::test]
+async fn every_key_field_is_required() {
+    let dir = ScratchDir::new("composite-required");
+    let store = store_with_prices(&dir).await;
+    for record in [
+        json!({"product": 17, "sku": 1}),
+        json!({"region": "eu", "sku": 1}),
+        json!({"product": 17, "region": null, "sku": 1}),
+    ] {
+        let error = store.insert("prices", &record).await.unwrap_err();
+        assert!(
+            matches!(error, JsonStoreError::InvalidKey { .. }),
+            "{}: {}",
+            record,
+            error
+        );
+    }
+    // by the object form too
+    assert!(matches!(
+        store
+            .select_by_key_fields::<Value>("prices", &json!({"product": 17}))
+            .await,
+        Err(JsonStoreError::InvalidKey { .. })
+    ));
+    assert!(all(&store, "prices").await.is_empty());
+}
+
+#[tokio::test]
+async fn the_object_form_updates_and_deletes() {
+    let dir = ScratchDir::new("composite-object-form");
+    let store = store_with_prices(&dir).await;
+    let key = json!({"product": 17, "region": "eu"});
+    store.insert("prices", &priced(&key, 1)).await.unwrap();
+
+    // other fields of the object are ignored
+    let mut with_extras = key.clone();
+    with_extras["amount"] = json!(99);
+    store
+        .update_by_key_fields("prices", &with_extras, &json!({"sku": 1, "amount": 12}))
+        .await
+        .unwrap();
+    let found: Value = store.select_by_key_fields("prices", &key).await.unwrap();
+    assert_eq!(
+        found,
+        json!({"id": 1, "product": 17, "region": "eu", "sku": 1, "amount": 12})
+    );
+
+    // an update can't move the record to another key
+    let error = store
+        .update_by_key_fields("prices", &key, &json!({"product": 18, "region": "eu"}))
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(error, JsonStoreError::InvalidKey { .. }),
+        "{}",
+        error
+    );
+    let error = store
+        .update("prices", &json!({"id": 1, "product": 17, "region": "us"}))
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(error, JsonStoreError::InvalidKey { .. }),
+        "{}",
+        error
+    );
+
+    store.delete_by_key_fields("prices", &key).await.unwrap();
+    assert!(all(&store, "prices").await.is_empty());
+    assert!(matches!(
+        store.delete_by_key_fields("prices", &key).await,
+        Err(JsonStoreError::KeyNotExist { .. })
+    ));
+
+    // the key is free again
+    store.insert("prices", &priced(&key, 1)).await.unwrap();
+    assert_eq!(
+        store
+            .sequence_of_key("prices", r#"[17,"eu"]"#)
+            .await
+            .unwrap(),
+        2
+    );
+}
+
+#[tokio::test]
+async fn the_key_sits_with_constraints_and_indexes() {
+    let dir = ScratchDir::new("composite-constraints");
+    let store = store_with_prices(&dir).await;
+    let eu = json!({"product": 17, "region": "eu"});
+    let us = json!({"product": 17, "region": "us"});
+    store.insert("prices", &priced(&eu, 1)).await.unwrap();
+
+    // a taken key clashes, as does a taken sku under a free key
+    let error = store.insert("prices", &priced(&eu, 2)).await.unwrap_err();
+    assert!(
+        matches!(error, JsonStoreError::DuplicateUniqueFields(_)),
+        "{}",
+        error
+    );
+    let error = store.insert("prices", &priced(&us, 1)).await.unwrap_err();
+    assert!(
+        matches!(error, JsonStoreError::DuplicateUniqueFields(_)),
+        "{}",
+        error
+    );
+
+    store.insert("prices", &priced(&us, 2)).await.unwrap();
+    let euros = store
+        .find_by_field::<Value>("prices", "currency", &json!("EUR"))
+        .await
+        .unwrap();
+    assert_eq!(euros.len(), 2);
+    let by_region = store
+        .find_by_field::<Value>("prices", "region", &json!("us"))
+        .await
+        .unwrap();
+    assert_eq!(
+        by_region,
+        vec![store.select::<Value>("prices", 2).await.unwrap()]
+    );
+}
+
+// file, exported as format, imported into copy, skipping records that clash
+async fn import(
+    store: &JsonStore,
+    format: &ExportFormat,
+    file: &Path,
+    mode: ImportMode,
+) -> ImportReport {
+    match format {
+        ExportFormat::NdJson => {
+            store
+                .import_ndjson("copy", file, mode, OnConflict::Skip)
+                .await
+        }
+        _ => {
+            store
+                .import_tree_with("copy", file, mode, OnConflict::Skip)
+                .await
+        }
+    }
+    .unwrap()
+}
+
+#[tokio::test]
+async fn exports_carry_the_key_fields_back_in() {
+    for format in [ExportFormat::JsonArray, ExportFormat::NdJson] {
+        let dir = ScratchDir::new("composite-export");
+        let store = store_with_prices(&dir).await;
+        for (n, (a, b)) in lookalikes().iter().enumerate() {
+            store.insert("prices", &priced(a, 2 * n)).await.unwrap();
+            store.insert("prices", &priced(b, 2 * n + 1)).await.unwrap();
+        }
+        let file = dir.path().join("prices.export");
+        store
+            .export_tree("prices", &file, format.clone())
+            .await
+            .unwrap();
+
+        store.create_tree("copy", prices()).await.unwrap();
+        let report = import(&store, &format, &file, ImportMode::Preserve).await;
+        assert!(report.skipped.is_empty(), "{:?}", report.skipped);
+        assert_eq!(all(&store, "copy").await, all(&store, "prices").await);
+        for (a, _) in lookalikes() {
+            let original = store.select_by_key_fields::<Value>("prices", &a).await;
+            let copied = store.select_by_key_fields::<Value>("copy", &a).await;
+            assert_eq!(copied.unwrap(), original.unwrap());
+        }
+
+        // importing it again clashes record by record on the keys
+        let report = import(&store, &format, &file, ImportMode::Append).await;
+        assert!(report.imported.is_empty());
+        assert_eq!(report.skipped.len(), 2 * lookalikes().len());
+    }
+}
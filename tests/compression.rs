@@ -0,0 +1,128 @@
+mod common;
+
+use common::{all, read_json, users, ScratchDir};
+use flate2::read::GzDecoder;
+use json_store::store::{Compression, Info, JsonStore};
+use serde_json::{json, Value};
+use std::{fs, io::Read, path::Path};
+
+fn gzipped() -> Info {
+    Info::builder()
+        .sequence_field("id")
+        .unique("email", ["email"])
+        .compression(Compression::Gzip)
+        .build()
+        .unwrap()
+}
+
+async fn fill(store: &JsonStore, tname: &str) {
+    for i in 0..200 {
+        let record =
+            json!({"email": format!("{}@x", i), "bio": "the same words again ".repeat(10)});
+        store.insert(tname, &record).await.unwrap();
+    }
+    store.save().await.unwrap();
+}
+
+fn gunzip(path: &Path) -> Value {
+    let mut text = String::new();
+    GzDecoder::new(fs::File::open(path).unwrap())
+        .read_to_string(&mut text)
+        .unwrap();
+    serde_json::from_str(&text).unwrap()
+}
+
+#[tokio::test]
+async fn a_compressed_tree_loads_the_same_as_a_plain_one() {
+    let dir = ScratchDir::new("gzip-round-trip");
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    store.create_tree("plain", users()).await.unwrap();
+    store.create_tree("packed", gzipped()).await.unwrap();
+    fill(&store, "plain").await;
+    fill(&store, "packed").await;
+
+    let packed = dir.path().join("packed.json.gz");
+    assert!(!dir.path().join("packed.json").exists());
+    assert_eq!(gunzip(&packed), read_json(&dir.path().join("plain.json")));
+    let plain_size = fs::metadata(dir.path().join("plain.json")).unwrap().len();
+    assert!(fs::metadata(&packed).unwrap().len() * 5 < plain_size);
+    assert!(dir.path().join("packed.json.gz.sha256").exists());
+
+    let reloaded = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(
+        all(&reloaded, "packed").await,
+        all(&reloaded, "plain").await
+    );
+    assert_eq!(all(&reloaded, "packed").await.len(), 200);
+}
+
+#[tokio::test]
+async fn turning_compression_on_takes_effect_at_the_next_save() {
+    let dir = ScratchDir::new("gzip-enable");
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    store.create_tree("users", users()).await.unwrap();
+    fill(&store, "users").await;
+    store.close().await.unwrap();
+
+    let infos = dir.path().join("infos.json");
+    let mut catalog = read_json(&infos);
+    catalog["users"]["compression"] = json!("gzip");
+    fs::write(&infos, catalog.to_string()).unwrap();
+
+    // only the plain file is there, and it is read
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(all(&store, "users").await.len(), 200);
+    assert!(dir.path().join("users.json").exists());
+
+    store
+        .insert("users", &json!({"email": "new@x"}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+    assert!(!dir.path().join("users.json").exists());
+    assert!(!dir.path().join("users.json.sha256").exists());
+    assert_eq!(
+        gunzip(&dir.path().join("users.json.gz"))
+            .as_object()
+            .unwrap()
+            .len(),
+        201
+    );
+
+    // no temp files left from the compressed write
+    let leftovers = fs::read_dir(dir.path())
+        .unwrap()
+        .filter(|entry| {
+            entry
+                .as_ref()
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .ends_with(".tmp")
+        })
+        .count();
+    assert_eq!(leftovers, 0);
+
+    let reloaded = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(all(&reloaded, "users").await.len(), 201);
+}
+
+// as for plain files, a failed write (here to /dev/full) leaves the old file whole
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn a_failed_compressed_write_leaves_the_original() {
+    let dir = ScratchDir::new("gzip-atomic");
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    store.create_tree("packed", gzipped()).await.unwrap();
+    fill(&store, "packed").await;
+    let packed = dir.path().join("packed.json.gz");
+    let saved = gunzip(&packed);
+
+    store
+        .insert("packed", &json!({"email": "new@x"}))
+        .await
+        .unwrap();
+    std::os::unix::fs::symlink("/dev/full", dir.path().join("packed.json.gz.tmp")).unwrap();
+    assert!(store.save().await.is_err());
+    assert_eq!(gunzip(&packed), saved);
+}
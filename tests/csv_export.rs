@@ -0,0 +1,111 @@
+#![cfg(feature = "csv")]
+
+mod common;
+
+use common::{store_with_users, ScratchDir};
+use json_store::{export::ExportFormat, store::JsonStore};
+use serde_json::json;
+
+async fn store_with_people(dir: &ScratchDir) -> JsonStore {
+    let store = store_with_users(dir).await;
+    for record in [
+        json!({"email": "ada@x", "name": "Ada", "tags": ["a", "b"], "nil": null}),
+        json!({"email": "bob@x", "name": "Bob, Jr.", "address": {"city": "Oslo", "geo": {"lat": 1}}}),
+        json!({"email": "cy@x", "note": "line\nbreak \"q\""}),
+    ] {
+        store.insert("users", &record).await.unwrap();
+    }
+    store
+}
+
+async fn export(store: &JsonStore, dir: &ScratchDir, format: ExportFormat) -> String {
+    let path = dir.path().join("out.csv");
+    assert_eq!(store.export_tree("users", &path, format).await.unwrap(), 3);
+    std::fs::read_to_string(path).unwrap()
+}
+
+fn columns(names: &[&str]) -> Option<Vec<String>> {
+    Some(names.iter().map(|name| name.to_string()).collect())
+}
+
+#[tokio::test]
+async fn flattened_nested_fields_become_dotted_columns() {
+    let dir = ScratchDir::new("csv-flatten");
+    let store = store_with_people(&dir).await;
+    let format = ExportFormat::Csv {
+        delimiter: b',',
+        flatten: true,
+        columns: columns(&[
+            "id",
+            "name",
+            "address.city",
+            "address.geo.lat",
+            "tags",
+            "nil",
+            "note",
+        ]),
+    };
+
+    assert_eq!(
+        export(&store, &dir, format).await,
+        "id,name,address.city,address.geo.lat,tags,nil,note\n\
+         1,Ada,,,\"[\"\"a\"\",\"\"b\"\"]\",,\n\
+         2,\"Bob, Jr.\",Oslo,1,,,\n\
+         3,,,,,,\"line\nbreak \"\"q\"\"\"\n"
+    );
+}
+
+#[tokio::test]
+async fn unflattened_objects_are_written_as_json() {
+    let dir = ScratchDir::new("csv-nested");
+    let store = store_with_people(&dir).await;
+    let format = ExportFormat::Csv {
+        delimiter: b';',
+        flatten: false,
+        columns: columns(&["id", "address", "missing"]),
+    };
+
+    assert_eq!(
+        export(&store, &dir, format).await,
+        "id;address;missing\n\
+         1;;\n\
+         2;\"{\"\"city\"\":\"\"Oslo\"\",\"\"geo\"\":{\"\"lat\"\":1}}\";\n\
+         3;;\n"
+    );
+}
+
+#[tokio::test]
+async fn without_columns_the_header_is_every_field() {
+    let dir = ScratchDir::new("csv-union");
+    let store = store_with_people(&dir).await;
+
+    for (flatten, expected) in [
+        (
+            true,
+            vec![
+                "address.city",
+                "address.geo.lat",
+                "email",
+                "id",
+                "name",
+                "nil",
+                "note",
+                "tags",
+            ],
+        ),
+        (
+            false,
+            vec!["address", "email", "id", "name", "nil", "note", "tags"],
+        ),
+    ] {
+        let format = ExportFormat::Csv {
+            delimiter: b',',
+            flatten,
+            columns: None,
+        };
+        let text = export(&store, &dir, format).await;
+        let mut header = text.lines().next().unwrap().split(',').collect::<Vec<_>>();
+        header.sort();
+        assert_eq!(header, expected);
+    }
+}
@@ -0,0 +1,176 @@
+#![cfg(feature = "csv")]
+
+mod common;
+
+use common::{all, store_with_users, users, ScratchDir};
+use json_store::{
+    export::ExportFormat,
+    import::{CsvImportOptions, CsvType, EmptyCells, ImportMode, OnConflict},
+    store::Info,
+};
+use serde_json::json;
+use std::{collections::HashMap, path::PathBuf};
+
+fn write(dir: &ScratchDir, contents: &str) -> PathBuf {
+    let path = dir.path().join("in.csv");
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+#[tokio::test]
+async fn cells_are_typed_by_inference_or_by_column() {
+    let dir = ScratchDir::new("csv-types");
+    let store = store_with_users(&dir).await;
+    let file = write(
+        &dir,
+        "email,age,score,admin,zip,note\n\
+         a@x,36,1.5,true,0042,\"hello, world\"\n\
+         b@x,-3,2,FALSE,10001,\n",
+    );
+    let options = CsvImportOptions {
+        types: HashMap::from([("zip".to_string(), CsvType::String)]),
+        ..Default::default()
+    };
+
+    let report = store.import_csv("users", &file, options).await.unwrap();
+    assert_eq!(report.imported, [1, 2]);
+    assert_eq!(
+        all(&store, "users").await,
+        [
+            json!({"id": 1, "email": "a@x", "age": 36, "score": 1.5, "admin": true, "zip": "0042", "note": "hello, world"}),
+            json!({"id": 2, "email": "b@x", "age": -3, "score": 2, "admin": "FALSE", "zip": "10001", "note": null}),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn empty_cells_can_be_left_out() {
+    let dir = ScratchDir::new("csv-omit");
+    let store = store_with_users(&dir).await;
+    let file = write(&dir, "email;note\na@x;\n");
+    let options = CsvImportOptions {
+        delimiter: b';',
+        empty_cells: EmptyCells::Omit,
+        ..Default::default()
+    };
+
+    store.import_csv("users", &file, options).await.unwrap();
+    assert_eq!(
+        all(&store, "users").await,
+        [json!({"id": 1, "email": "a@x"})]
+    );
+}
+
+#[tokio::test]
+async fn bad_rows_are_reported_by_line_and_the_rest_imported() {
+    let dir = ScratchDir::new("csv-skip");
+    let store = store_with_users(&dir).await;
+    let file = write(
+        &dir,
+        "email,name,age\n\
+         a@x,Ada,1\n\
+         ,Nobody,2\n\
+         a@x,Again,3\n\
+         b@x,Bob,old\n\
+         c@x,Cy,4\n",
+    );
+    let options = CsvImportOptions {
+        required: vec!["email".to_string()],
+        types: HashMap::from([("age".to_string(), CsvType::Integer)]),
+        ..Default::default()
+    };
+
+    let report = store.import_csv("users", &file, options).await.unwrap();
+    assert_eq!(report.imported.len(), 2);
+    let lines = report
+        .skipped
+        .iter()
+        .map(|issue| issue.index)
+        .collect::<Vec<_>>();
+    assert_eq!(lines, [3, 4, 5]);
+    assert!(
+        report.skipped[0].reason.contains("email"),
+        "{:?}",
+        report.skipped
+    );
+    assert!(
+        report.skipped[2].reason.contains("age"),
+        "{:?}",
+        report.skipped
+    );
+    let emails = all(&store, "users")
+        .await
+        .into_iter()
+        .map(|record| record["email"].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(emails, ["a@x", "c@x"]);
+}
+
+#[tokio::test]
+async fn strict_imports_nothing_when_a_row_is_bad() {
+    let dir = ScratchDir::new("csv-strict");
+    let store = store_with_users(&dir).await;
+    let file = write(&dir, "email\na@x\na@x\n");
+    let options = CsvImportOptions {
+        on_conflict: OnConflict::Fail,
+        ..Default::default()
+    };
+
+    assert!(store.import_csv("users", &file, options).await.is_err());
+    assert!(all(&store, "users").await.is_empty());
+}
+
+#[tokio::test]
+async fn without_headers_columns_are_named_by_position() {
+    let dir = ScratchDir::new("csv-no-headers");
+    let store = store_with_users(&dir).await;
+    let info = Info::builder().sequence_field("id").build().unwrap();
+    store.create_tree("plain", info).await.unwrap();
+    let file = write(&dir, "x,1\ny,2\n");
+    let options = CsvImportOptions {
+        has_headers: false,
+        ..Default::default()
+    };
+
+    store.import_csv("plain", &file, options).await.unwrap();
+    assert_eq!(
+        all(&store, "plain").await,
+        [
+            json!({"id": 1, "0": "x", "1": 1}),
+            json!({"id": 2, "0": "y", "1": 2})
+        ]
+    );
+}
+
+#[tokio::test]
+async fn an_exported_tree_imports_back_the_same() {
+    let dir = ScratchDir::new("csv-round-trip");
+    let store = store_with_users(&dir).await;
+    for record in [
+        json!({"email": "a@x", "name": "Ada, \"the first\"", "tags": ["x", "y"], "age": 36}),
+        json!({"email": "b@x", "name": "Bob\nBobson", "address": {"city": "Oslo"}, "ok": false}),
+    ] {
+        store.insert("users", &record).await.unwrap();
+    }
+    let path = dir.path().join("users.csv");
+    let format = ExportFormat::Csv {
+        delimiter: b',',
+        flatten: false,
+        columns: None,
+    };
+    store.export_tree("users", &path, format).await.unwrap();
+
+    store.create_tree("copy", users()).await.unwrap();
+    let options = CsvImportOptions {
+        types: HashMap::from([
+            ("tags".to_string(), CsvType::Json),
+            ("address".to_string(), CsvType::Json),
+        ]),
+        empty_cells: EmptyCells::Omit,
+        mode: ImportMode::Preserve,
+        on_conflict: OnConflict::Fail,
+        ..Default::default()
+    };
+    store.import_csv("copy", &path, options).await.unwrap();
+    assert_eq!(all(&store, "copy").await, all(&store, "users").await);
+}
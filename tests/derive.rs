@@ -0,0 +1,99 @@
+#![cfg(feature = "derive")]
+
+mod common;
+
+use common::{read_json, ScratchDir};
+use json_store::{
+    entity::StoreEntity,
+    error::JsonStoreError,
+    store::{Info, JsonStore},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, StoreEntity)]
+#[store(tree = "members", capacity = 50)]
+#[serde(rename_all = "camelCase")]
+struct Member {
+    #[store(sequence)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    member_id: Option<u64>,
+    #[store(unique)]
+    #[serde(rename = "mail")]
+    email: String,
+    #[store(unique(group = "name_org"))]
+    display_name: String,
+    #[store(unique(group = "name_org"))]
+    org_id: u64,
+}
+
+fn member(email: &str, name: &str) -> Member {
+    Member {
+        member_id: None,
+        email: email.to_string(),
+        display_name: name.to_string(),
+        org_id: 1,
+    }
+}
+
+#[test]
+fn the_info_is_made_from_the_attributes() {
+    let expected = Info::builder()
+        .sequence_field("memberId")
+        .unique("mail", ["mail"])
+        .unique("name_org", ["displayName", "orgId"])
+        .capacity(50)
+        .build()
+        .unwrap();
+    assert_eq!(Member::tree_name(), "members");
+    assert_eq!(Member::info(), expected);
+}
+
+#[tokio::test]
+async fn entities_go_through_the_tree_of_their_type() {
+    let dir = ScratchDir::new("derive-entities");
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    store.create_tree_for::<Member>().await.unwrap();
+    // twice is the same tree
+    store.create_tree_for::<Member>().await.unwrap();
+
+    let seq = store.insert_entity(&member("a@x", "A")).await.unwrap();
+    let mut selected = store.select_entity::<Member>(seq).await.unwrap();
+    assert_eq!(selected.member_id, Some(seq));
+    assert_eq!(selected.email, "a@x");
+
+    // the constraints come from the attributes
+    assert!(matches!(
+        store.insert_entity(&member("a@x", "B")).await,
+        Err(JsonStoreError::DuplicateUniqueFields(_))
+    ));
+    assert!(matches!(
+        store.insert_entity(&member("b@x", "A")).await,
+        Err(JsonStoreError::DuplicateUniqueFields(_))
+    ));
+
+    selected.display_name = "C".to_string();
+    store.update_entity(&selected).await.unwrap();
+    assert_eq!(store.select_entity::<Member>(seq).await.unwrap(), selected);
+    store.close().await.unwrap();
+    assert_eq!(
+        read_json(&dir.path().join("members.json"))[seq.to_string()],
+        json!({"memberId": seq, "mail": "a@x", "displayName": "C", "orgId": 1})
+    );
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    // one never inserted has no sequence to delete by
+    assert!(matches!(
+        store.delete_entity(&member("a@x", "A")).await,
+        Err(JsonStoreError::SequenceNotExist(_))
+    ));
+    store.delete_entity(&selected).await.unwrap();
+    assert!(store.select_entity::<Member>(seq).await.is_err());
+}
+
+#[test]
+fn invalid_attributes_fail_to_compile() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/derive/pass/*.rs");
+    cases.compile_fail("tests/derive/fail/*.rs");
+}
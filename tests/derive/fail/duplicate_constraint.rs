@@ -0,0 +1,15 @@
+use json_store::entity::StoreEntity;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, StoreEntity)]
+#[store(tree = "users")]
+struct User {
+    #[store(sequence)]
+    id: Option<u64>,
+    #[store(unique)]
+    email: String,
+    #[store(unique(group = "email"))]
+    backup_email: String,
+}
+
+fn main() {}
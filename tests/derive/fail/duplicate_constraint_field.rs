@@ -0,0 +1,17 @@
+use json_store::entity::StoreEntity;
+use serde::Serialize;
+
+// serde would warn of the clash too, on deserializing
+#[derive(Serialize, StoreEntity)]
+#[store(tree = "users")]
+struct User {
+    #[store(sequence)]
+    id: Option<u64>,
+    #[store(unique(group = "name"))]
+    first: String,
+    #[store(unique(group = "name"))]
+    #[serde(rename = "first")]
+    given: String,
+}
+
+fn main() {}
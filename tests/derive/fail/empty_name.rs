@@ -0,0 +1,12 @@
+use json_store::entity::StoreEntity;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, StoreEntity)]
+#[store(tree = "users")]
+struct User {
+    #[store(sequence)]
+    #[serde(rename = "")]
+    id: Option<u64>,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use json_store::entity::StoreEntity;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, StoreEntity)]
+#[store(tree = "users")]
+struct User {
+    email: String,
+}
+
+fn main() {}
@@ -0,0 +1,14 @@
+use json_store::entity::StoreEntity;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, StoreEntity)]
+#[store(tree = "users")]
+struct User {
+    #[store(sequence)]
+    id: Option<u64>,
+    #[store(unique)]
+    #[serde(skip)]
+    email: String,
+}
+
+fn main() {}
@@ -0,0 +1,15 @@
+use json_store::entity::StoreEntity;
+use serde::Serialize;
+
+// serde would warn of the clash too, on deserializing
+#[derive(Serialize, StoreEntity)]
+#[store(tree = "users")]
+struct User {
+    #[store(sequence)]
+    id: Option<u64>,
+    #[store(unique)]
+    #[serde(rename = "id")]
+    legacy_id: u64,
+}
+
+fn main() {}
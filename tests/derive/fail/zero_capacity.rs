@@ -0,0 +1,11 @@
+use json_store::entity::StoreEntity;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, StoreEntity)]
+#[store(tree = "users", capacity = 0)]
+struct User {
+    #[store(sequence)]
+    id: Option<u64>,
+}
+
+fn main() {}
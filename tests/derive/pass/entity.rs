@@ -0,0 +1,24 @@
+use json_store::entity::StoreEntity;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, StoreEntity)]
+#[store(tree = "users", capacity = 1000)]
+#[serde(rename_all = "kebab-case")]
+struct User {
+    #[store(sequence)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<u64>,
+    #[store(unique)]
+    email: String,
+    #[store(unique(group = "name_org"))]
+    #[serde(rename(serialize = "full-name", deserialize = "name"))]
+    name: String,
+    #[store(unique(group = "name_org"))]
+    org_id: u64,
+    #[serde(skip)]
+    cached: bool,
+}
+
+fn main() {
+    User::info().validate().unwrap();
+}
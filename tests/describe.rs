@@ -0,0 +1,80 @@
+mod common;
+
+use common::{store_with_users, users, ScratchDir};
+use json_store::{stats::StoreDescription, store::JsonStore};
+use serde_json::{json, Value};
+
+const SECRET: &str = "hunter2";
+
+// users holding a secret each, saved, then read again with only users loaded
+async fn store_with_secrets(dir: &ScratchDir) -> JsonStore {
+    let store = store_with_users(dir).await;
+    store.create_tree("teams", users()).await.unwrap();
+    for tname in ["users", "teams"] {
+        for n in 0..3 {
+            store
+                .insert(
+                    tname,
+                    &json!({"email": format!("{}@x", n), "password": format!("{}{}", SECRET, n)}),
+                )
+                .await
+                .unwrap();
+        }
+    }
+    store.close().await.unwrap();
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    store.select::<Value>("users", 1).await.unwrap();
+    store
+}
+
+// the description every way it is shown
+fn shown(description: &StoreDescription) -> [String; 3] {
+    [
+        description.to_string(),
+        format!("{:?}", description),
+        serde_json::to_string(description).unwrap(),
+    ]
+}
+
+#[tokio::test]
+async fn a_description_holds_no_records() {
+    let dir = ScratchDir::new("describe-no-records");
+    let store = store_with_secrets(&dir).await;
+
+    let description = store.describe().await.unwrap();
+    let users = &description.trees["users"];
+    assert_eq!((users.records, users.sequence), (3, 3));
+    assert!(users.loaded && !users.dirty);
+    assert!(users.samples.is_empty());
+    for text in shown(&description) {
+        assert!(!text.contains(SECRET), "{}", text);
+        assert!(!text.contains("0@x"), "{}", text);
+    }
+    // nor a samples field, even an empty one
+    let serialized = serde_json::to_value(&description).unwrap();
+    assert!(serialized["trees"]["users"].get("samples").is_none());
+}
+
+#[tokio::test]
+async fn samples_are_shown_only_when_asked_for() {
+    let dir = ScratchDir::new("describe-samples");
+    let store = store_with_secrets(&dir).await;
+
+    let description = store.describe_with_samples(2).await.unwrap();
+    let samples = &description.trees["users"].samples;
+    assert_eq!(
+        samples.iter().map(|s| s["id"].clone()).collect::<Vec<_>>(),
+        [json!(1), json!(2)]
+    );
+    for text in shown(&description) {
+        assert!(text.contains("hunter20"), "{}", text);
+        assert!(!text.contains("hunter22"), "{}", text);
+    }
+
+    // a tree not loaded isn't read for them
+    let teams = &description.trees["teams"];
+    assert!(!teams.loaded);
+    assert!(teams.samples.is_empty());
+    assert_eq!(store.loaded_trees().await, ["users"]);
+}
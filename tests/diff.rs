@@ -0,0 +1,182 @@
+mod common;
+
+use common::{store_with_users, users, ScratchDir};
+use json_store::{
+    diff::{DiffOptions, FieldChange, InfoDiff, RecordDiff, TreeDiff},
+    error::JsonStoreError,
+    store::{Info, JsonStore},
+};
+use serde_json::json;
+use std::collections::BTreeMap;
+
+// two stores with the same three users, so a change made to one shows in the diff
+async fn twins(old: &ScratchDir, new: &ScratchDir) -> (JsonStore, JsonStore) {
+    let old = store_with_users(old).await;
+    let new = store_with_users(new).await;
+    for store in [&old, &new] {
+        for (email, name) in [("a@x", "A"), ("b@x", "B"), ("c@x", "C")] {
+            store
+                .insert("users", &json!({"email": email, "name": name, "seen": 1}))
+                .await
+                .unwrap();
+        }
+    }
+    (old, new)
+}
+
+fn change(old: Option<serde_json::Value>, new: Option<serde_json::Value>) -> FieldChange {
+    FieldChange { old, new }
+}
+
+#[tokio::test]
+async fn the_same_records_make_an_empty_diff() {
+    let (old, new) = (
+        ScratchDir::new("diff-same-old"),
+        ScratchDir::new("diff-same-new"),
+    );
+    let (old, new) = twins(&old, &new).await;
+    // whatever order their fields were written in
+    new.update(
+        "users",
+        &json!({"seen": 1, "name": "A", "email": "a@x", "id": 1}),
+    )
+    .await
+    .unwrap();
+
+    assert!(old.diff_tree("users", &new).await.unwrap().is_empty());
+    assert!(old.diff(&new).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn added_removed_and_changed_records_are_told_apart() {
+    let (old, new) = (
+        ScratchDir::new("diff-records-old"),
+        ScratchDir::new("diff-records-new"),
+    );
+    let (old, new) = twins(&old, &new).await;
+    new.delete("users", 2).await.unwrap();
+    new.insert("users", &json!({"email": "d@x"})).await.unwrap();
+    // a changed field, a dropped one, a null one and a new one
+    new.update(
+        "users",
+        &json!({"id": 3, "email": "c@y", "name": null, "admin": true}),
+    )
+    .await
+    .unwrap();
+
+    let diff = old.diff_tree("users", &new).await.unwrap();
+    let fields = BTreeMap::from([
+        ("admin".to_string(), change(None, Some(json!(true)))),
+        (
+            "email".to_string(),
+            change(Some(json!("c@x")), Some(json!("c@y"))),
+        ),
+        (
+            "name".to_string(),
+            change(Some(json!("C")), Some(json!(null))),
+        ),
+        ("seen".to_string(), change(Some(json!(1)), None)),
+    ]);
+    assert_eq!(
+        diff,
+        TreeDiff {
+            added: vec![4],
+            removed: vec![2],
+            modified: vec![RecordDiff {
+                sequence: 3,
+                fields,
+            }],
+            info: None,
+        }
+    );
+    // which way round they are is which side is old
+    let back = new.diff_tree("users", &old).await.unwrap();
+    assert_eq!((back.added, back.removed), (vec![2], vec![4]));
+
+    // a missing field is left out of the JSON, a null one is not
+    let serialized = serde_json::to_value(&diff.modified[0]).unwrap();
+    assert_eq!(serialized["fields"]["seen"], json!({"old": 1}));
+    assert_eq!(
+        serialized["fields"]["name"],
+        json!({"old": "C", "new": null})
+    );
+}
+
+#[tokio::test]
+async fn ignored_fields_are_not_compared() {
+    let (old, new) = (
+        ScratchDir::new("diff-ignore-old"),
+        ScratchDir::new("diff-ignore-new"),
+    );
+    let (old, new) = twins(&old, &new).await;
+    new.update(
+        "users",
+        &json!({"id": 1, "email": "a@x", "name": "A", "seen": 2}),
+    )
+    .await
+    .unwrap();
+    new.update(
+        "users",
+        &json!({"id": 2, "email": "b@x", "name": "Bee", "seen": 2}),
+    )
+    .await
+    .unwrap();
+
+    let options = DiffOptions {
+        ignore_fields: vec!["seen".to_string()],
+    };
+    let diff = old.diff_tree_with("users", &new, &options).await.unwrap();
+    assert_eq!(
+        diff.modified.iter().map(|r| r.sequence).collect::<Vec<_>>(),
+        [2]
+    );
+    assert_eq!(diff.modified[0].fields.keys().collect::<Vec<_>>(), ["name"]);
+}
+
+#[tokio::test]
+async fn a_different_info_is_part_of_the_diff() {
+    let (old, new) = (
+        ScratchDir::new("diff-info-old"),
+        ScratchDir::new("diff-info-new"),
+    );
+    let (old, new) = twins(&old, &new).await;
+    new.set_tree_capacity("users", 10).await.unwrap();
+
+    let diff = old.diff_tree("users", &new).await.unwrap();
+    assert!(!diff.is_empty());
+    assert!(diff.added.is_empty() && diff.removed.is_empty() && diff.modified.is_empty());
+    let InfoDiff {
+        old: old_info,
+        new: new_info,
+    } = diff.info.unwrap();
+    assert_eq!(old_info, users());
+    assert_eq!(new_info.capacity, 10);
+}
+
+#[tokio::test]
+async fn a_store_diff_covers_every_tree() {
+    let (old, new) = (
+        ScratchDir::new("diff-store-old"),
+        ScratchDir::new("diff-store-new"),
+    );
+    let (old, new) = twins(&old, &new).await;
+    let plain = || Info::builder().sequence_field("id").build().unwrap();
+    for store in [&old, &new] {
+        store.create_tree("teams", plain()).await.unwrap();
+    }
+    old.create_tree("archive", plain()).await.unwrap();
+    new.create_tree("audit", plain()).await.unwrap();
+    new.insert("teams", &json!({"name": "t"})).await.unwrap();
+
+    let diff = old.diff(&new).await.unwrap();
+    assert_eq!(diff.added_trees, ["audit"]);
+    assert_eq!(diff.removed_trees, ["archive"]);
+    // trees that match aren't listed
+    assert_eq!(diff.trees.keys().collect::<Vec<_>>(), ["teams"]);
+    assert_eq!(diff.trees["teams"].added, [1]);
+
+    assert!(matches!(
+        old.diff_tree("archive", &new).await,
+        Err(JsonStoreError::NotFoundTree(_))
+    ));
+}
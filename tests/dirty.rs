@@ -0,0 +1,78 @@
+mod common;
+
+use common::{store_with_users, users, ScratchDir};
+use json_store::error::JsonStoreError;
+use serde_json::json;
+
+#[tokio::test]
+async fn writes_dirty_a_tree_and_saves_clean_it() {
+    let dir = ScratchDir::new("dirty-writes");
+    let store = store_with_users(&dir).await;
+    assert!(!store.is_dirty("users").await.unwrap());
+
+    let seq = store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    assert!(store.is_dirty("users").await.unwrap());
+    store.save_tree("users").await.unwrap();
+    assert!(!store.is_dirty("users").await.unwrap());
+
+    store
+        .update("users", &json!({"id": seq, "email": "b@x"}))
+        .await
+        .unwrap();
+    assert!(store.is_dirty("users").await.unwrap());
+    store.save().await.unwrap();
+
+    store.delete("users", seq).await.unwrap();
+    assert!(store.is_dirty("users").await.unwrap());
+}
+
+#[tokio::test]
+async fn failed_writes_and_reads_leave_a_tree_clean() {
+    let dir = ScratchDir::new("dirty-failed");
+    let store = store_with_users(&dir).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+
+    assert!(store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .is_err());
+    assert!(store.delete("users", 99).await.is_err());
+    common::all(&store, "users").await;
+    assert!(!store.is_dirty("users").await.unwrap());
+}
+
+#[tokio::test]
+async fn unsaved_changes_lists_dirty_trees_in_order() {
+    let dir = ScratchDir::new("dirty-list");
+    let store = store_with_users(&dir).await;
+    store.create_tree("b", users()).await.unwrap();
+    store.create_tree("a", users()).await.unwrap();
+    assert!(store.unsaved_changes().await.is_empty());
+
+    for tname in ["users", "b", "a"] {
+        store.insert(tname, &json!({"email": "x"})).await.unwrap();
+    }
+    assert_eq!(store.unsaved_changes().await, ["a", "b", "users"]);
+
+    store.save_tree("b").await.unwrap();
+    assert_eq!(store.unsaved_changes().await, ["a", "users"]);
+    store.save().await.unwrap();
+    assert!(store.unsaved_changes().await.is_empty());
+}
+
+#[tokio::test]
+async fn is_dirty_needs_an_existing_tree() {
+    let dir = ScratchDir::new("dirty-missing");
+    let store = store_with_users(&dir).await;
+    assert!(matches!(
+        store.is_dirty("nope").await,
+        Err(JsonStoreError::NotFoundTree(_))
+    ));
+}
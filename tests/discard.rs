@@ -0,0 +1,97 @@
+mod common;
+
+use common::{all, store_with_users, users, ScratchDir};
+use json_store::store::JsonStore;
+use serde_json::json;
+
+#[tokio::test]
+async fn discard_reverts_to_the_saved_files() {
+    let dir = ScratchDir::new("discard-revert");
+    let store = store_with_users(&dir).await;
+    let seq = store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+
+    store
+        .update("users", &json!({"id": seq, "email": "b@x"}))
+        .await
+        .unwrap();
+    store
+        .insert("users", &json!({"email": "c@x"}))
+        .await
+        .unwrap();
+    store.discard_changes("users").await.unwrap();
+
+    assert!(!store.is_dirty("users").await.unwrap());
+    assert_eq!(
+        all(&store, "users").await,
+        [json!({"id": 1, "email": "a@x"})]
+    );
+
+    // the sequence goes back too, and the unique index with the records
+    let next = store
+        .insert("users", &json!({"email": "c@x"}))
+        .await
+        .unwrap();
+    assert_eq!(next, 2);
+    assert!(store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn a_tree_never_written_to_comes_back_empty() {
+    let dir = ScratchDir::new("discard-empty");
+    let store = store_with_users(&dir).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+
+    store.discard_changes("users").await.unwrap();
+    assert!(all(&store, "users").await.is_empty());
+    assert_eq!(
+        store
+            .insert("users", &json!({"email": "a@x"}))
+            .await
+            .unwrap(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn discard_all_reverts_only_dirty_trees() {
+    let dir = ScratchDir::new("discard-all");
+    let store = store_with_users(&dir).await;
+    store.create_tree("other", users()).await.unwrap();
+    store.create_tree("kept", users()).await.unwrap();
+    store
+        .insert("kept", &json!({"email": "k@x"}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store
+        .insert("other", &json!({"email": "o@x"}))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        store.discard_all_changes().await.unwrap(),
+        ["other", "users"]
+    );
+    assert!(store.unsaved_changes().await.is_empty());
+    assert!(all(&store, "users").await.is_empty());
+    assert!(all(&store, "other").await.is_empty());
+    assert_eq!(all(&store, "kept").await.len(), 1);
+
+    let reloaded = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(all(&reloaded, "kept").await.len(), 1);
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use common::{all, users, ScratchDir};
+use json_store::store::{Durability, JsonStore, LoadOptions};
+use serde_json::json;
+
+async fn store_with(dir: &ScratchDir, durability: Durability) -> JsonStore {
+    let options = LoadOptions {
+        durability,
+        ..Default::default()
+    };
+    let store = JsonStore::load_with_options(dir.path(), options)
+        .await
+        .unwrap();
+    store.create_tree("users", users()).await.unwrap();
+    store
+}
+
+#[tokio::test]
+async fn every_level_saves_the_same_data() {
+    for durability in [Durability::None, Durability::Flush, Durability::Fsync] {
+        let dir = ScratchDir::new("durability-levels");
+        let store = store_with(&dir, durability).await;
+        assert_eq!(store.durability(), durability);
+
+        store
+            .insert("users", &json!({"email": "a@x"}))
+            .await
+            .unwrap();
+        store.save().await.unwrap();
+        store
+            .insert("users", &json!({"email": "b@x"}))
+            .await
+            .unwrap();
+        store.save_tree("users").await.unwrap();
+
+        let reloaded = JsonStore::load(dir.path()).await.unwrap();
+        assert_eq!(all(&reloaded, "users").await.len(), 2, "{:?}", durability);
+    }
+}
+
+#[tokio::test]
+async fn flush_is_the_default() {
+    let dir = ScratchDir::new("durability-default");
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(store.durability(), Durability::Flush);
+}
+
+// None overwrites the file where it is, the others replace it with a new one
+#[cfg(unix)]
+#[tokio::test]
+async fn only_none_writes_in_place() {
+    use std::os::unix::fs::MetadataExt;
+
+    for (durability, in_place) in [
+        (Durability::None, true),
+        (Durability::Flush, false),
+        (Durability::Fsync, false),
+    ] {
+        let dir = ScratchDir::new("durability-in-place");
+        let store = store_with(&dir, Durability::Flush).await;
+        let file = dir.path().join("users.json");
+        let before = std::fs::metadata(&file).unwrap().ino();
+
+        store
+            .insert("users", &json!({"email": "a@x"}))
+            .await
+            .unwrap();
+        store.save_with(durability).await.unwrap();
+
+        let after = std::fs::metadata(&file).unwrap().ino();
+        assert_eq!(before == after, in_place, "{:?}", durability);
+    }
+}
+
+// a per-call durability overrides the store's: with None the temp file, here a
+// writer that always fails, is never touched
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn save_with_overrides_the_store_durability() {
+    let dir = ScratchDir::new("durability-override");
+    let store = store_with(&dir, Durability::Fsync).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    std::os::unix::fs::symlink("/dev/full", dir.path().join("users.json.tmp")).unwrap();
+
+    assert!(store.save().await.is_err());
+    store
+        .save_tree_with("users", Durability::None)
+        .await
+        .unwrap();
+    assert!(!store.is_dirty("users").await.unwrap());
+}
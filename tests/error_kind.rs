@@ -0,0 +1,482 @@
+use json_store::{
+    codec::Codec,
+    error::{ErrorKind, InfoValidationError, JsonStoreError as E},
+    integrity::TreeIntegrityReport,
+    layout::Layout,
+    store::Info,
+};
+use std::path::PathBuf;
+
+// The name of e's variant. The match has no wildcard arm, so a new variant stops
+// this file compiling until it is named here, and then the table below wants a row.
+fn variant(e: &E) -> &'static str {
+    match e {
+        E::DeserializeFromStr(_) => "DeserializeFromStr",
+        E::Io(_) => "Io",
+        #[cfg(feature = "csv")]
+        E::Csv(_) => "Csv",
+        #[cfg(feature = "sqlite")]
+        E::Sqlite(_) => "Sqlite",
+        E::ReadFile { .. } => "ReadFile",
+        E::WriteFile { .. } => "WriteFile",
+        E::ParseFile { .. } => "ParseFile",
+        E::DeserializeRecord { .. } => "DeserializeRecord",
+        E::InvalidUtf8 { .. } => "InvalidUtf8",
+        E::InUseTree(_) => "InUseTree",
+        E::NotFoundTree(_) => "NotFoundTree",
+        E::FoundTree(_) => "FoundTree",
+        E::DuplicateUniqueFields(_) => "DuplicateUniqueFields",
+        E::CapacityExceeded(_) => "CapacityExceeded",
+        E::UnableToMutValue(_) => "UnableToMutValue",
+        E::SequenceNotExist(_) => "SequenceNotExist",
+        E::CorruptSequenceFile { .. } => "CorruptSequenceFile",
+        E::TreeCorrupt { .. } => "TreeCorrupt",
+        E::IntegrityViolation { .. } => "IntegrityViolation",
+        E::HistoryNotFound { .. } => "HistoryNotFound",
+        E::NothingToUndo(_) => "NothingToUndo",
+        E::UndoConflict { .. } => "UndoConflict",
+        E::RestorePointUnavailable { .. } => "RestorePointUnavailable",
+        E::MergeConflict { .. } => "MergeConflict",
+        E::ArchiveConflict { .. } => "ArchiveConflict",
+        E::DanglingReference { .. } => "DanglingReference",
+        E::ExternallyModified { .. } => "ExternallyModified",
+        E::RecordLocked { .. } => "RecordLocked",
+        E::SaveFailed { .. } => "SaveFailed",
+        E::KeyNotExist { .. } => "KeyNotExist",
+        E::SequenceNotReserved { .. } => "SequenceNotReserved",
+        E::UniqueConstraintNotFound { .. } => "UniqueConstraintNotFound",
+        E::FixtureTreeNotFound { .. } => "FixtureTreeNotFound",
+        E::InvalidKey { .. } => "InvalidKey",
+        E::ChecksumMismatch { .. } => "ChecksumMismatch",
+        E::UnsupportedFormatVersion { .. } => "UnsupportedFormatVersion",
+        E::BackupDestinationNotEmpty(_) => "BackupDestinationNotEmpty",
+        E::ImportRejected { .. } => "ImportRejected",
+        E::InvalidBackup { .. } => "InvalidBackup",
+        E::InvalidStorePath { .. } => "InvalidStorePath",
+        E::InvalidArchive { .. } => "InvalidArchive",
+        E::Codec { .. } => "Codec",
+        E::CodecUnavailable(_) => "CodecUnavailable",
+        E::CodecMismatch { .. } => "CodecMismatch",
+        E::TreeNameCollision { .. } => "TreeNameCollision",
+        E::InfoMismatch { .. } => "InfoMismatch",
+        E::MigrationFailed { .. } => "MigrationFailed",
+        E::LayoutMismatch { .. } => "LayoutMismatch",
+        E::InvalidLayout(_) => "InvalidLayout",
+        E::InvalidInfo(_) => "InvalidInfo",
+        E::InvalidTreeInfo { .. } => "InvalidTreeInfo",
+        E::InvalidOptions(_) => "InvalidOptions",
+        #[cfg(feature = "object_store")]
+        E::ObjectStore { .. } => "ObjectStore",
+        E::Backend { .. } => "Backend",
+        E::ReadOnlyStore => "ReadOnlyStore",
+        E::TreeFrozen(_) => "TreeFrozen",
+        E::UnknownStore(_) => "UnknownStore",
+        E::ReplicaStore => "ReplicaStore",
+        E::StoreClosed => "StoreClosed",
+        E::UnObjectValue => "UnObjectValue",
+        E::DefaultError => "DefaultError",
+    }
+}
+
+fn json_error() -> serde_json::Error {
+    serde_json::from_str::<serde_json::Value>("{").unwrap_err()
+}
+
+fn io_error() -> std::io::Error {
+    std::io::Error::other("disk on fire")
+}
+
+fn utf8_error() -> std::str::Utf8Error {
+    let bytes = vec![b'a', 0xff];
+    std::str::from_utf8(&bytes).unwrap_err()
+}
+
+fn t() -> String {
+    "users".to_string()
+}
+
+fn path() -> PathBuf {
+    PathBuf::from("store/users.json")
+}
+
+fn info() -> Info {
+    Info::builder().sequence_field("id").build().unwrap()
+}
+
+fn why() -> String {
+    "because".to_string()
+}
+
+// one of every variant with the kind it must have
+fn table() -> Vec<(E, ErrorKind)> {
+    use ErrorKind::*;
+
+    #[allow(unused_mut)]
+    let mut rows = vec![
+        (E::DeserializeFromStr(json_error()), InvalidInput),
+        (E::Io(io_error()), Io),
+        (
+            E::ReadFile {
+                path: path(),
+                source: io_error(),
+            },
+            Io,
+        ),
+        (
+            E::WriteFile {
+                path: path(),
+                source: io_error(),
+            },
+            Io,
+        ),
+        (
+            E::ParseFile {
+                path: path(),
+                source: json_error(),
+            },
+            Corruption,
+        ),
+        (
+            E::DeserializeRecord {
+                tree: t(),
+                sequence: 1,
+                source: json_error(),
+            },
+            InvalidInput,
+        ),
+        (
+            E::InvalidUtf8 {
+                path: path(),
+                source: utf8_error(),
+            },
+            Corruption,
+        ),
+        (E::InUseTree(t()), Locked),
+        (E::NotFoundTree(t()), NotFound),
+        (E::FoundTree(t()), Conflict),
+        (E::DuplicateUniqueFields(t()), Conflict),
+        (E::CapacityExceeded(t()), CapacityExceeded),
+        (E::UnableToMutValue(t()), Internal),
+        (E::SequenceNotExist(t()), NotFound),
+        (
+            E::CorruptSequenceFile {
+                tree: t(),
+                path: path(),
+                content: why(),
+            },
+            Corruption,
+        ),
+        (
+            E::TreeCorrupt {
+                tree: t(),
+                reason: why(),
+            },
+            Corruption,
+        ),
+        (
+            E::IntegrityViolation {
+                tree: t(),
+                report: Box::new(TreeIntegrityReport::default()),
+            },
+            Corruption,
+        ),
+        (
+            E::HistoryNotFound {
+                tree: t(),
+                sequence: 1,
+                index: 0,
+            },
+            NotFound,
+        ),
+        (E::NothingToUndo(t()), NotFound),
+        (
+            E::UndoConflict {
+                tree: t(),
+                sequence: 1,
+                reason: why(),
+            },
+            Conflict,
+        ),
+        (
+            E::RestorePointUnavailable {
+                tree: t(),
+                reason: why(),
+            },
+            InvalidInput,
+        ),
+        (
+            E::MergeConflict {
+                tree: t(),
+                sequence: 1,
+                reason: why(),
+            },
+            Conflict,
+        ),
+        (
+            E::ArchiveConflict {
+                tree: t(),
+                sequence: 1,
+                reason: why(),
+            },
+            Conflict,
+        ),
+        (
+            E::DanglingReference {
+                tree: t(),
+                sequence: 1,
+                field: "author".to_string(),
+                target: 2,
+                referenced: "people".to_string(),
+            },
+            Conflict,
+        ),
+        (
+            E::ExternallyModified {
+                tree: t(),
+                path: path(),
+            },
+            Conflict,
+        ),
+        (
+            E::RecordLocked {
+                tree: t(),
+                sequence: 1,
+                owner: "me".to_string(),
+            },
+            Locked,
+        ),
+        (
+            E::SaveFailed {
+                failed: vec![(t(), E::Io(io_error()))],
+                saved: vec![],
+            },
+            Io,
+        ),
+        (
+            E::KeyNotExist {
+                tree: t(),
+                key: "k".to_string(),
+            },
+            NotFound,
+        ),
+        (
+            E::SequenceNotReserved {
+                tree: t(),
+                sequence: 1,
+            },
+            InvalidInput,
+        ),
+        (
+            E::UniqueConstraintNotFound {
+                tree: t(),
+                constraint: "email".to_string(),
+            },
+            NotFound,
+        ),
+        (
+            E::FixtureTreeNotFound {
+                tree: t(),
+                path: path(),
+            },
+            NotFound,
+        ),
+        (
+            E::InvalidKey {
+                tree: t(),
+                reason: why(),
+            },
+            InvalidInput,
+        ),
+        (
+            E::ChecksumMismatch {
+                tree: t(),
+                path: path(),
+            },
+            Corruption,
+        ),
+        (
+            E::UnsupportedFormatVersion {
+                found: 9,
+                supported: 2,
+            },
+            InvalidInput,
+        ),
+        (E::BackupDestinationNotEmpty(path()), Conflict),
+        (
+            E::ImportRejected {
+                tree: t(),
+                index: 0,
+                reason: why(),
+            },
+            InvalidInput,
+        ),
+        (
+            E::InvalidBackup {
+                path: path(),
+                reason: why(),
+            },
+            InvalidInput,
+        ),
+        (
+            E::InvalidStorePath {
+                path: path(),
+                reason: why(),
+            },
+            InvalidInput,
+        ),
+        (
+            E::InvalidArchive {
+                path: path(),
+                reason: why(),
+            },
+            InvalidInput,
+        ),
+        (
+            E::Codec {
+                codec: Codec::Json,
+                message: why(),
+            },
+            Corruption,
+        ),
+        (E::CodecUnavailable(Codec::Cbor), InvalidInput),
+        (
+            E::CodecMismatch {
+                store: Codec::Json,
+                requested: Codec::Cbor,
+            },
+            InvalidInput,
+        ),
+        (
+            E::TreeNameCollision {
+                tree: t(),
+                existing: "Users".to_string(),
+            },
+            Conflict,
+        ),
+        (
+            E::InfoMismatch {
+                tree: t(),
+                stored: Box::new(info()),
+                requested: Box::new(info()),
+            },
+            Conflict,
+        ),
+        (
+            E::MigrationFailed {
+                tree: t(),
+                version: 2,
+                sequence: None,
+                reason: why(),
+            },
+            InvalidInput,
+        ),
+        (
+            E::LayoutMismatch {
+                store: Box::new(Layout::default()),
+                requested: Box::new(Layout::default()),
+            },
+            InvalidInput,
+        ),
+        (E::InvalidLayout(why()), InvalidInput),
+        (E::InvalidInfo(why()), InvalidInput),
+        (
+            E::InvalidTreeInfo {
+                tree: t(),
+                source: InfoValidationError::ZeroCapacity,
+            },
+            InvalidInput,
+        ),
+        (E::InvalidOptions(why()), InvalidInput),
+        (
+            E::Backend {
+                key: "users.json".to_string(),
+                message: why(),
+            },
+            Io,
+        ),
+        (E::ReadOnlyStore, InvalidInput),
+        (E::TreeFrozen(t()), Conflict),
+        (E::UnknownStore("other".to_string()), NotFound),
+        (E::ReplicaStore, InvalidInput),
+        (E::StoreClosed, InvalidInput),
+        (E::UnObjectValue, InvalidInput),
+        (E::DefaultError, Internal),
+    ];
+    #[cfg(feature = "csv")]
+    rows.push((E::Csv(csv::Error::from(io_error())), InvalidInput));
+    #[cfg(feature = "sqlite")]
+    rows.push((
+        E::Sqlite(rusqlite::Error::QueryReturnedNoRows),
+        InvalidInput,
+    ));
+    #[cfg(feature = "object_store")]
+    rows.push((
+        E::ObjectStore {
+            key: "users.json".to_string(),
+            attempts: 3,
+            source: object_store::Error::Generic {
+                store: "test",
+                source: "timeout".into(),
+            },
+        },
+        Io,
+    ));
+    rows
+}
+
+#[test]
+fn every_variant_has_its_kind() {
+    let mut seen = std::collections::BTreeSet::new();
+    for (error, kind) in table() {
+        let name = variant(&error);
+        assert!(seen.insert(name), "{} is in the table twice", name);
+        assert_eq!(error.kind(), kind, "{}", name);
+        assert_eq!(
+            error.is_not_found(),
+            kind == ErrorKind::NotFound,
+            "{}",
+            name
+        );
+        assert_eq!(error.is_conflict(), kind == ErrorKind::Conflict, "{}", name);
+    }
+}
+
+#[test]
+fn only_busy_trees_and_transient_io_are_retryable() {
+    let transient = || std::io::Error::from(std::io::ErrorKind::TimedOut);
+    let retryable = [
+        E::InUseTree(t()),
+        E::RecordLocked {
+            tree: t(),
+            sequence: 1,
+            owner: "me".to_string(),
+        },
+        E::Io(std::io::Error::from(std::io::ErrorKind::Interrupted)),
+        E::ReadFile {
+            path: path(),
+            source: transient(),
+        },
+        E::WriteFile {
+            path: path(),
+            source: std::io::Error::from(std::io::ErrorKind::WouldBlock),
+        },
+        E::SaveFailed {
+            failed: vec![(t(), E::Io(transient()))],
+            saved: vec![t()],
+        },
+    ];
+    for error in retryable {
+        assert!(error.is_retryable(), "{:?}", error);
+    }
+
+    let mixed = E::SaveFailed {
+        failed: vec![(t(), E::Io(transient())), (t(), E::Io(io_error()))],
+        saved: vec![],
+    };
+    assert!(!mixed.is_retryable());
+    for (error, kind) in table() {
+        if kind != ErrorKind::Locked && kind != ErrorKind::Io {
+            assert!(!error.is_retryable(), "{}", variant(&error));
+        }
+    }
+}
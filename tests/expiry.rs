@@ -0,0 +1,247 @@
+mod common;
+
+use common::{all, read_json, ScratchDir};
+use json_store::{
+    clock::Clock,
+    error::JsonStoreError,
+    store::{Info, JsonStore, LoadOptions},
+};
+use serde_json::{json, Value};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// 2026-01-01T00:00:00Z, where every test's clock starts
+const START: u64 = 1_767_225_600;
+
+// a clock standing still until moved on
+#[derive(Debug)]
+struct TestClock(Mutex<SystemTime>);
+
+impl TestClock {
+    fn new() -> Arc<Self> {
+        Arc::new(Self(Mutex::new(UNIX_EPOCH + Duration::from_secs(START))))
+    }
+
+    fn advance(&self, by: Duration) {
+        *self.0.lock().unwrap() += by;
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> SystemTime {
+        *self.0.lock().unwrap()
+    }
+}
+
+// sessions by sequence "id", unique by token, expiring at "expires_at"
+fn sessions() -> Info {
+    Info::builder()
+        .sequence_field("id")
+        .unique("token", ["token"])
+        .index("user")
+        .expiry_field("expires_at")
+        .build()
+        .unwrap()
+}
+
+async fn store_with_sessions(dir: &ScratchDir, clock: &Arc<TestClock>) -> JsonStore {
+    let options = LoadOptions {
+        clock: Some(clock.clone()),
+        ..Default::default()
+    };
+    let store = JsonStore::load_with_options(dir.path(), options)
+        .await
+        .unwrap();
+    store.create_tree("sessions", sessions()).await.unwrap();
+    store
+}
+
+// Five sessions of user 7: one expiring in an hour as a number of milliseconds, one
+// a minute ago as RFC 3339, one an hour on as RFC 3339 with an offset, one without
+// the field and one with something there that isn't a time.
+async fn fill(store: &JsonStore) {
+    let hour = 3_600_000;
+    let now = START * 1000;
+    for record in [
+        json!({"token": "a", "user": 7, "expires_at": now + hour}),
+        json!({"token": "b", "user": 7, "expires_at": "2025-12-31T23:59:00Z"}),
+        json!({"token": "c", "user": 7, "expires_at": "2026-01-01T02:00:00+01:00"}),
+        json!({"token": "d", "user": 7}),
+        json!({"token": "e", "user": 7, "expires_at": "soon"}),
+    ] {
+        store.insert("sessions", &record).await.unwrap();
+    }
+}
+
+fn tokens(records: &[Value]) -> Vec<&str> {
+    records
+        .iter()
+        .map(|record| record["token"].as_str().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn reads_leave_expired_records_out() {
+    let dir = ScratchDir::new("expiry-reads");
+    let clock = TestClock::new();
+    let store = store_with_sessions(&dir, &clock).await;
+    fill(&store).await;
+
+    // b has gone, in both the number and the string forms the rest are in
+    assert!(matches!(
+        store.select::<Value>("sessions", 2).await,
+        Err(JsonStoreError::SequenceNotExist(_))
+    ));
+    assert_eq!(tokens(&all(&store, "sessions").await), ["a", "c", "d", "e"]);
+    let by_user = store
+        .find_by_field::<Value>("sessions", "user", &json!(7))
+        .await
+        .unwrap();
+    assert_eq!(tokens(&by_user), ["a", "c", "d", "e"]);
+
+    // an hour on, a and c (01:00 UTC) have gone too; d and e never go
+    clock.advance(Duration::from_secs(3600));
+    assert_eq!(tokens(&all(&store, "sessions").await), ["d", "e"]);
+    assert!(store.select::<Value>("sessions", 1).await.is_err());
+
+    let file = dir.path().join("export.json");
+    let exported = store
+        .export_tree("sessions", &file, Default::default())
+        .await
+        .unwrap();
+    assert_eq!(exported, 2);
+    assert_eq!(tokens(read_json(&file).as_array().unwrap()), ["d", "e"]);
+
+    // they stay in the tree until purged
+    assert_eq!(store.tree_stats("sessions").await.unwrap().records, 5);
+}
+
+#[tokio::test]
+async fn purge_removes_expired_records_and_returns_their_sequences() {
+    let dir = ScratchDir::new("expiry-purge");
+    let clock = TestClock::new();
+    let store = store_with_sessions(&dir, &clock).await;
+    fill(&store).await;
+
+    assert_eq!(store.purge_expired("sessions").await.unwrap(), [2]);
+    assert_eq!(
+        store.purge_expired("sessions").await.unwrap(),
+        Vec::<u64>::new()
+    );
+    clock.advance(Duration::from_secs(3600));
+    assert_eq!(store.purge_expired("sessions").await.unwrap(), [1, 3]);
+    assert_eq!(store.tree_stats("sessions").await.unwrap().records, 2);
+
+    // and stay removed
+    store.close().await.unwrap();
+    let store = JsonStore::load_with_options(
+        dir.path(),
+        LoadOptions {
+            clock: Some(clock.clone()),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(tokens(&all(&store, "sessions").await), ["d", "e"]);
+    assert_eq!(store.tree_stats("sessions").await.unwrap().records, 2);
+}
+
+#[tokio::test]
+async fn a_tree_without_an_expiry_field_keeps_everything() {
+    let dir = ScratchDir::new("expiry-none");
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    store
+        .create_tree(
+            "sessions",
+            Info::builder().sequence_field("id").build().unwrap(),
+        )
+        .await
+        .unwrap();
+    fill(&store).await;
+    assert_eq!(all(&store, "sessions").await.len(), 5);
+    assert_eq!(
+        store.purge_expired("sessions").await.unwrap(),
+        Vec::<u64>::new()
+    );
+}
+
+// The decision pinned here: an expired record doesn't hold its unique values. The
+// write that would clash with it removes it instead, and undo puts it back.
+#[tokio::test]
+async fn expired_records_do_not_hold_their_unique_values() {
+    let dir = ScratchDir::new("expiry-unique");
+    let clock = TestClock::new();
+    let store = store_with_sessions(&dir, &clock).await;
+    fill(&store).await;
+
+    // b is taken by an expired record: the insert goes through and b is gone
+    let seq = store
+        .insert("sessions", &json!({"token": "b", "user": 8}))
+        .await
+        .unwrap();
+    assert_eq!(seq, 6);
+    assert_eq!(store.tree_stats("sessions").await.unwrap().records, 5);
+    assert_eq!(
+        store.purge_expired("sessions").await.unwrap(),
+        Vec::<u64>::new()
+    );
+    let b: Value = store.select("sessions", 6).await.unwrap();
+    assert_eq!(b["user"], 8);
+
+    // a is live, so it clashes as ever, by insert or update
+    assert!(matches!(
+        store
+            .insert("sessions", &json!({"token": "a", "user": 8}))
+            .await,
+        Err(JsonStoreError::DuplicateUniqueFields(_))
+    ));
+    assert!(matches!(
+        store
+            .update("sessions", &json!({"id": 4, "token": "a"}))
+            .await,
+        Err(JsonStoreError::DuplicateUniqueFields(_))
+    ));
+
+    // once it expires, an update may take its token too
+    clock.advance(Duration::from_secs(3600));
+    store
+        .update("sessions", &json!({"id": 4, "token": "a", "user": 7}))
+        .await
+        .unwrap();
+    assert!(store.select::<Value>("sessions", 4).await.is_ok());
+    assert_eq!(store.tree_stats("sessions").await.unwrap().records, 4);
+
+    // undo takes the update back and the expired record with it
+    let undone = store.undo_last("sessions").await.unwrap();
+    assert!(undone.sequences.contains(&1), "{:?}", undone);
+    assert_eq!(store.tree_stats("sessions").await.unwrap().records, 5);
+    let d: Value = store.select("sessions", 4).await.unwrap();
+    assert_eq!(d["token"], "d");
+}
+
+#[tokio::test]
+async fn writes_still_reach_an_expired_record() {
+    let dir = ScratchDir::new("expiry-writes");
+    let clock = TestClock::new();
+    let store = store_with_sessions(&dir, &clock).await;
+    fill(&store).await;
+
+    // a later time brings b back
+    store
+        .update(
+            "sessions",
+            &json!({"id": 2, "token": "b", "user": 7, "expires_at": "2026-01-02T00:00:00Z"}),
+        )
+        .await
+        .unwrap();
+    let b: Value = store.select("sessions", 2).await.unwrap();
+    assert_eq!(b["token"], "b");
+
+    // and an expired record can be deleted like any other
+    clock.advance(Duration::from_secs(2 * 86_400));
+    store.delete("sessions", 2).await.unwrap();
+    assert_eq!(store.purge_expired("sessions").await.unwrap(), [1, 3]);
+}
@@ -0,0 +1,100 @@
+mod common;
+
+use common::{all, fixture_path, store_with_users, users, ScratchDir};
+use json_store::{export::ExportFormat, import::ImportMode, store::JsonStore};
+use serde_json::{json, Value};
+use std::fs;
+
+// a store whose users tree holds the records of the export fixture, sequences and all
+async fn store_with_fixture(dir: &ScratchDir) -> JsonStore {
+    let store = store_with_users(dir).await;
+    store
+        .import_tree(
+            "users",
+            &fixture_path("export/users.json"),
+            ImportMode::Preserve,
+        )
+        .await
+        .unwrap();
+    store
+}
+
+fn parse(path: &std::path::Path) -> Value {
+    serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn export_writes_the_fixture_back_out() {
+    let dir = ScratchDir::new("export-array");
+    let store = store_with_fixture(&dir).await;
+    let out = dir.path().join("out.json");
+
+    assert_eq!(
+        store
+            .export_tree("users", &out, ExportFormat::JsonArray)
+            .await
+            .unwrap(),
+        4
+    );
+    assert_eq!(parse(&out), parse(&fixture_path("export/users.json")));
+
+    // one record a line, in sequence order
+    let text = fs::read_to_string(&out).unwrap();
+    let lines = text.lines().collect::<Vec<_>>();
+    assert_eq!((lines[0], lines[lines.len() - 1]), ("[", "]"));
+    let ids = lines[1..lines.len() - 1]
+        .iter()
+        .map(|line| {
+            serde_json::from_str::<Value>(line.trim_end_matches(',')).unwrap()["id"].clone()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(ids, [1, 2, 4, 7]);
+}
+
+#[tokio::test]
+async fn an_exported_file_imports_into_the_same_records() {
+    let dir = ScratchDir::new("export-reimport");
+    let store = store_with_fixture(&dir).await;
+    let out = dir.path().join("out.json");
+    store
+        .export_tree("users", &out, ExportFormat::JsonArray)
+        .await
+        .unwrap();
+
+    store.create_tree("copy", users()).await.unwrap();
+    store
+        .import_tree("copy", &out, ImportMode::Preserve)
+        .await
+        .unwrap();
+    assert_eq!(all(&store, "copy").await, all(&store, "users").await);
+}
+
+#[tokio::test]
+async fn a_filter_exports_a_subset() {
+    let dir = ScratchDir::new("export-filter");
+    let store = store_with_fixture(&dir).await;
+    let out = dir.path().join("named.json");
+
+    let count = store
+        .export_tree_where("users", &out, ExportFormat::JsonArray, |record| {
+            record["name"].is_string()
+        })
+        .await
+        .unwrap();
+    assert_eq!(count, 3);
+    let names = parse(&out)
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|record| record["name"].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["Ada", "Bob, Jr.", "Dee"]);
+
+    let none = dir.path().join("none.json");
+    let count = store
+        .export_tree_where("users", &none, ExportFormat::JsonArray, |_| false)
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+    assert_eq!(parse(&none), json!([]));
+}
@@ -0,0 +1,139 @@
+mod common;
+
+use common::{all, edit, read_json, store_with_users, ScratchDir};
+use json_store::error::JsonStoreError;
+use serde_json::json;
+
+#[tokio::test]
+async fn save_refuses_to_overwrite_an_external_edit() {
+    let dir = ScratchDir::new("external-save");
+    let store = store_with_users(&dir).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+
+    let file = dir.path().join("users.json");
+    let edited = r#"{"1": {"id": 1, "email": "edited@x"}}"#;
+    edit(&file, edited);
+    store
+        .insert("users", &json!({"email": "b@x"}))
+        .await
+        .unwrap();
+
+    match store.save_tree("users").await {
+        Err(JsonStoreError::ExternallyModified { tree, path }) => {
+            assert_eq!(tree, "users");
+            assert_eq!(path, file);
+        }
+        other => panic!("expected ExternallyModified, got {:?}", other),
+    }
+    assert!(matches!(
+        store.save().await,
+        Err(JsonStoreError::SaveFailed { .. })
+    ));
+    // the edit is still there, and so are our changes
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), edited);
+    assert!(store.is_dirty("users").await.unwrap());
+    assert_eq!(all(&store, "users").await.len(), 2);
+}
+
+#[tokio::test]
+async fn reload_takes_the_edit_and_drops_unsaved_changes() {
+    let dir = ScratchDir::new("external-reload");
+    let store = store_with_users(&dir).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+
+    edit(
+        &dir.path().join("users.json"),
+        r#"{"1": {"id": 1, "email": "edited@x"}}"#,
+    );
+    store
+        .insert("users", &json!({"email": "b@x"}))
+        .await
+        .unwrap();
+
+    // taking it is asked for, as an edit reads like a damaged file
+    assert!(matches!(
+        store.reload_tree("users").await,
+        Err(JsonStoreError::ChecksumMismatch { .. })
+    ));
+    store.rehash_tree("users").await.unwrap();
+    store.reload_tree("users").await.unwrap();
+    assert!(!store.is_dirty("users").await.unwrap());
+    assert_eq!(
+        all(&store, "users").await,
+        vec![json!({"id": 1, "email": "edited@x"})]
+    );
+
+    // once reloaded, saving works again
+    store
+        .insert("users", &json!({"email": "c@x"}))
+        .await
+        .unwrap();
+    assert!(store.save_tree("users").await.unwrap());
+}
+
+#[tokio::test]
+async fn save_tree_force_overwrites_the_edit() {
+    let dir = ScratchDir::new("external-force");
+    let store = store_with_users(&dir).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+
+    let file = dir.path().join("users.json");
+    edit(&file, "{}");
+    store.save_tree_force("users").await.unwrap();
+
+    assert_eq!(read_json(&file), json!({"1": {"id": 1, "email": "a@x"}}));
+    // and the next save isn't taken for a conflict
+    store
+        .insert("users", &json!({"email": "b@x"}))
+        .await
+        .unwrap();
+    assert!(store.save_tree("users").await.unwrap());
+}
+
+#[tokio::test]
+async fn sequence_file_is_watched_too() {
+    let dir = ScratchDir::new("external-seq");
+    let store = store_with_users(&dir).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+
+    edit(&dir.path().join("users.seq"), "10");
+    store
+        .insert("users", &json!({"email": "b@x"}))
+        .await
+        .unwrap();
+    assert!(matches!(
+        store.save_tree("users").await,
+        Err(JsonStoreError::ExternallyModified { .. })
+    ));
+}
+
+#[tokio::test]
+async fn own_saves_are_not_taken_for_external_ones() {
+    let dir = ScratchDir::new("external-own");
+    let store = store_with_users(&dir).await;
+    for n in 0..5 {
+        store
+            .insert("users", &json!({"email": format!("{}@x", n)}))
+            .await
+            .unwrap();
+        assert!(store.save_tree("users").await.unwrap());
+    }
+    // nothing changed since, so nothing to write
+    assert!(!store.save_tree("users").await.unwrap());
+}
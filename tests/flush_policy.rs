@@ -0,0 +1,135 @@
+mod common;
+
+use common::{all, edit, read_json, store_with_users, ScratchDir};
+use json_store::store::{FlushPolicy, JsonStore};
+use serde_json::{json, Value};
+use std::{
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+};
+
+async fn insert(store: &JsonStore, n: usize) {
+    static NEXT: AtomicU32 = AtomicU32::new(0);
+    for _ in 0..n {
+        let i = NEXT.fetch_add(1, Ordering::Relaxed);
+        store
+            .insert("users", &json!({"email": format!("{}@x", i)}))
+            .await
+            .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn manual_is_the_default_and_never_saves() {
+    let dir = ScratchDir::new("flush-manual");
+    let store = store_with_users(&dir).await;
+    assert_eq!(store.flush_policy(), FlushPolicy::Manual);
+
+    insert(&store, 10).await;
+    assert!(store.is_dirty("users").await.unwrap());
+}
+
+#[tokio::test]
+async fn every_write_saves_each_time() {
+    let dir = ScratchDir::new("flush-every");
+    let store = store_with_users(&dir).await;
+    store.set_flush_policy(FlushPolicy::EveryWrite);
+
+    insert(&store, 1).await;
+    assert!(!store.is_dirty("users").await.unwrap());
+    store.delete("users", 1).await.unwrap();
+    assert!(!store.is_dirty("users").await.unwrap());
+}
+
+#[tokio::test]
+async fn after_writes_saves_on_the_nth_write() {
+    let dir = ScratchDir::new("flush-after");
+    let store = store_with_users(&dir).await;
+    store.set_flush_policy(FlushPolicy::AfterWrites(3));
+
+    insert(&store, 2).await;
+    assert!(store.is_dirty("users").await.unwrap());
+    insert(&store, 1).await;
+    assert!(!store.is_dirty("users").await.unwrap());
+
+    // a manual save starts the count again
+    insert(&store, 2).await;
+    store.save().await.unwrap();
+    insert(&store, 2).await;
+    assert!(store.is_dirty("users").await.unwrap());
+    insert(&store, 1).await;
+    assert!(!store.is_dirty("users").await.unwrap());
+}
+
+#[tokio::test]
+async fn interval_saves_on_a_write_once_the_last_save_is_old_enough() {
+    let dir = ScratchDir::new("flush-interval");
+    let store = store_with_users(&dir).await;
+    store.set_flush_policy(FlushPolicy::Interval(Duration::from_millis(100)));
+    store.save_tree_force("users").await.unwrap();
+
+    insert(&store, 1).await;
+    assert!(store.is_dirty("users").await.unwrap());
+
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    insert(&store, 1).await;
+    assert!(!store.is_dirty("users").await.unwrap());
+}
+
+#[tokio::test]
+async fn a_tree_policy_overrides_the_store_one() {
+    let dir = ScratchDir::new("flush-tree");
+    let store = store_with_users(&dir).await;
+    store.create_tree("other", common::users()).await.unwrap();
+    store.set_flush_policy(FlushPolicy::EveryWrite);
+    store
+        .set_tree_flush_policy("users", Some(FlushPolicy::Manual))
+        .await
+        .unwrap();
+
+    insert(&store, 1).await;
+    store
+        .insert("other", &json!({"email": "o@x"}))
+        .await
+        .unwrap();
+    assert!(store.is_dirty("users").await.unwrap());
+    assert!(!store.is_dirty("other").await.unwrap());
+
+    store.set_tree_flush_policy("users", None).await.unwrap();
+    insert(&store, 1).await;
+    assert!(!store.is_dirty("users").await.unwrap());
+
+    let reloaded = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(common::all(&reloaded, "users").await.len(), 2);
+}
+
+// the write is made whether or not the save after it goes through
+#[tokio::test]
+async fn a_failed_flush_does_not_fail_the_write() {
+    let dir = ScratchDir::new("flush-failed");
+    let store = store_with_users(&dir).await;
+    store.set_flush_policy(FlushPolicy::EveryWrite);
+    insert(&store, 1).await;
+
+    // files changed behind the store's back refuse the save
+    let file = dir.path().join("users.json");
+    edit(&file, "{}");
+    let seq = store
+        .insert("users", &json!({"email": "kept@x"}))
+        .await
+        .unwrap();
+    assert_eq!(
+        store.select::<Value>("users", seq).await.unwrap()["email"],
+        "kept@x"
+    );
+    assert!(store.is_dirty("users").await.unwrap());
+    store.delete("users", 1).await.unwrap();
+    assert_eq!(all(&store, "users").await.len(), 1);
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), "{}");
+
+    // until the conflict is settled
+    store.save_tree_force("users").await.unwrap();
+    insert(&store, 1).await;
+    assert!(!store.is_dirty("users").await.unwrap());
+    assert_eq!(read_json(&file).as_object().unwrap().len(), 2);
+}
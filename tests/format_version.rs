@@ -0,0 +1,143 @@
+mod common;
+
+use common::{all, fixture, read_json, ScratchDir};
+use json_store::{
+    checksum::ChecksumStatus,
+    error::JsonStoreError,
+    meta::FORMAT_VERSION,
+    store::{JsonStore, LoadOptions},
+};
+use serde_json::json;
+use std::{collections::BTreeMap, fs};
+
+const FILES: [&str; 5] = [
+    "infos.json",
+    "posts.json",
+    "posts.seq",
+    "users.json",
+    "users.seq",
+];
+
+fn contents(dir: &ScratchDir) -> BTreeMap<String, Vec<u8>> {
+    FILES
+        .iter()
+        .map(|name| (name.to_string(), fs::read(dir.path().join(name)).unwrap()))
+        .collect()
+}
+
+#[tokio::test]
+async fn a_v1_directory_is_upgraded_in_place() {
+    let dir = ScratchDir::new("format-upgrade");
+    fixture("format-v1", &dir);
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(
+        read_json(&dir.path().join("meta.json"))["format_version"],
+        FORMAT_VERSION
+    );
+
+    // every Info written in full
+    let infos = read_json(&dir.path().join("infos.json"));
+    assert_eq!(infos["users"]["storage"], "snapshot");
+    assert_eq!(infos["posts"]["capacity"], 1000);
+
+    // snapshots in sequence order, each with a sidecar that checks out
+    assert_eq!(
+        fs::read_to_string(dir.path().join("posts.json")).unwrap(),
+        r#"{"1":{"pid":1,"title":"first"},"2":{"pid":2,"title":"second"}}"#
+    );
+    let users = fs::read_to_string(dir.path().join("users.json")).unwrap();
+    let order = ["\"1\"", "\"3\"", "\"10\""].map(|key| users.find(key).unwrap());
+    assert!(order.is_sorted(), "{}", users);
+    let report = store.verify().await.unwrap();
+    assert!(report.is_ok());
+    assert!(
+        report.trees.values().all(|s| *s == ChecksumStatus::Ok),
+        "{:?}",
+        report
+    );
+
+    // and the data is as it was
+    assert_eq!(
+        all(&store, "users").await,
+        [
+            json!({"id": 1, "email": "ada@x", "name": "Ada"}),
+            json!({"id": 3, "email": "grace@x", "name": "Grace"}),
+            json!({"id": 10, "email": "hedy@x", "name": "Hedy"}),
+        ]
+    );
+    assert_eq!(
+        store
+            .insert("posts", &json!({"title": "third"}))
+            .await
+            .unwrap(),
+        3
+    );
+    assert!(store
+        .insert("users", &json!({"email": "ada@x"}))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn the_originals_are_backed_up_first() {
+    let dir = ScratchDir::new("format-backup");
+    fixture("format-v1", &dir);
+    let originals = contents(&dir);
+
+    JsonStore::load(dir.path()).await.unwrap();
+
+    let backup = dir.path().join(".backup-format-v1");
+    for (name, original) in &originals {
+        assert_eq!(&fs::read(backup.join(name)).unwrap(), original, "{}", name);
+    }
+
+    // an upgraded store is not upgraded, or backed up, again
+    fs::remove_dir_all(&backup).unwrap();
+    let upgraded = contents(&dir);
+    JsonStore::load(dir.path()).await.unwrap();
+    assert!(!backup.exists());
+    assert_eq!(contents(&dir), upgraded);
+}
+
+#[tokio::test]
+async fn a_read_only_load_reads_v1_without_upgrading() {
+    let dir = ScratchDir::new("format-read-only");
+    fixture("format-v1", &dir);
+    let originals = contents(&dir);
+
+    let options = LoadOptions {
+        read_only: true,
+        ..Default::default()
+    };
+    let store = JsonStore::load_with_options(dir.path(), options)
+        .await
+        .unwrap();
+    assert_eq!(all(&store, "users").await.len(), 3);
+    assert_eq!(contents(&dir), originals);
+    assert!(!dir.path().join("meta.json").exists());
+}
+
+#[tokio::test]
+async fn a_newer_version_is_refused_untouched() {
+    let dir = ScratchDir::new("format-newer");
+    fixture("format-v1", &dir);
+    let meta = json!({"format_version": FORMAT_VERSION + 1});
+    fs::write(dir.path().join("meta.json"), meta.to_string()).unwrap();
+    let originals = contents(&dir);
+
+    for read_only in [false, true] {
+        let options = LoadOptions {
+            read_only,
+            ..Default::default()
+        };
+        match JsonStore::load_with_options(dir.path(), options).await {
+            Err(JsonStoreError::UnsupportedFormatVersion { found, supported }) => {
+                assert_eq!((found, supported), (FORMAT_VERSION + 1, FORMAT_VERSION));
+            }
+            other => panic!("expected UnsupportedFormatVersion, got {:?}", other.err()),
+        }
+    }
+    assert_eq!(contents(&dir), originals);
+    assert!(!dir.path().join(".backup-format-v2").exists());
+}
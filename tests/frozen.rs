@@ -0,0 +1,317 @@
+mod common;
+
+use common::{all, edit, users, ScratchDir};
+use json_store::{
+    cold::{ArchiveDest, PruneStrategy},
+    error::JsonStoreError,
+    import::{ImportMode, OnConflict},
+    merge::MergePolicy,
+    migrations::Migration,
+    repair::RepairStrategy,
+    seed::SeedMode,
+    store::{Info, JsonStore},
+    wal::RestorePoint,
+};
+use serde_json::{json, Value};
+use std::fmt::Debug;
+
+// users with history, holding two records of which the first was updated once
+fn archive() -> Info {
+    Info::builder()
+        .sequence_field("id")
+        .unique("email", ["email"])
+        .history(3)
+        .build()
+        .unwrap()
+}
+
+async fn frozen_store(dir: &ScratchDir) -> JsonStore {
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    store.create_tree("archive", archive()).await.unwrap();
+    store.create_tree("spare", users()).await.unwrap();
+    store
+        .insert("archive", &json!({"email": "a@x", "name": "A"}))
+        .await
+        .unwrap();
+    store
+        .insert("archive", &json!({"email": "b@x", "name": "B"}))
+        .await
+        .unwrap();
+    store
+        .update("archive", &json!({"id": 1, "email": "a@x", "name": "Ann"}))
+        .await
+        .unwrap();
+    store.freeze_tree("archive").await.unwrap();
+    store
+}
+
+#[track_caller]
+fn assert_frozen<T: Debug>(what: &str, result: Result<T, JsonStoreError>) {
+    match result {
+        Err(JsonStoreError::TreeFrozen(tree)) => assert_eq!(tree, "archive", "{}", what),
+        other => panic!("{}: {:?}", what, other),
+    }
+}
+
+// Every call that would change the frozen tree's records, Info or files is tried in
+// turn; each must be refused and leave the records as they were.
+#[tokio::test]
+async fn every_write_is_refused() {
+    let dir = ScratchDir::new("frozen-writes");
+    let store = frozen_store(&dir).await;
+    let records = all(&store, "archive").await;
+    let record = json!({"id": 1, "email": "a@x", "name": "Changed"});
+    let new = json!({"email": "c@x"});
+
+    assert_frozen("insert", store.insert("archive", &new).await);
+    assert_frozen(
+        "insert_reserved",
+        store.insert_reserved("archive", 9, &new).await,
+    );
+    assert_frozen(
+        "allocate_sequences",
+        store.allocate_sequences("archive", 5).await,
+    );
+    assert_frozen("update", store.update("archive", &record).await);
+    assert_frozen(
+        "update_as",
+        store.update_as("archive", &record, "owner").await,
+    );
+    assert_frozen(
+        "merge_patch",
+        store
+            .merge_patch("archive", 1, &json!({"name": "Changed"}))
+            .await,
+    );
+    assert_frozen("delete", store.delete("archive", 2).await);
+    assert_frozen("delete_as", store.delete_as("archive", 2, "owner").await);
+    assert_frozen("revert", store.revert("archive", 1, 0).await);
+    assert_frozen("undo_last", store.undo_last("archive").await);
+    assert_frozen(
+        "set_tree_capacity",
+        store.set_tree_capacity("archive", 10).await,
+    );
+    assert_frozen("vacuum_tree", store.vacuum_tree("archive", true).await);
+    assert_frozen(
+        "repair_tree",
+        store
+            .repair_tree("archive", RepairStrategy::StartEmpty)
+            .await,
+    );
+    assert_frozen("reshard_tree", store.reshard_tree("archive", 2).await);
+    assert_frozen(
+        "restore_tree_to",
+        store
+            .restore_tree_to("archive", RestorePoint::Change(0))
+            .await,
+    );
+    assert_frozen(
+        "archive_where",
+        store
+            .archive_where("archive", |_| true, ArchiveDest::Tree("spare".to_string()))
+            .await,
+    );
+    assert_frozen(
+        "prune_to_capacity",
+        store
+            .prune_to_capacity("archive", PruneStrategy::Oldest)
+            .await,
+    );
+    assert_frozen(
+        "prune_to_capacity_archiving",
+        store
+            .prune_to_capacity_archiving(
+                "archive",
+                PruneStrategy::Oldest,
+                ArchiveDest::Tree("spare".to_string()),
+            )
+            .await,
+    );
+    assert_frozen("purge_expired", store.purge_expired("archive").await);
+    assert_frozen(
+        "seed",
+        store
+            .seed("archive", std::slice::from_ref(&new), SeedMode::Replace)
+            .await,
+    );
+    assert_frozen("drop_tree", store.drop_tree("archive").await);
+
+    let file = dir.path().join("import.json");
+    edit(&file, r#"[{"email": "c@x"}]"#);
+    assert_frozen(
+        "import_tree",
+        store
+            .import_tree("archive", &file, ImportMode::Append)
+            .await,
+    );
+    let file = dir.path().join("import.ndjson");
+    edit(&file, "{\"email\": \"c@x\"}\n");
+    assert_frozen(
+        "import_ndjson",
+        store
+            .import_ndjson("archive", &file, ImportMode::Append, OnConflict::Skip)
+            .await,
+    );
+
+    // nor through a session or a handle
+    let mut session = store.session();
+    assert_frozen("session insert", session.insert("archive", &new).await);
+    assert_frozen("session delete", session.delete("archive", 1).await);
+    session.abandon();
+    let handle = store.tree::<Value>("archive").unwrap();
+    assert_frozen("handle insert", handle.insert(&new).await);
+
+    // nor from another tree's side: archiving into it
+    assert_frozen(
+        "archive_where into it",
+        store
+            .archive_where("spare", |_| true, ArchiveDest::Tree("archive".to_string()))
+            .await,
+    );
+
+    assert_eq!(all(&store, "archive").await, records);
+    assert!(!store.is_dirty("archive").await.unwrap());
+}
+
+#[tokio::test]
+async fn migrations_and_merges_are_refused() {
+    let dir = ScratchDir::new("frozen-migrate");
+    let store = frozen_store(&dir).await;
+    let records = all(&store, "archive").await;
+    store
+        .register_migration(Migration {
+            version: 1,
+            tree: "archive".to_string(),
+            up: |record| {
+                record["migrated"] = json!(true);
+                Ok(())
+            },
+        })
+        .unwrap();
+    assert_frozen("migrate", store.migrate().await);
+
+    let other = ScratchDir::new("frozen-merge-other");
+    let source = JsonStore::load(other.path()).await.unwrap();
+    source.create_tree("archive", archive()).await.unwrap();
+    source
+        .insert("archive", &json!({"email": "z@x"}))
+        .await
+        .unwrap();
+    source.close().await.unwrap();
+    // a merge goes tree by tree, reporting those it couldn't merge
+    let report = store
+        .merge_from(other.path(), MergePolicy::PreferOther)
+        .await
+        .unwrap();
+    assert_eq!(report.failed.len(), 1);
+    let (tree, error) = report.failed.into_iter().next().unwrap();
+    assert_eq!(tree, "archive");
+    assert_frozen("merge_from", Err::<(), _>(error));
+
+    assert_eq!(all(&store, "archive").await, records);
+}
+
+#[tokio::test]
+async fn reads_and_exports_work() {
+    let dir = ScratchDir::new("frozen-reads");
+    let store = frozen_store(&dir).await;
+
+    let ann: Value = store.select("archive", 1).await.unwrap();
+    assert_eq!(ann["name"], "Ann");
+    assert_eq!(all(&store, "archive").await.len(), 2);
+    let found = store
+        .find_by_field::<Value>("archive", "email", &json!("b@x"))
+        .await
+        .unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(store.history("archive", 1).await.unwrap().len(), 1);
+
+    let file = dir.path().join("export.json");
+    assert_eq!(
+        store
+            .export_tree("archive", &file, Default::default())
+            .await
+            .unwrap(),
+        2
+    );
+
+    // indexes and metadata sit beside the records and still change
+    store.create_index("archive", "name").await.unwrap();
+    store
+        .set_tree_metadata("archive", "note", json!("sealed"))
+        .await
+        .unwrap();
+    assert_eq!(
+        store.get_info("archive").unwrap().metadata["note"],
+        "sealed"
+    );
+
+    // other trees are untouched
+    store
+        .insert("spare", &json!({"email": "c@x"}))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn freezing_saves_pending_changes_and_survives_a_reload() {
+    let dir = ScratchDir::new("frozen-reload");
+    let store = frozen_store(&dir).await;
+    assert!(store.get_info("archive").unwrap().frozen);
+    // what was unsaved when it froze is on disk
+    assert!(!store.is_dirty("archive").await.unwrap());
+    let records = all(&store, "archive").await;
+    store.close().await.unwrap();
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert!(store.get_info("archive").unwrap().frozen);
+    assert_eq!(all(&store, "archive").await, records);
+    assert_frozen(
+        "insert after reload",
+        store.insert("archive", &json!({"email": "c@x"})).await,
+    );
+
+    store.unfreeze_tree("archive").await.unwrap();
+    store.close().await.unwrap();
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert!(!store.get_info("archive").unwrap().frozen);
+    assert_eq!(
+        store
+            .insert("archive", &json!({"email": "c@x"}))
+            .await
+            .unwrap(),
+        3
+    );
+}
+
+#[tokio::test]
+async fn freezing_twice_or_thawing_a_thawed_tree_does_nothing() {
+    let dir = ScratchDir::new("frozen-twice");
+    let store = frozen_store(&dir).await;
+    store.freeze_tree("archive").await.unwrap();
+    assert!(store.get_info("archive").unwrap().frozen);
+    store.unfreeze_tree("archive").await.unwrap();
+    store.unfreeze_tree("archive").await.unwrap();
+    assert!(!store.get_info("archive").unwrap().frozen);
+
+    assert!(matches!(
+        store.freeze_tree("nothing").await,
+        Err(JsonStoreError::NotFoundTree(_))
+    ));
+}
+
+#[tokio::test]
+async fn only_a_forced_drop_removes_a_frozen_tree() {
+    let dir = ScratchDir::new("frozen-drop");
+    let store = frozen_store(&dir).await;
+    assert_frozen("drop_tree", store.drop_tree("archive").await);
+    assert!(store.has_tree("archive"));
+
+    store.drop_tree_force("archive").await.unwrap();
+    assert!(!store.has_tree("archive"));
+    assert!(!dir.path().join("archive.json").exists());
+    store.close().await.unwrap();
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert!(!store.has_tree("archive"));
+}
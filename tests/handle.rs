@@ -0,0 +1,72 @@
+mod common;
+
+use common::{store_with_users, ScratchDir};
+use json_store::{error::JsonStoreError, store::Info};
+use serde_json::{json, Value};
+
+// users again, unique by name now rather than email
+fn by_name() -> Info {
+    Info::builder()
+        .sequence_field("id")
+        .unique("name", ["name"])
+        .build()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn a_handle_follows_a_tree_made_again_under_its_name() {
+    let dir = ScratchDir::new("handle-recreated");
+    let store = store_with_users(&dir).await;
+    let handle = store.tree::<Value>("users").unwrap().with_cache(10);
+    handle
+        .insert(&json!({"email": "a@x", "name": "A"}))
+        .await
+        .unwrap();
+    handle.select_cached(1).await.unwrap();
+
+    // gone, for every call
+    store.drop_tree("users").await.unwrap();
+    assert!(matches!(
+        handle.info(),
+        Err(JsonStoreError::NotFoundTree(_))
+    ));
+    assert!(matches!(
+        handle.select_cached(1).await,
+        Err(JsonStoreError::NotFoundTree(_))
+    ));
+    assert!(matches!(
+        handle.insert(&json!({"email": "b@x"})).await,
+        Err(JsonStoreError::NotFoundTree(_))
+    ));
+
+    // and back with other constraints, which the handle's writes are held to
+    store.create_tree("users", by_name()).await.unwrap();
+    assert_eq!(handle.info().unwrap(), by_name());
+    handle
+        .insert(&json!({"email": "a@x", "name": "B"}))
+        .await
+        .unwrap();
+    handle
+        .insert(&json!({"email": "a@x", "name": "C"}))
+        .await
+        .unwrap();
+    assert!(matches!(
+        handle.insert(&json!({"email": "d@x", "name": "B"})).await,
+        Err(JsonStoreError::DuplicateUniqueFields(_))
+    ));
+    assert!(handle
+        .check_unique("email", &json!({"email": "a@x"}))
+        .await
+        .is_err());
+    assert_eq!(
+        handle
+            .check_unique("name", &json!({"name": "C"}))
+            .await
+            .unwrap(),
+        Some(2)
+    );
+
+    // nor does its cache hand out a record of the tree that went
+    let record = handle.select_cached(1).await.unwrap();
+    assert_eq!(record["name"], "B");
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use common::{all, ScratchDir};
+use json_store::{
+    error::JsonStoreError,
+    history::HistoryOp,
+    store::{Info, JsonStore},
+};
+use serde_json::{json, Value};
+
+// users keeping the last max_versions versions of each record
+async fn store_with_history(dir: &ScratchDir, max_versions: u32) -> JsonStore {
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    let info = Info::builder()
+        .sequence_field("id")
+        .unique("email", ["email"])
+        .history(max_versions)
+        .build()
+        .unwrap();
+    store.create_tree("users", info).await.unwrap();
+    store
+}
+
+// the ops and values of the record's history, newest first
+async fn versions(store: &JsonStore, sequence: u64) -> Vec<(HistoryOp, Value)> {
+    store
+        .history("users", sequence)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|entry| (entry.op, entry.value))
+        .collect()
+}
+
+fn user(n: u64) -> Value {
+    json!({"id": 1, "email": "a@x", "n": n})
+}
+
+#[tokio::test]
+async fn history_is_newest_first_and_trimmed_to_max_versions() {
+    let dir = ScratchDir::new("history-trimmed");
+    let store = store_with_history(&dir, 2).await;
+    store.insert("users", &user(1)).await.unwrap();
+    assert!(versions(&store, 1).await.is_empty());
+
+    for n in 2..=4 {
+        store.update("users", &user(n)).await.unwrap();
+    }
+    // 1 was the oldest of three, so it went
+    assert_eq!(
+        versions(&store, 1).await,
+        [(HistoryOp::Update, user(3)), (HistoryOp::Update, user(2))]
+    );
+    let history = store.history("users", 1).await.unwrap();
+    assert!(history[0].timestamp >= history[1].timestamp);
+
+    // and kept across a reload
+    store.close().await.unwrap();
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(versions(&store, 1).await.len(), 2);
+}
+
+#[tokio::test]
+async fn revert_puts_a_version_back_and_records_the_one_it_replaced() {
+    let dir = ScratchDir::new("history-revert");
+    let store = store_with_history(&dir, 5).await;
+    store.insert("users", &user(1)).await.unwrap();
+    store.update("users", &user(2)).await.unwrap();
+
+    store.revert("users", 1, 0).await.unwrap();
+    assert_eq!(store.select::<Value>("users", 1).await.unwrap(), user(1));
+    assert_eq!(
+        versions(&store, 1).await,
+        [(HistoryOp::Revert, user(2)), (HistoryOp::Update, user(1))]
+    );
+
+    // a deleted record comes back under its sequence
+    store.delete("users", 1).await.unwrap();
+    assert_eq!(versions(&store, 1).await[0], (HistoryOp::Delete, user(1)));
+    store.revert("users", 1, 1).await.unwrap();
+    assert_eq!(all(&store, "users").await, [user(2)]);
+    // with nothing replaced, nothing more is recorded
+    assert_eq!(versions(&store, 1).await.len(), 3);
+}
+
+#[tokio::test]
+async fn revert_is_held_to_the_unique_constraints() {
+    let dir = ScratchDir::new("history-revert-unique");
+    let store = store_with_history(&dir, 5).await;
+    store.insert("users", &user(1)).await.unwrap();
+    store
+        .update("users", &json!({"id": 1, "email": "b@x"}))
+        .await
+        .unwrap();
+    // a@x, free again, taken by another record
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        store.revert("users", 1, 0).await,
+        Err(JsonStoreError::DuplicateUniqueFields(_))
+    ));
+    assert_eq!(
+        all(&store, "users").await,
+        [
+            json!({"id": 1, "email": "b@x"}),
+            json!({"id": 2, "email": "a@x"}),
+        ]
+    );
+    assert_eq!(versions(&store, 1).await.len(), 1);
+}
+
+#[tokio::test]
+async fn revert_to_a_version_that_isnt_there_fails() {
+    let dir = ScratchDir::new("history-not-found");
+    let store = store_with_history(&dir, 5).await;
+    store.insert("users", &user(1)).await.unwrap();
+    store.update("users", &user(2)).await.unwrap();
+
+    for (sequence, index) in [(1, 1), (2, 0)] {
+        match store.revert("users", sequence, index).await {
+            Err(JsonStoreError::HistoryNotFound {
+                tree,
+                sequence: s,
+                index: i,
+            }) => assert_eq!((tree.as_str(), s, i), ("users", sequence, index)),
+            other => panic!("{:?}", other),
+        }
+    }
+    assert_eq!(all(&store, "users").await, [user(2)]);
+}
@@ -0,0 +1,218 @@
+#![cfg(feature = "http")]
+
+mod common;
+
+use common::{store_with_users, users, ScratchDir};
+use json_store::{http::router, store::JsonStore};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+// router(store) served on a free local port, as serve() would
+async fn start(store: JsonStore) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router(store)).await.unwrap() });
+    addr
+}
+
+// One HTTP/1.1 exchange on a fresh connection: the status and the body as JSON, null
+// if there is none.
+async fn call(addr: SocketAddr, method: &str, path: &str, body: Option<Value>) -> (u16, Value) {
+    let body = body.map(|body| body.to_string()).unwrap_or_default();
+    let request = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        method,
+        path,
+        addr,
+        body.len(),
+        body
+    );
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+
+    let (head, body) = response.split_once("\r\n\r\n").expect("a response");
+    let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+    let body = match body.is_empty() {
+        true => Value::Null,
+        false => serde_json::from_str(body).expect("a JSON body"),
+    };
+    (status, body)
+}
+
+async fn get(addr: SocketAddr, path: &str) -> (u16, Value) {
+    call(addr, "GET", path, None).await
+}
+
+// a server over users with count records, team n % 3 for the n-th
+async fn serving_users(dir: &ScratchDir, count: u64) -> (JsonStore, SocketAddr) {
+    let store = store_with_users(dir).await;
+    for n in 0..count {
+        let user = json!({"email": format!("{}@x", n), "team": n % 3});
+        store.insert("users", &user).await.unwrap();
+    }
+    let addr = start(store.clone()).await;
+    (store, addr)
+}
+
+fn ids(page: &Value) -> Vec<u64> {
+    page["records"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|record| record["id"].as_u64().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn trees_are_listed_and_created() {
+    let dir = ScratchDir::new("http_trees");
+    let (store, addr) = serving_users(&dir, 0).await;
+
+    let (status, trees) = get(addr, "/trees").await;
+    assert_eq!(status, 200);
+    assert_eq!(trees[0]["name"], "users");
+    assert_eq!(trees[0]["info"], serde_json::to_value(users()).unwrap());
+
+    let new = json!({"name": "teams", "info": users()});
+    let (status, created) = call(addr, "POST", "/trees", Some(new.clone())).await;
+    assert_eq!(status, 201);
+    assert_eq!(created["name"], "teams");
+    assert!(store.list_trees().contains(&"teams".to_string()));
+
+    let (status, error) = call(addr, "POST", "/trees", Some(new)).await;
+    assert_eq!(status, 409);
+    assert_eq!(error["kind"], "Conflict");
+}
+
+#[tokio::test]
+async fn a_record_goes_through_every_route() {
+    let dir = ScratchDir::new("http_record");
+    let (store, addr) = serving_users(&dir, 0).await;
+
+    let (status, inserted) = call(
+        addr,
+        "POST",
+        "/trees/users/records",
+        Some(json!({"email": "ada@x", "name": "Ada"})),
+    )
+    .await;
+    assert_eq!(status, 201);
+    let seq = inserted["sequence"].as_u64().unwrap();
+    assert_eq!(inserted["record"]["id"], seq);
+    let path = format!("/trees/users/records/{}", seq);
+
+    let (status, record) = get(addr, &path).await;
+    assert_eq!((status, &record["name"]), (200, &json!("Ada")));
+
+    // the path's sequence wins over the body's
+    let (status, _) = call(
+        addr,
+        "PUT",
+        &path,
+        Some(json!({"id": 99, "email": "ada@x", "name": "Ada L."})),
+    )
+    .await;
+    assert_eq!(status, 200);
+    let (status, patched) = call(addr, "PATCH", &path, Some(json!({"city": "London"}))).await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        patched,
+        json!({"id": seq, "email": "ada@x", "name": "Ada L.", "city": "London"})
+    );
+    let stored: Value = store.select("users", seq).await.unwrap();
+    assert_eq!(stored, patched);
+
+    let (status, body) = call(addr, "DELETE", &path, None).await;
+    assert_eq!((status, body), (204, Value::Null));
+    let (status, error) = get(addr, &path).await;
+    assert_eq!(status, 404);
+    assert_eq!(error["kind"], "NotFound");
+}
+
+#[tokio::test]
+async fn records_are_paged_in_sequence_order() {
+    let dir = ScratchDir::new("http_pages");
+    let (_store, addr) = serving_users(&dir, 25).await;
+
+    let (status, page) = get(addr, "/trees/users/records?offset=5&limit=10").await;
+    assert_eq!(status, 200);
+    assert_eq!(ids(&page), (6..=15).collect::<Vec<_>>());
+    assert_eq!(
+        (&page["offset"], &page["limit"], &page["total"]),
+        (&json!(5), &json!(10), &json!(25))
+    );
+
+    // the last page runs short, and past it there are none
+    let (_, page) = get(addr, "/trees/users/records?offset=20&limit=10").await;
+    assert_eq!(ids(&page), (21..=25).collect::<Vec<_>>());
+    let (_, page) = get(addr, "/trees/users/records?offset=40").await;
+    assert_eq!((ids(&page), &page["total"]), (vec![], &json!(25)));
+
+    // the default page is everything here
+    let (_, page) = get(addr, "/trees/users/records").await;
+    assert_eq!(ids(&page).len(), 25);
+    assert_eq!(page["limit"], 100);
+}
+
+#[tokio::test]
+async fn filters_page_through_the_matches_alone() {
+    let dir = ScratchDir::new("http_filters");
+    let (_store, addr) = serving_users(&dir, 25).await;
+
+    // team 1 is users 2, 5, 8, ... 23
+    let (_, page) = get(addr, "/trees/users/records?team=1&offset=2&limit=3").await;
+    assert_eq!(ids(&page), vec![8, 11, 14]);
+    assert_eq!(page["total"], 8);
+
+    // values that aren't JSON are strings, and fields that are missing null
+    let (_, page) = get(addr, "/trees/users/records?email=4@x").await;
+    assert_eq!(ids(&page), vec![5]);
+    let (_, page) = get(addr, "/trees/users/records?name=null&limit=1").await;
+    assert_eq!((ids(&page), &page["total"]), (vec![1], &json!(25)));
+}
+
+#[tokio::test]
+async fn a_page_is_no_larger_than_the_maximum() {
+    let dir = ScratchDir::new("http_max_page");
+    let (_store, addr) = serving_users(&dir, 1_005).await;
+
+    let (_, page) = get(addr, "/trees/users/records?limit=5000").await;
+    assert_eq!(page["limit"], 1_000);
+    assert_eq!(ids(&page).len(), 1_000);
+    assert_eq!(page["total"], 1_005);
+}
+
+#[tokio::test]
+async fn errors_answer_with_the_status_of_their_kind() {
+    let dir = ScratchDir::new("http_errors");
+    let (_store, addr) = serving_users(&dir, 1).await;
+
+    let (status, error) = get(addr, "/trees/missing/records").await;
+    assert_eq!((status, &error["kind"]), (404, &json!("NotFound")));
+    assert!(error["error"].as_str().unwrap().contains("missing"));
+
+    let (status, error) = get(addr, "/trees/users/records?limit=ten").await;
+    assert_eq!((status, &error["kind"]), (400, &json!("InvalidInput")));
+
+    let duplicate = json!({"email": "0@x"});
+    let (status, error) = call(addr, "POST", "/trees/users/records", Some(duplicate)).await;
+    assert_eq!((status, &error["kind"]), (409, &json!("Conflict")));
+}
+
+#[tokio::test]
+async fn save_says_whether_it_wrote() {
+    let dir = ScratchDir::new("http_save");
+    let (_store, addr) = serving_users(&dir, 3).await;
+
+    let (status, saved) = call(addr, "POST", "/trees/users/save", None).await;
+    assert_eq!((status, saved), (200, json!({"saved": true})));
+    assert!(dir.path().join("users.json").exists());
+    let (_, saved) = call(addr, "POST", "/trees/users/save", None).await;
+    assert_eq!(saved, json!({"saved": false}));
+}
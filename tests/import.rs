@@ -0,0 +1,179 @@
+mod common;
+
+use common::{all, fixture_path, store_with_users, ScratchDir};
+use json_store::{
+    import::{ImportMode, OnConflict},
+    store::{Info, JsonStore},
+};
+use serde_json::json;
+use std::path::PathBuf;
+
+fn write(dir: &ScratchDir, name: &str, contents: &str) -> PathBuf {
+    let path = dir.path().join(name);
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+async fn store_with_one(dir: &ScratchDir) -> JsonStore {
+    let store = store_with_users(dir).await;
+    store
+        .insert("users", &json!({"email": "old@x"}))
+        .await
+        .unwrap();
+    store
+}
+
+#[tokio::test]
+async fn append_gives_fresh_sequences() {
+    let dir = ScratchDir::new("import-append");
+    let store = store_with_one(&dir).await;
+
+    let report = store
+        .import_tree(
+            "users",
+            &fixture_path("export/users.json"),
+            ImportMode::Append,
+        )
+        .await
+        .unwrap();
+    assert_eq!(report.imported, [2, 3, 4, 5]);
+    assert!(report.skipped.is_empty());
+
+    let records = all(&store, "users").await;
+    assert_eq!(records[1]["email"], "ada@x");
+    assert_eq!(
+        records[4],
+        json!({"id": 5, "email": "dee@x", "name": "Dee", "score": 2.5, "active": false})
+    );
+    assert!(store.is_dirty("users").await.unwrap());
+}
+
+#[tokio::test]
+async fn preserve_keeps_sequences_and_moves_the_counter_past_them() {
+    let dir = ScratchDir::new("import-preserve");
+    let store = store_with_users(&dir).await;
+
+    let report = store
+        .import_tree(
+            "users",
+            &fixture_path("export/users.json"),
+            ImportMode::Preserve,
+        )
+        .await
+        .unwrap();
+    assert_eq!(report.imported, [1, 2, 4, 7]);
+    assert_eq!(
+        store
+            .insert("users", &json!({"email": "new@x"}))
+            .await
+            .unwrap(),
+        8
+    );
+}
+
+#[tokio::test]
+async fn fail_imports_nothing_on_the_first_problem() {
+    let dir = ScratchDir::new("import-fail");
+    let store = store_with_one(&dir).await;
+
+    // sequence 1 is taken
+    let file = write(
+        &dir,
+        "clash.json",
+        r#"[{"id": 5, "email": "a@x"}, {"id": 1, "email": "b@x"}]"#,
+    );
+    assert!(store
+        .import_tree("users", &file, ImportMode::Preserve)
+        .await
+        .is_err());
+    assert_eq!(all(&store, "users").await.len(), 1);
+
+    // and so is the email
+    let file = write(
+        &dir,
+        "unique.json",
+        r#"[{"email": "a@x"}, {"email": "old@x"}]"#,
+    );
+    assert!(store
+        .import_tree("users", &file, ImportMode::Append)
+        .await
+        .is_err());
+    assert_eq!(all(&store, "users").await.len(), 1);
+    assert_eq!(
+        store
+            .insert("users", &json!({"email": "n@x"}))
+            .await
+            .unwrap(),
+        2
+    );
+}
+
+#[tokio::test]
+async fn skip_reports_each_problem_by_index() {
+    let dir = ScratchDir::new("import-skip");
+    let store = store_with_one(&dir).await;
+    let file = write(
+        &dir,
+        "mixed.json",
+        r#"[
+            {"id": 1, "email": "taken-seq@x"},
+            {"id": 2, "email": "a@x"},
+            "not an object",
+            {"id": 3, "email": "old@x"},
+            {"id": 4, "email": "a@x"},
+            {"id": 5, "email": "b@x"}
+        ]"#,
+    );
+
+    let report = store
+        .import_tree_with("users", &file, ImportMode::Preserve, OnConflict::Skip)
+        .await
+        .unwrap();
+    assert_eq!(report.imported, [2, 5]);
+    let skipped = report
+        .skipped
+        .iter()
+        .map(|issue| issue.index)
+        .collect::<Vec<_>>();
+    assert_eq!(skipped, [0, 2, 3, 4]);
+    assert!(report.skipped.iter().all(|issue| !issue.reason.is_empty()));
+    assert_eq!(all(&store, "users").await.len(), 3);
+}
+
+#[tokio::test]
+async fn capacity_applies() {
+    let dir = ScratchDir::new("import-capacity");
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    let info = Info::builder()
+        .sequence_field("id")
+        .capacity(2)
+        .build()
+        .unwrap();
+    store.create_tree("small", info).await.unwrap();
+
+    let report = store
+        .import_tree_with(
+            "small",
+            &fixture_path("export/users.json"),
+            ImportMode::Append,
+            OnConflict::Skip,
+        )
+        .await
+        .unwrap();
+    assert_eq!(report.imported.len(), 2);
+    assert_eq!(report.skipped.len(), 2);
+    assert_eq!(all(&store, "small").await.len(), 2);
+}
+
+#[tokio::test]
+async fn a_file_that_is_not_an_array_fails() {
+    let dir = ScratchDir::new("import-not-array");
+    let store = store_with_users(&dir).await;
+    let file = write(&dir, "object.json", r#"{"1": {"email": "a@x"}}"#);
+
+    assert!(store
+        .import_tree("users", &file, ImportMode::Append)
+        .await
+        .is_err());
+    assert!(all(&store, "users").await.is_empty());
+}
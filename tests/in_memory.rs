@@ -0,0 +1,148 @@
+mod common;
+
+use common::{all, store_with_users, users, ScratchDir};
+use json_store::{
+    error::JsonStoreError,
+    store::{Info, JsonStore},
+};
+use serde_json::{json, Value};
+
+// Constraints, capacity, sequences and queries, with each step's outcome recorded
+// as text so two stores can be compared step by step.
+async fn workload(store: &JsonStore) -> Vec<String> {
+    fn show<T: std::fmt::Debug>(result: Result<T, JsonStoreError>) -> String {
+        match result {
+            Ok(value) => format!("ok {:?}", value),
+            Err(e) => format!("err {:?}", e.kind()),
+        }
+    }
+
+    let capped = Info::builder()
+        .sequence_field("id")
+        .capacity(2)
+        .build()
+        .unwrap();
+    let mut steps = vec![
+        show(store.create_tree("users", users()).await),
+        show(store.create_tree("capped", capped).await),
+        show(store.create_tree("users", users()).await),
+    ];
+    for email in ["a@x", "b@x", "a@x", "c@x"] {
+        steps.push(show(store.insert("users", &json!({"email": email})).await));
+    }
+    for n in 0..3 {
+        steps.push(show(store.insert("capped", &json!({"n": n})).await));
+    }
+    steps.push(show(
+        store
+            .update("users", &json!({"id": 2, "email": "c@x"}))
+            .await,
+    ));
+    steps.push(show(
+        store
+            .update("users", &json!({"id": 2, "email": "bb@x"}))
+            .await,
+    ));
+    steps.push(show(store.delete("users", 1).await));
+    steps.push(show(store.delete("users", 1).await));
+    steps.push(show(store.insert("users", &json!({"email": "a@x"})).await));
+    steps.push(show(store.select::<Value>("users", 2).await));
+    steps.push(show(store.select::<Value>("users", 1).await));
+    steps.push(show(
+        store
+            .select_where::<Value, _>("users", |v| v["email"].as_str() < Some("c"))
+            .await,
+    ));
+    steps.push(show(store.save().await));
+    steps.push(show(store.drop_tree("capped").await));
+    steps.push(show(store.select::<Value>("capped", 1).await));
+    steps.push(format!("{:?}", store.list_trees()));
+    steps
+}
+
+#[tokio::test]
+async fn in_memory_behaves_like_a_store_on_disk() {
+    let dir = ScratchDir::new("in-memory-same");
+    let on_disk = JsonStore::load(dir.path()).await.unwrap();
+    let in_memory = JsonStore::in_memory();
+
+    let expected = workload(&on_disk).await;
+    assert_eq!(workload(&in_memory).await, expected);
+    // the workload is not trivially all successes or all failures
+    assert!(expected.iter().any(|step| step.starts_with("ok")));
+    assert!(expected.iter().any(|step| step.starts_with("err")));
+    assert_eq!(all(&in_memory, "users").await, all(&on_disk, "users").await);
+}
+
+#[tokio::test]
+async fn saving_in_memory_leaves_no_changes_behind() {
+    let store = JsonStore::in_memory();
+    store.create_tree("users", users()).await.unwrap();
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    assert!(store.is_dirty("users").await.unwrap());
+
+    assert_eq!(store.save().await.unwrap(), ["users"]);
+    assert!(!store.is_dirty("users").await.unwrap());
+    assert!(store.unsaved_changes().await.is_empty());
+    assert!(!store.is_read_only());
+}
+
+#[tokio::test]
+async fn persist_to_writes_a_loadable_store() {
+    let dir = ScratchDir::new("in-memory-persist");
+    let dest = dir.path().join("persisted");
+    let store = JsonStore::in_memory();
+    store.create_tree("users", users()).await.unwrap();
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store
+        .insert("users", &json!({"email": "b@x"}))
+        .await
+        .unwrap();
+    store.delete("users", 1).await.unwrap();
+    // unsaved changes go along too
+    store.persist_to(&dest).await.unwrap();
+
+    let loaded = JsonStore::load(&dest).await.unwrap();
+    assert_eq!(all(&loaded, "users").await, all(&store, "users").await);
+    assert_eq!(
+        loaded
+            .insert("users", &json!({"email": "a@x"}))
+            .await
+            .unwrap(),
+        3
+    );
+    assert!(matches!(
+        loaded.insert("users", &json!({"email": "b@x"})).await,
+        Err(JsonStoreError::DuplicateUniqueFields { .. })
+    ));
+
+    // the in-memory store goes on by itself
+    store
+        .insert("users", &json!({"email": "c@x"}))
+        .await
+        .unwrap();
+    assert_eq!(all(&store, "users").await.len(), 2);
+}
+
+#[tokio::test]
+async fn persist_to_will_not_overwrite_a_store() {
+    let dir = ScratchDir::new("in-memory-persist-over");
+    store_with_users(&dir).await.save().await.unwrap();
+
+    let store = JsonStore::in_memory();
+    store.create_tree("posts", users()).await.unwrap();
+    assert!(matches!(
+        store.persist_to(dir.path()).await,
+        Err(JsonStoreError::BackupDestinationNotEmpty(_))
+    ));
+    assert_eq!(
+        JsonStore::load(dir.path()).await.unwrap().list_trees(),
+        ["users"]
+    );
+}
@@ -0,0 +1,178 @@
+mod common;
+
+use common::{all, read_json, users, ScratchDir};
+use json_store::store::{JsonStore, LoadOptions};
+use serde_json::{json, Value};
+
+fn options() -> LoadOptions {
+    LoadOptions {
+        incremental_save: Some(0.1),
+        ..Default::default()
+    }
+}
+
+async fn load(dir: &ScratchDir) -> JsonStore {
+    JsonStore::load_with_options(dir.path(), options())
+        .await
+        .unwrap()
+}
+
+// users with 100 records, saved whole
+async fn saved_store(dir: &ScratchDir) -> JsonStore {
+    let store = load(dir).await;
+    store.create_tree("users", users()).await.unwrap();
+    for n in 1..=100 {
+        store
+            .insert("users", &json!({"email": format!("{}@x", n)}))
+            .await
+            .unwrap();
+    }
+    store.save().await.unwrap();
+    assert!(!dir.path().join("users.delta.json").exists());
+    store
+}
+
+fn snapshot(dir: &ScratchDir) -> Vec<u8> {
+    std::fs::read(dir.path().join("users.json")).unwrap()
+}
+
+fn delta(dir: &ScratchDir) -> Value {
+    read_json(&dir.path().join("users.delta.json"))
+}
+
+async fn check_reload(dir: &ScratchDir, store: &JsonStore) {
+    let expected = all(store, "users").await;
+    assert_eq!(all(&load(dir).await, "users").await, expected);
+    // a store without the option reads the delta too
+    assert_eq!(
+        all(&JsonStore::load(dir.path()).await.unwrap(), "users").await,
+        expected
+    );
+}
+
+#[tokio::test]
+async fn a_small_change_goes_to_the_delta_only() {
+    let dir = ScratchDir::new("incremental-small");
+    let store = saved_store(&dir).await;
+    let before = snapshot(&dir);
+
+    store
+        .update("users", &json!({"id": 7, "email": "seven@x"}))
+        .await
+        .unwrap();
+    store.delete("users", 8).await.unwrap();
+    store.save().await.unwrap();
+
+    assert_eq!(snapshot(&dir), before);
+    assert_eq!(
+        delta(&dir),
+        json!({"7": {"id": 7, "email": "seven@x"}, "8": null})
+    );
+    check_reload(&dir, &store).await;
+}
+
+#[tokio::test]
+async fn the_delta_holds_every_save_since_the_snapshot() {
+    let dir = ScratchDir::new("incremental-accumulate");
+    let store = saved_store(&dir).await;
+
+    store.delete("users", 1).await.unwrap();
+    store.save().await.unwrap();
+    store
+        .insert("users", &json!({"email": "101@x"}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+
+    assert_eq!(
+        delta(&dir),
+        json!({"1": null, "101": {"id": 101, "email": "101@x"}})
+    );
+    // and a store loaded from them carries on where they left off
+    let reloaded = load(&dir).await;
+    reloaded
+        .update("users", &json!({"id": 2, "email": "two@x"}))
+        .await
+        .unwrap();
+    reloaded.save().await.unwrap();
+    assert_eq!(delta(&dir).as_object().unwrap().len(), 3);
+    check_reload(&dir, &reloaded).await;
+}
+
+#[tokio::test]
+async fn a_record_deleted_then_put_back_is_in_the_delta() {
+    let dir = ScratchDir::new("incremental-reinsert");
+    let store = saved_store(&dir).await;
+
+    store.delete("users", 5).await.unwrap();
+    store.save().await.unwrap();
+    assert_eq!(delta(&dir)["5"], Value::Null);
+
+    // undo puts the record back under its old sequence
+    store.undo_last("users").await.unwrap();
+    store.save().await.unwrap();
+    assert_eq!(delta(&dir)["5"], json!({"id": 5, "email": "5@x"}));
+    check_reload(&dir, &store).await;
+
+    // both within one save as well
+    store.delete("users", 6).await.unwrap();
+    store.undo_last("users").await.unwrap();
+    store.save().await.unwrap();
+    let reloaded = load(&dir).await;
+    assert_eq!(
+        reloaded.select::<Value>("users", 6).await.unwrap(),
+        json!({"id": 6, "email": "6@x"})
+    );
+}
+
+#[tokio::test]
+async fn past_the_threshold_the_snapshot_is_consolidated() {
+    let dir = ScratchDir::new("incremental-consolidate");
+    let store = saved_store(&dir).await;
+
+    for seq in 1..=5 {
+        store.delete("users", seq).await.unwrap();
+    }
+    store.save().await.unwrap();
+    assert!(dir.path().join("users.delta.json").exists());
+
+    // 5 more changes make 10 of 95 records, over a tenth
+    for seq in 6..=11 {
+        store
+            .update("users", &json!({"id": seq, "email": format!("n{}@x", seq)}))
+            .await
+            .unwrap();
+    }
+    store.save().await.unwrap();
+
+    assert!(!dir.path().join("users.delta.json").exists());
+    let file = read_json(&dir.path().join("users.json"));
+    assert_eq!(file.as_object().unwrap().len(), 95);
+    assert!(file.get("1").is_none());
+    assert_eq!(file["11"]["email"], "n11@x");
+    check_reload(&dir, &store).await;
+
+    // after which small changes go to a fresh delta
+    store.delete("users", 50).await.unwrap();
+    store.save().await.unwrap();
+    assert_eq!(delta(&dir), json!({"50": null}));
+}
+
+#[tokio::test]
+async fn without_the_option_every_save_is_whole() {
+    let dir = ScratchDir::new("incremental-off");
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    store.create_tree("users", users()).await.unwrap();
+    for n in 1..=20 {
+        store
+            .insert("users", &json!({"email": format!("{}@x", n)}))
+            .await
+            .unwrap();
+    }
+    store.save().await.unwrap();
+    store.delete("users", 3).await.unwrap();
+    store.save().await.unwrap();
+
+    assert!(!dir.path().join("users.delta.json").exists());
+    assert!(read_json(&dir.path().join("users.json")).get("3").is_none());
+}
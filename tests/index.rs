@@ -0,0 +1,125 @@
+mod common;
+
+use common::{users, ScratchDir};
+use json_store::{
+    index::SortOrder,
+    store::{JsonStore, LoadOptions},
+};
+use serde_json::{json, Value};
+
+// with metrics, so records_scanned tells whether a query went by an index
+async fn load(dir: &ScratchDir) -> JsonStore {
+    let options = LoadOptions {
+        metrics: true,
+        ..Default::default()
+    };
+    JsonStore::load_with_options(dir.path(), options)
+        .await
+        .unwrap()
+}
+
+// users 1 to 9 in teams 1 to 3 by turn, team indexed
+async fn store_with_teams(dir: &ScratchDir) -> JsonStore {
+    let store = load(dir).await;
+    store.create_tree("users", users()).await.unwrap();
+    store.create_index("users", "team").await.unwrap();
+    for n in 0..9 {
+        store
+            .insert(
+                "users",
+                &json!({"email": format!("{}@x", n), "team": n % 3 + 1, "age": n}),
+            )
+            .await
+            .unwrap();
+    }
+    store
+}
+
+async fn team(store: &JsonStore, team: Value) -> Vec<u64> {
+    store
+        .find_by_field::<Value>("users", "team", &team)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|record| record["id"].as_u64().unwrap())
+        .collect()
+}
+
+fn scanned(store: &JsonStore) -> u64 {
+    store.metrics().unwrap().trees["users"].records_scanned
+}
+
+#[tokio::test]
+async fn lookups_follow_updates_and_deletes() {
+    let dir = ScratchDir::new("index-postings");
+    let store = store_with_teams(&dir).await;
+    assert_eq!(team(&store, json!(1)).await, [1, 4, 7]);
+
+    // 4 moves to team 2, and is found there only
+    store
+        .update("users", &json!({"id": 4, "email": "3@x", "team": 2}))
+        .await
+        .unwrap();
+    assert_eq!(team(&store, json!(1)).await, [1, 7]);
+    assert_eq!(team(&store, json!(2)).await, [2, 4, 5, 8]);
+
+    // 7 leaves its team, counting as null after
+    store
+        .update("users", &json!({"id": 7, "email": "6@x"}))
+        .await
+        .unwrap();
+    assert_eq!(team(&store, json!(1)).await, [1]);
+    assert_eq!(team(&store, Value::Null).await, [7]);
+
+    // 1 and 7 go altogether
+    store.delete("users", 1).await.unwrap();
+    store.delete("users", 7).await.unwrap();
+    assert!(team(&store, json!(1)).await.is_empty());
+    assert!(team(&store, Value::Null).await.is_empty());
+    assert_eq!(scanned(&store), 0);
+
+    // the index is built again at the next load, from the records as they are
+    store.close().await.unwrap();
+    let store = load(&dir).await;
+    assert_eq!(team(&store, json!(2)).await, [2, 4, 5, 8]);
+    assert_eq!(team(&store, json!(3)).await, [3, 6, 9]);
+    assert_eq!(scanned(&store), 0);
+}
+
+#[tokio::test]
+async fn a_field_without_an_index_is_scanned() {
+    let dir = ScratchDir::new("index-scanned");
+    let store = store_with_teams(&dir).await;
+
+    team(&store, json!(1)).await;
+    assert_eq!(scanned(&store), 0);
+    let found = store
+        .find_by_field::<Value>("users", "age", &json!(4))
+        .await
+        .unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(scanned(&store), 9);
+
+    // and each ordered query over it in turn, until it has an ordered index
+    store
+        .find_range::<Value, _>("users", "age", json!(2)..json!(5))
+        .await
+        .unwrap();
+    store
+        .find_sorted::<Value>("users", "age", SortOrder::Descending, 2)
+        .await
+        .unwrap();
+    assert_eq!(scanned(&store), 27);
+    store.create_index_ordered("users", "age").await.unwrap();
+    let range = store
+        .find_range::<Value, _>("users", "age", json!(2)..json!(5))
+        .await
+        .unwrap();
+    assert_eq!(range.len(), 3);
+    let oldest = store
+        .find_sorted::<Value>("users", "age", SortOrder::Descending, 2)
+        .await
+        .unwrap();
+    assert_eq!(oldest[0]["age"], 8);
+    assert_eq!(scanned(&store), 27);
+}
@@ -0,0 +1,349 @@
+mod common;
+
+use common::{all, ScratchDir};
+use json_store::{
+    schema::SchemaOptions,
+    store::{Info, JsonStore},
+};
+use serde_json::{json, Map, Value};
+
+// There's no validator in the crate, so this checks the keywords infer_schema writes
+// (type, properties, required, items, format) the way 2020-12 reads them, and takes
+// x-minimum and x-maximum as the annotations they are. Where value doesn't validate,
+// the answer says where it first fails.
+fn validate(schema: &Value, value: &Value, path: &str) -> Result<(), String> {
+    let schema = match schema {
+        Value::Bool(true) => return Ok(()),
+        Value::Object(schema) => schema,
+        other => return Err(format!("{}: schema {}", path, other)),
+    };
+    if let Some(types) = schema.get("type") {
+        let types = match types {
+            Value::Array(types) => types.iter().map(|t| t.as_str().unwrap()).collect(),
+            t => vec![t.as_str().unwrap()],
+        };
+        if !types.iter().any(|t| is_type(t, value)) {
+            return Err(format!("{}: {} is no {:?}", path, value, types));
+        }
+    }
+    match value {
+        Value::Object(map) => validate_object(schema, map, path),
+        Value::Array(items) => match schema.get("items") {
+            Some(item_schema) => items
+                .iter()
+                .enumerate()
+                .try_for_each(|(n, item)| validate(item_schema, item, &format!("{}/{}", path, n))),
+            None => Ok(()),
+        },
+        Value::String(s) => match schema.get("format").and_then(Value::as_str) {
+            Some("date-time") if !is_date_time(s) => {
+                Err(format!("{}: {} is no date-time", path, s))
+            }
+            Some("uuid") if !is_uuid(s) => Err(format!("{}: {} is no uuid", path, s)),
+            _ => Ok(()),
+        },
+        _ => Ok(()),
+    }
+}
+
+fn validate_object(
+    schema: &Map<String, Value>,
+    map: &Map<String, Value>,
+    path: &str,
+) -> Result<(), String> {
+    let required = schema.get("required").and_then(Value::as_array);
+    for field in required.into_iter().flatten() {
+        let field = field.as_str().unwrap();
+        if !map.contains_key(field) {
+            return Err(format!("{}: no {}", path, field));
+        }
+    }
+    let properties = schema.get("properties").and_then(Value::as_object);
+    for (field, value) in map {
+        if let Some(property) = properties.and_then(|properties| properties.get(field)) {
+            validate(property, value, &format!("{}/{}", path, field))?;
+        }
+    }
+    Ok(())
+}
+
+fn is_type(t: &str, value: &Value) -> bool {
+    match (t, value) {
+        ("integer", Value::Number(n)) => n.is_i64() || n.is_u64(),
+        ("number", Value::Number(_))
+        | ("null", Value::Null)
+        | ("boolean", Value::Bool(_))
+        | ("string", Value::String(_))
+        | ("object", Value::Object(_))
+        | ("array", Value::Array(_)) => true,
+        _ => false,
+    }
+}
+
+// close enough for the strings here
+fn is_date_time(s: &str) -> bool {
+    s.len() >= 20 && s.as_bytes()[10] == b'T' && (s.ends_with('Z') || s.contains('+'))
+}
+
+fn is_uuid(s: &str) -> bool {
+    s.len() == 36 && s.split('-').map(str::len).eq([8, 4, 4, 4, 12])
+}
+
+#[track_caller]
+fn assert_valid(schema: &Value, record: &Value) {
+    if let Err(e) = validate(schema, record, "") {
+        panic!("{} against {}", e, schema);
+    }
+}
+
+// orders with something of every kind: fields some records don't have, nulls, mixed
+// types, date-times and uuids, nested objects and arrays of objects
+fn orders() -> Vec<Value> {
+    vec![
+        json!({
+            "ref": "2f1d4c3e-8a7b-4c6d-9e0f-1a2b3c4d5e6f",
+            "placed": "2026-03-01T10:00:00Z",
+            "total": 12,
+            "paid": true,
+            "note": null,
+            "customer": {"name": "Ann", "address": {"city": "Oslo", "zip": "0150"}},
+            "lines": [{"sku": "a", "qty": 1}, {"sku": "b", "qty": 2, "gift": true}],
+            "tags": ["new"],
+        }),
+        json!({
+            "ref": "0A1B2C3D-4E5F-6A7B-8C9D-0E1F2A3B4C5D",
+            "placed": "2026-03-02T11:30:00+02:00",
+            "total": 7.5,
+            "paid": false,
+            "customer": {"name": "Bo", "address": {"city": "Rome", "zip": 100}},
+            "lines": [{"sku": "c", "qty": 3}],
+            "tags": [],
+        }),
+        json!({
+            "ref": "4c6d9e0f-1a2b-3c4d-5e6f-2f1d4c3e8a7b",
+            "placed": "2026-03-03T09:15:00Z",
+            "total": -3,
+            "paid": null,
+            "note": "leave at the door",
+            "customer": {"name": "Cy"},
+            "lines": [],
+            "tags": ["vip", 1],
+        }),
+    ]
+}
+
+fn info() -> Info {
+    Info::builder().sequence_field("id").build().unwrap()
+}
+
+async fn store_with_orders(dir: &ScratchDir, info: Info) -> JsonStore {
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    store.create_tree("orders", info).await.unwrap();
+    for order in orders() {
+        store.insert("orders", &order).await.unwrap();
+    }
+    store
+}
+
+#[tokio::test]
+async fn every_record_validates_against_the_inferred_schema() {
+    let dir = ScratchDir::new("schema-roundtrip");
+    let store = store_with_orders(&dir, info()).await;
+    let schema = store.infer_schema("orders").await.unwrap();
+
+    assert_eq!(
+        schema["$schema"],
+        "https://json-schema.org/draft/2020-12/schema"
+    );
+    assert_eq!(schema["title"], "orders");
+    for record in all(&store, "orders").await {
+        assert_valid(&schema, &record);
+    }
+
+    // and not because it lets anything through: each of these is the first order
+    // with one thing it never had
+    let mut order = orders().remove(0);
+    order["id"] = json!(4);
+    for (pointer, value) in [
+        ("/id", json!("4")),
+        ("/id", json!(4.5)),
+        ("/ref", json!("not-a-uuid")),
+        ("/placed", json!("yesterday")),
+        ("/total", json!("12")),
+        ("/paid", json!("yes")),
+        ("/customer", json!(null)),
+        ("/customer/address/city", json!(1)),
+        ("/lines/0/qty", json!("one")),
+        ("/lines/1", json!("c")),
+        ("/tags/0", json!(false)),
+    ] {
+        let mut bad = order.clone();
+        *bad.pointer_mut(pointer).unwrap() = value;
+        assert!(validate(&schema, &bad, "").is_err(), "{} {}", pointer, bad);
+    }
+    for field in ["ref", "customer", "lines"] {
+        let mut bad = order.clone();
+        bad.as_object_mut().unwrap().remove(field);
+        assert!(validate(&schema, &bad, "").is_err(), "no {}", field);
+    }
+}
+
+#[tokio::test]
+async fn the_schema_says_what_the_records_did() {
+    let dir = ScratchDir::new("schema-pieces");
+    let store = store_with_orders(&dir, info()).await;
+    let schema = store.infer_schema("orders").await.unwrap();
+    let fields = &schema["properties"];
+
+    assert_eq!(schema["type"], "object");
+    assert_eq!(
+        schema["required"],
+        json!(["customer", "id", "lines", "paid", "placed", "ref", "tags", "total"])
+    );
+    assert_eq!(
+        fields["id"],
+        json!({"type": "integer", "x-minimum": 1, "x-maximum": 3})
+    );
+    assert_eq!(fields["ref"], json!({"type": "string", "format": "uuid"}));
+    assert_eq!(
+        fields["placed"],
+        json!({"type": "string", "format": "date-time"})
+    );
+    // one fraction among them makes them numbers, not integers
+    assert_eq!(
+        fields["total"],
+        json!({"type": "number", "x-minimum": -3, "x-maximum": 12})
+    );
+    assert_eq!(fields["paid"], json!({"type": ["boolean", "null"]}));
+    // present, even as null, doesn't make it required where it's missing
+    assert_eq!(fields["note"], json!({"type": ["string", "null"]}));
+
+    let customer = &fields["customer"];
+    assert_eq!(customer["required"], json!(["name"]));
+    assert_eq!(
+        customer["properties"]["address"]["properties"]["zip"],
+        json!({"type": ["string", "integer"], "x-minimum": 100, "x-maximum": 100})
+    );
+    // the items of all three arrays, so gift is in one of three lines
+    let line = &fields["lines"]["items"];
+    assert_eq!(line["type"], "object");
+    assert_eq!(line["required"], json!(["qty", "sku"]));
+    assert_eq!(line["properties"]["gift"], json!({"type": "boolean"}));
+    assert_eq!(
+        fields["tags"]["items"],
+        json!({"type": ["string", "integer"], "x-minimum": 1, "x-maximum": 1})
+    );
+}
+
+#[tokio::test]
+async fn depth_stops_the_description() {
+    let dir = ScratchDir::new("schema-depth");
+    let store = store_with_orders(&dir, info()).await;
+
+    // the records' fields and no further
+    let schema = store
+        .infer_schema_with("orders", SchemaOptions { depth: 1 })
+        .await
+        .unwrap();
+    let fields = &schema["properties"];
+    assert_eq!(fields["customer"], json!({"type": "object"}));
+    assert_eq!(fields["lines"], json!({"type": "array"}));
+    assert_eq!(fields["ref"]["format"], "uuid");
+
+    let schema = store
+        .infer_schema_with("orders", SchemaOptions { depth: 2 })
+        .await
+        .unwrap();
+    let customer = &schema["properties"]["customer"];
+    assert_eq!(customer["properties"]["address"], json!({"type": "object"}));
+    assert_eq!(customer["required"], json!(["name"]));
+    // an array's items are a level below it
+    assert_eq!(
+        schema["properties"]["lines"]["items"],
+        json!({"type": "object"})
+    );
+
+    // each is still one every record validates against
+    for depth in 0..4 {
+        let options = SchemaOptions { depth };
+        let schema = store.infer_schema_with("orders", options).await.unwrap();
+        for record in all(&store, "orders").await {
+            assert_valid(&schema, &record);
+        }
+    }
+    let schema = store
+        .infer_schema_with("orders", SchemaOptions { depth: 0 })
+        .await
+        .unwrap();
+    assert!(schema.get("properties").is_none(), "{}", schema);
+}
+
+#[tokio::test]
+async fn an_empty_tree_describes_nothing() {
+    let dir = ScratchDir::new("schema-empty");
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    store.create_tree("orders", info()).await.unwrap();
+    assert_eq!(
+        store.infer_schema("orders").await.unwrap(),
+        json!({"$schema": "https://json-schema.org/draft/2020-12/schema", "title": "orders"})
+    );
+    assert!(store.infer_schema("nothing").await.is_err());
+}
+
+#[tokio::test]
+async fn expired_records_are_left_out() {
+    let dir = ScratchDir::new("schema-expired");
+    let info = Info::builder()
+        .sequence_field("id")
+        .expiry_field("expires_at")
+        .build()
+        .unwrap();
+    let store = store_with_orders(&dir, info).await;
+    store
+        .insert(
+            "orders",
+            &json!({"ref": 1, "secret": true, "expires_at": "2000-01-01T00:00:00Z"}),
+        )
+        .await
+        .unwrap();
+
+    let schema = store.infer_schema("orders").await.unwrap();
+    assert!(schema["properties"].get("secret").is_none(), "{}", schema);
+    assert_eq!(schema["properties"]["ref"]["type"], "string");
+    assert_eq!(schema["properties"]["id"]["x-maximum"], 3);
+}
+
+// a tree keeping only some records in memory describes the ones spilled too
+#[tokio::test]
+async fn spilled_records_are_described() {
+    let dir = ScratchDir::new("schema-spill");
+    let limited = Info::builder()
+        .sequence_field("id")
+        .resident_limit(2)
+        .build()
+        .unwrap();
+    let store = store_with_orders(&dir, limited).await;
+    for n in 0..20 {
+        store
+            .insert(
+                "orders",
+                &json!({"ref": format!("extra {}", n), "total": n}),
+            )
+            .await
+            .unwrap();
+    }
+
+    let whole = ScratchDir::new("schema-spill-whole");
+    let expected = store_with_orders(&whole, info()).await;
+    for mut record in all(&store, "orders").await.into_iter().skip(3) {
+        record.as_object_mut().unwrap().remove("id");
+        expected.insert("orders", &record).await.unwrap();
+    }
+    let expected = expected.infer_schema("orders").await.unwrap();
+    assert_eq!(expected["properties"]["ref"], json!({"type": "string"}));
+
+    assert_eq!(store.infer_schema("orders").await.unwrap(), expected);
+    store.close().await.unwrap();
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(store.infer_schema("orders").await.unwrap(), expected);
+}
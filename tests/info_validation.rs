@@ -0,0 +1,268 @@
+mod common;
+
+use common::{all, read_json, store_with_users, users, ScratchDir};
+use json_store::{
+    error::{InfoValidationError as Invalid, JsonStoreError},
+    repair::{CorruptionPolicy, TreeOutcome},
+    store::{Info, JsonStore, LoadOptions},
+};
+use serde_json::json;
+use std::collections::HashMap;
+
+// an Info put together by hand, as one read from JSON or built without the builder
+fn info(sequence_field: &str, constraints: &[(&str, &[&str])], capacity: u32) -> Info {
+    let unique_fields = constraints
+        .iter()
+        .map(|(name, fields)| {
+            let fields = fields.iter().map(|f| f.to_string()).collect();
+            (name.to_string(), fields)
+        })
+        .collect::<HashMap<_, _>>();
+    Info::new(sequence_field.to_string(), unique_fields, capacity)
+}
+
+fn constraint(name: &str) -> String {
+    name.to_string()
+}
+
+// each shape an Info can't have, with the rule it breaks
+fn invalid() -> Vec<(Info, Invalid)> {
+    vec![
+        (info("", &[], 10), Invalid::EmptySequenceField),
+        (info("id", &[], 0), Invalid::ZeroCapacity),
+        (
+            info("id", &[("email", &[])], 10),
+            Invalid::EmptyConstraint {
+                constraint: constraint("email"),
+            },
+        ),
+        (
+            info("id", &[("email", &["email", ""])], 10),
+            Invalid::EmptyConstraintField {
+                constraint: constraint("email"),
+            },
+        ),
+        (
+            info("id", &[("by_id", &["id"])], 10),
+            Invalid::ConstraintHasSequenceField {
+                constraint: constraint("by_id"),
+                field: "id".to_string(),
+            },
+        ),
+        (
+            info("id", &[("handle", &["realm", "id", "handle"])], 10),
+            Invalid::ConstraintHasSequenceField {
+                constraint: constraint("handle"),
+                field: "id".to_string(),
+            },
+        ),
+        (
+            info("id", &[("handle", &["realm", "handle", "realm"])], 10),
+            Invalid::DuplicateConstraintField {
+                constraint: constraint("handle"),
+                field: "realm".to_string(),
+            },
+        ),
+        // among good constraints, the bad one is named
+        (
+            info("id", &[("a", &["a"]), ("b", &[]), ("c", &["c"])], 10),
+            Invalid::EmptyConstraint {
+                constraint: constraint("b"),
+            },
+        ),
+    ]
+}
+
+#[test]
+fn each_rule_is_reported_with_its_constraint() {
+    for (info, expected) in invalid() {
+        assert_eq!(info.validate(), Err(expected.clone()), "{:?}", info);
+        let name = expected.constraint();
+        if let Some(name) = name {
+            assert!(expected.to_string().contains(name), "{}", expected);
+        }
+    }
+    // the same constraint is named however the map is ordered
+    let two = info("id", &[("x", &[]), ("y", &["y", "y"])], 10);
+    for _ in 0..10 {
+        assert_eq!(two.validate().unwrap_err().constraint(), Some("x"));
+    }
+
+    for valid in [
+        info("id", &[], 1),
+        info("id", &[("email", &["email"])], u32::MAX),
+        info("id", &[("handle", &["realm", "handle"]), ("n", &["n"])], 10),
+        // the same field in two constraints is two constraints
+        info("id", &[("a", &["a"]), ("ab", &["a", "b"])], 10),
+        users(),
+    ] {
+        assert_eq!(valid.validate(), Ok(()), "{:?}", valid);
+    }
+}
+
+#[tokio::test]
+async fn create_and_ensure_refuse_an_invalid_info() {
+    let dir = ScratchDir::new("info-create");
+    let store = JsonStore::load(dir.path()).await.unwrap();
+
+    for (info, expected) in invalid() {
+        for result in [
+            store.create_tree("t", info.clone()).await,
+            store.ensure_tree("t", info.clone()).await,
+        ] {
+            match result {
+                Err(JsonStoreError::InvalidTreeInfo { tree, source }) => {
+                    assert_eq!(tree, "t");
+                    assert_eq!(source, expected);
+                }
+                other => panic!("{:?}: {:?}", info, other),
+            }
+        }
+        assert!(!store.has_tree("t"));
+    }
+    store.close().await.unwrap();
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert!(store.list_trees().is_empty());
+}
+
+#[tokio::test]
+async fn the_builder_keeps_the_same_rules() {
+    let builder = || Info::builder().sequence_field("id");
+    for built in [
+        Info::builder().build(),
+        builder().capacity(0).build(),
+        builder().unique("email", Vec::<String>::new()).build(),
+        builder().unique("email", ["email", ""]).build(),
+        builder().unique("by_id", ["id"]).build(),
+        builder().unique("handle", ["handle", "handle"]).build(),
+    ] {
+        assert!(
+            matches!(built, Err(JsonStoreError::InvalidInfo(_))),
+            "{:?}",
+            built
+        );
+    }
+}
+
+// a built Info is the one Info::new gives for the same fields, down to what's written
+#[test]
+fn the_builder_gives_what_new_does() {
+    let cases = [
+        (
+            Info::builder().sequence_field("id"),
+            info("id", &[], u32::MAX),
+        ),
+        (
+            Info::builder()
+                .sequence_field("id")
+                .unique("email", ["email"])
+                .unique("handle", ["realm", "handle"])
+                .capacity(10),
+            info(
+                "id",
+                &[("email", &["email"]), ("handle", &["realm", "handle"])],
+                10,
+            ),
+        ),
+    ];
+    for (builder, expected) in cases {
+        let built = builder.build().unwrap();
+        assert_eq!(
+            serde_json::to_value(&built).unwrap(),
+            serde_json::to_value(&expected).unwrap()
+        );
+        assert_eq!(built, expected);
+    }
+}
+
+#[tokio::test]
+async fn capacity_cannot_be_set_to_zero() {
+    let dir = ScratchDir::new("info-capacity");
+    let store = store_with_users(&dir).await;
+    assert!(matches!(
+        store.set_tree_capacity("users", 0).await,
+        Err(JsonStoreError::InvalidTreeInfo {
+            source: Invalid::ZeroCapacity,
+            ..
+        })
+    ));
+    store.set_tree_capacity("users", 1).await.unwrap();
+}
+
+// a store whose infos.json gives teams a constraint on its sequence field, as one
+// edited by hand might, besides a healthy users
+async fn store_with_invalid_info(dir: &ScratchDir) {
+    let store = store_with_users(dir).await;
+    store.create_tree("teams", users()).await.unwrap();
+    for tname in ["users", "teams"] {
+        store.insert(tname, &json!({"email": "a@x"})).await.unwrap();
+    }
+    store.close().await.unwrap();
+
+    let path = dir.path().join("infos.json");
+    let mut infos = read_json(&path);
+    infos["teams"]["unique_fields"]["by_id"] = json!(["id"]);
+    std::fs::write(&path, infos.to_string()).unwrap();
+}
+
+async fn load(dir: &ScratchDir, policy: CorruptionPolicy) -> Result<JsonStore, JsonStoreError> {
+    let options = LoadOptions {
+        corruption_policy: policy,
+        ..Default::default()
+    };
+    JsonStore::load_with_options(dir.path(), options).await
+}
+
+#[tokio::test]
+async fn an_invalid_stored_info_fails_a_strict_load() {
+    let dir = ScratchDir::new("info-load-fail");
+    store_with_invalid_info(&dir).await;
+
+    match load(&dir, CorruptionPolicy::Fail).await {
+        Err(JsonStoreError::InvalidTreeInfo { tree, source }) => {
+            assert_eq!(tree, "teams");
+            assert_eq!(source.constraint(), Some("by_id"));
+        }
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn a_lenient_load_sets_the_tree_aside() {
+    for policy in [CorruptionPolicy::Skip, CorruptionPolicy::Report] {
+        let dir = ScratchDir::new("info-load-skip");
+        store_with_invalid_info(&dir).await;
+        let store = load(&dir, policy).await.unwrap();
+
+        // a report names it and the rule, and the rest of the store works
+        if policy == CorruptionPolicy::Report {
+            let report = store.load_report().unwrap();
+            assert_eq!(report.corrupt(), ["teams"]);
+            assert_eq!(report.trees["users"], TreeOutcome::Loaded);
+            let TreeOutcome::Corrupt(reason) = &report.trees["teams"] else {
+                panic!("{:?}", report);
+            };
+            assert!(reason.contains("by_id"), "{}", reason);
+        } else {
+            assert!(store.load_report().is_none());
+        }
+        assert!(matches!(
+            store.insert("teams", &json!({"email": "b@x"})).await,
+            Err(JsonStoreError::TreeCorrupt { .. })
+        ));
+        assert_eq!(all(&store, "users").await.len(), 1);
+        store
+            .insert("users", &json!({"email": "b@x"}))
+            .await
+            .unwrap();
+        store.save().await.unwrap();
+
+        // and it can be dropped, and made again with a valid info
+        store.drop_tree("teams").await.unwrap();
+        store.create_tree("teams", users()).await.unwrap();
+        store.close().await.unwrap();
+        let store = load(&dir, CorruptionPolicy::Fail).await.unwrap();
+        assert_eq!(all(&store, "users").await.len(), 2);
+        assert!(all(&store, "teams").await.is_empty());
+    }
+}
@@ -0,0 +1,310 @@
+mod common;
+
+use common::{all, hand_edit, ScratchDir};
+use json_store::{
+    error::{ErrorKind, JsonStoreError},
+    integrity::{TreeIntegrityReport, Violation},
+    repair::{CorruptionPolicy, TreeOutcome},
+    store::{Info, JsonStore, LoadOptions},
+};
+use serde_json::{json, Value};
+
+// Write tname's files by hand, records by the sequence they're stored under and the
+// counter, over those of a tree the store made.
+async fn written(
+    dir: &ScratchDir,
+    tname: &str,
+    info: Info,
+    records: &[(u64, Value)],
+    counter: u64,
+) {
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    store.ensure_tree(tname, info).await.unwrap();
+    store.insert(tname, &json!({})).await.unwrap();
+    store.close().await.unwrap();
+
+    let records = records
+        .iter()
+        .map(|(seq, record)| (seq.to_string(), record.clone()))
+        .collect::<serde_json::Map<_, _>>();
+    let dir = dir.path();
+    hand_edit(&dir.join(format!("{}.seq", tname)), &counter.to_string());
+    hand_edit(
+        &dir.join(format!("{}.json", tname)),
+        &json!(records).to_string(),
+    );
+}
+
+// users by "id" with email unique, all in memory: load only checks the records it
+// keeps there (see integrity.rs)
+fn users() -> Info {
+    Info::builder()
+        .sequence_field("id")
+        .unique("email", ["email"])
+        .build()
+        .unwrap()
+}
+
+fn user(id: u64, email: &str) -> (u64, Value) {
+    (id, json!({"id": id, "email": email}))
+}
+
+// users with every kind of violation load can find: 3 and 4 share an email, the
+// record at 5 says it is 6, and the one at 7 has no id at all
+fn broken() -> Vec<(u64, Value)> {
+    vec![
+        user(1, "a@x"),
+        user(3, "dup@x"),
+        user(4, "dup@x"),
+        (5, json!({"id": 6, "email": "e@x"})),
+        (7, json!({"email": "g@x"})),
+    ]
+}
+
+fn broken_report() -> TreeIntegrityReport {
+    TreeIntegrityReport {
+        records: 5,
+        violations: vec![
+            Violation::SequenceMismatch {
+                sequence: 5,
+                found: json!(6),
+            },
+            Violation::SequenceMismatch {
+                sequence: 7,
+                found: Value::Null,
+            },
+            Violation::DuplicateUnique {
+                constraint: Some("email".to_string()),
+                values: vec![json!("dup@x")],
+                sequences: vec![3, 4],
+            },
+        ],
+    }
+}
+
+async fn load(dir: &ScratchDir, policy: CorruptionPolicy) -> Result<JsonStore, JsonStoreError> {
+    let options = LoadOptions {
+        corruption_policy: policy,
+        ..Default::default()
+    };
+    JsonStore::load_with_options(dir.path(), options).await
+}
+
+#[tokio::test]
+async fn a_clean_tree_has_nothing_to_report() {
+    let dir = ScratchDir::new("integrity-clean");
+    written(&dir, "users", users(), &[user(1, "a@x"), user(2, "b@x")], 2).await;
+    let store = load(&dir, CorruptionPolicy::Report).await.unwrap();
+
+    let report = store.validate_tree("users").await.unwrap();
+    assert!(report.is_ok());
+    assert_eq!(report.records, 2);
+    assert!(store.load_report().unwrap().integrity.is_empty());
+    assert!(matches!(
+        store.validate_tree("nothing").await,
+        Err(JsonStoreError::NotFoundTree(_))
+    ));
+}
+
+#[tokio::test]
+async fn fail_refuses_the_tree_on_its_first_read() {
+    let dir = ScratchDir::new("integrity-fail");
+    written(&dir, "users", users(), &broken(), 7).await;
+    // the tree isn't read until it's used
+    let store = load(&dir, CorruptionPolicy::Fail).await.unwrap();
+
+    for _ in 0..2 {
+        match store.select::<Value>("users", 1).await {
+            Err(e @ JsonStoreError::IntegrityViolation { .. }) => {
+                assert_eq!(e.kind(), ErrorKind::Corruption);
+                assert_eq!(e.tree(), Some("users"));
+                // the message gives the count and the first of them
+                let message = e.to_string();
+                assert!(message.contains('3'), "{}", message);
+                assert!(message.contains("sequence 5"), "{}", message);
+                let JsonStoreError::IntegrityViolation { report, .. } = e else {
+                    unreachable!();
+                };
+                assert_eq!(*report, broken_report());
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    // and an eager load fails outright
+    let options = LoadOptions {
+        eager: true,
+        ..Default::default()
+    };
+    assert!(matches!(
+        JsonStore::load_with_options(dir.path(), options).await,
+        Err(JsonStoreError::IntegrityViolation { .. })
+    ));
+}
+
+#[tokio::test]
+async fn skip_loads_the_tree_as_it_is() {
+    let dir = ScratchDir::new("integrity-skip");
+    written(&dir, "users", users(), &broken(), 7).await;
+    let store = load(&dir, CorruptionPolicy::Skip).await.unwrap();
+
+    assert_eq!(all(&store, "users").await.len(), 5);
+    assert!(store.load_report().is_none());
+    assert_eq!(store.validate_tree("users").await.unwrap(), broken_report());
+
+    // so the violations can be fixed through the store
+    store
+        .update("users", &json!({"id": 4, "email": "d@x"}))
+        .await
+        .unwrap();
+    store.delete("users", 5).await.unwrap();
+    store.delete("users", 7).await.unwrap();
+    assert!(store.validate_tree("users").await.unwrap().is_ok());
+    store.close().await.unwrap();
+    let store = load(&dir, CorruptionPolicy::Fail).await.unwrap();
+    assert_eq!(all(&store, "users").await.len(), 3);
+}
+
+#[tokio::test]
+async fn validate_tree_reads_spilled_records_too() {
+    let dir = ScratchDir::new("integrity-spilled");
+    let info = Info {
+        resident_limit: Some(1),
+        ..users()
+    };
+    written(&dir, "users", info, &broken(), 7).await;
+    let store = load(&dir, CorruptionPolicy::Skip).await.unwrap();
+
+    assert_eq!(store.validate_tree("users").await.unwrap(), broken_report());
+}
+
+#[tokio::test]
+async fn report_lists_the_violations_of_each_tree() {
+    let dir = ScratchDir::new("integrity-report");
+    written(&dir, "users", users(), &broken(), 7).await;
+    written(&dir, "teams", users(), &[user(1, "t@x")], 1).await;
+    let store = load(&dir, CorruptionPolicy::Report).await.unwrap();
+
+    let report = store.load_report().unwrap();
+    assert!(report.corrupt().is_empty());
+    for tname in ["teams", "users"] {
+        assert_eq!(report.trees[tname], TreeOutcome::Loaded);
+    }
+    assert_eq!(report.integrity.keys().collect::<Vec<_>>(), ["users"]);
+    assert_eq!(report.integrity["users"], broken_report());
+    // as read by load, and by validate_tree since
+    assert_eq!(
+        store.validate_tree("users").await.unwrap(),
+        report.integrity["users"]
+    );
+
+    let shown = broken_report()
+        .violations
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        shown,
+        [
+            "record at sequence 5 has 6 in its sequence field",
+            "record at sequence 7 has null in its sequence field",
+            "sequences [3, 4] share a key under unique constraint 'email'",
+        ]
+    );
+    let serialized = serde_json::to_value(&report.integrity["users"]).unwrap();
+    assert_eq!(serialized["violations"][2]["kind"], "duplicate_unique");
+    assert_eq!(serialized["violations"][2]["sequences"], json!([3, 4]));
+}
+
+#[tokio::test]
+async fn duplicates_are_found_for_each_constraint_in_order() {
+    let dir = ScratchDir::new("integrity-constraints");
+    let info = Info::builder()
+        .sequence_field("id")
+        .unique("email", ["email"])
+        .unique("handle", ["realm", "handle"])
+        .build()
+        .unwrap();
+    let records = [
+        (
+            1,
+            json!({"id": 1, "email": "a@x", "realm": "r", "handle": "h"}),
+        ),
+        (
+            2,
+            json!({"id": 2, "email": "b@x", "realm": "r", "handle": "h"}),
+        ),
+        (
+            3,
+            json!({"id": 3, "email": "a@x", "realm": "s", "handle": "h"}),
+        ),
+        (
+            4,
+            json!({"id": 4, "email": "b@x", "realm": "r", "handle": "h"}),
+        ),
+        // with a field missing, its value is null
+        (5, json!({"id": 5, "email": "c@x", "handle": "k"})),
+        (6, json!({"id": 6, "email": "d@x", "handle": "k"})),
+    ];
+    written(&dir, "users", info, &records, 6).await;
+    let store = load(&dir, CorruptionPolicy::Skip).await.unwrap();
+
+    let duplicates = store
+        .validate_tree("users")
+        .await
+        .unwrap()
+        .violations
+        .into_iter()
+        .map(|violation| match violation {
+            Violation::DuplicateUnique {
+                constraint,
+                values,
+                sequences,
+            } => (constraint.unwrap(), values, sequences),
+            other => panic!("{:?}", other),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        duplicates,
+        [
+            ("email".to_string(), vec![json!("a@x")], vec![1, 3]),
+            ("email".to_string(), vec![json!("b@x")], vec![2, 4]),
+            (
+                "handle".to_string(),
+                vec![json!("r"), json!("h")],
+                vec![1, 2, 4]
+            ),
+            (
+                "handle".to_string(),
+                vec![Value::Null, json!("k")],
+                vec![5, 6]
+            ),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn a_counter_behind_the_records_is_raised_on_load() {
+    let dir = ScratchDir::new("integrity-counter");
+    written(&dir, "users", users(), &[user(1, "a@x"), user(9, "i@x")], 2).await;
+    let store = load(&dir, CorruptionPolicy::Fail).await.unwrap();
+
+    // so it is never a violation a load finds, and the next insert goes past the records
+    assert!(store.validate_tree("users").await.unwrap().is_ok());
+    let seq = store
+        .insert("users", &json!({"email": "j@x"}))
+        .await
+        .unwrap();
+    assert_eq!(seq, 10);
+    store.close().await.unwrap();
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("users.seq")).unwrap(),
+        "10"
+    );
+
+    let behind = Violation::CounterBehind { counter: 2, max: 9 };
+    assert_eq!(
+        behind.to_string(),
+        "sequence counter 2 is behind the highest sequence 9"
+    );
+}
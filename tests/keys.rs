@@ -0,0 +1,695 @@
+mod common;
+
+use common::{all, edit, hand_edit, read_json, ScratchDir};
+use json_store::{
+    error::{ErrorKind, JsonStoreError},
+    key::{KeyGenerator, KeyKind},
+    store::{Info, JsonStore, LoadOptions, StorageFormat},
+    wal::WalOptions,
+};
+use serde_json::{json, Value};
+
+// One tree per kind of key, with the records each test starts from. The shared tests
+// below run through all three, so a keyed tree has to do whatever a Sequence tree
+// does through the sequence methods; the rest look at what only keys do.
+struct Case {
+    info: Info,
+    records: Vec<Value>,
+    // the key of each record as the *_by_key methods take it, none by sequence
+    keys: Vec<Option<String>>,
+}
+
+fn sequence() -> Case {
+    Case {
+        info: Info::builder().sequence_field("id").build().unwrap(),
+        records: vec![
+            json!({"code": "FR", "name": "France"}),
+            json!({"code": "DE", "name": "Germany"}),
+            json!({"code": "IT", "name": "Italy"}),
+        ],
+        keys: vec![None, None, None],
+    }
+}
+
+fn string() -> Case {
+    Case {
+        info: Info::builder()
+            .sequence_field("id")
+            .string_key("code", KeyGenerator::None)
+            .build()
+            .unwrap(),
+        ..sequence()
+    }
+    .keyed(&["FR", "DE", "IT"])
+}
+
+fn composite() -> Case {
+    Case {
+        info: Info::builder()
+            .sequence_field("id")
+            .composite_key(["product", "region"])
+            .build()
+            .unwrap(),
+        records: vec![
+            json!({"product": 17, "region": "eu", "name": "France"}),
+            json!({"product": 17, "region": "us", "name": "Germany"}),
+            json!({"product": 4, "region": "eu", "name": "Italy"}),
+        ],
+        keys: vec![None, None, None],
+    }
+    .keyed(&[r#"[17,"eu"]"#, r#"[17,"us"]"#, r#"[4,"eu"]"#])
+}
+
+impl Case {
+    fn keyed(mut self, keys: &[&str]) -> Self {
+        self.keys = keys.iter().map(|key| Some(key.to_string())).collect();
+        self
+    }
+
+    // record n with its sequence, as the store hands it back
+    fn record(&self, n: usize) -> Value {
+        let mut record = self.records[n].clone();
+        record["id"] = json!(n as u64 + 1);
+        record
+    }
+
+    fn key(&self, n: usize) -> &str {
+        self.keys[n].as_deref().expect("a keyed tree")
+    }
+}
+
+fn cases() -> [Case; 3] {
+    [sequence(), string(), composite()]
+}
+
+async fn filled(dir: &ScratchDir, case: &Case, options: LoadOptions) -> JsonStore {
+    let store = JsonStore::load_with_options(dir.path(), options)
+        .await
+        .unwrap();
+    store
+        .create_tree("places", case.info.clone())
+        .await
+        .unwrap();
+    for record in &case.records {
+        store.insert("places", record).await.unwrap();
+    }
+    store
+}
+
+fn wal() -> LoadOptions {
+    LoadOptions {
+        wal: Some(WalOptions::default()),
+        ..Default::default()
+    }
+}
+
+fn incremental() -> LoadOptions {
+    LoadOptions {
+        incremental_save: Some(0.5),
+        ..Default::default()
+    }
+}
+
+fn snapshot(dir: &ScratchDir) -> Value {
+    read_json(&dir.path().join("places.json"))
+}
+
+#[tokio::test]
+async fn the_sequence_methods_work_whatever_the_key() {
+    for case in cases() {
+        let dir = ScratchDir::new("keys-sequence-methods");
+        let store = filled(&dir, &case, LoadOptions::default()).await;
+
+        let second: Value = store.select("places", 2).await.unwrap();
+        assert_eq!(second, case.record(1));
+
+        let mut renamed = case.record(1);
+        renamed["name"] = json!("Deutschland");
+        store.update("places", &renamed).await.unwrap();
+        store.delete("places", 1).await.unwrap();
+        assert_eq!(all(&store, "places").await, [renamed, case.record(2)]);
+        assert_eq!(
+            store.select::<Value>("places", 1).await.unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+    }
+}
+
+#[tokio::test]
+async fn a_tree_reloads_as_it_was_saved() {
+    for case in cases() {
+        let dir = ScratchDir::new("keys-reload");
+        let store = filled(&dir, &case, LoadOptions::default()).await;
+        store.delete("places", 2).await.unwrap();
+        store.close().await.unwrap();
+
+        let store = JsonStore::load(dir.path()).await.unwrap();
+        assert_eq!(
+            all(&store, "places").await,
+            [case.record(0), case.record(2)]
+        );
+        // sequences go on from where they were
+        let sequence = store.insert("places", &case.records[1]).await.unwrap();
+        assert_eq!(sequence, 4);
+        if case.keys[1].is_some() {
+            assert_eq!(
+                store.sequence_of_key("places", case.key(1)).await.unwrap(),
+                4
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn the_log_replays_into_a_keyed_tree() {
+    for case in cases() {
+        let dir = ScratchDir::new("keys-wal");
+        let store = filled(&dir, &case, wal()).await;
+        store.save().await.unwrap();
+        store.delete("places", 1).await.unwrap();
+        let mut renamed = case.record(2);
+        renamed["name"] = json!("Italia");
+        store.update("places", &renamed).await.unwrap();
+        store.insert("places", &case.records[0]).await.unwrap();
+        // dropped unsaved, so only the log has the last three writes
+        drop(store);
+
+        let store = JsonStore::load_with_options(dir.path(), wal())
+            .await
+            .unwrap();
+        let mut readded = case.record(0);
+        readded["id"] = json!(4);
+        assert_eq!(
+            all(&store, "places").await,
+            [case.record(1), renamed, readded]
+        );
+        if case.keys[0].is_some() {
+            assert_eq!(
+                store.sequence_of_key("places", case.key(0)).await.unwrap(),
+                4
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn the_delta_reloads_into_a_keyed_tree() {
+    for case in cases() {
+        let dir = ScratchDir::new("keys-delta");
+        let store = filled(&dir, &case, incremental()).await;
+        for n in 0..7 {
+            let mut record = case.records[n % 3].clone();
+            record["name"] = json!(format!("filler {}", n));
+            let field = case
+                .info
+                .key
+                .fields()
+                .first()
+                .map(|field| field.to_string());
+            if let Some(field) = field {
+                record[field] = json!(format!("filler {}", n));
+            }
+            store.insert("places", &record).await.unwrap();
+        }
+        store.save().await.unwrap();
+        store.delete("places", 1).await.unwrap();
+        let mut renamed = case.record(1);
+        renamed["name"] = json!("Deutschland");
+        store.update("places", &renamed).await.unwrap();
+        store.save().await.unwrap();
+        assert!(dir.path().join("places.delta.json").exists());
+
+        let expected = all(&store, "places").await;
+        drop(store);
+        for options in [incremental(), LoadOptions::default()] {
+            let store = JsonStore::load_with_options(dir.path(), options)
+                .await
+                .unwrap();
+            assert_eq!(all(&store, "places").await, expected);
+        }
+    }
+}
+
+#[tokio::test]
+async fn by_key_methods_refuse_a_sequence_tree() {
+    let case = sequence();
+    let dir = ScratchDir::new("keys-by-sequence");
+    let store = filled(&dir, &case, LoadOptions::default()).await;
+
+    let error = store
+        .select_by_key::<Value>("places", "FR")
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(error, JsonStoreError::InvalidKey { .. }),
+        "{}",
+        error
+    );
+    assert!(store.delete_by_key("places", "1").await.is_err());
+    assert!(store
+        .select_by_key_fields::<Value>("places", &json!({"code": "FR"}))
+        .await
+        .is_err());
+    assert_eq!(all(&store, "places").await.len(), 3);
+}
+
+#[tokio::test]
+async fn records_are_found_updated_and_deleted_by_key() {
+    for case in [string(), composite()] {
+        let dir = ScratchDir::new("keys-by-key");
+        let store = filled(&dir, &case, LoadOptions::default()).await;
+
+        let second: Value = store.select_by_key("places", case.key(1)).await.unwrap();
+        assert_eq!(second, case.record(1));
+        let by_fields: Value = store
+            .select_by_key_fields("places", &case.record(1))
+            .await
+            .unwrap();
+        assert_eq!(by_fields, case.record(1));
+        assert_eq!(
+            store.sequence_of_key("places", case.key(2)).await.unwrap(),
+            3
+        );
+
+        // the key and sequence fields may be left out of an update
+        store
+            .update_by_key("places", case.key(1), &json!({"name": "Deutschland"}))
+            .await
+            .unwrap();
+        let mut renamed = case.record(1);
+        renamed["name"] = json!("Deutschland");
+        assert_eq!(store.select::<Value>("places", 2).await.unwrap(), renamed);
+
+        store
+            .update_by_key_fields("places", &case.record(2), &json!({"name": "Italia"}))
+            .await
+            .unwrap();
+        store.delete_by_key("places", case.key(0)).await.unwrap();
+        let error = store
+            .select_by_key::<Value>("places", case.key(0))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(error, JsonStoreError::KeyNotExist { .. }),
+            "{}",
+            error
+        );
+        store
+            .delete_by_key_fields("places", &case.record(2))
+            .await
+            .unwrap();
+        assert_eq!(all(&store, "places").await, [renamed]);
+    }
+}
+
+#[tokio::test]
+async fn a_missing_key_is_not_found() {
+    for case in [string(), composite()] {
+        let dir = ScratchDir::new("keys-missing");
+        let store = filled(&dir, &case, LoadOptions::default()).await;
+        let missing = match case.info.key {
+            KeyKind::Composite { .. } => r#"[17,"asia"]"#,
+            _ => "ES",
+        };
+        for error in [
+            store
+                .select_by_key::<Value>("places", missing)
+                .await
+                .unwrap_err(),
+            store.delete_by_key("places", missing).await.unwrap_err(),
+            store
+                .update_by_key("places", missing, &json!({"name": "x"}))
+                .await
+                .unwrap_err(),
+        ] {
+            assert!(
+                matches!(error, JsonStoreError::KeyNotExist { .. }),
+                "{}",
+                error
+            );
+            assert_eq!(error.kind(), ErrorKind::NotFound);
+        }
+        assert_eq!(all(&store, "places").await.len(), 3);
+    }
+}
+
+#[tokio::test]
+async fn a_key_is_taken_once() {
+    for case in [string(), composite()] {
+        let dir = ScratchDir::new("keys-unique");
+        let store = filled(&dir, &case, LoadOptions::default()).await;
+
+        let mut twin = case.records[0].clone();
+        twin["name"] = json!("twin");
+        let error = store.insert("places", &twin).await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Conflict, "{}", error);
+        // nor by import
+        let file = dir.path().join("twin.json");
+        edit(&file, &serde_json::to_string(&[twin.clone()]).unwrap());
+        assert!(store
+            .import_tree("places", &file, Default::default())
+            .await
+            .is_err());
+        assert_eq!(all(&store, "places").await.len(), 3);
+
+        // a key deleted is free again
+        store.delete("places", 1).await.unwrap();
+        let sequence = store.insert("places", &twin).await.unwrap();
+        assert_eq!(
+            store.sequence_of_key("places", case.key(0)).await.unwrap(),
+            sequence
+        );
+    }
+}
+
+#[tokio::test]
+async fn a_key_does_not_change() {
+    for case in [string(), composite()] {
+        let dir = ScratchDir::new("keys-unchanged");
+        let store = filled(&dir, &case, LoadOptions::default()).await;
+
+        let field = case.info.key.fields()[0].to_string();
+        let mut moved = case.record(0);
+        moved[&field] = json!("elsewhere");
+        let error = store.update("places", &moved).await.unwrap_err();
+        assert!(
+            matches!(error, JsonStoreError::InvalidKey { .. }),
+            "{}",
+            error
+        );
+        let error = store
+            .update_by_key("places", case.key(0), &moved)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(error, JsonStoreError::InvalidKey { .. }),
+            "{}",
+            error
+        );
+        assert_eq!(all(&store, "places").await[0], case.record(0));
+    }
+}
+
+#[tokio::test]
+async fn a_record_without_its_key_is_refused() {
+    for case in [string(), composite()] {
+        let dir = ScratchDir::new("keys-keyless");
+        let store = filled(&dir, &case, LoadOptions::default()).await;
+        let field = case.info.key.fields()[0].to_string();
+        let mut keyless = case.records[0].clone();
+        keyless.as_object_mut().unwrap().remove(&field);
+        let error = store.insert("places", &keyless).await.unwrap_err();
+        assert!(
+            matches!(error, JsonStoreError::InvalidKey { .. }),
+            "{}",
+            error
+        );
+    }
+
+    // nor is a String key that isn't a non-empty string
+    let dir = ScratchDir::new("keys-not-a-string");
+    let store = filled(&dir, &string(), LoadOptions::default()).await;
+    for code in [json!(""), json!(7), json!(["FR"])] {
+        assert!(store
+            .insert("places", &json!({"code": code}))
+            .await
+            .is_err());
+    }
+    assert_eq!(all(&store, "places").await.len(), 3);
+}
+
+#[cfg(feature = "uuid")]
+#[tokio::test]
+async fn a_uuid_key_is_generated() {
+    let dir = ScratchDir::new("keys-uuid");
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    let info = Info::builder()
+        .sequence_field("id")
+        .string_key("uuid", KeyGenerator::Uuid)
+        .build()
+        .unwrap();
+    store.create_tree("places", info).await.unwrap();
+
+    let first = store
+        .insert("places", &json!({"name": "France"}))
+        .await
+        .unwrap();
+    let second = store
+        .insert("places", &json!({"name": "France"}))
+        .await
+        .unwrap();
+    let first: Value = store.select("places", first).await.unwrap();
+    let second: Value = store.select("places", second).await.unwrap();
+    let key = first["uuid"].as_str().unwrap();
+    assert_eq!(key.len(), 36);
+    assert_ne!(first["uuid"], second["uuid"]);
+    assert_eq!(
+        store.select_by_key::<Value>("places", key).await.unwrap(),
+        first
+    );
+    // a key given is kept
+    let given = store
+        .insert("places", &json!({"uuid": "mine", "name": "Italy"}))
+        .await
+        .unwrap();
+    assert_eq!(
+        store.sequence_of_key("places", "mine").await.unwrap(),
+        given
+    );
+}
+
+#[tokio::test]
+async fn a_keyed_snapshot_is_an_object_by_key() {
+    let dir = ScratchDir::new("keys-snapshot-string");
+    let store = filled(&dir, &string(), LoadOptions::default()).await;
+    store.save().await.unwrap();
+    assert_eq!(
+        snapshot(&dir),
+        json!({
+            "DE": {"id": 2, "code": "DE", "name": "Germany"},
+            "FR": {"id": 1, "code": "FR", "name": "France"},
+            "IT": {"id": 3, "code": "IT", "name": "Italy"}
+        })
+    );
+
+    let dir = ScratchDir::new("keys-snapshot-composite");
+    let store = filled(&dir, &composite(), LoadOptions::default()).await;
+    store.save().await.unwrap();
+    let file = snapshot(&dir);
+    let mut keys: Vec<_> = file.as_object().unwrap().keys().cloned().collect();
+    keys.sort();
+    assert_eq!(keys, [r#"[17,"eu"]"#, r#"[17,"us"]"#, r#"[4,"eu"]"#]);
+    assert_eq!(file[r#"[4,"eu"]"#]["name"], "Italy");
+
+    // and a Sequence tree's stays by sequence
+    let dir = ScratchDir::new("keys-snapshot-sequence");
+    let store = filled(&dir, &sequence(), LoadOptions::default()).await;
+    store.save().await.unwrap();
+    assert_eq!(snapshot(&dir)["2"]["code"], "DE");
+}
+
+#[tokio::test]
+async fn a_keyed_delta_is_an_object_by_key() {
+    let dir = ScratchDir::new("keys-delta-file");
+    let store = filled(&dir, &string(), incremental()).await;
+    for n in 0..7 {
+        store
+            .insert("places", &json!({"code": format!("X{}", n)}))
+            .await
+            .unwrap();
+    }
+    store.save().await.unwrap();
+    store.delete_by_key("places", "DE").await.unwrap();
+    store
+        .update_by_key("places", "IT", &json!({"name": "Italia"}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+
+    assert_eq!(
+        read_json(&dir.path().join("places.delta.json")),
+        json!({"DE": null, "IT": {"id": 3, "code": "IT", "name": "Italia"}})
+    );
+
+    // a key deleted and taken again by a new record is that record in the delta
+    store
+        .insert("places", &json!({"code": "DE", "name": "again"}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+    assert_eq!(
+        read_json(&dir.path().join("places.delta.json"))["DE"],
+        json!({"id": 11, "code": "DE", "name": "again"})
+    );
+    let expected = all(&store, "places").await;
+    drop(store);
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(all(&store, "places").await, expected);
+    assert_eq!(store.sequence_of_key("places", "DE").await.unwrap(), 11);
+}
+
+// a file written by hand, leaving out what the loader fills in
+#[tokio::test]
+async fn a_hand_written_file_loads() {
+    let dir = ScratchDir::new("keys-by-hand");
+    let store = filled(&dir, &string(), LoadOptions::default()).await;
+    store.close().await.unwrap();
+    hand_edit(
+        &dir.path().join("places.json"),
+        r#"{
+            "FR": {"id": 1, "name": "France"},
+            "ES": {"code": "ES", "name": "Spain"},
+            "PT": {"name": "Portugal"}
+        }"#,
+    );
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(
+        all(&store, "places").await,
+        [
+            json!({"id": 1, "code": "FR", "name": "France"}),
+            json!({"id": 4, "code": "ES", "name": "Spain"}),
+            json!({"id": 5, "code": "PT", "name": "Portugal"})
+        ]
+    );
+    assert_eq!(store.sequence_of_key("places", "PT").await.unwrap(), 5);
+    assert_eq!(
+        store
+            .insert("places", &json!({"code": "NL"}))
+            .await
+            .unwrap(),
+        6
+    );
+
+    // what was filled in is written back
+    store.save().await.unwrap();
+    assert_eq!(
+        snapshot(&dir)["ES"],
+        json!({"id": 4, "code": "ES", "name": "Spain"})
+    );
+}
+
+#[tokio::test]
+async fn a_hand_written_composite_file_loads() {
+    let dir = ScratchDir::new("keys-by-hand-composite");
+    let store = filled(&dir, &composite(), LoadOptions::default()).await;
+    store.close().await.unwrap();
+    hand_edit(
+        &dir.path().join("places.json"),
+        r#"{
+            "[17,\"eu\"]": {"id": 1, "name": "France"},
+            "[\"a,b\",\"eu\"]": {"name": "odd"}
+        }"#,
+    );
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    let odd: Value = store
+        .select_by_key_fields("places", &json!({"product": "a,b", "region": "eu"}))
+        .await
+        .unwrap();
+    assert_eq!(
+        odd,
+        json!({"id": 4, "product": "a,b", "region": "eu", "name": "odd"})
+    );
+    let france: Value = store.select("places", 1).await.unwrap();
+    assert_eq!(france["product"], 17);
+}
+
+// the error reading places, which loads when first read
+async fn unreadable(dir: &ScratchDir) -> JsonStoreError {
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    store
+        .select_where::<Value, _>("places", |_| true)
+        .await
+        .unwrap_err()
+}
+
+#[tokio::test]
+async fn a_record_disagreeing_with_its_key_is_corrupt() {
+    for contents in [
+        r#"{"FR": {"id": 1, "code": "DE"}}"#,
+        // a key that isn't one
+        r#"{"FR": {"id": 1, "code": 7}}"#,
+        // a Sequence tree's file
+        r#"{"1": {"id": 1, "code": "FR"}}"#,
+    ] {
+        let dir = ScratchDir::new("keys-corrupt");
+        let store = filled(&dir, &string(), LoadOptions::default()).await;
+        store.close().await.unwrap();
+        hand_edit(&dir.path().join("places.json"), contents);
+
+        let error = unreadable(&dir).await;
+        assert!(
+            matches!(error, JsonStoreError::TreeCorrupt { .. }),
+            "{}: {}",
+            contents,
+            error
+        );
+    }
+
+    let dir = ScratchDir::new("keys-corrupt-composite");
+    let store = filled(&dir, &composite(), LoadOptions::default()).await;
+    store.close().await.unwrap();
+    hand_edit(&dir.path().join("places.json"), r#"{"17,eu": {"id": 1}}"#);
+    let error = unreadable(&dir).await;
+    assert!(
+        matches!(error, JsonStoreError::TreeCorrupt { .. }),
+        "{}",
+        error
+    );
+}
+
+#[tokio::test]
+async fn a_tree_migrates_to_keys_through_an_export() {
+    let dir = ScratchDir::new("keys-migrate");
+    let case = sequence();
+    let store = filled(&dir, &case, LoadOptions::default()).await;
+    store.delete("places", 1).await.unwrap();
+    let file = dir.path().join("export.json");
+    store
+        .export_tree("places", &file, Default::default())
+        .await
+        .unwrap();
+
+    store.create_tree("keyed", string().info).await.unwrap();
+    store
+        .import_tree("keyed", &file, json_store::import::ImportMode::Preserve)
+        .await
+        .unwrap();
+    assert_eq!(all(&store, "keyed").await, all(&store, "places").await);
+    assert_eq!(store.sequence_of_key("keyed", "IT").await.unwrap(), 3);
+}
+
+// the other layouts keep their own files and come back keyed all the same
+#[tokio::test]
+async fn a_keyed_tree_reloads_from_any_layout() {
+    let builders = [
+        Info::builder().shards(4),
+        Info::builder().storage(StorageFormat::AppendLog),
+    ];
+    for builder in builders {
+        let dir = ScratchDir::new("keys-layouts");
+        let case = Case {
+            info: builder
+                .sequence_field("id")
+                .string_key("code", KeyGenerator::None)
+                .build()
+                .unwrap(),
+            ..string()
+        };
+        let store = filled(&dir, &case, LoadOptions::default()).await;
+        store.save().await.unwrap();
+        store.delete_by_key("places", "DE").await.unwrap();
+        store
+            .update_by_key("places", "IT", &json!({"name": "Italia"}))
+            .await
+            .unwrap();
+        let expected = all(&store, "places").await;
+        store.close().await.unwrap();
+
+        let store = JsonStore::load(dir.path()).await.unwrap();
+        assert_eq!(all(&store, "places").await, expected);
+        assert_eq!(store.sequence_of_key("places", "IT").await.unwrap(), 3);
+        assert!(store.select_by_key::<Value>("places", "DE").await.is_err());
+    }
+}
@@ -0,0 +1,36 @@
+mod common;
+
+use common::{all, store_with_users, ScratchDir};
+use json_store::store::JsonStore;
+use serde_json::json;
+
+// well past any buffer a single write call would fill
+const RECORDS: usize = 20_000;
+
+#[tokio::test]
+async fn a_multi_megabyte_tree_saves_and_loads_whole() {
+    let dir = ScratchDir::new("large-tree");
+    let store = store_with_users(&dir).await;
+    let padding = "x".repeat(256);
+    for i in 0..RECORDS {
+        store
+            .insert(
+                "users",
+                &json!({"email": format!("{}@x", i), "bio": padding, "n": i}),
+            )
+            .await
+            .unwrap();
+    }
+    store.save().await.unwrap();
+
+    let size = std::fs::metadata(dir.path().join("users.json"))
+        .unwrap()
+        .len();
+    assert!(size > 5 * 1024 * 1024, "only {} bytes", size);
+
+    let reloaded = JsonStore::load(dir.path()).await.unwrap();
+    let records = all(&reloaded, "users").await;
+    assert_eq!(records.len(), RECORDS);
+    assert_eq!(records[RECORDS - 1]["n"], RECORDS - 1);
+    assert_eq!(records[RECORDS - 1]["bio"], padding);
+}
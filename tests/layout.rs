@@ -0,0 +1,232 @@
+mod common;
+
+use common::{all, read_json, users, ScratchDir};
+use json_store::{
+    error::JsonStoreError,
+    layout::Layout,
+    store::{JsonStore, LoadOptions},
+};
+use serde_json::json;
+
+fn custom() -> Layout {
+    Layout {
+        infos_file: "catalog.json".to_string(),
+        tree_dir: Some("trees".to_string()),
+        data_extension: "data".to_string(),
+        sequence_extension: "counter".to_string(),
+    }
+}
+
+async fn load_as(dir: &ScratchDir, layout: Layout) -> Result<JsonStore, JsonStoreError> {
+    let options = LoadOptions {
+        layout: Some(layout),
+        ..Default::default()
+    };
+    JsonStore::load_with_options(dir.path(), options).await
+}
+
+async fn fill(store: &JsonStore) {
+    store.create_tree("users", users()).await.unwrap();
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store
+        .insert("users", &json!({"email": "b@x"}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+}
+
+fn records() -> Vec<serde_json::Value> {
+    vec![
+        json!({"id": 1, "email": "a@x"}),
+        json!({"id": 2, "email": "b@x"}),
+    ]
+}
+
+#[tokio::test]
+async fn the_default_layout_is_the_flat_one() {
+    let dir = ScratchDir::new("layout-default");
+    fill(&JsonStore::load(dir.path()).await.unwrap()).await;
+
+    for name in ["infos.json", "users.json", "users.seq"] {
+        assert!(dir.path().join(name).is_file(), "{}", name);
+    }
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("users.seq")).unwrap(),
+        "2"
+    );
+}
+
+#[tokio::test]
+async fn a_custom_layout_is_remembered() {
+    let dir = ScratchDir::new("layout-custom");
+    fill(&load_as(&dir, custom()).await.unwrap()).await;
+
+    let trees = dir.path().join("trees");
+    assert!(dir.path().join("catalog.json").is_file());
+    assert!(trees.join("users.data").is_file());
+    assert!(trees.join("users.counter").is_file());
+    for name in ["infos.json", "users.json", "users.seq"] {
+        assert!(!dir.path().join(name).exists(), "{}", name);
+    }
+    assert_eq!(
+        read_json(&dir.path().join("meta.json"))["layout"]["tree_dir"],
+        "trees"
+    );
+
+    // a plain load finds the records rather than an empty store
+    let reloaded = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(reloaded.list_trees(), ["users"]);
+    assert_eq!(all(&reloaded, "users").await, records());
+    let reloaded = load_as(&dir, custom()).await.unwrap();
+    assert_eq!(all(&reloaded, "users").await, records());
+}
+
+#[tokio::test]
+async fn asking_for_another_layout_is_an_error() {
+    let dir = ScratchDir::new("layout-mismatch");
+    fill(&load_as(&dir, custom()).await.unwrap()).await;
+
+    match load_as(&dir, Layout::default()).await {
+        Err(JsonStoreError::LayoutMismatch { store, requested }) => {
+            assert_eq!(*store, custom());
+            assert_eq!(*requested, Layout::default());
+        }
+        other => panic!("expected LayoutMismatch, got {:?}", other.err()),
+    }
+
+    let dir = ScratchDir::new("layout-mismatch-default");
+    fill(&JsonStore::load(dir.path()).await.unwrap()).await;
+    assert!(matches!(
+        load_as(&dir, custom()).await,
+        Err(JsonStoreError::LayoutMismatch { .. })
+    ));
+}
+
+#[tokio::test]
+async fn unusable_layouts_are_refused() {
+    let layouts = [
+        Layout {
+            tree_dir: Some("a/b".to_string()),
+            ..Layout::default()
+        },
+        Layout {
+            infos_file: String::new(),
+            ..Layout::default()
+        },
+        Layout {
+            infos_file: "meta.json".to_string(),
+            ..Layout::default()
+        },
+        Layout {
+            data_extension: ".json".to_string(),
+            ..Layout::default()
+        },
+        Layout {
+            data_extension: "wal".to_string(),
+            ..Layout::default()
+        },
+        Layout {
+            sequence_extension: "json".to_string(),
+            ..Layout::default()
+        },
+    ];
+    for layout in layouts {
+        let dir = ScratchDir::new("layout-invalid");
+        assert!(
+            matches!(
+                load_as(&dir, layout.clone()).await,
+                Err(JsonStoreError::InvalidLayout(_))
+            ),
+            "{:?}",
+            layout
+        );
+        assert!(!dir.path().join("meta.json").exists());
+    }
+}
+
+#[tokio::test]
+async fn a_tree_dir_keeps_trees_clear_of_the_catalog() {
+    let dir = ScratchDir::new("layout-infos-flat");
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    for tname in ["infos", "Infos", "meta"] {
+        assert!(
+            matches!(
+                store.create_tree(tname, users()).await,
+                Err(JsonStoreError::InvalidLayout(_))
+            ),
+            "{}",
+            tname
+        );
+    }
+
+    let dir = ScratchDir::new("layout-infos-nested");
+    let store = load_as(
+        &dir,
+        Layout {
+            tree_dir: Some("trees".to_string()),
+            ..Layout::default()
+        },
+    )
+    .await
+    .unwrap();
+    store.create_tree("infos", users()).await.unwrap();
+    store
+        .insert("infos", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+    assert_eq!(
+        all(&JsonStore::load(dir.path()).await.unwrap(), "infos").await,
+        [json!({"id": 1, "email": "a@x"})]
+    );
+}
+
+#[tokio::test]
+async fn relayout_moves_the_files_both_ways() {
+    let dir = ScratchDir::new("layout-relayout");
+    fill(&JsonStore::load(dir.path()).await.unwrap()).await;
+
+    JsonStore::relayout(dir.path(), custom()).await.unwrap();
+    assert!(dir.path().join("trees/users.data").is_file());
+    assert!(!dir.path().join("users.json").exists());
+    assert!(!dir.path().join("infos.json").exists());
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(all(&store, "users").await, records());
+    assert_eq!(
+        store
+            .insert("users", &json!({"email": "c@x"}))
+            .await
+            .unwrap(),
+        3
+    );
+    store.save().await.unwrap();
+    drop(store);
+
+    JsonStore::relayout(dir.path(), Layout::default())
+        .await
+        .unwrap();
+    assert!(dir.path().join("users.json").is_file());
+    assert!(!dir.path().join("trees").join("users.data").exists());
+    assert!(!dir.path().join("catalog.json").exists());
+    let store = load_as(&dir, Layout::default()).await.unwrap();
+    assert_eq!(all(&store, "users").await.len(), 3);
+
+    // the same layout again changes nothing
+    JsonStore::relayout(dir.path(), Layout::default())
+        .await
+        .unwrap();
+    assert!(matches!(
+        JsonStore::relayout(
+            dir.path(),
+            Layout {
+                infos_file: "meta.json".to_string(),
+                ..Layout::default()
+            }
+        )
+        .await,
+        Err(JsonStoreError::InvalidLayout(_))
+    ));
+}
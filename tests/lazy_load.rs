@@ -0,0 +1,171 @@
+mod common;
+
+use common::{all, damage, hand_edit, store_with_users, users, ScratchDir};
+use json_store::{
+    error::{ErrorKind, JsonStoreError},
+    store::{JsonStore, LoadOptions},
+};
+use serde_json::{json, Value};
+
+// users and posts saved with a record each
+async fn saved_store(dir: &ScratchDir) {
+    let store = store_with_users(dir).await;
+    store.create_tree("posts", users()).await.unwrap();
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store
+        .insert("posts", &json!({"email": "p@x"}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+}
+
+#[tokio::test]
+async fn load_reads_no_tree_until_it_is_used() {
+    let dir = ScratchDir::new("lazy-untouched");
+    saved_store(&dir).await;
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(store.list_trees(), ["posts", "users"]);
+    assert!(store.loaded_trees().await.is_empty());
+
+    store.select::<Value>("users", 1).await.unwrap();
+    assert_eq!(store.loaded_trees().await, ["users"]);
+    store
+        .insert("posts", &json!({"email": "q@x"}))
+        .await
+        .unwrap();
+    assert_eq!(store.loaded_trees().await, ["posts", "users"]);
+}
+
+#[tokio::test]
+async fn a_damaged_tree_fails_its_first_use_not_the_load() {
+    let dir = ScratchDir::new("lazy-damaged");
+    saved_store(&dir).await;
+    damage(&dir.path().join("posts.json"), "{\"1\": ");
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(
+        all(&store, "users").await,
+        [json!({"id": 1, "email": "a@x"})]
+    );
+    for _ in 0..2 {
+        let error = store.select::<Value>("posts", 1).await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Corruption, "{:?}", error);
+        assert!(store
+            .insert("posts", &json!({"email": "q@x"}))
+            .await
+            .is_err());
+    }
+    assert_eq!(store.loaded_trees().await, ["users"]);
+
+    // nothing was cached from the failure: a repaired file is read on the next try
+    hand_edit(
+        &dir.path().join("posts.json"),
+        r#"{"1": {"id": 1, "email": "p@x"}}"#,
+    );
+    assert_eq!(
+        store.select::<Value>("posts", 1).await.unwrap(),
+        json!({"id": 1, "email": "p@x"})
+    );
+}
+
+#[tokio::test]
+async fn a_data_file_gone_missing_reads_as_an_eager_load_would() {
+    let dir = ScratchDir::new("lazy-missing");
+    saved_store(&dir).await;
+
+    let lazy = JsonStore::load(dir.path()).await.unwrap();
+    std::fs::remove_file(dir.path().join("posts.json")).unwrap();
+    let eager = JsonStore::load_with_options(
+        dir.path(),
+        LoadOptions {
+            eager: true,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    for store in [&lazy, &eager] {
+        assert!(matches!(
+            store.select::<Value>("posts", 1).await,
+            Err(JsonStoreError::SequenceNotExist(t)) if t == "posts"
+        ));
+        assert!(all(store, "posts").await.is_empty());
+        assert!(store.select::<Value>("users", 1).await.is_ok());
+    }
+}
+
+#[tokio::test]
+async fn eager_loading_reads_everything_up_front() {
+    let dir = ScratchDir::new("lazy-eager");
+    saved_store(&dir).await;
+    let eager = || LoadOptions {
+        eager: true,
+        ..Default::default()
+    };
+
+    let store = JsonStore::load_with_options(dir.path(), eager())
+        .await
+        .unwrap();
+    assert_eq!(store.loaded_trees().await, ["posts", "users"]);
+    drop(store);
+
+    damage(&dir.path().join("posts.json"), "{\"1\": ");
+    let error = JsonStore::load_with_options(dir.path(), eager())
+        .await
+        .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::Corruption, "{:?}", error);
+}
+
+#[tokio::test]
+async fn preload_reads_the_trees_it_is_given() {
+    let dir = ScratchDir::new("lazy-preload");
+    saved_store(&dir).await;
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    store.preload(&["posts"]).await.unwrap();
+    assert_eq!(store.loaded_trees().await, ["posts"]);
+    store.preload(&["posts", "users"]).await.unwrap();
+    assert_eq!(store.loaded_trees().await, ["posts", "users"]);
+
+    assert!(matches!(
+        store.preload(&["missing"]).await,
+        Err(JsonStoreError::NotFoundTree(t)) if t == "missing"
+    ));
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    damage(&dir.path().join("posts.json"), "[");
+    assert!(store.preload(&["users", "posts"]).await.is_err());
+    assert_eq!(store.loaded_trees().await, ["users"]);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_first_uses_read_the_tree_once() {
+    let dir = ScratchDir::new("lazy-concurrent");
+    saved_store(&dir).await;
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    let tasks = (0..32)
+        .map(|n| {
+            let store = store.clone();
+            tokio::spawn(async move {
+                store
+                    .insert("users", &json!({"email": format!("{}@x", n)}))
+                    .await
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut seqs = Vec::new();
+    for task in tasks {
+        seqs.push(task.await.unwrap().unwrap());
+    }
+    seqs.sort();
+
+    // a second read of the file would have dropped inserts made before it
+    assert_eq!(seqs, (2..=33).collect::<Vec<_>>());
+    assert_eq!(all(&store, "users").await.len(), 33);
+}
@@ -0,0 +1,141 @@
+mod common;
+
+use common::{all, damage, users, ScratchDir};
+use json_store::{
+    error::{ErrorKind, JsonStoreError},
+    repair::{CorruptionPolicy, TreeOutcome},
+    store::{JsonStore, LoadOptions},
+};
+use serde_json::{json, Value};
+
+const TREES: usize = 24;
+
+fn tname(n: usize) -> String {
+    format!("t{:02}", n)
+}
+
+// TREES trees of three records each, saved, with those numbered in corrupt damaged
+async fn saved_trees(dir: &ScratchDir, corrupt: &[usize]) {
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    for n in 0..TREES {
+        store.create_tree(&tname(n), users()).await.unwrap();
+        for i in 0..3 {
+            store
+                .insert(&tname(n), &json!({"email": format!("{}@{}", i, n)}))
+                .await
+                .unwrap();
+        }
+    }
+    store.close().await.unwrap();
+    for &n in corrupt {
+        damage(&dir.path().join(format!("{}.json", tname(n))), "{\"1\": ");
+    }
+}
+
+fn options(policy: CorruptionPolicy, eager: bool) -> LoadOptions {
+    LoadOptions {
+        eager,
+        corruption_policy: policy,
+        load_concurrency: 4,
+        ..Default::default()
+    }
+}
+
+async fn load(dir: &ScratchDir, policy: CorruptionPolicy) -> Result<JsonStore, JsonStoreError> {
+    JsonStore::load_with_options(dir.path(), options(policy, true)).await
+}
+
+#[tokio::test]
+async fn every_tree_is_read_whatever_the_concurrency() {
+    let dir = ScratchDir::new("concurrency-clean");
+    saved_trees(&dir, &[]).await;
+
+    for concurrency in [0, 1, 4, TREES * 2] {
+        let options = LoadOptions {
+            load_concurrency: concurrency,
+            ..options(CorruptionPolicy::Fail, true)
+        };
+        let store = JsonStore::load_with_options(dir.path(), options)
+            .await
+            .unwrap();
+        assert_eq!(store.loaded_trees().await.len(), TREES);
+        for n in 0..TREES {
+            let records = all(&store, &tname(n)).await;
+            assert_eq!(records.len(), 3);
+            assert_eq!(records[2]["email"], format!("2@{}", n));
+        }
+    }
+}
+
+#[tokio::test]
+async fn fail_names_the_first_corrupt_tree() {
+    let dir = ScratchDir::new("concurrency-fail");
+    saved_trees(&dir, &[17, 7]).await;
+
+    // by name, whichever of the two was read first
+    for _ in 0..3 {
+        let error = load(&dir, CorruptionPolicy::Fail)
+            .await
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Corruption, "{:?}", error);
+        assert_eq!(error.tree(), Some("t07"));
+    }
+}
+
+#[tokio::test]
+async fn skip_loads_every_other_tree() {
+    let dir = ScratchDir::new("concurrency-skip");
+    saved_trees(&dir, &[7]).await;
+
+    let store = load(&dir, CorruptionPolicy::Skip).await.unwrap();
+    assert!(store.load_report().is_none());
+    assert_eq!(store.loaded_trees().await.len(), TREES - 1);
+    for n in (0..TREES).filter(|n| *n != 7) {
+        assert_eq!(all(&store, &tname(n)).await.len(), 3);
+    }
+    assert!(matches!(
+        store.select::<Value>("t07", 1).await,
+        Err(JsonStoreError::TreeCorrupt { .. })
+    ));
+}
+
+#[tokio::test]
+async fn report_lists_the_corrupt_tree_among_the_loaded() {
+    let dir = ScratchDir::new("concurrency-report");
+    saved_trees(&dir, &[7]).await;
+
+    let store = load(&dir, CorruptionPolicy::Report).await.unwrap();
+    let report = store.load_report().unwrap();
+    assert_eq!(report.corrupt(), ["t07"]);
+    assert_eq!(report.trees.len(), TREES);
+    for n in (0..TREES).filter(|n| *n != 7) {
+        assert_eq!(report.trees[&tname(n)], TreeOutcome::Loaded);
+    }
+    assert_eq!(store.loaded_trees().await.len(), TREES - 1);
+}
+
+#[tokio::test]
+async fn preload_reads_the_rest_and_reports_the_first_failure() {
+    let dir = ScratchDir::new("concurrency-preload");
+    saved_trees(&dir, &[17, 7]).await;
+
+    for policy in [
+        CorruptionPolicy::Fail,
+        CorruptionPolicy::Skip,
+        CorruptionPolicy::Report,
+    ] {
+        let store = JsonStore::load_with_options(dir.path(), options(policy, false))
+            .await
+            .unwrap();
+        let names = (0..TREES).map(tname).collect::<Vec<_>>();
+        let names = names.iter().map(String::as_str).collect::<Vec<_>>();
+
+        let error = store.preload(&names).await.unwrap_err();
+        assert_eq!(error.tree(), Some("t07"), "{:?}", policy);
+        assert_eq!(store.loaded_trees().await.len(), TREES - 2, "{:?}", policy);
+        // and the failing trees stay failing
+        assert!(store.preload(&["t17"]).await.is_err());
+        assert_eq!(all(&store, "t18").await.len(), 3);
+    }
+}
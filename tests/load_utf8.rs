@@ -0,0 +1,43 @@
+mod common;
+
+use common::{all, edit, hand_edit, store_with_users, ScratchDir};
+use json_store::{error::JsonStoreError, store::JsonStore};
+use serde_json::json;
+
+#[tokio::test]
+async fn multi_byte_text_across_lines_loads_intact() {
+    let dir = ScratchDir::new("utf8-lines");
+    store_with_users(&dir).await;
+
+    // every line ends on or right after a multi-byte character
+    let name = "ありがとう\n😀é\nü✓";
+    let file = "{\n\"1\": {\"id\": 1, \"email\": \"ü@x\",\n\"name\": \"ありがとう\\n😀é\\nü✓\",\n\"note\": \"😀\"}\n}\n";
+    hand_edit(&dir.path().join("users.json"), file);
+    edit(&dir.path().join("users.seq"), "1");
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(
+        all(&store, "users").await,
+        [json!({"id": 1, "email": "ü@x", "name": name, "note": "😀"})]
+    );
+}
+
+#[tokio::test]
+async fn invalid_utf8_names_the_file() {
+    let dir = ScratchDir::new("utf8-invalid");
+    store_with_users(&dir).await;
+    let path = dir.path().join("users.json");
+    std::fs::remove_file(dir.path().join("users.json.sha256")).unwrap();
+    std::fs::write(&path, b"{\"1\": {\"id\": 1, \"email\": \"\xff\xfe\"}}").unwrap();
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    let err = store
+        .select_where::<serde_json::Value, _>("users", |_| true)
+        .await
+        .unwrap_err();
+    match &err {
+        JsonStoreError::InvalidUtf8 { path: found, .. } => assert_eq!(found, &path),
+        other => panic!("expected InvalidUtf8, got {:?}", other),
+    }
+    assert!(err.to_string().contains("users.json"), "{}", err);
+}
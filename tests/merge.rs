@@ -0,0 +1,246 @@
+mod common;
+
+use common::{all, store_with_users, users, ScratchDir};
+use json_store::{
+    error::JsonStoreError,
+    merge::{MergeOptions, MergePolicy, MergeReport, TreeMerge},
+    store::{Info, JsonStore},
+};
+use serde_json::json;
+
+// users holding a@x and b@x
+async fn here(dir: &ScratchDir) -> JsonStore {
+    let store = store_with_users(dir).await;
+    for (email, name) in [("a@x", "A"), ("b@x", "B")] {
+        store
+            .insert("users", &json!({"email": email, "name": name}))
+            .await
+            .unwrap();
+    }
+    store
+}
+
+// the other store, written to disk: users under info holding c@x, then a@x changed,
+// then b@x as it is here; and a teams tree only it has
+async fn other(dir: &ScratchDir, info: Info) {
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    store.create_tree("users", info).await.unwrap();
+    for (email, name) in [("c@x", "C"), ("a@x", "A2"), ("b@x", "B")] {
+        store
+            .insert("users", &json!({"email": email, "name": name}))
+            .await
+            .unwrap();
+    }
+    store.create_tree("teams", users()).await.unwrap();
+    store
+        .insert("teams", &json!({"email": "t@x"}))
+        .await
+        .unwrap();
+    store.close().await.unwrap();
+}
+
+// here merged with other under policy, and its report
+async fn merged(name: &str, policy: MergePolicy) -> (ScratchDir, JsonStore, MergeReport) {
+    let dir = ScratchDir::new(&format!("merge-{}", name));
+    let other_dir = ScratchDir::new(&format!("merge-{}-other", name));
+    let store = here(&dir).await;
+    other(&other_dir, users()).await;
+
+    let options = MergeOptions {
+        policy,
+        ..Default::default()
+    };
+    let report = store
+        .merge_from_with(other_dir.path(), options)
+        .await
+        .unwrap();
+    // the tree only the other has comes across whatever the policy
+    assert_eq!(report.created, ["teams"]);
+    assert_eq!(
+        all(&store, "teams").await,
+        [json!({"id": 1, "email": "t@x"})]
+    );
+    (dir, store, report)
+}
+
+#[tokio::test]
+async fn prefer_self_keeps_the_record_here() {
+    let (_dir, store, report) = merged("self", MergePolicy::PreferSelf).await;
+
+    // c@x added, b@x the same, a@x settled for the record here
+    assert_eq!(
+        report.trees["users"],
+        TreeMerge {
+            merged: 1,
+            skipped: 2,
+            conflicting: 1,
+        }
+    );
+    assert_eq!(
+        all(&store, "users").await,
+        [
+            json!({"id": 1, "email": "a@x", "name": "A"}),
+            json!({"id": 2, "email": "b@x", "name": "B"}),
+            json!({"id": 3, "email": "c@x", "name": "C"}),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn prefer_other_replaces_it_under_its_sequence_here() {
+    let (_dir, store, report) = merged("other", MergePolicy::PreferOther).await;
+
+    assert_eq!(
+        report.trees["users"],
+        TreeMerge {
+            merged: 2,
+            skipped: 1,
+            conflicting: 1,
+        }
+    );
+    assert_eq!(
+        all(&store, "users").await,
+        [
+            json!({"id": 1, "email": "a@x", "name": "A2"}),
+            json!({"id": 2, "email": "b@x", "name": "B"}),
+            json!({"id": 3, "email": "c@x", "name": "C"}),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn error_leaves_the_tree_unmerged() {
+    let (_dir, store, report) = merged("error", MergePolicy::Error).await;
+
+    assert!(!report.trees.contains_key("users"));
+    match &report.failed[..] {
+        // a@x, as the other store numbers it
+        [(tree, JsonStoreError::MergeConflict { sequence, .. })] => {
+            assert_eq!((tree.as_str(), *sequence), ("users", 2));
+        }
+        other => panic!("{:?}", other),
+    }
+    // not even c@x, which clashed with nothing
+    assert_eq!(
+        all(&store, "users").await,
+        [
+            json!({"id": 1, "email": "a@x", "name": "A"}),
+            json!({"id": 2, "email": "b@x", "name": "B"}),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn a_different_sequence_field_writes_nothing() {
+    let dir = ScratchDir::new("merge-mismatch");
+    let other_dir = ScratchDir::new("merge-mismatch-other");
+    let store = here(&dir).await;
+    store.save().await.unwrap();
+    let uid = Info::builder()
+        .sequence_field("uid")
+        .unique("email", ["email"])
+        .build()
+        .unwrap();
+    other(&other_dir, uid).await;
+    let files =
+        || ["users.json", "users.seq"].map(|name| std::fs::read(dir.path().join(name)).unwrap());
+    let before = files();
+
+    let report = store
+        .merge_from(other_dir.path(), MergePolicy::PreferOther)
+        .await
+        .unwrap();
+    match &report.failed[..] {
+        [(
+            tree,
+            JsonStoreError::InfoMismatch {
+                stored, requested, ..
+            },
+        )] => {
+            assert_eq!(tree, "users");
+            assert_eq!(
+                (
+                    stored.sequence_field.as_str(),
+                    requested.sequence_field.as_str()
+                ),
+                ("id", "uid")
+            );
+        }
+        other => panic!("{:?}", other),
+    }
+
+    // users as it was, in memory and on disk, with its sequence where it was
+    assert_eq!(
+        all(&store, "users").await,
+        [
+            json!({"id": 1, "email": "a@x", "name": "A"}),
+            json!({"id": 2, "email": "b@x", "name": "B"}),
+        ]
+    );
+    assert!(!store.is_dirty("users").await.unwrap());
+    store.save().await.unwrap();
+    assert_eq!(files(), before);
+    let seq = store
+        .insert("users", &json!({"email": "d@x"}))
+        .await
+        .unwrap();
+    assert_eq!(seq, 3);
+}
+
+// A missing unique field counts as null, here as in writes: a record without one
+// stands against a record with it null, and nothing else.
+#[tokio::test]
+async fn a_missing_unique_field_matches_a_null_one() {
+    let dir = ScratchDir::new("merge-null");
+    let other_dir = ScratchDir::new("merge-null-other");
+    let info = || {
+        Info::builder()
+            .sequence_field("id")
+            .unique("email", ["email"])
+            .unique("place", ["team", "desk"])
+            .build()
+            .unwrap()
+    };
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    store.create_tree("users", info()).await.unwrap();
+    for record in [
+        json!({"name": "N", "team": 1, "desk": 1}),
+        json!({"email": "a@x", "team": 1}),
+    ] {
+        store.insert("users", &record).await.unwrap();
+    }
+
+    let other = JsonStore::load(other_dir.path()).await.unwrap();
+    other.create_tree("users", info()).await.unwrap();
+    for record in [
+        // against 1 by email, and 2 by place, with desk null there
+        json!({"email": null, "name": "N2", "team": 2, "desk": 2}),
+        json!({"email": "b@x", "team": 1, "desk": null}),
+        // a null email and a string one are two keys
+        json!({"email": "c@x", "team": 3}),
+    ] {
+        other.insert("users", &record).await.unwrap();
+    }
+    other.close().await.unwrap();
+
+    let report = store
+        .merge_from(other_dir.path(), MergePolicy::PreferOther)
+        .await
+        .unwrap();
+    assert_eq!(
+        report.trees["users"],
+        TreeMerge {
+            merged: 3,
+            skipped: 0,
+            conflicting: 2,
+        }
+    );
+    assert_eq!(
+        all(&store, "users").await,
+        [
+            json!({"id": 1, "email": null, "name": "N2", "team": 2, "desk": 2}),
+            json!({"id": 2, "email": "b@x", "team": 1, "desk": null}),
+            json!({"id": 3, "email": "c@x", "team": 3}),
+        ]
+    );
+}
@@ -0,0 +1,130 @@
+mod common;
+
+use common::{all, fixture, read_json, store_with_users, users, ScratchDir};
+use json_store::{
+    error::JsonStoreError,
+    store::{Info, JsonStore},
+};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn metadata_is_kept_across_a_reload() {
+    let dir = ScratchDir::new("metadata-reload");
+    let store = store_with_users(&dir).await;
+    store
+        .set_tree_metadata("users", "owner", json!("ops"))
+        .await
+        .unwrap();
+    store
+        .set_tree_metadata("users", "limits", json!({"daily": 10, "tags": ["a", "b"]}))
+        .await
+        .unwrap();
+    store
+        .set_tree_metadata("users", "gone", json!(1))
+        .await
+        .unwrap();
+    // Null takes a key out
+    store
+        .set_tree_metadata("users", "gone", json!(null))
+        .await
+        .unwrap();
+
+    // written right away, no save needed
+    drop(store);
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(
+        store.get_tree_metadata("users", "owner").unwrap(),
+        Some(json!("ops"))
+    );
+    assert_eq!(
+        store.get_tree_metadata("users", "limits").unwrap(),
+        Some(json!({"daily": 10, "tags": ["a", "b"]}))
+    );
+    assert_eq!(store.get_tree_metadata("users", "gone").unwrap(), None);
+    assert_eq!(store.get_info("users").unwrap().metadata.len(), 2);
+
+    assert!(matches!(
+        store.get_tree_metadata("teams", "owner"),
+        Err(JsonStoreError::NotFoundTree(_))
+    ));
+}
+
+#[tokio::test]
+async fn a_catalog_without_metadata_loads_with_none() {
+    let dir = ScratchDir::new("metadata-older");
+    // an infos.json from before Info had metadata
+    fixture("format-v1", &dir);
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert!(store.get_info("users").unwrap().metadata.is_empty());
+    assert_eq!(store.get_tree_metadata("users", "owner").unwrap(), None);
+
+    // and an Info without any is written without the field
+    store
+        .set_tree_metadata("users", "owner", json!("ops"))
+        .await
+        .unwrap();
+    let infos = read_json(&dir.path().join("infos.json"));
+    assert_eq!(infos["users"]["metadata"], json!({"owner": "ops"}));
+    assert!(infos["posts"].get("metadata").is_none());
+}
+
+#[tokio::test]
+async fn metadata_stays_out_of_the_records() {
+    let dir = ScratchDir::new("metadata-records");
+    let store = store_with_users(&dir).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store
+        .set_tree_metadata("users", "owner", json!("metadata-owner"))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+
+    assert_eq!(
+        all(&store, "users").await,
+        [json!({"id": 1, "email": "a@x"})]
+    );
+    let data = std::fs::read_to_string(dir.path().join("users.json")).unwrap();
+    assert!(!data.contains("metadata-owner"), "{}", data);
+}
+
+// Infos compare by what their maps hold, whatever order the keys went in
+#[tokio::test]
+async fn infos_with_metadata_set_in_another_order_are_equal() {
+    let forwards = (0..16)
+        .map(|n| (format!("key{}", n), json!(n)))
+        .collect::<Vec<_>>();
+    let backwards = forwards.iter().rev().cloned().collect::<Vec<_>>();
+    let built = |entries: &[(String, Value)]| {
+        entries
+            .iter()
+            .fold(
+                Info::builder().sequence_field("id"),
+                |builder, (key, value)| builder.metadata(key.clone(), value.clone()),
+            )
+            .build()
+            .unwrap()
+    };
+    assert_eq!(built(&forwards), built(&backwards));
+    assert_ne!(built(&forwards), built(&forwards[1..]));
+
+    // and so through the store, for trees given the same metadata in turn
+    let dir = ScratchDir::new("metadata-order");
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    for (tname, entries) in [("forwards", &forwards), ("backwards", &backwards)] {
+        store.create_tree(tname, users()).await.unwrap();
+        for (key, value) in entries {
+            store
+                .set_tree_metadata(tname, key, value.clone())
+                .await
+                .unwrap();
+        }
+    }
+    assert_eq!(
+        store.get_info("forwards").unwrap(),
+        store.get_info("backwards").unwrap()
+    );
+}
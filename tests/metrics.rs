@@ -0,0 +1,207 @@
+mod common;
+
+use common::{users, ScratchDir};
+use futures::future::BoxFuture;
+use json_store::{
+    backend::{FsBackend, Stamp, StorageBackend},
+    error::{ErrorKind, JsonStoreError},
+    metrics::{OpStats, LATENCY_BUCKETS_MICROS},
+    store::{Durability, JsonStore, LoadOptions},
+};
+use serde_json::{json, Value};
+use std::{path::PathBuf, time::Duration};
+
+const N: u64 = 50;
+
+fn options() -> LoadOptions {
+    LoadOptions {
+        metrics: true,
+        ..Default::default()
+    }
+}
+
+// users and teams, N records inserted into users and each read back, then reads of
+// records that aren't there
+async fn workload(store: &JsonStore) {
+    store.create_tree("users", users()).await.unwrap();
+    store.create_tree("teams", users()).await.unwrap();
+    for n in 1..=N {
+        store
+            .insert("users", &json!({"email": format!("{}@x", n)}))
+            .await
+            .unwrap();
+    }
+    for n in 1..=N {
+        store.select::<Value>("users", n).await.unwrap();
+    }
+    for n in N + 1..=N + 5 {
+        store.select::<Value>("users", n).await.unwrap_err();
+    }
+    store
+        .insert("teams", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+}
+
+// what each counted call adds up to, whatever the timings were
+fn assert_consistent(stats: &OpStats) {
+    assert_eq!(
+        stats.latency_buckets.len(),
+        LATENCY_BUCKETS_MICROS.len() + 1
+    );
+    assert_eq!(stats.latency_buckets.iter().sum::<u64>(), stats.count);
+    assert!(stats.total_micros <= stats.max_micros * stats.count);
+    // the slowest call is in the last bucket holding any
+    let last = stats.latency_buckets.iter().rposition(|n| *n > 0).unwrap();
+    let bucket = LATENCY_BUCKETS_MICROS
+        .iter()
+        .position(|bound| stats.max_micros <= *bound)
+        .unwrap_or(LATENCY_BUCKETS_MICROS.len());
+    assert_eq!(last, bucket, "{:?}", stats);
+}
+
+#[tokio::test]
+async fn each_tree_counts_its_own_calls() {
+    let dir = ScratchDir::new("metrics-workload");
+    let store = JsonStore::load_with_options(dir.path(), options())
+        .await
+        .unwrap();
+    workload(&store).await;
+
+    let metrics = store.metrics().unwrap();
+    let users = &metrics.trees["users"];
+    let insert = &users.ops["insert"];
+    assert_eq!((insert.count, insert.error_count()), (N, 0));
+    assert_consistent(insert);
+
+    // the failed reads are counted, by kind
+    let select = &users.ops["select"];
+    assert_eq!(select.count, N + 5);
+    assert_eq!(select.errors.get(&ErrorKind::NotFound), Some(&5));
+    assert_eq!(select.error_count(), 5);
+    assert_consistent(select);
+
+    let teams = &metrics.trees["teams"];
+    assert_eq!(teams.ops["insert"].count, 1);
+    assert!(!teams.ops.contains_key("select"));
+    assert!(users.lock_acquisitions >= 2 * N);
+    assert!(users.lock_contended <= users.lock_acquisitions);
+
+    // a save counts under the store, and under each tree it wrote
+    store.save().await.unwrap();
+    let metrics = store.metrics().unwrap();
+    assert_eq!(metrics.store["save"].count, 1);
+    assert_eq!(metrics.trees["users"].ops["save"].count, 1);
+    assert_eq!(metrics.trees["teams"].ops["save"].count, 1);
+    assert!(!metrics.store.contains_key("insert"));
+}
+
+#[tokio::test]
+async fn a_reset_starts_the_counts_again() {
+    let dir = ScratchDir::new("metrics-reset");
+    let store = JsonStore::load_with_options(dir.path(), options())
+        .await
+        .unwrap();
+    workload(&store).await;
+    let since = store.metrics().unwrap().since;
+
+    store.reset_metrics();
+    let metrics = store.metrics().unwrap();
+    assert!(metrics.trees.is_empty() && metrics.store.is_empty());
+    assert!(metrics.since >= since);
+
+    store.select::<Value>("users", 1).await.unwrap();
+    let metrics = store.metrics().unwrap();
+    assert_eq!(metrics.trees["users"].ops["select"].count, 1);
+    assert_eq!(metrics.trees["users"].ops.len(), 1);
+}
+
+#[tokio::test]
+async fn no_counts_unless_asked_for() {
+    let dir = ScratchDir::new("metrics-off");
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    workload(&store).await;
+    assert!(store.metrics().is_none());
+}
+
+// the disk, taking DELAY over each write
+#[derive(Debug)]
+struct Slow {
+    disk: FsBackend,
+}
+
+const DELAY: Duration = Duration::from_millis(20);
+
+impl StorageBackend for Slow {
+    fn read<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>, JsonStoreError>> {
+        self.disk.read(key)
+    }
+
+    fn write<'a>(
+        &'a self,
+        key: &'a str,
+        bytes: Vec<u8>,
+        durability: Durability,
+    ) -> BoxFuture<'a, Result<(), JsonStoreError>> {
+        Box::pin(async move {
+            tokio::time::sleep(DELAY).await;
+            self.disk.write(key, bytes, durability).await
+        })
+    }
+
+    fn append<'a>(
+        &'a self,
+        key: &'a str,
+        bytes: Vec<u8>,
+        fsync: bool,
+    ) -> BoxFuture<'a, Result<(), JsonStoreError>> {
+        self.disk.append(key, bytes, fsync)
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), JsonStoreError>> {
+        self.disk.delete(key)
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<String>, JsonStoreError>> {
+        self.disk.list()
+    }
+
+    fn stamp<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Stamp>, JsonStoreError>> {
+        self.disk.stamp(key)
+    }
+
+    fn location(&self, key: &str) -> PathBuf {
+        self.disk.location(key)
+    }
+}
+
+#[tokio::test]
+async fn a_slow_call_lands_in_a_slow_bucket() {
+    let dir = ScratchDir::new("metrics-latency");
+    let backend = Slow {
+        disk: FsBackend::new(dir.path()),
+    };
+    let store = JsonStore::load_with_backend(backend, options())
+        .await
+        .unwrap();
+    store.create_tree("users", users()).await.unwrap();
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store.reset_metrics();
+
+    store.save_tree("users").await.unwrap();
+    let metrics = store.metrics().unwrap();
+    let save = &metrics.trees["users"].ops["save"];
+    assert_eq!(save.count, 1);
+    assert!(save.max_micros >= DELAY.as_micros() as u64);
+    assert_eq!(save.total_micros, save.max_micros);
+    // nothing in the buckets below DELAY
+    let slow = LATENCY_BUCKETS_MICROS
+        .iter()
+        .position(|bound| *bound >= DELAY.as_micros() as u64)
+        .unwrap();
+    assert_eq!(save.latency_buckets[..slow].iter().sum::<u64>(), 0);
+    assert_consistent(save);
+}
@@ -0,0 +1,264 @@
+mod common;
+
+use common::{all, read_json, store_with_users, users, ScratchDir};
+use futures::future::BoxFuture;
+use json_store::{
+    backend::{FsBackend, Stamp, StorageBackend},
+    error::{InfoValidationError, JsonStoreError},
+    migrations::Migration,
+    store::{Durability, JsonStore, LoadOptions},
+};
+use serde_json::{json, Value};
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+// counts how many times it ran over each record
+fn bump(record: &mut Value) -> Result<(), String> {
+    let runs = record["runs"].as_u64().unwrap_or(0);
+    record["runs"] = json!(runs + 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn a_step_leaving_two_records_alike_changes_none() {
+    let dir = ScratchDir::new("migrate-unique");
+    let store = store_with_users(&dir).await;
+    for email in ["a@x", "B@x", "b@x"] {
+        store
+            .insert("users", &json!({ "email": email }))
+            .await
+            .unwrap();
+    }
+    store
+        .register_migration(Migration {
+            version: 1,
+            tree: "users".to_string(),
+            up: |record| {
+                let email = record["email"].as_str().unwrap().to_lowercase();
+                record["email"] = json!(email);
+                Ok(())
+            },
+        })
+        .unwrap();
+
+    match store.migrate().await {
+        Err(JsonStoreError::MigrationFailed {
+            version, sequence, ..
+        }) => assert_eq!((version, sequence), (1, Some(3))),
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(store.migration_version("users").unwrap(), 0);
+    let emails = all(&store, "users").await;
+    let emails = emails
+        .iter()
+        .map(|r| r["email"].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(emails, ["a@x", "B@x", "b@x"]);
+
+    // and the constraint still holds after
+    assert!(store
+        .insert("users", &json!({"email": "b@x"}))
+        .await
+        .is_err());
+}
+
+// the disk, failing every write while failing is set
+#[derive(Debug)]
+struct Failing {
+    disk: FsBackend,
+    failing: Arc<AtomicBool>,
+}
+
+impl StorageBackend for Failing {
+    fn read<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>, JsonStoreError>> {
+        self.disk.read(key)
+    }
+
+    fn write<'a>(
+        &'a self,
+        key: &'a str,
+        bytes: Vec<u8>,
+        durability: Durability,
+    ) -> BoxFuture<'a, Result<(), JsonStoreError>> {
+        if self.failing.load(Ordering::SeqCst) {
+            return Box::pin(async move {
+                Err(JsonStoreError::Backend {
+                    key: key.to_string(),
+                    message: "failing".to_string(),
+                })
+            });
+        }
+        self.disk.write(key, bytes, durability)
+    }
+
+    fn append<'a>(
+        &'a self,
+        key: &'a str,
+        bytes: Vec<u8>,
+        fsync: bool,
+    ) -> BoxFuture<'a, Result<(), JsonStoreError>> {
+        self.disk.append(key, bytes, fsync)
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), JsonStoreError>> {
+        self.disk.delete(key)
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<String>, JsonStoreError>> {
+        self.disk.list()
+    }
+
+    fn stamp<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Stamp>, JsonStoreError>> {
+        self.disk.stamp(key)
+    }
+
+    fn location(&self, key: &str) -> PathBuf {
+        self.disk.location(key)
+    }
+}
+
+#[tokio::test]
+async fn a_step_that_fails_to_write_runs_again_on_the_old_records() {
+    let dir = ScratchDir::new("migrate-write");
+    let store = store_with_users(&dir).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store.close().await.unwrap();
+
+    let failing = Arc::new(AtomicBool::new(false));
+    let backend = Failing {
+        disk: FsBackend::new(dir.path()),
+        failing: failing.clone(),
+    };
+    let store = JsonStore::load_with_backend(backend, LoadOptions::default())
+        .await
+        .unwrap();
+    store
+        .register_migration(Migration {
+            version: 1,
+            tree: "users".to_string(),
+            up: bump,
+        })
+        .unwrap();
+
+    failing.store(true, Ordering::SeqCst);
+    assert!(store.migrate().await.is_err());
+    assert_eq!(store.migration_version("users").unwrap(), 0);
+    let record = store.select::<Value>("users", 1).await.unwrap();
+    assert_eq!(record, json!({"id": 1, "email": "a@x"}));
+
+    // so the step runs once over each record when it is tried again
+    failing.store(false, Ordering::SeqCst);
+    let report = store.migrate().await.unwrap();
+    assert_eq!(report.applied.len(), 1);
+    assert_eq!(store.migration_version("users").unwrap(), 1);
+    store.close().await.unwrap();
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(store.migration_version("users").unwrap(), 1);
+    let record = store.select::<Value>("users", 1).await.unwrap();
+    assert_eq!(record["runs"], 1);
+}
+
+#[tokio::test]
+async fn the_old_metadata_key_is_reserved() {
+    let dir = ScratchDir::new("migrate-metadata");
+    let store = store_with_users(&dir).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store
+        .register_migration(Migration {
+            version: 1,
+            tree: "users".to_string(),
+            up: bump,
+        })
+        .unwrap();
+    store.migrate().await.unwrap();
+
+    match store
+        .set_tree_metadata("users", "migration_version", json!(0))
+        .await
+    {
+        Err(JsonStoreError::InvalidTreeInfo { tree, source }) => {
+            assert_eq!(tree, "users");
+            assert_eq!(
+                source,
+                InfoValidationError::ReservedMetadataKey {
+                    key: "migration_version".to_string()
+                }
+            );
+        }
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(store.migration_version("users").unwrap(), 1);
+    assert!(store.migrate().await.unwrap().applied.is_empty());
+
+    // nor can a new tree bring it in with its Info
+    let mut info = users();
+    info.metadata
+        .insert("migration_version".to_string(), json!(0));
+    assert!(matches!(
+        store.create_tree("teams", info).await,
+        Err(JsonStoreError::InvalidTreeInfo { .. })
+    ));
+    store.close().await.unwrap();
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(store.migration_version("users").unwrap(), 1);
+    assert!(store.get_info("users").unwrap().metadata.is_empty());
+}
+
+// a store whose catalog keeps users' version in metadata, as one written before
+// Info::migration_version did
+async fn store_with_version_in_metadata(dir: &ScratchDir, version: Value) {
+    let store = store_with_users(dir).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store.close().await.unwrap();
+
+    let path = dir.path().join("infos.json");
+    let mut infos = read_json(&path);
+    infos["users"]["metadata"] = json!({"migration_version": version, "owner": "me"});
+    std::fs::write(&path, infos.to_string()).unwrap();
+}
+
+#[tokio::test]
+async fn a_version_kept_in_metadata_is_read_as_the_trees() {
+    let dir = ScratchDir::new("migrate-legacy");
+    store_with_version_in_metadata(&dir, json!(2)).await;
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(store.migration_version("users").unwrap(), 2);
+    let metadata = store.get_info("users").unwrap().metadata;
+    assert_eq!(metadata.keys().collect::<Vec<_>>(), ["owner"]);
+    store
+        .register_migration(Migration {
+            version: 2,
+            tree: "users".to_string(),
+            up: bump,
+        })
+        .unwrap();
+    assert!(store.migrate().await.unwrap().applied.is_empty());
+}
+
+#[tokio::test]
+async fn a_version_too_big_for_a_u32_fails_the_load() {
+    let dir = ScratchDir::new("migrate-legacy-overflow");
+    store_with_version_in_metadata(&dir, json!(u32::MAX as u64 + 1)).await;
+
+    match JsonStore::load(dir.path()).await {
+        Err(JsonStoreError::InvalidInfo(reason)) => assert!(reason.contains("users")),
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+}
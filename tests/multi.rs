@@ -0,0 +1,162 @@
+mod common;
+
+use common::{all, hand_edit, store_with_users, ScratchDir};
+use json_store::{
+    error::{ErrorKind, JsonStoreError},
+    multi::{MultiStore, Tagged},
+    store::JsonStore,
+};
+use serde_json::{json, Value};
+
+// stores for tenants "acme" and "initech", with users of their own
+async fn tenants(acme: &ScratchDir, initech: &ScratchDir) -> MultiStore {
+    let multi = MultiStore::new();
+    for (label, dir, emails) in [
+        ("initech", initech, ["peter@i", "milton@i"]),
+        ("acme", acme, ["wile@a", "road@a"]),
+    ] {
+        let store = store_with_users(dir).await;
+        for email in emails {
+            store
+                .insert("users", &json!({ "email": email }))
+                .await
+                .unwrap();
+        }
+        multi.attach(label, store).unwrap();
+    }
+    multi
+}
+
+fn tagged(label: &str, id: u64, email: &str) -> Tagged<Value> {
+    Tagged {
+        label: label.to_string(),
+        record: json!({"id": id, "email": email}),
+    }
+}
+
+#[tokio::test]
+async fn each_label_reaches_its_own_store() {
+    let (acme, initech) = (
+        ScratchDir::new("multi-acme"),
+        ScratchDir::new("multi-initech"),
+    );
+    let multi = tenants(&acme, &initech).await;
+    assert_eq!(multi.labels(), ["acme", "initech"]);
+
+    // the same sequence is a different record in each
+    let first = multi.select::<Value>("acme", "users", 1).await.unwrap();
+    assert_eq!(first["email"], "wile@a");
+    let first = multi.select::<Value>("initech", "users", 1).await.unwrap();
+    assert_eq!(first["email"], "peter@i");
+
+    let seq = multi
+        .insert("acme", "users", &json!({"email": "coyote@a"}))
+        .await
+        .unwrap();
+    assert_eq!(seq, 3);
+    multi
+        .update("acme", "users", &json!({"id": 3, "email": "acme@a"}))
+        .await
+        .unwrap();
+    multi.delete("initech", "users", 2).await.unwrap();
+    let found = multi
+        .select_where::<Value, _>("acme", "users", |user| user["id"] == 3)
+        .await
+        .unwrap();
+    assert_eq!(found, [json!({"id": 3, "email": "acme@a"})]);
+
+    // and writes through it are writes to the store itself
+    let store = multi.store("initech").unwrap();
+    assert!(store.select::<Value>("users", 2).await.is_err());
+    assert_eq!(all(&multi.store("acme").unwrap(), "users").await.len(), 3);
+}
+
+#[tokio::test]
+async fn query_all_reads_every_store_with_the_tree() {
+    let (acme, initech) = (
+        ScratchDir::new("multi-all-acme"),
+        ScratchDir::new("multi-all-initech"),
+    );
+    let multi = tenants(&acme, &initech).await;
+    // a store without the tree is left out rather than failing
+    let empty = ScratchDir::new("multi-all-empty");
+    multi
+        .attach("empty", JsonStore::load(empty.path()).await.unwrap())
+        .unwrap();
+
+    let fan_out = multi
+        .query_all::<Value, _>("users", |user| user["id"] != 2)
+        .await;
+    assert!(fan_out.errors.is_empty());
+    assert_eq!(
+        fan_out.records,
+        [tagged("acme", 1, "wile@a"), tagged("initech", 1, "peter@i")]
+    );
+
+    let fan_out = multi.query_all::<Value, _>("teams", |_| true).await;
+    assert!(fan_out.records.is_empty() && fan_out.errors.is_empty());
+}
+
+#[tokio::test]
+async fn a_failing_store_leaves_the_others_in_the_results() {
+    let (acme, initech) = (
+        ScratchDir::new("multi-fail-acme"),
+        ScratchDir::new("multi-fail-initech"),
+    );
+    // save both, then break initech's users before it is read again
+    let saved = tenants(&acme, &initech).await;
+    for label in saved.labels() {
+        saved.detach(&label).unwrap().close().await.unwrap();
+    }
+    hand_edit(&initech.path().join("users.json"), "{not json");
+
+    let multi = MultiStore::new();
+    for (label, dir) in [("acme", &acme), ("initech", &initech)] {
+        multi
+            .attach(label, JsonStore::load(dir.path()).await.unwrap())
+            .unwrap();
+    }
+    let fan_out = multi.query_all::<Value, _>("users", |_| true).await;
+    assert_eq!(
+        fan_out.records,
+        [tagged("acme", 1, "wile@a"), tagged("acme", 2, "road@a")]
+    );
+    assert_eq!(fan_out.errors.keys().collect::<Vec<_>>(), ["initech"]);
+}
+
+#[tokio::test]
+async fn labels_are_unique_and_must_be_attached() {
+    let (acme, initech) = (
+        ScratchDir::new("multi-label-acme"),
+        ScratchDir::new("multi-label-initech"),
+    );
+    let multi = tenants(&acme, &initech).await;
+
+    let again = multi.store("acme").unwrap();
+    assert!(matches!(
+        multi.attach("acme", again),
+        Err(JsonStoreError::InvalidOptions(_))
+    ));
+
+    match multi.select::<Value>("globex", "users", 1).await {
+        Err(e @ JsonStoreError::UnknownStore(_)) => {
+            assert_eq!(e.kind(), ErrorKind::NotFound);
+            assert!(e.to_string().contains("globex"), "{}", e);
+        }
+        other => panic!("{:?}", other),
+    }
+    assert!(matches!(
+        multi.insert("globex", "users", &json!({})).await,
+        Err(JsonStoreError::UnknownStore(_))
+    ));
+
+    // a detached store is gone from the set, and from clones of it
+    let clone = multi.clone();
+    assert!(multi.detach("initech").is_some());
+    assert!(multi.detach("initech").is_none());
+    assert_eq!(clone.labels(), ["acme"]);
+    assert!(matches!(
+        clone.store("initech"),
+        Err(JsonStoreError::UnknownStore(_))
+    ));
+}
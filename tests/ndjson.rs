@@ -0,0 +1,118 @@
+mod common;
+
+use common::{all, store_with_users, users, ScratchDir};
+use json_store::{
+    error::JsonStoreError,
+    export::ExportFormat,
+    import::{ImportMode, OnConflict},
+};
+use serde_json::json;
+use std::path::PathBuf;
+
+// a broken line in the middle, between blank lines
+const BROKEN: &str = r#"{"email": "a@x"}
+
+{"email": "b@x"
+{"email": "c@x"}
+
+"#;
+
+fn write(dir: &ScratchDir, contents: &str) -> PathBuf {
+    let path = dir.path().join("in.ndjson");
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+#[tokio::test]
+async fn export_writes_a_record_a_line_in_sequence_order() {
+    let dir = ScratchDir::new("ndjson-export");
+    let store = store_with_users(&dir).await;
+    for email in ["a@x", "b@x", "c@x"] {
+        store
+            .insert("users", &json!({ "email": email }))
+            .await
+            .unwrap();
+    }
+    store.delete("users", 2).await.unwrap();
+    let path = dir.path().join("out.ndjson");
+
+    assert_eq!(
+        store
+            .export_tree("users", &path, ExportFormat::NdJson)
+            .await
+            .unwrap(),
+        2
+    );
+    let lines = std::fs::read_to_string(&path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect::<Vec<serde_json::Value>>();
+    assert_eq!(lines, all(&store, "users").await);
+}
+
+#[tokio::test]
+async fn an_exported_file_imports_back_the_same() {
+    let dir = ScratchDir::new("ndjson-round-trip");
+    let store = store_with_users(&dir).await;
+    for email in ["a@x", "b@x", "c@x"] {
+        store
+            .insert("users", &json!({ "email": email }))
+            .await
+            .unwrap();
+    }
+    store.delete("users", 1).await.unwrap();
+    let path = dir.path().join("out.ndjson");
+    store
+        .export_tree("users", &path, ExportFormat::NdJson)
+        .await
+        .unwrap();
+
+    store.create_tree("copy", users()).await.unwrap();
+    let report = store
+        .import_ndjson("copy", &path, ImportMode::Preserve, OnConflict::Fail)
+        .await
+        .unwrap();
+    assert_eq!(report.imported, [2, 3]);
+    assert_eq!(all(&store, "copy").await, all(&store, "users").await);
+}
+
+#[tokio::test]
+async fn strict_import_stops_at_a_broken_line() {
+    let dir = ScratchDir::new("ndjson-strict");
+    let store = store_with_users(&dir).await;
+    let path = write(&dir, BROKEN);
+
+    match store
+        .import_ndjson("users", &path, ImportMode::Append, OnConflict::Fail)
+        .await
+    {
+        Err(JsonStoreError::ImportRejected { tree, index, .. }) => {
+            assert_eq!((tree.as_str(), index), ("users", 3));
+        }
+        other => panic!("expected ImportRejected, got {:?}", other),
+    }
+    assert!(all(&store, "users").await.is_empty());
+}
+
+#[tokio::test]
+async fn lenient_import_skips_a_broken_line_and_counts_it() {
+    let dir = ScratchDir::new("ndjson-lenient");
+    let store = store_with_users(&dir).await;
+    let path = write(&dir, BROKEN);
+
+    let report = store
+        .import_ndjson("users", &path, ImportMode::Append, OnConflict::Skip)
+        .await
+        .unwrap();
+    assert_eq!(report.imported, [1, 2]);
+    assert_eq!(report.skipped.len(), 1);
+    assert_eq!(report.skipped[0].index, 3);
+    assert!(report.skipped[0].reason.starts_with("malformed line"));
+    let emails = all(&store, "users")
+        .await
+        .into_iter()
+        .map(|record| record["email"].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(emails, ["a@x", "c@x"]);
+}
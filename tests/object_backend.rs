@@ -0,0 +1,360 @@
+#![cfg(feature = "object_store")]
+
+mod common;
+
+use async_trait::async_trait;
+use common::{all, users};
+use futures::stream::BoxStream;
+use json_store::{
+    backend::StorageBackend,
+    error::{ErrorKind, JsonStoreError},
+    object_backend::{ObjectStoreBackend, ObjectStoreOptions},
+    store::{Durability, JsonStore, LoadOptions},
+};
+use object_store::{
+    memory::InMemory, path::Path, CopyOptions, GetOptions, GetResult, ListResult, MultipartUpload,
+    ObjectMeta, ObjectStore, ObjectStoreExt, PutMultipartOptions, PutOptions, PutPayload,
+    PutResult,
+};
+use serde_json::{json, Value};
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+// InMemory that can be told to fail its next requests, and that remembers where
+// every PUT went
+#[derive(Debug, Default)]
+struct Flaky {
+    inner: InMemory,
+    failures: AtomicU32,
+    transient: Mutex<bool>,
+    requests: AtomicU32,
+    puts: Mutex<Vec<String>>,
+}
+
+impl Flaky {
+    fn fail_next(&self, requests: u32, transient: bool) {
+        *self.transient.lock().unwrap() = transient;
+        self.failures.store(requests, Ordering::SeqCst);
+        self.requests.store(0, Ordering::SeqCst);
+    }
+
+    fn requests(&self) -> u32 {
+        self.requests.load(Ordering::SeqCst)
+    }
+
+    fn take_puts(&self) -> Vec<String> {
+        std::mem::take(&mut *self.puts.lock().unwrap())
+    }
+
+    fn check(&self, location: &Path) -> object_store::Result<()> {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        let failing = self
+            .failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok();
+        match (failing, *self.transient.lock().unwrap()) {
+            (false, _) => Ok(()),
+            (true, true) => Err(object_store::Error::Generic {
+                store: "flaky",
+                source: "connection reset".into(),
+            }),
+            (true, false) => Err(object_store::Error::Unauthenticated {
+                path: location.to_string(),
+                source: "expired token".into(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Flaky {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Flaky({})", self.inner)
+    }
+}
+
+#[async_trait]
+impl ObjectStore for Flaky {
+    async fn put_opts(
+        &self,
+        location: &Path,
+        payload: PutPayload,
+        opts: PutOptions,
+    ) -> object_store::Result<PutResult> {
+        self.check(location)?;
+        self.puts.lock().unwrap().push(location.to_string());
+        self.inner.put_opts(location, payload, opts).await
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        opts: PutMultipartOptions,
+    ) -> object_store::Result<Box<dyn MultipartUpload>> {
+        self.inner.put_multipart_opts(location, opts).await
+    }
+
+    async fn get_opts(
+        &self,
+        location: &Path,
+        options: GetOptions,
+    ) -> object_store::Result<GetResult> {
+        self.check(location)?;
+        self.inner.get_opts(location, options).await
+    }
+
+    fn delete_stream(
+        &self,
+        locations: BoxStream<'static, object_store::Result<Path>>,
+    ) -> BoxStream<'static, object_store::Result<Path>> {
+        self.inner.delete_stream(locations)
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'static, object_store::Result<ObjectMeta>> {
+        self.inner.list(prefix)
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> object_store::Result<ListResult> {
+        self.inner.list_with_delimiter(prefix).await
+    }
+
+    async fn copy_opts(
+        &self,
+        from: &Path,
+        to: &Path,
+        options: CopyOptions,
+    ) -> object_store::Result<()> {
+        self.inner.copy_opts(from, to, options).await
+    }
+}
+
+fn options() -> ObjectStoreOptions {
+    ObjectStoreOptions {
+        retries: 2,
+        retry_delay: Duration::from_millis(1),
+        ..Default::default()
+    }
+}
+
+fn backend(bucket: &Arc<Flaky>, prefix: &str, options: ObjectStoreOptions) -> ObjectStoreBackend {
+    ObjectStoreBackend::new(bucket.clone(), prefix, options)
+}
+
+async fn load(bucket: &Arc<Flaky>, prefix: &str, options: ObjectStoreOptions) -> JsonStore {
+    JsonStore::load_with_backend(backend(bucket, prefix, options), LoadOptions::default())
+        .await
+        .unwrap()
+}
+
+async fn object(bucket: &Flaky, path: &str) -> Option<Value> {
+    match bucket.inner.get(&Path::from(path)).await {
+        Ok(result) => Some(serde_json::from_slice(&result.bytes().await.unwrap()).unwrap()),
+        Err(object_store::Error::NotFound { .. }) => None,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+#[tokio::test]
+async fn a_store_lives_under_its_prefix() {
+    let bucket = Arc::new(Flaky::default());
+    for (prefix, email) in [("stores/a", "a@x"), ("stores/b", "b@x")] {
+        let store = load(&bucket, prefix, options()).await;
+        store.create_tree("users", users()).await.unwrap();
+        store
+            .insert("users", &json!({"email": email}))
+            .await
+            .unwrap();
+        store.save().await.unwrap();
+    }
+
+    assert_eq!(
+        object(&bucket, "stores/a/users.json").await,
+        Some(json!({"1": {"id": 1, "email": "a@x"}}))
+    );
+    for (prefix, email) in [("stores/a", "a@x"), ("stores/b", "b@x")] {
+        let reloaded = load(&bucket, prefix, options()).await;
+        assert_eq!(
+            all(&reloaded, "users").await,
+            [json!({"id": 1, "email": email})]
+        );
+    }
+
+    let backend = backend(&bucket, "stores/a", options());
+    assert_eq!(
+        backend.location("users.json").to_str(),
+        Some("stores/a/users.json")
+    );
+    // objects of the other prefix, or below this one, are not this store's
+    assert!(backend
+        .list()
+        .await
+        .unwrap()
+        .iter()
+        .all(|key| !key.contains('/')));
+    assert!(load(&bucket, "stores", options())
+        .await
+        .list_trees()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn the_backend_keeps_the_storage_contract() {
+    let bucket = Arc::new(Flaky::default());
+    let backend = backend(&bucket, "p", options());
+
+    assert_eq!(backend.read("a.json").await.unwrap(), None);
+    assert_eq!(backend.stamp("a.json").await.unwrap(), None);
+    backend
+        .write("a.json", b"[1]".to_vec(), Durability::Fsync)
+        .await
+        .unwrap();
+    assert_eq!(backend.read("a.json").await.unwrap().unwrap(), b"[1]");
+    assert_eq!(backend.stamp("a.json").await.unwrap().unwrap().len(), 3);
+
+    backend
+        .append("a.wal", b"one\n".to_vec(), true)
+        .await
+        .unwrap();
+    backend
+        .append("a.wal", b"two\n".to_vec(), true)
+        .await
+        .unwrap();
+    assert_eq!(backend.read("a.wal").await.unwrap().unwrap(), b"one\ntwo\n");
+    assert_eq!(backend.list().await.unwrap(), ["a.json", "a.wal"]);
+
+    backend.delete("a.json").await.unwrap();
+    backend.delete("a.json").await.unwrap();
+    assert_eq!(backend.list().await.unwrap(), ["a.wal"]);
+}
+
+#[tokio::test]
+async fn the_manifest_is_written_after_the_trees() {
+    let bucket = Arc::new(Flaky::default());
+    let with_manifest = ObjectStoreOptions {
+        manifest: true,
+        ..options()
+    };
+    let store = load(&bucket, "p", with_manifest).await;
+    store.create_tree("users", users()).await.unwrap();
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    bucket.take_puts();
+    store.save().await.unwrap();
+
+    let puts = bucket.take_puts();
+    assert_eq!(puts.last().map(String::as_str), Some("p/manifest.json"));
+    assert!(puts.contains(&"p/users.json".to_string()), "{:?}", puts);
+
+    let manifest = object(&bucket, "p/manifest.json").await.unwrap();
+    let objects = manifest["objects"].as_object().unwrap();
+    assert!(objects.contains_key("users.json"));
+    assert!(!objects.contains_key("manifest.json"));
+
+    // an object nobody saved is left out while the manifest is in charge
+    bucket
+        .inner
+        .put(&Path::from("p/stray.json"), PutPayload::from_static(b"{}"))
+        .await
+        .unwrap();
+    let listed = backend(&bucket, "p", with_manifest).list().await.unwrap();
+    assert!(!listed.contains(&"stray.json".to_string()));
+    assert!(backend(&bucket, "p", options())
+        .list()
+        .await
+        .unwrap()
+        .contains(&"stray.json".to_string()));
+
+    let reloaded = load(&bucket, "p", with_manifest).await;
+    assert_eq!(
+        all(&reloaded, "users").await,
+        [json!({"id": 1, "email": "a@x"})]
+    );
+}
+
+#[tokio::test]
+async fn transient_failures_are_retried() {
+    let bucket = Arc::new(Flaky::default());
+    let backend = backend(&bucket, "p", options());
+
+    bucket.fail_next(2, true);
+    backend
+        .write("a.json", b"[1]".to_vec(), Durability::default())
+        .await
+        .unwrap();
+    assert_eq!(bucket.requests(), 3);
+
+    bucket.fail_next(3, true);
+    match backend.read("a.json").await {
+        Err(JsonStoreError::ObjectStore { key, attempts, .. }) => {
+            assert_eq!((key.as_str(), attempts), ("a.json", 3));
+        }
+        other => panic!("expected ObjectStore, got {:?}", other),
+    }
+    assert_eq!(bucket.requests(), 3);
+    assert_eq!(backend.read("a.json").await.unwrap().unwrap(), b"[1]");
+}
+
+#[tokio::test]
+async fn other_failures_are_not_retried() {
+    let bucket = Arc::new(Flaky::default());
+    let backend = backend(&bucket, "p", options());
+
+    bucket.fail_next(1, false);
+    let error = backend
+        .write("a.json", b"[1]".to_vec(), Durability::default())
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        &error,
+        JsonStoreError::ObjectStore {
+            attempts: 1,
+            source: object_store::Error::Unauthenticated { .. },
+            ..
+        }
+    ));
+    assert_eq!(bucket.requests(), 1);
+    assert_eq!(backend.read("a.json").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn network_failures_are_told_apart_from_bad_objects() {
+    let bucket = Arc::new(Flaky::default());
+    let store = load(&bucket, "p", options()).await;
+    store.create_tree("users", users()).await.unwrap();
+    store.save().await.unwrap();
+
+    bucket.fail_next(u32::MAX, true);
+    let network =
+        JsonStore::load_with_backend(backend(&bucket, "p", options()), LoadOptions::default())
+            .await
+            .unwrap_err();
+    assert!(matches!(network, JsonStoreError::ObjectStore { .. }));
+    assert_eq!(network.kind(), ErrorKind::Io);
+
+    bucket.fail_next(0, true);
+    bucket
+        .inner
+        .put(
+            &Path::from("p/users.json"),
+            PutPayload::from_static(b"{\"1\":"),
+        )
+        .await
+        .unwrap();
+    let parse = match load(&bucket, "p", options())
+        .await
+        .select::<Value>("users", 1)
+        .await
+    {
+        Err(e) => e,
+        Ok(_) => panic!("a truncated object read back"),
+    };
+    assert!(!matches!(parse, JsonStoreError::ObjectStore { .. }));
+    assert_eq!(parse.kind(), ErrorKind::Corruption);
+}
@@ -0,0 +1,199 @@
+mod common;
+
+use common::{store_with_users, ScratchDir};
+use json_store::{error::JsonStoreError, index::SortOrder, store::JsonStore};
+use serde_json::{json, Value};
+use std::ops::{Bound, RangeBounds};
+
+// users with an age of each kind of value, or none; sequences 1 to 10
+async fn with_ages(dir: &ScratchDir, indexed: bool) -> JsonStore {
+    let store = store_with_users(dir).await;
+    if indexed {
+        store.create_index_ordered("users", "age").await.unwrap();
+        store.create_index_ordered("users", "name").await.unwrap();
+    }
+    for (n, age) in [
+        json!(30),
+        json!(12.5),
+        json!(-4),
+        json!("thirty"),
+        json!(null),
+        json!(true),
+        json!(12),
+        json!([30]),
+        json!(30.0),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let name = ["eve", "bob", "al", "dan", "cy", "fay", "gus", "hal", "ida"][n];
+        store
+            .insert(
+                "users",
+                &json!({"email": format!("{}@x", name), "name": name, "age": age}),
+            )
+            .await
+            .unwrap();
+    }
+    store
+        .insert("users", &json!({"email": "jo@x", "name": "jo"}))
+        .await
+        .unwrap();
+    store
+}
+
+fn ids(records: Vec<Value>) -> Vec<u64> {
+    records
+        .into_iter()
+        .map(|record| record["id"].as_u64().unwrap())
+        .collect()
+}
+
+// the sequences find_range gives, checked to be the same with the index and without
+async fn range<R: RangeBounds<Value> + Clone>(
+    stores: &[JsonStore; 2],
+    field: &str,
+    range: R,
+) -> Vec<u64> {
+    let indexed = ids(stores[0]
+        .find_range("users", field, range.clone())
+        .await
+        .unwrap());
+    let scanned = ids(stores[1].find_range("users", field, range).await.unwrap());
+    assert_eq!(indexed, scanned, "the index and a scan differ");
+    indexed
+}
+
+async fn stores(name: &str) -> ([JsonStore; 2], [ScratchDir; 2]) {
+    let dirs = [
+        ScratchDir::new(&format!("{}-indexed", name)),
+        ScratchDir::new(&format!("{}-scanned", name)),
+    ];
+    let stores = [
+        with_ages(&dirs[0], true).await,
+        with_ages(&dirs[1], false).await,
+    ];
+    (stores, dirs)
+}
+
+#[tokio::test]
+async fn numbers_are_found_by_value_in_order() {
+    let (stores, _dirs) = stores("ordered-numbers").await;
+
+    // by value and then sequence, an integer and a float equal
+    assert_eq!(
+        range(&stores, "age", json!(12)..=json!(30)).await,
+        [7, 2, 1, 9]
+    );
+    assert_eq!(range(&stores, "age", json!(12)..json!(30)).await, [7, 2]);
+    let above = (Bound::Excluded(json!(12)), Bound::Included(json!(30.0)));
+    assert_eq!(range(&stores, "age", above).await, [2, 1, 9]);
+    assert!(range(&stores, "age", json!(13)..json!(14)).await.is_empty());
+    // a backwards range has nothing in it
+    assert!(range(&stores, "age", json!(30)..json!(12)).await.is_empty());
+}
+
+#[tokio::test]
+async fn strings_are_found_by_their_bytes() {
+    let (stores, _dirs) = stores("ordered-strings").await;
+
+    assert_eq!(
+        range(&stores, "name", json!("bob")..json!("eve")).await,
+        [2, 5, 4]
+    );
+    assert_eq!(range(&stores, "name", json!("gus")..).await, [7, 8, 9, 10]);
+    // "Z" sorts before every lowercase name
+    assert!(range(&stores, "name", ..json!("Z")).await.is_empty());
+}
+
+#[tokio::test]
+async fn an_open_end_keeps_to_the_type_of_the_other() {
+    let (stores, _dirs) = stores("ordered-mixed").await;
+
+    // numbers only, not the null, boolean, string or array below or above them
+    assert_eq!(range(&stores, "age", ..json!(13)).await, [3, 7, 2]);
+    assert_eq!(range(&stores, "age", json!(13)..).await, [1, 9]);
+    assert_eq!(range(&stores, "age", json!("")..).await, [4]);
+    assert!(range(&stores, "age", ..=json!(false)).await.is_empty());
+    assert_eq!(range(&stores, "age", json!(true)..).await, [6]);
+    // a range with both ends given crosses types in compare's order
+    assert_eq!(
+        range(&stores, "age", json!(true)..=json!("thirty")).await,
+        [6, 3, 7, 2, 1, 9, 4]
+    );
+}
+
+#[tokio::test]
+async fn sorted_reads_the_index_in_either_direction() {
+    let (stores, _dirs) = stores("ordered-sorted").await;
+
+    for store in stores.iter() {
+        let oldest = store
+            .find_sorted::<Value>("users", "age", SortOrder::Descending, 3)
+            .await
+            .unwrap();
+        // the array sorts above numbers and strings
+        assert_eq!(ids(oldest), [8, 4, 1]);
+        let first = store
+            .find_sorted::<Value>("users", "name", SortOrder::Ascending, 2)
+            .await
+            .unwrap();
+        assert_eq!(ids(first), [3, 2]);
+    }
+}
+
+#[tokio::test]
+async fn the_index_follows_inserts_updates_and_deletes() {
+    let (stores, dirs) = stores("ordered-writes").await;
+    for store in stores.iter() {
+        store
+            .update("users", &json!({"id": 1, "email": "eve@x", "age": 13}))
+            .await
+            .unwrap();
+        store.delete("users", 7).await.unwrap();
+        store
+            .insert("users", &json!({"email": "kim@x", "age": 12}))
+            .await
+            .unwrap();
+        // from a string to a number, so into the range below
+        store
+            .update("users", &json!({"id": 4, "email": "dan@x", "age": 12.25}))
+            .await
+            .unwrap();
+    }
+    assert_eq!(
+        range(&stores, "age", json!(12)..=json!(30)).await,
+        [11, 4, 2, 1, 9]
+    );
+    assert!(range(&stores, "age", json!("")..).await.is_empty());
+    // the update dropped name, so it is gone from that index
+    assert!(range(&stores, "name", json!("eve")..=json!("eve"))
+        .await
+        .is_empty());
+
+    // the index is declared in the tree's Info and built again at load
+    for store in stores {
+        store.close().await.unwrap();
+    }
+    let stores = [
+        JsonStore::load(dirs[0].path()).await.unwrap(),
+        JsonStore::load(dirs[1].path()).await.unwrap(),
+    ];
+    assert!(stores[0]
+        .get_info("users")
+        .unwrap()
+        .ordered_indexes
+        .contains("age"));
+    assert_eq!(
+        range(&stores, "age", json!(12)..=json!(30)).await,
+        [11, 4, 2, 1, 9]
+    );
+
+    // and a dropped one leaves find_range to scan
+    stores[0].drop_index("users", "age").await.unwrap();
+    assert_eq!(range(&stores, "age", json!(13)..).await, [1, 9]);
+    assert!(matches!(
+        stores[0].drop_index("users", "age").await,
+        Err(JsonStoreError::InvalidOptions(_))
+    ));
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use common::{all, users, ScratchDir};
+use json_store::store::{JsonStore, LoadOptions, OutputFormat};
+use serde_json::json;
+
+async fn store_with(dir: &ScratchDir, format: OutputFormat) -> JsonStore {
+    let options = LoadOptions {
+        format,
+        ..Default::default()
+    };
+    JsonStore::load_with_options(dir.path(), options)
+        .await
+        .unwrap()
+}
+
+// Each store keeps its records in a map of its own, hashed differently, so two stores
+// filled alike still hold them in different orders.
+async fn fill(store: &JsonStore) {
+    store.create_tree("users", users()).await.unwrap();
+    for i in 1..=12 {
+        let record = json!({"email": format!("{}@x", i), "name": "n", "age": i});
+        store.insert("users", &record).await.unwrap();
+    }
+    store.save().await.unwrap();
+}
+
+fn saved(dir: &ScratchDir) -> String {
+    std::fs::read_to_string(dir.path().join("users.json")).unwrap()
+}
+
+#[tokio::test]
+async fn pretty_output_is_byte_identical_across_saves() {
+    let first = ScratchDir::new("format-pretty-a");
+    let second = ScratchDir::new("format-pretty-b");
+    fill(&store_with(&first, OutputFormat::Pretty).await).await;
+    fill(&store_with(&second, OutputFormat::Pretty).await).await;
+
+    let text = saved(&first);
+    assert_eq!(text, saved(&second));
+    assert!(text.lines().count() > 12, "{}", text);
+
+    // sequences ascend numerically, 10 after 9
+    let nine = text.find("\"9\"").unwrap();
+    let ten = text.find("\"10\"").unwrap();
+    assert!(nine < ten);
+    // and fields are sorted, unless arbitrary-precision keeps them as written
+    let record = &text[ten..];
+    let (age, email) = (
+        record.find("\"age\"").unwrap(),
+        record.find("\"email\"").unwrap(),
+    );
+    assert_eq!(age < email, cfg!(not(feature = "arbitrary-precision")));
+
+    // saving again unchanged gives the same bytes
+    let store = store_with(&first, OutputFormat::Pretty).await;
+    store.save_tree_force("users").await.unwrap();
+    assert_eq!(saved(&first), text);
+}
+
+#[tokio::test]
+async fn compact_is_the_default_and_both_load_alike() {
+    let compact = ScratchDir::new("format-compact");
+    let pretty = ScratchDir::new("format-pretty");
+    fill(&JsonStore::load(compact.path()).await.unwrap()).await;
+    fill(&store_with(&pretty, OutputFormat::Pretty).await).await;
+
+    assert_eq!(saved(&compact).lines().count(), 1);
+    let a = all(&JsonStore::load(compact.path()).await.unwrap(), "users").await;
+    let b = all(&JsonStore::load(pretty.path()).await.unwrap(), "users").await;
+    assert_eq!(a.len(), 12);
+    assert_eq!(a, b);
+}
@@ -0,0 +1,418 @@
+mod common;
+
+use common::{all, read_json, ScratchDir};
+use futures::future::BoxFuture;
+use json_store::{
+    backend::{FsBackend, Stamp, StorageBackend},
+    error::JsonStoreError,
+    partition::{Granularity, PartitionStats},
+    store::{Durability, Info, JsonStore, LoadOptions},
+};
+use serde_json::{json, Value};
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+fn events(granularity: Granularity) -> Info {
+    Info::builder()
+        .sequence_field("id")
+        .partition_by("at", granularity)
+        .build()
+        .unwrap()
+}
+
+// midnight UTC at the start of a date from 1970 on
+fn at(date: &str) -> SystemTime {
+    let mut parts = date.split('-').map(|part| part.parse::<u64>().unwrap());
+    let (year, month, day) = (
+        parts.next().unwrap(),
+        parts.next().unwrap(),
+        parts.next().unwrap(),
+    );
+    let leap = |year: u64| {
+        year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
+    };
+    let mut days = (1970..year)
+        .map(|year| if leap(year) { 366 } else { 365 })
+        .sum::<u64>();
+    // days in the year before each month
+    let before = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+    days += before[month as usize - 1] + day - 1;
+    if month > 2 && leap(year) {
+        days += 1;
+    }
+    UNIX_EPOCH + Duration::from_secs(days * 86_400)
+}
+
+fn stats(partitions: Vec<PartitionStats>) -> Vec<(String, usize, bool)> {
+    partitions
+        .into_iter()
+        .map(|p| (p.period, p.records, p.loaded))
+        .collect()
+}
+
+fn stat(period: &str, records: usize, loaded: bool) -> (String, usize, bool) {
+    (period.to_string(), records, loaded)
+}
+
+// events in June and July 2024 and two without a usable time, saved and read again
+// so that none of the partitions is loaded
+async fn store_with_events(dir: &ScratchDir, granularity: Granularity) -> JsonStore {
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    store
+        .create_tree("events", events(granularity))
+        .await
+        .unwrap();
+    let june_2 = at("2024-06-02").duration_since(UNIX_EPOCH).unwrap();
+    for record in [
+        json!({"name": "a", "at": "2024-06-01"}),
+        json!({"name": "b", "at": june_2.as_millis() as u64 + 1000}),
+        // still June in UTC
+        json!({"name": "c", "at": "2024-07-01T01:00:00+02:00"}),
+        json!({"name": "d", "at": "2024-07-15T12:00:00.250Z"}),
+        json!({"name": "e"}),
+        json!({"name": "f", "at": "soon"}),
+    ] {
+        store.insert("events", &record).await.unwrap();
+    }
+    store.close().await.unwrap();
+    JsonStore::load(dir.path()).await.unwrap()
+}
+
+fn names(records: Vec<Value>) -> Vec<String> {
+    records
+        .into_iter()
+        .map(|record| record["name"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn records_are_filed_by_month() {
+    let dir = ScratchDir::new("partition-month");
+    let store = store_with_events(&dir, Granularity::Month).await;
+
+    assert_eq!(
+        stats(store.partitions("events").await.unwrap()),
+        [
+            stat("2024-06", 3, false),
+            stat("2024-07", 1, false),
+            stat("undated", 2, false),
+        ]
+    );
+    let tree = dir.path().join("events");
+    assert_eq!(
+        read_json(&tree.join("2024-06.json"))
+            .as_object()
+            .unwrap()
+            .keys()
+            .collect::<Vec<_>>(),
+        ["1", "2", "3"]
+    );
+    assert_eq!(
+        read_json(&dir.path().join("events.partitions.json")),
+        json!({"2024-06": [1, 2, 3], "2024-07": [4], "undated": [5, 6]})
+    );
+    assert!(!dir.path().join("events.json").exists());
+}
+
+#[tokio::test]
+async fn records_are_filed_by_day() {
+    let dir = ScratchDir::new("partition-day");
+    let store = store_with_events(&dir, Granularity::Day).await;
+
+    let periods = stats(store.partitions("events").await.unwrap())
+        .into_iter()
+        .map(|(period, records, _)| (period, records))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        periods,
+        [
+            ("2024-06-01".to_string(), 1),
+            ("2024-06-02".to_string(), 1),
+            ("2024-06-30".to_string(), 1),
+            ("2024-07-15".to_string(), 1),
+            ("undated".to_string(), 2),
+        ]
+    );
+    assert!(dir.path().join("events/2024-06-30.json").exists());
+}
+
+#[tokio::test]
+async fn partitions_are_read_as_they_are_used() {
+    let dir = ScratchDir::new("partition-lazy");
+    let store = store_with_events(&dir, Granularity::Month).await;
+    let loaded = |store: JsonStore| async move {
+        stats(store.partitions("events").await.unwrap())
+            .into_iter()
+            .filter(|(_, _, loaded)| *loaded)
+            .map(|(period, _, _)| period)
+            .collect::<Vec<_>>()
+    };
+
+    // a select reads the partition of its record
+    let d = store.select::<Value>("events", 4).await.unwrap();
+    assert_eq!(d["name"], "d");
+    assert_eq!(loaded(store.clone()).await, ["2024-07"]);
+
+    // a range the periods it overlaps, and only its records
+    let found = store
+        .select_range::<Value>("events", at("2024-06-02"), at("2024-06-30"))
+        .await
+        .unwrap();
+    assert_eq!(names(found), ["b"]);
+    assert_eq!(loaded(store.clone()).await, ["2024-06", "2024-07"]);
+
+    // an insert the one it goes to, new or not
+    store
+        .insert("events", &json!({"name": "g", "at": "2024-08-01"}))
+        .await
+        .unwrap();
+    assert_eq!(
+        loaded(store.clone()).await,
+        ["2024-06", "2024-07", "2024-08"]
+    );
+
+    // and an update both the one it leaves and the one it moves to
+    store
+        .update("events", &json!({"id": 6, "name": "f", "at": "2024-07-02"}))
+        .await
+        .unwrap();
+    assert_eq!(
+        loaded(store.clone()).await,
+        ["2024-06", "2024-07", "2024-08", "undated"]
+    );
+    store.close().await.unwrap();
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(
+        read_json(&dir.path().join("events.partitions.json")),
+        json!({"2024-06": [1, 2, 3], "2024-07": [4, 6], "2024-08": [7], "undated": [5]})
+    );
+    // what reads the whole tree reads every partition
+    assert_eq!(
+        names(all(&store, "events").await),
+        ["a", "b", "c", "d", "e", "f", "g"]
+    );
+    assert_eq!(
+        loaded(store).await,
+        ["2024-06", "2024-07", "2024-08", "undated"]
+    );
+}
+
+#[tokio::test]
+async fn old_partitions_can_be_unloaded() {
+    let dir = ScratchDir::new("partition-unload");
+    let store = store_with_events(&dir, Granularity::Month).await;
+    assert_eq!(all(&store, "events").await.len(), 6);
+    store
+        .insert("events", &json!({"name": "g", "at": "2024-06-03"}))
+        .await
+        .unwrap();
+
+    // the insert is saved first, and undated records are never before anything
+    let unloaded = store
+        .unload_partitions_before("events", at("2024-07-01"))
+        .await
+        .unwrap();
+    assert_eq!(unloaded, ["2024-06"]);
+    assert!(!store.is_dirty("events").await.unwrap());
+    assert_eq!(
+        stats(store.partitions("events").await.unwrap()),
+        [
+            stat("2024-06", 4, false),
+            stat("2024-07", 1, true),
+            stat("undated", 2, true),
+        ]
+    );
+
+    // and the next read brings them back
+    assert_eq!(
+        store.select::<Value>("events", 7).await.unwrap()["name"],
+        "g"
+    );
+    assert!(stats(store.partitions("events").await.unwrap())[0].2);
+}
+
+#[tokio::test]
+async fn old_partitions_can_be_archived() {
+    let dir = ScratchDir::new("partition-archive");
+    let store = store_with_events(&dir, Granularity::Month).await;
+
+    let archived = store
+        .archive_partitions_before("events", at("2024-07-01"))
+        .await
+        .unwrap();
+    assert_eq!(archived, ["2024-06"]);
+    assert_eq!(names(all(&store, "events").await), ["d", "e", "f"]);
+    assert!(!dir.path().join("events/2024-06.json").exists());
+    let aside = archive_dir(dir.path());
+    assert_eq!(
+        read_json(&aside.join("events/2024-06.json"))
+            .as_object()
+            .unwrap()
+            .len(),
+        3
+    );
+
+    // their sequences aren't handed out again
+    let seq = store
+        .insert("events", &json!({"name": "g", "at": "2024-06-05"}))
+        .await
+        .unwrap();
+    assert_eq!(seq, 7);
+    // and with nothing before the cutoff, nothing more is archived
+    assert!(store
+        .archive_partitions_before("events", at("2024-06-01"))
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+// the one `.archive-*` directory in dir
+fn archive_dir(dir: &Path) -> PathBuf {
+    let archives = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.file_name()
+                .unwrap()
+                .to_string_lossy()
+                .starts_with(".archive-")
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(archives.len(), 1, "{:?}", archives);
+    archives.into_iter().next().unwrap()
+}
+
+// An FsBackend that fails the writes, or the deletes, of keys ending in failing, as
+// a crash at that point would leave the files.
+#[derive(Debug)]
+struct Crashing {
+    disk: FsBackend,
+    failing: &'static str,
+    deletes: bool,
+}
+
+fn crashed(key: &str) -> JsonStoreError {
+    JsonStoreError::Backend {
+        key: key.to_string(),
+        message: "crashed".to_string(),
+    }
+}
+
+impl StorageBackend for Crashing {
+    fn read<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>, JsonStoreError>> {
+        self.disk.read(key)
+    }
+
+    fn write<'a>(
+        &'a self,
+        key: &'a str,
+        bytes: Vec<u8>,
+        durability: Durability,
+    ) -> BoxFuture<'a, Result<(), JsonStoreError>> {
+        if !self.deletes && key.ends_with(self.failing) {
+            return Box::pin(async move { Err(crashed(key)) });
+        }
+        self.disk.write(key, bytes, durability)
+    }
+
+    fn append<'a>(
+        &'a self,
+        key: &'a str,
+        bytes: Vec<u8>,
+        fsync: bool,
+    ) -> BoxFuture<'a, Result<(), JsonStoreError>> {
+        self.disk.append(key, bytes, fsync)
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), JsonStoreError>> {
+        if self.deletes && key.ends_with(self.failing) {
+            return Box::pin(async move { Err(crashed(key)) });
+        }
+        self.disk.delete(key)
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<String>, JsonStoreError>> {
+        self.disk.list()
+    }
+
+    fn stamp<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Stamp>, JsonStoreError>> {
+        self.disk.stamp(key)
+    }
+
+    fn location(&self, key: &str) -> PathBuf {
+        self.disk.location(key)
+    }
+}
+
+async fn archive_crashing(dir: &ScratchDir, failing: &'static str, deletes: bool) {
+    store_with_events(dir, Granularity::Month)
+        .await
+        .close()
+        .await
+        .unwrap();
+    let backend = Crashing {
+        disk: FsBackend::new(dir.path()),
+        failing,
+        deletes,
+    };
+    let store = JsonStore::load_with_backend(backend, LoadOptions::default())
+        .await
+        .unwrap();
+    assert!(matches!(
+        store
+            .archive_partitions_before("events", at("2024-07-01"))
+            .await,
+        Err(JsonStoreError::Backend { .. })
+    ));
+}
+
+#[tokio::test]
+async fn a_crash_before_the_index_is_written_keeps_the_records_in_the_tree() {
+    let dir = ScratchDir::new("partition-archive-index");
+    archive_crashing(&dir, "events.partitions.json", false).await;
+
+    // copied aside, but the tree still lists and holds them
+    let aside = archive_dir(dir.path());
+    assert!(aside.join("events/2024-06.json").exists());
+    assert!(dir.path().join("events/2024-06.json").exists());
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(all(&store, "events").await.len(), 6);
+}
+
+#[tokio::test]
+async fn a_crash_before_the_files_are_removed_keeps_them_archived() {
+    let dir = ScratchDir::new("partition-archive-delete");
+    archive_crashing(&dir, "2024-06.json", true).await;
+
+    // the index no longer lists them, so the tree is without them, and the archive
+    // has them
+    assert_eq!(
+        read_json(&dir.path().join("events.partitions.json")),
+        json!({"2024-07": [4], "undated": [5, 6]})
+    );
+    let aside = archive_dir(dir.path());
+    assert!(aside.join("events/2024-06.json").exists());
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(names(all(&store, "events").await), ["d", "e", "f"]);
+}
+
+#[tokio::test]
+async fn only_partitioned_trees_have_partitions() {
+    let dir = ScratchDir::new("partition-none");
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    store
+        .create_tree(
+            "users",
+            Info::builder().sequence_field("id").build().unwrap(),
+        )
+        .await
+        .unwrap();
+    assert!(store.partitions("users").await.is_err());
+    assert!(store
+        .select_range::<Value>("users", UNIX_EPOCH, SystemTime::now())
+        .await
+        .is_err());
+}
@@ -0,0 +1,131 @@
+mod common;
+
+use common::{store_with_users, ScratchDir};
+use json_store::{
+    profile::{FieldProfile, NumberStats, StringStats, TypeCounts, ValueCount, TOP_VALUES},
+    store::JsonStore,
+};
+use serde_json::json;
+
+// six users: "age" is a number in three, null in one, text in one and missing from
+// one; "city" a string in four
+async fn store_with_people(dir: &ScratchDir) -> JsonStore {
+    let store = store_with_users(dir).await;
+    for person in [
+        json!({"email": "a@x", "age": 30, "city": "Oslo"}),
+        json!({"email": "b@x", "age": 13.5, "city": "Lima"}),
+        json!({"email": "c@x", "age": 30, "city": "Oslo", "tags": ["x"]}),
+        json!({"email": "d@x", "age": null, "city": "Århus"}),
+        json!({"email": "e@x", "age": "old", "admin": true}),
+        json!({"email": "f@x", "meta": {"k": 1}}),
+    ] {
+        store.insert("users", &person).await.unwrap();
+    }
+    store
+}
+
+#[tokio::test]
+async fn a_field_is_profiled_over_every_record() {
+    let dir = ScratchDir::new("profile-field");
+    let store = store_with_people(&dir).await;
+
+    assert_eq!(
+        store.profile_field("users", "age").await.unwrap(),
+        FieldProfile {
+            field: "age".to_string(),
+            present: 4,
+            null: 1,
+            missing: 1,
+            types: TypeCounts {
+                numbers: 3,
+                strings: 1,
+                ..Default::default()
+            },
+            numbers: Some(NumberStats {
+                min: 13.5,
+                max: 30.0,
+                mean: 24.5,
+            }),
+            strings: Some(StringStats {
+                min: "old".to_string(),
+                max: "old".to_string(),
+                min_length: 3,
+                max_length: 3,
+                mean_length: 3.0,
+            }),
+            top_values: vec![
+                ValueCount {
+                    value: json!(30),
+                    count: 2,
+                },
+                ValueCount {
+                    value: json!("old"),
+                    count: 1,
+                },
+                ValueCount {
+                    value: json!(13.5),
+                    count: 1,
+                },
+            ],
+        }
+    );
+}
+
+#[tokio::test]
+async fn string_lengths_count_chars() {
+    let dir = ScratchDir::new("profile-strings");
+    let store = store_with_people(&dir).await;
+
+    let city = store.profile_field("users", "city").await.unwrap();
+    assert_eq!((city.present, city.null, city.missing), (4, 0, 2));
+    assert!(city.numbers.is_none());
+    // "Århus" is five chars in six bytes, and sorts after the ASCII names
+    assert_eq!(
+        city.strings,
+        Some(StringStats {
+            min: "Lima".to_string(),
+            max: "Århus".to_string(),
+            min_length: 4,
+            max_length: 5,
+            mean_length: 4.25,
+        })
+    );
+    assert_eq!(city.top_values[0].value, "Oslo");
+    assert_eq!(city.top_values[0].count, 2);
+}
+
+#[tokio::test]
+async fn a_tree_is_profiled_field_by_field() {
+    let dir = ScratchDir::new("profile-tree");
+    let store = store_with_people(&dir).await;
+
+    let profiles = store.profile_tree("users").await.unwrap();
+    assert_eq!(
+        profiles.keys().collect::<Vec<_>>(),
+        ["admin", "age", "city", "email", "id", "meta", "tags"]
+    );
+    // as profile_field gives them
+    for field in ["age", "city"] {
+        assert_eq!(
+            profiles[field],
+            store.profile_field("users", field).await.unwrap()
+        );
+    }
+    let kinds = |field: &str| profiles[field].types.clone();
+    assert_eq!(kinds("admin").bools, 1);
+    assert_eq!(kinds("meta").objects, 1);
+    assert_eq!(kinds("tags").arrays, 1);
+    assert_eq!(profiles["tags"].missing, 5);
+
+    // every email is its own value, and only the most frequent few are listed
+    let email = &profiles["email"];
+    assert_eq!(email.present, 6);
+    assert_eq!(email.top_values.len(), 6.min(TOP_VALUES));
+    assert_eq!(email.top_values[0].value, "a@x");
+
+    // and a field no record has is missing from all of them
+    let none = store.profile_field("users", "nothing").await.unwrap();
+    assert_eq!((none.present, none.missing), (0, 6));
+    assert!(none.numbers.is_none() && none.strings.is_none());
+    assert!(none.top_values.is_empty());
+}
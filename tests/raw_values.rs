@@ -0,0 +1,186 @@
+mod common;
+
+use common::{all, ScratchDir};
+use json_store::{
+    error::JsonStoreError,
+    raw::ValueMode,
+    store::{Info, JsonStore},
+};
+use serde_json::{json, Value};
+
+fn docs(raw: bool) -> Info {
+    let mode = match raw {
+        true => ValueMode::Raw,
+        false => ValueMode::Parsed,
+    };
+    Info::builder()
+        .sequence_field("id")
+        .value_mode(mode)
+        .build()
+        .unwrap()
+}
+
+async fn store_with_docs(dir: &ScratchDir) -> JsonStore {
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    store.create_tree("docs", docs(true)).await.unwrap();
+    store
+}
+
+// text serde_json would write differently: spacing, key order, number forms, escapes
+const DOCUMENTS: [&str; 4] = [
+    r#"{ "b" : 1.50, "a":[ 1e3, -0.0 ],"z" :"café \"q\"" }"#,
+    "{\n  \"nested\": {\"deep\": {\"er\": [true, false, null]}},\n  \"big\": 123456789012345678901234567890\n}",
+    r#"{}"#,
+    r#"{"text":"😀 tab\t", "dup": 1, "dup": 2}"#,
+];
+
+#[tokio::test]
+async fn documents_come_back_byte_for_byte() {
+    let dir = ScratchDir::new("raw-round-trip");
+    let store = store_with_docs(&dir).await;
+
+    let mut expected = Vec::new();
+    for document in DOCUMENTS {
+        let seq = store.insert_raw("docs", document).await.unwrap();
+        // the sequence goes in as the first field, the rest untouched
+        let open = document.find('{').unwrap() + 1;
+        let separator = if document == "{}" { "" } else { "," };
+        let text = format!(
+            "{}\"id\":{}{}{}",
+            &document[..open],
+            seq,
+            separator,
+            &document[open..]
+        );
+        assert_eq!(store.select_raw("docs", seq).await.unwrap().get(), text);
+        expected.push((seq, text));
+    }
+    store.save().await.unwrap();
+
+    let reloaded = JsonStore::load(dir.path()).await.unwrap();
+    for (seq, text) in expected {
+        assert_eq!(reloaded.select_raw("docs", seq).await.unwrap().get(), text);
+    }
+}
+
+#[tokio::test]
+async fn a_sequence_field_in_the_document_is_overwritten_in_place() {
+    let dir = ScratchDir::new("raw-sequence-field");
+    let store = store_with_docs(&dir).await;
+
+    let seq = store
+        .insert_raw("docs", r#"{"a": 1,  "id" : 999 , "b": 2}"#)
+        .await
+        .unwrap();
+    assert_eq!(seq, 1);
+    assert_eq!(
+        store.select_raw("docs", 1).await.unwrap().get(),
+        r#"{"a": 1,  "id" : 1 , "b": 2}"#
+    );
+
+    let update = r#"{"b":  3, "id": 1}"#;
+    store.update_raw("docs", update).await.unwrap();
+    assert_eq!(store.select_raw("docs", 1).await.unwrap().get(), update);
+    assert!(matches!(
+        store.update_raw("docs", r#"{"id": 7}"#).await,
+        Err(JsonStoreError::SequenceNotExist(_))
+    ));
+}
+
+#[tokio::test]
+async fn raw_and_parsed_trees_agree_on_values() {
+    let dir = ScratchDir::new("raw-agree");
+    let store = store_with_docs(&dir).await;
+    store.create_tree("parsed", docs(false)).await.unwrap();
+
+    for document in &DOCUMENTS[..3] {
+        let raw = store.insert_raw("docs", document).await.unwrap();
+        let parsed = store.insert_raw("parsed", document).await.unwrap();
+        assert_eq!(
+            store.select::<Value>("docs", raw).await.unwrap(),
+            store.select::<Value>("parsed", parsed).await.unwrap()
+        );
+    }
+    assert_eq!(all(&store, "docs").await, all(&store, "parsed").await);
+
+    // a parsed tree gives serde_json's rendering, not the text it was given
+    let text = store.select_raw("parsed", 3).await.unwrap();
+    assert_eq!(text.get(), r#"{"id":3}"#);
+
+    // queries parse the raw tree and find the same
+    let big = |v: &Value| v.get("big").is_some();
+    assert_eq!(
+        store.select_where::<Value, _>("docs", big).await.unwrap(),
+        store.select_where::<Value, _>("parsed", big).await.unwrap()
+    );
+}
+
+#[tokio::test]
+async fn values_and_raw_text_mix_in_a_raw_tree() {
+    let dir = ScratchDir::new("raw-mixed");
+    let store = store_with_docs(&dir).await;
+
+    store.insert_raw("docs", r#"{"n":  1}"#).await.unwrap();
+    store.insert("docs", &json!({"n": 2})).await.unwrap();
+    store
+        .update("docs", &json!({"id": 1, "n": 10}))
+        .await
+        .unwrap();
+    store.delete("docs", 2).await.unwrap();
+    store.save().await.unwrap();
+
+    let reloaded = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(all(&reloaded, "docs").await, [json!({"id": 1, "n": 10})]);
+}
+
+#[tokio::test]
+async fn raw_trees_still_keep_their_constraints() {
+    let dir = ScratchDir::new("raw-constraints");
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    let info = Info::builder()
+        .sequence_field("id")
+        .value_mode(ValueMode::Raw)
+        .unique("email", ["email"])
+        .capacity(2)
+        .build()
+        .unwrap();
+    store.create_tree("docs", info).await.unwrap();
+
+    store
+        .insert_raw("docs", r#"{"email":"a@x"}"#)
+        .await
+        .unwrap();
+    assert!(matches!(
+        store.insert_raw("docs", r#"{ "email" : "a@x" }"#).await,
+        Err(JsonStoreError::DuplicateUniqueFields(_))
+    ));
+    store
+        .insert_raw("docs", r#"{"email":"b@x"}"#)
+        .await
+        .unwrap();
+    assert!(matches!(
+        store.insert_raw("docs", r#"{"email":"c@x"}"#).await,
+        Err(JsonStoreError::CapacityExceeded(_))
+    ));
+}
+
+#[tokio::test]
+async fn raw_records_take_less_memory() {
+    let dir = ScratchDir::new("raw-memory");
+    let store = store_with_docs(&dir).await;
+    store.create_tree("parsed", docs(false)).await.unwrap();
+
+    let document = json!({
+        "tags": (0..50).map(|n| format!("t{}", n)).collect::<Vec<_>>(),
+        "nested": {"a": {"b": {"c": [1, 2, 3, 4, 5, 6, 7, 8]}}},
+    })
+    .to_string();
+    for _ in 0..200 {
+        store.insert_raw("docs", &document).await.unwrap();
+        store.insert_raw("parsed", &document).await.unwrap();
+    }
+
+    let raw = store.memory_estimate("docs").await.unwrap();
+    let parsed = store.memory_estimate("parsed").await.unwrap();
+    assert!(raw * 2 < parsed, "raw {} parsed {}", raw, parsed);
+}
@@ -0,0 +1,249 @@
+mod common;
+
+use common::{users, ScratchDir};
+use json_store::{
+    error::JsonStoreError,
+    handle::TreeHandle,
+    store::{JsonStore, LoadOptions},
+};
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, time::Duration};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct User {
+    #[serde(default)]
+    id: u64,
+    email: String,
+    visits: u64,
+}
+
+fn user(n: u64) -> User {
+    User {
+        id: 0,
+        email: format!("{}@x", n),
+        visits: 0,
+    }
+}
+
+async fn load(dir: &ScratchDir) -> JsonStore {
+    let options = LoadOptions {
+        metrics: true,
+        ..Default::default()
+    };
+    let store = JsonStore::load_with_options(dir.path(), options)
+        .await
+        .unwrap();
+    store.create_tree("users", users()).await.unwrap();
+    store
+}
+
+// a cached handle over users with records 1 to n
+async fn cached(store: &JsonStore, n: u64, capacity: usize) -> TreeHandle<User> {
+    let handle = store.tree::<User>("users").unwrap().with_cache(capacity);
+    for n in 1..=n {
+        handle.insert(&user(n)).await.unwrap();
+    }
+    handle
+}
+
+fn hits_and_misses(store: &JsonStore) -> (u64, u64) {
+    let metrics = store.metrics().unwrap();
+    let users = &metrics.trees["users"];
+    (users.cache_hits, users.cache_misses)
+}
+
+#[tokio::test]
+async fn a_second_read_is_answered_from_the_cache() {
+    let dir = ScratchDir::new("read_cache_hit");
+    let store = load(&dir).await;
+    let handle = cached(&store, 3, 10).await;
+
+    let first = handle.select_cached(2).await.unwrap();
+    let second = handle.select_cached(2).await.unwrap();
+    assert_eq!(first.email, "2@x");
+    assert!(Arc::ptr_eq(&first, &second));
+    assert_eq!(hits_and_misses(&store), (1, 1));
+
+    // clones of the handle share its cache
+    let clone = handle.clone();
+    assert!(Arc::ptr_eq(&first, &clone.select_cached(2).await.unwrap()));
+    assert_eq!(hits_and_misses(&store), (2, 1));
+}
+
+#[tokio::test]
+async fn a_handle_without_a_cache_reads_the_tree_each_time() {
+    let dir = ScratchDir::new("read_cache_none");
+    let store = load(&dir).await;
+    let handle = store.tree::<User>("users").unwrap();
+    handle.insert(&user(1)).await.unwrap();
+
+    let first = handle.select_cached(1).await.unwrap();
+    let second = handle.select_cached(1).await.unwrap();
+    assert_eq!(first, second);
+    assert!(!Arc::ptr_eq(&first, &second));
+    assert_eq!(hits_and_misses(&store), (0, 0));
+}
+
+#[tokio::test]
+async fn an_update_drops_the_cached_record() {
+    let dir = ScratchDir::new("read_cache_update");
+    let store = load(&dir).await;
+    let handle = cached(&store, 3, 10).await;
+    let mut record = (*handle.select_cached(1).await.unwrap()).clone();
+    handle.select_cached(2).await.unwrap();
+
+    // through the handle itself
+    record.visits = 1;
+    handle.update(&record).await.unwrap();
+    assert_eq!(handle.select_cached(1).await.unwrap().visits, 1);
+
+    // through the store, and through a handle of its own
+    record.visits = 2;
+    store.update("users", &record).await.unwrap();
+    assert_eq!(handle.select_cached(1).await.unwrap().visits, 2);
+    record.visits = 3;
+    let other = store.tree::<User>("users").unwrap();
+    other.update(&record).await.unwrap();
+    assert_eq!(handle.select_cached(1).await.unwrap().visits, 3);
+
+    // record 2 was left alone all along
+    let (hits, misses) = hits_and_misses(&store);
+    handle.select_cached(2).await.unwrap();
+    assert_eq!(hits_and_misses(&store), (hits + 1, misses));
+}
+
+#[tokio::test]
+async fn a_delete_drops_the_cached_record() {
+    let dir = ScratchDir::new("read_cache_delete");
+    let store = load(&dir).await;
+    let handle = cached(&store, 3, 10).await;
+    handle.select_cached(1).await.unwrap();
+    handle.select_cached(3).await.unwrap();
+
+    handle.delete(1).await.unwrap();
+    store.delete("users", 3).await.unwrap();
+    for sequence in [1, 3] {
+        assert!(handle.select_cached(sequence).await.is_err());
+    }
+    assert_eq!(handle.select_cached(2).await.unwrap().email, "2@x");
+}
+
+#[tokio::test]
+async fn a_reload_drops_every_cached_record() {
+    let dir = ScratchDir::new("read_cache_reload");
+    let store = load(&dir).await;
+    let handle = cached(&store, 2, 10).await;
+    store.save().await.unwrap();
+    handle.select_cached(1).await.unwrap();
+    handle.select_cached(2).await.unwrap();
+
+    store.reload_tree("users").await.unwrap();
+    let (hits, misses) = hits_and_misses(&store);
+    handle.select_cached(1).await.unwrap();
+    handle.select_cached(2).await.unwrap();
+    assert_eq!(hits_and_misses(&store), (hits, misses + 2));
+}
+
+#[tokio::test]
+async fn the_least_recently_read_goes_first() {
+    let dir = ScratchDir::new("read_cache_lru");
+    let store = load(&dir).await;
+    let handle = cached(&store, 3, 2).await;
+
+    handle.select_cached(1).await.unwrap();
+    handle.select_cached(2).await.unwrap();
+    handle.select_cached(1).await.unwrap();
+    // 2 is now the oldest read, so 3 takes its place
+    handle.select_cached(3).await.unwrap();
+    assert_eq!(hits_and_misses(&store), (1, 3));
+
+    handle.select_cached(1).await.unwrap();
+    handle.select_cached(3).await.unwrap();
+    assert_eq!(hits_and_misses(&store), (3, 3));
+    handle.select_cached(2).await.unwrap();
+    assert_eq!(hits_and_misses(&store), (3, 4));
+}
+
+#[tokio::test]
+async fn a_cache_of_nothing_never_hits() {
+    let dir = ScratchDir::new("read_cache_zero");
+    let store = load(&dir).await;
+    let handle = cached(&store, 1, 0).await;
+    for _ in 0..3 {
+        assert_eq!(handle.select_cached(1).await.unwrap().email, "1@x");
+    }
+    assert_eq!(hits_and_misses(&store), (0, 3));
+}
+
+#[tokio::test]
+async fn a_missing_record_is_not_cached() {
+    let dir = ScratchDir::new("read_cache_missing");
+    let store = load(&dir).await;
+    let handle = cached(&store, 1, 10).await;
+    assert!(handle.select_cached(7).await.is_err());
+    assert!(matches!(
+        store.tree::<User>("missing"),
+        Err(JsonStoreError::NotFoundTree(_))
+    ));
+    let (hits, _) = hits_and_misses(&store);
+    assert_eq!(hits, 0);
+}
+
+// Readers never see a record go back to a count they saw it pass, and once the writer
+// is done every reader sees its last write.
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn readers_see_every_write_in_order() {
+    let dir = ScratchDir::new("read_cache_concurrent");
+    let store = load(&dir).await;
+    let handle = cached(&store, 4, 10).await;
+    let writes = 300;
+
+    let writer = {
+        let handle = handle.clone();
+        tokio::spawn(async move {
+            for visits in 1..=writes {
+                for sequence in 1..=4 {
+                    let mut record = (*handle.select_cached(sequence).await.unwrap()).clone();
+                    record.visits = visits;
+                    handle.update(&record).await.unwrap();
+                }
+                if visits % 50 == 0 {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+            }
+        })
+    };
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let handle = handle.clone();
+            tokio::spawn(async move {
+                let mut seen = [0; 4];
+                while seen.iter().any(|&visits| visits < writes) {
+                    for sequence in 1..=4 {
+                        let record = handle.select_cached(sequence).await.unwrap();
+                        let last = &mut seen[sequence as usize - 1];
+                        assert!(record.visits >= *last, "{} after {}", record.visits, last);
+                        *last = record.visits;
+                    }
+                    tokio::task::yield_now().await;
+                }
+            })
+        })
+        .collect();
+
+    writer.await.unwrap();
+    for reader in readers {
+        tokio::time::timeout(Duration::from_secs(30), reader)
+            .await
+            .expect("readers to catch up")
+            .unwrap();
+    }
+    for sequence in 1..=4 {
+        let cached = handle.select_cached(sequence).await.unwrap();
+        let stored: User = store.select("users", sequence).await.unwrap();
+        assert_eq!(*cached, stored);
+        assert_eq!(stored.visits, writes);
+    }
+    let (hits, misses) = hits_and_misses(&store);
+    assert!(hits > 0 && misses > 0, "{} hits, {} misses", hits, misses);
+}
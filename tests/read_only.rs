@@ -0,0 +1,151 @@
+mod common;
+
+use common::{all, store_with_users, users, ScratchDir};
+use json_store::{
+    error::JsonStoreError,
+    store::{JsonStore, LoadOptions},
+    wal::WalOptions,
+};
+use serde_json::{json, Value};
+use std::{collections::BTreeMap, path::Path, time::SystemTime};
+
+// every file below dir with its contents and modification time
+fn snapshot(dir: &Path) -> BTreeMap<String, (Vec<u8>, SystemTime)> {
+    let mut files = BTreeMap::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(current) = dirs.pop() {
+        for entry in std::fs::read_dir(&current).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+            let name = path.strip_prefix(dir).unwrap().display().to_string();
+            files.insert(name, (std::fs::read(&path).unwrap(), modified));
+        }
+    }
+    files
+}
+
+async fn saved_store(dir: &ScratchDir) {
+    let store = store_with_users(dir).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store
+        .insert("users", &json!({"email": "b@x"}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+}
+
+fn refused<T: std::fmt::Debug>(result: Result<T, JsonStoreError>) {
+    assert!(
+        matches!(result, Err(JsonStoreError::ReadOnlyStore)),
+        "{:?}",
+        result
+    );
+}
+
+#[tokio::test]
+async fn every_change_is_refused_and_nothing_on_disk_moves() {
+    let dir = ScratchDir::new("read-only-refused");
+    saved_store(&dir).await;
+    let before = snapshot(dir.path());
+
+    let store = JsonStore::load_read_only(dir.path()).await.unwrap();
+    assert!(store.is_read_only());
+
+    refused(store.create_tree("posts", users()).await);
+    refused(store.drop_tree("users").await);
+    refused(store.insert("users", &json!({"email": "c@x"})).await);
+    refused(
+        store
+            .update("users", &json!({"id": 1, "email": "z@x"}))
+            .await,
+    );
+    refused(store.delete("users", 1).await);
+    refused(store.save().await);
+    refused(store.save_tree("users").await);
+    refused(store.reshard_tree("users", 2).await);
+    refused(store.set_store_metadata("owner", json!("me")).await);
+
+    // reads work as usual
+    assert_eq!(
+        store.select::<Value>("users", 1).await.unwrap(),
+        json!({"id": 1, "email": "a@x"})
+    );
+    assert_eq!(all(&store, "users").await.len(), 2);
+    assert_eq!(store.list_trees(), ["users"]);
+    assert!(store.unsaved_changes().await.is_empty());
+    store.stats().await.unwrap();
+    store.close().await.unwrap();
+
+    assert_eq!(snapshot(dir.path()), before);
+}
+
+#[tokio::test]
+async fn leftovers_and_logs_stay_as_they_are() {
+    let dir = ScratchDir::new("read-only-leftovers");
+    let options = || LoadOptions {
+        wal: Some(WalOptions::default()),
+        ..Default::default()
+    };
+    let store = JsonStore::load_with_options(dir.path(), options())
+        .await
+        .unwrap();
+    store.create_tree("users", users()).await.unwrap();
+    store.save().await.unwrap();
+    // logged but never saved
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    drop(store);
+    // what a crashed save leaves behind
+    std::fs::write(dir.path().join("users.json.tmp"), "[").unwrap();
+    let before = snapshot(dir.path());
+
+    let store = JsonStore::load_with_options(
+        dir.path(),
+        LoadOptions {
+            read_only: true,
+            ..options()
+        },
+    )
+    .await
+    .unwrap();
+    // the WAL is replayed in memory only
+    assert_eq!(
+        all(&store, "users").await,
+        [json!({"id": 1, "email": "a@x"})]
+    );
+    store.unload_tree("users").await.unwrap();
+    assert_eq!(all(&store, "users").await.len(), 1);
+    drop(store);
+
+    assert_eq!(snapshot(dir.path()), before);
+}
+
+#[tokio::test]
+async fn a_read_only_store_sees_its_own_files_only() {
+    let dir = ScratchDir::new("read-only-missing");
+    saved_store(&dir).await;
+    let store = JsonStore::load_read_only(dir.path()).await.unwrap();
+
+    assert!(matches!(
+        store.select::<Value>("posts", 1).await,
+        Err(JsonStoreError::NotFoundTree(_))
+    ));
+    // a writable store can go on next to it
+    let writer = JsonStore::load(dir.path()).await.unwrap();
+    writer
+        .insert("users", &json!({"email": "c@x"}))
+        .await
+        .unwrap();
+    writer.save().await.unwrap();
+    // users was not read yet, so its first use finds the new record
+    assert_eq!(all(&store, "users").await.len(), 3);
+}
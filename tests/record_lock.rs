@@ -0,0 +1,144 @@
+mod common;
+
+use common::{store_with_users, ScratchDir};
+use json_store::{error::JsonStoreError, store::JsonStore};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+const HOUR: Duration = Duration::from_secs(3600);
+
+async fn store_with_one(dir: &ScratchDir) -> (JsonStore, u64) {
+    let store = store_with_users(dir).await;
+    let seq = store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    (store, seq)
+}
+
+#[tokio::test]
+async fn a_held_lock_turns_away_other_writers() {
+    let dir = ScratchDir::new("lock-held");
+    let (store, seq) = store_with_one(&dir).await;
+
+    let lock = store
+        .lock_record("users", seq, "alice", HOUR)
+        .await
+        .unwrap();
+    assert_eq!(
+        (lock.tree(), lock.sequence(), lock.owner()),
+        ("users", seq, "alice")
+    );
+
+    let update = json!({"id": seq, "email": "b@x"});
+    match store.update("users", &update).await {
+        Err(JsonStoreError::RecordLocked {
+            tree,
+            sequence,
+            owner,
+        }) => {
+            assert_eq!(
+                (tree.as_str(), sequence, owner.as_str()),
+                ("users", seq, "alice")
+            );
+        }
+        other => panic!("expected RecordLocked, got {:?}", other),
+    }
+    assert!(store.update_as("users", &update, "bob").await.is_err());
+    assert!(store.delete("users", seq).await.is_err());
+    assert!(store.lock_record("users", seq, "bob", HOUR).await.is_err());
+
+    // reads are not affected
+    let record: Value = store.select("users", seq).await.unwrap();
+    assert_eq!(record["email"], "a@x");
+
+    store.update_as("users", &update, "alice").await.unwrap();
+    store.delete_as("users", seq, "alice").await.unwrap();
+}
+
+#[tokio::test]
+async fn dropping_or_unlocking_releases_the_record() {
+    let dir = ScratchDir::new("lock-release");
+    let (store, seq) = store_with_one(&dir).await;
+    let update = json!({"id": seq, "email": "b@x"});
+
+    let lock = store
+        .lock_record("users", seq, "alice", HOUR)
+        .await
+        .unwrap();
+    drop(lock);
+    store.update("users", &update).await.unwrap();
+
+    let _lock = store
+        .lock_record("users", seq, "alice", HOUR)
+        .await
+        .unwrap();
+    assert!(matches!(
+        store.unlock_record("users", seq, "bob").await,
+        Err(JsonStoreError::RecordLocked { .. })
+    ));
+    store.unlock_record("users", seq, "alice").await.unwrap();
+    store.update("users", &update).await.unwrap();
+}
+
+#[tokio::test]
+async fn renewing_makes_the_old_guard_inert() {
+    let dir = ScratchDir::new("lock-renew");
+    let (store, seq) = store_with_one(&dir).await;
+
+    let first = store
+        .lock_record("users", seq, "alice", HOUR)
+        .await
+        .unwrap();
+    let _second = store
+        .lock_record("users", seq, "alice", HOUR)
+        .await
+        .unwrap();
+    drop(first);
+
+    assert!(store
+        .update("users", &json!({"id": seq, "email": "b@x"}))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn an_expired_lock_no_longer_holds() {
+    let dir = ScratchDir::new("lock-expired");
+    let (store, seq) = store_with_one(&dir).await;
+
+    let _lock = store
+        .lock_record("users", seq, "alice", Duration::from_millis(20))
+        .await
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+
+    store
+        .update("users", &json!({"id": seq, "email": "b@x"}))
+        .await
+        .unwrap();
+    store.lock_record("users", seq, "bob", HOUR).await.unwrap();
+}
+
+#[tokio::test]
+async fn locks_need_an_existing_record_and_are_not_persisted() {
+    let dir = ScratchDir::new("lock-memory");
+    let (store, seq) = store_with_one(&dir).await;
+
+    assert!(matches!(
+        store.lock_record("users", seq + 1, "alice", HOUR).await,
+        Err(JsonStoreError::SequenceNotExist(_))
+    ));
+
+    let _lock = store
+        .lock_record("users", seq, "alice", HOUR)
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+
+    let other = JsonStore::load(dir.path()).await.unwrap();
+    other
+        .update("users", &json!({"id": seq, "email": "b@x"}))
+        .await
+        .unwrap();
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use common::{all, store_with_users, ScratchDir};
+use json_store::{
+    export::{ExportFormat, Redaction, RedactionPolicy},
+    store::JsonStore,
+};
+use serde_json::{json, Value};
+
+// sha256 of "secret" and of the text of 42
+const SECRET: &str = "2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b";
+const FORTY_TWO: &str = "73475cb40a568e8da8a045ced110137e159f890ac4da883b6b17dc651b3a8049";
+
+async fn store_with_team(dir: &ScratchDir) -> (JsonStore, Value) {
+    let store = store_with_users(dir).await;
+    let record = json!({
+        "email": "a@x",
+        "password_hash": "secret",
+        "ssn": 42,
+        "profile": {"phone": "555", "city": "Oslo"},
+        "members": [{"email": "m1@x", "role": "dev"}, {"email": "m2@x", "role": "ops"}],
+        "keys": {"one": "k1", "two": "k2"},
+    });
+    store.insert("users", &record).await.unwrap();
+    let stored = all(&store, "users").await.remove(0);
+    (store, stored)
+}
+
+async fn export(store: &JsonStore, dir: &ScratchDir, redaction: Redaction) -> Value {
+    let path = dir.path().join("out.ndjson");
+    store
+        .export_tree_with("users", &path, ExportFormat::NdJson, &redaction, |_| true)
+        .await
+        .unwrap();
+    serde_json::from_str(std::fs::read_to_string(path).unwrap().trim()).unwrap()
+}
+
+#[tokio::test]
+async fn each_policy_applies_to_its_field() {
+    let dir = ScratchDir::new("redact-policies");
+    let (store, stored) = store_with_team(&dir).await;
+    let redaction = Redaction::new()
+        .field("password_hash", RedactionPolicy::Hash)
+        .field("ssn", RedactionPolicy::Hash)
+        .field("profile.phone", RedactionPolicy::Replace(json!("***")))
+        .field("profile.missing", RedactionPolicy::Drop)
+        .field("email", RedactionPolicy::Drop);
+
+    let mut expected = stored.clone();
+    expected["password_hash"] = json!(SECRET);
+    expected["ssn"] = json!(FORTY_TWO);
+    expected["profile"]["phone"] = json!("***");
+    expected.as_object_mut().unwrap().remove("email");
+    assert_eq!(export(&store, &dir, redaction).await, expected);
+
+    // the store itself is left alone
+    assert_eq!(all(&store, "users").await, [stored]);
+}
+
+#[tokio::test]
+async fn a_star_stands_for_every_element_or_field() {
+    let dir = ScratchDir::new("redact-star");
+    let (store, stored) = store_with_team(&dir).await;
+    let redaction = Redaction::new()
+        .field("members.*.email", RedactionPolicy::Drop)
+        .field("keys.*", RedactionPolicy::Replace(json!(null)));
+
+    let mut expected = stored;
+    expected["members"] = json!([{"role": "dev"}, {"role": "ops"}]);
+    expected["keys"] = json!({"one": null, "two": null});
+    assert_eq!(export(&store, &dir, redaction).await, expected);
+}
+
+#[tokio::test]
+async fn dropping_a_star_empties_the_container() {
+    let dir = ScratchDir::new("redact-star-drop");
+    let (store, stored) = store_with_team(&dir).await;
+    let redaction = Redaction::new()
+        .field("members.*", RedactionPolicy::Drop)
+        .field("keys.*", RedactionPolicy::Drop);
+
+    let mut expected = stored;
+    expected["members"] = json!([]);
+    expected["keys"] = json!({});
+    assert_eq!(export(&store, &dir, redaction).await, expected);
+}
+
+#[tokio::test]
+async fn an_empty_redaction_exports_records_as_they_are() {
+    let dir = ScratchDir::new("redact-none");
+    let (store, stored) = store_with_team(&dir).await;
+    assert!(Redaction::new().is_empty());
+    assert_eq!(export(&store, &dir, Redaction::new()).await, stored);
+}
@@ -0,0 +1,252 @@
+mod common;
+
+use common::{all, damage, users, ScratchDir};
+use json_store::{
+    backup::BackupOptions,
+    error::{ErrorKind, JsonStoreError},
+    repair::{CorruptionPolicy, RepairStrategy, TreeOutcome},
+    store::{JsonStore, LoadOptions},
+};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+
+const TREES: [&str; 3] = ["orders", "products", "users"];
+
+// three trees of two records each, with products then cut off mid-file
+async fn damaged_store(dir: &ScratchDir) {
+    saved_store(dir).await;
+    damage(&dir.path().join("products.json"), "{\"1\": {\"id\": 1, ");
+}
+
+async fn saved_store(dir: &ScratchDir) {
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    for tname in TREES {
+        store.create_tree(tname, users()).await.unwrap();
+        for n in 1..=2 {
+            let email = format!("{}-{}@x", tname, n);
+            store
+                .insert(tname, &json!({ "email": email }))
+                .await
+                .unwrap();
+        }
+    }
+    store.save().await.unwrap();
+}
+
+async fn load_with(dir: &ScratchDir, policy: CorruptionPolicy) -> JsonStore {
+    let options = LoadOptions {
+        corruption_policy: policy,
+        ..Default::default()
+    };
+    JsonStore::load_with_options(dir.path(), options)
+        .await
+        .unwrap()
+}
+
+fn unavailable<T: std::fmt::Debug>(result: Result<T, JsonStoreError>) {
+    match result {
+        Err(e @ JsonStoreError::TreeCorrupt { .. }) => {
+            assert_eq!(e.kind(), ErrorKind::Corruption);
+        }
+        other => panic!("expected TreeCorrupt, got {:?}", other),
+    }
+}
+
+// the healthy trees take reads, writes and saves as usual
+async fn check_healthy(store: &JsonStore) {
+    for tname in ["orders", "users"] {
+        assert_eq!(all(store, tname).await.len(), 2);
+        let seq = store
+            .insert(tname, &json!({"email": format!("{}-3@x", tname)}))
+            .await
+            .unwrap();
+        assert_eq!(seq, 3);
+        store.delete(tname, 1).await.unwrap();
+    }
+    assert_eq!(store.save().await.unwrap(), ["orders", "users"]);
+}
+
+#[tokio::test]
+async fn by_default_the_bad_tree_fails_every_use_without_hiding_it() {
+    let dir = ScratchDir::new("repair-fail");
+    damaged_store(&dir).await;
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    for _ in 0..2 {
+        let error = store.select::<Value>("products", 1).await.unwrap_err();
+        assert!(!matches!(error, JsonStoreError::TreeCorrupt { .. }));
+        assert_eq!(error.kind(), ErrorKind::Corruption);
+    }
+    check_healthy(&store).await;
+    assert!(store.load_report().is_none());
+
+    let eager = LoadOptions {
+        eager: true,
+        ..Default::default()
+    };
+    assert!(JsonStore::load_with_options(dir.path(), eager)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn skip_keeps_the_other_trees_usable() {
+    let dir = ScratchDir::new("repair-skip");
+    damaged_store(&dir).await;
+
+    let store = load_with(&dir, CorruptionPolicy::Skip).await;
+    assert_eq!(store.list_trees(), TREES);
+    for _ in 0..2 {
+        unavailable(store.select::<Value>("products", 1).await);
+        unavailable(store.insert("products", &json!({"email": "p@x"})).await);
+        unavailable(store.select_where::<Value, _>("products", |_| true).await);
+    }
+    check_healthy(&store).await;
+
+    // the damaged file is left for repair_tree
+    let text = std::fs::read_to_string(dir.path().join("products.json")).unwrap();
+    assert_eq!(text, "{\"1\": {\"id\": 1, ");
+    let reloaded = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(all(&reloaded, "users").await.len(), 2);
+}
+
+#[tokio::test]
+async fn report_lists_every_tree() {
+    let dir = ScratchDir::new("repair-report");
+    damaged_store(&dir).await;
+
+    let store = load_with(&dir, CorruptionPolicy::Report).await;
+    let report = store.load_report().unwrap();
+    assert_eq!(report.corrupt(), ["products"]);
+    assert_eq!(report.trees["orders"], TreeOutcome::Loaded);
+    assert_eq!(report.trees["users"], TreeOutcome::Loaded);
+    assert!(
+        matches!(&report.trees["products"], TreeOutcome::Corrupt(reason) if !reason.is_empty())
+    );
+    assert!(report.integrity.is_empty());
+
+    unavailable(store.select::<Value>("products", 2).await);
+    check_healthy(&store).await;
+
+    // a healthy store reports nothing corrupt
+    let dir = ScratchDir::new("repair-report-healthy");
+    saved_store(&dir).await;
+    let store = load_with(&dir, CorruptionPolicy::Report).await;
+    assert!(store.load_report().unwrap().corrupt().is_empty());
+    assert_eq!(store.load_report().unwrap().trees.len(), 3);
+}
+
+fn corrupt_dirs(dir: &ScratchDir) -> Vec<PathBuf> {
+    std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.file_name()
+                .unwrap()
+                .to_string_lossy()
+                .starts_with(".corrupt-")
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn start_empty_sets_the_bad_files_aside() {
+    let dir = ScratchDir::new("repair-empty");
+    damaged_store(&dir).await;
+
+    let store = load_with(&dir, CorruptionPolicy::Skip).await;
+    store
+        .repair_tree("products", RepairStrategy::StartEmpty)
+        .await
+        .unwrap();
+
+    assert!(all(&store, "products").await.is_empty());
+    // sequences of the lost records aren't handed out again
+    assert_eq!(
+        store
+            .insert("products", &json!({"email": "p@x"}))
+            .await
+            .unwrap(),
+        3
+    );
+    store.save().await.unwrap();
+
+    let aside = corrupt_dirs(&dir);
+    assert_eq!(aside.len(), 1);
+    assert_eq!(
+        std::fs::read_to_string(aside[0].join("products.json")).unwrap(),
+        "{\"1\": {\"id\": 1, "
+    );
+
+    let reloaded = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(
+        all(&reloaded, "products").await,
+        [json!({"id": 3, "email": "p@x"})]
+    );
+    assert_eq!(all(&reloaded, "orders").await.len(), 2);
+}
+
+#[tokio::test]
+async fn restore_from_backup_brings_the_records_back() {
+    let dir = ScratchDir::new("repair-backup");
+    let backup = ScratchDir::new("repair-backup-copy");
+    saved_store(&dir).await;
+    JsonStore::load(dir.path())
+        .await
+        .unwrap()
+        .backup_with(backup.path(), BackupOptions { overwrite: true })
+        .await
+        .unwrap();
+    damage(&dir.path().join("products.json"), "[");
+
+    let store = load_with(&dir, CorruptionPolicy::Skip).await;
+    store
+        .repair_tree(
+            "products",
+            RepairStrategy::RestoreFromBackup(backup.path().to_path_buf()),
+        )
+        .await
+        .unwrap();
+    assert_eq!(all(&store, "products").await.len(), 2);
+    assert_eq!(corrupt_dirs(&dir).len(), 1);
+
+    let reloaded = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(
+        reloaded.select::<Value>("products", 2).await.unwrap()["email"],
+        "products-2@x"
+    );
+}
+
+#[tokio::test]
+async fn a_backup_without_the_tree_is_refused_before_anything_moves() {
+    let dir = ScratchDir::new("repair-backup-missing");
+    let empty = ScratchDir::new("repair-backup-empty");
+    JsonStore::load(empty.path())
+        .await
+        .unwrap()
+        .save()
+        .await
+        .unwrap();
+    damaged_store(&dir).await;
+
+    let store = load_with(&dir, CorruptionPolicy::Skip).await;
+    assert!(matches!(
+        store
+            .repair_tree(
+                "products",
+                RepairStrategy::RestoreFromBackup(empty.path().to_path_buf())
+            )
+            .await,
+        Err(JsonStoreError::InvalidBackup { .. })
+    ));
+    assert!(corrupt_dirs(&dir).is_empty());
+    unavailable(store.select::<Value>("products", 1).await);
+
+    let read_only = JsonStore::load_read_only(dir.path()).await.unwrap();
+    assert!(matches!(
+        read_only
+            .repair_tree("products", RepairStrategy::StartEmpty)
+            .await,
+        Err(JsonStoreError::ReadOnlyStore)
+    ));
+}
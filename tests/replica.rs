@@ -0,0 +1,167 @@
+mod common;
+
+use common::{all, users, ScratchDir};
+use json_store::{
+    error::JsonStoreError,
+    replica::TreeSync,
+    store::{JsonStore, LoadOptions},
+    wal::WalOptions,
+};
+use serde_json::json;
+
+async fn primary(dir: &ScratchDir) -> JsonStore {
+    let options = LoadOptions {
+        wal: Some(WalOptions::default()),
+        ..Default::default()
+    };
+    JsonStore::load_with_options(dir.path(), options)
+        .await
+        .unwrap()
+}
+
+async fn replica(dir: &ScratchDir) -> JsonStore {
+    let options = LoadOptions {
+        replica: true,
+        ..Default::default()
+    };
+    JsonStore::load_with_options(dir.path(), options)
+        .await
+        .unwrap()
+}
+
+// every tree of both, record for record
+async fn assert_same(primary: &JsonStore, replica: &JsonStore) {
+    assert_eq!(primary.list_trees(), replica.list_trees());
+    for tname in primary.list_trees() {
+        assert_eq!(
+            all(primary, &tname).await,
+            all(replica, &tname).await,
+            "{}",
+            tname
+        );
+    }
+}
+
+#[tokio::test]
+async fn a_replica_catches_up_from_the_log_then_from_snapshots() {
+    let primary_dir = ScratchDir::new("replica-primary");
+    let replica_dir = ScratchDir::new("replica-copy");
+    let source = primary(&primary_dir).await;
+    source.create_tree("users", users()).await.unwrap();
+    source.create_tree("teams", users()).await.unwrap();
+    for n in 0..5 {
+        source
+            .insert("users", &json!({ "email": format!("{}@x", n) }))
+            .await
+            .unwrap();
+    }
+    source
+        .insert("teams", &json!({"email": "t@x"}))
+        .await
+        .unwrap();
+    source.save().await.unwrap();
+
+    let copy = replica(&replica_dir).await;
+    let report = copy.sync_from(primary_dir.path()).await.unwrap();
+    assert_eq!(
+        report.trees["users"],
+        TreeSync {
+            inserted: 5,
+            ..Default::default()
+        }
+    );
+    assert_same(&source, &copy).await;
+
+    // changes in the log only, not yet checkpointed into the snapshot
+    source
+        .update("users", &json!({"id": 1, "email": "a@x"}))
+        .await
+        .unwrap();
+    source.delete("users", 2).await.unwrap();
+    source
+        .insert("users", &json!({"email": "new@x"}))
+        .await
+        .unwrap();
+    let report = copy.sync_from(primary_dir.path()).await.unwrap();
+    assert_eq!(
+        report.trees["users"],
+        TreeSync {
+            inserted: 1,
+            updated: 1,
+            deleted: 1,
+            from_log: true,
+        }
+    );
+    assert_eq!(report.trees["teams"].applied(), 0);
+    assert_same(&source, &copy).await;
+
+    // nothing new: nothing done
+    let report = copy.sync_from(primary_dir.path()).await.unwrap();
+    assert_eq!(report.applied(), 0);
+    assert!(report.trees["users"].from_log);
+    assert!(report.dropped.is_empty());
+
+    // a checkpoint writes a new snapshot, so the two are compared record by record
+    source.checkpoint("users").await.unwrap();
+    source.delete("users", 3).await.unwrap();
+    source.drop_tree("teams").await.unwrap();
+    let report = copy.sync_from(primary_dir.path()).await.unwrap();
+    assert_eq!(
+        report.trees["users"],
+        TreeSync {
+            deleted: 1,
+            ..Default::default()
+        }
+    );
+    assert_eq!(report.dropped, ["teams"]);
+    assert_same(&source, &copy).await;
+
+    // and the replica reads back as synced, refusing changes of its own
+    drop(copy);
+    let copy = replica(&replica_dir).await;
+    assert_same(&source, &copy).await;
+    assert!(matches!(
+        copy.insert("users", &json!({"email": "mine@x"})).await,
+        Err(JsonStoreError::ReplicaStore)
+    ));
+}
+
+#[tokio::test]
+async fn the_primarys_metadata_comes_across_without_the_cursor() {
+    let primary_dir = ScratchDir::new("replica-metadata-primary");
+    let replica_dir = ScratchDir::new("replica-metadata-copy");
+    let source = primary(&primary_dir).await;
+    source.create_tree("users", users()).await.unwrap();
+    source
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    source.save().await.unwrap();
+    let copy = replica(&replica_dir).await;
+    copy.sync_from(primary_dir.path()).await.unwrap();
+    assert_eq!(
+        copy.get_tree_metadata("users", "sync_cursor").unwrap(),
+        None
+    );
+
+    // a key of the primary's own, whatever its name, is copied and left alone
+    source
+        .set_tree_metadata("users", "sync_cursor", json!("mine"))
+        .await
+        .unwrap();
+    source
+        .insert("users", &json!({"email": "b@x"}))
+        .await
+        .unwrap();
+    let report = copy.sync_from(primary_dir.path()).await.unwrap();
+    assert!(report.trees["users"].from_log);
+    assert_eq!(report.trees["users"].inserted, 1);
+    assert_eq!(
+        copy.get_tree_metadata("users", "sync_cursor").unwrap(),
+        Some(json!("mine"))
+    );
+    let report = copy.sync_from(primary_dir.path()).await.unwrap();
+    assert_eq!(report.applied(), 0);
+    assert!(report.trees["users"].from_log);
+    assert_same(&source, &copy).await;
+}
@@ -0,0 +1,141 @@
+mod common;
+
+use common::{all, damage, store_with_users, ScratchDir};
+use json_store::{error::JsonStoreError, store::JsonStore};
+use serde_json::json;
+use std::{fs, path::PathBuf};
+
+// a live store with records a and b, backed up, then changed to hold only c
+async fn live_and_backup(live: &ScratchDir, dest: &ScratchDir) {
+    let store = store_with_users(live).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store
+        .insert("users", &json!({"email": "b@x"}))
+        .await
+        .unwrap();
+    store.backup(dest.path()).await.unwrap();
+
+    store.delete("users", 1).await.unwrap();
+    store.delete("users", 2).await.unwrap();
+    store
+        .insert("users", &json!({"email": "c@x"}))
+        .await
+        .unwrap();
+    store.close().await.unwrap();
+}
+
+fn set_aside(live: &ScratchDir) -> Vec<PathBuf> {
+    fs::read_dir(live.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.file_name()
+                .unwrap()
+                .to_string_lossy()
+                .starts_with(".pre-restore-")
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn restore_puts_the_backup_in_place() {
+    let live = ScratchDir::new("restore-live");
+    let dest = ScratchDir::new("restore-backup");
+    live_and_backup(&live, &dest).await;
+    fs::write(live.path().join("notes.txt"), "mine").unwrap();
+
+    JsonStore::restore(live.path(), dest.path()).await.unwrap();
+
+    let store = JsonStore::load(live.path()).await.unwrap();
+    assert_eq!(
+        all(&store, "users").await,
+        [
+            json!({"id": 1, "email": "a@x"}),
+            json!({"id": 2, "email": "b@x"})
+        ]
+    );
+
+    // what was there is kept aside
+    let aside = set_aside(&live);
+    assert_eq!(aside.len(), 1);
+    let before = JsonStore::load_backup(&aside[0]).await.unwrap();
+    assert_eq!(
+        all(&before, "users").await,
+        [json!({"id": 3, "email": "c@x"})]
+    );
+    assert!(aside[0].join("notes.txt").exists());
+
+    // and no staging directory is left behind
+    let leftovers = fs::read_dir(live.path())
+        .unwrap()
+        .filter(|entry| {
+            let name = entry.as_ref().unwrap().file_name();
+            name.to_string_lossy().starts_with(".restore-")
+        })
+        .count();
+    assert_eq!(leftovers, 0);
+}
+
+#[tokio::test]
+async fn a_backup_without_infos_is_refused() {
+    let live = ScratchDir::new("restore-no-infos");
+    let dest = ScratchDir::new("restore-no-infos-backup");
+    live_and_backup(&live, &dest).await;
+    fs::remove_file(dest.path().join("infos.json")).unwrap();
+
+    assert!(matches!(
+        JsonStore::restore(live.path(), dest.path()).await,
+        Err(JsonStoreError::InvalidBackup { .. })
+    ));
+    assert!(set_aside(&live).is_empty());
+    let store = JsonStore::load(live.path()).await.unwrap();
+    assert_eq!(all(&store, "users").await.len(), 1);
+}
+
+#[tokio::test]
+async fn a_damaged_backup_is_refused() {
+    let live = ScratchDir::new("restore-damaged");
+    let dest = ScratchDir::new("restore-damaged-backup");
+    live_and_backup(&live, &dest).await;
+    damage(
+        &dest.path().join("users.json"),
+        r#"{"1":{"id":1,"email":"z@x"}}"#,
+    );
+
+    // the eager load of the backup meets the damage before the checksums are compared
+    match JsonStore::restore(live.path(), dest.path()).await {
+        Err(JsonStoreError::ChecksumMismatch { tree, path }) => {
+            assert_eq!(
+                (tree.as_str(), path),
+                ("users", dest.path().join("users.json"))
+            );
+        }
+        other => panic!("expected ChecksumMismatch, got {:?}", other),
+    }
+    assert!(set_aside(&live).is_empty());
+    let store = JsonStore::load(live.path()).await.unwrap();
+    assert_eq!(
+        all(&store, "users").await,
+        [json!({"id": 3, "email": "c@x"})]
+    );
+}
+
+#[tokio::test]
+async fn load_backup_opens_it_read_only_in_place() {
+    let live = ScratchDir::new("restore-open");
+    let dest = ScratchDir::new("restore-open-backup");
+    live_and_backup(&live, &dest).await;
+    let files = fs::read_dir(dest.path()).unwrap().count();
+
+    let backup = JsonStore::load_backup(dest.path()).await.unwrap();
+    assert!(backup.is_read_only());
+    assert_eq!(all(&backup, "users").await.len(), 2);
+    assert!(matches!(
+        backup.insert("users", &json!({"email": "d@x"})).await,
+        Err(JsonStoreError::ReadOnlyStore)
+    ));
+    assert_eq!(fs::read_dir(dest.path()).unwrap().count(), files);
+}
@@ -0,0 +1,158 @@
+mod common;
+
+use common::{all, users, ScratchDir};
+use json_store::{
+    clock::Clock,
+    error::JsonStoreError,
+    store::{JsonStore, LoadOptions},
+    wal::{RestorePoint, WalOptions},
+};
+use serde_json::{json, Value};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// A clock the test sets by hand.
+#[derive(Debug)]
+struct TestClock(Mutex<SystemTime>);
+
+impl TestClock {
+    fn set(&self, secs: u64) {
+        *self.0.lock().unwrap() = at(secs);
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> SystemTime {
+        *self.0.lock().unwrap()
+    }
+}
+
+fn at(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+async fn load(dir: &ScratchDir, clock: Arc<TestClock>) -> JsonStore {
+    let options = LoadOptions {
+        wal: Some(WalOptions::default()),
+        clock: Some(clock),
+        ..Default::default()
+    };
+    JsonStore::load_with_options(dir.path(), options)
+        .await
+        .unwrap()
+}
+
+// what restore_tree_to left in users, read back again from disk too
+async fn assert_users(store: &JsonStore, dir: &ScratchDir, expected: &[Value]) {
+    assert_eq!(all(store, "users").await, expected);
+    let reloaded = JsonStore::load_read_only(dir.path()).await.unwrap();
+    assert_eq!(all(&reloaded, "users").await, expected);
+}
+
+#[tokio::test]
+async fn a_tree_rewinds_to_each_point_of_its_log() {
+    let dir = ScratchDir::new("restore-timeline");
+    let clock = Arc::new(TestClock(Mutex::new(at(100))));
+    let store = load(&dir, clock.clone()).await;
+    store.create_tree("users", users()).await.unwrap();
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+
+    // one change at each of 200, 300 and 400
+    clock.set(200);
+    store
+        .insert("users", &json!({"email": "b@x"}))
+        .await
+        .unwrap();
+    clock.set(300);
+    store
+        .update("users", &json!({"id": 1, "email": "a2@x"}))
+        .await
+        .unwrap();
+    clock.set(400);
+    store.delete("users", 2).await.unwrap();
+
+    // between the second and third change
+    store
+        .restore_tree_to("users", RestorePoint::Time(at(350)))
+        .await
+        .unwrap();
+    assert_users(
+        &store,
+        &dir,
+        &[
+            json!({"id": 1, "email": "a2@x"}),
+            json!({"id": 2, "email": "b@x"}),
+        ],
+    )
+    .await;
+
+    // at the first change, which is kept
+    store
+        .restore_tree_to("users", RestorePoint::Time(at(200)))
+        .await
+        .unwrap();
+    assert_users(
+        &store,
+        &dir,
+        &[
+            json!({"id": 1, "email": "a@x"}),
+            json!({"id": 2, "email": "b@x"}),
+        ],
+    )
+    .await;
+
+    // past the log's end now: the change there was cut with the rest
+    assert!(matches!(
+        store
+            .restore_tree_to("users", RestorePoint::Change(2))
+            .await,
+        Err(JsonStoreError::RestorePointUnavailable { .. })
+    ));
+
+    // and back to the checkpoint
+    store
+        .restore_tree_to("users", RestorePoint::Change(0))
+        .await
+        .unwrap();
+    assert_users(&store, &dir, &[json!({"id": 1, "email": "a@x"})]).await;
+
+    // a rewound sequence isn't handed out again
+    let seq = store
+        .insert("users", &json!({"email": "c@x"}))
+        .await
+        .unwrap();
+    assert_eq!(seq, 3);
+}
+
+#[tokio::test]
+async fn a_time_before_the_log_is_refused() {
+    let dir = ScratchDir::new("restore-too-early");
+    let clock = Arc::new(TestClock(Mutex::new(at(100))));
+    let store = load(&dir, clock.clone()).await;
+    store.create_tree("users", users()).await.unwrap();
+    store.save().await.unwrap();
+    clock.set(200);
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+
+    match store
+        .restore_tree_to("users", RestorePoint::Time(at(150)))
+        .await
+    {
+        Err(JsonStoreError::RestorePointUnavailable { tree, reason }) => {
+            assert_eq!(tree, "users");
+            assert!(reason.contains("200000 ms"), "{}", reason);
+        }
+        other => panic!("{:?}", other),
+    }
+    // nothing was rewound
+    assert_users(&store, &dir, &[json!({"id": 1, "email": "a@x"})]).await;
+}
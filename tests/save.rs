@@ -0,0 +1,65 @@
+mod common;
+
+use common::{all, edit, users, ScratchDir};
+use json_store::{error::JsonStoreError, store::JsonStore};
+use serde_json::json;
+
+async fn store_with_trees(dir: &ScratchDir, tnames: &[&str]) -> JsonStore {
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    for tname in tnames {
+        store.create_tree(tname, users()).await.unwrap();
+    }
+    store
+}
+
+#[tokio::test]
+async fn save_writes_every_changed_tree() {
+    let dir = ScratchDir::new("save-all");
+    let tnames = ["a", "b", "c", "d", "e", "f"];
+    let store = store_with_trees(&dir, &tnames).await;
+    for tname in &tnames[..4] {
+        store.insert(tname, &json!({"email": tname})).await.unwrap();
+    }
+
+    assert_eq!(store.save().await.unwrap(), ["a", "b", "c", "d"]);
+    assert!(store.save().await.unwrap().is_empty());
+
+    let reloaded = JsonStore::load(dir.path()).await.unwrap();
+    for tname in &tnames[..4] {
+        assert_eq!(
+            all(&reloaded, tname).await,
+            [json!({"id": 1, "email": tname})]
+        );
+    }
+    for tname in &tnames[4..] {
+        assert!(all(&reloaded, tname).await.is_empty());
+    }
+}
+
+#[tokio::test]
+async fn one_failing_tree_does_not_stop_the_others() {
+    let dir = ScratchDir::new("save-failed");
+    let store = store_with_trees(&dir, &["a", "b", "c"]).await;
+    for tname in ["a", "b", "c"] {
+        store.insert(tname, &json!({"email": tname})).await.unwrap();
+    }
+    edit(&dir.path().join("b.json"), "{}");
+
+    match store.save().await {
+        Err(JsonStoreError::SaveFailed { failed, saved }) => {
+            assert_eq!(saved, ["a", "c"]);
+            assert_eq!(failed.len(), 1);
+            assert_eq!(failed[0].0, "b");
+            assert!(matches!(
+                failed[0].1,
+                JsonStoreError::ExternallyModified { .. }
+            ));
+        }
+        other => panic!("expected SaveFailed, got {:?}", other),
+    }
+
+    assert!(!store.is_dirty("a").await.unwrap());
+    assert!(store.is_dirty("b").await.unwrap());
+    assert!(!store.is_dirty("c").await.unwrap());
+    assert_eq!(common::read_json(&dir.path().join("b.json")), json!({}));
+}
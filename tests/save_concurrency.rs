@@ -0,0 +1,62 @@
+mod common;
+
+use common::{all, ScratchDir};
+use json_store::store::{Info, JsonStore};
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+const RECORDS: u64 = 50_000;
+
+// a tree large enough that encoding it takes a good while, all unsaved
+async fn large_store(dir: &ScratchDir) -> JsonStore {
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    let info = Info::builder().sequence_field("id").build().unwrap();
+    store.create_tree("events", info).await.unwrap();
+    let body = "x".repeat(200);
+    for n in 0..RECORDS {
+        store
+            .insert("events", &json!({"n": n, "body": body}))
+            .await
+            .unwrap();
+    }
+    store
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn writes_go_on_while_a_large_tree_is_encoded() {
+    let dir = ScratchDir::new("save-concurrent-insert");
+    let store = large_store(&dir).await;
+
+    let started = Instant::now();
+    let saving = tokio::spawn({
+        let store = store.clone();
+        async move {
+            store.save_tree("events").await.unwrap();
+            started.elapsed()
+        }
+    });
+    // let the save take the tree and start encoding it
+    tokio::time::sleep(Duration::from_millis(5)).await;
+
+    let seq = store.insert("events", &json!({"n": -1})).await.unwrap();
+    let inserted = started.elapsed();
+    let in_flight = !saving.is_finished();
+    let saved = saving.await.unwrap();
+
+    assert_eq!(seq, RECORDS + 1);
+    assert!(
+        in_flight && inserted < saved,
+        "insert done after {:?}, save after {:?}",
+        inserted,
+        saved
+    );
+
+    // the insert is in the file, or still waits for the next save
+    store.save().await.unwrap();
+    let reloaded = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(
+        reloaded.select::<Value>("events", seq).await.unwrap(),
+        json!({"id": seq, "n": -1})
+    );
+    assert_eq!(all(&reloaded, "events").await.len() as u64, RECORDS + 1);
+}
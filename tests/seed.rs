@@ -0,0 +1,350 @@
+mod common;
+
+use common::{all, store_with_users, users, ScratchDir};
+use json_store::{
+    error::JsonStoreError,
+    key::KeyGenerator,
+    seed::{SeedMode, SeedReport},
+    store::{Info, JsonStore},
+};
+use serde_json::{json, Value};
+use std::path::Path;
+
+fn report(created: usize, updated: usize, skipped: usize, deleted: usize) -> SeedReport {
+    SeedReport {
+        created,
+        updated,
+        skipped,
+        deleted,
+    }
+}
+
+fn fixtures() -> Vec<Value> {
+    vec![
+        json!({"email": "a@x", "name": "Ann"}),
+        json!({"email": "b@x", "name": "Bo"}),
+    ]
+}
+
+// users a@x and c@x, as a tree already in use holds them
+async fn store_in_use(dir: &ScratchDir) -> JsonStore {
+    let store = store_with_users(dir).await;
+    for record in [
+        json!({"email": "a@x", "name": "old", "visits": 3}),
+        json!({"email": "c@x", "name": "Cy"}),
+    ] {
+        store.insert("users", &record).await.unwrap();
+    }
+    store
+}
+
+#[track_caller]
+fn assert_rejected(result: Result<SeedReport, JsonStoreError>, index: usize) {
+    match result {
+        Err(JsonStoreError::ImportRejected { index: i, .. }) => assert_eq!(i, index),
+        other => panic!("{}: {:?}", index, other),
+    }
+}
+
+#[tokio::test]
+async fn if_empty_seeds_only_an_empty_tree() {
+    let dir = ScratchDir::new("seed-if-empty");
+    let store = store_with_users(&dir).await;
+
+    let seeded = store
+        .seed("users", &fixtures(), SeedMode::IfEmpty)
+        .await
+        .unwrap();
+    assert_eq!(seeded, report(2, 0, 0, 0));
+    // the fixtures' sequence fields are the tree's to give
+    assert_eq!(
+        all(&store, "users").await,
+        [
+            json!({"id": 1, "email": "a@x", "name": "Ann"}),
+            json!({"id": 2, "email": "b@x", "name": "Bo"}),
+        ]
+    );
+
+    // a second start finds it seeded
+    let again = store
+        .seed("users", &[json!({"email": "z@x"})], SeedMode::IfEmpty)
+        .await
+        .unwrap();
+    assert_eq!(again, report(0, 0, 1, 0));
+    assert_eq!(all(&store, "users").await.len(), 2);
+    assert!(store.select::<Value>("users", 3).await.is_err());
+}
+
+#[tokio::test]
+async fn merge_updates_by_key_and_inserts_the_rest() {
+    let dir = ScratchDir::new("seed-merge");
+    let store = store_in_use(&dir).await;
+
+    let seeded = store
+        .seed("users", &fixtures(), SeedMode::Merge("email".to_string()))
+        .await
+        .unwrap();
+    assert_eq!(seeded, report(1, 1, 0, 0));
+    // a@x replaced whole, keeping its sequence; c@x left alone
+    assert_eq!(
+        all(&store, "users").await,
+        [
+            json!({"id": 1, "email": "a@x", "name": "Ann"}),
+            json!({"id": 2, "email": "c@x", "name": "Cy"}),
+            json!({"id": 3, "email": "b@x", "name": "Bo"}),
+        ]
+    );
+
+    // seeding the same fixtures again changes nothing but counts them as updated
+    let again = store
+        .seed("users", &fixtures(), SeedMode::Merge("email".to_string()))
+        .await
+        .unwrap();
+    assert_eq!(again, report(0, 2, 0, 0));
+    assert_eq!(all(&store, "users").await.len(), 3);
+}
+
+#[tokio::test]
+async fn replace_clears_the_tree_first() {
+    let dir = ScratchDir::new("seed-replace");
+    let store = store_in_use(&dir).await;
+
+    let seeded = store
+        .seed("users", &fixtures(), SeedMode::Replace)
+        .await
+        .unwrap();
+    assert_eq!(seeded, report(2, 0, 0, 2));
+    // new records go on from the counter, so no old sequence is handed out again
+    assert_eq!(
+        all(&store, "users").await,
+        [
+            json!({"id": 3, "email": "a@x", "name": "Ann"}),
+            json!({"id": 4, "email": "b@x", "name": "Bo"}),
+        ]
+    );
+
+    // and it lasts
+    store.close().await.unwrap();
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(all(&store, "users").await.len(), 2);
+}
+
+#[tokio::test]
+async fn a_failed_seed_writes_nothing() {
+    let dir = ScratchDir::new("seed-atomic");
+    let store = store_in_use(&dir).await;
+    let before = all(&store, "users").await;
+
+    let not_object = [json!({"email": "n@x"}), json!("n@x")];
+    let same_key = [
+        json!({"email": "n@x"}),
+        json!({"email": "c@x"}),
+        json!({"email": "n@x", "name": "again"}),
+    ];
+    for (records, mode, index) in [
+        (&not_object[..], SeedMode::Replace, 1),
+        (&not_object[..], SeedMode::Merge("email".to_string()), 1),
+        // the third has the key of the first, so both would update one record
+        (&same_key[..], SeedMode::Merge("email".to_string()), 2),
+        // and with nothing to update, they clash
+        (&same_key[..], SeedMode::Replace, 2),
+    ] {
+        let result = store.seed("users", records, mode.clone()).await;
+        assert_rejected(result, index);
+        assert_eq!(all(&store, "users").await, before, "{:?}", mode);
+    }
+
+    // among the fixtures themselves, the second of a clashing pair is refused
+    let dir = ScratchDir::new("seed-atomic-fresh");
+    let store = store_with_users(&dir).await;
+    let result = store
+        .seed(
+            "users",
+            &[json!({"email": "a@x"}), json!({"email": "a@x"})],
+            SeedMode::IfEmpty,
+        )
+        .await;
+    assert_rejected(result, 1);
+    assert!(all(&store, "users").await.is_empty());
+    assert!(!store.is_dirty("users").await.unwrap());
+}
+
+#[tokio::test]
+async fn a_seed_is_undone_in_one_step() {
+    let dir = ScratchDir::new("seed-undo");
+    let store = store_in_use(&dir).await;
+    let before = all(&store, "users").await;
+
+    for mode in [SeedMode::Merge("email".to_string()), SeedMode::Replace] {
+        store.seed("users", &fixtures(), mode).await.unwrap();
+        store.undo_last("users").await.unwrap();
+        assert_eq!(all(&store, "users").await, before);
+    }
+}
+
+#[tokio::test]
+async fn bad_seeds_are_errors() {
+    let dir = ScratchDir::new("seed-errors");
+    let store = store_in_use(&dir).await;
+    assert!(matches!(
+        store
+            .seed("users", &fixtures(), SeedMode::Merge("phone".to_string()))
+            .await,
+        Err(JsonStoreError::UniqueConstraintNotFound { .. })
+    ));
+    assert!(matches!(
+        store.seed("nothing", &fixtures(), SeedMode::Replace).await,
+        Err(JsonStoreError::NotFoundTree(_))
+    ));
+
+    // more than the tree holds, counting what Replace clears
+    let small = Info::builder()
+        .sequence_field("id")
+        .capacity(2)
+        .build()
+        .unwrap();
+    store.create_tree("small", small).await.unwrap();
+    store.insert("small", &json!({})).await.unwrap();
+    let three = [json!({}), json!({}), json!({})];
+    assert!(matches!(
+        store
+            .seed("small", &three[..2], SeedMode::Merge("x".to_string()))
+            .await,
+        Err(JsonStoreError::UniqueConstraintNotFound { .. })
+    ));
+    assert!(matches!(
+        store.seed("small", &three, SeedMode::Replace).await,
+        Err(JsonStoreError::CapacityExceeded(_))
+    ));
+    store
+        .seed("small", &three[..2], SeedMode::Replace)
+        .await
+        .unwrap();
+}
+
+fn write_fixture(dir: &Path, name: &str, contents: &str) {
+    std::fs::write(dir.join(name), contents).unwrap();
+}
+
+#[tokio::test]
+async fn a_directory_seeds_the_tree_of_each_file() {
+    let dir = ScratchDir::new("seed-dir");
+    let store = store_in_use(&dir).await;
+    store.create_tree("teams", users()).await.unwrap();
+    let fixtures = ScratchDir::new("seed-dir-fixtures");
+    write_fixture(
+        fixtures.path(),
+        "users.json",
+        &json!([{"email": "a@x", "name": "Ann"}, {"email": "b@x"}]).to_string(),
+    );
+    write_fixture(
+        fixtures.path(),
+        "teams.json",
+        &json!([{"email": "core@x"}]).to_string(),
+    );
+    // not fixtures
+    write_fixture(fixtures.path(), "README.md", "# fixtures");
+    std::fs::create_dir(fixtures.path().join("old.json")).unwrap();
+
+    let reports = store
+        .seed_from_dir(fixtures.path(), SeedMode::Merge("email".to_string()))
+        .await
+        .unwrap();
+    assert_eq!(
+        reports.into_iter().collect::<Vec<_>>(),
+        [
+            ("teams".to_string(), report(1, 0, 0, 0)),
+            ("users".to_string(), report(1, 1, 0, 0)),
+        ]
+    );
+    assert_eq!(all(&store, "users").await.len(), 3);
+    assert_eq!(all(&store, "teams").await[0]["email"], "core@x");
+
+    // with IfEmpty only the empty ones
+    let dir = ScratchDir::new("seed-dir-if-empty");
+    let store = store_in_use(&dir).await;
+    store.create_tree("teams", users()).await.unwrap();
+    let reports = store
+        .seed_from_dir(fixtures.path(), SeedMode::IfEmpty)
+        .await
+        .unwrap();
+    assert_eq!(reports["teams"], report(1, 0, 0, 0));
+    assert_eq!(reports["users"], report(0, 0, 2, 0));
+}
+
+#[tokio::test]
+async fn a_fixture_for_no_tree_seeds_nothing() {
+    let dir = ScratchDir::new("seed-dir-missing");
+    let store = store_with_users(&dir).await;
+    let fixtures = ScratchDir::new("seed-dir-missing-fixtures");
+    write_fixture(fixtures.path(), "users.json", r#"[{"email": "a@x"}]"#);
+    write_fixture(fixtures.path(), "usres.json", r#"[{"email": "b@x"}]"#);
+
+    let error = store
+        .seed_from_dir(fixtures.path(), SeedMode::Replace)
+        .await
+        .unwrap_err();
+    match &error {
+        JsonStoreError::FixtureTreeNotFound { tree, path } => {
+            assert_eq!(tree, "usres");
+            assert_eq!(path, &fixtures.path().join("usres.json"));
+        }
+        other => panic!("{:?}", other),
+    }
+    assert!(error.to_string().contains("usres"), "{}", error);
+    assert!(all(&store, "users").await.is_empty());
+
+    // nor does a file that isn't an array of records
+    std::fs::remove_file(fixtures.path().join("usres.json")).unwrap();
+    write_fixture(fixtures.path(), "users.json", r#"{"email": "a@x"}"#);
+    assert!(store
+        .seed_from_dir(fixtures.path(), SeedMode::Replace)
+        .await
+        .is_err());
+    assert!(all(&store, "users").await.is_empty());
+}
+
+// a tree keyed by a field of its records is seeded as one by sequence, and read by key
+#[tokio::test]
+async fn a_keyed_tree_is_seeded_too() {
+    let dir = ScratchDir::new("seed-keyed");
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    let info = Info::builder()
+        .sequence_field("id")
+        .string_key("code", KeyGenerator::None)
+        .unique("code", ["code"])
+        .build()
+        .unwrap();
+    store.create_tree("countries", info).await.unwrap();
+    store
+        .insert("countries", &json!({"code": "FR", "name": "F"}))
+        .await
+        .unwrap();
+
+    let countries = [
+        json!({"code": "FR", "name": "France"}),
+        json!({"code": "DE"}),
+    ];
+    let seeded = store
+        .seed("countries", &countries, SeedMode::Merge("code".to_string()))
+        .await
+        .unwrap();
+    assert_eq!(seeded, report(1, 1, 0, 0));
+    let france: Value = store.select_by_key("countries", "FR").await.unwrap();
+    assert_eq!(france["name"], "France");
+
+    store
+        .seed("countries", &[json!({"code": "IT"})], SeedMode::Replace)
+        .await
+        .unwrap();
+    store.close().await.unwrap();
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert!(store
+        .select_by_key::<Value>("countries", "IT")
+        .await
+        .is_ok());
+    assert!(matches!(
+        store.select_by_key::<Value>("countries", "FR").await,
+        Err(JsonStoreError::KeyNotExist { .. })
+    ));
+}
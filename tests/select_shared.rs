@@ -0,0 +1,70 @@
+mod common;
+
+use common::{resident_limit, store_with_users, ScratchDir};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+#[tokio::test]
+async fn a_shared_record_outlives_a_later_update() {
+    let dir = ScratchDir::new("shared-update");
+    let store = store_with_users(&dir).await;
+    store
+        .insert("users", &json!({"email": "a@x", "name": "A"}))
+        .await
+        .unwrap();
+
+    let before = store.select_shared("users", 1).await.unwrap();
+    store
+        .update("users", &json!({"id": 1, "email": "b@x", "name": "B"}))
+        .await
+        .unwrap();
+
+    // the one handed out is as it was read, and the tree has the new one
+    assert_eq!(*before, json!({"id": 1, "email": "a@x", "name": "A"}));
+    let after = store.select_shared("users", 1).await.unwrap();
+    assert_eq!(after["email"], "b@x");
+    assert!(!Arc::ptr_eq(&before, &after));
+
+    // nor does a delete take it away
+    store.delete("users", 1).await.unwrap();
+    assert_eq!(before["email"], "a@x");
+    assert!(store.select_shared("users", 1).await.is_err());
+}
+
+#[tokio::test]
+async fn reads_share_one_record_until_it_is_written() {
+    // records kept out of memory are read back as new ones each time
+    if resident_limit().is_some() {
+        return;
+    }
+    let dir = ScratchDir::new("shared-reads");
+    let store = store_with_users(&dir).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+
+    let first = store.select_shared("users", 1).await.unwrap();
+    let second = store.select_shared("users", 1).await.unwrap();
+    assert!(Arc::ptr_eq(&first, &second));
+}
+
+#[tokio::test]
+async fn a_rewrite_in_place_copies_a_shared_record_first() {
+    let dir = ScratchDir::new("shared-vacuum");
+    let store = store_with_users(&dir).await;
+    for email in ["a@x", "b@x"] {
+        store
+            .insert("users", &json!({ "email": email }))
+            .await
+            .unwrap();
+    }
+    store.delete("users", 1).await.unwrap();
+
+    // vacuum_tree rewrites each record's sequence field where it lies
+    let held = store.select_shared("users", 2).await.unwrap();
+    store.vacuum_tree("users", true).await.unwrap();
+    assert_eq!(*held, json!({"id": 2, "email": "b@x"}));
+    let moved = store.select::<Value>("users", 1).await.unwrap();
+    assert_eq!(moved, json!({"id": 1, "email": "b@x"}));
+}
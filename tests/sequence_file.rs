@@ -0,0 +1,138 @@
+mod common;
+
+use common::{all, edit, store_with_users, ScratchDir};
+use json_store::{
+    error::{ErrorKind, JsonStoreError},
+    store::JsonStore,
+};
+use serde_json::{json, Value};
+
+// users with records 1 to 3 saved, then 2 deleted, so the highest sequence left is 3
+async fn saved_store(dir: &ScratchDir) {
+    let store = store_with_users(dir).await;
+    for email in ["a@x", "b@x", "c@x"] {
+        store
+            .insert("users", &json!({"email": email}))
+            .await
+            .unwrap();
+    }
+    store.delete("users", 2).await.unwrap();
+    store.save().await.unwrap();
+}
+
+fn seq_file(dir: &ScratchDir) -> String {
+    std::fs::read_to_string(dir.path().join("users.seq")).unwrap()
+}
+
+async fn next_insert(dir: &ScratchDir) -> u64 {
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    let seq = store
+        .insert("users", &json!({"email": "d@x"}))
+        .await
+        .unwrap();
+    // the record with the highest sequence before is still there
+    assert_eq!(
+        store.select::<Value>("users", 3).await.unwrap()["email"],
+        "c@x"
+    );
+    store.save().await.unwrap();
+    seq
+}
+
+#[tokio::test]
+async fn a_corrupt_counter_is_recovered_from_the_records() {
+    for content in ["", "x", "-1", "3.5", "99999999999999999999999"] {
+        let dir = ScratchDir::new("seq-corrupt");
+        saved_store(&dir).await;
+        edit(&dir.path().join("users.seq"), content);
+
+        assert_eq!(next_insert(&dir).await, 4, "{:?}", content);
+        assert_eq!(seq_file(&dir), "4");
+    }
+}
+
+#[tokio::test]
+async fn a_recovered_counter_is_saved_without_other_changes() {
+    let dir = ScratchDir::new("seq-corrupt-saved");
+    saved_store(&dir).await;
+    edit(&dir.path().join("users.seq"), "garbage");
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(all(&store, "users").await.len(), 2);
+    assert!(store.is_dirty("users").await.unwrap());
+    store.save().await.unwrap();
+    assert_eq!(seq_file(&dir), "3");
+}
+
+#[tokio::test]
+async fn a_missing_counter_is_recovered_from_the_records() {
+    let dir = ScratchDir::new("seq-missing");
+    saved_store(&dir).await;
+    std::fs::remove_file(dir.path().join("users.seq")).unwrap();
+
+    assert_eq!(next_insert(&dir).await, 4);
+    assert_eq!(seq_file(&dir), "4");
+}
+
+#[tokio::test]
+async fn a_counter_behind_the_records_is_raised() {
+    let dir = ScratchDir::new("seq-behind");
+    saved_store(&dir).await;
+    edit(&dir.path().join("users.seq"), "1");
+
+    assert_eq!(next_insert(&dir).await, 4);
+}
+
+#[tokio::test]
+async fn a_counter_ahead_of_the_records_is_kept() {
+    let dir = ScratchDir::new("seq-ahead");
+    saved_store(&dir).await;
+    // records 4 to 9 were taken and deleted since
+    edit(&dir.path().join("users.seq"), "9\n");
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert!(!store.is_dirty("users").await.unwrap());
+    drop(store);
+    assert_eq!(next_insert(&dir).await, 10);
+}
+
+#[tokio::test]
+async fn a_corrupt_counter_with_no_records_is_an_error() {
+    let dir = ScratchDir::new("seq-corrupt-empty");
+    store_with_users(&dir).await.save().await.unwrap();
+    edit(&dir.path().join("users.seq"), "oops");
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    match store.insert("users", &json!({"email": "a@x"})).await {
+        Err(e @ JsonStoreError::CorruptSequenceFile { .. }) => {
+            assert_eq!(e.kind(), ErrorKind::Corruption);
+            let JsonStoreError::CorruptSequenceFile {
+                tree,
+                path,
+                content,
+            } = e
+            else {
+                unreachable!()
+            };
+            assert_eq!(tree, "users");
+            assert_eq!(path, dir.path().join("users.seq"));
+            assert_eq!(content, "oops");
+        }
+        other => panic!("expected CorruptSequenceFile, got {:?}", other),
+    }
+    assert_eq!(seq_file(&dir), "oops");
+}
+
+#[tokio::test]
+async fn a_read_only_store_recovers_in_memory_only() {
+    let dir = ScratchDir::new("seq-read-only");
+    saved_store(&dir).await;
+    edit(&dir.path().join("users.seq"), "x");
+
+    let store = JsonStore::load_read_only(dir.path()).await.unwrap();
+    assert_eq!(all(&store, "users").await.len(), 2);
+    assert!(!store.is_dirty("users").await.unwrap());
+    assert_eq!(store.tree_stats("users").await.unwrap().sequence, 3);
+    drop(store);
+    assert_eq!(seq_file(&dir), "x");
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use common::{all, store_with_users, users, ScratchDir};
+use json_store::store::JsonStore;
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn reads_see_the_session_writes_before_commit() {
+    let dir = ScratchDir::new("session-read");
+    let store = store_with_users(&dir).await;
+
+    let mut session = store.session();
+    let seq = session
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    let through_session: Value = session.select("users", seq).await.unwrap();
+    let through_store: Value = store.select("users", seq).await.unwrap();
+    assert_eq!(through_session, json!({"id": seq, "email": "a@x"}));
+    assert_eq!(through_store, through_session);
+
+    session
+        .update("users", &json!({"id": seq, "email": "b@x"}))
+        .await
+        .unwrap();
+    let updated: Value = session.select("users", seq).await.unwrap();
+    assert_eq!(updated["email"], "b@x");
+    session.commit().await.unwrap();
+}
+
+#[tokio::test]
+async fn save_waits_for_commit() {
+    let dir = ScratchDir::new("session-deferred");
+    let store = store_with_users(&dir).await;
+
+    let mut session = store.session();
+    session
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    session
+        .insert("users", &json!({"email": "b@x"}))
+        .await
+        .unwrap();
+    session.delete("users", 1).await.unwrap();
+
+    let on_disk = JsonStore::load(dir.path()).await.unwrap();
+    assert!(all(&on_disk, "users").await.is_empty());
+
+    session.commit().await.unwrap();
+    assert!(!store.is_dirty("users").await.unwrap());
+    let on_disk = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(
+        all(&on_disk, "users").await,
+        vec![json!({"id": 2, "email": "b@x"})]
+    );
+}
+
+#[tokio::test]
+async fn commit_saves_only_the_trees_the_session_wrote() {
+    let dir = ScratchDir::new("session-only");
+    let store = store_with_users(&dir).await;
+    store.create_tree("other", users()).await.unwrap();
+    store
+        .insert("other", &json!({"email": "o@x"}))
+        .await
+        .unwrap();
+
+    let mut session = store.session();
+    session
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    assert_eq!(session.dirty_trees(), vec!["users".to_string()]);
+    session.commit().await.unwrap();
+
+    assert!(!store.is_dirty("users").await.unwrap());
+    assert!(store.is_dirty("other").await.unwrap());
+}
+
+#[tokio::test]
+async fn abandon_leaves_changes_in_memory_unsaved() {
+    let dir = ScratchDir::new("session-abandon");
+    let store = store_with_users(&dir).await;
+
+    let mut session = store.session();
+    session
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    session.abandon();
+
+    assert!(store.is_dirty("users").await.unwrap());
+    assert_eq!(all(&store, "users").await.len(), 1);
+    let on_disk = JsonStore::load(dir.path()).await.unwrap();
+    assert!(all(&on_disk, "users").await.is_empty());
+}
+
+#[tokio::test]
+async fn failed_write_leaves_the_session_clean() {
+    let dir = ScratchDir::new("session-failed");
+    let store = store_with_users(&dir).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+
+    let mut session = store.session();
+    assert!(session
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .is_err());
+    assert!(session.dirty_trees().is_empty());
+}
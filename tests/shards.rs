@@ -0,0 +1,178 @@
+mod common;
+
+use common::{all, read_json, ScratchDir};
+use json_store::{
+    error::JsonStoreError,
+    store::{Info, JsonStore},
+};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+fn sharded(shards: u32) -> Info {
+    Info::builder()
+        .sequence_field("id")
+        .shards(shards)
+        .build()
+        .unwrap()
+}
+
+fn part(dir: &Path, index: u32) -> PathBuf {
+    dir.join("users").join(format!("part-{:03}.json", index))
+}
+
+fn parts(dir: &Path) -> Vec<String> {
+    let mut names = std::fs::read_dir(dir.join("users"))
+        .map(|entries| {
+            entries
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+                .filter(|name| name.ends_with(".json"))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+fn ids(path: &Path) -> Vec<u64> {
+    let mut ids = read_json(path)
+        .as_object()
+        .unwrap()
+        .values()
+        .map(|record| record["id"].as_u64().unwrap())
+        .collect::<Vec<_>>();
+    ids.sort();
+    ids
+}
+
+// a save replaces a file through a rename, so a rewritten shard has a new inode
+#[cfg(unix)]
+fn inode(dir: &Path, index: u32) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::metadata(part(dir, index)).unwrap().ino()
+}
+
+async fn store_with_records(dir: &ScratchDir, shards: u32, count: u64) -> JsonStore {
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    store.create_tree("users", sharded(shards)).await.unwrap();
+    for n in 1..=count {
+        store.insert("users", &json!({"n": n})).await.unwrap();
+    }
+    store.save().await.unwrap();
+    store
+}
+
+fn expected(count: u64) -> Vec<Value> {
+    (1..=count).map(|n| json!({"id": n, "n": n})).collect()
+}
+
+#[tokio::test]
+async fn records_are_split_by_sequence() {
+    let dir = ScratchDir::new("shards-split");
+    store_with_records(&dir, 4, 10).await;
+
+    assert_eq!(
+        parts(dir.path()),
+        [
+            "part-000.json",
+            "part-001.json",
+            "part-002.json",
+            "part-003.json"
+        ]
+    );
+    assert!(!dir.path().join("users.json").exists());
+    assert_eq!(ids(&part(dir.path(), 0)), [4, 8]);
+    assert_eq!(ids(&part(dir.path(), 1)), [1, 5, 9]);
+    assert_eq!(ids(&part(dir.path(), 2)), [2, 6, 10]);
+    assert_eq!(ids(&part(dir.path(), 3)), [3, 7]);
+
+    let reloaded = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(all(&reloaded, "users").await, expected(10));
+    assert_eq!(reloaded.get_info("users").unwrap().shards, Some(4));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn one_changed_record_rewrites_one_shard() {
+    let dir = ScratchDir::new("shards-dirty");
+    let store = store_with_records(&dir, 4, 10).await;
+    let before = (0..4).map(|i| inode(dir.path(), i)).collect::<Vec<_>>();
+
+    store
+        .update("users", &json!({"id": 6, "n": 60}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+
+    let rewritten = (0..4)
+        .filter(|i| inode(dir.path(), *i) != before[*i as usize])
+        .collect::<Vec<_>>();
+    assert_eq!(rewritten, [2]);
+    assert_eq!(read_json(&part(dir.path(), 2))["6"]["n"], 60);
+
+    // deletes and inserts mark their own shard only, too
+    store.delete("users", 5).await.unwrap();
+    store.insert("users", &json!({"n": 11})).await.unwrap();
+    assert_eq!(store.save().await.unwrap(), ["users"]);
+    assert_eq!(ids(&part(dir.path(), 1)), [1, 9]);
+    assert_eq!(ids(&part(dir.path(), 3)), [3, 7, 11]);
+    assert_eq!(ids(&part(dir.path(), 0)), [4, 8]);
+}
+
+#[tokio::test]
+async fn dropping_a_sharded_tree_removes_its_directory() {
+    let dir = ScratchDir::new("shards-drop");
+    let store = store_with_records(&dir, 3, 5).await;
+    assert!(dir.path().join("users").is_dir());
+
+    store.drop_tree("users").await.unwrap();
+    assert!(!dir.path().join("users").exists());
+    assert!(JsonStore::load(dir.path())
+        .await
+        .unwrap()
+        .list_trees()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn reshard_rewrites_every_record_into_the_new_layout() {
+    let dir = ScratchDir::new("shards-reshard");
+    let store = store_with_records(&dir, 4, 10).await;
+    // an unsaved change goes along
+    store.insert("users", &json!({"n": 11})).await.unwrap();
+
+    store.reshard_tree("users", 2).await.unwrap();
+    assert_eq!(parts(dir.path()), ["part-000.json", "part-001.json"]);
+    assert_eq!(ids(&part(dir.path(), 0)), [2, 4, 6, 8, 10]);
+    assert_eq!(ids(&part(dir.path(), 1)), [1, 3, 5, 7, 9, 11]);
+    assert_eq!(
+        read_json(&dir.path().join("infos.json"))["users"]["shards"],
+        2
+    );
+    assert_eq!(all(&store, "users").await, expected(11));
+
+    store.reshard_tree("users", 0).await.unwrap();
+    assert!(!dir.path().join("users").exists());
+    assert_eq!(ids(&dir.path().join("users.json")).len(), 11);
+    assert_eq!(store.get_info("users").unwrap().shards, None);
+    // and an unsharded tree's Info is written without the field, as before shards
+    assert!(read_json(&dir.path().join("infos.json"))["users"]
+        .get("shards")
+        .is_none());
+
+    store.reshard_tree("users", 3).await.unwrap();
+    assert!(!dir.path().join("users.json").exists());
+    let reloaded = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(all(&reloaded, "users").await, expected(11));
+    assert_eq!(
+        reloaded.insert("users", &json!({"n": 12})).await.unwrap(),
+        12
+    );
+}
+
+#[tokio::test]
+async fn zero_shards_is_not_a_sharded_tree() {
+    assert!(matches!(
+        Info::builder().sequence_field("id").shards(0).build(),
+        Err(JsonStoreError::InvalidInfo(_))
+    ));
+}
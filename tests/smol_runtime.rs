@@ -0,0 +1,81 @@
+#![cfg(all(feature = "smol", not(feature = "tokio")))]
+
+mod common;
+
+use common::{all, store_with_users, ScratchDir};
+use json_store::store::JsonStore;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+// The store on smol's executor, with no tokio runtime anywhere: everything else in
+// the suite runs under #[tokio::test] whichever runtime the store is built for, so
+// these drive the smol build from smol alone. Run with
+//
+//   cargo test --no-default-features --features smol
+
+#[test]
+fn records_survive_a_save_and_load() {
+    smol::block_on(async {
+        let dir = ScratchDir::new("smol_round_trip");
+        let store = store_with_users(&dir).await;
+        for n in 0..50 {
+            let user = json!({"email": format!("{}@x", n)});
+            store.insert("users", &user).await.unwrap();
+        }
+        store.delete("users", 7).await.unwrap();
+        store.close().await.unwrap();
+
+        let store = JsonStore::load(dir.path()).await.unwrap();
+        let users = all(&store, "users").await;
+        assert_eq!(users.len(), 49);
+        let user: Value = store.select("users", 8).await.unwrap();
+        assert_eq!(user["email"], "7@x");
+    })
+}
+
+#[test]
+fn tasks_on_smol_share_a_store() {
+    smol::block_on(async {
+        let dir = ScratchDir::new("smol_tasks");
+        let store = store_with_users(&dir).await;
+        let tasks = (0..8)
+            .map(|task| {
+                let store = store.clone();
+                smol::spawn(async move {
+                    for n in 0..25 {
+                        let user = json!({"email": format!("{}-{}@x", task, n)});
+                        store.insert("users", &user).await.unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for task in tasks {
+            task.await;
+        }
+        assert_eq!(all(&store, "users").await.len(), 200);
+        store.save().await.unwrap();
+    })
+}
+
+// autosave runs on the runtime's timer and in a task of its own
+#[test]
+fn autosave_runs_on_smol() {
+    smol::block_on(async {
+        let dir = ScratchDir::new("smol_autosave");
+        let store = store_with_users(&dir).await;
+        let autosave = store.start_autosave(Duration::from_millis(20));
+        store
+            .insert("users", &json!({"email": "a@x"}))
+            .await
+            .unwrap();
+
+        for _ in 0..250 {
+            if !store.is_dirty("users").await.unwrap() {
+                break;
+            }
+            smol::Timer::after(Duration::from_millis(20)).await;
+        }
+        assert!(!store.is_dirty("users").await.unwrap());
+        autosave.stop().await.unwrap();
+    })
+}
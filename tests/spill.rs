@@ -0,0 +1,181 @@
+mod common;
+
+use common::{all, ScratchDir};
+use json_store::{
+    index::SortOrder,
+    store::{Info, JsonStore, LoadOptions},
+};
+use serde_json::{json, Value};
+use std::path::Path;
+
+// The rest of the suite runs with spilling on as well when JSON_STORE_TEST_RESIDENT_LIMIT
+// is set, see tests/common. These set limits of their own and compare with a tree
+// that keeps everything.
+
+fn info(limit: Option<u32>) -> Info {
+    let builder = Info::builder()
+        .sequence_field("id")
+        .unique("email", ["email"])
+        .index("team")
+        .index_ordered("age");
+    match limit {
+        Some(limit) => builder.resident_limit(limit),
+        None => builder,
+    }
+    .build()
+    .unwrap()
+}
+
+async fn load(dir: &Path, spill: &Path) -> JsonStore {
+    let options = LoadOptions {
+        spill_dir: Some(spill.to_path_buf()),
+        ..Default::default()
+    };
+    JsonStore::load_with_options(dir, options).await.unwrap()
+}
+
+fn spill_files(spill: &Path) -> usize {
+    std::fs::read_dir(spill).unwrap().count()
+}
+
+// the same 200 users, with some updated and deleted, in a tree keeping limit of them
+async fn filled(dir: &ScratchDir, limit: Option<u32>) -> JsonStore {
+    let (data, spill) = (dir.path().join("data"), dir.path().join("spill"));
+    std::fs::create_dir_all(&spill).unwrap();
+    let store = load(&data, &spill).await;
+    store.create_tree("users", info(limit)).await.unwrap();
+    for n in 0..200u64 {
+        let user = json!({
+            "email": format!("{}@x", n),
+            "team": n % 7,
+            "age": n % 50,
+            "name": "x".repeat(40),
+        });
+        store.insert("users", &user).await.unwrap();
+    }
+    for id in (1..=200u64).step_by(9) {
+        let mut user: Value = store.select("users", id).await.unwrap();
+        user["age"] = json!(99);
+        store.update("users", &user).await.unwrap();
+    }
+    for id in (5..=200u64).step_by(13) {
+        store.delete("users", id).await.unwrap();
+    }
+    store
+}
+
+// each kind of find, indexed and not, over the users of filled
+async fn finds(store: &JsonStore) -> Vec<Vec<Value>> {
+    vec![
+        store
+            .find_by_field("users", "team", &json!(3))
+            .await
+            .unwrap(),
+        store
+            .find_by_field("users", "name", &json!("x".repeat(40)))
+            .await
+            .unwrap(),
+        store
+            .find_range("users", "age", json!(10)..json!(20))
+            .await
+            .unwrap(),
+        store
+            .find_sorted("users", "age", SortOrder::Descending, 15)
+            .await
+            .unwrap(),
+        store
+            .select_where("users", |user| user["team"] == json!(0))
+            .await
+            .unwrap(),
+    ]
+}
+
+#[tokio::test]
+async fn a_limited_tree_answers_as_one_kept_whole() {
+    let (whole_dir, limited_dir) = (
+        ScratchDir::new("spill_whole"),
+        ScratchDir::new("spill_limited"),
+    );
+    let whole = filled(&whole_dir, None).await;
+    let limited = filled(&limited_dir, Some(10)).await;
+    assert!(spill_files(&limited_dir.path().join("spill")) > 0);
+    assert_eq!(spill_files(&whole_dir.path().join("spill")), 0);
+
+    assert_eq!(all(&limited, "users").await, all(&whole, "users").await);
+    for id in [1, 2, 5, 100, 199, 200] {
+        let a: Result<Value, _> = whole.select("users", id).await;
+        let b: Result<Value, _> = limited.select("users", id).await;
+        assert_eq!(a.ok(), b.ok(), "user {}", id);
+    }
+
+    assert_eq!(finds(&limited).await, finds(&whole).await);
+
+    // uniqueness covers spilled records too
+    assert!(limited
+        .insert("users", &json!({"email": "0@x"}))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn a_limited_tree_saves_and_loads_every_record() {
+    let dir = ScratchDir::new("spill_reload");
+    let store = filled(&dir, Some(10)).await;
+    let expected = all(&store, "users").await;
+    store.save().await.unwrap();
+    drop(store);
+
+    let spill = dir.path().join("spill");
+    let store = load(&dir.path().join("data"), &spill).await;
+    assert_eq!(all(&store, "users").await, expected);
+    assert!(spill_files(&spill) > 0);
+    let user: Value = store.select("users", 2).await.unwrap();
+    assert_eq!(user["email"], "1@x");
+}
+
+#[tokio::test]
+async fn the_spill_file_goes_with_the_tree() {
+    let dir = ScratchDir::new("spill_drop");
+    let store = filled(&dir, Some(10)).await;
+    let spill = dir.path().join("spill");
+    assert_eq!(spill_files(&spill), 1);
+
+    store.drop_tree("users").await.unwrap();
+    assert_eq!(spill_files(&spill), 0);
+}
+
+#[tokio::test]
+async fn stale_copies_are_compacted_away() {
+    let dir = ScratchDir::new("spill_compact");
+    let (data, spill) = (dir.path().join("data"), dir.path().join("spill"));
+    std::fs::create_dir_all(&spill).unwrap();
+    let store = load(&data, &spill).await;
+    store.create_tree("users", info(Some(10))).await.unwrap();
+    let body = "x".repeat(2_000);
+    for n in 0..300u64 {
+        let user = json!({"email": format!("{}@x", n), "body": body});
+        store.insert("users", &user).await.unwrap();
+    }
+
+    // every record is read back, changed and spilled again, leaving its old copy dead
+    let mut written = 0;
+    for round in 0..4 {
+        for id in 1..=300u64 {
+            let mut user: Value = store.select("users", id).await.unwrap();
+            user["round"] = json!(round);
+            store.update("users", &user).await.unwrap();
+            written += body.len();
+        }
+    }
+    let size = std::fs::read_dir(&spill)
+        .unwrap()
+        .map(|entry| entry.unwrap().metadata().unwrap().len())
+        .sum::<u64>();
+    assert!((size as usize) < written / 2, "{} bytes", size);
+
+    let users = all(&store, "users").await;
+    assert_eq!(users.len(), 300);
+    assert!(users
+        .iter()
+        .all(|user| user["round"] == json!(3) && user["body"] == json!(body)));
+}
@@ -0,0 +1,442 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use common::{all, store_with_users, users, ScratchDir};
+use json_store::{
+    error::JsonStoreError,
+    import::{ImportMode, OnConflict},
+    sqlite::{SqliteColumns, SqliteExportOptions, SqliteImport},
+    store::JsonStore,
+};
+use rusqlite::{types::Value as SqlValue, Connection};
+use serde_json::{json, Value};
+use std::{collections::BTreeMap, path::PathBuf};
+
+// users with a field of every kind: some in every record, some in a few, some null,
+// some of mixed type, and some that can only go to a json column. Fields are written
+// in name order, so their columns come in the same order with arbitrary-precision on.
+async fn store_with_people(dir: &ScratchDir) -> JsonStore {
+    let store = store_with_users(dir).await;
+    for user in [
+        json!({"address": {"city": "Oslo"}, "admin": true, "age": 36, "email": "a@x",
+            "score": 1.5, "tag": "x"}),
+        json!({"admin": false, "age": -3, "email": "b@x", "langs": ["en", "no"],
+            "nick": null, "score": 2.25, "tag": 7}),
+        json!({"admin": false, "age": 1, "email": "c@x", "nick": "cy", "score": 0.5,
+            "tag": null}),
+    ] {
+        store.insert("users", &user).await.unwrap();
+    }
+    store
+}
+
+fn database(dir: &ScratchDir) -> PathBuf {
+    dir.path().join("out.sqlite")
+}
+
+fn flat() -> SqliteExportOptions {
+    SqliteExportOptions {
+        columns: SqliteColumns::Flat,
+        ..Default::default()
+    }
+}
+
+// every row of table, each as its columns' values in order
+fn rows(file: &PathBuf, table: &str) -> Vec<Vec<SqlValue>> {
+    let db = Connection::open(file).unwrap();
+    let mut select = db.prepare(&format!("SELECT * FROM \"{}\"", table)).unwrap();
+    let width = select.column_count();
+    let rows = select
+        .query_map([], |row| {
+            (0..width).map(|i| row.get::<_, SqlValue>(i)).collect()
+        })
+        .unwrap();
+    rows.map(Result::unwrap).collect()
+}
+
+// (name, declared type) of each column of table
+fn columns(file: &PathBuf, table: &str) -> Vec<(String, String)> {
+    let db = Connection::open(file).unwrap();
+    let mut info = db
+        .prepare(&format!("PRAGMA table_info(\"{}\")", table))
+        .unwrap();
+    let columns = info
+        .query_map([], |row| Ok((row.get(1)?, row.get(2)?)))
+        .unwrap();
+    columns.map(Result::unwrap).collect()
+}
+
+// a store with an empty users tree to import into
+async fn empty(name: &str) -> (ScratchDir, JsonStore) {
+    let dir = ScratchDir::new(name);
+    let store = store_with_users(&dir).await;
+    (dir, store)
+}
+
+#[tokio::test]
+async fn the_json_column_round_trips() {
+    let dir = ScratchDir::new("sqlite-json");
+    let store = store_with_people(&dir).await;
+    let file = database(&dir);
+
+    let written = store
+        .export_sqlite(&file, SqliteExportOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(written, BTreeMap::from([("users".to_string(), 3)]));
+    assert_eq!(
+        columns(&file, "users"),
+        [
+            ("id".to_string(), "INTEGER".to_string()),
+            ("json".to_string(), "TEXT".to_string())
+        ]
+    );
+    let expected = all(&store, "users").await;
+    for (row, record) in rows(&file, "users").iter().zip(&expected) {
+        assert_eq!(row[0], SqlValue::Integer(record["id"].as_i64().unwrap()));
+        let SqlValue::Text(text) = &row[1] else {
+            panic!("{:?}", row);
+        };
+        assert_eq!(&serde_json::from_str::<Value>(text).unwrap(), record);
+    }
+
+    let (_other, into) = empty("sqlite-json-in").await;
+    let mapping = SqliteImport {
+        json_column: Some("json".to_string()),
+        mode: ImportMode::Preserve,
+        ..SqliteImport::new("users")
+    };
+    let report = into.import_sqlite("users", &file, mapping).await.unwrap();
+    assert_eq!(report.imported, [1, 2, 3]);
+    assert!(report.skipped.is_empty());
+    assert_eq!(all(&into, "users").await, expected);
+}
+
+#[tokio::test]
+async fn flat_columns_round_trip() {
+    let dir = ScratchDir::new("sqlite-flat");
+    let store = store_with_people(&dir).await;
+    let file = database(&dir);
+    store.export_sqlite(&file, flat()).await.unwrap();
+
+    // a column per scalar field, typed by what it held; the rest as JSON
+    assert_eq!(
+        columns(&file, "users"),
+        [
+            ("id", "INTEGER"),
+            ("admin", "BOOLEAN"),
+            ("age", "INTEGER"),
+            ("email", "TEXT"),
+            ("score", "REAL"),
+            ("tag", ""),
+            ("nick", "TEXT"),
+            ("json", "TEXT"),
+        ]
+        .map(|(name, ty)| (name.to_string(), ty.to_string()))
+    );
+    assert_eq!(
+        rows(&file, "users")[1],
+        [
+            SqlValue::Integer(2),
+            SqlValue::Integer(0),
+            SqlValue::Integer(-3),
+            SqlValue::Text("b@x".to_string()),
+            SqlValue::Real(2.25),
+            SqlValue::Integer(7),
+            SqlValue::Null,
+            SqlValue::Text(json!({"langs": ["en", "no"]}).to_string()),
+        ]
+    );
+    // a record with nothing left over has NULL there
+    assert_eq!(rows(&file, "users")[2][7], SqlValue::Null);
+
+    let (_other, into) = empty("sqlite-flat-in").await;
+    let mapping = SqliteImport {
+        json_column: Some("json".to_string()),
+        mode: ImportMode::Preserve,
+        ..SqliteImport::new("users")
+    };
+    into.import_sqlite("users", &file, mapping).await.unwrap();
+
+    // as given, but that a missing field with a column comes back null
+    let mut expected = all(&store, "users").await;
+    expected[0]["nick"] = Value::Null;
+    assert_eq!(all(&into, "users").await, expected);
+}
+
+#[tokio::test]
+async fn a_flat_column_of_only_scalars_needs_no_json_column() {
+    let dir = ScratchDir::new("sqlite-flat-only");
+    let store = store_with_users(&dir).await;
+    for n in 0..3 {
+        store
+            .insert("users", &json!({ "email": format!("{}@x", n), "n": n }))
+            .await
+            .unwrap();
+    }
+    let file = database(&dir);
+    store.export_sqlite(&file, flat()).await.unwrap();
+    let names = columns(&file, "users")
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["id", "email", "n"]);
+}
+
+// What each JSON value goes to, and comes back as, in a flat column; the strings
+// among the numbers leave it with no type, so SQLite keeps each as written.
+#[tokio::test]
+async fn values_map_to_sqlite_types_and_back() {
+    let cases = [
+        (json!(null), SqlValue::Null, json!(null)),
+        (json!(true), SqlValue::Integer(1), json!(1)),
+        (json!(false), SqlValue::Integer(0), json!(0)),
+        (json!(-42), SqlValue::Integer(-42), json!(-42)),
+        (
+            json!(i64::MAX),
+            SqlValue::Integer(i64::MAX),
+            json!(i64::MAX),
+        ),
+        (json!(0.5), SqlValue::Real(0.5), json!(0.5)),
+        (json!(2.0), SqlValue::Real(2.0), json!(2.0)),
+        // past i64, so rounded
+        (
+            json!(u64::MAX),
+            SqlValue::Real(u64::MAX as f64),
+            json!(u64::MAX as f64),
+        ),
+        (
+            json!("text"),
+            SqlValue::Text("text".to_string()),
+            json!("text"),
+        ),
+        (json!(""), SqlValue::Text(String::new()), json!("")),
+    ];
+    let dir = ScratchDir::new("sqlite-types");
+    let store = store_with_users(&dir).await;
+    for (n, (value, _, _)) in cases.iter().enumerate() {
+        store
+            .insert("users", &json!({ "email": n, "v": value }))
+            .await
+            .unwrap();
+    }
+    let file = database(&dir);
+    store.export_sqlite(&file, flat()).await.unwrap();
+    assert_eq!(columns(&file, "users")[2], ("v".to_string(), String::new()));
+    let rows = rows(&file, "users");
+    for (row, (value, sql, _)) in rows.iter().zip(&cases) {
+        assert_eq!(&row[2], sql, "{}", value);
+    }
+
+    let (_other, into) = empty("sqlite-types-in").await;
+    let mapping = SqliteImport {
+        columns: vec![
+            ("email".to_string(), "email".to_string()),
+            ("v".to_string(), "v".to_string()),
+        ],
+        ..SqliteImport::new("users")
+    };
+    into.import_sqlite("users", &file, mapping).await.unwrap();
+    let records = all(&into, "users").await;
+    assert_eq!(records.len(), cases.len());
+    for (record, (value, _, back)) in records.iter().zip(&cases) {
+        assert_eq!(&record["v"], back, "{}", value);
+    }
+
+    // a column of booleans only is declared so, and comes back as booleans
+    let admins = ScratchDir::new("sqlite-bools");
+    let store = store_with_users(&admins).await;
+    for (n, admin) in [true, false].into_iter().enumerate() {
+        store
+            .insert("users", &json!({ "admin": admin, "email": n }))
+            .await
+            .unwrap();
+    }
+    let file = database(&admins);
+    store.export_sqlite(&file, flat()).await.unwrap();
+    assert_eq!(columns(&file, "users")[1].1, "BOOLEAN");
+    let (_other, into) = empty("sqlite-bools-in").await;
+    into.import_sqlite("users", &file, SqliteImport::new("users"))
+        .await
+        .unwrap();
+    let admins = all(&into, "users")
+        .await
+        .iter()
+        .map(|record| record["admin"].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(admins, [json!(true), json!(false)]);
+}
+
+// numbers of both sorts in one column, which SQLite would change in a NUMERIC one
+#[tokio::test]
+async fn integers_and_fractions_keep_their_form() {
+    let dir = ScratchDir::new("sqlite-numbers");
+    let store = store_with_users(&dir).await;
+    for (n, v) in [json!(1), json!(2.0), json!(0.5)].into_iter().enumerate() {
+        store
+            .insert("users", &json!({ "email": n, "v": v }))
+            .await
+            .unwrap();
+    }
+    let file = database(&dir);
+    store.export_sqlite(&file, flat()).await.unwrap();
+    assert_eq!(columns(&file, "users")[2], ("v".to_string(), String::new()));
+
+    let (_other, into) = empty("sqlite-numbers-in").await;
+    into.import_sqlite("users", &file, SqliteImport::new("users"))
+        .await
+        .unwrap();
+    assert_eq!(all(&into, "users").await, all(&store, "users").await);
+}
+
+// a table made by hand, with the kinds of values no export writes
+fn write_table(file: &PathBuf) {
+    let db = Connection::open(file).unwrap();
+    db.execute_batch(
+        "CREATE TABLE people (pid INTEGER PRIMARY KEY, mail TEXT, photo BLOB, ok BOOL, \
+             extra TEXT, ignored TEXT);
+         INSERT INTO people VALUES (10, 'a@x', x'00ff10', 1, '{\"age\": 3}', 'no');
+         INSERT INTO people VALUES (20, 'b@x', NULL, 0, NULL, 'no');
+         INSERT INTO people VALUES (30, 'a@x', NULL, 5, NULL, 'no');
+         INSERT INTO people VALUES (40, 'd@x', NULL, NULL, '[1]', 'no');",
+    )
+    .unwrap();
+}
+
+#[tokio::test]
+async fn columns_map_to_the_fields_given() {
+    let dir = ScratchDir::new("sqlite-mapping");
+    let file = database(&dir);
+    write_table(&file);
+    let store = store_with_users(&dir).await;
+
+    let mapping = SqliteImport {
+        columns: vec![
+            ("pid".to_string(), "id".to_string()),
+            ("mail".to_string(), "email".to_string()),
+            ("photo".to_string(), "photo".to_string()),
+            ("ok".to_string(), "ok".to_string()),
+        ],
+        json_column: Some("extra".to_string()),
+        mode: ImportMode::Preserve,
+        on_conflict: OnConflict::Skip,
+        ..SqliteImport::new("people")
+    };
+    let report = store.import_sqlite("users", &file, mapping).await.unwrap();
+    // the sequences kept; row 2 clashes on email and row 3 has no JSON object
+    assert_eq!(report.imported, [10, 20]);
+    let reasons = report
+        .skipped
+        .iter()
+        .map(|issue| issue.index)
+        .collect::<Vec<_>>();
+    assert_eq!(reasons, [2, 3]);
+    assert!(
+        report.skipped[1].reason.contains("'extra'"),
+        "{:?}",
+        report.skipped
+    );
+    assert_eq!(
+        all(&store, "users").await,
+        [
+            json!({"id": 10, "email": "a@x", "photo": "00ff10", "ok": true, "age": 3}),
+            json!({"id": 20, "email": "b@x", "photo": null, "ok": false}),
+        ]
+    );
+
+    // with Append they're numbered on from the tree's counter instead
+    let (_other, into) = empty("sqlite-mapping-append").await;
+    let mapping = SqliteImport {
+        columns: vec![
+            ("pid".to_string(), "id".to_string()),
+            ("mail".to_string(), "email".to_string()),
+        ],
+        on_conflict: OnConflict::Skip,
+        ..SqliteImport::new("people")
+    };
+    let report = into.import_sqlite("users", &file, mapping).await.unwrap();
+    assert_eq!(report.imported, [1, 2, 3]);
+}
+
+#[tokio::test]
+async fn bad_mappings_and_tables_are_errors() {
+    let dir = ScratchDir::new("sqlite-errors");
+    let file = database(&dir);
+    write_table(&file);
+    let store = store_with_users(&dir).await;
+
+    for mapping in [
+        SqliteImport {
+            columns: vec![("phone".to_string(), "phone".to_string())],
+            ..SqliteImport::new("people")
+        },
+        SqliteImport {
+            json_column: Some("json".to_string()),
+            ..SqliteImport::new("people")
+        },
+    ] {
+        assert!(matches!(
+            store.import_sqlite("users", &file, mapping).await,
+            Err(JsonStoreError::InvalidOptions(_))
+        ));
+    }
+    assert!(matches!(
+        store
+            .import_sqlite("users", &file, SqliteImport::new("nobody"))
+            .await,
+        Err(JsonStoreError::Sqlite(_))
+    ));
+    // with Fail, the first bad row stops the import and nothing goes in
+    let mapping = SqliteImport {
+        columns: vec![("mail".to_string(), "email".to_string())],
+        ..SqliteImport::new("people")
+    };
+    assert!(store.import_sqlite("users", &file, mapping).await.is_err());
+    assert!(all(&store, "users").await.is_empty());
+    assert!(matches!(
+        store
+            .import_sqlite("nothing", &file, SqliteImport::new("people"))
+            .await,
+        Err(JsonStoreError::NotFoundTree(_))
+    ));
+}
+
+#[tokio::test]
+async fn an_export_replaces_its_tables_and_leaves_others() {
+    let dir = ScratchDir::new("sqlite-replace");
+    let store = store_with_people(&dir).await;
+    store.create_tree("teams", users()).await.unwrap();
+    let file = database(&dir);
+    write_table(&file);
+
+    store
+        .export_sqlite(&file, SqliteExportOptions::default())
+        .await
+        .unwrap();
+    store.delete("users", 1).await.unwrap();
+    // only the trees asked for, and as flat as they are now
+    let options = SqliteExportOptions {
+        trees: Some(vec!["users".to_string()]),
+        ..flat()
+    };
+    let written = store.export_sqlite(&file, options).await.unwrap();
+    assert_eq!(written, BTreeMap::from([("users".to_string(), 2)]));
+
+    assert_eq!(rows(&file, "users").len(), 2);
+    assert!(columns(&file, "users").len() > 2);
+    assert!(rows(&file, "teams").is_empty());
+    assert_eq!(rows(&file, "people").len(), 4);
+
+    // a field called as the json column would be written twice
+    store
+        .insert("users", &json!({"email": "j@x", "json": 1, "list": []}))
+        .await
+        .unwrap();
+    assert!(matches!(
+        store.export_sqlite(&file, flat()).await,
+        Err(JsonStoreError::InvalidOptions(_))
+    ));
+    // and the failed export left the database as it was
+    assert_eq!(rows(&file, "users").len(), 2);
+}
@@ -0,0 +1,153 @@
+mod common;
+
+use common::{store_with_users, users, ScratchDir};
+use json_store::{error::JsonStoreError, store::JsonStore};
+use serde_json::json;
+use std::path::Path;
+
+// bytes of the files in dir named tname.*
+fn files_of(dir: &Path, tname: &str) -> u64 {
+    std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap())
+        .filter(|entry| {
+            let name = entry.file_name();
+            name.to_string_lossy().starts_with(&format!("{}.", tname))
+        })
+        .map(|entry| entry.metadata().unwrap().len())
+        .sum()
+}
+
+#[tokio::test]
+async fn tree_stats_count_records_and_follow_saves() {
+    let dir = ScratchDir::new("stats-tree");
+    let store = store_with_users(&dir).await;
+    for n in 1..=5 {
+        store
+            .insert("users", &json!({"email": format!("{}@x", n)}))
+            .await
+            .unwrap();
+    }
+    store.delete("users", 2).await.unwrap();
+
+    let stats = store.tree_stats("users").await.unwrap();
+    assert_eq!((stats.records, stats.sequence), (4, 5));
+    assert!(stats.dirty && stats.loaded);
+    assert!(stats.memory_bytes > 0);
+    assert_eq!(
+        stats.memory_bytes,
+        store.memory_estimate("users").await.unwrap()
+    );
+
+    store.save().await.unwrap();
+    let stats = store.tree_stats("users").await.unwrap();
+    assert!(!stats.dirty);
+    assert_eq!(stats.disk_bytes, files_of(dir.path(), "users"));
+    assert!(stats.disk_bytes > 0);
+
+    assert!(matches!(
+        store.tree_stats("missing").await,
+        Err(JsonStoreError::NotFoundTree(_))
+    ));
+}
+
+#[tokio::test]
+async fn memory_grows_with_the_records() {
+    let dir = ScratchDir::new("stats-memory");
+    let store = store_with_users(&dir).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    let one = store.tree_stats("users").await.unwrap().memory_bytes;
+    store
+        .insert("users", &json!({"email": "b@x", "bio": "x".repeat(10_000)}))
+        .await
+        .unwrap();
+    let two = store.tree_stats("users").await.unwrap().memory_bytes;
+    assert!(two >= one + 10_000, "{} then {}", one, two);
+}
+
+#[tokio::test]
+async fn a_tree_not_loaded_has_no_records_but_its_files() {
+    let dir = ScratchDir::new("stats-unloaded");
+    let store = store_with_users(&dir).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    let stats = store.tree_stats("users").await.unwrap();
+    assert!(!stats.loaded && !stats.dirty);
+    assert_eq!((stats.records, stats.memory_bytes), (0, 0));
+    assert_eq!(stats.disk_bytes, files_of(dir.path(), "users"));
+    // asking does not load it
+    assert!(store.loaded_trees().await.is_empty());
+}
+
+#[tokio::test]
+async fn store_stats_add_up_the_trees() {
+    let dir = ScratchDir::new("stats-store");
+    let store = store_with_users(&dir).await;
+    store.create_tree("posts", users()).await.unwrap();
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store
+        .insert("posts", &json!({"email": "p@x"}))
+        .await
+        .unwrap();
+    store
+        .insert("posts", &json!({"email": "q@x"}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+
+    let stats = store.stats().await.unwrap();
+    assert_eq!(stats.path, dir.path());
+    assert_eq!(stats.tree_count, 2);
+    assert_eq!(stats.records, 3);
+    assert_eq!(stats.trees["posts"].records, 2);
+    assert_eq!(
+        stats.memory_bytes,
+        stats.trees.values().map(|t| t.memory_bytes).sum::<usize>()
+    );
+    assert_eq!(
+        stats.disk_bytes,
+        files_of(dir.path(), "users") + files_of(dir.path(), "posts")
+    );
+}
+
+#[tokio::test]
+async fn stats_serialize_and_print() {
+    let dir = ScratchDir::new("stats-output");
+    let store = store_with_users(&dir).await;
+    store.create_tree("posts", users()).await.unwrap();
+    store.save().await.unwrap();
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+
+    let stats = store.stats().await.unwrap();
+    let value = serde_json::to_value(&stats).unwrap();
+    assert_eq!(value["tree_count"], 2);
+    assert_eq!(value["trees"]["users"]["records"], 1);
+    assert_eq!(value["trees"]["users"]["dirty"], true);
+    assert_eq!(value["trees"]["posts"]["loaded"], false);
+    assert_eq!(value["path"], json!(dir.path()));
+
+    let text = stats.to_string();
+    assert!(text.contains("2 trees, 1 records"), "{}", text);
+    assert!(text.contains("  users: 1 records, sequence 1"), "{}", text);
+    assert!(text
+        .lines()
+        .any(|l| l.starts_with("  users") && l.ends_with("(dirty)")));
+    assert!(text
+        .lines()
+        .any(|l| l.starts_with("  posts") && l.ends_with("(not loaded)")));
+}
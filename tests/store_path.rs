@@ -0,0 +1,137 @@
+mod common;
+
+use common::{all, users, ScratchDir};
+use json_store::{
+    error::{ErrorKind, JsonStoreError},
+    store::{JsonStore, LoadOptions},
+};
+use serde_json::json;
+use std::path::Path;
+
+fn strict() -> LoadOptions {
+    LoadOptions {
+        create_if_missing: false,
+        ..Default::default()
+    }
+}
+
+fn reason(result: Result<JsonStore, JsonStoreError>, expected: &Path) -> String {
+    match result {
+        Err(e @ JsonStoreError::InvalidStorePath { .. }) => {
+            assert_eq!(e.kind(), ErrorKind::InvalidInput);
+            // the message names the path
+            assert!(
+                e.to_string().contains(&expected.display().to_string()),
+                "{}",
+                e
+            );
+            match e {
+                JsonStoreError::InvalidStorePath { path, reason } => {
+                    assert_eq!(path, expected);
+                    reason
+                }
+                _ => unreachable!(),
+            }
+        }
+        Err(e) => panic!("expected InvalidStorePath, got {:?}", e),
+        Ok(_) => panic!("loaded a store at {:?}", expected),
+    }
+}
+
+#[tokio::test]
+async fn a_missing_nested_directory_is_created() {
+    let dir = ScratchDir::new("store-path-nested");
+    let path = dir.path().join("a/b/c");
+
+    let store = JsonStore::load(&path).await.unwrap();
+    assert!(path.is_dir());
+    store.create_tree("users", users()).await.unwrap();
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+
+    let reloaded = JsonStore::load(&path).await.unwrap();
+    assert_eq!(all(&reloaded, "users").await.len(), 1);
+    // no probe or temp file is left behind
+    let names = std::fs::read_dir(&path)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    assert!(
+        names
+            .iter()
+            .all(|n| !n.contains("probe") && !n.ends_with(".tmp")),
+        "{:?}",
+        names
+    );
+}
+
+#[tokio::test]
+async fn strict_loading_wants_the_directory_to_exist() {
+    let dir = ScratchDir::new("store-path-strict");
+    let path = dir.path().join("missing");
+
+    let why = reason(JsonStore::load_with_options(&path, strict()).await, &path);
+    assert_eq!(why, "does not exist");
+    assert!(!path.exists());
+
+    // an existing directory loads either way
+    std::fs::create_dir(&path).unwrap();
+    JsonStore::load_with_options(&path, strict()).await.unwrap();
+}
+
+#[tokio::test]
+async fn a_file_in_the_way_is_refused() {
+    let dir = ScratchDir::new("store-path-file");
+    let path = dir.path().join("data");
+    std::fs::write(&path, "not a store").unwrap();
+
+    let why = reason(JsonStore::load(&path).await, &path);
+    assert_eq!(why, "not a directory");
+    let why = reason(JsonStore::load_read_only(&path).await, &path);
+    assert_eq!(why, "not a directory");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "not a store");
+
+    // nor can a directory be made below it
+    let below = path.join("store");
+    let why = reason(JsonStore::load(&below).await, &below);
+    assert!(why.to_lowercase().contains("directory"), "{}", why);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn a_read_only_directory_is_refused() {
+    use std::{fs::Permissions, os::unix::fs::PermissionsExt};
+
+    let dir = ScratchDir::new("store-path-read-only");
+    let path = dir.path().join("data");
+    std::fs::create_dir(&path).unwrap();
+    std::fs::set_permissions(&path, Permissions::from_mode(0o555)).unwrap();
+
+    // root writes anyway, so there is nothing to see
+    let probe = path.join("probe");
+    if std::fs::write(&probe, "").is_ok() {
+        std::fs::remove_file(&probe).unwrap();
+        std::fs::set_permissions(&path, Permissions::from_mode(0o755)).unwrap();
+        return;
+    }
+
+    let why = reason(JsonStore::load(&path).await, &path);
+    assert!(why.starts_with("not writable"), "{}", why);
+    // reading needs no writes
+    JsonStore::load_read_only(&path).await.unwrap();
+
+    std::fs::set_permissions(&path, Permissions::from_mode(0o755)).unwrap();
+}
+
+#[tokio::test]
+async fn a_read_only_load_creates_nothing() {
+    let dir = ScratchDir::new("store-path-read-only-missing");
+    let path = dir.path().join("missing");
+
+    let why = reason(JsonStore::load_read_only(&path).await, &path);
+    assert_eq!(why, "does not exist");
+    assert!(!path.exists());
+}
@@ -0,0 +1,167 @@
+#![cfg(feature = "tracing")]
+
+mod common;
+
+use common::{store_with_users, ScratchDir};
+use serde_json::{json, Value};
+use std::{
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    Event, Level, Metadata, Subscriber,
+};
+
+// what was traced, each span or event as its name or message and its fields
+#[derive(Debug, Clone, PartialEq)]
+struct Traced {
+    level: Level,
+    name: String,
+    fields: Vec<(String, String)>,
+}
+
+#[derive(Default)]
+struct Fields(Vec<(String, String)>, Option<String>);
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        let value = format!("{:?}", value);
+        match field.name() {
+            "message" => self.1 = Some(value),
+            name => self.0.push((name.to_string(), value)),
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.push((field.name().to_string(), value.to_string()));
+    }
+}
+
+// a subscriber keeping every span and event, enough for checking what the store emits
+#[derive(Clone, Default)]
+struct Capture {
+    spans: Arc<Mutex<Vec<Traced>>>,
+    events: Arc<Mutex<Vec<Traced>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Subscriber for Capture {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut fields = Fields::default();
+        span.record(&mut fields);
+        self.spans.lock().unwrap().push(Traced {
+            level: *span.metadata().level(),
+            name: span.metadata().name().to_string(),
+            fields: fields.0,
+        });
+        Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed) + 1)
+    }
+
+    fn record(&self, _: &Id, _: &Record<'_>) {}
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        self.events.lock().unwrap().push(Traced {
+            level: *event.metadata().level(),
+            name: fields.1.unwrap_or_default(),
+            fields: fields.0,
+        });
+    }
+
+    fn enter(&self, _: &Id) {}
+
+    fn exit(&self, _: &Id) {}
+}
+
+impl Capture {
+    fn spans(&self, name: &str) -> Vec<Traced> {
+        let spans = self.spans.lock().unwrap();
+        spans.iter().filter(|s| s.name == name).cloned().collect()
+    }
+}
+
+fn field(name: &str, value: &str) -> (String, String) {
+    (name.to_string(), value.to_string())
+}
+
+#[tokio::test]
+async fn operations_run_in_spans_naming_their_tree() {
+    let capture = Capture::default();
+    // the test runtime is on this one thread, so the store's spans come here
+    let _default = tracing::subscriber::set_default(capture.clone());
+
+    let dir = ScratchDir::new("tracing-spans");
+    let store = store_with_users(&dir).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store.select::<Value>("users", 1).await.unwrap();
+    store.select_shared("users", 1).await.unwrap();
+    store.drop_tree("users").await.unwrap();
+
+    for op in ["insert", "select", "select_shared", "drop_tree"] {
+        let spans = capture.spans(op);
+        assert!(!spans.is_empty(), "no {} span", op);
+        assert_eq!(spans[0].level, Level::DEBUG);
+        assert!(
+            spans[0].fields.contains(&field("tree", "users")),
+            "{:?}",
+            spans[0]
+        );
+    }
+    // and the record's sequence where one is named
+    let select = &capture.spans("select_shared")[0];
+    assert!(
+        select.fields.contains(&field("sequence", "1")),
+        "{:?}",
+        select
+    );
+}
+
+#[tokio::test]
+async fn a_store_dropped_unsaved_warns() {
+    let capture = Capture::default();
+    let _default = tracing::subscriber::set_default(capture.clone());
+
+    let dir = ScratchDir::new("tracing-events");
+    let store = store_with_users(&dir).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    drop(store);
+
+    let events = capture.events.lock().unwrap();
+    let dropped = events
+        .iter()
+        .find(|event| event.name.contains("dropped with unsaved changes"))
+        .expect("a warning");
+    assert_eq!(dropped.level, Level::WARN);
+    assert!(
+        dropped.fields.contains(&field("trees", "[\"users\"]")),
+        "{:?}",
+        dropped
+    );
+}
+
+// show() is kept, as it was, for callers of old
+#[allow(deprecated)]
+#[tokio::test]
+async fn show_still_works() {
+    let dir = ScratchDir::new("tracing-show");
+    let store = store_with_users(&dir).await;
+    store.show();
+}
@@ -0,0 +1,135 @@
+mod common;
+
+use common::{read_json, store_with_users, users, ScratchDir};
+use json_store::{error::JsonStoreError, merge::MergePolicy, store::JsonStore};
+use serde_json::json;
+
+// "café" with é as one code point (NFC) and as e and a combining acute (NFD)
+const CAFE_NFC: &str = "caf\u{e9}";
+const CAFE_NFD: &str = "cafe\u{301}";
+
+#[track_caller]
+fn assert_collision(result: Result<(), JsonStoreError>, tree: &str, existing: &str) {
+    match result {
+        Err(JsonStoreError::TreeNameCollision {
+            tree: t,
+            existing: e,
+        }) => {
+            assert_eq!(t, tree);
+            assert_eq!(e, existing);
+        }
+        other => panic!("{} against {}: {:?}", tree, existing, other),
+    }
+}
+
+#[tokio::test]
+async fn names_that_fold_alike_are_refused() {
+    let dir = ScratchDir::new("names-fold");
+    let store = store_with_users(&dir).await;
+    store.create_tree(CAFE_NFC, users()).await.unwrap();
+    store.create_tree("Straße", users()).await.unwrap();
+
+    for (tree, existing) in [
+        ("Users", "users"),
+        ("USERS", "users"),
+        ("uSeRs", "users"),
+        (CAFE_NFD, CAFE_NFC),
+        // case and normalization at once
+        ("CAFE\u{301}", CAFE_NFC),
+        ("CAF\u{c9}", CAFE_NFC),
+        ("STRAßE", "Straße"),
+    ] {
+        assert_collision(store.create_tree(tree, users()).await, tree, existing);
+        assert_collision(store.ensure_tree(tree, users()).await, tree, existing);
+        assert!(!store.has_tree(tree));
+    }
+    let mut trees = store.list_trees();
+    trees.sort();
+    assert_eq!(trees, ["Straße", "caf\u{e9}", "users"]);
+}
+
+#[tokio::test]
+async fn distinct_names_are_still_distinct() {
+    let dir = ScratchDir::new("names-distinct");
+    let store = store_with_users(&dir).await;
+    for tree in ["user", "users2", "users_", "usérs", "cafe", CAFE_NFC] {
+        store.create_tree(tree, users()).await.unwrap();
+    }
+    assert_eq!(store.list_trees().len(), 7);
+    // the name is kept as given, not folded
+    store
+        .insert(CAFE_NFC, &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store.close().await.unwrap();
+    assert!(dir.path().join(format!("{}.json", CAFE_NFC)).exists());
+    let infos = read_json(&dir.path().join("infos.json"));
+    assert!(infos.get(CAFE_NFC).is_some(), "{}", infos);
+}
+
+#[tokio::test]
+async fn a_name_is_free_again_once_its_tree_is_dropped() {
+    let dir = ScratchDir::new("names-dropped");
+    let store = store_with_users(&dir).await;
+    store.drop_tree("users").await.unwrap();
+    store.create_tree("Users", users()).await.unwrap();
+    assert!(store.has_tree("Users"));
+    assert!(!store.has_tree("users"));
+}
+
+// infos.json written before names were checked, or edited by hand
+#[tokio::test]
+async fn a_store_already_holding_a_collision_does_not_load() {
+    for (a, b) in [("Users", "users"), (CAFE_NFC, CAFE_NFD)] {
+        let dir = ScratchDir::new("names-load");
+        let store = store_with_users(&dir).await;
+        store.close().await.unwrap();
+        let infos = dir.path().join("infos.json");
+        let mut catalog = read_json(&infos);
+        let info = catalog["users"].take();
+        catalog = json!({ a: info.clone(), b: info });
+        std::fs::write(&infos, catalog.to_string()).unwrap();
+
+        let error = JsonStore::load(dir.path()).await.unwrap_err();
+        let JsonStoreError::TreeNameCollision { tree, existing } = &error else {
+            panic!("{}", error);
+        };
+        let mut names = [tree.as_str(), existing.as_str()];
+        names.sort();
+        let mut expected = [a, b];
+        expected.sort();
+        assert_eq!(names, expected);
+        assert!(error.to_string().contains(tree.as_str()), "{}", error);
+    }
+}
+
+#[tokio::test]
+async fn a_merge_does_not_bring_in_a_colliding_tree() {
+    let dir = ScratchDir::new("names-merge");
+    let store = store_with_users(&dir).await;
+    let other = ScratchDir::new("names-merge-other");
+    let source = JsonStore::load(other.path()).await.unwrap();
+    source.create_tree("Users", users()).await.unwrap();
+    source
+        .insert("Users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    // a tree after it in the source is merged all the same
+    source.create_tree("zebras", users()).await.unwrap();
+    source.close().await.unwrap();
+
+    let report = store
+        .merge_from(other.path(), MergePolicy::PreferOther)
+        .await
+        .unwrap();
+    assert_eq!(report.created, ["zebras"], "{:?}", report);
+    assert!(!store.has_tree("Users"));
+    assert!(store.has_tree("zebras"));
+    let (tree, error) = &report.failed[0];
+    assert_eq!(tree, "Users");
+    assert!(
+        matches!(error, JsonStoreError::TreeNameCollision { .. }),
+        "{}",
+        error
+    );
+}
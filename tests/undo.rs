@@ -0,0 +1,171 @@
+mod common;
+
+use common::{all, store_with_users, users, ScratchDir};
+use json_store::{
+    cold::{ArchiveDest, PruneStrategy},
+    error::JsonStoreError,
+    import::{ImportMode, OnConflict},
+    store::{Info, JsonStore},
+    undo::{UndoInfo, UndoOp},
+};
+use serde_json::{json, Value};
+
+fn undone(op: UndoOp, sequences: &[u64]) -> UndoInfo {
+    UndoInfo {
+        op,
+        sequences: sequences.to_vec(),
+    }
+}
+
+#[tokio::test]
+async fn each_write_is_taken_back() {
+    let dir = ScratchDir::new("undo-each");
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    let info = Info::builder()
+        .sequence_field("id")
+        .unique("email", ["email"])
+        .history(5)
+        .build()
+        .unwrap();
+    store.create_tree("users", info).await.unwrap();
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+
+    // insert
+    store
+        .insert("users", &json!({"email": "b@x"}))
+        .await
+        .unwrap();
+    let info = store.undo_last("users").await.unwrap();
+    assert_eq!(info, undone(UndoOp::Insert, &[2]));
+    assert_eq!(
+        all(&store, "users").await,
+        [json!({"id": 1, "email": "a@x"})]
+    );
+
+    // update
+    store
+        .update("users", &json!({"id": 1, "email": "c@x"}))
+        .await
+        .unwrap();
+    let info = store.undo_last("users").await.unwrap();
+    assert_eq!(info, undone(UndoOp::Update, &[1]));
+    assert_eq!(
+        all(&store, "users").await,
+        [json!({"id": 1, "email": "a@x"})]
+    );
+
+    // delete
+    store.delete("users", 1).await.unwrap();
+    let info = store.undo_last("users").await.unwrap();
+    assert_eq!(info, undone(UndoOp::Delete, &[1]));
+    assert_eq!(
+        all(&store, "users").await,
+        [json!({"id": 1, "email": "a@x"})]
+    );
+
+    // revert, to c@x as the undo of the update kept it; newer is the delete's a@x
+    store.revert("users", 1, 1).await.unwrap();
+    assert_eq!(
+        store.select::<Value>("users", 1).await.unwrap()["email"],
+        "c@x"
+    );
+    let info = store.undo_last("users").await.unwrap();
+    assert_eq!(info, undone(UndoOp::Revert, &[1]));
+    assert_eq!(
+        all(&store, "users").await,
+        [json!({"id": 1, "email": "a@x"})]
+    );
+
+    // a bulk import, all of it
+    let file = dir.path().join("more.ndjson");
+    std::fs::write(
+        &file,
+        "{\"email\":\"d@x\"}\n{\"email\":\"e@x\"}\n{\"email\":\"f@x\"}\n",
+    )
+    .unwrap();
+    let report = store
+        .import_ndjson("users", &file, ImportMode::Append, OnConflict::Fail)
+        .await
+        .unwrap();
+    assert_eq!(report.imported.len(), 3);
+    let info = store.undo_last("users").await.unwrap();
+    assert_eq!(info.op, UndoOp::Import);
+    let mut sequences = info.sequences;
+    sequences.sort();
+    assert_eq!(sequences, report.imported);
+    assert_eq!(
+        all(&store, "users").await,
+        [json!({"id": 1, "email": "a@x"})]
+    );
+
+    // and down to the first insert, then nothing
+    assert_eq!(
+        store.undo_last("users").await.unwrap(),
+        undone(UndoOp::Insert, &[1])
+    );
+    assert!(all(&store, "users").await.is_empty());
+    match store.undo_last("users").await {
+        Err(JsonStoreError::NothingToUndo(tree)) => assert_eq!(tree, "users"),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn an_undo_clashing_with_a_later_record_changes_nothing() {
+    let dir = ScratchDir::new("undo-conflict");
+    let store = store_with_users(&dir).await;
+    for email in ["a@x", "b@x", "c@x"] {
+        store
+            .insert("users", &json!({ "email": email }))
+            .await
+            .unwrap();
+    }
+    // 1 and 2 pruned in one go, for one undo to bring back
+    store.set_tree_capacity("users", 1).await.unwrap();
+    let pruned = store
+        .prune_to_capacity("users", PruneStrategy::Oldest)
+        .await
+        .unwrap();
+    assert_eq!(pruned, [1, 2]);
+    store.set_tree_capacity("users", 10).await.unwrap();
+
+    // b@x comes back under another sequence, by a move that leaves users' undo
+    // stack as it was
+    store.create_tree("old", users()).await.unwrap();
+    for email in ["x@x", "y@x", "z@x", "w@x", "b@x"] {
+        store
+            .insert("old", &json!({ "email": email }))
+            .await
+            .unwrap();
+    }
+    store
+        .archive_where(
+            "old",
+            |record| record["email"] == "b@x",
+            ArchiveDest::Tree("users".to_string()),
+        )
+        .await
+        .unwrap();
+    let before = all(&store, "users").await;
+    assert_eq!(
+        before,
+        [
+            json!({"id": 3, "email": "c@x"}),
+            json!({"id": 5, "email": "b@x"}),
+        ]
+    );
+
+    // neither 1 nor 2 comes back, and the prune is still there to undo
+    for _ in 0..2 {
+        match store.undo_last("users").await {
+            Err(JsonStoreError::UndoConflict { tree, sequence, .. }) => {
+                assert_eq!((tree.as_str(), sequence), ("users", 2));
+            }
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(all(&store, "users").await, before);
+    }
+}
@@ -0,0 +1,122 @@
+mod common;
+
+use common::{all, read_json, store_with_users, users, ScratchDir};
+use futures::FutureExt;
+use json_store::{error::JsonStoreError, store::JsonStore};
+use serde_json::{json, Value};
+use std::sync::Mutex;
+
+async fn store_with_records(dir: &ScratchDir, count: u64) -> JsonStore {
+    let store = store_with_users(dir).await;
+    for n in 1..=count {
+        store
+            .insert("users", &json!({"email": format!("{}@x", n)}))
+            .await
+            .unwrap();
+    }
+    store
+}
+
+#[tokio::test]
+async fn unload_saves_and_the_next_use_reads_it_back() {
+    let dir = ScratchDir::new("unload-round-trip");
+    let store = store_with_records(&dir, 50).await;
+    let records = all(&store, "users").await;
+    assert_eq!(store.loaded_trees().await, ["users"]);
+
+    store.unload_tree("users").await.unwrap();
+    assert!(store.loaded_trees().await.is_empty());
+    assert_eq!(store.memory_estimate("users").await.unwrap(), 0);
+    // the unsaved inserts went to disk first
+    let file = read_json(&dir.path().join("users.json"));
+    assert_eq!(file.as_object().unwrap().len(), 50);
+    assert!(store.unsaved_changes().await.is_empty());
+
+    assert_eq!(all(&store, "users").await, records);
+    assert_eq!(store.loaded_trees().await, ["users"]);
+    assert!(store.memory_estimate("users").await.unwrap() > 0);
+    assert_eq!(
+        store
+            .insert("users", &json!({"email": "51@x"}))
+            .await
+            .unwrap(),
+        51
+    );
+}
+
+#[tokio::test]
+async fn unload_leaves_other_trees_and_the_catalog_alone() {
+    let dir = ScratchDir::new("unload-others");
+    let store = store_with_records(&dir, 3).await;
+    store.create_tree("posts", users()).await.unwrap();
+    store
+        .insert("posts", &json!({"email": "p@x"}))
+        .await
+        .unwrap();
+
+    store.unload_tree("users").await.unwrap();
+    assert_eq!(store.loaded_trees().await, ["posts"]);
+    assert_eq!(store.list_trees(), ["posts", "users"]);
+    assert_eq!(store.get_info("users").unwrap(), users());
+    // posts kept its unsaved insert
+    assert!(store.is_dirty("posts").await.unwrap());
+
+    // unloading twice, or a tree never loaded, is fine
+    store.unload_tree("users").await.unwrap();
+    assert!(matches!(
+        store.unload_tree("missing").await,
+        Err(JsonStoreError::NotFoundTree(_))
+    ));
+}
+
+#[tokio::test]
+async fn the_estimate_follows_the_records() {
+    let dir = ScratchDir::new("unload-estimate");
+    let store = store_with_records(&dir, 1).await;
+    let small = store.memory_estimate("users").await.unwrap();
+    for n in 2..=500 {
+        store
+            .insert("users", &json!({"email": format!("{}@x", n)}))
+            .await
+            .unwrap();
+    }
+    assert!(store.memory_estimate("users").await.unwrap() > small * 10);
+}
+
+#[tokio::test]
+async fn unload_refuses_a_tree_in_use() {
+    let dir = ScratchDir::new("unload-in-use");
+    let store = store_with_records(&dir, 3).await;
+
+    // the predicate runs with the tree's lock held
+    let during = Mutex::new(None);
+    store
+        .select_where::<Value, _>("users", |_| {
+            let mut during = during.lock().unwrap();
+            if during.is_none() {
+                *during = store.unload_tree("users").now_or_never();
+            }
+            true
+        })
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        during.into_inner().unwrap(),
+        Some(Err(JsonStoreError::InUseTree(t))) if t == "users"
+    ));
+    assert_eq!(store.loaded_trees().await, ["users"]);
+    store.unload_tree("users").await.unwrap();
+}
+
+#[tokio::test]
+async fn a_read_only_store_unloads_without_saving() {
+    let dir = ScratchDir::new("unload-read-only");
+    store_with_records(&dir, 3).await.save().await.unwrap();
+
+    let store = JsonStore::load_read_only(dir.path()).await.unwrap();
+    assert_eq!(all(&store, "users").await.len(), 3);
+    store.unload_tree("users").await.unwrap();
+    assert!(store.loaded_trees().await.is_empty());
+    assert_eq!(all(&store, "users").await.len(), 3);
+}
@@ -0,0 +1,134 @@
+mod common;
+
+use common::{all, ScratchDir};
+use json_store::{
+    error::JsonStoreError,
+    store::{Info, JsonStore},
+};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+// users, each maybe with a manager among them, 3 and 5 of five left; and posts by
+// them
+async fn store_with_gaps(dir: &ScratchDir) -> JsonStore {
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    let users = Info::builder()
+        .sequence_field("id")
+        .unique("email", ["email"])
+        .reference("manager", "users")
+        .build()
+        .unwrap();
+    let posts = Info::builder()
+        .sequence_field("pid")
+        .reference("author", "users")
+        .build()
+        .unwrap();
+    store.create_tree("users", users).await.unwrap();
+    store.create_tree("posts", posts).await.unwrap();
+
+    for n in 1..=5 {
+        store
+            .insert("users", &json!({ "email": format!("{}@x", n) }))
+            .await
+            .unwrap();
+    }
+    store
+        .update("users", &json!({"id": 5, "email": "5@x", "manager": 3}))
+        .await
+        .unwrap();
+    for seq in [1, 2, 4] {
+        store.delete("users", seq).await.unwrap();
+    }
+    for author in [5, 3, 5] {
+        store
+            .insert("posts", &json!({ "author": author }))
+            .await
+            .unwrap();
+    }
+    store
+}
+
+fn contents(dir: &ScratchDir) -> Vec<Vec<u8>> {
+    ["users.json", "users.seq", "posts.json"]
+        .map(|name| std::fs::read(dir.path().join(name)).unwrap())
+        .to_vec()
+}
+
+#[tokio::test]
+async fn references_follow_the_records_they_name() {
+    let dir = ScratchDir::new("vacuum-references");
+    let store = store_with_gaps(&dir).await;
+
+    let renumbered = store.vacuum_tree("users", true).await.unwrap();
+    assert_eq!(renumbered, HashMap::from([(3, 1), (5, 2)]));
+    assert_eq!(
+        all(&store, "users").await,
+        [
+            json!({"id": 1, "email": "3@x"}),
+            json!({"id": 2, "email": "5@x", "manager": 1}),
+        ]
+    );
+    let authors = all(&store, "posts")
+        .await
+        .iter()
+        .map(|post| post["author"].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(authors, [2, 1, 2]);
+
+    // the counter starts again after the last
+    let seq = store
+        .insert("users", &json!({"email": "new@x"}))
+        .await
+        .unwrap();
+    assert_eq!(seq, 3);
+    store.close().await.unwrap();
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(
+        store.select::<Value>("posts", 2).await.unwrap()["author"],
+        1
+    );
+}
+
+#[tokio::test]
+async fn a_second_vacuum_changes_nothing() {
+    let dir = ScratchDir::new("vacuum-again");
+    let store = store_with_gaps(&dir).await;
+    store.vacuum_tree("users", true).await.unwrap();
+    store.save().await.unwrap();
+    let before = contents(&dir);
+
+    let renumbered = store.vacuum_tree("users", true).await.unwrap();
+    assert_eq!(renumbered, HashMap::from([(1, 1), (2, 2)]));
+    for tname in ["users", "posts"] {
+        assert!(!store.is_dirty(tname).await.unwrap(), "{}", tname);
+    }
+    store.save().await.unwrap();
+    assert_eq!(contents(&dir), before);
+}
+
+#[tokio::test]
+async fn a_dangling_reference_stops_it_before_anything_changes() {
+    let dir = ScratchDir::new("vacuum-dangling");
+    let store = store_with_gaps(&dir).await;
+    store.insert("posts", &json!({"author": 2})).await.unwrap();
+    let users = all(&store, "users").await;
+
+    match store.vacuum_tree("users", true).await {
+        Err(JsonStoreError::DanglingReference {
+            tree,
+            sequence,
+            target,
+            ..
+        }) => assert_eq!((tree.as_str(), sequence, target), ("posts", 4, 2)),
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(all(&store, "users").await, users);
+
+    // nor does anything run unconfirmed
+    store.delete("posts", 4).await.unwrap();
+    assert!(matches!(
+        store.vacuum_tree("users", false).await,
+        Err(JsonStoreError::InvalidOptions(_))
+    ));
+    assert_eq!(all(&store, "users").await, users);
+}
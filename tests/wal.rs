@@ -0,0 +1,269 @@
+mod common;
+
+use common::{all, users, ScratchDir};
+use json_store::{
+    store::{JsonStore, LoadOptions},
+    wal::WalOptions,
+};
+use serde_json::json;
+use std::io::Write;
+
+async fn load(dir: &ScratchDir) -> JsonStore {
+    let options = LoadOptions {
+        wal: Some(WalOptions::default()),
+        ..Default::default()
+    };
+    JsonStore::load_with_options(dir.path(), options)
+        .await
+        .unwrap()
+}
+
+// a store whose users tree has two saved records and three more in its log only
+async fn crashed(dir: &ScratchDir) {
+    let store = load(dir).await;
+    store.create_tree("users", users()).await.unwrap();
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store
+        .insert("users", &json!({"email": "b@x"}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+
+    store
+        .insert("users", &json!({"email": "c@x"}))
+        .await
+        .unwrap();
+    store
+        .update("users", &json!({"id": 1, "email": "a2@x"}))
+        .await
+        .unwrap();
+    store.delete("users", 2).await.unwrap();
+    // dropped unsaved, as if the process died
+}
+
+#[tokio::test]
+async fn load_replays_the_log_after_a_crash() {
+    let dir = ScratchDir::new("wal-replay");
+    crashed(&dir).await;
+
+    let store = load(&dir).await;
+    assert_eq!(
+        all(&store, "users").await,
+        [
+            json!({"id": 1, "email": "a2@x"}),
+            json!({"id": 3, "email": "c@x"})
+        ]
+    );
+    // the sequence counter comes back too
+    assert_eq!(
+        store
+            .insert("users", &json!({"email": "d@x"}))
+            .await
+            .unwrap(),
+        4
+    );
+}
+
+#[tokio::test]
+async fn a_torn_final_line_is_dropped_and_cut_off() {
+    let dir = ScratchDir::new("wal-torn");
+    crashed(&dir).await;
+    let log = dir.path().join("users.wal");
+    let good = std::fs::read(&log).unwrap();
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(&log)
+        .unwrap()
+        .write_all(br#"{"op":"insert","tree":"users","seq":9,"val"#)
+        .unwrap();
+
+    let store = load(&dir).await;
+    assert_eq!(all(&store, "users").await.len(), 2);
+    assert_eq!(std::fs::read(&log).unwrap(), good);
+
+    // appends after the repair land on a line of their own and replay
+    store
+        .insert("users", &json!({"email": "e@x"}))
+        .await
+        .unwrap();
+    drop(store);
+    assert_eq!(all(&load(&dir).await, "users").await.len(), 3);
+}
+
+#[tokio::test]
+async fn a_final_line_missing_only_its_newline_is_kept() {
+    let dir = ScratchDir::new("wal-newline");
+    crashed(&dir).await;
+    let log = dir.path().join("users.wal");
+    let mut text = std::fs::read(&log).unwrap();
+    text.pop();
+    std::fs::write(&log, &text).unwrap();
+
+    let store = load(&dir).await;
+    assert_eq!(all(&store, "users").await.len(), 2);
+    assert!(std::fs::read(&log).unwrap().ends_with(b"\n"));
+}
+
+#[tokio::test]
+async fn a_read_only_load_replays_but_leaves_a_torn_log() {
+    let dir = ScratchDir::new("wal-read-only");
+    crashed(&dir).await;
+    let log = dir.path().join("users.wal");
+    let mut text = std::fs::read(&log).unwrap();
+    text.extend_from_slice(b"{\"op\":");
+    std::fs::write(&log, &text).unwrap();
+
+    let options = LoadOptions {
+        wal: Some(WalOptions::default()),
+        read_only: true,
+        ..Default::default()
+    };
+    let store = JsonStore::load_with_options(dir.path(), options)
+        .await
+        .unwrap();
+    assert_eq!(all(&store, "users").await.len(), 2);
+    assert_eq!(std::fs::read(&log).unwrap(), text);
+}
+
+#[tokio::test]
+async fn save_empties_the_log() {
+    let dir = ScratchDir::new("wal-save");
+    crashed(&dir).await;
+
+    let store = load(&dir).await;
+    assert!(store.wal_size("users").await.unwrap() > 0);
+    store.save_tree_force("users").await.unwrap();
+    assert_eq!(store.wal_size("users").await.unwrap(), 0);
+    drop(store);
+
+    // the snapshot now holds what the log did
+    let plain = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(all(&plain, "users").await.len(), 2);
+}
+
+#[tokio::test]
+async fn checkpoint_folds_the_log_into_the_snapshot() {
+    let dir = ScratchDir::new("wal-checkpoint");
+    crashed(&dir).await;
+
+    let store = load(&dir).await;
+    let size = store.wal_size("users").await.unwrap();
+    let stats = store.checkpoint("users").await.unwrap();
+    assert_eq!(stats.bytes_reclaimed, size);
+    assert_eq!(stats.entries_folded, 3);
+    assert_eq!(store.wal_size("users").await.unwrap(), 0);
+    assert!(!store.is_dirty("users").await.unwrap());
+
+    // nothing left to fold
+    let stats = store.checkpoint("users").await.unwrap();
+    assert_eq!((stats.bytes_reclaimed, stats.entries_folded), (0, 0));
+
+    let plain = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(all(&plain, "users").await.len(), 2);
+}
+
+#[tokio::test]
+async fn a_full_log_checkpoints_on_its_own() {
+    let dir = ScratchDir::new("wal-auto");
+    let options = LoadOptions {
+        wal: Some(WalOptions {
+            checkpoint_after_entries: 3,
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let store = JsonStore::load_with_options(dir.path(), options)
+        .await
+        .unwrap();
+    store.create_tree("users", users()).await.unwrap();
+
+    for email in ["a@x", "b@x"] {
+        store
+            .insert("users", &json!({ "email": email }))
+            .await
+            .unwrap();
+    }
+    assert!(store.wal_size("users").await.unwrap() > 0);
+    assert!(store.is_dirty("users").await.unwrap());
+
+    store
+        .insert("users", &json!({"email": "c@x"}))
+        .await
+        .unwrap();
+    assert_eq!(store.wal_size("users").await.unwrap(), 0);
+    assert!(!store.is_dirty("users").await.unwrap());
+
+    let plain = JsonStore::load(dir.path()).await.unwrap();
+    assert_eq!(all(&plain, "users").await.len(), 3);
+}
+
+#[tokio::test]
+async fn discarding_empties_the_log_too() {
+    let dir = ScratchDir::new("wal-discard");
+    let store = load(&dir).await;
+    store.create_tree("users", users()).await.unwrap();
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+
+    store
+        .insert("users", &json!({"email": "b@x"}))
+        .await
+        .unwrap();
+    store.delete("users", 1).await.unwrap();
+    store.discard_changes("users").await.unwrap();
+    assert_eq!(
+        all(&store, "users").await,
+        [json!({"id": 1, "email": "a@x"})]
+    );
+    assert!(!store.is_dirty("users").await.unwrap());
+    assert_eq!(store.wal_size("users").await.unwrap(), 0);
+
+    // nor do they come back after a crash
+    drop(store);
+    let store = load(&dir).await;
+    assert_eq!(
+        all(&store, "users").await,
+        [json!({"id": 1, "email": "a@x"})]
+    );
+
+    // and writes made after a discard are logged and replayed as usual
+    store
+        .insert("users", &json!({"email": "c@x"}))
+        .await
+        .unwrap();
+    drop(store);
+    assert_eq!(
+        all(&load(&dir).await, "users").await,
+        [
+            json!({"id": 1, "email": "a@x"}),
+            json!({"id": 2, "email": "c@x"})
+        ]
+    );
+}
+
+// writes replayed from a crash are unsaved changes like any other
+#[tokio::test]
+async fn discarding_after_a_crash_drops_the_replayed_writes() {
+    let dir = ScratchDir::new("wal-discard-crashed");
+    crashed(&dir).await;
+
+    let store = load(&dir).await;
+    assert_eq!(all(&store, "users").await.len(), 2);
+    assert!(store.is_dirty("users").await.unwrap());
+    store.discard_changes("users").await.unwrap();
+    assert_eq!(
+        all(&store, "users").await,
+        [
+            json!({"id": 1, "email": "a@x"}),
+            json!({"id": 2, "email": "b@x"})
+        ]
+    );
+    drop(store);
+    assert_eq!(all(&load(&dir).await, "users").await.len(), 2);
+}
@@ -0,0 +1,149 @@
+#![cfg(all(feature = "wasm", target_arch = "wasm32"))]
+
+use futures::future::LocalBoxFuture;
+use json_store::{
+    error::{ErrorKind, JsonStoreError},
+    kv::KeyValue,
+    store::{Info, JsonStore, LoadOptions},
+};
+use serde_json::{json, Value};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+// The wasm32 build, run under node by wasm-bindgen's test runner:
+//
+//   CARGO_TARGET_WASM32_UNKNOWN_UNKNOWN_RUNNER=wasm-bindgen-test-runner \
+//     cargo test --target wasm32-unknown-unknown --no-default-features \
+//     --features wasm,uuid --test wasm
+//
+// tests/common works on the filesystem, which isn't here, so these keep to the
+// in-memory and key-value backends.
+
+fn users() -> Info {
+    Info::builder()
+        .sequence_field("id")
+        .unique("email", ["email"])
+        .index("team")
+        .build()
+        .unwrap()
+}
+
+// a host keeping its values in a map, shared with the test through the Rc
+#[derive(Debug, Clone, Default)]
+struct Host(Rc<RefCell<HashMap<String, Vec<u8>>>>);
+
+impl KeyValue for Host {
+    fn get<'a>(
+        &'a self,
+        key: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Option<Vec<u8>>, JsonStoreError>> {
+        Box::pin(async move { Ok(self.0.borrow().get(key).cloned()) })
+    }
+
+    fn set<'a>(
+        &'a self,
+        key: &'a str,
+        value: Vec<u8>,
+    ) -> LocalBoxFuture<'a, Result<(), JsonStoreError>> {
+        Box::pin(async move {
+            self.0.borrow_mut().insert(key.to_string(), value);
+            Ok(())
+        })
+    }
+
+    fn remove<'a>(&'a self, key: &'a str) -> LocalBoxFuture<'a, Result<(), JsonStoreError>> {
+        Box::pin(async move {
+            self.0.borrow_mut().remove(key);
+            Ok(())
+        })
+    }
+
+    fn keys(&self) -> LocalBoxFuture<'_, Result<Vec<String>, JsonStoreError>> {
+        Box::pin(async move { Ok(self.0.borrow().keys().cloned().collect()) })
+    }
+}
+
+async fn fill(store: &JsonStore, count: u64) {
+    store.create_tree("users", users()).await.unwrap();
+    for n in 0..count {
+        let user = json!({"email": format!("{}@x", n), "team": n % 3});
+        store.insert("users", &user).await.unwrap();
+    }
+}
+
+#[wasm_bindgen_test]
+async fn an_in_memory_store_reads_and_writes() {
+    let store = JsonStore::in_memory();
+    fill(&store, 10).await;
+
+    let user: Value = store.select("users", 4).await.unwrap();
+    assert_eq!(user, json!({"id": 4, "email": "3@x", "team": 0}));
+    let team: Vec<Value> = store
+        .find_by_field("users", "team", &json!(1))
+        .await
+        .unwrap();
+    assert_eq!(team.len(), 3);
+
+    let mut user = user;
+    user["team"] = json!(1);
+    store.update("users", &user).await.unwrap();
+    store.delete("users", 1).await.unwrap();
+    let team: Vec<Value> = store
+        .find_by_field("users", "team", &json!(1))
+        .await
+        .unwrap();
+    assert_eq!(team.len(), 4);
+    store.save().await.unwrap();
+}
+
+#[wasm_bindgen_test]
+async fn uniqueness_holds() {
+    let store = JsonStore::in_memory();
+    fill(&store, 2).await;
+    let error = store
+        .insert("users", &json!({"email": "1@x"}))
+        .await
+        .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::Conflict);
+}
+
+#[wasm_bindgen_test]
+async fn a_key_value_store_loads_what_it_saved() {
+    let host = Host::default();
+    let store = JsonStore::load_key_value(host.clone(), LoadOptions::default())
+        .await
+        .unwrap();
+    fill(&store, 20).await;
+    store.delete("users", 7).await.unwrap();
+    store.close().await.unwrap();
+    assert!(!host.0.borrow().is_empty());
+
+    let store = JsonStore::load_key_value(host, LoadOptions::default())
+        .await
+        .unwrap();
+    let users: Vec<Value> = store.select_where("users", |_| true).await.unwrap();
+    assert_eq!(users.len(), 19);
+    let user: Value = store.select("users", 8).await.unwrap();
+    assert_eq!(user["email"], "7@x");
+    assert!(store
+        .insert("users", &json!({"email": "0@x"}))
+        .await
+        .is_err());
+}
+
+// there is nowhere to spill to
+#[wasm_bindgen_test]
+async fn a_resident_limit_is_refused() {
+    let store = JsonStore::in_memory();
+    let info = Info::builder()
+        .sequence_field("id")
+        .resident_limit(10)
+        .build()
+        .unwrap();
+    let error = store.create_tree("users", info).await.unwrap_err();
+    assert!(
+        matches!(error, JsonStoreError::InvalidOptions(_)),
+        "{}",
+        error
+    );
+}
@@ -0,0 +1,243 @@
+#![cfg(feature = "watch")]
+
+mod common;
+
+use common::{all, store_with_users, users, ScratchDir};
+use json_store::{
+    store::JsonStore,
+    watch::{WatchEvent, WatchHandle},
+};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::{path::PathBuf, time::Duration};
+
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+// long enough for notify to deliver and the debounce to pass many times over
+const PATIENCE: Duration = Duration::from_secs(10);
+
+// a store with users a@x, saved, and a watch on it
+async fn watched(dir: &ScratchDir) -> (JsonStore, WatchHandle) {
+    let store = store_with_users(dir).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store.save().await.unwrap();
+    let watch = store.watch_with(DEBOUNCE).await.unwrap();
+    // past the file clock's tick of the save, as below
+    tokio::time::sleep(DEBOUNCE).await;
+    (store, watch)
+}
+
+fn users_file(dir: &ScratchDir) -> PathBuf {
+    dir.path().join("users.json")
+}
+
+// Write the users files as another process would, with the given emails: the counter
+// first, so that no half of the edit leaves it behind the records, and the checksum
+// sidecar last, as the edited snapshot fails to read without it.
+async fn write_users(dir: &ScratchDir, emails: &[&str]) {
+    let counter = emails.len().to_string();
+    tokio::fs::write(dir.path().join("users.seq"), counter)
+        .await
+        .unwrap();
+    let records = emails
+        .iter()
+        .enumerate()
+        .map(|(i, email)| {
+            let id = i as u64 + 1;
+            (id.to_string(), json!({"id": id, "email": email}))
+        })
+        .collect::<serde_json::Map<_, _>>();
+    let contents = json!(records).to_string();
+    tokio::fs::write(users_file(dir), &contents).await.unwrap();
+    let digest = Sha256::digest(contents.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    let sidecar = format!("{}  users.json\n", digest);
+    tokio::fs::write(dir.path().join("users.json.sha256"), sidecar)
+        .await
+        .unwrap();
+}
+
+async fn next(watch: &mut WatchHandle) -> WatchEvent {
+    tokio::time::timeout(PATIENCE, watch.next())
+        .await
+        .expect("an event")
+        .expect("the watch running")
+}
+
+// nothing comes within a few debounce intervals
+async fn assert_quiet(watch: &mut WatchHandle) {
+    tokio::time::sleep(DEBOUNCE * 5).await;
+    if let Some(event) = watch.try_next() {
+        panic!("{:?}", event);
+    }
+}
+
+async fn emails(store: &JsonStore) -> Vec<String> {
+    all(store, "users")
+        .await
+        .iter()
+        .map(|user| user["email"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn an_external_edit_reloads_a_clean_tree() {
+    let dir = ScratchDir::new("watch-reload");
+    let (store, mut watch) = watched(&dir).await;
+
+    write_users(&dir, &["edited@x", "b@x"]).await;
+    match next(&mut watch).await {
+        WatchEvent::Reloaded { tree } => assert_eq!(tree, "users"),
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(emails(&store).await, ["edited@x", "b@x"]);
+    assert!(!store.is_dirty("users").await.unwrap());
+
+    // and the reloaded tree saves without taking the edit for another
+    store
+        .insert("users", &json!({"email": "c@x"}))
+        .await
+        .unwrap();
+    assert!(store.save_tree("users").await.unwrap());
+}
+
+#[tokio::test]
+async fn our_own_saves_are_not_changes() {
+    let dir = ScratchDir::new("watch-own");
+    let (store, mut watch) = watched(&dir).await;
+
+    for n in 0..5 {
+        store
+            .insert("users", &json!({ "email": format!("{}@x", n) }))
+            .await
+            .unwrap();
+        store.save().await.unwrap();
+    }
+    store.create_tree("teams", users()).await.unwrap();
+    store.save().await.unwrap();
+    assert_quiet(&mut watch).await;
+    assert_eq!(emails(&store).await.len(), 6);
+}
+
+#[tokio::test]
+async fn unsaved_changes_are_kept_and_reported() {
+    let dir = ScratchDir::new("watch-conflict");
+    let (store, mut watch) = watched(&dir).await;
+    store
+        .insert("users", &json!({"email": "ours@x"}))
+        .await
+        .unwrap();
+
+    write_users(&dir, &["theirs@x"]).await;
+    match next(&mut watch).await {
+        WatchEvent::Conflict { tree, path } => {
+            assert_eq!(tree, "users");
+            // the first of its files found changed
+            let seq = dir.path().join("users.seq");
+            assert!(path == seq || path == users_file(&dir), "{:?}", path);
+        }
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(emails(&store).await, ["a@x", "ours@x"]);
+    assert!(store.is_dirty("users").await.unwrap());
+
+    // until it's settled, each change to the files brings another
+    write_users(&dir, &["theirs@x", "again@x"]).await;
+    assert!(matches!(
+        next(&mut watch).await,
+        WatchEvent::Conflict { .. }
+    ));
+
+    // taking their version settles it, and later edits reload again
+    store.reload_tree("users").await.unwrap();
+    assert_eq!(emails(&store).await, ["theirs@x", "again@x"]);
+    // An edit in the same tick of the file clock as the reload would carry the stamps
+    // the reload read, and be taken for files the store already has (see watch.rs);
+    // no one at an editor is that quick.
+    tokio::time::sleep(DEBOUNCE).await;
+    write_users(&dir, &["later@x"]).await;
+    match next(&mut watch).await {
+        WatchEvent::Reloaded { .. } => {}
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(emails(&store).await, ["later@x"]);
+}
+
+#[tokio::test]
+async fn a_burst_of_writes_is_one_reload() {
+    let dir = ScratchDir::new("watch-burst");
+    let (store, mut watch) = watched(&dir).await;
+
+    // each sooner after the last than the debounce
+    for n in 0..10 {
+        let email = format!("{}@x", n);
+        write_users(&dir, &[&email]).await;
+        tokio::time::sleep(DEBOUNCE / 5).await;
+    }
+    assert!(matches!(
+        next(&mut watch).await,
+        WatchEvent::Reloaded { .. }
+    ));
+    assert_quiet(&mut watch).await;
+    assert_eq!(emails(&store).await, ["9@x"]);
+}
+
+#[tokio::test]
+async fn a_half_written_file_fails_until_it_is_whole() {
+    let dir = ScratchDir::new("watch-failed");
+    let (store, mut watch) = watched(&dir).await;
+
+    tokio::fs::write(users_file(&dir), r#"{"1": {"id": 1, "em"#)
+        .await
+        .unwrap();
+    match next(&mut watch).await {
+        WatchEvent::Failed { tree, .. } => assert_eq!(tree, "users"),
+        other => panic!("{:?}", other),
+    }
+    // the tree is left as it was
+    assert_eq!(emails(&store).await, ["a@x"]);
+
+    write_users(&dir, &["whole@x"]).await;
+    assert!(matches!(
+        next(&mut watch).await,
+        WatchEvent::Reloaded { .. }
+    ));
+    assert_eq!(emails(&store).await, ["whole@x"]);
+}
+
+#[tokio::test]
+async fn a_tree_not_read_yet_is_left_to_read_the_edit() {
+    let dir = ScratchDir::new("watch-unloaded");
+    let store = store_with_users(&dir).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    store.close().await.unwrap();
+
+    let store = JsonStore::load(dir.path()).await.unwrap();
+    let mut watch = store.watch_with(DEBOUNCE).await.unwrap();
+    write_users(&dir, &["edited@x"]).await;
+    assert_quiet(&mut watch).await;
+    // its first read finds the edit anyway
+    assert_eq!(emails(&store).await, ["edited@x"]);
+}
+
+#[tokio::test]
+async fn stopping_ends_the_events() {
+    let dir = ScratchDir::new("watch-stop");
+    let (store, watch) = watched(&dir).await;
+    watch.stop().await;
+
+    write_users(&dir, &["edited@x"]).await;
+    tokio::time::sleep(DEBOUNCE * 5).await;
+    assert_eq!(emails(&store).await, ["a@x"]);
+
+    // nor can a store that isn't in a directory be watched
+    assert!(JsonStore::in_memory().watch().await.is_err());
+}
@@ -0,0 +1,222 @@
+mod common;
+
+use common::{all, users, ScratchDir};
+use json_store::{
+    batch::WriteBatching,
+    store::{JsonStore, LoadOptions},
+};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+
+// The rest of the suite runs through the batched path as well when
+// JSON_STORE_TEST_WRITE_BATCHING is set (see common); these compare the two directly.
+
+const MODES: [WriteBatching; 3] = [
+    WriteBatching::Off,
+    WriteBatching::Contended,
+    WriteBatching::Always,
+];
+
+async fn store(dir: &ScratchDir, batching: WriteBatching) -> JsonStore {
+    let options = LoadOptions {
+        write_batching: batching,
+        ..Default::default()
+    };
+    let store = JsonStore::load_with_options(dir.path(), options)
+        .await
+        .unwrap();
+    store.create_tree("users", users()).await.unwrap();
+    store
+}
+
+// Writes that succeed and writes that fail, each failing for its own reason, with
+// what each returned written down as text.
+async fn script(store: &JsonStore) -> Vec<String> {
+    let mut results = Vec::new();
+    macro_rules! run {
+        ($call:expr) => {
+            results.push(format!("{:?}", $call.await))
+        };
+    }
+    run!(store.insert("users", &json!({"email": "a@x"})));
+    run!(store.insert("users", &json!({"email": "b@x"})));
+    run!(store.insert("users", &json!({"email": "a@x"})));
+    run!(store.insert("users", &json!("not an object")));
+    run!(store.update("users", &json!({"id": 1, "email": "b@x"})));
+    run!(store.update("users", &json!({"id": 1, "email": "a@x", "name": "A"})));
+    run!(store.update("users", &json!({"id": 99, "email": "z@x"})));
+    run!(store.update("users", &json!({"email": "no id"})));
+    run!(store.delete("users", 2));
+    run!(store.delete("users", 2));
+    run!(store.insert("users", &json!({"email": "b@x"})));
+    run!(store.undo_last("users"));
+    run!(store.insert("users", &json!({"email": "c@x"})));
+    // inserts at reserved sequences, one of them taken twice and one never reserved
+    run!(store.allocate_sequences("users", 2));
+    run!(store.insert_reserved("users", 6, &json!({"email": "e@x"})));
+    run!(store.insert_reserved("users", 5, &json!({"email": "d@x"})));
+    run!(store.insert_reserved("users", 5, &json!({"email": "f@x"})));
+    run!(store.insert_reserved("users", 9, &json!({"email": "g@x"})));
+    results
+}
+
+#[tokio::test]
+async fn batched_writes_return_what_direct_ones_do() {
+    let dir = ScratchDir::new("batching-direct");
+    let direct = store(&dir, WriteBatching::Off).await;
+    let expected = script(&direct).await;
+    let records = all(&direct, "users").await;
+
+    for batching in MODES {
+        let dir = ScratchDir::new("batching-script");
+        let store = store(&dir, batching).await;
+        assert_eq!(script(&store).await, expected, "{:?}", batching);
+        assert_eq!(all(&store, "users").await, records, "{:?}", batching);
+        assert!(store.is_dirty("users").await.unwrap());
+
+        // and what they leave on disk is the same
+        store.close().await.unwrap();
+        let store = JsonStore::load(dir.path()).await.unwrap();
+        assert_eq!(all(&store, "users").await, records, "{:?}", batching);
+    }
+}
+
+// Many writers at once, every other record a clash with one already in: each write
+// gets its own answer, and each writer's records are in the order it wrote them.
+#[tokio::test]
+async fn racing_writers_each_get_their_own_result() {
+    const WRITERS: u64 = 16;
+    const EACH: u64 = 40;
+    for batching in MODES {
+        let dir = ScratchDir::new("batching-race");
+        let store = store(&dir, batching).await;
+        store
+            .insert("users", &json!({"email": "taken"}))
+            .await
+            .unwrap();
+
+        let tasks = (0..WRITERS).map(|writer| {
+            let store = store.clone();
+            tokio::spawn(async move {
+                let mut sequences = Vec::new();
+                for n in 0..EACH {
+                    let email = match n % 2 {
+                        0 => format!("{}-{}", writer, n),
+                        _ => "taken".to_string(),
+                    };
+                    let result = store.insert("users", &json!({ "email": email })).await;
+                    assert_eq!(result.is_ok(), n % 2 == 0, "{:?}", result);
+                    if let Ok(seq) = result {
+                        sequences.push(seq);
+                    }
+                }
+                // a writer's updates and deletes land too, after its inserts
+                for seq in &sequences[..2] {
+                    store.delete("users", *seq).await.unwrap();
+                }
+                let seq = sequences[2];
+                store
+                    .update(
+                        "users",
+                        &json!({"id": seq, "email": format!("{}-renamed", writer)}),
+                    )
+                    .await
+                    .unwrap();
+                sequences
+            })
+        });
+        let mut taken = BTreeSet::new();
+        for task in tasks.collect::<Vec<_>>() {
+            let sequences = task.await.unwrap();
+            assert_eq!(sequences.len() as u64, EACH / 2);
+            assert!(sequences.windows(2).all(|pair| pair[0] < pair[1]));
+            for seq in sequences {
+                assert!(taken.insert(seq), "{} given twice", seq);
+            }
+        }
+
+        let records = all(&store, "users").await;
+        assert_eq!(
+            records.len() as u64,
+            1 + WRITERS * (EACH / 2 - 2),
+            "{:?}",
+            batching
+        );
+        let renamed = records
+            .iter()
+            .filter(|record| record["email"].as_str().unwrap().ends_with("-renamed"))
+            .count();
+        assert_eq!(renamed as u64, WRITERS);
+    }
+}
+
+// inserts at reserved sequences queue like the rest, each landing where reserved
+#[tokio::test]
+async fn racing_reserved_inserts_land_where_reserved() {
+    const WRITERS: u64 = 8;
+    const EACH: u64 = 20;
+    for batching in MODES {
+        let dir = ScratchDir::new("batching-reserved");
+        let store = store(&dir, batching).await;
+
+        let tasks = (0..WRITERS).map(|writer| {
+            let store = store.clone();
+            tokio::spawn(async move {
+                let reserved = store.allocate_sequences("users", EACH).await.unwrap();
+                // back to front, each interleaved with a plain insert
+                for seq in reserved.clone().rev() {
+                    let email = format!("{}@{}", seq, writer);
+                    store
+                        .insert_reserved("users", seq, &json!({ "email": email }))
+                        .await
+                        .unwrap();
+                    let email = format!("plain-{}@{}", seq, writer);
+                    store
+                        .insert("users", &json!({ "email": email }))
+                        .await
+                        .unwrap();
+                }
+                (writer, reserved)
+            })
+        });
+        for task in tasks.collect::<Vec<_>>() {
+            let (writer, reserved) = task.await.unwrap();
+            for seq in reserved {
+                let record: Value = store.select("users", seq).await.unwrap();
+                assert_eq!(record["email"], format!("{}@{}", seq, writer));
+            }
+        }
+        assert_eq!(
+            all(&store, "users").await.len() as u64,
+            2 * WRITERS * EACH,
+            "{:?}",
+            batching
+        );
+    }
+}
+
+// a record lock's owner writes past the queue, and is still answered as ever
+#[tokio::test]
+async fn writes_as_a_lock_owner_are_not_queued() {
+    let dir = ScratchDir::new("batching-owner");
+    let store = store(&dir, WriteBatching::Always).await;
+    store
+        .insert("users", &json!({"email": "a@x"}))
+        .await
+        .unwrap();
+    let _lock = store
+        .lock_record("users", 1, "me", std::time::Duration::from_secs(60))
+        .await
+        .unwrap();
+
+    assert!(store
+        .update("users", &json!({"id": 1, "email": "b@x"}))
+        .await
+        .is_err());
+    store
+        .update_as("users", &json!({"id": 1, "email": "b@x"}), "me")
+        .await
+        .unwrap();
+    let record: Value = store.select("users", 1).await.unwrap();
+    assert_eq!(record["email"], "b@x");
+}